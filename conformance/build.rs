@@ -0,0 +1,84 @@
+use std::{env, path::PathBuf};
+
+use micropb_gen::{Config, Generator};
+
+const WELL_KNOWN_TYPES: &[&str] = &[
+    "any.proto",
+    "duration.proto",
+    "field_mask.proto",
+    "struct.proto",
+    "timestamp.proto",
+    "wrappers.proto",
+];
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=PROTOBUF_DIR");
+    let protobuf_dir = PathBuf::from(
+        env::var("PROTOBUF_DIR")
+            .expect("PROTOBUF_DIR must point to a checkout of the protobuf repository"),
+    );
+    let src_dir = protobuf_dir.join("src");
+    let conformance_dir = protobuf_dir.join("conformance");
+
+    let mut protos = vec![
+        conformance_dir.join("conformance.proto"),
+        src_dir.join("google/protobuf/test_messages_proto2.proto"),
+        src_dir.join("google/protobuf/test_messages_proto3.proto"),
+    ];
+    protos.extend(
+        WELL_KNOWN_TYPES
+            .iter()
+            .map(|p| src_dir.join("google/protobuf").join(p)),
+    );
+    for proto in &protos {
+        println!("cargo:rerun-if-changed={}", proto.display());
+    }
+
+    let mut generator = Generator::new();
+    generator.add_protoc_arg(format!("-I{}", src_dir.display()));
+    generator.add_protoc_arg(format!("-I{}", conformance_dir.display()));
+    generator.use_container_std();
+    // Variants such as `NullValue::NULL_VALUE` consist of nothing but the enum prefix
+    generator.retain_enum_prefix(true);
+
+    for msg in [
+        ".protobuf_test_messages.proto2.TestAllTypesProto2",
+        ".protobuf_test_messages.proto3.TestAllTypesProto3",
+    ] {
+        // Break the cycles between the test message and its nested message
+        generator.configure(
+            &format!("{msg}.recursive_message"),
+            Config::new().boxed(true),
+        );
+        generator.configure(
+            &format!("{msg}.NestedMessage.corecursive"),
+            Config::new().boxed(true),
+        );
+    }
+
+    // Group fields are unsupported, so their values are dropped as unknown fields
+    let proto2 = ".protobuf_test_messages.proto2";
+    for field in [
+        "TestAllTypesProto2.data",
+        "TestAllTypesProto2.multiwordgroupfield",
+    ] {
+        generator.configure(&format!("{proto2}.{field}"), Config::new().skip(true));
+    }
+    // Only `TestAllTypesProto2` is requested by the test runner
+    for msg in ["TestAllRequiredTypesProto2", "UnknownToTestAllTypes"] {
+        generator.configure(&format!("{proto2}.{msg}"), Config::new().skip(true));
+    }
+
+    // `AliasedEnum` has variants that only differ in case, such as `MOO` and `moo`, which map to
+    // the same Rust name. Skip the enum and its field.
+    let proto3 = ".protobuf_test_messages.proto3.TestAllTypesProto3";
+    generator.configure(&format!("{proto3}.AliasedEnum"), Config::new().skip(true));
+    generator.configure(
+        &format!("{proto3}.optional_aliased_enum"),
+        Config::new().skip(true),
+    );
+
+    generator
+        .compile_protos(&protos, env::var("OUT_DIR").unwrap() + "/conformance.rs")
+        .unwrap();
+}
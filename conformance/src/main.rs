@@ -0,0 +1,101 @@
+//! Testee for the Protobuf conformance test runner.
+//!
+//! The runner sends length-prefixed `ConformanceRequest`s over stdin and expects a
+//! length-prefixed `ConformanceResponse` over stdout for each one. Only the binary wire format is
+//! supported, so requests involving JSON or text format are reported as skipped.
+
+extern crate alloc;
+
+use std::io::{self, Read, Write};
+
+use micropb::{MessageDecode, MessageEncode, PbEncoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/conformance.rs"));
+}
+
+use proto::conformance_::{
+    ConformanceRequest, ConformanceRequest_::Payload, ConformanceResponse,
+    ConformanceResponse_::Result as Response, WireFormat,
+};
+use proto::protobuf_test_messages_::{proto2_::TestAllTypesProto2, proto3_::TestAllTypesProto3};
+
+/// Decode the payload and encode it back
+fn roundtrip<M: Default + MessageDecode + MessageEncode>(payload: &[u8]) -> Response {
+    let mut msg = M::default();
+    if let Err(e) = msg.merge_from_bytes(payload) {
+        return Response::ParseError(e.to_string());
+    }
+    let mut encoder = PbEncoder::new(Vec::with_capacity(msg.compute_size()));
+    match msg.encode(&mut encoder) {
+        Ok(()) => Response::ProtobufPayload(encoder.into_writer()),
+        Err(never) => match never {},
+    }
+}
+
+fn run_test(request: &ConformanceRequest) -> Response {
+    // The runner asks for the list of expected failures first, without a payload. Reply with an
+    // empty list, so that the runner uses the list passed with `--failure_list` instead.
+    if request.message_type == "conformance.FailureSet" {
+        return Response::ProtobufPayload(vec![]);
+    }
+    let Some(Payload::ProtobufPayload(payload)) = &request.payload else {
+        return Response::Skipped("Only protobuf input is supported".to_owned());
+    };
+    if request.requested_output_format != WireFormat::Protobuf {
+        return Response::Skipped("Only protobuf output is supported".to_owned());
+    }
+    match request.message_type.as_str() {
+        "protobuf_test_messages.proto2.TestAllTypesProto2" => {
+            roundtrip::<TestAllTypesProto2>(payload)
+        }
+        "protobuf_test_messages.proto3.TestAllTypesProto3" => {
+            roundtrip::<TestAllTypesProto3>(payload)
+        }
+        msg_type => Response::Skipped(format!("Unsupported message type {msg_type}")),
+    }
+}
+
+/// Read a length-prefixed request. Returns `None` once the runner closes stdin.
+fn read_request(input: &mut impl Read) -> io::Result<Option<ConformanceRequest>> {
+    let mut len = [0; 4];
+    match input.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let mut buf = vec![0; u32::from_le_bytes(len) as usize];
+    input.read_exact(&mut buf)?;
+
+    let mut request = ConformanceRequest::default();
+    request
+        .merge_from_bytes(&buf)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    Ok(Some(request))
+}
+
+fn write_response(output: &mut impl Write, response: &ConformanceResponse) -> io::Result<()> {
+    let mut encoder = PbEncoder::new(Vec::with_capacity(response.compute_size()));
+    match response.encode(&mut encoder) {
+        Ok(()) => {}
+        Err(never) => match never {},
+    }
+    let buf = encoder.into_writer();
+    output.write_all(&(buf.len() as u32).to_le_bytes())?;
+    output.write_all(&buf)?;
+    output.flush()
+}
+
+fn main() -> io::Result<()> {
+    let mut input = io::stdin().lock();
+    let mut output = io::stdout().lock();
+    while let Some(request) = read_request(&mut input)? {
+        let response = ConformanceResponse {
+            result: Some(run_test(&request)),
+        };
+        write_response(&mut output, &response)?;
+    }
+    Ok(())
+}
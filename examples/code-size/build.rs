@@ -0,0 +1,13 @@
+use micropb_gen::{Config, Generator};
+
+fn main() {
+    let mut gen = Generator::new();
+    gen.use_container_heapless()
+        .dyn_encode(std::env::var_os("CARGO_FEATURE_DYN_ENCODE").is_some())
+        .configure(".", Config::new().max_len(8).max_bytes(32))
+        .compile_protos(
+            &["firmware.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/firmware.rs",
+        )
+        .unwrap();
+}
@@ -0,0 +1,21 @@
+#!/bin/sh
+# Compare the size of the encoding logic with and without `dyn_encode`
+set -e
+cd "$(dirname "$0")"
+
+encode_size() {
+    cargo build -q --release -p code-size "$@"
+    # Sum the sizes of all code symbols that come from the generated messages or `micropb`
+    nm -S -C ../../target/release/code-size |
+        grep -E ' [tT] .*(code_size|micropb)' |
+        {
+            total=0
+            while read -r _ size _; do total=$((total + 0x$size)); done
+            echo "$total"
+        }
+}
+
+generic=$(encode_size)
+dyn=$(encode_size --features dyn-encode)
+echo "generic: $generic bytes"
+echo "dyn:     $dyn bytes"
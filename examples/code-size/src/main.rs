@@ -0,0 +1,37 @@
+use std::hint::black_box;
+
+use micropb::{heapless, BackpatchWriter, MessageEncode, PbEncoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/firmware.rs"));
+}
+
+use proto::firmware_::*;
+
+/// Encode the message into each of the writer types used by the application
+#[inline(never)]
+fn encode_all<M: MessageEncode>(msg: &M) -> usize {
+    let mut buf = [0; 256];
+    let mut len = msg.encode_to_slice(&mut buf).map_or(0, |data| data.len());
+
+    let mut encoder = PbEncoder::new(BackpatchWriter::exact(&mut buf));
+    if msg.encode(&mut encoder).is_ok() {
+        len += encoder.into_writer().written();
+    }
+
+    let mut encoder = PbEncoder::new(heapless::Vec::<u8, 256>::new());
+    if msg.encode(&mut encoder).is_ok() {
+        len += encoder.into_writer().len();
+    }
+    len
+}
+
+fn main() {
+    // `black_box` prevents the encoding logic from being optimized out
+    let total = encode_all(&black_box(Telemetry::default()))
+        + encode_all(&black_box(Command::default()))
+        + encode_all(&black_box(Ack::default()));
+    println!("Encoded {total} bytes");
+}
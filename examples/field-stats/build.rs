@@ -0,0 +1,12 @@
+use micropb_gen::Generator;
+
+fn main() {
+    let mut gen = Generator::new();
+    gen.use_container_std()
+        .field_stats(true)
+        .compile_protos(
+            &["telemetry.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/telemetry.rs",
+        )
+        .unwrap();
+}
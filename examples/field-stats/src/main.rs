@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use micropb::{
+    DecodeError, FieldStats, FieldStatsReader, MessageDecode, MessageEncode, Never, PbDecoder,
+    PbEncoder,
+};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/telemetry.rs"));
+}
+
+use proto::telemetry_::{Position, Report};
+
+#[derive(Default)]
+struct Histogram {
+    /// Occurrences and total bytes of each field, keyed by nesting depth and field number
+    fields: HashMap<(u8, u32), (usize, usize)>,
+}
+
+impl FieldStats for Histogram {
+    fn record(&mut self, depth: u8, field_num: u32, bytes: usize) {
+        let entry = self.fields.entry((depth, field_num)).or_default();
+        entry.0 += 1;
+        entry.1 += bytes;
+    }
+}
+
+/// Generate a corpus of length-delimited reports for when no corpus file is provided
+fn synthetic_corpus() -> Vec<u8> {
+    let mut encoder = PbEncoder::new(vec![]);
+    for i in 0..100u32 {
+        let mut report = Report {
+            device_id: 1000 + i % 4,
+            timestamp: 1_700_000_000_000 + i as u64 * 250,
+            samples: (0..i % 16).map(|s| s as i32 * 37 - 200).collect(),
+            status: if i % 10 == 0 {
+                "low battery".to_owned()
+            } else {
+                String::new()
+            },
+            ..Default::default()
+        };
+        report.set_position(Position {
+            lat: 515_074_000 + i as i32,
+            lon: -1_278_000 - i as i32,
+            alt: 35,
+        });
+        report.encode_len_delimited(&mut encoder).unwrap();
+    }
+    encoder.into_writer()
+}
+
+fn collect_stats(corpus: &[u8]) -> Result<(usize, Histogram), DecodeError<Never>> {
+    let mut histogram = Histogram::default();
+    let mut decoder = PbDecoder::new(FieldStatsReader::new(corpus, &mut histogram));
+    let mut count = 0;
+    while !decoder.is_eof()? {
+        // Decode the length prefix separately, so that the fields of each report are recorded
+        // at depth 0
+        let len = decoder.decode_varint32()? as usize;
+        let mut report = Report::default();
+        report.decode(&mut decoder, len)?;
+        count += 1;
+    }
+    Ok((count, histogram))
+}
+
+fn main() {
+    let corpus = match std::env::args().nth(1) {
+        Some(path) => std::fs::read(path).expect("failed to read corpus"),
+        None => synthetic_corpus(),
+    };
+    let (count, histogram) = collect_stats(&corpus).expect("failed to decode corpus");
+
+    let mut fields: Vec<_> = histogram.fields.into_iter().collect();
+    fields.sort_by(|a, b| b.1 .1.cmp(&a.1 .1).then(a.0.cmp(&b.0)));
+    println!("{count} messages, {} bytes", corpus.len());
+    println!(
+        "{:>5} {:>5} {:>8} {:>8} {:>6}",
+        "depth", "field", "count", "bytes", "share"
+    );
+    for ((depth, field_num), (occurrences, bytes)) in fields {
+        let share = bytes as f64 * 100.0 / corpus.len() as f64;
+        println!("{depth:>5} {field_num:>5} {occurrences:>8} {bytes:>8} {share:>5.1}%");
+    }
+}
@@ -0,0 +1,44 @@
+use micropb_gen::{
+    config::{OnOverflow, OverflowPolicy},
+    Config, Generator,
+};
+
+fn main() {
+    let proto = "../tests/basic-proto/proto/arbitrary.proto";
+    println!("cargo:rerun-if-changed={proto}");
+
+    let mut generator = Generator::new();
+    generator.add_protoc_arg("-I../tests/basic-proto/proto");
+    generator.use_container_heapless();
+    generator.configure(".", Config::new().max_len(4).max_bytes(8));
+    // heapless maps only implement `PartialEq` for `Eq` values
+    generator.configure(".Arbitrary", Config::new().no_partial_eq_impl(true));
+    generator.configure(".Arbitrary.child", Config::new().boxed(true));
+    generator.configure(".Arbitrary.oneof_child", Config::new().boxed(true));
+    generator.configure(
+        ".Arbitrary.strs",
+        Config::new().overflow_policy(OverflowPolicy::DropExtra),
+    );
+    generator.configure(
+        ".Arbitrary.text",
+        Config::new().on_overflow(OnOverflow::Truncate),
+    );
+    generator
+        .compile_protos(
+            &[proto],
+            std::env::var("OUT_DIR").unwrap() + "/arbitrary_heapless.rs",
+        )
+        .unwrap();
+
+    let mut generator = Generator::new();
+    generator.add_protoc_arg("-I../tests/basic-proto/proto");
+    generator.use_container_alloc();
+    generator.configure(".Arbitrary.child", Config::new().boxed(true));
+    generator.configure(".Arbitrary.oneof_child", Config::new().boxed(true));
+    generator
+        .compile_protos(
+            &[proto],
+            std::env::var("OUT_DIR").unwrap() + "/arbitrary_alloc.rs",
+        )
+        .unwrap();
+}
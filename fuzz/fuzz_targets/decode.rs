@@ -0,0 +1,31 @@
+#![no_main]
+
+extern crate alloc;
+
+use libfuzzer_sys::fuzz_target;
+use micropb::{MessageDecode, PbDecoder};
+
+mod heapless_proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/arbitrary_heapless.rs"));
+}
+
+mod alloc_proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/arbitrary_alloc.rs"));
+}
+
+// Decoding arbitrary input must only ever return errors, never panic
+fuzz_target!(|data: &[u8]| {
+    let _ = heapless_proto::Arbitrary::default().merge_from_bytes(data);
+    let _ = alloc_proto::Arbitrary::default().merge_from_bytes(data);
+
+    // Also exercise the non-contiguous reader paths
+    let (first, second) = data.split_at(data.len() / 2);
+    let mut decoder = PbDecoder::new((first, second));
+    let _ = heapless_proto::Arbitrary::default().decode(&mut decoder, data.len());
+    let mut decoder = PbDecoder::new((first, second));
+    let _ = alloc_proto::Arbitrary::default().decode(&mut decoder, data.len());
+});
@@ -1,9 +1,10 @@
 //! Configuration options for Protobuf types and fields.
 
-use proc_macro2::Span;
-use syn::Ident;
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{parse::Parser, Ident};
 
-use crate::generator::sanitized_ident;
+use crate::generator::{container::ContainerType, sanitized_ident};
 
 #[derive(Debug, Clone, Copy)]
 #[cfg_attr(test, derive(PartialEq, Eq))]
@@ -17,6 +18,15 @@ pub enum IntSize {
     S32,
     /// 64-bit int
     S64,
+    /// Pointer-sized int (`usize` or `isize`), whose width depends on the target.
+    ///
+    /// Unlike the other sizes, values on the wire are never truncated to fit. Decoding fails with
+    /// `DecodeError::IntOverflow` if a value doesn't fit into the target's pointer width, so a
+    /// `uint64` field stored as `usize` behaves the same on a 32-bit target as on a 64-bit host
+    /// for values that fit, and fails loudly for values that don't. Using this size for a 64-bit
+    /// Protobuf type causes a generation warning, since the field may hold values that only fit
+    /// on 64-bit targets.
+    Size,
 }
 
 impl IntSize {
@@ -30,6 +40,8 @@ impl IntSize {
             IntSize::S32 => "u32",
             IntSize::S64 if signed => "i64",
             IntSize::S64 => "u64",
+            IntSize::Size if signed => "isize",
+            IntSize::Size => "usize",
         };
         Ident::new(t, Span::call_site())
     }
@@ -63,6 +75,78 @@ pub enum OptionalRepr {
     Option,
 }
 
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+/// Behaviour when a fixed-capacity repeated or `map` field runs out of space during decoding
+pub enum OverflowPolicy {
+    /// Fail decoding with a capacity error.
+    ///
+    /// Default for all fields.
+    Error,
+    /// Discard the excess elements and continue decoding.
+    DropExtra,
+    /// Discard the excess elements and set the `truncated` flag of the decoder.
+    DropExtraWithFlag,
+}
+
+impl OverflowPolicy {
+    pub(crate) fn generate_policy(self) -> TokenStream {
+        match self {
+            OverflowPolicy::Error => quote! { ::micropb::OverflowPolicy::Error },
+            OverflowPolicy::DropExtra => quote! { ::micropb::OverflowPolicy::DropExtra },
+            OverflowPolicy::DropExtraWithFlag => {
+                quote! { ::micropb::OverflowPolicy::DropExtraWithFlag }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+/// Behaviour when a fixed-capacity `string` or `bytes` field receives more bytes than it can hold
+/// during decoding
+pub enum OnOverflow {
+    /// Fail decoding with a capacity error.
+    ///
+    /// Default for all fields.
+    Error,
+    /// Keep as many bytes as fit into the container and skip the rest. Strings are cut at the
+    /// last UTF-8 character boundary that fits.
+    Truncate,
+    /// Same as [`Truncate`](OnOverflow::Truncate), and also set the `truncated` flag of the
+    /// decoder.
+    TruncateWithFlag,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+/// Behaviour when an enum field receives a value that isn't declared in the Protobuf enum
+pub enum OnUnknownEnum {
+    /// Store the value as-is. Generated enums are open, so unknown values are preserved.
+    ///
+    /// Default for all fields.
+    Preserve,
+    /// Fail decoding with `DecodeError::UnknownEnumValue`.
+    Error,
+    /// Skip the value and continue decoding, leaving the field unchanged. Unknown elements of
+    /// repeated fields and `map` entries with unknown values are discarded.
+    Drop,
+    /// Replace the value with the default variant of the enum, which is its first variant.
+    Default,
+}
+
+impl OnOverflow {
+    pub(crate) fn generate_policy(self) -> Option<TokenStream> {
+        match self {
+            OnOverflow::Error => None,
+            OnOverflow::Truncate => Some(quote! { ::micropb::OverflowPolicy::DropExtra }),
+            OnOverflow::TruncateWithFlag => {
+                Some(quote! { ::micropb::OverflowPolicy::DropExtraWithFlag })
+            }
+        }
+    }
+}
+
 macro_rules! config_decl {
     ($($(#[$doc:meta])* $([$placeholder:ident])? $field:ident : $([$placeholder2:ident])? Option<$type:ty>,)+) => {
         #[non_exhaustive]
@@ -89,6 +173,12 @@ macro_rules! config_decl {
                 $(config_decl!(@merge $([$placeholder])? $field, self, other);)+
             }
 
+            /// Like `merge`, except that non-inherited options are only overwritten if they're
+            /// set in `other`, since both configs apply to the same element.
+            pub(crate) fn overlay(&mut self, other: &Self) {
+                $(config_decl!(@merge $field, self, other);)+
+            }
+
             $(config_decl!(@setter $(#[$doc])* $field: $([$placeholder2])? $type);)+
         }
     };
@@ -130,7 +220,8 @@ config_decl! {
     /// the container in the generated code.
     ///
     /// For example, if `vec_type` is `ArrayVec` and `max_len` is 5, then the generated container
-    /// type will be `ArrayVec<_, 5>`.
+    /// type will be `ArrayVec<_, 5>`. See
+    /// [`container_needs_capacity`](Config::container_needs_capacity) for how bounds are checked.
     max_len: Option<u32>,
 
     /// Max number of bytes for fixed-capacity `string` and `bytes` fields.
@@ -141,10 +232,86 @@ config_decl! {
     /// the generated code.
     max_bytes: Option<u32>,
 
+    /// Whether the configured container types need a capacity.
+    ///
+    /// The generator knows that containers from `heapless` and `arrayvec` need a capacity, and that
+    /// containers from `std` and `alloc` don't take one. For other container types, set this
+    /// option to let the generator check the bounds. If it's `true`, generation fails when a field
+    /// that uses the container doesn't have [`max_len`](Config::max_len) or
+    /// [`max_bytes`](Config::max_bytes) configured. If it's `false`, configured bounds are
+    /// ignored with a warning.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // `MyVec<T, N>` is fixed-capacity, so forgetting `max_len` is an error
+    /// gen.configure(".pkg.Message.list", Config::new().vec_type("MyVec").container_needs_capacity(true));
+    /// ```
+    container_needs_capacity: Option<bool>,
+
+    /// Determine what happens when a fixed-capacity repeated or `map` field receives more
+    /// elements than it can hold during decoding.
+    ///
+    /// By default, decoding fails with a capacity error. With the drop policies, the excess
+    /// elements are still consumed from the wire, so the fields that follow are decoded normally.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config, config::OverflowPolicy};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Keep the first `max_len` elements of the list
+    /// gen.configure(".Message.list", Config::new().overflow_policy(OverflowPolicy::DropExtra));
+    /// // Keep the first `max_len` entries of the map, and set `decoder.truncated` on overflow
+    /// gen.configure(".Message.map", Config::new().overflow_policy(OverflowPolicy::DropExtraWithFlag));
+    /// ```
+    overflow_policy: Option<OverflowPolicy>,
+
+    /// Determine what happens when a fixed-capacity `string` or `bytes` field receives more bytes
+    /// than [`max_bytes`](Config::max_bytes) during decoding.
+    ///
+    /// By default, decoding fails with a capacity error. With truncation, the remaining bytes of
+    /// the field are skipped, and strings are cut at a UTF-8 character boundary. A truncated
+    /// optional field still counts as present, so its hazzer bit is set as usual.
+    ///
+    /// Unlike [`overflow_policy`](Config::overflow_policy), this applies to the individual
+    /// `string` and `bytes` values rather than to the number of elements in a container.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config, config::OnOverflow};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Log messages can be cut short
+    /// gen.configure(".Log.message", Config::new().max_bytes(32).on_overflow(OnOverflow::Truncate));
+    /// // Elements of a repeated string field
+    /// gen.configure(".Log.tags.elem", Config::new().on_overflow(OnOverflow::TruncateWithFlag));
+    /// ```
+    on_overflow: Option<OnOverflow>,
+
+    /// Determine what happens when an enum field receives a value that isn't declared in the
+    /// Protobuf enum during decoding, such as a variant added by a newer version of the schema.
+    ///
+    /// By default, unknown values are preserved, since generated enums are open. Otherwise,
+    /// decoding can fail, skip the value, or fall back to the default variant. Skipped values are
+    /// still consumed from the wire, so the fields that follow are decoded normally. This
+    /// configuration only affects decoding; encoding writes whatever value the field holds.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config, config::OnUnknownEnum};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Reject unknown values for all enum fields in the package
+    /// gen.configure(".pkg", Config::new().on_unknown_enum(OnUnknownEnum::Error));
+    /// // Leave the field unset if its value is unknown
+    /// gen.configure(".pkg.Message.state", Config::new().on_unknown_enum(OnUnknownEnum::Drop));
+    /// ```
+    on_unknown_enum: Option<OnUnknownEnum>,
+
     /// Override the integer type of integer fields such as `int32` or `fixed64`.
     ///
     /// Change the integer fields to be 8, 16, 32, or 64 bytes. If the integer type is smaller than
-    /// the value on the wire, the value will be truncated to fit.
+    /// the value on the wire, the value will be truncated to fit. Pointer-sized integers are the
+    /// exception, see [`IntSize::Size`].
     ///
     /// # Example
     /// ```no_run
@@ -167,7 +334,8 @@ config_decl! {
     /// Set attributes for message fields.
     ///
     /// The attribute string will be placed before matched fields. The string must be in the syntax
-    /// of 0 or more Rust attributes.
+    /// of 0 or more outer Rust attributes. Otherwise, generation fails with the position of the
+    /// attribute that couldn't be parsed.
     ///
     /// # Example
     /// ```no_run
@@ -210,6 +378,10 @@ config_decl! {
     /// or `u8` for `bytes` fields, and `N` will be [`max_len`](Config::max_len) or
     /// [`max_bytes`](Config::max_bytes) if set.
     ///
+    /// For types whose parameters are in a different order, the string can instead be a template
+    /// containing the placeholders `$T` and `$N`, which are replaced with the element type and
+    /// capacity respectively. A template without `$N` is treated as dynamic-capacity.
+    ///
     /// # Example
     /// ```no_run
     /// # use micropb_gen::{Generator, Config, config::IntSize};
@@ -218,6 +390,8 @@ config_decl! {
     /// gen.configure(".pkg.Message.bytes_field", Config::new().vec_type("Vec"));
     /// // repeated field configured to `arrayvec::ArrayVec<T, 5>` (fixed-capacity)
     /// gen.configure(".pkg.Message.list", Config::new().vec_type("arrayvec::ArrayVec").max_len(5));
+    /// // repeated field configured to `MyVec<5, T>` using a template
+    /// gen.configure(".pkg.Message.list", Config::new().vec_type("MyVec<$N, $T>").max_len(5));
     /// ```
     vec_type: [deref] Option<String>,
 
@@ -232,6 +406,9 @@ config_decl! {
     /// filled in by the generator. Specifically, `N` will be [`max_bytes`](Config::max_bytes) if
     /// set.
     ///
+    /// For types that take other parameters, the string can instead be a template containing the
+    /// placeholder `$N`, which is replaced with the capacity.
+    ///
     /// # Example
     /// ```no_run
     /// # use micropb_gen::{Generator, Config, config::IntSize};
@@ -240,9 +417,93 @@ config_decl! {
     /// gen.configure(".pkg.Message.string_field", Config::new().string_type("String"));
     /// // `string` field configured to `ArrayString<4>` (fixed-capacity)
     /// gen.configure(".pkg.Message.string_field", Config::new().string_type("ArrayString").max_bytes(4));
+    /// // `string` field configured to `PoolString<'static, 4>` using a template
+    /// gen.configure(
+    ///     ".pkg.Message.string_field",
+    ///     Config::new().string_type("PoolString<'static, $N>").max_bytes(4),
+    /// );
     /// ```
     string_type: [deref] Option<String>,
 
+    /// Represent `string` fields with the byte container from [`vec_type`](Config::vec_type)
+    /// instead of [`string_type`](Config::string_type).
+    ///
+    /// Strings represented as bytes aren't validated as UTF-8 when decoding, which saves time
+    /// when the peer is trusted and prevents invalid strings from failing the decode. The field
+    /// is still encoded the same way as a `string`, so the wire format is unaffected.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // `string` field is generated as a `Vec<u8>`
+    /// gen.configure(".pkg.Message.log_text", Config::new().string_as_bytes(true).vec_type("Vec"));
+    /// ```
+    string_as_bytes: Option<bool>,
+
+    /// Decode message fields lazily.
+    ///
+    /// A lazy message field stores the raw encoded bytes of the message in the byte container
+    /// from [`vec_type`](Config::vec_type), rather than decoding the message. The message is
+    /// decoded on demand by calling the generated `decode_<field>` method, and can be set with the
+    /// generated `set_<field>_msg` method, which encodes the message into the field. When encoding,
+    /// the stored bytes are written verbatim.
+    ///
+    /// Repeated occurrences of a lazy field on the wire have their bytes concatenated, which is
+    /// equivalent to merging the messages.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // `payload` field is stored as a `Vec<u8>` and only decoded when needed
+    /// gen.configure(".pkg.Envelope.payload", Config::new().lazy(true).vec_type("Vec"));
+    /// ```
+    lazy: Option<bool>,
+
+    /// Represent fields of the wrapper well-known types, such as `google.protobuf.Int32Value`
+    /// and `google.protobuf.StringValue`, as their wrapped values.
+    ///
+    /// Wrapper messages only exist to make scalars nullable, so instead of generating the nested
+    /// message, the field is generated as the type of its `value` field, with presence tracked by
+    /// [`optional_repr`](Config::optional_repr) like any other message field. Decoding a wrapper
+    /// sets the field, with the value defaulting to zero if the wrapper is empty, and encoding
+    /// writes the wrapper only if the field is set. The wire format is unaffected.
+    ///
+    /// `StringValue` and `BytesValue` fields use the containers from
+    /// [`string_type`](Config::string_type) and [`vec_type`](Config::vec_type), along with
+    /// [`max_bytes`](Config::max_bytes), and integer wrappers respect
+    /// [`int_size`](Config::int_size). In JSON, the fields are written as their bare values,
+    /// which is the canonical JSON form of the wrappers.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // `google.protobuf.UInt32Value limit` is generated as an optional `u32`
+    /// gen.configure(".", Config::new().unwrap_wrappers(true));
+    /// ```
+    unwrap_wrappers: Option<bool>,
+
+    /// Represent a singular `bytes` field as a range into the decoder's input, rather than
+    /// copying the bytes into a container.
+    ///
+    /// The field is generated as a `core::ops::Range<usize>`, along with a `<field>_slice` method
+    /// that retrieves the bytes from the original input buffer. Decoding such a field from a
+    /// reader that isn't a contiguous slice fails with `DecodeError::NonContiguous`. Since the
+    /// bytes aren't stored in the message, messages with such fields can't be encoded, so this
+    /// config requires [`EncodeDecode::DecodeOnly`](crate::EncodeDecode::DecodeOnly).
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config, EncodeDecode};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// gen.encode_decode(EncodeDecode::DecodeOnly);
+    /// // `payload` field is generated as a `Range<usize>`
+    /// gen.configure(".pkg.Message.payload", Config::new().bytes_range(true));
+    /// ```
+    bytes_range: Option<bool>,
+
     /// Container type that's generated for `map` fields. The provided type must implement `PbMap`.
     ///
     /// If the provided type is fixed-capacity, such as `FnvIndexMap`, then it should have type
@@ -254,6 +515,11 @@ config_decl! {
     /// filled in by the generator. Specifically, `K` and `V` will be the key and value types, and
     /// `N` will be [`max_len`](Config::max_len) if set.
     ///
+    /// For map types whose parameters are in a different order or that take extra parameters,
+    /// the string can instead be a template containing the placeholders `$K`, `$V`, and `$N`,
+    /// which are replaced with the key type, value type, and capacity respectively. Templates are
+    /// parsed as Rust types, and using `$N` requires [`max_len`](Config::max_len) to be set.
+    ///
     /// # Example
     /// ```no_run
     /// # use micropb_gen::{Generator, Config, config::IntSize};
@@ -262,9 +528,39 @@ config_decl! {
     /// gen.configure(".pkg.Message.map_field", Config::new().map_type("BTreeMap"));
     /// // `map` field configured to `FnvIndexMap<K, V, 4>` (fixed-capacity)
     /// gen.configure(".pkg.Message.map_field", Config::new().map_type("FnvIndexMap").max_len(4));
+    /// // `map` field configured to `MyMap<4, K, V, MyHasher>` using a template
+    /// gen.configure(
+    ///     ".pkg.Message.map_field",
+    ///     Config::new().map_type("MyMap<$N, $K, $V, MyHasher>").max_len(4),
+    /// );
     /// ```
     map_type: [deref] Option<String>,
 
+    /// Encode the entries of `map` fields in ascending key order.
+    ///
+    /// Unordered containers such as `HashMap` or `FnvIndexMap` iterate in an arbitrary order, so
+    /// equal messages can encode to different bytes. With this option, map entries are sorted by
+    /// key before encoding, so equal messages always produce the same bytes. Since everything
+    /// else is already encoded in a fixed order, this makes the output suitable for hashing or
+    /// change detection. Note that this is only stable across `micropb` versions and
+    /// configurations, not "canonical" Protobuf that's guaranteed to match other
+    /// implementations.
+    ///
+    /// If [`max_len`](Config::max_len) is set, the entries are sorted in a scratch array of
+    /// `max_len` references on the stack. Otherwise, the map is scanned once per entry to find the
+    /// next key in order, which takes quadratic time. Maps that are already ordered, such as
+    /// `BTreeMap`, don't need this option.
+    ///
+    /// Only applies to `map` fields.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// gen.configure(".pkg.Message.map_field", Config::new().sort_map_entries(true));
+    /// ```
+    sort_map_entries: Option<bool>,
+
     /// Determine how optional fields are represented.
     ///
     /// Presence of optional fields is tracked by either a bitfield in the message struct called a
@@ -288,6 +584,80 @@ config_decl! {
     /// ```
     optional_repr: Option<OptionalRepr>,
 
+    /// Always encode fields without explicit presence, even if they hold the default value.
+    ///
+    /// Normally, fields without explicit presence are skipped when encoding if they're zero,
+    /// `false`, or empty, as required by the Protobuf spec. With this option, such fields are
+    /// always written to the wire, which is useful for decoders that expect every field to be
+    /// present. Empty strings and bytes are written as zero-length fields. Fields with explicit
+    /// presence still follow their hazzer or `Option`, and empty repeated and `map` fields are
+    /// still skipped, since they have no elements to write.
+    ///
+    /// Set this on a message to apply it to all of its fields.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// gen.configure(".pkg.Status", Config::new().emit_defaults(true));
+    /// ```
+    emit_defaults: Option<bool>,
+
+    /// Pass the elements of a repeated field to the message's visitor instead of storing them.
+    ///
+    /// A visited field isn't included in the message struct. Instead, the message gets a field
+    /// named `_visitor` of the type configured by [`visitor`](Config::visitor), which must
+    /// implement the `_Visitor` trait generated in the message's module. The trait has an
+    /// `on_<field>` method for each visited field, which is called with every element decoded from
+    /// the wire, so elements never need to be kept in memory. When encoding, the elements are
+    /// produced by the trait's `for_each_<field>` method, which produces no elements by default.
+    /// Visited fields are always encoded unpacked.
+    ///
+    /// `for_each_<field>` is called once per traversal of the field, so it must produce the same
+    /// elements every time it's called. Computing the message's size traverses the field, so
+    /// `compute_size` followed by `encode` calls `for_each_<field>` twice. This also happens when
+    /// the message is nested inside another message or encoded with `encode_len_delimited`, since
+    /// the length prefix is computed before the message is written. Encoding with
+    /// `BackpatchWriter` avoids the extra traversal, since it fills in length prefixes after
+    /// writing the message. Reverse encoding doesn't, because elements can only be produced
+    /// front-to-back.
+    ///
+    /// Only applies to repeated fields, not including `map` fields.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// gen.configure(".Telemetry.batches", Config::new().visit(true));
+    /// gen.configure(".Telemetry", Config::new().visitor("crate::BatchProcessor"));
+    /// ```
+    /// ```ignore
+    /// // Generated trait
+    /// pub trait _Visitor {
+    ///     fn on_batches(&mut self, elem: &SampleBatch);
+    ///     fn for_each_batches<E>(&self, f: impl FnMut(&SampleBatch) -> Result<(), E>) -> Result<(), E>;
+    /// }
+    /// ```
+    visit: Option<bool>,
+
+    /// Whether elements of a visited field are passed to the visitor when decoding.
+    ///
+    /// If set to false, occurrences of the field on the wire are skipped without being decoded,
+    /// and the `on_<field>` method isn't generated. This is useful for fields that are only ever
+    /// produced by the message, such as a stream of outgoing samples. Encoding still uses
+    /// `for_each_<field>`. Defaults to true.
+    ///
+    /// Only applies to fields with [`visit`](Config::visit) set.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Only encode `points`, ignoring it when decoding
+    /// gen.configure(".Report.points", Config::new().visit(true).visit_decode(false));
+    /// ```
+    visit_decode: Option<bool>,
+
     /// Replace generated field with an user-provided type. See
     /// [`CustomField`](crate::config::CustomField) for more info.
     ///
@@ -345,6 +715,18 @@ config_decl! {
     /// not propagated to "children" paths.
     [no_inherit] rename_field: [deref] Option<String>,
 
+    /// Leave a field or oneof out of the conversions generated by
+    /// [`domain_type`](Config::domain_type).
+    ///
+    /// When converting from the message, the field isn't read, and when converting to the
+    /// message, the field is left at its default. The domain type must not have a field with the
+    /// same name, unless it's listed in [`domain_extra_fields`](Config::domain_extra_fields).
+    ///
+    /// # Note
+    /// This configuration is only applied to the path passed to `configure`. It is
+    /// not propagated to "children" paths.
+    [no_inherit] domain_skip: Option<bool>,
+
     // Type configs
 
     /// Override the integer size of Protobuf enums.
@@ -356,7 +738,8 @@ config_decl! {
     /// Set attributes for generated types, such as messages and enums.
     ///
     /// The attribute string will be placed before type definitions. The string must be in the
-    /// syntax of 0 or more Rust attributes.
+    /// syntax of 0 or more outer Rust attributes. Otherwise, generation fails with the position of
+    /// the attribute that couldn't be parsed.
     ///
     /// # Example
     /// ```no_run
@@ -390,6 +773,212 @@ config_decl! {
     /// Disable generating `Clone` trait derives for message types.
     no_clone_impl: Option<bool>,
 
+    /// Implement `serde::Serialize` and `serde::Deserialize` on generated types.
+    ///
+    /// Messages are serialized as structs whose keys are the Rust field names. Optional fields
+    /// tracked by the hazzer are serialized as nullable values based on their presence, and the
+    /// hazzer itself isn't serialized, nor are the `_unknown` and `_visitor` fields. When
+    /// deserializing, the message starts from its `Default` value, and fields that are missing or
+    /// null are left untouched. Enums are serialized as their integer values, so that unknown
+    /// values survive the round trip, and oneofs are serialized as externally-tagged enums.
+    ///
+    /// This applies to messages, enums, and oneofs, so set it on a package or on `.` to cover
+    /// all types. The crate containing the generated code must depend on `serde` with the
+    /// `derive` feature, and all field types must implement the Serde traits. Enable the `serde`
+    /// feature of `micropb` for Serde support on `heapless` and `arrayvec` containers.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Dump all types from the `telemetry` package as JSON on the host
+    /// gen.configure(".telemetry", Config::new().serde(true));
+    /// ```
+    serde: Option<bool>,
+
+    /// Derive `defmt::Format` on generated types, behind the `defmt` feature.
+    ///
+    /// The derives are wrapped in `#[cfg_attr(feature = "defmt", ...)]`, so the crate that
+    /// includes the generated code must declare a `defmt` feature that enables its `defmt`
+    /// dependency. Host builds that leave the feature off don't need `defmt` at all.
+    ///
+    /// This applies to messages, enums, oneofs, and hazzers, so set it on a package or on `.` to
+    /// cover all types. All field types must implement `Format`, except for the `_unknown` field,
+    /// which is formatted with `defmt::Debug2Format`. The message's visitor must implement
+    /// `Format` as well.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// gen.configure(".", Config::new().defmt(true));
+    /// ```
+    defmt: Option<bool>,
+
+    /// Implement `micropb::json::MessageToJson` and `micropb::json::MessageFromJson` on messages
+    /// and `micropb::json::JsonEnum` on enums, which convert them to and from the canonical
+    /// Protobuf JSON format.
+    ///
+    /// Fields are written with their JSON names, which are the `json_name` options if set, or the
+    /// lowerCamelCase forms of the field names. Both the JSON names and the original names are
+    /// accepted when parsing. Renaming the Rust fields doesn't change the JSON names. Requires the
+    /// `json` feature of `micropb`.
+    ///
+    /// Custom fields, visited fields, lazy message fields, and
+    /// [`bytes_range`](Config::bytes_range) fields are skipped, as are map fields with
+    /// `bytes`-backed string keys and `string` or `bytes` fields whose types have lifetimes.
+    /// Parsing treats the keys of skipped fields as unknown. Well-known types are handled as
+    /// regular messages, without their special JSON forms.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// gen.configure(".", Config::new().json(true));
+    /// ```
+    json: Option<bool>,
+
+    /// Generate constants with the Protobuf name and JSON name of each field of a message.
+    ///
+    /// The constants are placed in the `_field_names` and `_json_names` modules inside the
+    /// message's module, and are named after the fields in upper snake case. The JSON names are
+    /// the `json_name` options of the fields if set, or the lowerCamelCase forms of the field
+    /// names, computed when generating the code. This lets hand-written code refer to fields by
+    /// name, such as when converting to other formats.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// gen.configure(".Reading", Config::new().field_name_consts(true));
+    /// // `Reading_::_json_names::SENSOR_ID` is now `"sensorId"`
+    /// ```
+    field_name_consts: Option<bool>,
+
+    /// Implement `micropb::MessageFields` on messages, which provides a static table describing
+    /// each field.
+    ///
+    /// Each entry of the table records the field number, Protobuf name, wire type, and whether the
+    /// field is repeated, a map, or part of a oneof. Together with `MessageName::FULL_NAME`, this
+    /// lets generic code such as debug dumps or protocol analyzers label fields without a
+    /// descriptor set. Entries are sorted by field number. Skipped fields are left out, and
+    /// renaming a field doesn't change its recorded name. Custom fields and custom oneofs are also
+    /// left out, since their encoding is determined by user code.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// gen.configure(".", Config::new().field_descriptors(true));
+    /// ```
+    field_descriptors: Option<bool>,
+
+    /// Include the Protobuf names of the fields in the tables generated by
+    /// [`field_descriptors`](Config::field_descriptors). Defaults to true.
+    ///
+    /// Disabling this sets every name in the tables to `None`, so the name strings don't take up
+    /// flash space.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// gen.configure(".", Config::new().field_descriptors(true).field_descriptor_names(false));
+    /// ```
+    field_descriptor_names: Option<bool>,
+
+    /// Implement `micropb::text::MessageToText` and `micropb::text::MessageFromText` on messages
+    /// and `micropb::text::TextEnum` on enums, which write and parse them in the Protobuf text
+    /// format used by `protoc --encode` and `protoc --decode`.
+    ///
+    /// Fields are written with their names in the `.proto` file and enums with their value names.
+    /// Fields without presence that hold their default values are omitted, as are unset optional
+    /// fields. Parsing accepts enums as names or numbers. Requires the `text` feature of
+    /// `micropb`.
+    ///
+    /// Custom fields, visited fields, lazy message fields, and
+    /// [`bytes_range`](Config::bytes_range) fields are skipped. Parsing also skips `string` and
+    /// `bytes` fields whose types have lifetimes, and treats the names of skipped fields as
+    /// unknown.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// gen.configure(".", Config::new().text(true));
+    /// ```
+    text: Option<bool>,
+
+    /// Implement `micropb::field_mask::ApplyFieldMask` on messages, which copies the fields named
+    /// by the paths of a `google.protobuf.FieldMask` from one message into another.
+    ///
+    /// Paths use the field names in the `.proto` file, and members of oneofs are named directly.
+    /// Paths that continue past a message field apply to the fields of the nested message, so the
+    /// nested message type must also have `field_mask` set. Optional fields are copied along with
+    /// their presence, and repeated and `map` fields are replaced as a whole. Copying requires the
+    /// field types to implement `Clone`. Requires the `field-mask` feature of `micropb`.
+    ///
+    /// Custom fields, visited fields, and [`bytes_range`](Config::bytes_range) fields can't be
+    /// named by paths, and neither can the fields of messages inside oneofs.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// gen.configure(".", Config::new().field_mask(true));
+    /// ```
+    field_mask: Option<bool>,
+
+    /// Implement `arbitrary::Arbitrary` on generated types when compiled with `--cfg fuzzing`,
+    /// which `cargo fuzz` sets, for structured fuzzing.
+    ///
+    /// Strings, bytes, repeated fields, and `map` fields are truncated to fit the capacity of
+    /// their containers, rather than failing. The presence of optional fields is chosen
+    /// independently of their values, and nested messages are only generated up to 4 levels
+    /// deep, so recursive messages stay small. Enums derive `Arbitrary`, so unknown values are
+    /// generated as well.
+    ///
+    /// This applies to messages, enums, and oneofs, so set it on a package or on `.` to cover
+    /// all types. Nested message types must also have `arbitrary` set. Fields of extern types
+    /// are left at their defaults, while custom fields must implement `Arbitrary`. Messages
+    /// must implement `Default`. The crate containing the generated code must depend on
+    /// `arbitrary` with the `derive` feature when fuzzing, and should declare the `fuzzing` cfg
+    /// with `check-cfg` to avoid `unexpected_cfgs` warnings.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// gen.configure(".", Config::new().arbitrary(true));
+    /// ```
+    arbitrary: Option<bool>,
+
+    /// Generate `arbitrary_strategy` constructors returning `proptest` strategies on messages,
+    /// enums, and oneofs, behind the `proptest` feature.
+    ///
+    /// Message strategies compose the strategies of their fields, so values shrink the way
+    /// `proptest` shrinks tuples and collections. Strings, bytes, repeated fields, and `map`
+    /// fields are generated within their `max_bytes` and `max_len` bounds, or with up to 8
+    /// elements if they're unbounded. Enums only generate their declared variants, oneofs may
+    /// be unset, and nested messages are only generated up to 4 levels deep, so recursive
+    /// messages stay small.
+    ///
+    /// The constructors are wrapped in `#[cfg(feature = "proptest")]`, so the crate that
+    /// includes the generated code must declare a `proptest` feature that enables its
+    /// `proptest` dependency. Nested message types must also have `proptest` set, and messages
+    /// must implement `Default` and `Debug`. Fields of extern types are left at their defaults,
+    /// while custom fields must implement `proptest::arbitrary::Arbitrary`. Messages with
+    /// lifetimes are skipped. String strategies are built from `alloc::string::String`, so the
+    /// crate must also declare `extern crate alloc`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// gen.configure(".", Config::new().proptest(true));
+    /// ```
+    proptest: Option<bool>,
+
     /// Add a custom handler on a message struct for handling unknown fields.
     ///
     /// When decoding a message, unknown fields are skipped by default. If a message has
@@ -397,8 +986,136 @@ config_decl! {
     /// added to the message struct. This field will handle decoding of all unknown fields and will
     /// also be encoded, so the handler type must implement `FieldEncode` and `FieldDecode`,
     /// like with [`custom_field`](Config::custom_field).
+    ///
+    /// The unknown fields are encoded after all other fields of the message, unless
+    /// [`canonical_order`](Config::canonical_order) is set. To preserve unknown fields across
+    /// decode-encode cycles, use `micropb::UnknownFields`, which writes them back out in the order
+    /// they were decoded.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Keep the unknown fields of `Packet`, so that forwarded packets aren't modified
+    /// gen.configure(
+    ///     ".Packet",
+    ///     Config::new().unknown_handler("micropb::UnknownFields<Vec<u8>>"),
+    /// );
+    /// ```
     unknown_handler: [deref] Option<String>,
 
+    /// Type of the `_visitor` field that handles the message's visited fields. See
+    /// [`visit`](Config::visit) for more info.
+    ///
+    /// The visitor is only added to messages that have visited fields. It's not reset when the
+    /// message is cleared, and must implement any traits derived by the message, such as `Debug`
+    /// and `Clone`.
+    visitor: [deref] Option<String>,
+
+    /// Encode the fields of a message in ascending order of field number.
+    ///
+    /// By default, fields are encoded in declaration order, followed by the oneofs and then the
+    /// unknown fields. With this option, everything is encoded in order of field number,
+    /// regardless of declaration order, which is needed by schemes that sign or hash encoded
+    /// messages. Each oneof variant is encoded at its own field number. Custom fields are encoded
+    /// at their own field number, along with any fields delegated to them.
+    ///
+    /// Unknown fields are interleaved with the other fields at their numeric positions if the
+    /// [`unknown_handler`](Config::unknown_handler) overrides `FieldEncode::encode_fields_in`,
+    /// as `micropb::UnknownFields` does. Otherwise, they're encoded after all other fields.
+    ///
+    /// This applies to message types, so set it on a package or on `.` to cover nested messages
+    /// as well. Combine it with [`sort_map_entries`](Config::sort_map_entries) for fully
+    /// deterministic output.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// gen.configure(".signed", Config::new().canonical_order(true));
+    /// ```
+    canonical_order: Option<bool>,
+
+    /// Generate conversions between a message and a user-defined domain type.
+    ///
+    /// The generator implements `TryFrom<Message> for DomainType` and the reverse. Fields are
+    /// mapped to the fields of the domain type with the same name, each converted with
+    /// `TryInto`. Optional fields and oneofs map to `Option`s, and repeated and `map` fields map
+    /// to any collection that can be collected from, and iterated into, their elements. Pushing
+    /// past the capacity of a fixed container is an error. Enums and nested messages use their own
+    /// conversions, such as the ones generated for nested messages with their own domain types.
+    ///
+    /// Both conversions return a generated `DomainError` on failure, which is placed at the root
+    /// of the generated module and names the message and the field that failed to convert. Since
+    /// the conversions name every field of both types, a field that's only present on one side is
+    /// a compile error, unless it's left out with [`domain_skip`](Config::domain_skip) or
+    /// [`domain_extra_fields`](Config::domain_extra_fields).
+    ///
+    /// The message must implement `Default`, and messages with lifetimes aren't supported.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Generates `TryFrom<Sensor> for crate::Sensor` and `TryFrom<crate::Sensor> for Sensor`
+    /// gen.configure(".Sensor", Config::new().domain_type("crate::Sensor"));
+    /// // The raw reading isn't part of the domain type
+    /// gen.configure(".Sensor.raw", Config::new().domain_skip(true));
+    /// ```
+    ///
+    /// # Note
+    /// This configuration is only applied to the path passed to `configure`. It is
+    /// not propagated to "children" paths.
+    [no_inherit] domain_type: [deref] Option<String>,
+
+    /// Comma-separated names of the fields of the [`domain_type`](Config::domain_type) that
+    /// aren't in the message.
+    ///
+    /// These fields are set to their defaults when converting from the message, and ignored when
+    /// converting to it.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// gen.configure(
+    ///     ".Sensor",
+    ///     Config::new()
+    ///         .domain_type("crate::Sensor")
+    ///         .domain_extra_fields("calibrated, last_seen"),
+    /// );
+    /// ```
+    ///
+    /// # Note
+    /// This configuration is only applied to the path passed to `configure`. It is
+    /// not propagated to "children" paths.
+    [no_inherit] domain_extra_fields: [deref] Option<String>,
+
+    /// Generate conversions between messages and the types generated by `prost-build` for the
+    /// same schema, for code bases that use both during a migration.
+    ///
+    /// The value is the Rust path of the module that `prost-build` generated for the message's
+    /// package. The generator implements `From` from each message into its `prost` counterpart,
+    /// which is found using `prost-build`'s naming rules, and `TryFrom` in the other direction,
+    /// as well as between their oneof enums. The conversions bridge the representation differences between the two:
+    /// hazzers and `Option`s, fixed containers and `Vec`/`String`/`HashMap`, and open enums and
+    /// `i32`. Fields boxed in micropb are expected to be boxed in `prost` as well, which is the
+    /// case for the recursive fields that `prost-build` boxes automatically.
+    ///
+    /// The conversions require `alloc`. Converting from `prost` returns
+    /// `micropb::ConversionError` if a value doesn't fit in a fixed-capacity container, or in an
+    /// integer field configured with a smaller [`int_size`](Config::int_size). Custom fields,
+    /// visited fields, lazy messages, and `bytes_range` fields are not supported.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Config};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // The prost types of package `sensor` are in `crate::pb::sensor`
+    /// gen.configure(".sensor", Config::new().prost_path("crate::pb::sensor"));
+    /// ```
+    prost_path: [deref] Option<String>,
+
     // General configs
 
     /// Skip generating a type or field
@@ -408,17 +1125,124 @@ config_decl! {
     skip: Option<bool>,
 }
 
-struct Attributes(Vec<syn::Attribute>);
+/// Line and column of a byte offset within `s`, for error messages
+fn str_position(s: &str, offset: usize) -> String {
+    let before = &s[..offset];
+    let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+    match before.matches('\n').count() {
+        0 => format!("column {column}"),
+        lines => format!("line {}, column {column}", lines + 1),
+    }
+}
+
+/// Find the end of the bracketed attribute body starting at `s[0] == '['`, skipping over string
+/// and char literals
+fn attribute_end(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '[' | '(' | '{' => depth += 1,
+            ']' | ')' | '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            '"' => loop {
+                match chars.next()?.1 {
+                    '\\' => {
+                        chars.next()?;
+                    }
+                    '"' => break,
+                    _ => (),
+                }
+            },
+            // Char literals, which can't be confused with lifetimes since they're closed by `'`
+            '\'' if s[i..].starts_with("'\\") || s[i + 1..].chars().nth(1) == Some('\'') => {
+                if chars.next()?.1 == '\\' {
+                    chars.next()?;
+                }
+                chars.find(|&(_, c)| c == '\'')?;
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Parse a string of outer attributes, such as `#[derive(Eq)] #[cfg(test)]`. Each attribute is
+/// parsed separately, so errors point to the attribute that failed and its position.
+pub(crate) fn parse_attributes(s: &str) -> Result<Vec<syn::Attribute>, String> {
+    let mut attrs = vec![];
+    let mut pos = 0;
+    loop {
+        pos += s[pos..].len() - s[pos..].trim_start().len();
+        let rest = &s[pos..];
+        if rest.is_empty() {
+            return Ok(attrs);
+        }
 
-impl syn::parse::Parse for Attributes {
-    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
-        Ok(Self(input.call(syn::Attribute::parse_outer)?))
+        let Some(after_pound) = rest.strip_prefix('#') else {
+            let found = rest.split_whitespace().next().unwrap_or_default();
+            return Err(format!(
+                "expected an attribute starting with `#` at {}, found `{found}`",
+                str_position(s, pos)
+            ));
+        };
+        let body = after_pound.trim_start();
+        if body.starts_with('!') {
+            return Err(format!(
+                "inner attribute at {} isn't allowed, only outer attributes `#[...]` are",
+                str_position(s, pos)
+            ));
+        }
+        if !body.starts_with('[') {
+            return Err(format!(
+                "expected `[` after `#` at {}",
+                str_position(s, pos)
+            ));
+        }
+        let Some(body_len) = attribute_end(body) else {
+            return Err(format!(
+                "unbalanced delimiters in attribute at {}",
+                str_position(s, pos)
+            ));
+        };
+        let len = rest.len() - body.len() + body_len;
+        let text = &rest[..len];
+        let attr = syn::Attribute::parse_outer.parse_str(text).map_err(|e| {
+            format!(
+                "invalid attribute `{text}` at {}: {e}",
+                str_position(s, pos)
+            )
+        })?;
+        attrs.extend(attr);
+        pos += len;
     }
 }
 
-pub(crate) fn parse_attributes(s: &str) -> syn::Result<Vec<syn::Attribute>> {
-    let attrs: Attributes = syn::parse_str(s)?;
-    Ok(attrs.0)
+/// Whether a well-known container type needs a capacity parameter, or `None` if it's unknown
+fn builtin_needs_capacity(container: &str) -> Option<bool> {
+    // Templates take a capacity exactly when they have a placeholder for it
+    if container.contains('$') {
+        return Some(container.contains("$N"));
+    }
+    let mut segments = container
+        .split("::")
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .peekable();
+    let first = *segments.peek()?;
+    if segments.clone().any(|s| s == "heapless" || s == "arrayvec")
+        || matches!(segments.last(), Some("ArrayVec" | "ArrayString"))
+    {
+        Some(true)
+    } else if first == "std" || first == "alloc" {
+        Some(false)
+    } else {
+        None
+    }
 }
 
 impl Config {
@@ -449,32 +1273,104 @@ impl Config {
         }
     }
 
-    pub(crate) fn vec_type_parsed(&self) -> Result<Option<syn::Path>, String> {
+    /// Parse `vec_type`, substituting `$N` with `capacity`, which is either `max_len` or
+    /// `max_bytes` depending on what the container is used for
+    pub(crate) fn vec_type_parsed(
+        &self,
+        capacity: Option<u32>,
+    ) -> Result<Option<ContainerType>, String> {
         self.vec_type
             .as_ref()
             .map(|t| {
-                syn::parse_str(t)
-                    .map_err(|e| format!("Failed to parse vec_type \"{t}\" as type path: {e}"))
+                ContainerType::parse(
+                    "vec_type",
+                    t,
+                    &[("T", None), ("N", capacity.map(|n| n.to_string()))],
+                )
             })
             .transpose()
     }
 
-    pub(crate) fn string_type_parsed(&self) -> Result<Option<syn::Path>, String> {
+    pub(crate) fn string_type_parsed(&self) -> Result<Option<ContainerType>, String> {
         self.string_type
             .as_ref()
             .map(|t| {
-                syn::parse_str(t)
-                    .map_err(|e| format!("Failed to parse string_type \"{t}\" as type path: {e}"))
+                ContainerType::parse(
+                    "string_type",
+                    t,
+                    &[("N", self.max_bytes.map(|n| n.to_string()))],
+                )
             })
             .transpose()
     }
 
-    pub(crate) fn map_type_parsed(&self) -> Result<Option<syn::Path>, String> {
+    /// Resolve the capacity parameter of a container type from its configured bound.
+    ///
+    /// Fails if the container needs a capacity but the bound isn't set. If the container doesn't
+    /// take a capacity, the bound is dropped.
+    pub(crate) fn container_capacity(
+        &self,
+        container_opt: &str,
+        container: &str,
+        bound_opt: &str,
+        bound: Option<u32>,
+    ) -> Result<Option<u32>, String> {
+        match (
+            self.container_needs_capacity
+                .or_else(|| builtin_needs_capacity(container)),
+            bound,
+        ) {
+            (Some(true), None) => Err(format!(
+                "{container_opt} `{container}` needs a capacity, but {bound_opt} was not configured for it"
+            )),
+            (Some(false), Some(_)) => Ok(None),
+            (_, bound) => Ok(bound),
+        }
+    }
+
+    /// Bound options that are set but ignored, because none of the container types they apply to
+    /// take a capacity
+    pub(crate) fn ignored_bounds(&self) -> Vec<&'static str> {
+        let ignored = |bound: Option<u32>, containers: [Option<&str>; 2]| {
+            bound.is_some() && {
+                let mut containers = containers.into_iter().flatten().peekable();
+                containers.peek().is_some()
+                    && containers.all(|c| {
+                        self.container_needs_capacity
+                            .or_else(|| builtin_needs_capacity(c))
+                            == Some(false)
+                    })
+            }
+        };
+        let mut out = vec![];
+        if ignored(
+            self.max_len,
+            [self.vec_type.as_deref(), self.map_type.as_deref()],
+        ) {
+            out.push("max_len");
+        }
+        if ignored(
+            self.max_bytes,
+            [self.string_type.as_deref(), self.vec_type.as_deref()],
+        ) {
+            out.push("max_bytes");
+        }
+        out
+    }
+
+    pub(crate) fn map_type_parsed(&self) -> Result<Option<ContainerType>, String> {
         self.map_type
             .as_ref()
             .map(|t| {
-                syn::parse_str(t)
-                    .map_err(|e| format!("Failed to parse map_type \"{t}\" as type path: {e}"))
+                ContainerType::parse(
+                    "map_type",
+                    t,
+                    &[
+                        ("K", None),
+                        ("V", None),
+                        ("N", self.max_len.map(|n| n.to_string())),
+                    ],
+                )
             })
             .transpose()
     }
@@ -490,6 +1386,52 @@ impl Config {
             .transpose()
     }
 
+    pub(crate) fn visitor_parsed(&self) -> Result<Option<syn::Type>, String> {
+        self.visitor
+            .as_ref()
+            .map(|t| {
+                syn::parse_str(t)
+                    .map_err(|e| format!("Failed to parse visitor \"{t}\" as Rust type: {e}"))
+            })
+            .transpose()
+    }
+
+    pub(crate) fn domain_type_parsed(&self) -> Result<Option<syn::Type>, String> {
+        self.domain_type
+            .as_ref()
+            .map(|t| {
+                syn::parse_str(t)
+                    .map_err(|e| format!("Failed to parse domain_type \"{t}\" as Rust type: {e}"))
+            })
+            .transpose()
+    }
+
+    pub(crate) fn prost_path_parsed(&self) -> Result<Option<syn::Path>, String> {
+        self.prost_path
+            .as_ref()
+            .map(|p| {
+                syn::parse_str(p)
+                    .map_err(|e| format!("Failed to parse prost_path \"{p}\" as Rust path: {e}"))
+            })
+            .transpose()
+    }
+
+    pub(crate) fn domain_extra_fields_parsed(&self) -> Result<Vec<syn::Ident>, String> {
+        let Some(fields) = &self.domain_extra_fields else {
+            return Ok(vec![]);
+        };
+        fields
+            .split(',')
+            .map(str::trim)
+            .filter(|f| !f.is_empty())
+            .map(|f| {
+                syn::parse_str::<syn::Ident>(f).map_err(|e| {
+                    format!("Failed to parse domain_extra_fields entry \"{f}\" as field name: {e}")
+                })
+            })
+            .collect()
+    }
+
     pub(crate) fn custom_field_parsed(
         &self,
     ) -> Result<Option<crate::generator::field::CustomField>, String> {
@@ -533,6 +1475,12 @@ mod tests {
         assert!(mergee.max_len.is_none());
         // rename_field gets overwritten unconditionally when merging
         assert!(mergee.rename_field.is_none());
+
+        let mut overlayed = Config::new().rename_field("rename").skip(true);
+        overlayed.overlay(&Config::new().skip(false));
+        assert!(!overlayed.skip.unwrap());
+        // rename_field is kept, since the overlay doesn't set it
+        assert_eq!(overlayed.rename_field.unwrap(), "rename");
     }
 
     #[test]
@@ -545,7 +1493,7 @@ mod tests {
 
         assert_eq!(
             config
-                .vec_type_parsed()
+                .vec_type_parsed(None)
                 .unwrap()
                 .to_token_stream()
                 .to_string(),
@@ -611,4 +1559,88 @@ mod tests {
         };
         assert_eq!(del, format_ident!("name"));
     }
+
+    #[test]
+    fn attributes() {
+        assert!(parse_attributes("").unwrap().is_empty());
+        let attrs = parse_attributes("#[serde(skip)]").unwrap();
+        assert_eq!(
+            quote! { #(#attrs)* }.to_string(),
+            quote! { #[serde(skip)] }.to_string()
+        );
+        let attrs = parse_attributes("#[serde(skip)]\n #[cfg(feature = \"x\")]").unwrap();
+        assert_eq!(
+            quote! { #(#attrs)* }.to_string(),
+            quote! { #[serde(skip)] #[cfg(feature = "x")] }.to_string()
+        );
+        let attrs = parse_attributes(r#"#[doc = "] \" ["] #[sep(']', '\'')]"#).unwrap();
+        assert_eq!(attrs.len(), 2);
+
+        let err = parse_attributes("#[serde(skip)] #[cfg(feature = \"x\"]").unwrap_err();
+        assert_eq!(err, "unbalanced delimiters in attribute at column 16");
+        let err = parse_attributes("#[serde(skip)] #[cfg(feature = \"]\"").unwrap_err();
+        assert_eq!(err, "unbalanced delimiters in attribute at column 16");
+        let err = parse_attributes("#[serde(skip)] #[cfg(feature = \"x\") test]").unwrap_err();
+        assert!(
+            err.starts_with("invalid attribute `#[cfg(feature = \"x\") test]` at column 16"),
+            "{err}"
+        );
+        let err = parse_attributes("#[a]\n#[b = ]").unwrap_err();
+        assert!(
+            err.starts_with("invalid attribute `#[b = ]` at line 2, column 1"),
+            "{err}"
+        );
+        let err = parse_attributes("#![no_std]").unwrap_err();
+        assert!(err.starts_with("inner attribute at column 1"), "{err}");
+        let err = parse_attributes("#[a] derive(Eq)").unwrap_err();
+        assert_eq!(
+            err,
+            "expected an attribute starting with `#` at column 6, found `derive(Eq)`"
+        );
+        let err = parse_attributes("#[a] #").unwrap_err();
+        assert_eq!(err, "expected `[` after `#` at column 6");
+    }
+
+    #[test]
+    fn container_capacity() {
+        assert_eq!(
+            builtin_needs_capacity("::micropb::heapless::Vec"),
+            Some(true)
+        );
+        assert_eq!(builtin_needs_capacity("arrayvec::ArrayString"), Some(true));
+        assert_eq!(builtin_needs_capacity("ArrayVec"), Some(true));
+        assert_eq!(builtin_needs_capacity("::alloc::vec::Vec"), Some(false));
+        assert_eq!(builtin_needs_capacity("std::string::String"), Some(false));
+        assert_eq!(builtin_needs_capacity("MyVec"), None);
+
+        let config = Config::new().vec_type("heapless::Vec");
+        config
+            .container_capacity("vec_type", "heapless::Vec", "max_len", None)
+            .unwrap_err();
+        assert_eq!(
+            config.container_capacity("vec_type", "heapless::Vec", "max_len", Some(3)),
+            Ok(Some(3))
+        );
+        assert_eq!(
+            config.container_capacity("vec_type", "::std::vec::Vec", "max_len", Some(3)),
+            Ok(None)
+        );
+        assert_eq!(
+            config.container_capacity("vec_type", "MyVec", "max_len", None),
+            Ok(None)
+        );
+        let config = Config::new().container_needs_capacity(true);
+        config
+            .container_capacity("vec_type", "MyVec", "max_len", None)
+            .unwrap_err();
+
+        let config = Config::new()
+            .vec_type("::std::vec::Vec")
+            .string_type("heapless::String")
+            .max_len(3)
+            .max_bytes(4);
+        assert_eq!(config.ignored_bounds(), vec!["max_len"]);
+        let config = config.container_needs_capacity(false);
+        assert_eq!(config.ignored_bounds(), vec!["max_len", "max_bytes"]);
+    }
 }
@@ -35,7 +35,7 @@ pub mod google_ {
                                 self.r#file.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#file.len()));
                             }
                         }
                         _ => {
@@ -402,7 +402,7 @@ pub mod google_ {
                                 self.r#dependency.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#dependency.len()));
                             }
                         }
                         10u32 => {
@@ -419,7 +419,7 @@ pub mod google_ {
                                         .pb_push(decoder.decode_int32()? as _),
                                     decoder.ignore_repeated_cap_err,
                                 ) {
-                                    return Err(::micropb::DecodeError::Capacity);
+                                    return Err(::micropb::DecodeError::capacity(1, self.r#public_dependency.len()));
                                 }
                             }
                         }
@@ -437,7 +437,7 @@ pub mod google_ {
                                         .pb_push(decoder.decode_int32()? as _),
                                     decoder.ignore_repeated_cap_err,
                                 ) {
-                                    return Err(::micropb::DecodeError::Capacity);
+                                    return Err(::micropb::DecodeError::capacity(1, self.r#weak_dependency.len()));
                                 }
                             }
                         }
@@ -451,7 +451,7 @@ pub mod google_ {
                                 self.r#message_type.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#message_type.len()));
                             }
                         }
                         5u32 => {
@@ -464,7 +464,7 @@ pub mod google_ {
                                 self.r#enum_type.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#enum_type.len()));
                             }
                         }
                         6u32 => {
@@ -477,7 +477,7 @@ pub mod google_ {
                                 self.r#service.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#service.len()));
                             }
                         }
                         7u32 => {
@@ -490,7 +490,7 @@ pub mod google_ {
                                 self.r#extension.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#extension.len()));
                             }
                         }
                         8u32 => {
@@ -1043,7 +1043,7 @@ pub mod google_ {
                                 self.r#field.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#field.len()));
                             }
                         }
                         6u32 => {
@@ -1056,7 +1056,7 @@ pub mod google_ {
                                 self.r#extension.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#extension.len()));
                             }
                         }
                         3u32 => {
@@ -1069,7 +1069,7 @@ pub mod google_ {
                                 self.r#nested_type.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#nested_type.len()));
                             }
                         }
                         4u32 => {
@@ -1082,7 +1082,7 @@ pub mod google_ {
                                 self.r#enum_type.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#enum_type.len()));
                             }
                         }
                         5u32 => {
@@ -1095,7 +1095,7 @@ pub mod google_ {
                                 self.r#extension_range.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#extension_range.len()));
                             }
                         }
                         8u32 => {
@@ -1108,7 +1108,7 @@ pub mod google_ {
                                 self.r#oneof_decl.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#oneof_decl.len()));
                             }
                         }
                         7u32 => {
@@ -1128,7 +1128,7 @@ pub mod google_ {
                                 self.r#reserved_range.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#reserved_range.len()));
                             }
                         }
                         10u32 => {
@@ -1142,7 +1142,7 @@ pub mod google_ {
                                 self.r#reserved_name.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#reserved_name.len()));
                             }
                         }
                         _ => {
@@ -1629,7 +1629,7 @@ pub mod google_ {
                                 self.r#uninterpreted_option.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#uninterpreted_option.len()));
                             }
                         }
                         2u32 => {
@@ -1642,7 +1642,7 @@ pub mod google_ {
                                 self.r#declaration.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#declaration.len()));
                             }
                         }
                         50u32 => {
@@ -2825,7 +2825,7 @@ pub mod google_ {
                                 self.r#value.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#value.len()));
                             }
                         }
                         3u32 => {
@@ -2845,7 +2845,7 @@ pub mod google_ {
                                 self.r#reserved_range.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#reserved_range.len()));
                             }
                         }
                         5u32 => {
@@ -2859,7 +2859,7 @@ pub mod google_ {
                                 self.r#reserved_name.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#reserved_name.len()));
                             }
                         }
                         _ => {
@@ -3221,7 +3221,7 @@ pub mod google_ {
                                 self.r#method.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#method.len()));
                             }
                         }
                         3u32 => {
@@ -4837,7 +4837,7 @@ pub mod google_ {
                                 self.r#uninterpreted_option.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#uninterpreted_option.len()));
                             }
                         }
                         _ => {
@@ -5237,7 +5237,7 @@ pub mod google_ {
                                 self.r#uninterpreted_option.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#uninterpreted_option.len()));
                             }
                         }
                         _ => {
@@ -6425,7 +6425,7 @@ pub mod google_ {
                                         ),
                                     decoder.ignore_repeated_cap_err,
                                 ) {
-                                    return Err(::micropb::DecodeError::Capacity);
+                                    return Err(::micropb::DecodeError::capacity(1, self.r#targets.len()));
                                 }
                             }
                         }
@@ -6439,7 +6439,7 @@ pub mod google_ {
                                 self.r#edition_defaults.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#edition_defaults.len()));
                             }
                         }
                         21u32 => {
@@ -6466,7 +6466,7 @@ pub mod google_ {
                                 self.r#uninterpreted_option.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#uninterpreted_option.len()));
                             }
                         }
                         _ => {
@@ -6573,7 +6573,7 @@ pub mod google_ {
                                 self.r#uninterpreted_option.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#uninterpreted_option.len()));
                             }
                         }
                         _ => {
@@ -6851,7 +6851,7 @@ pub mod google_ {
                                 self.r#uninterpreted_option.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#uninterpreted_option.len()));
                             }
                         }
                         _ => {
@@ -7123,7 +7123,7 @@ pub mod google_ {
                                 self.r#uninterpreted_option.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#uninterpreted_option.len()));
                             }
                         }
                         _ => {
@@ -7284,7 +7284,7 @@ pub mod google_ {
                                 self.r#uninterpreted_option.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#uninterpreted_option.len()));
                             }
                         }
                         _ => {
@@ -7526,7 +7526,7 @@ pub mod google_ {
                                 self.r#uninterpreted_option.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#uninterpreted_option.len()));
                             }
                         }
                         _ => {
@@ -8027,7 +8027,7 @@ pub mod google_ {
                                 self.r#name.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#name.len()));
                             }
                         }
                         3u32 => {
@@ -8955,7 +8955,7 @@ pub mod google_ {
                                 self.r#defaults.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#defaults.len()));
                             }
                         }
                         4u32 => {
@@ -9136,7 +9136,7 @@ pub mod google_ {
                                         self.r#path.pb_push(decoder.decode_int32()? as _),
                                         decoder.ignore_repeated_cap_err,
                                     ) {
-                                        return Err(::micropb::DecodeError::Capacity);
+                                        return Err(::micropb::DecodeError::capacity(1, self.r#path.len()));
                                     }
                                 }
                             }
@@ -9152,7 +9152,7 @@ pub mod google_ {
                                         self.r#span.pb_push(decoder.decode_int32()? as _),
                                         decoder.ignore_repeated_cap_err,
                                     ) {
-                                        return Err(::micropb::DecodeError::Capacity);
+                                        return Err(::micropb::DecodeError::capacity(1, self.r#span.len()));
                                     }
                                 }
                             }
@@ -9183,7 +9183,7 @@ pub mod google_ {
                                     self.r#leading_detached_comments.pb_push(val),
                                     decoder.ignore_repeated_cap_err,
                                 ) {
-                                    return Err(::micropb::DecodeError::Capacity);
+                                    return Err(::micropb::DecodeError::capacity(1, self.r#leading_detached_comments.len()));
                                 }
                             }
                             _ => {
@@ -9229,7 +9229,7 @@ pub mod google_ {
                                 self.r#location.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#location.len()));
                             }
                         }
                         _ => {
@@ -9496,7 +9496,7 @@ pub mod google_ {
                                         self.r#path.pb_push(decoder.decode_int32()? as _),
                                         decoder.ignore_repeated_cap_err,
                                     ) {
-                                        return Err(::micropb::DecodeError::Capacity);
+                                        return Err(::micropb::DecodeError::capacity(1, self.r#path.len()));
                                     }
                                 }
                             }
@@ -9577,7 +9577,7 @@ pub mod google_ {
                                 self.r#annotation.pb_push(val),
                                 decoder.ignore_repeated_cap_err,
                             ) {
-                                return Err(::micropb::DecodeError::Capacity);
+                                return Err(::micropb::DecodeError::capacity(1, self.r#annotation.len()));
                             }
                         }
                         _ => {
@@ -1,30 +1,31 @@
 use std::{
     borrow::{Borrow, Cow},
-    cell::RefCell,
-    collections::HashMap,
+    cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
     ffi::OsString,
-    io,
+    fmt, io,
     path::PathBuf,
 };
 
-use convert_case::{Case, Casing};
+use convert_case::{Boundary, Case, Casing};
 use proc_macro2::{Literal, Span, TokenStream};
 use quote::{format_ident, quote};
-use syn::{Attribute, Ident};
+use syn::{parse_quote, Attribute, Ident};
 
 use crate::{
     config::{Config, IntSize},
     descriptor::{
         DescriptorProto, EnumDescriptorProto, EnumValueDescriptorProto, FileDescriptorProto,
-        FileDescriptorSet,
+        FileDescriptorSet, ServiceDescriptorProto,
     },
     pathtree::{Node, PathTree},
-    split_pkg_name, EncodeDecode,
+    split_pkg_name, EncodeDecode, OptimizeFor, Target,
 };
 
 use self::message::Message;
 use super::WarningCb;
 
+pub(crate) mod container;
 pub(crate) mod field;
 pub(crate) mod message;
 pub(crate) mod oneof;
@@ -38,6 +39,12 @@ fn derive_msg_attr(debug: bool, default: bool, partial_eq: bool, clone: bool) ->
     quote! { #[derive(#debug #default #partial_eq #clone)] }
 }
 
+/// Derive `defmt::Format`, gated behind the `defmt` feature of the crate that includes the
+/// generated code
+fn derive_defmt_attr(defmt: bool) -> Option<TokenStream> {
+    defmt.then(|| quote! { #[cfg_attr(feature = "defmt", derive(::defmt::Format))] })
+}
+
 fn derive_enum_attr() -> TokenStream {
     quote! { #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)] }
 }
@@ -61,6 +68,20 @@ impl<'a> CurrentConfig<'a> {
         }
     }
 
+    /// Merge the config at `path` below `base` into this config, without inheriting the configs
+    /// of the nodes in between. This lets a path alias another path, such as oneof variants that
+    /// are addressed through their oneof.
+    fn merge_path(mut self, base: &CurrentConfig, path: &[&str]) -> Self {
+        let node = base.node.and_then(|node| {
+            path.iter()
+                .try_fold(node, |node, segment| node.next(segment))
+        });
+        if let Some(conf) = node.and_then(|n| n.access_value().as_ref()) {
+            (*self.config.to_mut()).overlay(conf);
+        }
+        self
+    }
+
     fn derive_dbg(&self) -> bool {
         !self.config.no_debug_impl.unwrap_or(false)
     }
@@ -78,6 +99,21 @@ impl<'a> CurrentConfig<'a> {
     }
 }
 
+/// Numbers and names of the values of an enum, skipping aliases. Aliased values share the same
+/// number, so the first name is used for the number.
+fn unique_enum_values(values: &[EnumValueDescriptorProto]) -> (Vec<Literal>, Vec<&String>) {
+    let mut seen = vec![];
+    values
+        .iter()
+        .filter(|v| {
+            let new = !seen.contains(&v.number);
+            seen.push(v.number);
+            new
+        })
+        .map(|v| (Literal::i32_unsuffixed(v.number), &v.name))
+        .unzip()
+}
+
 fn generate_mod_tree(mod_node: &mut Node<TokenStream>) -> TokenStream {
     let code = mod_node.value_mut().take().unwrap_or_default();
     let submods = mod_node.children_mut().map(|(submod_name, inner_node)| {
@@ -92,21 +128,111 @@ fn generate_mod_tree(mod_node: &mut Node<TokenStream>) -> TokenStream {
     }
 }
 
-fn field_error(pkg: &str, msg_name: &str, field_name: &str, err_text: &str) -> io::Error {
-    let dot = if pkg.is_empty() { "" } else { "." };
-    io::Error::other(format!("({dot}{pkg}.{msg_name}.{field_name}) {err_text}"))
+/// Error for a Protobuf element that the generator can't handle, such as an unsupported construct
+/// or a configuration that doesn't apply to it.
+///
+/// [`Generator::compile_protos`] and [`Generator::compile_fdset_file`] return this wrapped in an
+/// [`io::Error`]. It can be recovered with [`io::Error::get_ref`] and `downcast_ref`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct GenerateError {
+    /// Name of the `.proto` file that contains the element, as passed to `protoc`
+    pub file: String,
+    /// Fully-qualified Protobuf name of the element, such as `.pkg.Message.field`
+    pub element: String,
+    /// What went wrong
+    pub message: String,
+    /// How to work around the error, if there's a known way
+    pub suggestion: Option<String>,
 }
 
-fn msg_error(pkg: &str, msg_name: &str, err_text: &str) -> io::Error {
-    let dot = if pkg.is_empty() { "" } else { "." };
-    io::Error::other(format!("({dot}{pkg}.{msg_name}) {err_text}"))
+impl fmt::Display for GenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ({}) {}", self.file, self.element, self.message)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, "; {suggestion}")?;
+        }
+        Ok(())
+    }
 }
 
+impl std::error::Error for GenerateError {}
+
 pub(crate) enum EncodeFunc {
     Sizeof(Ident),
     Encode(Ident),
 }
 
+/// Maximum size of a value on the wire, used to generate `MessageEncode::MAX_SIZE`. Unbounded
+/// sizes are represented by wrapping this in `None`.
+///
+/// Sizes that are known during generation are folded together, so only sizes that depend on
+/// other messages end up as expressions in the generated code.
+pub(crate) enum MaxSize {
+    Known(usize),
+    /// Expression of type `Option<usize>`
+    Expr(TokenStream),
+}
+
+impl MaxSize {
+    fn into_expr(self) -> TokenStream {
+        match self {
+            MaxSize::Known(size) => {
+                let size = Literal::usize_unsuffixed(size);
+                quote! { ::core::option::Option::Some(#size) }
+            }
+            MaxSize::Expr(expr) => expr,
+        }
+    }
+
+    pub(crate) fn add(self, other: MaxSize) -> MaxSize {
+        match (self, other) {
+            (MaxSize::Known(a), MaxSize::Known(b)) => MaxSize::Known(a + b),
+            (MaxSize::Known(0), other) | (other, MaxSize::Known(0)) => other,
+            (a, b) => {
+                let (a, b) = (a.into_expr(), b.into_expr());
+                MaxSize::Expr(quote! { ::micropb::size::max_size_add(#a, #b) })
+            }
+        }
+    }
+
+    pub(crate) fn mul(self, n: u32) -> MaxSize {
+        match self {
+            MaxSize::Known(size) => MaxSize::Known(size * n as usize),
+            MaxSize::Expr(expr) => {
+                let n = Literal::usize_unsuffixed(n as usize);
+                MaxSize::Expr(quote! { ::micropb::size::max_size_mul(#expr, #n) })
+            }
+        }
+    }
+
+    pub(crate) fn max(self, other: MaxSize) -> MaxSize {
+        match (self, other) {
+            (MaxSize::Known(a), MaxSize::Known(b)) => MaxSize::Known(a.max(b)),
+            (a, b) => {
+                let (a, b) = (a.into_expr(), b.into_expr());
+                MaxSize::Expr(quote! { ::micropb::size::max_size_max(#a, #b) })
+            }
+        }
+    }
+
+    pub(crate) fn len_record(self) -> MaxSize {
+        match self {
+            MaxSize::Known(len) => MaxSize::Known(::micropb::size::sizeof_len_record(len)),
+            MaxSize::Expr(expr) => {
+                MaxSize::Expr(quote! { ::micropb::size::max_size_len_record(#expr) })
+            }
+        }
+    }
+
+    pub(crate) fn generate(max_size: Option<MaxSize>) -> TokenStream {
+        match max_size {
+            Some(size) => size.into_expr(),
+            None => quote! { ::core::option::Option::None },
+        }
+    }
+}
+
 #[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
 pub(crate) enum Syntax {
     #[default]
@@ -153,17 +279,52 @@ pub struct Generator {
     pub(crate) pkg_path: Vec<String>,
     pub(crate) pkg: String,
     pub(crate) type_path: RefCell<Vec<String>>,
+    /// Name of the file being generated
+    pub(crate) file: String,
 
     pub(crate) warning_cb: WarningCb,
 
     pub(crate) encode_decode: EncodeDecode,
+    /// Names of the Cargo features that gate the encode and decode logic
+    pub(crate) feature_gates: Option<(String, String)>,
+    pub(crate) dyn_encode: bool,
+    pub(crate) optimize: OptimizeFor,
+    pub(crate) field_stats: bool,
+    pub(crate) roundtrip_tests: bool,
+    pub(crate) services: bool,
+    pub(crate) capacity_asserts: bool,
+    pub(crate) encode_reverse: bool,
+    pub(crate) max_size: bool,
+    pub(crate) missing_fields: bool,
+    pub(crate) message_names: bool,
+    pub(crate) in_place_clear: bool,
+    /// Round-trip tests of the generated messages, collected into a module at the root of the
+    /// output
+    pub(crate) roundtrip_test_fns: RefCell<Vec<TokenStream>>,
+    /// Whether any message converts to a domain type, which needs `DomainError` in the output
+    pub(crate) domain_error_used: Cell<bool>,
     pub(crate) retain_enum_prefix: bool,
+    pub(crate) target: Option<Target>,
     pub(crate) format: bool,
     pub(crate) fdset_path: Option<PathBuf>,
     pub(crate) protoc_args: Vec<OsString>,
 
     pub(crate) config_tree: PathTree<Box<Config>>,
     pub(crate) extern_paths: HashMap<String, TokenStream>,
+    /// Message fields stored inline, used to find messages that contain themselves
+    pub(crate) inline_fields: RefCell<Vec<InlineField>>,
+}
+
+/// Field that stores a message inline, without any indirection
+#[derive(Debug)]
+pub(crate) struct InlineField {
+    /// File that defines the field's message
+    file: String,
+    /// Fully-qualified Protobuf name of the field's message, with a leading dot
+    msg: String,
+    field: String,
+    /// Fully-qualified Protobuf name of the stored message, with a leading dot
+    typ: String,
 }
 
 impl Generator {
@@ -174,13 +335,107 @@ impl Generator {
         });
     }
 
+    /// Fail if a message contains itself through fields that store messages inline, since the
+    /// generated struct would have infinite size
+    fn check_recursive_msgs(&self) -> io::Result<()> {
+        let fields = self.inline_fields.borrow();
+        for start in fields.iter() {
+            // Breadth-first search for a path from the stored message back to the start
+            let mut prev: HashMap<&str, &InlineField> = HashMap::new();
+            let mut queue = VecDeque::from([start.typ.as_str()]);
+            while let Some(msg) = queue.pop_front() {
+                if msg == start.msg {
+                    let mut cycle = vec![];
+                    let mut cur = msg;
+                    while cur != start.typ {
+                        let f = prev[cur];
+                        cycle.push(f);
+                        cur = &f.msg;
+                    }
+                    cycle.push(start);
+                    let path: Vec<_> = cycle
+                        .iter()
+                        .rev()
+                        .map(|f| format!("{}.{}", f.msg, f.field))
+                        .collect();
+                    return Err(io::Error::other(GenerateError {
+                        file: start.file.clone(),
+                        element: format!("{}.{}", start.msg, start.field),
+                        message: format!(
+                            "message contains itself through {}, so it would have infinite size",
+                            path.join(" -> ")
+                        ),
+                        suggestion: Some(
+                            "consider setting `boxed` on one of the fields in the cycle".to_owned(),
+                        ),
+                    }));
+                }
+                for f in fields.iter().filter(|f| f.msg == msg) {
+                    if f.typ != start.typ && !prev.contains_key(f.typ.as_str()) {
+                        prev.insert(&f.typ, f);
+                        queue.push_back(&f.typ);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Error for an element of the current file, identified by its fully-qualified name without
+    /// the leading dot
+    pub(crate) fn element_error(
+        &self,
+        full_name: &str,
+        message: &str,
+        suggestion: Option<&str>,
+    ) -> io::Error {
+        io::Error::other(GenerateError {
+            file: self.file.clone(),
+            element: format!(".{full_name}"),
+            message: message.to_owned(),
+            suggestion: suggestion.map(ToOwned::to_owned),
+        })
+    }
+
+    /// Error for a field of a message or service in the current scope
+    pub(crate) fn field_error(
+        &self,
+        msg_name: &str,
+        field_name: &str,
+        err_text: &str,
+    ) -> io::Error {
+        let full_name = format!("{}.{field_name}", self.full_type_name(msg_name));
+        self.element_error(&full_name, err_text, None)
+    }
+
+    /// Error for a message or enum in the current scope
+    pub(crate) fn msg_error(&self, msg_name: &str, err_text: &str) -> io::Error {
+        self.element_error(&self.full_type_name(msg_name), err_text, None)
+    }
+
+    /// Emit a warning about a field of a message in the current package
+    pub(crate) fn warn_field(&self, msg_name: &str, field_name: &str, text: fmt::Arguments) {
+        let dot = if self.pkg.is_empty() { "" } else { "." };
+        (self.warning_cb)(format_args!(
+            "({dot}{}.{msg_name}.{field_name}) {text}",
+            self.pkg
+        ));
+    }
+
     pub(crate) fn generate_fdset(&mut self, fdset: &FileDescriptorSet) -> io::Result<TokenStream> {
+        self.configure_target_containers();
         let mut mod_tree = PathTree::new(TokenStream::new());
 
         for file in &fdset.file {
             let code = self.generate_fdproto(file)?;
             if let Some(pkg_name) = file.package() {
-                *mod_tree.root.add_path(split_pkg_name(pkg_name)).value_mut() = Some(code);
+                // Files of the same package share a module
+                mod_tree
+                    .root
+                    .add_path(split_pkg_name(pkg_name))
+                    .value_mut()
+                    .get_or_insert_with(TokenStream::new)
+                    .extend([code]);
             } else {
                 mod_tree
                     .root
@@ -191,23 +446,135 @@ impl Generator {
             }
         }
 
-        Ok(generate_mod_tree(&mut mod_tree.root))
+        self.check_recursive_msgs()?;
+
+        let mut out = generate_mod_tree(&mut mod_tree.root);
+        out.extend(self.generate_roundtrip_tests_mod());
+        out.extend(self.generate_domain_error());
+        Ok(out)
+    }
+
+    /// Fill in the container types of the root config that aren't configured, based on the target
+    fn configure_target_containers(&mut self) {
+        let (vec_type, string_type, map_type) = match self.target {
+            None | Some(Target::NoAlloc) => return,
+            Some(Target::Alloc) => (
+                "::alloc::vec::Vec",
+                "::alloc::string::String",
+                "::alloc::collections::BTreeMap",
+            ),
+            Some(Target::Std) => (
+                "::std::vec::Vec",
+                "::std::string::String",
+                "::std::collections::HashMap",
+            ),
+        };
+        let root = self
+            .config_tree
+            .root
+            .value_mut()
+            .get_or_insert_with(|| Box::new(Config::new()));
+        root.vec_type.get_or_insert_with(|| vec_type.to_owned());
+        root.string_type
+            .get_or_insert_with(|| string_type.to_owned());
+        root.map_type.get_or_insert_with(|| map_type.to_owned());
+    }
+
+    /// Path of the crate that provides heap-allocated types, such as `Box`
+    pub(crate) fn alloc_crate(&self) -> TokenStream {
+        match self.target {
+            Some(Target::Std) => quote! { ::std },
+            _ => quote! { ::alloc },
+        }
+    }
+
+    fn generate_domain_error(&self) -> Option<TokenStream> {
+        if !self.domain_error_used.get() {
+            return None;
+        }
+        Some(quote! {
+            /// Error from converting between a message and its domain type, naming the field
+            /// that failed to convert
+            #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+            pub struct DomainError {
+                /// Fully-qualified Protobuf name of the message
+                pub message: &'static str,
+                /// Protobuf name of the field
+                pub field: &'static str,
+            }
+
+            impl ::core::fmt::Display for DomainError {
+                fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                    write!(f, "failed to convert field `{}` of `{}`", self.field, self.message)
+                }
+            }
+        })
+    }
+
+    fn generate_roundtrip_tests_mod(&self) -> Option<TokenStream> {
+        let tests = self.roundtrip_test_fns.take();
+        if tests.is_empty() {
+            return None;
+        }
+        let cfg = match &self.feature_gates {
+            Some((encode, decode)) => {
+                quote! { #[cfg(all(test, feature = #encode, feature = #decode))] }
+            }
+            None => quote! { #[cfg(test)] },
+        };
+
+        Some(quote! {
+            #cfg
+            #[allow(non_snake_case)]
+            mod roundtrip_tests {
+                // Test harnesses link `std` even if the crate is `no_std`
+                extern crate std;
+
+                /// Encode the message, decode it into a new instance, and check that the sizes
+                /// of both encodings match what `compute_size` returns
+                fn round_trip<M>(msg: &M) -> M
+                where
+                    M: ::micropb::MessageEncode + ::micropb::MessageDecode + ::core::default::Default,
+                {
+                    let size = msg.compute_size();
+                    let mut buf = std::vec![0u8; size];
+                    let encoded = msg.encode_to_slice(&mut buf).expect("encoding failed");
+                    assert_eq!(encoded.len(), size, "compute_size differs from the encoded size");
+
+                    let mut decoded = M::default();
+                    decoded.merge_from_bytes(encoded).expect("decoding failed");
+                    assert_eq!(decoded.compute_size(), size, "decoded message has a different size");
+                    decoded
+                }
+
+                #(#tests)*
+            }
+        })
     }
 
     pub(crate) fn generate_fdproto(
         &mut self,
         fdproto: &FileDescriptorProto,
     ) -> io::Result<TokenStream> {
-        self.syntax = match fdproto.syntax.as_str() {
-            // If the syntax is "editions", still treat as proto3 for now
-            "proto3" | "editions" => Syntax::Proto3,
-            _ => Syntax::Proto2,
-        };
+        self.file = fdproto.name.clone();
         self.pkg_path = fdproto
             .package()
             .map(|s| split_pkg_name(s).map(ToOwned::to_owned).collect())
             .unwrap_or_default();
         self.pkg = fdproto.package().cloned().unwrap_or_default();
+        self.syntax = match fdproto.syntax.as_str() {
+            "proto3" => Syntax::Proto3,
+            // Editions resolve field presence and encoding through features, which aren't
+            // handled, so treating them as either syntax would generate the wrong code
+            "editions" => {
+                return Err(self.element_error(
+                    &self.pkg,
+                    "file uses Protobuf editions, which micropb doesn't support",
+                    Some("consider using `syntax = \"proto3\"` or `syntax = \"proto2\"` instead"),
+                ))
+            }
+            _ => Syntax::Proto2,
+        };
 
         let root_node = &self.config_tree.root;
         let mut conf = root_node
@@ -231,6 +598,11 @@ impl Generator {
         for e in &fdproto.enum_type {
             out.extend(self.generate_enum(e, cur_config.next_conf(&e.name))?);
         }
+        if self.services {
+            for s in &fdproto.service {
+                out.extend(self.generate_service(s)?);
+            }
+        }
 
         Ok(out)
     }
@@ -244,6 +616,11 @@ impl Generator {
     ) -> TokenStream {
         let nums = values.iter().map(|v| Literal::i32_unsuffixed(v.number));
         let var_names = values.iter().map(|v| self.enum_variant_name(&v.name, name));
+        // Aliased variants share the same number, which would make the match patterns redundant
+        let mut known_nums: Vec<_> = values.iter().map(|v| v.number).collect();
+        known_nums.sort_unstable();
+        known_nums.dedup();
+        let known_nums = known_nums.into_iter().map(Literal::i32_unsuffixed);
         let default_num = Literal::i32_unsuffixed(values[0].number);
         let derive_enum = derive_enum_attr();
         let itype = enum_int_type.type_name(true);
@@ -256,6 +633,11 @@ impl Generator {
 
             impl #name {
                 #(pub const #var_names: Self = Self(#nums);)*
+
+                /// Whether the value is one of the variants declared in the Protobuf enum
+                pub const fn is_known(self) -> bool {
+                    matches!(self.0, #(#known_nums)|*)
+                }
             }
 
             impl core::default::Default for #name {
@@ -272,6 +654,96 @@ impl Generator {
         }
     }
 
+    fn generate_enum_proptest(
+        &self,
+        name: &Ident,
+        values: &[EnumValueDescriptorProto],
+    ) -> TokenStream {
+        // Aliases are skipped, so that every number is equally likely
+        let mut nums = vec![];
+        let var_names = values
+            .iter()
+            .filter(|v| {
+                let new = !nums.contains(&v.number);
+                nums.push(v.number);
+                new
+            })
+            .map(|v| self.enum_variant_name(&v.name, name));
+        quote! {
+            #[cfg(feature = "proptest")]
+            impl #name {
+                /// Strategy that generates the variants declared in the Protobuf enum, for property tests with `proptest`
+                pub fn arbitrary_strategy() -> impl ::proptest::strategy::Strategy<Value = Self> {
+                    ::proptest::sample::select(&[#(Self::#var_names),*][..])
+                }
+            }
+        }
+    }
+
+    fn generate_enum_json(&self, name: &Ident, values: &[EnumValueDescriptorProto]) -> TokenStream {
+        let (nums, json_names) = unique_enum_values(values);
+        // All names are accepted when parsing, including aliases
+        let all_names = values.iter().map(|v| &v.name);
+        let var_names = values.iter().map(|v| self.enum_variant_name(&v.name, name));
+
+        quote! {
+            impl ::micropb::json::JsonEnum for #name {
+                fn json_name(&self) -> ::core::option::Option<&'static str> {
+                    match self.0 {
+                        #(#nums => ::core::option::Option::Some(#json_names),)*
+                        _ => ::core::option::Option::None,
+                    }
+                }
+
+                fn json_value(&self) -> i32 {
+                    self.0 as _
+                }
+
+                fn from_json_name(name: &str) -> ::core::option::Option<Self> {
+                    match name {
+                        #(#all_names => ::core::option::Option::Some(Self::#var_names),)*
+                        _ => ::core::option::Option::None,
+                    }
+                }
+
+                fn from_json_value(val: i32) -> Self {
+                    Self(val as _)
+                }
+            }
+        }
+    }
+
+    fn generate_enum_text(&self, name: &Ident, values: &[EnumValueDescriptorProto]) -> TokenStream {
+        let (nums, text_names) = unique_enum_values(values);
+        let all_names = values.iter().map(|v| &v.name);
+        let var_names = values.iter().map(|v| self.enum_variant_name(&v.name, name));
+        quote! {
+            impl ::micropb::text::TextEnum for #name {
+                fn text_name(&self) -> ::core::option::Option<&'static str> {
+                    match self.0 {
+                        #(#nums => ::core::option::Option::Some(#text_names),)*
+                        _ => ::core::option::Option::None,
+                    }
+                }
+
+                fn text_value(&self) -> i32 {
+                    self.0 as _
+                }
+
+                fn from_text_name(name: &str) -> ::core::option::Option<Self> {
+                    match name {
+                        #(#all_names => ::core::option::Option::Some(Self::#var_names),)*
+                        _ => ::core::option::Option::None,
+                    }
+                }
+
+                fn from_text_value(val: i32) -> Self {
+                    Self(val as _)
+                }
+            }
+        }
+    }
+
     fn generate_enum(
         &self,
         enum_type: &EnumDescriptorProto,
@@ -283,14 +755,143 @@ impl Generator {
 
         let name = sanitized_ident(&enum_type.name);
         let enum_int_type = enum_conf.config.enum_int_size.unwrap_or(IntSize::S32);
-        let attrs = &enum_conf
+        let mut attrs = enum_conf
             .config
             .type_attr_parsed()
-            .map_err(|e| msg_error(&self.pkg, &enum_type.name, &e))?;
-        let out = self.generate_enum_decl(&name, &enum_type.value, enum_int_type, attrs);
+            .map_err(|e| self.msg_error(&enum_type.name, &e))?;
+        if enum_conf.config.serde.unwrap_or(false) {
+            // Enums are (de)serialized as their integer values
+            attrs.splice(
+                0..0,
+                [
+                    parse_quote! { #[derive(::serde::Serialize, ::serde::Deserialize)] },
+                    parse_quote! { #[serde(transparent)] },
+                ],
+            );
+        }
+        if enum_conf.config.defmt.unwrap_or(false) {
+            attrs.insert(
+                0,
+                parse_quote! { #[cfg_attr(feature = "defmt", derive(::defmt::Format))] },
+            );
+        }
+        if enum_conf.config.arbitrary.unwrap_or(false) {
+            attrs.insert(
+                0,
+                parse_quote! { #[cfg_attr(fuzzing, derive(::arbitrary::Arbitrary))] },
+            );
+        }
+        let mut out = self.generate_enum_decl(&name, &enum_type.value, enum_int_type, &attrs);
+        if enum_conf.config.json.unwrap_or(false) {
+            out.extend(self.generate_enum_json(&name, &enum_type.value));
+        }
+        if enum_conf.config.text.unwrap_or(false) {
+            out.extend(self.generate_enum_text(&name, &enum_type.value));
+        }
+        if enum_conf.config.proptest.unwrap_or(false) {
+            out.extend(self.generate_enum_proptest(&name, &enum_type.value));
+        }
         Ok(out)
     }
 
+    fn generate_service(&self, service: &ServiceDescriptorProto) -> io::Result<TokenStream> {
+        let name = sanitized_ident(&service.name);
+        let mod_name = resolve_path_elem(&service.name);
+        let full_name = self.full_type_name(&service.name);
+
+        let mut methods = vec![];
+        for m in &service.method {
+            if m.client_streaming || m.server_streaming {
+                return Err(self.element_error(
+                    &format!("{full_name}.{}", m.name),
+                    "RPC is streaming, which micropb doesn't support",
+                    Some("consider a unary RPC whose messages carry a batch of items"),
+                ));
+            }
+            methods.push((sanitized_ident(&m.name.to_case(Case::Snake)), m));
+        }
+
+        let trait_methods = methods.iter().map(|(ident, m)| {
+            let req = self.resolve_type_name(&m.input_type);
+            let resp = self.resolve_type_name(&m.output_type);
+            let doc = format!("Handle the `{}` RPC", m.name);
+            quote! {
+                #[doc = #doc]
+                fn #ident(&mut self, req: #req) -> ::core::result::Result<#resp, Self::Error>;
+            }
+        });
+
+        // The table and dispatcher are in the service's module, so resolve types from there
+        self.type_path.borrow_mut().push(service.name.to_owned());
+        let descriptors = methods.iter().enumerate().map(|(i, (_, m))| {
+            let name = &m.name;
+            let req = &m.input_type[1..];
+            let resp = &m.output_type[1..];
+            quote! {
+                ::micropb::service::MethodDescriptor {
+                    index: #i,
+                    name: #name,
+                    request: #req,
+                    response: #resp,
+                }
+            }
+        });
+        let dispatch = (self.encode_decode.is_encode() && self.encode_decode.is_decode()).then(|| {
+            let cfg = self
+                .feature_gates
+                .as_ref()
+                .map(|(encode, decode)| quote! { #[cfg(all(feature = #encode, feature = #decode))] });
+            let arms = methods.iter().enumerate().map(|(i, (ident, m))| {
+                let req = self.resolve_type_name(&m.input_type);
+                quote! {
+                    #i => {
+                        let mut req = <#req as ::core::default::Default>::default();
+                        ::micropb::MessageDecode::merge_from_bytes(&mut req, request)
+                            .map_err(::micropb::service::DispatchError::Decode)?;
+                        let resp = service.#ident(req).map_err(::micropb::service::DispatchError::Service)?;
+                        ::micropb::MessageEncode::encode(&resp, encoder)
+                            .map_err(::micropb::service::DispatchError::Encode)
+                    }
+                }
+            });
+            quote! {
+                /// Decode the request of the method at `method` in [`METHODS`], call the method on
+                /// `service`, and encode the response into `encoder`
+                #cfg
+                pub fn dispatch<S: super::#name + ?Sized, W: ::micropb::PbWrite>(
+                    service: &mut S,
+                    method: usize,
+                    request: &[u8],
+                    encoder: &mut ::micropb::PbEncoder<W>,
+                ) -> ::core::result::Result<(), ::micropb::service::DispatchError<S::Error, ::micropb::EncodeError<W::Error>>> {
+                    match method {
+                        #(#arms)*
+                        _ => ::core::result::Result::Err(::micropb::service::DispatchError::UnknownMethod(method)),
+                    }
+                }
+            }
+        });
+        self.type_path.borrow_mut().pop();
+
+        let trait_doc = format!("Protobuf service `{full_name}`");
+        Ok(quote! {
+            #[doc = #trait_doc]
+            pub trait #name {
+                /// Error returned by the RPC handlers
+                type Error;
+
+                #(#trait_methods)*
+            }
+
+            pub mod #mod_name {
+                /// Descriptors of the service's methods, in declaration order
+                pub const METHODS: &[::micropb::service::MethodDescriptor] = &[#(#descriptors),*];
+
+                #dispatch
+            }
+        })
+    }
+
     fn generate_msg_mod(
         &self,
         msg: &Message,
@@ -298,6 +899,8 @@ impl Generator {
         msg_conf: &CurrentConfig,
     ) -> io::Result<(TokenStream, Option<Vec<syn::Attribute>>)> {
         let msg_mod_name = resolve_path_elem(msg.name);
+        let prost_paths = self.prost_type_path(msg);
+        let full_name = self.full_type_name(msg.name);
         self.type_path.borrow_mut().push(msg.name.to_owned());
 
         let mut msg_mod_body = TokenStream::new();
@@ -313,16 +916,30 @@ impl Generator {
         }
         for o in &msg.oneofs {
             msg_mod_body.extend(o.generate_decl(self));
+            if self.capacity_asserts && msg.lifetime.is_none() {
+                msg_mod_body.extend(o.generate_capacity_asserts(self, &full_name));
+            }
+            msg_mod_body.extend(o.generate_arbitrary_impl(self));
+            msg_mod_body.extend(o.generate_proptest_impl(self));
+            if msg.has_roundtrip_test(self) {
+                msg_mod_body.extend(o.generate_sample_impl(self));
+            }
+            if let Some((_, prost_mod)) = &prost_paths {
+                msg_mod_body.extend(o.generate_prost_impls(self, prost_mod));
+            }
         }
 
         let (hazzer_decl, hazzer_field_attr) = match msg
-            .generate_hazzer_decl(msg_conf.next_conf("_has"))
-            .map_err(|e| field_error(&self.pkg, msg.name, "_has", &e))?
+            .generate_hazzer_decl(self, msg_conf.next_conf("_has"))
+            // The message is already in the type path, so its full name is used directly
+            .map_err(|e| self.element_error(&format!("{full_name}._has"), &e, None))?
         {
             Some((d, a)) => (Some(d), Some(a)),
             None => (None, None),
         };
         msg_mod_body.extend(hazzer_decl);
+        msg_mod_body.extend(msg.generate_visitor_decl(self));
+        msg_mod_body.extend(msg.generate_name_consts());
 
         self.type_path.borrow_mut().pop();
 
@@ -342,16 +959,53 @@ impl Generator {
         let Some(msg) = Message::from_proto(proto, self, &msg_conf)? else {
             return Ok(quote! {});
         };
+        let full_msg_name = format!(".{}", self.full_type_name(msg.name));
+        self.inline_fields
+            .borrow_mut()
+            .extend(
+                msg.inline_msg_fields()
+                    .into_iter()
+                    .map(|(field, typ)| InlineField {
+                        file: self.file.clone(),
+                        msg: full_msg_name.clone(),
+                        field: field.to_owned(),
+                        typ: typ.to_owned(),
+                    }),
+            );
         let (msg_mod, hazzer_field_attr) = self.generate_msg_mod(&msg, proto, &msg_conf)?;
         let unknown_conf = msg_conf.next_conf("_unknown");
 
-        let default = msg.generate_default_impl(self, hazzer_field_attr.is_some())?;
+        let use_hazzer = hazzer_field_attr.is_some();
+        let default = msg.generate_default_impl(self, use_hazzer)?;
         let decl = msg.generate_decl(self, hazzer_field_attr, &unknown_conf)?;
         let msg_impl = msg.generate_impl(self);
+        let full_name = self.full_type_name(msg.name);
+        // `MessageFields` needs `MessageName` as its supertrait
+        let fields_impl = msg.generate_fields_impl();
+        let name_impl = (self.message_names || fields_impl.is_some())
+            .then(|| msg.generate_name_impl(&full_name));
+        let capacity_asserts = msg.generate_capacity_asserts(self, &full_name);
+        let any_impl = msg.generate_any_impl(self, &full_name);
+        let value_impl = msg.generate_value_impl(self, &full_name);
+        let serde = msg.generate_serde_impls(self);
+        let json = msg.generate_json_impl();
+        let json_parse = msg.generate_json_parse_impl(self);
+        let text = msg.generate_text_impl();
+        let text_parse = msg.generate_text_parse_impl(self);
+        let field_mask = msg.generate_field_mask_impl(self);
+        let arbitrary = msg.generate_arbitrary_impl(self);
+        let proptest = msg.generate_proptest_impl(self);
+        let sample = msg.generate_sample_impl(self);
+        let domain = self.generate_domain_impls(&msg, &full_name);
+        let prost = self
+            .prost_type_path(&msg)
+            .map(|(prost_type, _)| msg.generate_prost_impls(self, &prost_type));
+        self.add_roundtrip_test(&msg, &full_name);
         let decode = self
             .encode_decode
             .is_decode()
-            .then(|| msg.generate_decode_trait(self));
+            .then(|| msg.generate_decode_trait(self, use_hazzer))
+            .transpose()?;
         let encode = self
             .encode_decode
             .is_encode()
@@ -362,11 +1016,85 @@ impl Generator {
             #decl
             #default
             #msg_impl
+            #name_impl
+            #fields_impl
+            #capacity_asserts
+            #any_impl
+            #value_impl
+            #serde
+            #json
+            #json_parse
+            #text
+            #text_parse
+            #field_mask
+            #arbitrary
+            #proptest
+            #sample
+            #domain
+            #prost
             #decode
             #encode
         })
     }
 
+    /// Collect the round-trip test of the message, if it has one
+    fn add_roundtrip_test(&self, msg: &Message, full_name: &str) {
+        let type_path = self.type_path.borrow();
+        let path = self
+            .pkg_path
+            .iter()
+            .chain(type_path.iter())
+            .map(|elem| resolve_path_elem(elem));
+        let rust_name = &msg.rust_name;
+        let test_name = sanitized_ident(&full_name.replace('.', "__"));
+        if let Some(test) =
+            msg.generate_roundtrip_test(self, quote! { super::#(#path::)* #rust_name }, &test_name)
+        {
+            self.roundtrip_test_fns.borrow_mut().push(test);
+        }
+    }
+
+    /// Generate the conversions between the message and its domain type, if it has one
+    fn generate_domain_impls(&self, msg: &Message, full_name: &str) -> Option<TokenStream> {
+        // `DomainError` is at the root of the output
+        let depth = self.pkg_path.len() + self.type_path.borrow().len();
+        let supers = (0..depth).map(|_| quote! { super:: });
+        let impls =
+            msg.generate_domain_impls(self, quote! { #(#supers)* DomainError }, full_name)?;
+        self.domain_error_used.set(true);
+        Some(impls)
+    }
+
+    /// Path of the type that `prost-build` generates for a message in the current message, along
+    /// with the path of the module that contains its nested types
+    fn prost_type_path(&self, msg: &Message) -> Option<(TokenStream, TokenStream)> {
+        let prost_path = msg.prost_path.as_ref()?;
+        let type_path = self.type_path.borrow();
+        let mods: Vec<_> = type_path
+            .iter()
+            .map(|elem| prost_ident(elem, Case::Snake))
+            .collect();
+        let name = prost_ident(msg.name, Case::Pascal);
+        let nested = prost_ident(msg.name, Case::Snake);
+        Some((
+            quote! { #prost_path #(::#mods)* :: #name },
+            quote! { #prost_path #(::#mods)* :: #nested },
+        ))
+    }
+
+    /// Fully-qualified Protobuf name of a type in the current message, without the leading dot
+    fn full_type_name(&self, name: &str) -> String {
+        let type_path = self.type_path.borrow();
+        let mut full_name = self.pkg.clone();
+        for elem in type_path.iter().map(String::as_str).chain([name]) {
+            if !full_name.is_empty() {
+                full_name.push('.');
+            }
+            full_name.push_str(elem);
+        }
+        full_name
+    }
+
     fn resolve_type_name(&self, pb_fq_type_name: &str) -> TokenStream {
         // Type names provided by protoc will always be fully-qualified
         assert_eq!(".", &pb_fq_type_name[..1]);
@@ -400,9 +1128,12 @@ impl Generator {
     /// Convert variant name to Pascal-case, then strip the enum name from it
     fn enum_variant_name(&self, variant_name: &str, enum_name: &Ident) -> Ident {
         let variant_name_cased = variant_name.to_case(Case::Pascal);
+        // Variants named after the enum itself, such as `NULL_VALUE` in `NullValue`, aren't
+        // stripped, since nothing would be left
         let stripped = if !self.retain_enum_prefix {
             variant_name_cased
                 .strip_prefix(&enum_name.to_string())
+                .filter(|s| !s.is_empty())
                 .unwrap_or(&variant_name_cased)
         } else {
             &variant_name_cased
@@ -412,7 +1143,10 @@ impl Generator {
 
     fn wrapped_type(&self, typ: TokenStream, boxed: bool, optional: bool) -> TokenStream {
         let boxed_type = if boxed {
-            quote! { ::alloc::boxed::Box<#typ> }
+            {
+                let alloc = self.alloc_crate();
+                quote! { #alloc::boxed::Box<#typ> }
+            }
         } else {
             typ
         };
@@ -423,9 +1157,43 @@ impl Generator {
         }
     }
 
+    /// `#[cfg]` attribute of the encoding logic, if it's gated behind a feature
+    fn encode_cfg_attr(&self) -> Option<TokenStream> {
+        self.feature_gates
+            .as_ref()
+            .map(|(encode, _)| quote! { #[cfg(feature = #encode)] })
+    }
+
+    /// `#[cfg]` attribute of the decoding logic, if it's gated behind a feature
+    fn decode_cfg_attr(&self) -> Option<TokenStream> {
+        self.feature_gates
+            .as_ref()
+            .map(|(_, decode)| quote! { #[cfg(feature = #decode)] })
+    }
+
+    /// Inline attribute of small helpers, such as accessors
+    fn helper_inline_attr(&self) -> TokenStream {
+        match self.optimize {
+            OptimizeFor::Size => quote! {},
+            OptimizeFor::Balanced | OptimizeFor::Speed => quote! { #[inline] },
+        }
+    }
+
+    /// Inline attribute of the encoding and decoding logic of messages
+    fn message_inline_attr(&self) -> TokenStream {
+        match self.optimize {
+            OptimizeFor::Balanced => quote! {},
+            OptimizeFor::Speed => quote! { #[inline] },
+            OptimizeFor::Size => quote! { #[inline(never)] },
+        }
+    }
+
     fn wrapped_value(&self, val: TokenStream, boxed: bool, optional: bool) -> TokenStream {
         let boxed_type = if boxed {
-            quote! { ::alloc::boxed::Box::new(#val) }
+            {
+                let alloc = self.alloc_crate();
+                quote! { #alloc::boxed::Box::new(#val) }
+            }
         } else {
             val
         };
@@ -463,12 +1231,73 @@ pub(crate) fn sanitized_ident(name: &str) -> Ident {
     }
 }
 
+/// Identifier that `prost-build` generates for a Protobuf name, after converting it to `case`
+pub(crate) fn prost_ident(name: &str, case: Case) -> Ident {
+    // Match the word boundaries of `heck`, which `prost-build` uses
+    let name = name
+        .with_boundaries(&[
+            Boundary::Underscore,
+            Boundary::Hyphen,
+            Boundary::Space,
+            Boundary::LowerUpper,
+            Boundary::DigitUpper,
+            Boundary::Acronym,
+        ])
+        .to_case(case);
+    match name.as_str() {
+        // These keywords can't be raw idents, so prost suffixes them with underscore
+        "_" | "super" | "crate" | "self" | "Self" | "extern" => format_ident!("{name}_"),
+        name => sanitized_ident(name),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{config::parse_attributes, descriptor::EnumValueDescriptorProto};
+    use crate::{
+        config::parse_attributes,
+        descriptor::{
+            EnumValueDescriptorProto, FieldDescriptorProto, FieldDescriptorProto_::Type,
+            MethodDescriptorProto,
+        },
+    };
 
     use super::*;
 
+    fn generate_err(gen: &mut Generator, file: FileDescriptorProto) -> GenerateError {
+        let mut fdset = FileDescriptorSet::default();
+        fdset.file.push(file);
+        let err = gen.generate_fdset(&fdset).unwrap_err();
+        *err.into_inner()
+            .unwrap()
+            .downcast::<GenerateError>()
+            .unwrap()
+    }
+
+    fn msg_field(name: &str, number: i32, type_name: &str) -> FieldDescriptorProto {
+        let mut f = FieldDescriptorProto::default();
+        f.set_name(name.to_owned());
+        f.set_number(number);
+        f.set_type(Type::Message);
+        f.set_type_name(type_name.to_owned());
+        f
+    }
+
+    fn msg_proto(name: &str, fields: Vec<FieldDescriptorProto>) -> DescriptorProto {
+        let mut msg = DescriptorProto::default();
+        msg.set_name(name.to_owned());
+        msg.field = fields;
+        msg
+    }
+
+    fn file_proto(syntax: &str, messages: Vec<DescriptorProto>) -> FileDescriptorProto {
+        let mut file = FileDescriptorProto::default();
+        file.set_name("test.proto".to_owned());
+        file.set_package("pkg".to_owned());
+        file.set_syntax(syntax.to_owned());
+        file.message_type = messages;
+        file
+    }
+
     #[test]
     fn enum_variant_name() {
         let mut gen = Generator::new();
@@ -481,6 +1310,10 @@ mod tests {
             gen.enum_variant_name("ALIEN", &enum_name).to_string(),
             "Alien"
         );
+        assert_eq!(
+            gen.enum_variant_name("ENUM", &enum_name).to_string(),
+            "Enum"
+        );
 
         gen.retain_enum_prefix = true;
         assert_eq!(
@@ -528,6 +1361,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn full_type_name() {
+        let mut gen = Generator::new();
+        assert_eq!(gen.full_type_name("Message"), "Message");
+        gen.pkg = "pkg.sub".to_owned();
+        assert_eq!(gen.full_type_name("Message"), "pkg.sub.Message");
+        gen.type_path.borrow_mut().push("Message".to_owned());
+        assert_eq!(gen.full_type_name("Inner"), "pkg.sub.Message.Inner");
+    }
+
+    #[test]
+    fn prost_ident_case() {
+        assert_eq!(prost_ident("my_message", Case::Pascal), "MyMessage");
+        assert_eq!(prost_ident("HTTPServer2", Case::Snake), "r#http_server2");
+        assert_eq!(prost_ident("field1Name", Case::Snake), "r#field1_name");
+        assert_eq!(prost_ident("type", Case::Snake), "r#type");
+        assert_eq!(prost_ident("self", Case::Snake), "self_");
+    }
+
     #[test]
     fn enum_basic() {
         let name = Ident::new("Test", Span::call_site());
@@ -550,6 +1402,11 @@ mod tests {
             impl Test {
                 pub const One: Self = Self(1);
                 pub const OtherValue: Self = Self(2);
+
+                /// Whether the value is one of the variants declared in the Protobuf enum
+                pub const fn is_known(self) -> bool {
+                    matches!(self.0, 1 | 2)
+                }
             }
 
             impl core::default::Default for Test {
@@ -592,6 +1449,11 @@ mod tests {
 
             impl Enum {
                 pub const EnumOne: Self = Self(1);
+
+                /// Whether the value is one of the variants declared in the Protobuf enum
+                pub const fn is_known(self) -> bool {
+                    matches!(self.0, 1)
+                }
             }
 
             impl core::default::Default for Enum {
@@ -609,6 +1471,130 @@ mod tests {
         assert_eq!(out.to_string(), expected.to_string());
     }
 
+    #[test]
+    fn fdset_same_package() {
+        let mut fdset = FileDescriptorSet::default();
+        for enum_name in ["First", "Second"] {
+            let mut value = EnumValueDescriptorProto::default();
+            value.set_name("ZERO".to_owned());
+            value.set_number(0);
+            let mut enum_type = EnumDescriptorProto::default();
+            enum_type.set_name(enum_name.to_owned());
+            enum_type.value.push(value);
+            let mut file = FileDescriptorProto::default();
+            file.set_package("pkg".to_owned());
+            file.enum_type.push(enum_type);
+            fdset.file.push(file);
+        }
+
+        let mut gen = Generator::new();
+        let out = gen.generate_fdset(&fdset).unwrap().to_string();
+        // Both files end up in a single module
+        assert_eq!(out.matches("pub mod pkg_").count(), 1);
+        assert!(out.contains("pub struct First"));
+        assert!(out.contains("pub struct Second"));
+    }
+
+    #[test]
+    fn service_streaming() {
+        let mut method = MethodDescriptorProto::default();
+        method.set_name("Watch".to_owned());
+        method.set_input_type(".pkg.Request".to_owned());
+        method.set_output_type(".pkg.Response".to_owned());
+        let mut service = ServiceDescriptorProto::default();
+        service.set_name("Monitor".to_owned());
+        service.method.push(method);
+
+        let mut gen = Generator::new();
+        gen.pkg = "pkg".to_owned();
+        gen.pkg_path = vec!["pkg".to_owned()];
+        let out = gen.generate_service(&service).unwrap().to_string();
+        assert!(out.contains("pub trait Monitor"));
+        assert!(out.contains("fn r#watch"));
+
+        service.method[0].set_server_streaming(true);
+        let err = gen.generate_service(&service).unwrap_err();
+        assert!(err.to_string().contains("Monitor.Watch"));
+    }
+
+    #[test]
+    fn unsupported_group() {
+        let mut group = msg_field("data", 1, ".pkg.Msg.Data");
+        group.set_type(Type::Group);
+        let mut msg = msg_proto("Msg", vec![group]);
+        msg.nested_type.push(msg_proto("Data", vec![]));
+
+        let err = generate_err(&mut Generator::new(), file_proto("proto2", vec![msg]));
+        assert_eq!(err.file, "test.proto");
+        assert_eq!(err.element, ".pkg.Msg.data");
+        assert!(err.message.contains("proto2 groups"));
+        assert!(err.suggestion.unwrap().contains("nested message"));
+    }
+
+    #[test]
+    fn unsupported_editions() {
+        let err = generate_err(&mut Generator::new(), file_proto("editions", vec![]));
+        assert_eq!(err.file, "test.proto");
+        assert_eq!(err.element, ".pkg");
+        assert!(err.message.contains("Protobuf editions"));
+        assert!(err.suggestion.unwrap().contains("syntax = \"proto3\""));
+    }
+
+    #[test]
+    fn unsupported_streaming() {
+        let mut method = MethodDescriptorProto::default();
+        method.set_name("Watch".to_owned());
+        method.set_input_type(".pkg.Msg".to_owned());
+        method.set_output_type(".pkg.Msg".to_owned());
+        method.set_client_streaming(true);
+        let mut service = ServiceDescriptorProto::default();
+        service.set_name("Monitor".to_owned());
+        service.method.push(method);
+        let mut file = file_proto("proto3", vec![msg_proto("Msg", vec![])]);
+        file.service.push(service);
+
+        let mut gen = Generator::new();
+        gen.services(true);
+        let err = generate_err(&mut gen, file);
+        assert_eq!(err.element, ".pkg.Monitor.Watch");
+        assert!(err.message.contains("streaming"));
+        assert!(err.suggestion.unwrap().contains("unary RPC"));
+    }
+
+    #[test]
+    fn unboxed_recursion() {
+        let a = msg_proto("A", vec![msg_field("b", 1, ".pkg.B")]);
+        let b = msg_proto("B", vec![msg_field("a", 1, ".pkg.A")]);
+        let err = generate_err(&mut Generator::new(), file_proto("proto3", vec![a, b]));
+        assert_eq!(err.file, "test.proto");
+        assert_eq!(err.element, ".pkg.A.b");
+        assert!(
+            err.message.contains(".pkg.A.b -> .pkg.B.a"),
+            "{}",
+            err.message
+        );
+        assert!(err.suggestion.unwrap().contains("boxed"));
+
+        // Nested messages are named by their full path
+        let mut outer = msg_proto("Outer", vec![]);
+        outer.nested_type.push(msg_proto(
+            "Inner",
+            vec![msg_field("next", 1, ".pkg.Outer.Inner")],
+        ));
+        let err = generate_err(&mut Generator::new(), file_proto("proto3", vec![outer]));
+        assert_eq!(err.element, ".pkg.Outer.Inner.next");
+        assert!(err.to_string().starts_with("test.proto: (.pkg.Outer.Inner.next) message contains itself through .pkg.Outer.Inner.next,"));
+
+        // Boxing any field in the cycle makes the size finite
+        let a = msg_proto("A", vec![msg_field("b", 1, ".pkg.B")]);
+        let b = msg_proto("B", vec![msg_field("a", 1, ".pkg.A")]);
+        let mut fdset = FileDescriptorSet::default();
+        fdset.file.push(file_proto("proto3", vec![a, b]));
+        let mut gen = Generator::new();
+        gen.configure(".pkg.B.a", Config::new().boxed(true));
+        gen.generate_fdset(&fdset).unwrap();
+    }
+
     #[test]
     fn gen_mod_tree() {
         let mut mod_tree = PathTree::new(quote! { Root });
@@ -0,0 +1,247 @@
+use proc_macro2::{Group, Span, TokenStream, TokenTree};
+use quote::{quote, ToTokens};
+use syn::{Ident, Lifetime};
+
+use super::type_spec::{find_lifetime_from_path, find_lifetime_from_type};
+
+/// Prefix of the identifiers that stand in for placeholders in a parsed template
+const PLACEHOLDER_PREFIX: &str = "__micropb_placeholder_";
+
+/// Container type configured through an option such as `map_type`
+#[cfg_attr(test, derive(Debug, PartialEq, Eq))]
+pub(crate) enum ContainerType {
+    /// Path whose type parameters are filled in positionally
+    Path(syn::Path),
+    /// Type with `$`-placeholders for its parameters, such as `MyMap<$N, $K, $V>`
+    Template(Box<syn::Type>),
+}
+
+impl ContainerType {
+    /// Parse the value of the container option `opt`. If the value contains `$`, it's parsed as a
+    /// template that can only use the provided placeholders. Otherwise, it's parsed as a path.
+    ///
+    /// Placeholders whose values are already known are substituted before parsing, so parse
+    /// errors show the substituted template. The rest are substituted in [`Self::generate`].
+    pub(crate) fn parse(
+        opt: &str,
+        value: &str,
+        placeholders: &[(&str, Option<String>)],
+    ) -> Result<Self, String> {
+        if !value.contains('$') {
+            return syn::parse_str(value)
+                .map(Self::Path)
+                .map_err(|e| format!("Failed to parse {opt} \"{value}\" as type path: {e}"));
+        }
+
+        // Template with known placeholders substituted, which is shown in errors
+        let mut substituted = String::with_capacity(value.len());
+        // Template with the remaining placeholders replaced by identifiers, which is parsed
+        let mut replaced = String::with_capacity(value.len());
+        let mut rest = value;
+        while let Some(idx) = rest.find('$') {
+            substituted.push_str(&rest[..idx]);
+            replaced.push_str(&rest[..idx]);
+            rest = &rest[idx + 1..];
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            let name = &rest[..end];
+            match placeholders.iter().find(|(p, _)| *p == name) {
+                Some((_, Some(known))) => {
+                    substituted.push_str(known);
+                    replaced.push_str(known);
+                }
+                Some((_, None)) => {
+                    substituted.push('$');
+                    substituted.push_str(name);
+                    replaced.push_str(PLACEHOLDER_PREFIX);
+                    replaced.push_str(name);
+                }
+                None => {
+                    let allowed: Vec<_> =
+                        placeholders.iter().map(|(p, _)| format!("${p}")).collect();
+                    return Err(format!(
+                        "Unknown placeholder `${name}` in {opt} \"{value}\", expected one of {}",
+                        allowed.join(", ")
+                    ));
+                }
+            }
+            rest = &rest[end..];
+        }
+        substituted.push_str(rest);
+        replaced.push_str(rest);
+
+        syn::parse_str(&replaced)
+            .map(|ty| Self::Template(Box::new(ty)))
+            .map_err(|e| format!("Failed to parse {opt} \"{substituted}\" as type template: {e}"))
+    }
+
+    /// Find the first lifetime embedded in the container type
+    pub(crate) fn find_lifetime(&self) -> Option<&Lifetime> {
+        match self {
+            ContainerType::Path(path) => find_lifetime_from_path(path),
+            ContainerType::Template(ty) => find_lifetime_from_type(ty),
+        }
+    }
+
+    /// Whether the container uses the placeholder. Always false for plain paths.
+    pub(crate) fn uses_placeholder(&self, name: &str) -> bool {
+        fn contains(tokens: TokenStream, placeholder: &Ident) -> bool {
+            tokens.into_iter().any(|tt| match tt {
+                TokenTree::Ident(i) => &i == placeholder,
+                TokenTree::Group(g) => contains(g.stream(), placeholder),
+                _ => false,
+            })
+        }
+
+        match self {
+            ContainerType::Path(_) => false,
+            ContainerType::Template(ty) => contains(
+                ty.to_token_stream(),
+                &Ident::new(&format!("{PLACEHOLDER_PREFIX}{name}"), Span::call_site()),
+            ),
+        }
+    }
+
+    /// Generate the container type from its parameters.
+    ///
+    /// For plain paths, the parameters that are present are appended in order as type
+    /// parameters. For templates, each placeholder is replaced with its parameter. Templates are
+    /// expected to only use placeholders whose parameters are present.
+    pub(crate) fn generate(&self, params: &[(&str, Option<TokenStream>)]) -> TokenStream {
+        match self {
+            ContainerType::Path(path) => {
+                let params: Vec<_> = params.iter().filter_map(|(_, p)| p.as_ref()).collect();
+                if params.is_empty() {
+                    quote! { #path }
+                } else {
+                    quote! { #path <#(#params),*> }
+                }
+            }
+            ContainerType::Template(ty) => substitute(ty.to_token_stream(), params),
+        }
+    }
+}
+
+fn substitute(tokens: TokenStream, params: &[(&str, Option<TokenStream>)]) -> TokenStream {
+    tokens
+        .into_iter()
+        .flat_map(|tt| match tt {
+            TokenTree::Ident(i) => {
+                let name = i.to_string();
+                let param = name
+                    .strip_prefix(PLACEHOLDER_PREFIX)
+                    .and_then(|name| params.iter().find(|(p, _)| *p == name))
+                    .and_then(|(_, param)| param.clone());
+                param.unwrap_or_else(|| TokenTree::Ident(i).into())
+            }
+            TokenTree::Group(g) => {
+                let mut group = Group::new(g.delimiter(), substitute(g.stream(), params));
+                group.set_span(g.span());
+                TokenTree::Group(group).into()
+            }
+            tt => tt.into(),
+        })
+        .collect()
+}
+
+impl syn::parse::Parse for ContainerType {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        input.parse().map(Self::Path)
+    }
+}
+
+impl ToTokens for ContainerType {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        match self {
+            ContainerType::Path(path) => path.to_tokens(tokens),
+            ContainerType::Template(ty) => ty.to_tokens(tokens),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map_placeholders(n: Option<&str>) -> [(&'static str, Option<String>); 3] {
+        [("K", None), ("V", None), ("N", n.map(str::to_owned))]
+    }
+
+    fn map_params() -> [(&'static str, Option<TokenStream>); 3] {
+        [
+            ("K", Some(quote! { u32 })),
+            ("V", Some(quote! { Value })),
+            ("N", Some(quote! { 4 })),
+        ]
+    }
+
+    #[test]
+    fn path() {
+        let container =
+            ContainerType::parse("map_type", "heapless::FnvIndexMap", &map_placeholders(None))
+                .unwrap();
+        assert!(!container.uses_placeholder("N"));
+        assert_eq!(
+            container.generate(&map_params()).to_string(),
+            quote! { heapless::FnvIndexMap<u32, Value, 4> }.to_string()
+        );
+        assert_eq!(
+            container
+                .generate(&[
+                    ("K", Some(quote! { u32 })),
+                    ("V", Some(quote! { Value })),
+                    ("N", None)
+                ])
+                .to_string(),
+            quote! { heapless::FnvIndexMap<u32, Value> }.to_string()
+        );
+    }
+
+    #[test]
+    fn template() {
+        let container = ContainerType::parse(
+            "map_type",
+            "MyMap<$N, $K, Vec<$V>, Hasher>",
+            &map_placeholders(None),
+        )
+        .unwrap();
+        assert!(container.uses_placeholder("N"));
+        assert!(container.uses_placeholder("V"));
+        assert_eq!(
+            container.generate(&map_params()).to_string(),
+            quote! { MyMap<4, u32, Vec<Value>, Hasher> }.to_string()
+        );
+
+        let err =
+            ContainerType::parse("map_type", "MyMap<$K, $X>", &map_placeholders(None)).unwrap_err();
+        assert!(err.contains("Unknown placeholder `$X`"));
+        let err =
+            ContainerType::parse("map_type", "MyMap<$K, $V", &map_placeholders(None)).unwrap_err();
+        assert!(err.contains("as type template"));
+    }
+
+    #[test]
+    fn template_known_placeholder() {
+        let container = ContainerType::parse(
+            "string_type",
+            "PoolString<'static, $N, { pool::BUCKET }>",
+            &[("N", Some("8".to_owned()))],
+        )
+        .unwrap();
+        assert!(!container.uses_placeholder("N"));
+        assert!(container.find_lifetime().is_some());
+        assert_eq!(
+            container.generate(&[("N", Some(quote! { 8 }))]).to_string(),
+            quote! { PoolString<'static, 8, { pool::BUCKET }> }.to_string()
+        );
+
+        let err = ContainerType::parse(
+            "vec_type",
+            "MyVec<$T, $N",
+            &[("T", None), ("N", Some("8".to_owned()))],
+        )
+        .unwrap_err();
+        assert!(err.contains("\"MyVec<$T, 8\" as type template"));
+    }
+}
@@ -1,17 +1,24 @@
+use convert_case::Case;
 use proc_macro2::{Literal, Span, TokenStream};
-use quote::{format_ident, quote};
+use quote::{format_ident, quote, ToTokens};
 use syn::{Ident, Lifetime};
 
-use crate::config::OptionalRepr;
+use crate::config::{OptionalRepr, OverflowPolicy};
 use crate::descriptor::{
     DescriptorProto, FieldDescriptorProto,
     FieldDescriptorProto_::{Label, Type},
 };
+use crate::utils::json_name;
+use crate::OptimizeFor;
 
 use super::Syntax;
 use super::{
-    type_spec::{find_lifetime_from_type, TypeSpec},
-    CurrentConfig, EncodeFunc, Generator,
+    container::ContainerType,
+    prost_ident,
+    type_spec::{
+        capacity_assert, find_lifetime_from_type, TypeSpec, PROPTEST_MAX_LEN, ROUNDTRIP_FILL_LEN,
+    },
+    CurrentConfig, EncodeFunc, Generator, MaxSize,
 };
 
 #[cfg_attr(test, derive(Debug, PartialEq, Eq))]
@@ -26,8 +33,11 @@ pub(crate) enum FieldType {
     Map {
         key: TypeSpec,
         val: TypeSpec,
-        type_path: syn::Path,
+        type_path: ContainerType,
         max_len: Option<u32>,
+        overflow_policy: OverflowPolicy,
+        /// Encode entries in ascending key order
+        sorted: bool,
     },
     // Implicit presence
     Single(TypeSpec),
@@ -36,12 +46,28 @@ pub(crate) enum FieldType {
     Repeated {
         typ: TypeSpec,
         packed: bool,
-        type_path: syn::Path,
+        type_path: ContainerType,
         max_len: Option<u32>,
+        overflow_policy: OverflowPolicy,
+    },
+    // Repeated field whose elements are passed to the message's visitor
+    Visit {
+        typ: TypeSpec,
+        /// Pass decoded elements to the visitor, rather than skipping them
+        decode: bool,
     },
     Custom(CustomField),
 }
 
+impl FieldType {
+    pub(crate) fn is_bytes_range(&self) -> bool {
+        matches!(
+            self,
+            FieldType::Single(TypeSpec::BytesRange) | FieldType::Optional(TypeSpec::BytesRange, _)
+        )
+    }
+}
+
 #[cfg_attr(test, derive(Debug, PartialEq, Eq))]
 pub(crate) struct Field<'a> {
     pub(crate) num: u32,
@@ -52,8 +78,16 @@ pub(crate) struct Field<'a> {
     pub(crate) rust_name: String,
     /// Sanitized Rust ident after renaming, used for field name
     pub(crate) san_rust_name: Ident,
+    /// JSON name, used as the key when writing JSON
+    pub(crate) json_name: String,
     pub(crate) default: Option<&'a str>,
     pub(crate) boxed: bool,
+    /// Encode the field even if it holds the default value, if it has implicit presence
+    pub(crate) emit_defaults: bool,
+    /// Leave the field out of the conversions to and from the domain type
+    pub(crate) domain_skip: bool,
+    /// Proto2 `required` field
+    pub(crate) required: bool,
     pub(crate) attrs: Vec<syn::Attribute>,
 }
 
@@ -87,6 +121,10 @@ impl<'a> Field<'a> {
         let name = &proto.name;
         let (rust_name, raw_rust_name) = field_conf.config.rust_field_name(name)?;
         let boxed = field_conf.config.boxed.unwrap_or(false);
+        let overflow_policy = field_conf
+            .config
+            .overflow_policy
+            .unwrap_or(OverflowPolicy::Error);
 
         let ftype = match (
             field_conf.config.custom_field_parsed()?,
@@ -101,24 +139,61 @@ impl<'a> Field<'a> {
                 let type_path = field_conf.config.map_type_parsed()?.ok_or_else(|| {
                     "Field is of type `map`, but map_type was not configured for it".to_owned()
                 })?;
+                let max_len = field_conf.config.container_capacity(
+                    "map_type",
+                    field_conf.config.map_type.as_deref().unwrap_or_default(),
+                    "max_len",
+                    field_conf.config.max_len,
+                )?;
+                if max_len.is_none() && type_path.uses_placeholder("N") {
+                    return Err(
+                        "map_type uses the $N placeholder, but max_len was not configured for it"
+                            .to_owned(),
+                    );
+                }
                 FieldType::Map {
                     key,
                     val,
                     type_path,
-                    max_len: field_conf.config.max_len,
+                    max_len,
+                    overflow_policy,
+                    sorted: field_conf.config.sort_map_entries.unwrap_or(false),
+                }
+            }
+
+            (None, None, Label::Repeated) if field_conf.config.visit.unwrap_or(false) => {
+                FieldType::Visit {
+                    typ: TypeSpec::from_proto(proto, &field_conf.next_conf("elem"))?,
+                    decode: field_conf.config.visit_decode.unwrap_or(true),
                 }
             }
 
             (None, None, Label::Repeated) => FieldType::Repeated {
                 typ: TypeSpec::from_proto(proto, &field_conf.next_conf("elem"))?,
-                type_path: field_conf.config.vec_type_parsed()?.ok_or_else(|| {
-                    "Field is repeated, but vec_type was not configured for it".to_owned()
-                })?,
-                max_len: field_conf.config.max_len,
+                type_path: field_conf
+                    .config
+                    .vec_type_parsed(field_conf.config.max_len)?
+                    .ok_or_else(|| {
+                        "Field is repeated, but vec_type was not configured for it".to_owned()
+                    })?,
+                max_len: field_conf.config.container_capacity(
+                    "vec_type",
+                    field_conf.config.vec_type.as_deref().unwrap_or_default(),
+                    "max_len",
+                    field_conf.config.max_len,
+                )?,
+                overflow_policy,
+                // Repeated scalars are packed by default in proto3, but not in proto2
                 packed: proto
                     .options()
                     .and_then(|opt| opt.packed().copied())
-                    .unwrap_or(false),
+                    .unwrap_or(
+                        syntax == Syntax::Proto3
+                            && !matches!(
+                                proto.r#type,
+                                Type::String | Type::Bytes | Type::Message | Type::Group
+                            ),
+                    ),
             },
 
             (None, None, Label::Required | Label::Optional)
@@ -136,6 +211,20 @@ impl<'a> Field<'a> {
 
             (None, None, _) => FieldType::Single(TypeSpec::from_proto(proto, field_conf)?),
         };
+        if field_conf.config.visit.unwrap_or(false) && !matches!(ftype, FieldType::Visit { .. }) {
+            return Err("visit is only supported for repeated non-map fields".to_owned());
+        }
+        if field_conf.config.visit_decode.is_some() && !matches!(ftype, FieldType::Visit { .. }) {
+            return Err("visit_decode is only supported for visited fields".to_owned());
+        }
+        if field_conf.config.sort_map_entries.unwrap_or(false)
+            && !matches!(ftype, FieldType::Map { .. })
+        {
+            return Err("sort_map_entries is only supported for map fields".to_owned());
+        }
+        if field_conf.config.bytes_range.unwrap_or(false) && !ftype.is_bytes_range() {
+            return Err("bytes_range is only supported for singular bytes fields".to_owned());
+        }
         let attrs = field_conf.config.field_attr_parsed()?;
 
         Ok(Some(Field {
@@ -144,8 +233,15 @@ impl<'a> Field<'a> {
             name,
             rust_name,
             san_rust_name: raw_rust_name,
+            json_name: proto
+                .json_name()
+                .cloned()
+                .unwrap_or_else(|| json_name(name)),
             default: proto.default_value().map(String::as_str),
             boxed,
+            emit_defaults: field_conf.config.emit_defaults.unwrap_or(false),
+            domain_skip: field_conf.config.domain_skip.unwrap_or(false),
+            required: proto.label == Label::Required,
             attrs,
         }))
     }
@@ -161,8 +257,8 @@ impl<'a> Field<'a> {
             } => {
                 let k = key.generate_rust_type(gen);
                 let v = val.generate_rust_type(gen);
-                let max_len = max_len.map(Literal::u32_unsuffixed).into_iter();
-                quote! { #type_name <#k, #v #(, #max_len)* > }
+                let max_len = max_len.map(|n| Literal::u32_unsuffixed(n).into_token_stream());
+                type_name.generate(&[("K", Some(k)), ("V", Some(v)), ("N", max_len)])
             }
 
             FieldType::Single(t) | FieldType::Optional(t, _) => t.generate_rust_type(gen),
@@ -174,20 +270,99 @@ impl<'a> Field<'a> {
                 ..
             } => {
                 let t = typ.generate_rust_type(gen);
-                let max_len = max_len.map(Literal::u32_unsuffixed).into_iter();
-                quote! { #type_path <#t #(, #max_len)* > }
+                let max_len = max_len.map(|n| Literal::u32_unsuffixed(n).into_token_stream());
+                type_path.generate(&[("T", Some(t)), ("N", max_len)])
             }
 
             FieldType::Custom(CustomField::Type(t)) => return quote! {#t},
             FieldType::Custom(CustomField::Delegate(_)) => {
                 unreachable!("delegate field cannot have a type")
             }
+            FieldType::Visit { .. } => unreachable!("visited field cannot have a type"),
         };
         gen.wrapped_type(typ, self.boxed, self.is_option())
     }
 
+    /// Compile-time checks that the containers of the field can hold their configured bounds
+    pub(crate) fn generate_capacity_asserts(&self, gen: &Generator, msg_name: &str) -> TokenStream {
+        let field_path = format!("{msg_name}.{}", self.name);
+        let max_len_msg =
+            format!("max_len of `{field_path}` exceeds the capacity of its container");
+        match &self.ftype {
+            FieldType::Map {
+                key,
+                val,
+                type_path,
+                max_len,
+                ..
+            } => {
+                let k = key.generate_rust_type(gen);
+                let v = val.generate_rust_type(gen);
+                let map_assert = max_len.map(|max_len| {
+                    let n = Literal::u32_unsuffixed(max_len).into_token_stream();
+                    capacity_assert(
+                        type_path.generate(&[
+                            ("K", Some(k.clone())),
+                            ("V", Some(v.clone())),
+                            ("N", Some(n)),
+                        ]),
+                        quote! { ::micropb::PbMap<#k, #v> },
+                        max_len,
+                        &max_len_msg,
+                    )
+                });
+                let key_assert = key.generate_capacity_assert(gen, &format!("{field_path}.key"));
+                let val_assert = val.generate_capacity_assert(gen, &format!("{field_path}.value"));
+                quote! { #map_assert #key_assert #val_assert }
+            }
+            FieldType::Repeated {
+                typ,
+                type_path,
+                max_len,
+                ..
+            } => {
+                let t = typ.generate_rust_type(gen);
+                let vec_assert = max_len.map(|max_len| {
+                    let n = Literal::u32_unsuffixed(max_len).into_token_stream();
+                    capacity_assert(
+                        type_path.generate(&[("T", Some(t.clone())), ("N", Some(n))]),
+                        quote! { ::micropb::PbVec<#t> },
+                        max_len,
+                        &max_len_msg,
+                    )
+                });
+                let elem_assert = typ.generate_capacity_assert(gen, &field_path);
+                quote! { #vec_assert #elem_assert }
+            }
+            FieldType::Single(t) | FieldType::Optional(t, _) => t
+                .generate_capacity_assert(gen, &field_path)
+                .unwrap_or_default(),
+            FieldType::Custom(_) | FieldType::Visit { .. } => quote! {},
+        }
+    }
+
+    /// Whether any integer of the field is a 64-bit integer stored as a pointer-sized integer
+    pub(crate) fn has_64bit_as_size(&self) -> bool {
+        match &self.ftype {
+            FieldType::Map { key, val, .. } => key.is_64bit_as_size() || val.is_64bit_as_size(),
+            FieldType::Single(typ)
+            | FieldType::Optional(typ, _)
+            | FieldType::Repeated { typ, .. }
+            | FieldType::Visit { typ, .. } => typ.is_64bit_as_size(),
+            FieldType::Custom(_) => false,
+        }
+    }
+
+    /// Whether the field is part of the message struct
+    pub(crate) fn is_stored(&self) -> bool {
+        !matches!(
+            self.ftype,
+            FieldType::Custom(CustomField::Delegate(_)) | FieldType::Visit { .. }
+        )
+    }
+
     pub(crate) fn generate_field(&self, gen: &Generator) -> TokenStream {
-        if let FieldType::Custom(CustomField::Delegate(_)) = self.ftype {
+        if !self.is_stored() {
             return quote! {};
         }
         let typ = self.generate_rust_type(gen);
@@ -208,14 +383,51 @@ impl<'a> Field<'a> {
             FieldType::Optional(_, OptionalRepr::Option) => {
                 return Ok(quote! { ::core::option::Option::None })
             }
-            FieldType::Custom(CustomField::Delegate(_)) => {
-                unreachable!("delegate field cannot have default")
+            FieldType::Custom(CustomField::Delegate(_)) | FieldType::Visit { .. } => {
+                unreachable!("delegate and visited fields cannot have default")
             }
             _ => {}
         }
         Ok(quote! { ::core::default::Default::default() })
     }
 
+    pub(crate) fn generate_clear(&self, gen: &Generator) -> Result<TokenStream, String> {
+        let fname = &self.san_rust_name;
+        let extra_deref = self.boxed.then(|| quote! { * });
+        let stmt = match &self.ftype {
+            // Clear containers in-place to retain their allocations
+            FieldType::Map { .. } | FieldType::Repeated { .. } => {
+                quote! { self.#fname.pb_clear(); }
+            }
+            FieldType::Single(tspec) | FieldType::Optional(tspec, OptionalRepr::Hazzer) => {
+                match tspec {
+                    TypeSpec::Message(_) => {
+                        quote! { ::micropb::MessageDecode::clear(&mut #extra_deref self.#fname); }
+                    }
+                    TypeSpec::String { .. }
+                    | TypeSpec::Bytes { .. }
+                    | TypeSpec::LazyMessage { .. }
+                        if self.default.is_none() =>
+                    {
+                        quote! { self.#fname.pb_clear(); }
+                    }
+                    _ => {
+                        let default = self.generate_default(gen)?;
+                        quote! { self.#fname = #default; }
+                    }
+                }
+            }
+            FieldType::Optional(_, OptionalRepr::Option) => {
+                quote! { self.#fname = ::core::option::Option::None; }
+            }
+            FieldType::Custom(CustomField::Type(_)) => {
+                quote! { self.#fname = ::core::default::Default::default(); }
+            }
+            FieldType::Custom(CustomField::Delegate(_)) | FieldType::Visit { .. } => quote! {},
+        };
+        Ok(stmt)
+    }
+
     pub(crate) fn generate_decode_branch(
         &self,
         gen: &Generator,
@@ -227,27 +439,62 @@ impl<'a> Field<'a> {
         let mut_ref = Ident::new("mut_ref", Span::call_site());
         let extra_deref = self.boxed.then(|| quote! { * });
 
+        // When optimizing for size, scalar fields check the wire type and decode the value through
+        // a shared helper
+        let tagged_decode = match &self.ftype {
+            FieldType::Single(tspec) | FieldType::Optional(tspec, _)
+                if gen.optimize == OptimizeFor::Size =>
+            {
+                tspec.generate_decode_tagged_expr(decoder, tag)
+            }
+            _ => None,
+        };
+        let decode_field_mut = |tspec: &TypeSpec| match &tagged_decode {
+            Some(val_expr) => quote! {
+                let val = #val_expr?;
+                *#mut_ref = val as _;
+            },
+            None => tspec.generate_decode_field_mut(gen, decoder, &mut_ref),
+        };
+
         let decode_code = match &self.ftype {
-            FieldType::Map { key, val, .. } => {
-                let key_decode_expr = key.generate_decode_mut(gen, false, decoder, &mut_ref);
-                let val_decode_expr = val.generate_decode_mut(gen, false, decoder, &mut_ref);
+            FieldType::Map {
+                key,
+                val,
+                overflow_policy,
+                ..
+            } => {
+                let policy = overflow_policy.generate_policy();
+                let key_decode_expr = key.generate_decode_mut(gen, decoder, &mut_ref);
+                let val_decode_expr = val.generate_decode_mut(gen, decoder, &mut_ref);
                 let key_type = key.generate_rust_type(gen);
                 let val_type = val.generate_rust_type(gen);
-                quote! {
-                    if let Some((k, v)) = #decoder.decode_map_elem(
-                        |#mut_ref: &mut #key_type, #decoder| { #key_decode_expr; Ok(()) },
-                        |#mut_ref: &mut #val_type, #decoder| { #val_decode_expr; Ok(()) },
-                    )?
-                    {
-                        if let (Err(_), false) = (self.#fname.pb_insert(k, v), #decoder.ignore_repeated_cap_err) {
-                            return Err(::micropb::DecodeError::Capacity);
+                if val.drops_unknown_enum() {
+                    // Entries with unknown enum values are discarded entirely, so the value has
+                    // to be checked before it's inserted
+                    quote! {
+                        let (k, v) = #decoder.decode_map_elem(
+                            |#mut_ref: &mut #key_type, #decoder| { #key_decode_expr; Ok(()) },
+                            |#mut_ref: &mut #val_type, #decoder| { #val_decode_expr; Ok(()) },
+                        )?;
+                        if v.is_known() && self.#fname.pb_insert(k, v).is_err() {
+                            #decoder.handle_overflow(#policy, 1, self.#fname.pb_len())?;
                         }
                     }
+                } else {
+                    quote! {
+                        #decoder.decode_map_entry(
+                            &mut #extra_deref self.#fname,
+                            |#mut_ref: &mut #key_type, #decoder| { #key_decode_expr; Ok(()) },
+                            |#mut_ref: &mut #val_type, #decoder| { #val_decode_expr; Ok(()) },
+                            #policy,
+                        )?;
+                    }
                 }
             }
 
             FieldType::Single(tspec) => {
-                let decode_stmts = tspec.generate_decode_mut(gen, true, decoder, &mut_ref);
+                let decode_stmts = decode_field_mut(tspec);
                 quote! {
                     let #mut_ref = &mut #extra_deref self.#fname;
                     { #decode_stmts };
@@ -255,9 +502,25 @@ impl<'a> Field<'a> {
             }
 
             FieldType::Optional(tspec, OptionalRepr::Hazzer) => {
-                let decode_expr = tspec.generate_decode_mut(gen, false, decoder, &mut_ref);
+                let decode_expr = decode_field_mut(tspec);
                 let setter = format_ident!("set_{}", self.rust_name);
+                // Lazy messages append to the stored bytes, so stale bytes from a cleared field
+                // must be discarded first. Likewise, an empty wrapper must leave a zero value.
+                let clear_stale = match tspec {
+                    TypeSpec::LazyMessage { .. } => Some(quote! {
+                        if !self._has.#fname() {
+                            ::micropb::PbContainer::pb_clear(&mut #extra_deref self.#fname);
+                        }
+                    }),
+                    TypeSpec::Wrapper(_) => Some(quote! {
+                        if !self._has.#fname() {
+                            #extra_deref self.#fname = ::core::default::Default::default();
+                        }
+                    }),
+                    _ => None,
+                };
                 quote! {
+                    #clear_stale
                     let #mut_ref = &mut #extra_deref self.#fname;
                     { #decode_expr };
                     self._has.#setter();
@@ -265,37 +528,109 @@ impl<'a> Field<'a> {
             }
 
             FieldType::Optional(tspec, OptionalRepr::Option) => {
-                let decode_stmts = tspec.generate_decode_mut(gen, false, decoder, &mut_ref);
+                let decode_stmts = decode_field_mut(tspec);
                 quote! {
-                    let #mut_ref = &mut #extra_deref *self.#fname.get_or_insert_with(::core::default::Default::default);
+                    let #mut_ref = loop {
+                        if let ::core::option::Option::Some(val) = &mut self.#fname {
+                            break &mut #extra_deref *val;
+                        }
+                        ::micropb::__private::set_outlined(&mut self.#fname, || ::core::option::Option::Some(::core::default::Default::default()));
+                    };
                     { #decode_stmts };
                 }
             }
 
-            FieldType::Repeated { typ, .. } => {
+            FieldType::Repeated {
+                typ,
+                overflow_policy,
+                ..
+            } => {
+                let policy = overflow_policy.generate_policy();
                 // Type can be packed and is Copy, so we check the wire type to see if we can
                 // do packed decoding
-                if let Some(val) = typ.generate_decode_val(gen, decoder) {
+                if typ.drops_unknown_enum() {
+                    let val = typ.generate_decode_val(gen, decoder).unwrap();
+                    let rust_type = typ.generate_rust_type(gen);
+                    // Unknown elements are filtered out, so packed elements are pushed one by one
                     quote! {
                         if #tag.wire_type() == ::micropb::WIRE_TYPE_LEN {
-                            #decoder.decode_packed(&mut #extra_deref self.#fname, |#decoder| #val.map(|v| v as _))?;
+                            let mut overflow = 0;
+                            #decoder.visit_packed(|#decoder| #val, |val: #rust_type| {
+                                if val.is_known() && self.#fname.pb_push(val).is_err() {
+                                    overflow += 1;
+                                }
+                            })?;
+                            if overflow > 0 {
+                                #decoder.handle_overflow(#policy, overflow, self.#fname.len())?;
+                            }
                         } else {
-                            if let (Err(_), false) = (self.#fname.pb_push(#val? as _), #decoder.ignore_repeated_cap_err) {
-                                return Err(::micropb::DecodeError::Capacity);
+                            let val = #val?;
+                            if val.is_known() && self.#fname.pb_push(val).is_err() {
+                                #decoder.handle_overflow(#policy, 1, self.#fname.len())?;
                             }
                         }
                     }
+                } else if typ.is_bulk_fixed() {
+                    let val = typ.generate_decode_val(gen, decoder).unwrap();
+                    quote! {
+                        if #tag.wire_type() == ::micropb::WIRE_TYPE_LEN {
+                            #decoder.decode_packed_fixed(&mut #extra_deref self.#fname, #policy)?;
+                        } else if self.#fname.pb_push(#val?).is_err() {
+                            #decoder.handle_overflow(#policy, 1, self.#fname.len())?;
+                        }
+                    }
+                } else if let Some(val) = typ.generate_decode_val(gen, decoder) {
+                    quote! {
+                        if #tag.wire_type() == ::micropb::WIRE_TYPE_LEN {
+                            #decoder.decode_packed_with_policy(&mut #extra_deref self.#fname, #policy, |#decoder| #val.map(|v| v as _))?;
+                        } else if self.#fname.pb_push(#val? as _).is_err() {
+                            #decoder.handle_overflow(#policy, 1, self.#fname.len())?;
+                        }
+                    }
+                } else if let TypeSpec::Message(_) = typ {
+                    quote! { #decoder.decode_repeated_message(&mut #extra_deref self.#fname, #policy)?; }
                 } else {
-                    let decode_expr = typ.generate_decode_mut(gen, false, decoder, &mut_ref);
+                    let decode_expr = typ.generate_decode_mut(gen, decoder, &mut_ref);
                     let rust_type = typ.generate_rust_type(gen);
                     quote! {
                         let mut val: #rust_type = ::core::default::Default::default();
                         let #mut_ref = &mut val;
                         { #decode_expr };
-                        if let (Err(_), false) = (self.#fname.pb_push(val), #decoder.ignore_repeated_cap_err) {
-                            return Err(::micropb::DecodeError::Capacity);
+                        if self.#fname.pb_push(val).is_err() {
+                            #decoder.handle_overflow(#policy, 1, self.#fname.len())?;
+                        }
+                    }
+                }
+            }
+
+            // Elements are skipped without being decoded
+            FieldType::Visit { decode: false, .. } => {
+                quote! { #decoder.skip_wire_value(#tag.wire_type())?; }
+            }
+
+            FieldType::Visit { typ, .. } => {
+                let visit = format_ident!("on_{}", self.rust_name);
+                let rust_type = typ.generate_rust_type(gen);
+                if let Some(val) = typ.generate_decode_val(gen, decoder) {
+                    let known_check = typ
+                        .drops_unknown_enum()
+                        .then(|| quote! { if val.is_known() });
+                    quote! {
+                        if #tag.wire_type() == ::micropb::WIRE_TYPE_LEN {
+                            #decoder.visit_packed(|#decoder| #val.map(|v| v as _), |val: #rust_type| #known_check { self._visitor.#visit(&val) })?;
+                        } else {
+                            let val: #rust_type = #val? as _;
+                            #known_check { self._visitor.#visit(&val); }
                         }
                     }
+                } else {
+                    let decode_expr = typ.generate_decode_mut(gen, decoder, &mut_ref);
+                    quote! {
+                        let mut val: #rust_type = ::core::default::Default::default();
+                        let #mut_ref = &mut val;
+                        { #decode_expr };
+                        self._visitor.#visit(&val);
+                    }
                 }
             }
 
@@ -308,8 +643,44 @@ impl<'a> Field<'a> {
             }
         };
 
+        let wire_type_check = match &self.ftype {
+            FieldType::Custom(_) => None,
+            // Already checked by the helper
+            _ if tagged_decode.is_some() => None,
+            // Packable repeated fields can be either packed or unpacked on the wire
+            FieldType::Repeated { typ, .. } | FieldType::Visit { typ, .. }
+                if typ.wire_type() != micropb::WIRE_TYPE_LEN =>
+            {
+                let wire_type = typ.wire_type();
+                Some(quote! { #tag.wire_type() != #wire_type && #tag.wire_type() != ::micropb::WIRE_TYPE_LEN })
+            }
+            _ => {
+                let wire_type = self.wire_type();
+                Some(quote! { #tag.wire_type() != #wire_type })
+            }
+        }
+        .map(|cond| quote! { if #cond { return Err(::micropb::DecodeError::WrongWireType); } });
+
+        // Unknown enum values are dropped before the field is touched, so that optional fields
+        // aren't marked as present
+        let decode_code = match &self.ftype {
+            FieldType::Single(tspec) | FieldType::Optional(tspec, _)
+                if tspec.drops_unknown_enum() =>
+            {
+                let val = tspec.generate_decode_val(gen, decoder).unwrap();
+                quote! {
+                    let val = #val?;
+                    if val.is_known() { #decode_code }
+                }
+            }
+            _ => decode_code,
+        };
+
         quote! {
-            #fnum => { #decode_code }
+            #fnum => {
+                #wire_type_check
+                #decode_code
+            }
         }
     }
 
@@ -317,6 +688,7 @@ impl<'a> Field<'a> {
         match &self.ftype {
             FieldType::Single(typ)
             | FieldType::Optional(typ, _)
+            | FieldType::Visit { typ, .. }
             | FieldType::Repeated {
                 typ, packed: false, ..
             } => typ.wire_type(),
@@ -330,6 +702,111 @@ impl<'a> Field<'a> {
         }
     }
 
+    /// Wire type of the field's values and its `FieldLabel`, for the field's entry in the
+    /// `MessageFields` table. Returns `None` for custom fields.
+    pub(crate) fn descriptor_label(&self) -> Option<(u8, TokenStream)> {
+        let res = match &self.ftype {
+            FieldType::Single(typ) => (typ.wire_type(), quote! { Single }),
+            FieldType::Optional(typ, _) => (typ.wire_type(), quote! { Optional }),
+            FieldType::Repeated { typ, packed, .. } => {
+                (typ.wire_type(), quote! { Repeated { packed: #packed } })
+            }
+            FieldType::Visit { typ, .. } => {
+                (typ.wire_type(), quote! { Repeated { packed: false } })
+            }
+            FieldType::Map { .. } => (micropb::WIRE_TYPE_LEN, quote! { Map }),
+            FieldType::Custom(_) => return None,
+        };
+        Some(res)
+    }
+
+    /// Maximum size of the field on the wire, including tags. Returns `None` if the size is
+    /// unbounded.
+    pub(crate) fn max_size(&self, gen: &Generator) -> Option<MaxSize> {
+        // Boxed fields can be recursive, so referencing the size of the boxed type could lead to a
+        // cycle during constant evaluation
+        if self.boxed {
+            return None;
+        }
+        let tag = micropb::Tag::from_parts(self.num, self.wire_type());
+        let tag_len = MaxSize::Known(::micropb::size::sizeof_tag(tag));
+
+        match &self.ftype {
+            FieldType::Map {
+                key, val, max_len, ..
+            } => {
+                let elem = MaxSize::Known(2)
+                    .add(key.max_size(gen)?)
+                    .add(val.max_size(gen)?);
+                Some(tag_len.add(elem.len_record()).mul((*max_len)?))
+            }
+
+            FieldType::Single(tspec) | FieldType::Optional(tspec, _) => {
+                Some(tag_len.add(tspec.max_size(gen)?))
+            }
+
+            FieldType::Repeated {
+                typ,
+                packed: false,
+                max_len,
+                ..
+            } => Some(tag_len.add(typ.max_size(gen)?).mul((*max_len)?)),
+
+            FieldType::Repeated {
+                typ,
+                packed: true,
+                max_len,
+                ..
+            } => Some(tag_len.add(typ.max_size(gen)?.mul((*max_len)?).len_record())),
+
+            // Visited elements come from the visitor, so their number isn't bounded
+            FieldType::Visit { .. } | FieldType::Custom(CustomField::Type(_)) => None,
+            FieldType::Custom(CustomField::Delegate(_)) => Some(MaxSize::Known(0)),
+        }
+    }
+
+    /// Generate iterator over the entries of a map field for encoding
+    fn generate_map_iter(&self) -> TokenStream {
+        let fname = &self.san_rust_name;
+        let extra_deref = self.boxed.then(|| quote! { * });
+        match &self.ftype {
+            FieldType::Map {
+                sorted: true,
+                max_len: Some(max_len),
+                ..
+            } => {
+                let max_len = Literal::u32_unsuffixed(*max_len);
+                quote! { ::micropb::container::SortedMapEntries::<_, _, #max_len>::new(& #extra_deref self.#fname) }
+            }
+            FieldType::Map { sorted: true, .. } => {
+                quote! { ::micropb::container::SortedMapIter::new(& #extra_deref self.#fname) }
+            }
+            _ => quote! { self.#fname.pb_iter() },
+        }
+    }
+
+    /// Generate statements that encode the tag and value of a single element of the field
+    fn generate_encode_stmts(
+        &self,
+        gen: &Generator,
+        tspec: &TypeSpec,
+        encoder: &Ident,
+    ) -> TokenStream {
+        let val_ref = Ident::new("val_ref", Span::call_site());
+        let tag_val = micropb::Tag::from_parts(self.num, tspec.wire_type()).varint();
+        let tagged_expr = (gen.optimize == OptimizeFor::Size)
+            .then(|| tspec.generate_encode_tagged_expr(encoder, tag_val, &val_ref))
+            .flatten();
+        if let Some(tagged_expr) = tagged_expr {
+            return quote! { #tagged_expr?; };
+        }
+        let encode_expr = tspec.generate_encode_expr(gen, encoder, &val_ref);
+        quote! {
+            #encoder.encode_varint32(#tag_val)?;
+            #encode_expr?;
+        }
+    }
+
     pub(crate) fn generate_encode(&self, gen: &Generator, func_type: &EncodeFunc) -> TokenStream {
         let fname = &self.san_rust_name;
         let val_ref = Ident::new("val_ref", Span::call_site());
@@ -341,12 +818,17 @@ impl<'a> Field<'a> {
 
         let sizeof_code = match &self.ftype {
             FieldType::Map { key, val, .. } => {
+                // Order of the entries doesn't affect the size
+                let iter = match &func_type {
+                    EncodeFunc::Sizeof(_) => quote! { self.#fname.pb_iter() },
+                    EncodeFunc::Encode(_) => self.generate_map_iter(),
+                };
                 let key_sizeof = key.generate_sizeof(gen, &val_ref);
                 let val_sizeof = val.generate_sizeof(gen, &val_ref);
 
                 let stmts = match &func_type {
                     EncodeFunc::Sizeof(size) => {
-                        quote! { #size += ::micropb::size::sizeof_len_record(len) + #tag_len; }
+                        quote! { #size = #size.saturating_add(::micropb::size::sizeof_len_record(len)).saturating_add(#tag_len); }
                     }
                     EncodeFunc::Encode(encoder) => {
                         let key_encode = key.generate_encode_expr(gen, encoder, &val_ref);
@@ -364,7 +846,7 @@ impl<'a> Field<'a> {
                     }
                 };
                 quote! {
-                    for (k, v) in self.#fname.pb_iter() {
+                    for (k, v) in #iter {
                         let len = ::micropb::size::sizeof_map_elem(k, v, |#val_ref| { #key_sizeof }, |#val_ref| { #val_sizeof });
                         #stmts
                     }
@@ -375,7 +857,8 @@ impl<'a> Field<'a> {
                 let check = if let FieldType::Optional(..) = self.ftype {
                     quote! { if let Some(#val_ref) = self.#fname() }
                 } else {
-                    let implicit_presence_check = tspec.generate_implicit_presence_check(&val_ref);
+                    let implicit_presence_check = (!self.emit_defaults)
+                        .then(|| tspec.generate_implicit_presence_check(&val_ref));
                     quote! {
                         let #val_ref = &#extra_deref self.#fname;
                         #implicit_presence_check
@@ -384,15 +867,9 @@ impl<'a> Field<'a> {
                 let stmts = match &func_type {
                     EncodeFunc::Sizeof(size) => {
                         let sizeof_expr = tspec.generate_sizeof(gen, &val_ref);
-                        quote! { #size += #tag_len + #sizeof_expr; }
-                    }
-                    EncodeFunc::Encode(encoder) => {
-                        let encode_expr = tspec.generate_encode_expr(gen, encoder, &val_ref);
-                        quote! {
-                            #encoder.encode_varint32(#tag_val)?;
-                            #encode_expr?;
-                        }
+                        quote! { #size = #size.saturating_add(#tag_len).saturating_add(#sizeof_expr); }
                     }
+                    EncodeFunc::Encode(encoder) => self.generate_encode_stmts(gen, tspec, encoder),
                 };
                 quote! {
                     #check {
@@ -406,18 +883,14 @@ impl<'a> Field<'a> {
             } => 'expr: {
                 let stmts = match (&func_type, typ.fixed_size()) {
                     (EncodeFunc::Sizeof(size), Some(fixed)) => {
-                        break 'expr quote! { #size += self.#fname.len() * (#tag_len + #fixed); };
+                        break 'expr quote! { #size = #size.saturating_add(self.#fname.len().saturating_mul(#tag_len + #fixed)); };
                     }
                     (EncodeFunc::Sizeof(size), None) => {
                         let sizeof_expr = typ.generate_sizeof(gen, &val_ref);
-                        quote! { #size += #tag_len + #sizeof_expr; }
+                        quote! { #size = #size.saturating_add(#tag_len).saturating_add(#sizeof_expr); }
                     }
                     (EncodeFunc::Encode(encoder), _) => {
-                        let encode_expr = typ.generate_encode_expr(gen, encoder, &val_ref);
-                        quote! {
-                            #encoder.encode_varint32(#tag_val)?;
-                            #encode_expr?;
-                        }
+                        self.generate_encode_stmts(gen, typ, encoder)
                     }
                 };
                 quote! {
@@ -431,18 +904,26 @@ impl<'a> Field<'a> {
                 typ, packed: true, ..
             } => {
                 let len = if let Some(fixed) = typ.fixed_size() {
-                    quote! { self.#fname.len() * #fixed }
+                    quote! { self.#fname.len().saturating_mul(#fixed) }
                 } else {
                     let sizeof_expr = typ.generate_sizeof(gen, &val_ref);
                     quote! { ::micropb::size::sizeof_packed(& #extra_deref self.#fname, |#val_ref| #sizeof_expr) }
                 };
                 let stmts = match &func_type {
                     EncodeFunc::Sizeof(size) => {
-                        quote! { #size += #tag_len + ::micropb::size::sizeof_len_record(len); }
+                        quote! { #size = #size.saturating_add(#tag_len).saturating_add(::micropb::size::sizeof_len_record(#len)); }
+                    }
+                    // The length is computed by the encoder from the slice
+                    EncodeFunc::Encode(encoder) if typ.is_bulk_fixed() => {
+                        quote! {
+                            #encoder.encode_varint32(#tag_val)?;
+                            #encoder.encode_packed_fixed(& #extra_deref self.#fname)?;
+                        }
                     }
                     EncodeFunc::Encode(encoder) => {
                         let encode_expr = typ.generate_encode_expr(gen, encoder, &val_ref);
                         quote! {
+                            let len = #len;
                             #encoder.encode_varint32(#tag_val)?;
                             #encoder.encode_packed(len, & #extra_deref self.#fname, |#encoder, val| {let #val_ref = &val; #encode_expr})?;
                         }
@@ -450,17 +931,44 @@ impl<'a> Field<'a> {
                 };
                 quote! {
                     if !self.#fname.is_empty() {
-                        let len = #len;
                         #stmts
                     }
                 }
             }
 
             FieldType::Custom(CustomField::Type(_)) => match &func_type {
-                EncodeFunc::Sizeof(size) => quote! { #size += self.#fname.compute_fields_size(); },
+                EncodeFunc::Sizeof(size) => {
+                    quote! { #size = #size.saturating_add(self.#fname.compute_fields_size()); }
+                }
                 EncodeFunc::Encode(encoder) => quote! { self.#fname.encode_fields(#encoder)?; },
             },
 
+            // Visited fields are always encoded unpacked, since packed encoding would require
+            // iterating over the elements twice
+            FieldType::Visit { typ, .. } => {
+                let for_each = format_ident!("for_each_{}", self.rust_name);
+                match &func_type {
+                    EncodeFunc::Sizeof(size) => {
+                        let sizeof_expr = typ.generate_sizeof(gen, &val_ref);
+                        quote! {
+                            let _ = self._visitor.#for_each(|#val_ref| {
+                                #size = #size.saturating_add(#tag_len).saturating_add(#sizeof_expr);
+                                Ok::<(), ::core::convert::Infallible>(())
+                            });
+                        }
+                    }
+                    EncodeFunc::Encode(encoder) => {
+                        let encode_expr = typ.generate_encode_expr(gen, encoder, &val_ref);
+                        quote! {
+                            self._visitor.#for_each(|#val_ref| {
+                                #encoder.encode_varint32(#tag_val)?;
+                                #encode_expr
+                            })?;
+                        }
+                    }
+                }
+            }
+
             FieldType::Custom(CustomField::Delegate(_)) => quote! {},
         };
 
@@ -468,32 +976,1045 @@ impl<'a> Field<'a> {
             #sizeof_code
         }}
     }
-}
 
-#[cfg(test)]
-pub(crate) fn make_test_field(num: u32, name: &str, boxed: bool, ftype: FieldType) -> Field {
-    Field {
-        num,
-        ftype,
-        name,
-        rust_name: name.to_owned(),
-        san_rust_name: Ident::new_raw(name, proc_macro2::Span::call_site()),
-        default: None,
-        boxed,
-        attrs: vec![],
-    }
-}
+    /// Generate code that encodes the field back-to-front, writing each value before its tag
+    pub(crate) fn generate_encode_reverse(&self, gen: &Generator, encoder: &Ident) -> TokenStream {
+        let fname = &self.san_rust_name;
+        let val_ref = Ident::new("val_ref", Span::call_site());
+        let extra_deref = self.boxed.then(|| quote! { * });
+        let tag_val = micropb::Tag::from_parts(self.num, self.wire_type()).varint();
 
-#[cfg(test)]
-mod tests {
-    use std::borrow::Cow;
+        let code = match &self.ftype {
+            FieldType::Map { key, val, .. } => {
+                let key_encode = key.generate_encode_reverse_expr(gen, encoder, &val_ref);
+                let key_tag = micropb::Tag::from_parts(1, key.wire_type()).varint();
+                let val_encode = val.generate_encode_reverse_expr(gen, encoder, &val_ref);
+                let val_tag = micropb::Tag::from_parts(2, val.wire_type()).varint();
+                // Order of map entries doesn't matter, so they're encoded in iteration order, unless
+                // they're sorted, in which case they're iterated in reverse to end up in order
+                let iter = self.generate_map_iter();
+                let iter = if let FieldType::Map { sorted: true, .. } = self.ftype {
+                    quote! { #iter.rev() }
+                } else {
+                    iter
+                };
+                quote! {
+                    for (k, v) in #iter {
+                        #encoder.encode_len_delimited(|#encoder| {
+                            let #val_ref = v;
+                            #val_encode?;
+                            #encoder.encode_varint32(#val_tag)?;
+                            let #val_ref = k;
+                            #key_encode?;
+                            #encoder.encode_varint32(#key_tag)
+                        })?;
+                        #encoder.encode_varint32(#tag_val)?;
+                    }
+                }
+            }
 
-    use proc_macro2::Span;
+            FieldType::Single(tspec) | FieldType::Optional(tspec, _) => {
+                let check = if let FieldType::Optional(..) = self.ftype {
+                    quote! { if let Some(#val_ref) = self.#fname() }
+                } else {
+                    let implicit_presence_check = (!self.emit_defaults)
+                        .then(|| tspec.generate_implicit_presence_check(&val_ref));
+                    quote! {
+                        let #val_ref = &#extra_deref self.#fname;
+                        #implicit_presence_check
+                    }
+                };
+                let encode_expr = tspec.generate_encode_reverse_expr(gen, encoder, &val_ref);
+                quote! {
+                    #check {
+                        #encode_expr?;
+                        #encoder.encode_varint32(#tag_val)?;
+                    }
+                }
+            }
 
-    use crate::{
-        config::{parse_attributes, Config, IntSize},
-        generator::type_spec::PbInt,
-        pathtree::Node,
+            // Elements are encoded in reverse so that they end up in order on the wire
+            FieldType::Repeated {
+                typ, packed: false, ..
+            } => {
+                let encode_expr = typ.generate_encode_reverse_expr(gen, encoder, &val_ref);
+                quote! {
+                    for #val_ref in self.#fname.iter().rev() {
+                        #encode_expr?;
+                        #encoder.encode_varint32(#tag_val)?;
+                    }
+                }
+            }
+
+            FieldType::Repeated {
+                typ, packed: true, ..
+            } if typ.is_bulk_fixed() => {
+                quote! {
+                    if !self.#fname.is_empty() {
+                        #encoder.encode_packed_fixed(& #extra_deref self.#fname)?;
+                        #encoder.encode_varint32(#tag_val)?;
+                    }
+                }
+            }
+
+            FieldType::Repeated {
+                typ, packed: true, ..
+            } => {
+                let encode_expr = typ.generate_encode_reverse_expr(gen, encoder, &val_ref);
+                quote! {
+                    if !self.#fname.is_empty() {
+                        #encoder.encode_len_delimited(|#encoder| {
+                            for #val_ref in (& #extra_deref self.#fname).iter().rev() {
+                                #encode_expr?;
+                            }
+                            Ok(())
+                        })?;
+                        #encoder.encode_varint32(#tag_val)?;
+                    }
+                }
+            }
+
+            FieldType::Custom(CustomField::Type(_)) => {
+                quote! { self.#fname.encode_fields_reverse(#encoder)?; }
+            }
+
+            FieldType::Visit { .. } => {
+                unreachable!("messages with visited fields use the default reverse encoding")
+            }
+
+            FieldType::Custom(CustomField::Delegate(_)) => quote! {},
+        };
+
+        quote! {{
+            #code
+        }}
+    }
+
+    /// Generate code that writes the field into the JSON object `obj`. Returns `None` if the
+    /// field can't be written as JSON.
+    pub(crate) fn generate_json(&self, obj: &Ident) -> Option<TokenStream> {
+        let fname = &self.san_rust_name;
+        let json_name = &self.json_name;
+        let val_ref = Ident::new("val_ref", Span::call_site());
+        let writer = Ident::new("w", Span::call_site());
+        let extra_deref = self.boxed.then(|| quote! { * });
+
+        let code = match &self.ftype {
+            FieldType::Single(tspec) | FieldType::Optional(tspec, _) => {
+                let json_expr = tspec.generate_json_expr(&writer, &val_ref)?;
+                // Unlike the binary encoding, default values are always omitted in JSON
+                let check = if let FieldType::Optional(..) = self.ftype {
+                    quote! { if let Some(#val_ref) = self.#fname() }
+                } else {
+                    let implicit_presence_check = tspec.generate_implicit_presence_check(&val_ref);
+                    quote! {
+                        let #val_ref = &#extra_deref self.#fname;
+                        #implicit_presence_check
+                    }
+                };
+                quote! {
+                    #check {
+                        let #writer = #obj.key(#json_name)?;
+                        #json_expr?;
+                    }
+                }
+            }
+
+            FieldType::Repeated { typ, .. } => {
+                let json_expr = typ.generate_json_expr(&writer, &val_ref)?;
+                quote! {
+                    if !self.#fname.is_empty() {
+                        let mut arr = ::micropb::json::JsonArray::begin(#obj.key(#json_name)?)?;
+                        for #val_ref in self.#fname.iter() {
+                            let #writer = arr.elem()?;
+                            #json_expr?;
+                        }
+                        arr.end()?;
+                    }
+                }
+            }
+
+            FieldType::Map { key, val, .. } => {
+                let entries = Ident::new("entries", Span::call_site());
+                let key_ref = Ident::new("k", Span::call_site());
+                let key_expr = key.generate_json_key_expr(&entries, &key_ref)?;
+                let json_expr = val.generate_json_expr(&writer, &val_ref)?;
+                quote! {
+                    use ::micropb::PbMap as _;
+                    let mut iter = self.#fname.pb_iter().peekable();
+                    if iter.peek().is_some() {
+                        let mut #entries = ::micropb::json::JsonObject::begin(#obj.key(#json_name)?)?;
+                        for (#key_ref, #val_ref) in iter {
+                            let #writer = #key_expr?;
+                            #json_expr?;
+                        }
+                        #entries.end()?;
+                    }
+                }
+            }
+
+            FieldType::Visit { .. } | FieldType::Custom(_) => return None,
+        };
+
+        Some(quote! {{
+            #code
+        }})
+    }
+
+    /// Generate the match arm that parses the field from the value of a JSON object entry.
+    /// Returns `None` if the field can't be parsed from JSON.
+    pub(crate) fn generate_json_parse_arm(
+        &self,
+        gen: &Generator,
+        parser: &Ident,
+    ) -> Option<TokenStream> {
+        let fname = &self.san_rust_name;
+        let mut_ref = Ident::new("mut_ref", Span::call_site());
+        let extra_deref = self.boxed.then(|| quote! { * });
+
+        let (parse, null) = match &self.ftype {
+            FieldType::Single(tspec) => {
+                let parse_stmts = tspec.generate_json_parse_mut(parser, &mut_ref)?;
+                let parse = quote! {
+                    let #mut_ref = &mut #extra_deref self.#fname;
+                    #parse_stmts
+                };
+                (
+                    parse,
+                    quote! { self.#fname = ::core::default::Default::default(); },
+                )
+            }
+
+            FieldType::Optional(tspec, OptionalRepr::Hazzer) => {
+                let parse_stmts = tspec.generate_json_parse_mut(parser, &mut_ref)?;
+                let setter = format_ident!("set_{}", self.rust_name);
+                let clearer = format_ident!("clear_{}", self.rust_name);
+                let parse = quote! {
+                    let #mut_ref = &mut #extra_deref self.#fname;
+                    #parse_stmts
+                    self._has.#setter();
+                };
+                (parse, quote! { self._has.#clearer(); })
+            }
+
+            FieldType::Optional(tspec, OptionalRepr::Option) => {
+                let parse_stmts = tspec.generate_json_parse_mut(parser, &mut_ref)?;
+                let parse = quote! {
+                    let #mut_ref = &mut #extra_deref *self.#fname.get_or_insert_with(::core::default::Default::default);
+                    #parse_stmts
+                };
+                (
+                    parse,
+                    quote! { self.#fname = ::core::option::Option::None; },
+                )
+            }
+
+            FieldType::Repeated { typ, .. } => {
+                let parse_stmts = typ.generate_json_parse_mut(parser, &mut_ref)?;
+                let rust_type = typ.generate_rust_type(gen);
+                let parse = quote! {
+                    #parser.parse_array(|#parser| {
+                        let mut val: #rust_type = ::core::default::Default::default();
+                        let #mut_ref = &mut val;
+                        #parse_stmts
+                        self.#fname.pb_push(val).map_err(|_| #parser.capacity_error())
+                    })?;
+                };
+                (parse, quote! { self.#fname.pb_clear(); })
+            }
+
+            FieldType::Map { key, val, .. } => {
+                let key_ident = Ident::new("key", Span::call_site());
+                let key_parse = key.generate_json_key_parse(parser, &key_ident)?;
+                let parse_stmts = val.generate_json_parse_mut(parser, &mut_ref)?;
+                let key_type = key.generate_rust_type(gen);
+                let val_type = val.generate_rust_type(gen);
+                let parse = quote! {
+                    #parser.parse_object(|#parser, #key_ident| {
+                        let k: #key_type = #key_parse;
+                        let mut val: #val_type = ::core::default::Default::default();
+                        let #mut_ref = &mut val;
+                        #parse_stmts
+                        self.#fname.pb_insert(k, val).map_err(|_| #parser.capacity_error())
+                    })?;
+                };
+                (parse, quote! { self.#fname.pb_clear(); })
+            }
+
+            FieldType::Visit { .. } | FieldType::Custom(_) => return None,
+        };
+
+        let pattern = json_key_pattern(self.name, &self.json_name);
+        Some(quote! {
+            #pattern => {
+                if #parser.parse_null()? {
+                    #null
+                } else {
+                    #parse
+                }
+            }
+        })
+    }
+
+    /// Generate the match arm that parses the field from its value in text format. Returns `None`
+    /// if the field can't be parsed from text format.
+    pub(crate) fn generate_text_parse_arm(
+        &self,
+        gen: &Generator,
+        parser: &Ident,
+    ) -> Option<TokenStream> {
+        let fname = &self.san_rust_name;
+        let mut_ref = Ident::new("mut_ref", Span::call_site());
+        let extra_deref = self.boxed.then(|| quote! { * });
+        let ok = quote! { ::core::result::Result::Ok(()) };
+
+        let parse = match &self.ftype {
+            FieldType::Single(tspec) | FieldType::Optional(tspec, _) => {
+                let (parse_stmts, message) = tspec.generate_text_parse_mut(parser, &mut_ref)?;
+                let method = text_parse_method(message, false);
+                let (field_ref, setter) = match &self.ftype {
+                    FieldType::Optional(_, OptionalRepr::Option) => (
+                        quote! { *self.#fname.get_or_insert_with(::core::default::Default::default) },
+                        None,
+                    ),
+                    FieldType::Optional(_, OptionalRepr::Hazzer) => {
+                        let setter = format_ident!("set_{}", self.rust_name);
+                        (
+                            quote! { self.#fname },
+                            Some(quote! { self._has.#setter(); }),
+                        )
+                    }
+                    _ => (quote! { self.#fname }, None),
+                };
+                quote! {
+                    let #mut_ref = &mut #extra_deref #field_ref;
+                    #parser.#method(|#parser| {
+                        #parse_stmts
+                        #ok
+                    })?;
+                    #setter
+                }
+            }
+
+            FieldType::Repeated { typ, .. } => {
+                let (parse_stmts, message) = typ.generate_text_parse_mut(parser, &mut_ref)?;
+                let method = text_parse_method(message, true);
+                let rust_type = typ.generate_rust_type(gen);
+                quote! {
+                    #parser.#method(|#parser| {
+                        let mut val: #rust_type = ::core::default::Default::default();
+                        let #mut_ref = &mut val;
+                        #parse_stmts
+                        self.#fname.pb_push(val).map_err(|_| #parser.capacity_error())
+                    })?;
+                }
+            }
+
+            FieldType::Map { key, val, .. } => {
+                let (key_stmts, _) = key.generate_text_parse_mut(parser, &mut_ref)?;
+                let (val_stmts, message) = val.generate_text_parse_mut(parser, &mut_ref)?;
+                let val_method = text_parse_method(message, false);
+                let key_type = key.generate_rust_type(gen);
+                let val_type = val.generate_rust_type(gen);
+                // Each entry is written as a message with `key` and `value` fields
+                quote! {
+                    #parser.parse_repeated_message_field(|#parser| {
+                        let mut k: #key_type = ::core::default::Default::default();
+                        let mut val: #val_type = ::core::default::Default::default();
+                        #parser.parse_fields(|#parser, name| match name {
+                            "key" => #parser.parse_field(|#parser| {
+                                let #mut_ref = &mut k;
+                                #key_stmts
+                                #ok
+                            }),
+                            "value" => #parser.#val_method(|#parser| {
+                                let #mut_ref = &mut val;
+                                #val_stmts
+                                #ok
+                            }),
+                            _ => #parser.unknown_field(),
+                        })?;
+                        self.#fname.pb_insert(k, val).map_err(|_| #parser.capacity_error())
+                    })?;
+                }
+            }
+
+            FieldType::Visit { .. } | FieldType::Custom(_) => return None,
+        };
+
+        let name = self.name;
+        Some(quote! {
+            #name => {
+                #parse
+            }
+        })
+    }
+
+    /// Generate code that writes the field in text format into the text writer `t`. Returns
+    /// `None` if the field can't be written in text format.
+    pub(crate) fn generate_text(&self, t: &Ident) -> Option<TokenStream> {
+        let fname = &self.san_rust_name;
+        let name = self.name;
+        let val_ref = Ident::new("val_ref", Span::call_site());
+        let extra_deref = self.boxed.then(|| quote! { * });
+
+        let code = match &self.ftype {
+            FieldType::Single(tspec) | FieldType::Optional(tspec, _) => {
+                let text_expr = tspec.generate_text_expr(t, name, &val_ref)?;
+                let check = if let FieldType::Optional(..) = self.ftype {
+                    quote! { if let Some(#val_ref) = self.#fname() }
+                } else {
+                    let implicit_presence_check = tspec.generate_implicit_presence_check(&val_ref);
+                    quote! {
+                        let #val_ref = &#extra_deref self.#fname;
+                        #implicit_presence_check
+                    }
+                };
+                quote! {
+                    #check {
+                        #text_expr?;
+                    }
+                }
+            }
+
+            FieldType::Repeated { typ, .. } => {
+                let text_expr = typ.generate_text_expr(t, name, &val_ref)?;
+                quote! {
+                    for #val_ref in self.#fname.iter() {
+                        #text_expr?;
+                    }
+                }
+            }
+
+            FieldType::Map { key, val, .. } => {
+                let entry = Ident::new("entry", Span::call_site());
+                let key_ref = Ident::new("k", Span::call_site());
+                let key_expr = key.generate_text_expr(&entry, "key", &key_ref)?;
+                let val_expr = val.generate_text_expr(&entry, "value", &val_ref)?;
+                quote! {
+                    use ::micropb::PbMap as _;
+                    for (#key_ref, #val_ref) in self.#fname.pb_iter() {
+                        #t.message(#name, |#entry| {
+                            #key_expr?;
+                            #val_expr
+                        })?;
+                    }
+                }
+            }
+
+            FieldType::Visit { .. } | FieldType::Custom(_) => return None,
+        };
+
+        Some(quote! {{
+            #code
+        }})
+    }
+
+    /// Generate the statements that copy the field from `src` for `ApplyFieldMask`, along with
+    /// its presence. Returns `None` if the field can't be named by a field mask.
+    pub(crate) fn generate_field_mask_copy(&self) -> Option<TokenStream> {
+        let fname = &self.san_rust_name;
+        let copy = quote! { self.#fname = ::core::clone::Clone::clone(&src.#fname); };
+        match &self.ftype {
+            FieldType::Visit { .. } | FieldType::Custom(_) => None,
+            _ if self.ftype.is_bytes_range() => None,
+            FieldType::Optional(_, OptionalRepr::Hazzer) => {
+                let setter = format_ident!("set_{}", self.rust_name);
+                let clearer = format_ident!("clear_{}", self.rust_name);
+                Some(quote! {
+                    #copy
+                    if src._has.#fname() {
+                        self._has.#setter();
+                    } else {
+                        self._has.#clearer();
+                    }
+                })
+            }
+            _ => Some(copy),
+        }
+    }
+
+    /// Generate the expression that checks the rest of a nested field mask path and the
+    /// statements that apply it, for message fields. The rest of the path is in `rest`.
+    ///
+    /// If the field isn't set in `src`, the named fields are copied from a default message.
+    pub(crate) fn generate_field_mask_nested(
+        &self,
+        gen: &Generator,
+        rest: &Ident,
+    ) -> Option<(TokenStream, TokenStream)> {
+        let fname = &self.san_rust_name;
+        let extra_deref = self.boxed.then(|| quote! { * });
+        let (FieldType::Single(tspec @ TypeSpec::Message(_))
+        | FieldType::Optional(tspec @ TypeSpec::Message(_), _)) = &self.ftype
+        else {
+            return None;
+        };
+        let typ = tspec.generate_rust_type(gen);
+        let check = quote! {
+            <#typ as ::micropb::field_mask::ApplyFieldMask>::check_path(#rest)
+        };
+        let apply_to = |dst: TokenStream, src: TokenStream| {
+            quote! {
+                ::micropb::field_mask::ApplyFieldMask::apply_path(&mut #extra_deref *#dst, #src, #rest);
+            }
+        };
+
+        let apply = match &self.ftype {
+            FieldType::Optional(_, OptionalRepr::Option) => {
+                let apply_set = apply_to(quote! { dst }, quote! { &#extra_deref *src });
+                let apply_unset = apply_to(quote! { dst }, quote! { &default });
+                quote! {
+                    let dst = self.#fname.get_or_insert_with(::core::default::Default::default);
+                    if let ::core::option::Option::Some(src) = &src.#fname {
+                        #apply_set
+                    } else {
+                        let default: #typ = ::core::default::Default::default();
+                        #apply_unset
+                    }
+                }
+            }
+            FieldType::Optional(_, OptionalRepr::Hazzer) => {
+                let setter = format_ident!("set_{}", self.rust_name);
+                let apply_set = apply_to(
+                    quote! { &mut self.#fname },
+                    quote! { &#extra_deref src.#fname },
+                );
+                let apply_unset = apply_to(quote! { &mut self.#fname }, quote! { &default });
+                quote! {
+                    if !self._has.#fname() {
+                        self.#fname = ::core::default::Default::default();
+                        self._has.#setter();
+                    }
+                    if src._has.#fname() {
+                        #apply_set
+                    } else {
+                        let default: #typ = ::core::default::Default::default();
+                        #apply_unset
+                    }
+                }
+            }
+            _ => apply_to(
+                quote! { &mut self.#fname },
+                quote! { &#extra_deref src.#fname },
+            ),
+        };
+        Some((check, apply))
+    }
+
+    /// Generate statements that set the field of `msg` to an arbitrary value from the
+    /// `arbitrary::Unstructured` named `u`. Returns `None` for fields that aren't generated.
+    ///
+    /// Elements of repeated and `map` fields are added until the input says to stop or the
+    /// container is full.
+    pub(crate) fn generate_arbitrary(&self, gen: &Generator, depth: &Ident) -> Option<TokenStream> {
+        let fname = &self.san_rust_name;
+        let extra_deref = self.boxed.then(|| quote! { * });
+        let wrap = |val: TokenStream| gen.wrapped_value(val, self.boxed, false);
+        let stmt = match &self.ftype {
+            FieldType::Single(tspec) => {
+                let val = wrap(tspec.generate_arbitrary(gen, depth));
+                quote! { msg.#fname = #val; }
+            }
+            FieldType::Optional(tspec, OptionalRepr::Option) => {
+                let val = gen.wrapped_value(tspec.generate_arbitrary(gen, depth), self.boxed, true);
+                quote! {
+                    msg.#fname = if u.arbitrary()? {
+                        #val
+                    } else {
+                        ::core::option::Option::None
+                    };
+                }
+            }
+            // The presence bit is chosen separately from the value
+            FieldType::Optional(tspec, OptionalRepr::Hazzer) => {
+                let val = wrap(tspec.generate_arbitrary(gen, depth));
+                let setter = format_ident!("set_{}", self.rust_name);
+                quote! {
+                    msg.#fname = #val;
+                    if u.arbitrary()? {
+                        msg._has.#setter();
+                    }
+                }
+            }
+            FieldType::Repeated { typ, .. } => {
+                let elem = typ.generate_arbitrary(gen, depth);
+                quote! {
+                    while u.arbitrary()? {
+                        if ::micropb::PbVec::pb_push(&mut #extra_deref msg.#fname, #elem).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+            FieldType::Map { key, val, .. } => {
+                let key = key.generate_arbitrary(gen, depth);
+                let val = val.generate_arbitrary(gen, depth);
+                quote! {
+                    while u.arbitrary()? {
+                        let key = #key;
+                        let val = #val;
+                        if ::micropb::PbMap::pb_insert(&mut #extra_deref msg.#fname, key, val).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+            FieldType::Custom(CustomField::Type(_)) => quote! { msg.#fname = u.arbitrary()?; },
+            FieldType::Custom(CustomField::Delegate(_)) | FieldType::Visit { .. } => return None,
+        };
+        Some(stmt)
+    }
+
+    /// Generate the `proptest` strategy for the field, along with the statements that assign the
+    /// value generated by the strategy, bound to `val`, to the field of `msg`. Returns `None` for
+    /// fields that aren't generated.
+    pub(crate) fn generate_strategy(
+        &self,
+        gen: &Generator,
+        depth: &Ident,
+        val: &Ident,
+    ) -> Option<(TokenStream, TokenStream)> {
+        let fname = &self.san_rust_name;
+        let extra_deref = self.boxed.then(|| quote! { * });
+        let max_len = |max_len: &Option<u32>| {
+            Literal::usize_suffixed(max_len.unwrap_or(PROPTEST_MAX_LEN) as usize)
+        };
+        let res = match &self.ftype {
+            FieldType::Single(tspec) => {
+                let assign = gen.wrapped_value(quote! { #val }, self.boxed, false);
+                (
+                    tspec.generate_strategy(gen, depth),
+                    quote! { msg.#fname = #assign; },
+                )
+            }
+            FieldType::Optional(tspec, OptionalRepr::Option) => {
+                let strategy = tspec.generate_strategy(gen, depth);
+                let alloc = gen.alloc_crate();
+                let boxed = self.boxed.then(|| quote! { .map(#alloc::boxed::Box::new) });
+                (
+                    quote! { ::proptest::option::of(#strategy) },
+                    quote! { msg.#fname = #val #boxed; },
+                )
+            }
+            // The presence bit is generated separately from the value
+            FieldType::Optional(tspec, OptionalRepr::Hazzer) => {
+                let strategy = tspec.generate_strategy(gen, depth);
+                let assign = gen.wrapped_value(quote! { #val.0 }, self.boxed, false);
+                let setter = format_ident!("set_{}", self.rust_name);
+                (
+                    quote! { (#strategy, ::proptest::prelude::any::<bool>()) },
+                    quote! {
+                        msg.#fname = #assign;
+                        if #val.1 {
+                            msg._has.#setter();
+                        }
+                    },
+                )
+            }
+            FieldType::Repeated {
+                typ, max_len: len, ..
+            } => {
+                let elem = typ.generate_strategy(gen, depth);
+                let len = max_len(len);
+                (
+                    quote! { ::proptest::collection::vec(#elem, 0..=#len) },
+                    quote! {
+                        for elem in #val {
+                            if ::micropb::PbVec::pb_push(&mut #extra_deref msg.#fname, elem).is_err() {
+                                break;
+                            }
+                        }
+                    },
+                )
+            }
+            FieldType::Map {
+                key,
+                val: value,
+                max_len: len,
+                ..
+            } => {
+                let key = key.generate_strategy(gen, depth);
+                let value = value.generate_strategy(gen, depth);
+                let len = max_len(len);
+                (
+                    quote! { ::proptest::collection::vec((#key, #value), 0..=#len) },
+                    quote! {
+                        for (key, val) in #val {
+                            if ::micropb::PbMap::pb_insert(&mut #extra_deref msg.#fname, key, val).is_err() {
+                                break;
+                            }
+                        }
+                    },
+                )
+            }
+            FieldType::Custom(CustomField::Type(typ)) => (
+                quote! { ::proptest::prelude::any::<#typ>() },
+                quote! { msg.#fname = #val; },
+            ),
+            FieldType::Custom(CustomField::Delegate(_)) | FieldType::Visit { .. } => return None,
+        };
+        Some(res)
+    }
+
+    /// Generate the statements that set the field of `msg` for round-trip tests. Optional fields
+    /// are always set, and containers hold a single element, or as many as they fit if `fill` is
+    /// true. Returns `None` for fields that are left at their defaults.
+    pub(crate) fn generate_sample(
+        &self,
+        gen: &Generator,
+        fill: &Ident,
+        depth: &Ident,
+    ) -> Option<TokenStream> {
+        let fname = &self.san_rust_name;
+        let extra_deref = self.boxed.then(|| quote! { * });
+        let count = |max_len: &Option<u32>| {
+            let len = Literal::usize_suffixed(max_len.unwrap_or(ROUNDTRIP_FILL_LEN) as usize);
+            quote! { if #fill { #len } else { 1 } }
+        };
+        let stmt = match &self.ftype {
+            FieldType::Single(tspec) => {
+                let val =
+                    gen.wrapped_value(tspec.generate_sample(gen, fill, depth), self.boxed, false);
+                quote! { msg.#fname = #val; }
+            }
+            FieldType::Optional(tspec, OptionalRepr::Option) => {
+                let val =
+                    gen.wrapped_value(tspec.generate_sample(gen, fill, depth), self.boxed, true);
+                quote! { msg.#fname = #val; }
+            }
+            FieldType::Optional(tspec, OptionalRepr::Hazzer) => {
+                let val =
+                    gen.wrapped_value(tspec.generate_sample(gen, fill, depth), self.boxed, false);
+                let setter = format_ident!("set_{}", self.rust_name);
+                quote! {
+                    msg.#fname = #val;
+                    msg._has.#setter();
+                }
+            }
+            FieldType::Repeated { typ, max_len, .. } => {
+                let elem = typ.generate_sample(gen, fill, depth);
+                let count = count(max_len);
+                quote! {
+                    for _ in 0..#count {
+                        if ::micropb::PbVec::pb_push(&mut #extra_deref msg.#fname, #elem).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+            FieldType::Map {
+                key, val, max_len, ..
+            } => {
+                let i = Ident::new("i", Span::call_site());
+                let key = key.generate_sample_key(gen, fill, depth, &i);
+                let val = val.generate_sample(gen, fill, depth);
+                let count = count(max_len);
+                quote! {
+                    for #i in 0..#count {
+                        let key = #key;
+                        let val = #val;
+                        if ::micropb::PbMap::pb_insert(&mut #extra_deref msg.#fname, key, val).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+            FieldType::Custom(_) | FieldType::Visit { .. } => return None,
+        };
+        Some(stmt)
+    }
+
+    /// Generate the initializer of the domain type's field in the conversion from the message
+    /// `value`. `err` maps conversion errors to the error type of the conversion. Returns `None`
+    /// for fields that aren't converted.
+    pub(crate) fn generate_from_domain_init(&self, err: &TokenStream) -> Option<TokenStream> {
+        if self.domain_skip {
+            return None;
+        }
+        let fname = &self.san_rust_name;
+        let deref = self.boxed.then(|| quote! { * });
+        let val = match &self.ftype {
+            FieldType::Single(_) | FieldType::Custom(CustomField::Type(_)) => quote! {
+                ::core::convert::TryInto::try_into(#deref value.#fname).map_err(#err)?
+            },
+            FieldType::Optional(_, OptionalRepr::Option) => quote! {
+                value.#fname
+                    .map(|v| ::core::convert::TryInto::try_into(#deref v))
+                    .transpose()
+                    .map_err(#err)?
+            },
+            FieldType::Optional(_, OptionalRepr::Hazzer) => quote! {
+                if value._has.#fname() {
+                    ::core::option::Option::Some(
+                        ::core::convert::TryInto::try_into(#deref value.#fname).map_err(#err)?
+                    )
+                } else {
+                    ::core::option::Option::None
+                }
+            },
+            FieldType::Repeated { .. } => quote! {
+                ::core::iter::IntoIterator::into_iter(#deref value.#fname)
+                    .map(::core::convert::TryInto::try_into)
+                    .collect::<::core::result::Result<_, _>>()
+                    .map_err(#err)?
+            },
+            FieldType::Map { .. } => quote! {
+                ::core::iter::IntoIterator::into_iter(#deref value.#fname)
+                    .map(|(k, v)| {
+                        ::core::result::Result::Ok((
+                            ::core::convert::TryInto::try_into(k).map_err(|_| ())?,
+                            ::core::convert::TryInto::try_into(v).map_err(|_| ())?,
+                        ))
+                    })
+                    .collect::<::core::result::Result<_, ()>>()
+                    .map_err(#err)?
+            },
+            FieldType::Custom(CustomField::Delegate(_)) | FieldType::Visit { .. } => return None,
+        };
+        Some(quote! { #fname: #val, })
+    }
+
+    /// Generate the statements that set the field of `msg` in the conversion from the domain
+    /// type `value`. `err` maps conversion errors to the error type of the conversion. Returns
+    /// `None` for fields that aren't converted.
+    pub(crate) fn generate_into_domain_stmt(
+        &self,
+        gen: &Generator,
+        err: &TokenStream,
+    ) -> Option<TokenStream> {
+        if self.domain_skip {
+            return None;
+        }
+        let fname = &self.san_rust_name;
+        let deref = self.boxed.then(|| quote! { * });
+        let try_into = |v: TokenStream| {
+            quote! { ::core::convert::TryInto::try_into(#v).map_err(#err)? }
+        };
+        let stmt = match &self.ftype {
+            FieldType::Single(_) => {
+                let val = gen.wrapped_value(try_into(quote! { value.#fname }), self.boxed, false);
+                quote! { msg.#fname = #val; }
+            }
+            FieldType::Custom(CustomField::Type(_)) => {
+                let val = try_into(quote! { value.#fname });
+                quote! { msg.#fname = #val; }
+            }
+            FieldType::Optional(_, OptionalRepr::Option) => {
+                let val = gen.wrapped_value(try_into(quote! { v }), self.boxed, true);
+                quote! {
+                    if let ::core::option::Option::Some(v) = value.#fname {
+                        msg.#fname = #val;
+                    }
+                }
+            }
+            FieldType::Optional(_, OptionalRepr::Hazzer) => {
+                let val = gen.wrapped_value(try_into(quote! { v }), self.boxed, false);
+                let setter = format_ident!("set_{}", self.rust_name);
+                quote! {
+                    if let ::core::option::Option::Some(v) = value.#fname {
+                        msg.#fname = #val;
+                        msg._has.#setter();
+                    }
+                }
+            }
+            // Elements that don't fit in the container are errors as well
+            FieldType::Repeated { .. } => {
+                let elem = try_into(quote! { elem });
+                quote! {
+                    for elem in value.#fname {
+                        ::micropb::PbVec::pb_push(&mut #deref msg.#fname, #elem).map_err(#err)?;
+                    }
+                }
+            }
+            FieldType::Map { .. } => {
+                let key = try_into(quote! { k });
+                let val = try_into(quote! { v });
+                quote! {
+                    for (k, v) in value.#fname {
+                        ::micropb::PbMap::pb_insert(&mut #deref msg.#fname, #key, #val).map_err(#err)?;
+                    }
+                }
+            }
+            FieldType::Custom(CustomField::Delegate(_)) | FieldType::Visit { .. } => return None,
+        };
+        Some(stmt)
+    }
+
+    /// Generate the statement that sets the field of the `prost` message `msg` from the message
+    /// `value`
+    pub(crate) fn generate_to_prost_stmt(&self, gen: &Generator) -> TokenStream {
+        let fname = &self.san_rust_name;
+        let pname = prost_ident(self.name, Case::Snake);
+        let deref = self.boxed.then(|| quote! { * });
+        let val = match &self.ftype {
+            FieldType::Single(typ) => {
+                let val = typ.generate_to_prost(gen, quote! { (#deref value.#fname) });
+                gen.wrapped_value(val, self.boxed, false)
+            }
+            FieldType::Optional(typ, repr) => {
+                let elem = gen.wrapped_value(
+                    typ.generate_to_prost(gen, quote! { (#deref v) }),
+                    self.boxed,
+                    false,
+                );
+                let opt = match repr {
+                    OptionalRepr::Option => quote! { value.#fname.map(|v| #elem) },
+                    OptionalRepr::Hazzer => quote! {
+                        if value._has.#fname() {
+                            let v = value.#fname;
+                            ::core::option::Option::Some(#elem)
+                        } else {
+                            ::core::option::Option::None
+                        }
+                    },
+                };
+                // Required fields aren't optional in prost
+                if self.required {
+                    quote! { (#opt).unwrap_or_default() }
+                } else {
+                    opt
+                }
+            }
+            FieldType::Repeated { typ, .. } => {
+                let elem = typ.generate_to_prost(gen, quote! { v });
+                quote! {
+                    ::core::iter::IntoIterator::into_iter(#deref value.#fname)
+                        .map(|v| #elem)
+                        .collect()
+                }
+            }
+            FieldType::Map { key, val, .. } => {
+                let key = key.generate_to_prost(gen, quote! { k });
+                let val = val.generate_to_prost(gen, quote! { v });
+                quote! {
+                    ::core::iter::IntoIterator::into_iter(#deref value.#fname)
+                        .map(|(k, v)| (#key, #val))
+                        .collect()
+                }
+            }
+            FieldType::Custom(_) | FieldType::Visit { .. } => {
+                unreachable!("unsupported fields are rejected when parsing the message")
+            }
+        };
+        quote! { msg.#pname = #val; }
+    }
+
+    /// Generate the statements that set the field of `msg` from the `prost` message `value`
+    pub(crate) fn generate_from_prost_stmt(&self, gen: &Generator) -> TokenStream {
+        let fname = &self.san_rust_name;
+        let pname = prost_ident(self.name, Case::Snake);
+        let deref = self.boxed.then(|| quote! { * });
+        match &self.ftype {
+            FieldType::Single(typ) => {
+                let val = gen.wrapped_value(
+                    typ.generate_from_prost(gen, quote! { (#deref value.#pname) }),
+                    self.boxed,
+                    false,
+                );
+                quote! { msg.#fname = #val; }
+            }
+            FieldType::Optional(typ, repr) => {
+                let val = typ.generate_from_prost(gen, quote! { (#deref v) });
+                let set = match repr {
+                    OptionalRepr::Option => {
+                        let val = gen.wrapped_value(val, self.boxed, true);
+                        quote! { msg.#fname = #val; }
+                    }
+                    OptionalRepr::Hazzer => {
+                        let val = gen.wrapped_value(val, self.boxed, false);
+                        let setter = format_ident!("set_{}", self.rust_name);
+                        quote! {
+                            msg.#fname = #val;
+                            msg._has.#setter();
+                        }
+                    }
+                };
+                // Required fields aren't optional in prost
+                if self.required {
+                    quote! {{ let v = value.#pname; #set }}
+                } else {
+                    quote! {
+                        if let ::core::option::Option::Some(v) = value.#pname {
+                            #set
+                        }
+                    }
+                }
+            }
+            FieldType::Repeated { typ, .. } => {
+                let elem = typ.generate_from_prost(gen, quote! { v });
+                quote! {
+                    for v in value.#pname {
+                        ::micropb::PbVec::pb_push(&mut #deref msg.#fname, #elem)
+                            .map_err(|_| ::micropb::ConversionError::Capacity)?;
+                    }
+                }
+            }
+            FieldType::Map { key, val, .. } => {
+                let key = key.generate_from_prost(gen, quote! { k });
+                let val = val.generate_from_prost(gen, quote! { v });
+                quote! {
+                    for (k, v) in value.#pname {
+                        ::micropb::PbMap::pb_insert(&mut #deref msg.#fname, #key, #val)
+                            .map_err(|_| ::micropb::ConversionError::Capacity)?;
+                    }
+                }
+            }
+            FieldType::Custom(_) | FieldType::Visit { .. } => {
+                unreachable!("unsupported fields are rejected when parsing the message")
+            }
+        }
+    }
+}
+
+/// Method of `TextParser` that parses the value of a field with the given kind of values
+pub(crate) fn text_parse_method(message: bool, repeated: bool) -> Ident {
+    let method = match (message, repeated) {
+        (false, false) => "parse_field",
+        (false, true) => "parse_repeated_field",
+        (true, false) => "parse_message_field",
+        (true, true) => "parse_repeated_message_field",
+    };
+    Ident::new(method, Span::call_site())
+}
+
+/// Pattern that matches the original name and the JSON name of a field as JSON object keys
+pub(crate) fn json_key_pattern(name: &str, json_name: &str) -> TokenStream {
+    if name == json_name {
+        quote! { #name }
+    } else {
+        quote! { #name | #json_name }
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn make_test_field(num: u32, name: &str, boxed: bool, ftype: FieldType) -> Field<'_> {
+    Field {
+        num,
+        ftype,
+        name,
+        rust_name: name.to_owned(),
+        san_rust_name: Ident::new_raw(name, proc_macro2::Span::call_site()),
+        json_name: json_name(name),
+        default: None,
+        boxed,
+        emit_defaults: false,
+        domain_skip: false,
+        required: false,
+        attrs: vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use proc_macro2::Span;
+
+    use crate::{
+        config::{parse_attributes, Config, IntSize, OnOverflow},
+        generator::type_spec::PbInt,
+        pathtree::Node,
     };
 
     use super::*;
@@ -546,8 +2067,12 @@ mod tests {
                 name: "field",
                 rust_name: "field".to_owned(),
                 san_rust_name: Ident::new_raw("field", Span::call_site()),
+                json_name: "field".to_owned(),
                 default: None,
                 boxed: false,
+                emit_defaults: false,
+                domain_skip: false,
+                required: false,
                 attrs: vec![],
             }
         );
@@ -557,6 +2082,7 @@ mod tests {
             Config::new()
                 .boxed(true)
                 .rename_field("renamed")
+                .emit_defaults(true)
                 .field_attributes("#[attr]"),
         );
         let field_conf = CurrentConfig {
@@ -565,6 +2091,7 @@ mod tests {
         };
         let mut field = field_proto(2, "field", None, false);
         field.set_default_value("true".to_owned());
+        field.set_json_name("customName".to_owned());
         assert_eq!(
             Field::from_proto(&field, &field_conf, Syntax::Proto3, None)
                 .unwrap()
@@ -575,8 +2102,12 @@ mod tests {
                 name: "field",
                 rust_name: "renamed".to_owned(),
                 san_rust_name: Ident::new("renamed", Span::call_site()),
+                json_name: "customName".to_owned(),
                 default: Some("true"),
                 boxed: true,
+                emit_defaults: true,
+                domain_skip: false,
+                required: false,
                 attrs: parse_attributes("#[attr]").unwrap(),
             }
         );
@@ -709,32 +2240,57 @@ mod tests {
 
         let mut field = field_proto(0, "field", Some(Label::Repeated), false);
         field.set_type(Type::Int32);
-        assert_eq!(
-            Field::from_proto(&field, &field_conf, Syntax::Proto3, None)
-                .unwrap()
-                .unwrap()
-                .ftype,
-            FieldType::Repeated {
-                typ: TypeSpec::Int(PbInt::Int32, IntSize::S8),
-                packed: false,
-                type_path: syn::parse_str("Vec").unwrap(),
-                max_len: Some(21)
-            }
-        );
+        let repeated = |packed| FieldType::Repeated {
+            typ: TypeSpec::Int(PbInt::Int32, IntSize::S8),
+            packed,
+            type_path: syn::parse_str("Vec").unwrap(),
+            max_len: Some(21),
+            overflow_policy: OverflowPolicy::Error,
+        };
+        // Repeated scalars are packed by default only in proto3
+        for (syntax, packed) in [(Syntax::Proto2, false), (Syntax::Proto3, true)] {
+            assert_eq!(
+                Field::from_proto(&field, &field_conf, syntax, None)
+                    .unwrap()
+                    .unwrap()
+                    .ftype,
+                repeated(packed)
+            );
+        }
+
+        // Explicit option overrides the default of both syntaxes
         field.set_options(Default::default());
         field.options.set_packed(true);
-        assert_eq!(
+        for syntax in [Syntax::Proto2, Syntax::Proto3] {
+            assert_eq!(
+                Field::from_proto(&field, &field_conf, syntax, None)
+                    .unwrap()
+                    .unwrap()
+                    .ftype,
+                repeated(true)
+            );
+        }
+        field.options.set_packed(false);
+        for syntax in [Syntax::Proto2, Syntax::Proto3] {
+            assert_eq!(
+                Field::from_proto(&field, &field_conf, syntax, None)
+                    .unwrap()
+                    .unwrap()
+                    .ftype,
+                repeated(false)
+            );
+        }
+
+        // Length-delimited types can't be packed
+        let mut field = field_proto(0, "field", Some(Label::Repeated), false);
+        field.set_type(Type::Bytes);
+        assert!(matches!(
             Field::from_proto(&field, &field_conf, Syntax::Proto3, None)
                 .unwrap()
                 .unwrap()
                 .ftype,
-            FieldType::Repeated {
-                typ: TypeSpec::Int(PbInt::Int32, IntSize::S8),
-                packed: true,
-                type_path: syn::parse_str("Vec").unwrap(),
-                max_len: Some(21)
-            }
-        );
+            FieldType::Repeated { packed: false, .. }
+        ));
     }
 
     #[test]
@@ -784,11 +2340,65 @@ mod tests {
                 key: TypeSpec::Int(PbInt::Int32, IntSize::S8),
                 val: TypeSpec::String {
                     type_path: syn::parse_str("std::String").unwrap(),
-                    max_bytes: None
+                    max_bytes: None,
+                    on_overflow: OnOverflow::Error,
+                    as_bytes: false
                 },
                 type_path: syn::parse_str("std::Map").unwrap(),
-                max_len: None
+                max_len: None,
+                overflow_policy: OverflowPolicy::Error,
+                sorted: false
             }
         );
     }
+
+    #[test]
+    fn capacity_asserts() {
+        let gen = Generator::new();
+        let field = make_test_field(
+            1,
+            "list",
+            false,
+            FieldType::Repeated {
+                typ: TypeSpec::String {
+                    type_path: syn::parse_str("heapless::String").unwrap(),
+                    max_bytes: Some(3),
+                    on_overflow: OnOverflow::Error,
+                    as_bytes: false,
+                },
+                type_path: syn::parse_str("heapless::Vec").unwrap(),
+                max_len: Some(4),
+                overflow_policy: OverflowPolicy::Error,
+                packed: false,
+            },
+        );
+        assert_eq!(
+            field.generate_capacity_asserts(&gen, "pkg.Msg").to_string(),
+            quote! {
+                const _: () = ::core::assert!(
+                    <heapless::Vec<heapless::String<3>, 4> as ::micropb::PbVec<heapless::String<3> > >::CAPACITY >= 4,
+                    "max_len of `pkg.Msg.list` exceeds the capacity of its container"
+                );
+                const _: () = ::core::assert!(
+                    <heapless::String<3> as ::micropb::PbString>::CAPACITY >= 3,
+                    "max_bytes of `pkg.Msg.list` exceeds the capacity of its container"
+                );
+            }
+            .to_string()
+        );
+
+        let field = make_test_field(
+            1,
+            "list",
+            false,
+            FieldType::Repeated {
+                typ: TypeSpec::Bool,
+                type_path: syn::parse_str("Vec").unwrap(),
+                max_len: None,
+                overflow_policy: OverflowPolicy::Error,
+                packed: false,
+            },
+        );
+        assert!(field.generate_capacity_asserts(&gen, "pkg.Msg").is_empty());
+    }
 }
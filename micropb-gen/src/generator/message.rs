@@ -1,26 +1,30 @@
 use std::{collections::HashMap, io};
 
+use convert_case::{Case, Casing};
 use proc_macro2::{Literal, Span, TokenStream};
 use quote::{format_ident, quote};
-use syn::Ident;
+use syn::{ext::IdentExt, Ident};
 
 use crate::{
     config::OptionalRepr,
-    descriptor::DescriptorProto,
+    descriptor::{
+        DescriptorProto,
+        FieldDescriptorProto_::{Label, Type},
+    },
     generator::{
         field::{CustomField, FieldType},
         resolve_path_elem, EncodeFunc,
     },
+    Target,
 };
 
 use super::{
-    derive_msg_attr,
+    derive_defmt_attr, derive_msg_attr,
     field::Field,
-    field_error, msg_error,
     oneof::{Oneof, OneofField, OneofType},
-    sanitized_ident,
-    type_spec::find_lifetime_from_type,
-    CurrentConfig, Generator,
+    prost_ident, sanitized_ident,
+    type_spec::{find_lifetime_from_type, TypeSpec, ARBITRARY_DEPTH, ROUNDTRIP_DEPTH},
+    CurrentConfig, Generator, MaxSize,
 };
 
 #[cfg_attr(test, derive(Debug, PartialEq, Eq))]
@@ -37,7 +41,43 @@ pub(crate) struct Message<'a> {
     pub(crate) derive_clone: bool,
     pub(crate) attrs: Vec<syn::Attribute>,
     pub(crate) unknown_handler: Option<syn::Type>,
+    pub(crate) visitor: Option<syn::Type>,
     pub(crate) lifetime: Option<syn::Lifetime>,
+    /// Encode fields in order of field number
+    pub(crate) canonical_order: bool,
+    /// Implement the Serde traits
+    pub(crate) serde: bool,
+    /// Derive `defmt::Format` behind the `defmt` feature
+    pub(crate) defmt: bool,
+    /// Implement `MessageToJson` and `MessageFromJson`
+    pub(crate) json: bool,
+    /// Generate constants with the names of the fields
+    pub(crate) field_name_consts: bool,
+    /// Implement `MessageFields`, and whether to include field names in the table
+    pub(crate) field_descriptors: Option<bool>,
+    /// Implement `MessageToText` and `MessageFromText`
+    pub(crate) text: bool,
+    /// Implement `ApplyFieldMask`
+    pub(crate) field_mask: bool,
+    /// Implement `Arbitrary` when fuzzing
+    pub(crate) arbitrary: bool,
+    /// Generate `proptest` strategies
+    pub(crate) proptest: bool,
+    /// User type that the message is converted to and from
+    pub(crate) domain_type: Option<syn::Type>,
+    /// Fields of the domain type that aren't in the message, which are set to their defaults
+    pub(crate) domain_extra_fields: Vec<Ident>,
+    /// Module generated by `prost-build` for the message's package
+    pub(crate) prost_path: Option<syn::Path>,
+}
+
+const SIZE_INT_WARNING: &str = "64-bit integer is stored as usize or isize, so values that don't fit into 32 bits will fail to decode on 32-bit targets";
+
+/// Field or oneof variant that's encoded at a specific position when encoding in canonical order
+enum EncodeItem<'m, 'a> {
+    Field(&'m Field<'a>),
+    Variant(&'m Oneof<'a>, &'m OneofField<'a>),
+    CustomOneof(&'m Oneof<'a>),
 }
 
 impl<'a> Message<'a> {
@@ -54,7 +94,7 @@ impl<'a> Message<'a> {
         let mut oneofs = vec![];
         for (idx, oneof) in proto.oneof_decl.iter().enumerate() {
             let oneof = Oneof::from_proto(oneof, msg_conf.next_conf(&oneof.name), idx)
-                .map_err(|e| field_error(&gen.pkg, msg_name, &oneof.name, &e))?;
+                .map_err(|e| gen.field_error(msg_name, &oneof.name, &e))?;
             if let Some(oneof) = oneof {
                 oneofs.push(oneof);
             }
@@ -71,7 +111,36 @@ impl<'a> Message<'a> {
 
         let mut fields = vec![];
         for f in proto.field.iter() {
-            let field_conf = msg_conf.next_conf(&f.name);
+            let mut field_conf = msg_conf.next_conf(&f.name);
+            // Oneof variants can also be configured through their oneof, which takes precedence
+            if let Some(oneof) = f
+                .oneof_index()
+                .filter(|_| !f.proto3_optional)
+                .and_then(|idx| proto.oneof_decl.get(*idx as usize))
+            {
+                field_conf = field_conf.merge_path(msg_conf, &[&oneof.name, &f.name]);
+            }
+            if !field_conf.config.skip.unwrap_or(false)
+                && field_conf.config.custom_field.is_none()
+                && (f.label == Label::Repeated
+                    || matches!(f.r#type, Type::String | Type::Bytes)
+                    || field_conf.config.lazy.unwrap_or(false))
+            {
+                for bound in field_conf.config.ignored_bounds() {
+                    gen.warn_field(
+                        msg_name,
+                        &f.name,
+                        format_args!("{bound} is ignored, because the configured container types don't take a capacity"),
+                    );
+                }
+            }
+            if f.r#type == Type::Group && !field_conf.config.skip.unwrap_or(false) {
+                return Err(gen.element_error(
+                    &format!("{}.{}", gen.full_type_name(msg_name), f.name),
+                    "field uses proto2 groups, which micropb doesn't support",
+                    Some("consider converting the group to a nested message"),
+                ));
+            }
             let raw_msg_name = f
                 .type_name
                 .rsplit_once('.')
@@ -79,7 +148,7 @@ impl<'a> Message<'a> {
                 .unwrap_or(&f.type_name);
             let field = if let Some(map_msg) = map_types.remove(raw_msg_name) {
                 Field::from_proto(f, &field_conf, gen.syntax, Some(map_msg))
-                    .map_err(|e| field_error(&gen.pkg, msg_name, &f.name, &e))?
+                    .map_err(|e| gen.field_error(msg_name, &f.name, &e))?
             } else {
                 if let Some(idx) = f.oneof_index().copied() {
                     if f.proto3_optional {
@@ -92,16 +161,36 @@ impl<'a> Message<'a> {
                         {
                             Some(OneofType::Enum { fields, .. }) => {
                                 // Oneof field
-                                if let Some(field) = OneofField::from_proto(f, &field_conf)
-                                    .map_err(|e| field_error(&gen.pkg, msg_name, &f.name, &e))?
+                                if let Some(mut field) = OneofField::from_proto(f, &field_conf)
+                                    .map_err(|e| gen.field_error(msg_name, &f.name, &e))?
                                 {
+                                    // `google.protobuf.Value` contains itself through `Struct`
+                                    // and `ListValue`, so those variants are boxed by default
+                                    if field_conf.config.boxed.is_none()
+                                        && !matches!(field.tspec, TypeSpec::LazyMessage { .. })
+                                        && gen.full_type_name(msg_name) == "google.protobuf.Value"
+                                        && matches!(
+                                            f.type_name.as_str(),
+                                            ".google.protobuf.Struct"
+                                                | ".google.protobuf.ListValue"
+                                        )
+                                    {
+                                        field.boxed = true;
+                                    }
+                                    if field.tspec.is_64bit_as_size() {
+                                        gen.warn_field(
+                                            msg_name,
+                                            &f.name,
+                                            format_args!("{SIZE_INT_WARNING}"),
+                                        );
+                                    }
                                     fields.push(field);
                                 }
                             }
-                            Some(OneofType::Custom { nums, .. }) => {
-                                if !field_conf.config.skip.unwrap_or(false) {
-                                    nums.push(f.number);
-                                }
+                            Some(OneofType::Custom { nums, .. })
+                                if !field_conf.config.skip.unwrap_or(false) =>
+                            {
+                                nums.push(f.number);
                             }
                             _ => (),
                         }
@@ -110,13 +199,27 @@ impl<'a> Message<'a> {
                 }
                 // Normal field
                 Field::from_proto(f, &field_conf, gen.syntax, None)
-                    .map_err(|e| field_error(&gen.pkg, msg_name, &f.name, &e))?
+                    .map_err(|e| gen.field_error(msg_name, &f.name, &e))?
             };
             if let Some(field) = field {
+                if field.has_64bit_as_size() {
+                    gen.warn_field(msg_name, &f.name, format_args!("{SIZE_INT_WARNING}"));
+                }
                 fields.push(field);
             }
         }
 
+        // Range fields don't store their bytes, so they can't be encoded
+        if gen.encode_decode.is_encode() {
+            if let Some(f) = fields.iter().find(|f| f.ftype.is_bytes_range()) {
+                return Err(gen.field_error(
+                    msg_name,
+                    f.name,
+                    "bytes_range fields can't be encoded, so encode_decode must be DecodeOnly",
+                ));
+            }
+        }
+
         // Remove all oneofs that are empty enums or synthetic oneofs
         let oneofs: Vec<_> = oneofs
             .into_iter()
@@ -127,11 +230,95 @@ impl<'a> Message<'a> {
         let attrs = msg_conf
             .config
             .type_attr_parsed()
-            .map_err(|e| msg_error(&gen.pkg, msg_name, &e))?;
+            .map_err(|e| gen.msg_error(msg_name, &e))?;
         let unknown_handler = msg_conf
             .config
             .unknown_handler_parsed()
-            .map_err(|e| msg_error(&gen.pkg, msg_name, &e))?;
+            .map_err(|e| gen.msg_error(msg_name, &e))?;
+
+        // Only messages with visited fields need a visitor
+        let visitor = if fields
+            .iter()
+            .any(|f| matches!(f.ftype, FieldType::Visit { .. }))
+        {
+            let visitor = msg_conf
+                .config
+                .visitor_parsed()
+                .map_err(|e| gen.msg_error(msg_name, &e))?
+                .ok_or_else(|| {
+                    gen.msg_error(
+                        msg_name,
+                        "Message has visited fields, but visitor was not configured for it",
+                    )
+                })?;
+            Some(visitor)
+        } else {
+            None
+        };
+
+        let domain_type = msg_conf
+            .config
+            .domain_type_parsed()
+            .map_err(|e| gen.msg_error(msg_name, &e))?;
+        let domain_extra_fields = msg_conf
+            .config
+            .domain_extra_fields_parsed()
+            .map_err(|e| gen.msg_error(msg_name, &e))?;
+        let prost_path = msg_conf
+            .config
+            .prost_path_parsed()
+            .map_err(|e| gen.msg_error(msg_name, &e))?;
+        if gen.target == Some(Target::NoAlloc) {
+            let boxed = fields.iter().find(|f| f.boxed).map(|f| f.name).or_else(|| {
+                oneofs.iter().find_map(|o| match &o.otype {
+                    OneofType::Enum { fields, .. } if o.boxed || fields.iter().any(|f| f.boxed) => {
+                        Some(o.name)
+                    }
+                    _ => None,
+                })
+            });
+            if let Some(boxed) = boxed {
+                return Err(gen.field_error(msg_name,
+                    boxed,
+                    "Boxed fields require heap allocation, which isn't available with Target::NoAlloc",
+                ));
+            }
+        }
+        if prost_path.is_some() {
+            for f in &fields {
+                let unsupported = match &f.ftype {
+                    FieldType::Custom(_) => Some("custom fields"),
+                    FieldType::Visit { .. } => Some("visited fields"),
+                    FieldType::Single(TypeSpec::LazyMessage { .. })
+                    | FieldType::Optional(TypeSpec::LazyMessage { .. }, _) => Some("lazy messages"),
+                    _ if f.ftype.is_bytes_range() => Some("bytes_range fields"),
+                    _ => None,
+                };
+                if let Some(unsupported) = unsupported {
+                    return Err(gen.field_error(
+                        msg_name,
+                        f.name,
+                        &format!("prost_path doesn't support {unsupported}"),
+                    ));
+                }
+            }
+            for o in &oneofs {
+                let unsupported = match &o.otype {
+                    OneofType::Custom { .. } => Some("custom oneofs"),
+                    OneofType::Enum { fields, .. } => fields
+                        .iter()
+                        .any(|f| matches!(f.tspec, TypeSpec::LazyMessage { .. }))
+                        .then_some("lazy messages"),
+                };
+                if let Some(unsupported) = unsupported {
+                    return Err(gen.field_error(
+                        msg_name,
+                        o.name,
+                        &format!("prost_path doesn't support {unsupported}"),
+                    ));
+                }
+            }
+        }
 
         // Find any lifetime in the message definition (we only need one)
         let lifetime = fields
@@ -139,7 +326,20 @@ impl<'a> Message<'a> {
             .find_map(|f| f.find_lifetime())
             .or_else(|| oneofs.iter().find_map(|o| o.find_lifetime()))
             .or_else(|| unknown_handler.as_ref().and_then(find_lifetime_from_type))
+            .or_else(|| visitor.as_ref().and_then(find_lifetime_from_type))
             .cloned();
+        if domain_type.is_some() && lifetime.is_some() {
+            return Err(gen.msg_error(
+                msg_name,
+                "domain_type isn't supported on messages with lifetimes",
+            ));
+        }
+        if prost_path.is_some() && lifetime.is_some() {
+            return Err(gen.msg_error(
+                msg_name,
+                "prost_path isn't supported on messages with lifetimes",
+            ));
+        }
 
         Ok(Some(Self {
             name: msg_name,
@@ -152,17 +352,37 @@ impl<'a> Message<'a> {
             derive_clone: msg_conf.derive_clone(),
             attrs,
             unknown_handler,
+            visitor,
             lifetime,
+            canonical_order: msg_conf.config.canonical_order.unwrap_or(false),
+            serde: msg_conf.config.serde.unwrap_or(false),
+            defmt: msg_conf.config.defmt.unwrap_or(false),
+            json: msg_conf.config.json.unwrap_or(false),
+            field_name_consts: msg_conf.config.field_name_consts.unwrap_or(false),
+            field_descriptors: msg_conf
+                .config
+                .field_descriptors
+                .unwrap_or(false)
+                .then(|| msg_conf.config.field_descriptor_names.unwrap_or(true)),
+            text: msg_conf.config.text.unwrap_or(false),
+            field_mask: msg_conf.config.field_mask.unwrap_or(false),
+            arbitrary: msg_conf.config.arbitrary.unwrap_or(false),
+            proptest: msg_conf.config.proptest.unwrap_or(false),
+            domain_type,
+            domain_extra_fields,
+            prost_path,
         }))
     }
 
     pub(crate) fn generate_hazzer_decl(
         &self,
+        gen: &Generator,
         conf: CurrentConfig,
     ) -> Result<Option<(TokenStream, Vec<syn::Attribute>)>, String> {
         let hazzer_name = Ident::new("_Hazzer", Span::call_site());
         let attrs = &conf.config.type_attr_parsed()?;
         let derive_msg = derive_msg_attr(true, true, true, true);
+        let derive_defmt = derive_defmt_attr(conf.config.defmt.unwrap_or(false));
 
         let hazzers = self.fields.iter().filter(|f| f.is_hazzer());
         let count = hazzers.clone().count();
@@ -170,6 +390,7 @@ impl<'a> Message<'a> {
             return Ok(None);
         }
 
+        let inline = gen.helper_inline_attr();
         let methods = hazzers.enumerate().map(|(i, f)| {
             let fname = &f.san_rust_name;
             let setter = format_ident!("set_{}", f.rust_name);
@@ -185,27 +406,27 @@ impl<'a> Message<'a> {
 
             quote! {
                 #[doc = #getter_doc]
-                #[inline]
+                #inline
                 pub fn #fname(&self) -> bool {
                     (self.0[#idx] & #mask) != 0
                 }
 
                 #[doc = #setter_doc]
-                #[inline]
+                #inline
                 pub fn #setter(&mut self) {
                     let elem = &mut self.0[#idx];
                     *elem |= #mask;
                 }
 
                 #[doc = #clearer_doc]
-                #[inline]
+                #inline
                 pub fn #clearer(&mut self) {
                     let elem = &mut self.0[#idx];
                     *elem &= !#mask;
                 }
 
                 #[doc = #init_doc]
-                #[inline]
+                #inline
                 pub fn #init(mut self) -> Self {
                     self.#setter();
                     self
@@ -216,6 +437,7 @@ impl<'a> Message<'a> {
         let bytes = Literal::usize_unsuffixed(count.div_ceil(8));
         let decl = quote! {
             #derive_msg
+            #derive_defmt
             #(#attrs)*
             pub struct #hazzer_name([u8; #bytes]);
 
@@ -226,6 +448,110 @@ impl<'a> Message<'a> {
         Ok(Some((decl, conf.config.field_attr_parsed()?)))
     }
 
+    /// Fields and oneof variants that store a message inline without a `Box`, along with the
+    /// fully-qualified Protobuf name of that message
+    pub(crate) fn inline_msg_fields(&self) -> Vec<(&str, &str)> {
+        let fields = self
+            .fields
+            .iter()
+            .filter(|f| !f.boxed)
+            .filter_map(|f| match &f.ftype {
+                FieldType::Single(TypeSpec::Message(t))
+                | FieldType::Optional(TypeSpec::Message(t), _) => Some((f.name, t.as_str())),
+                _ => None,
+            });
+        let variants = self
+            .oneofs
+            .iter()
+            .filter(|o| !o.boxed)
+            .filter_map(|o| match &o.otype {
+                OneofType::Enum { fields, .. } => Some(fields),
+                OneofType::Custom { .. } => None,
+            })
+            .flatten()
+            .filter(|f| !f.boxed)
+            .filter_map(|f| match &f.tspec {
+                TypeSpec::Message(t) => Some((f.name, t.as_str())),
+                _ => None,
+            });
+        fields.chain(variants).collect()
+    }
+
+    /// Generate modules with constants holding the Protobuf and JSON names of the fields
+    pub(crate) fn generate_name_consts(&self) -> Option<TokenStream> {
+        if !self.field_name_consts {
+            return None;
+        }
+        let oneof_fields = self.oneofs.iter().flat_map(|o| match &o.otype {
+            OneofType::Enum { fields, .. } => {
+                fields.iter().map(|f| (f.name, &f.json_name)).collect()
+            }
+            OneofType::Custom { .. } => vec![],
+        });
+        let (names, json_names): (Vec<_>, Vec<_>) = self
+            .fields
+            .iter()
+            .map(|f| (f.name, &f.json_name))
+            .chain(oneof_fields)
+            .unzip();
+        let const_names: Vec<_> = names
+            .iter()
+            .map(|n| sanitized_ident(&n.to_case(Case::UpperSnake)))
+            .collect();
+        Some(quote! {
+            /// Protobuf names of the fields
+            pub mod _field_names {
+                #(pub const #const_names: &str = #names;)*
+            }
+
+            /// JSON names of the fields
+            pub mod _json_names {
+                #(pub const #const_names: &str = #json_names;)*
+            }
+        })
+    }
+
+    pub(crate) fn generate_visitor_decl(&self, gen: &Generator) -> Option<TokenStream> {
+        self.visitor.as_ref()?;
+        let methods = self.fields.iter().filter_map(|f| {
+            let FieldType::Visit { typ, decode } = &f.ftype else {
+                return None;
+            };
+            let rust_type = typ.generate_rust_type(gen);
+            let visit = format_ident!("on_{}", f.rust_name);
+            let for_each = format_ident!("for_each_{}", f.rust_name);
+            let visit_doc = format!("Called with each element of `{}` decoded from the wire", f.rust_name);
+            let for_each_doc = format!(
+                "Pass each element of `{}` to `f` when encoding. By default, no elements are encoded.\n\n\
+                 This is called once for each traversal of the field, which happens twice if the \
+                 message's size is computed before encoding it.",
+                f.rust_name
+            );
+            // Skipped fields have no decode hook
+            let visit_decl = decode.then(|| {
+                quote! {
+                    #[doc = #visit_doc]
+                    fn #visit(&mut self, elem: &#rust_type);
+                }
+            });
+            Some(quote! {
+                #visit_decl
+
+                #[doc = #for_each_doc]
+                fn #for_each<E>(&self, _f: impl FnMut(&#rust_type) -> Result<(), E>) -> Result<(), E> {
+                    Ok(())
+                }
+            })
+        });
+        let doc = format!("Handler for the visited fields of `{}`", self.name);
+        Some(quote! {
+            #[doc = #doc]
+            pub trait _Visitor {
+                #(#methods)*
+            }
+        })
+    }
+
     pub(crate) fn generate_decl(
         &self,
         gen: &Generator,
@@ -246,11 +572,19 @@ impl<'a> Message<'a> {
             let unknown_field_attr = unknown_conf
                 .config
                 .field_attr_parsed()
-                .map_err(|e| field_error(&gen.pkg, self.name, "_unknown", &e))?;
-            quote! { #(#unknown_field_attr)* pub _unknown: #handler, }
+                .map_err(|e| gen.field_error(self.name, "_unknown", &e))?;
+            // Unknown field handlers aren't expected to implement `Format`
+            let defmt_attr = self
+                .defmt
+                .then(|| quote! { #[cfg_attr(feature = "defmt", defmt(Debug2Format))] });
+            quote! { #defmt_attr #(#unknown_field_attr)* pub _unknown: #handler, }
         } else {
             quote! {}
         };
+        let visitor_field = self
+            .visitor
+            .as_ref()
+            .map(|visitor| quote! { pub _visitor: #visitor, });
 
         let derive_msg = derive_msg_attr(
             self.derive_dbg,
@@ -258,20 +592,40 @@ impl<'a> Message<'a> {
             self.derive_partial_eq,
             self.derive_clone,
         );
+        let derive_defmt = derive_defmt_attr(self.defmt);
         let attrs = &self.attrs;
 
         Ok(quote! {
             #derive_msg
+            #derive_defmt
             #(#attrs)*
             pub struct #rust_name<#lifetime> {
                 #(#msg_fields)*
                 #(#oneof_fields)*
                 #(#(#hazzer_field_attr)* pub _has: #msg_mod_name::_Hazzer,)*
                 #unknown_field
+                #visitor_field
             }
         })
     }
 
+    /// Compile-time checks that the containers of the message's fields can hold their configured
+    /// bounds. Oneof variants are checked in the message module, where their types are declared.
+    pub(crate) fn generate_capacity_asserts(
+        &self,
+        gen: &Generator,
+        full_name: &str,
+    ) -> Option<TokenStream> {
+        if !gen.capacity_asserts || self.lifetime.is_some() {
+            return None;
+        }
+        let asserts = self
+            .fields
+            .iter()
+            .map(|f| f.generate_capacity_asserts(gen, full_name));
+        Some(quote! { #(#asserts)* })
+    }
+
     pub(crate) fn generate_default_impl(
         &self,
         gen: &Generator,
@@ -283,12 +637,12 @@ impl<'a> Message<'a> {
 
         let mut field_defaults = TokenStream::new();
         for f in &self.fields {
-            // Skip delegate fields when generating defaults
-            if !matches!(f.ftype, FieldType::Custom(CustomField::Delegate(_))) {
+            // Skip delegate and visited fields when generating defaults
+            if f.is_stored() {
                 let name = &f.san_rust_name;
                 let default = f
                     .generate_default(gen)
-                    .map_err(|e| field_error(&gen.pkg, self.name, f.name, &e))?;
+                    .map_err(|e| gen.field_error(self.name, f.name, &e))?;
                 field_defaults.extend(quote! { #name: #default, });
             }
         }
@@ -310,6 +664,10 @@ impl<'a> Message<'a> {
             .unknown_handler
             .as_ref()
             .map(|_| quote! { _unknown: ::core::default::Default::default(), });
+        let visitor_default = self
+            .visitor
+            .as_ref()
+            .map(|_| quote! { _visitor: ::core::default::Default::default(), });
         let rust_name = &self.rust_name;
         let lifetime = &self.lifetime;
 
@@ -321,6 +679,7 @@ impl<'a> Message<'a> {
                         #(#oneof_names: ::core::default::Default::default(),)*
                         #hazzer_default
                         #unknown_default
+                        #visitor_default
                     }
                 }
             }
@@ -328,6 +687,7 @@ impl<'a> Message<'a> {
     }
 
     pub(crate) fn generate_impl(&self, gen: &Generator) -> TokenStream {
+        let inline = gen.helper_inline_attr();
         let accessors = self.fields.iter().map(|f| {
             if let FieldType::Optional(type_spec, opt) = &f.ftype {
                 let type_name = type_spec.generate_rust_type(gen);
@@ -348,77 +708,1141 @@ impl<'a> Message<'a> {
                 if let OptionalRepr::Hazzer = opt {
                     quote! {
                         #[doc = #getter_doc]
-                        #[inline]
+                        #inline
                         pub fn #fname(&self) -> ::core::option::Option<&#type_name> {
                             self._has.#fname().then_some(&self.#fname)
                         }
 
-                        #[doc = #muter_doc]
-                        #[inline]
-                        pub fn #muter_name(&mut self) -> ::core::option::Option<&mut #type_name> {
-                            self._has.#fname().then_some(&mut self.#fname)
-                        }
+                        #[doc = #muter_doc]
+                        #inline
+                        pub fn #muter_name(&mut self) -> ::core::option::Option<&mut #type_name> {
+                            self._has.#fname().then_some(&mut self.#fname)
+                        }
+
+                        #[doc = #setter_doc]
+                        #inline
+                        pub fn #setter_name(&mut self, value: #type_name) {
+                            self._has.#setter_name();
+                            self.#fname = value.into();
+                        }
+
+                        #[doc = #clearer_doc]
+                        #inline
+                        pub fn #clearer_name(&mut self) {
+                            self._has.#clearer_name();
+                        }
+                    }
+                } else {
+                    let (deref, deref_mut) = if f.boxed {
+                        (format_ident!("as_deref"), format_ident!("as_deref_mut"))
+                    } else {
+                        (format_ident!("as_ref"), format_ident!("as_mut"))
+                    };
+                    quote! {
+                        #[doc = #getter_doc]
+                        #inline
+                        pub fn #fname(&self) -> ::core::option::Option<&#type_name> {
+                            self.#fname.#deref()
+                        }
+
+                        #[doc = #muter_doc]
+                        #inline
+                        pub fn #muter_name(&mut self) -> ::core::option::Option<&mut #type_name> {
+                            self.#fname.#deref_mut()
+                        }
+
+                        #[doc = #setter_doc]
+                        #inline
+                        pub fn #setter_name(&mut self, value: #type_name) {
+                            self.#fname = ::core::option::Option::Some(value.into());
+                        }
+
+                        #[doc = #clearer_doc]
+                        #inline
+                        pub fn #clearer_name(&mut self) {
+                            self.#fname = ::core::option::Option::None;
+                        }
+                    }
+                }
+            } else {
+                quote! {}
+            }
+        });
+
+        let lazy_accessors = self.fields.iter().map(|f| {
+            let FieldType::Optional(
+                type_spec @ TypeSpec::LazyMessage {
+                    name: msg_name, ..
+                },
+                _,
+            ) = &f.ftype
+            else {
+                return quote! {};
+            };
+            let fname = &f.san_rust_name;
+            let msg_type = gen.resolve_type_name(msg_name);
+
+            let decode = gen.encode_decode.is_decode().then(|| {
+                let decode_name = format_ident!("decode_{}", f.rust_name);
+                let decode_doc = format!(
+                    "Decode the message stored in `{}`. Returns the default message if the field isn't present.",
+                    f.rust_name
+                );
+                let cfg = gen.decode_cfg_attr();
+                quote! {
+                    #[doc = #decode_doc]
+                    #cfg
+                    pub fn #decode_name(&self) -> Result<#msg_type, ::micropb::DecodeError<::micropb::Never>> {
+                        use ::micropb::MessageDecode as _;
+                        let mut msg = <#msg_type as ::core::default::Default>::default();
+                        if let ::core::option::Option::Some(bytes) = self.#fname() {
+                            msg.merge_from_bytes(bytes)?;
+                        }
+                        Ok(msg)
+                    }
+                }
+            });
+            let encode = gen.encode_decode.is_encode().then(|| {
+                let type_name = type_spec.generate_rust_type(gen);
+                let setter_name = format_ident!("set_{}", f.rust_name);
+                let msg_setter_name = format_ident!("set_{}_msg", f.rust_name);
+                let msg_setter_doc = format!(
+                    "Encode a message and set it as the value of `{}`. Fails if the field's container doesn't have enough capacity.",
+                    f.rust_name
+                );
+                let cfg = gen.encode_cfg_attr();
+                quote! {
+                    #[doc = #msg_setter_doc]
+                    #cfg
+                    pub fn #msg_setter_name(&mut self, msg: &#msg_type) -> Result<(), ()> {
+                        use ::micropb::MessageEncode as _;
+                        let mut bytes = <#type_name as ::core::default::Default>::default();
+                        msg.encode(&mut ::micropb::PbEncoder::new(::micropb::PbVecWriter(&mut bytes))).map_err(drop)?;
+                        self.#setter_name(bytes);
+                        Ok(())
+                    }
+                }
+            });
+            quote! { #decode #encode }
+        });
+
+        let range_accessors = self
+            .fields
+            .iter()
+            .filter(|f| f.ftype.is_bytes_range())
+            .map(|f| {
+                let fname = &f.san_rust_name;
+                let slice_name = format_ident!("{}_slice", f.rust_name);
+                let extra_deref = f.boxed.then(|| quote! { * });
+                if let FieldType::Optional(..) = f.ftype {
+                    let slice_doc = format!(
+                        "Return the bytes of `{}` within `input` as an `Option`. `input` must be the buffer that the message was decoded from, otherwise this may panic.",
+                        f.rust_name
+                    );
+                    quote! {
+                        #[doc = #slice_doc]
+                        #inline
+                        pub fn #slice_name<'input>(&self, input: &'input [u8]) -> ::core::option::Option<&'input [u8]> {
+                            self.#fname().map(|range| &input[range.clone()])
+                        }
+                    }
+                } else {
+                    let slice_doc = format!(
+                        "Return the bytes of `{}` within `input`. `input` must be the buffer that the message was decoded from, otherwise this may panic.",
+                        f.rust_name
+                    );
+                    quote! {
+                        #[doc = #slice_doc]
+                        #inline
+                        pub fn #slice_name<'input>(&self, input: &'input [u8]) -> &'input [u8] {
+                            &input[(#extra_deref self.#fname).clone()]
+                        }
+                    }
+                }
+            });
+
+        // Only fields and oneofs with presence tracking can be missing
+        let mut presence: Vec<_> = self
+            .fields
+            .iter()
+            .filter_map(|f| {
+                let fname = &f.san_rust_name;
+                match f.ftype {
+                    FieldType::Optional(_, OptionalRepr::Hazzer) => {
+                        Some((f.num, quote! { self._has.#fname() }))
+                    }
+                    FieldType::Optional(_, OptionalRepr::Option) => {
+                        Some((f.num, quote! { self.#fname.is_some() }))
+                    }
+                    _ => None,
+                }
+            })
+            .collect();
+        for o in &self.oneofs {
+            if let OneofType::Enum { fields, .. } = &o.otype {
+                let oname = &o.san_rust_name;
+                if let Some(num) = fields.iter().map(|f| f.num).min() {
+                    presence.push((num, quote! { self.#oname.is_some() }));
+                }
+            }
+        }
+        presence.sort_by_key(|(num, _)| *num);
+        let missing_fields = (gen.missing_fields && !presence.is_empty()).then(|| {
+            let len = presence.len();
+            let (nums, checks): (Vec<_>, Vec<_>) = presence.into_iter().unzip();
+            quote! {
+                /// Return the field numbers of the optional fields that aren't present, in
+                /// ascending order. A oneof with no variant set is reported once, using the lowest
+                /// field number in the oneof. Fields without presence tracking are never reported.
+                pub fn missing_fields(&self) -> impl ::core::iter::Iterator<Item = u32> {
+                    let present: [(u32, bool); #len] = [#((#nums, #checks)),*];
+                    present.into_iter().filter_map(|(num, has)| (!has).then_some(num))
+                }
+            }
+        });
+
+        let name = &self.rust_name;
+        let lifetime = &self.lifetime;
+        quote! {
+            impl<#lifetime> #name<#lifetime> {
+                #(#accessors)*
+                #(#lazy_accessors)*
+                #(#range_accessors)*
+                #missing_fields
+            }
+        }
+    }
+
+    /// Implement `MessageName` with the fully-qualified Protobuf name of the message
+    pub(crate) fn generate_name_impl(&self, full_name: &str) -> TokenStream {
+        let name = &self.rust_name;
+        let lifetime = &self.lifetime;
+        quote! {
+            impl<#lifetime> ::micropb::MessageName for #name<#lifetime> {
+                const FULL_NAME: &'static str = #full_name;
+            }
+        }
+    }
+
+    /// Implement `MessageFields` with a table describing the fields, sorted by number
+    pub(crate) fn generate_fields_impl(&self) -> Option<TokenStream> {
+        let with_names = self.field_descriptors?;
+        let oneof_fields = self.oneofs.iter().flat_map(|o| match &o.otype {
+            OneofType::Enum { fields, .. } => fields
+                .iter()
+                .map(|f| (f.num, f.name, f.tspec.wire_type(), quote! { Oneof }))
+                .collect(),
+            OneofType::Custom { .. } => vec![],
+        });
+        let mut entries: Vec<_> = self
+            .fields
+            .iter()
+            .filter_map(|f| {
+                let (wire_type, label) = f.descriptor_label()?;
+                Some((f.num, f.name, wire_type, label))
+            })
+            .chain(oneof_fields)
+            .collect();
+        entries.sort_by_key(|(num, ..)| *num);
+
+        let entries = entries.into_iter().map(|(num, fname, wire_type, label)| {
+            let num = Literal::u32_unsuffixed(num);
+            let wire_type = Literal::u8_unsuffixed(wire_type);
+            let fname = if with_names {
+                quote! { ::core::option::Option::Some(#fname) }
+            } else {
+                quote! { ::core::option::Option::None }
+            };
+            quote! {
+                ::micropb::FieldDescriptor {
+                    num: #num,
+                    name: #fname,
+                    wire_type: #wire_type,
+                    label: ::micropb::FieldLabel::#label,
+                }
+            }
+        });
+        let name = &self.rust_name;
+        let lifetime = &self.lifetime;
+        Some(quote! {
+            impl<#lifetime> ::micropb::MessageFields for #name<#lifetime> {
+                const FIELDS: &'static [::micropb::FieldDescriptor] = &[#(#entries,)*];
+            }
+        })
+    }
+
+    /// Generate the `pack` and `unpack` helpers of `google.protobuf.Any`. Returns `None` for
+    /// other messages, and if `type_url` and `value` aren't plain owned containers.
+    pub(crate) fn generate_any_impl(
+        &self,
+        gen: &Generator,
+        full_name: &str,
+    ) -> Option<TokenStream> {
+        if full_name != "google.protobuf.Any" || self.lifetime.is_some() {
+            return None;
+        }
+        let find_field = |fname: &str| self.fields.iter().find(|f| f.name == fname && !f.boxed);
+        let type_url = find_field("type_url")?;
+        let value = find_field("value")?;
+        if !matches!(
+            type_url.ftype,
+            FieldType::Single(TypeSpec::String {
+                as_bytes: false,
+                ..
+            })
+        ) || !matches!(value.ftype, FieldType::Single(TypeSpec::Bytes { .. }))
+        {
+            return None;
+        }
+        let type_url = &type_url.san_rust_name;
+        let value = &value.san_rust_name;
+
+        let pack = (gen.encode_decode.is_encode() && self.impl_default).then(|| {
+            let cfg = gen.encode_cfg_attr();
+            quote! {
+                /// Encode `msg` into a new `Any`, with a type URL made of
+                /// `micropb::DEFAULT_TYPE_URL_PREFIX` and the full name of the message. Fails if
+                /// the containers don't have enough capacity.
+                #cfg
+                pub fn pack<M: ::micropb::MessageEncode + ::micropb::MessageName>(msg: &M) -> Result<Self, ()> {
+                    Self::pack_with_prefix(msg, ::micropb::DEFAULT_TYPE_URL_PREFIX)
+                }
+
+                /// Encode `msg` into a new `Any`, with a type URL made of `prefix` and the full
+                /// name of the message. Fails if the containers don't have enough capacity.
+                #cfg
+                pub fn pack_with_prefix<M: ::micropb::MessageEncode + ::micropb::MessageName>(msg: &M, prefix: &str) -> Result<Self, ()> {
+                    let mut any = <Self as ::core::default::Default>::default();
+                    any.#type_url = M::type_url(prefix)?;
+                    msg.encode(&mut ::micropb::PbEncoder::new(::micropb::PbVecWriter(&mut any.#value))).map_err(drop)?;
+                    Ok(any)
+                }
+            }
+        });
+        let unpack = gen.encode_decode.is_decode().then(|| {
+            let cfg = gen.decode_cfg_attr();
+            quote! {
+                /// Decode the packed message as `M`. Returns `None` if the type URL doesn't refer
+                /// to `M`.
+                #cfg
+                pub fn unpack<M: ::micropb::MessageDecode + ::micropb::MessageName + ::core::default::Default>(&self) -> Result<::core::option::Option<M>, ::micropb::DecodeError<::micropb::Never>> {
+                    if !self.is::<M>() {
+                        return Ok(::core::option::Option::None);
+                    }
+                    let mut msg = M::default();
+                    msg.merge_from_bytes(&self.#value)?;
+                    Ok(::core::option::Option::Some(msg))
+                }
+            }
+        });
+        let name = &self.rust_name;
+        Some(quote! {
+            impl #name {
+                /// Check whether the type URL refers to the message type `M`. Only the part of
+                /// the URL after the last `/` is compared.
+                pub fn is<M: ::micropb::MessageName>(&self) -> bool {
+                    M::matches_type_url(&self.#type_url)
+                }
+
+                #pack
+                #unpack
+            }
+        })
+    }
+
+    /// Generate accessors for the variants of `google.protobuf.Value`
+    pub(crate) fn generate_value_impl(
+        &self,
+        gen: &Generator,
+        full_name: &str,
+    ) -> Option<TokenStream> {
+        if full_name != "google.protobuf.Value" {
+            return None;
+        }
+        let kind = self.oneofs.iter().find(|o| o.name == "kind")?;
+        let OneofType::Enum { type_name, fields } = &kind.otype else {
+            return None;
+        };
+        let msg_mod_name = resolve_path_elem(self.name);
+        let oneof_name = &kind.san_rust_name;
+        let as_ref = if kind.boxed {
+            quote! { as_deref }
+        } else {
+            quote! { as_ref }
+        };
+
+        let accessors = fields.iter().filter_map(|f| {
+            let variant_name = &f.rust_name;
+            let variant = quote! { #msg_mod_name::#type_name::#variant_name };
+            let unbox = f.boxed.then(|| quote! { let v = &**v; });
+            let (doc, method, ret, val) = match (f.name, &f.tspec) {
+                ("null_value", TypeSpec::Enum(..)) => {
+                    return Some(quote! {
+                        /// Returns `true` if the value is `null`.
+                        pub fn is_null(&self) -> bool {
+                            ::core::matches!(self.#oneof_name.#as_ref(), ::core::option::Option::Some(#variant(_)))
+                        }
+                    });
+                }
+                ("number_value", TypeSpec::Double) => {
+                    ("number", quote! { as_f64 }, quote! { f64 }, quote! { *v })
+                }
+                ("string_value", TypeSpec::String { as_bytes: false, .. }) => {
+                    ("string", quote! { as_str }, quote! { &str }, quote! { &**v })
+                }
+                ("bool_value", TypeSpec::Bool) => {
+                    ("boolean", quote! { as_bool }, quote! { bool }, quote! { *v })
+                }
+                ("struct_value", TypeSpec::Message(_)) => {
+                    let typ = f.tspec.generate_rust_type(gen);
+                    ("struct", quote! { as_struct }, quote! { &#typ }, quote! { v })
+                }
+                ("list_value", TypeSpec::Message(_)) => {
+                    let typ = f.tspec.generate_rust_type(gen);
+                    ("list", quote! { as_list }, quote! { &#typ }, quote! { v })
+                }
+                // Lazy variants hold the encoded message, which is decoded on access
+                (
+                    name @ ("struct_value" | "list_value"),
+                    TypeSpec::LazyMessage { name: msg_name, .. },
+                ) if gen.encode_decode.is_decode() => {
+                    let typ = gen.resolve_type_name(msg_name);
+                    let (doc, method) = if name == "struct_value" {
+                        ("struct", quote! { as_struct })
+                    } else {
+                        ("list", quote! { as_list })
+                    };
+                    let doc = format!(" Decodes the value if it's a {doc}.");
+                    let cfg = gen.decode_cfg_attr();
+                    return Some(quote! {
+                        #[doc = #doc]
+                        #cfg
+                        pub fn #method(&self) -> ::core::option::Option<::core::result::Result<#typ, ::micropb::DecodeError<::micropb::Never>>> {
+                            use ::micropb::MessageDecode as _;
+                            match self.#oneof_name.#as_ref() {
+                                ::core::option::Option::Some(#variant(v)) => {
+                                    #unbox
+                                    let mut msg = <#typ as ::core::default::Default>::default();
+                                    ::core::option::Option::Some(msg.merge_from_bytes(v).map(|_| msg))
+                                }
+                                _ => ::core::option::Option::None,
+                            }
+                        }
+                    });
+                }
+                _ => return None,
+            };
+            let doc = format!(" Returns the value if it's a {doc}.");
+            Some(quote! {
+                #[doc = #doc]
+                pub fn #method(&self) -> ::core::option::Option<#ret> {
+                    match self.#oneof_name.#as_ref() {
+                        ::core::option::Option::Some(#variant(v)) => {
+                            #unbox
+                            ::core::option::Option::Some(#val)
+                        }
+                        _ => ::core::option::Option::None,
+                    }
+                }
+            })
+        });
+
+        let name = &self.rust_name;
+        let lifetime = &self.lifetime;
+        Some(quote! {
+            impl<#lifetime> #name<#lifetime> {
+                #(#accessors)*
+            }
+        })
+    }
+
+    /// Implement the Serde traits by hand rather than deriving them, so that optional fields
+    /// tracked by the hazzer can be (de)serialized as nullable values based on their presence
+    pub(crate) fn generate_serde_impls(&self, gen: &Generator) -> Option<TokenStream> {
+        if !self.serde {
+            return None;
+        }
+        let msg_mod_name = resolve_path_elem(self.name);
+        let mut ser_fields = vec![];
+        let mut proxy_fields = vec![];
+        let mut assigns = vec![];
+
+        for f in self.fields.iter().filter(|f| f.is_stored()) {
+            let fname = &f.san_rust_name;
+            let key = fname.unraw().to_string();
+            let typ = f.generate_rust_type(gen);
+            if f.is_hazzer() {
+                let setter = format_ident!("set_{}", f.rust_name);
+                ser_fields.push(quote! {
+                    state.serialize_field(#key, &self._has.#fname().then_some(&self.#fname))?;
+                });
+                proxy_fields.push(quote! { #fname: ::core::option::Option<#typ>, });
+                assigns.push(quote! {
+                    if let ::core::option::Option::Some(val) = proxy.#fname {
+                        msg.#fname = val;
+                        msg._has.#setter();
+                    }
+                });
+            } else {
+                ser_fields.push(quote! { state.serialize_field(#key, &self.#fname)?; });
+                if f.is_option() {
+                    proxy_fields.push(quote! { #fname: #typ, });
+                    assigns.push(quote! { msg.#fname = proxy.#fname; });
+                } else {
+                    proxy_fields.push(quote! { #fname: ::core::option::Option<#typ>, });
+                    assigns.push(quote! {
+                        if let ::core::option::Option::Some(val) = proxy.#fname {
+                            msg.#fname = val;
+                        }
+                    });
+                }
+            }
+        }
+        for o in &self.oneofs {
+            let Some(typ) = o.generate_rust_type(gen, &msg_mod_name) else {
+                continue;
+            };
+            let name = &o.san_rust_name;
+            let key = name.unraw().to_string();
+            ser_fields.push(quote! { state.serialize_field(#key, &self.#name)?; });
+            if let OneofType::Enum { .. } = o.otype {
+                proxy_fields.push(quote! { #name: #typ, });
+                assigns.push(quote! { msg.#name = proxy.#name; });
+            } else {
+                proxy_fields.push(quote! { #name: ::core::option::Option<#typ>, });
+                assigns.push(quote! {
+                    if let ::core::option::Option::Some(val) = proxy.#name {
+                        msg.#name = val;
+                    }
+                });
+            }
+        }
+
+        let name = &self.rust_name;
+        let name_str = name.unraw().to_string();
+        let lifetime = &self.lifetime;
+        // The proxy only needs the lifetime if one of its fields borrows
+        let proxy_lifetime = self
+            .fields
+            .iter()
+            .filter(|f| f.is_stored())
+            .find_map(|f| f.find_lifetime())
+            .or_else(|| self.oneofs.iter().find_map(|o| o.find_lifetime()));
+        let de_bound = lifetime.as_ref().map(|lt| quote! { : #lt });
+        let len = ser_fields.len();
+        let (state_mut, msg_mut, proxy_var) = if len > 0 {
+            (quote! { mut }, quote! { mut }, quote! { proxy })
+        } else {
+            (quote! {}, quote! {}, quote! { _ })
+        };
+
+        Some(quote! {
+            impl<#lifetime> ::serde::Serialize for #name<#lifetime> {
+                fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> ::core::result::Result<S::Ok, S::Error> {
+                    use ::serde::ser::SerializeStruct as _;
+                    let #state_mut state = serializer.serialize_struct(#name_str, #len)?;
+                    #(#ser_fields)*
+                    state.end()
+                }
+            }
+
+            impl<'de #de_bound, #lifetime> ::serde::Deserialize<'de> for #name<#lifetime> {
+                fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> ::core::result::Result<Self, D::Error> {
+                    #[derive(::serde::Deserialize)]
+                    #[serde(rename = #name_str)]
+                    struct _Proxy<#proxy_lifetime> {
+                        #(#[serde(default)] #proxy_fields)*
+                    }
+
+                    let #proxy_var: _Proxy = ::serde::Deserialize::deserialize(deserializer)?;
+                    let #msg_mut msg = <Self as ::core::default::Default>::default();
+                    #(#assigns)*
+                    ::core::result::Result::Ok(msg)
+                }
+            }
+        })
+    }
+
+    pub(crate) fn generate_json_impl(&self) -> Option<TokenStream> {
+        if !self.json {
+            return None;
+        }
+        let msg_mod_name = resolve_path_elem(self.name);
+        let obj = Ident::new("obj", Span::call_site());
+        let mut stmts = vec![];
+        for f in &self.fields {
+            stmts.extend(f.generate_json(&obj));
+        }
+        for o in &self.oneofs {
+            stmts.extend(o.generate_json(&msg_mod_name, &obj));
+        }
+
+        let name = &self.rust_name;
+        let lifetime = &self.lifetime;
+        let obj_mut = (!stmts.is_empty()).then(|| quote! { mut });
+        Some(quote! {
+            impl<#lifetime> ::micropb::json::MessageToJson for #name<#lifetime> {
+                fn write_json<IMPL_MICROPB_FMT_WRITE: ::core::fmt::Write + ?Sized>(&self, w: &mut IMPL_MICROPB_FMT_WRITE) -> ::core::fmt::Result {
+                    let #obj_mut #obj = ::micropb::json::JsonObject::begin(w)?;
+                    #(#stmts)*
+                    #obj.end()
+                }
+            }
+        })
+    }
+
+    pub(crate) fn generate_text_impl(&self) -> Option<TokenStream> {
+        if !self.text {
+            return None;
+        }
+        let msg_mod_name = resolve_path_elem(self.name);
+        let t = Ident::new("t", Span::call_site());
+        let mut stmts = vec![];
+        for f in &self.fields {
+            stmts.extend(f.generate_text(&t));
+        }
+        for o in &self.oneofs {
+            stmts.extend(o.generate_text(&msg_mod_name, &t));
+        }
+
+        let name = &self.rust_name;
+        let lifetime = &self.lifetime;
+        let t_param = if stmts.is_empty() {
+            quote! { _ }
+        } else {
+            quote! { #t }
+        };
+        Some(quote! {
+            impl<#lifetime> ::micropb::text::MessageToText for #name<#lifetime> {
+                fn write_text_fields<IMPL_MICROPB_FMT_WRITE: ::core::fmt::Write + ?Sized>(&self, #t_param: &mut ::micropb::text::TextWriter<'_, IMPL_MICROPB_FMT_WRITE>) -> ::core::fmt::Result {
+                    #(#stmts)*
+                    Ok(())
+                }
+            }
+        })
+    }
+
+    pub(crate) fn generate_text_parse_impl(&self, gen: &Generator) -> Option<TokenStream> {
+        if !self.text {
+            return None;
+        }
+        let msg_mod_name = resolve_path_elem(self.name);
+        let parser = Ident::new("parser", Span::call_site());
+        let mut arms = vec![];
+        for f in &self.fields {
+            arms.extend(f.generate_text_parse_arm(gen, &parser));
+        }
+        for o in &self.oneofs {
+            arms.extend(o.generate_text_parse_arms(gen, &msg_mod_name, &parser));
+        }
+
+        let body = if arms.is_empty() {
+            quote! {
+                let _ = (name, #parser);
+                ::core::result::Result::Ok(false)
+            }
+        } else {
+            quote! {
+                use ::micropb::{PbContainer, PbVec, PbMap};
+                match name {
+                    #(#arms)*
+                    _ => return ::core::result::Result::Ok(false),
+                }
+                ::core::result::Result::Ok(true)
+            }
+        };
+        let name = &self.rust_name;
+        let lifetime = &self.lifetime;
+        Some(quote! {
+            impl<#lifetime> ::micropb::text::MessageFromText for #name<#lifetime> {
+                fn merge_text_field<'micropb_text>(
+                    &mut self,
+                    name: &str,
+                    #parser: &mut ::micropb::text::TextParser<'micropb_text>,
+                ) -> ::core::result::Result<bool, ::micropb::text::TextError<'micropb_text>> {
+                    #body
+                }
+            }
+        })
+    }
+
+    pub(crate) fn generate_json_parse_impl(&self, gen: &Generator) -> Option<TokenStream> {
+        if !self.json {
+            return None;
+        }
+        let msg_mod_name = resolve_path_elem(self.name);
+        let parser = Ident::new("parser", Span::call_site());
+        let mut arms = vec![];
+        for f in &self.fields {
+            arms.extend(f.generate_json_parse_arm(gen, &parser));
+        }
+        for o in &self.oneofs {
+            arms.extend(o.generate_json_parse_arms(gen, &msg_mod_name, &parser));
+        }
+
+        let body = if arms.is_empty() {
+            quote! {
+                let _ = (key, #parser);
+                ::core::result::Result::Ok(false)
+            }
+        } else {
+            quote! {
+                use ::micropb::{PbContainer, PbVec, PbMap};
+                match key {
+                    #(#arms)*
+                    _ => return ::core::result::Result::Ok(false),
+                }
+                ::core::result::Result::Ok(true)
+            }
+        };
+        let name = &self.rust_name;
+        let lifetime = &self.lifetime;
+        Some(quote! {
+            impl<#lifetime> ::micropb::json::MessageFromJson for #name<#lifetime> {
+                fn merge_json_field<'micropb_json>(
+                    &mut self,
+                    key: &str,
+                    #parser: &mut ::micropb::json::JsonParser<'micropb_json>,
+                ) -> ::core::result::Result<bool, ::micropb::json::JsonError<'micropb_json>> {
+                    #body
+                }
+            }
+        })
+    }
+
+    pub(crate) fn generate_field_mask_impl(&self, gen: &Generator) -> Option<TokenStream> {
+        if !self.field_mask {
+            return None;
+        }
+        let msg_mod_name = resolve_path_elem(self.name);
+        let rest = Ident::new("rest", Span::call_site());
+        let mut names = vec![];
+        let mut check_arms = vec![];
+        let mut apply_arms = vec![];
+        for f in &self.fields {
+            let Some(copy) = f.generate_field_mask_copy() else {
+                continue;
+            };
+            let name = f.name;
+            names.push(name);
+            apply_arms.push(quote! { (#name, ::core::option::Option::None) => { #copy } });
+            if let Some((check, apply)) = f.generate_field_mask_nested(gen, &rest) {
+                check_arms.push(quote! { (#name, ::core::option::Option::Some(#rest)) => #check, });
+                apply_arms
+                    .push(quote! { (#name, ::core::option::Option::Some(#rest)) => { #apply } });
+            }
+        }
+        for o in &self.oneofs {
+            for (name, copy) in o.generate_field_mask_copies(&msg_mod_name) {
+                names.push(name);
+                apply_arms.push(quote! { (#name, ::core::option::Option::None) => { #copy } });
+            }
+        }
+
+        // Paths can only continue past message fields
+        let (leaf_arms, src_param) = if names.is_empty() {
+            (None, quote! { _ })
+        } else {
+            (
+                Some(quote! {
+                    (#(#names)|*, ::core::option::Option::None) => ::core::result::Result::Ok(()),
+                    #(#check_arms)*
+                    (#(#names)|*, ::core::option::Option::Some(#rest)) => {
+                        ::core::result::Result::Err(::micropb::field_mask::split_path(#rest).0)
+                    }
+                }),
+                quote! { src },
+            )
+        };
+        let name = &self.rust_name;
+        let lifetime = &self.lifetime;
+        Some(quote! {
+            impl<#lifetime> ::micropb::field_mask::ApplyFieldMask for #name<#lifetime> {
+                fn check_path(path: &str) -> ::core::result::Result<(), &str> {
+                    match ::micropb::field_mask::split_path(path) {
+                        #leaf_arms
+                        (name, _) => ::core::result::Result::Err(name),
+                    }
+                }
+
+                fn apply_path(&mut self, #src_param: &Self, path: &str) {
+                    match ::micropb::field_mask::split_path(path) {
+                        #(#apply_arms)*
+                        _ => {}
+                    }
+                }
+            }
+        })
+    }
+
+    /// Generate the `arbitrary_strategy` constructor behind the `proptest` feature, along with
+    /// the hidden `_strategy_depth` constructor that stops generating nested messages once
+    /// `depth` runs out. Messages with lifetimes are skipped, since boxed strategies need
+    /// `'static` values.
+    pub(crate) fn generate_proptest_impl(&self, gen: &Generator) -> Option<TokenStream> {
+        if !self.proptest || self.lifetime.is_some() {
+            return None;
+        }
+        let msg_mod_name = resolve_path_elem(self.name);
+        let depth = Ident::new("depth", Span::call_site());
+        let mut items = vec![];
+        let mut assigns = vec![];
+        for f in &self.fields {
+            let val = format_ident!("v{}", items.len());
+            if let Some((strategy, assign)) = f.generate_strategy(gen, &depth, &val) {
+                items.push((strategy, quote! { #val }));
+                assigns.push(assign);
+            }
+        }
+        for o in &self.oneofs {
+            let name = &o.san_rust_name;
+            let val = format_ident!("v{}", items.len());
+            match &o.otype {
+                OneofType::Enum { type_name, .. } => {
+                    let alloc = gen.alloc_crate();
+                    let boxed = o.boxed.then(|| quote! { .map(#alloc::boxed::Box::new) });
+                    items.push((
+                        quote! { ::proptest::option::of(#msg_mod_name::#type_name::_strategy_depth(#depth)) },
+                        quote! { #val },
+                    ));
+                    assigns.push(quote! { msg.#name = #val #boxed; });
+                }
+                OneofType::Custom {
+                    field: CustomField::Type(typ),
+                    ..
+                } => {
+                    items.push((
+                        quote! { ::proptest::prelude::any::<#typ>() },
+                        quote! { #val },
+                    ));
+                    assigns.push(quote! { msg.#name = #val; });
+                }
+                OneofType::Custom { .. } => {}
+            }
+        }
+
+        let default = quote! {
+            ::proptest::strategy::LazyJust::new(<Self as ::core::default::Default>::default).boxed()
+        };
+        let body = if items.is_empty() {
+            default
+        } else {
+            let (strategy, pat) = nest_strategies(items);
+            quote! {
+                if #depth == 0 {
+                    return #default;
+                }
+                #strategy.prop_map(|#pat| {
+                    let mut msg = <Self as ::core::default::Default>::default();
+                    #(#assigns)*
+                    msg
+                }).boxed()
+            }
+        };
+        let name = &self.rust_name;
+        Some(quote! {
+            #[cfg(feature = "proptest")]
+            impl #name {
+                /// Strategy that generates arbitrary values of the message, for property tests with `proptest`
+                pub fn arbitrary_strategy() -> impl ::proptest::strategy::Strategy<Value = Self> {
+                    Self::_strategy_depth(#ARBITRARY_DEPTH)
+                }
+
+                #[doc(hidden)]
+                #[allow(unused_variables)]
+                pub fn _strategy_depth(#depth: usize) -> ::proptest::strategy::BoxedStrategy<Self> {
+                    use ::proptest::strategy::Strategy as _;
+                    #body
+                }
+            }
+        })
+    }
+
+    /// Implement `Arbitrary` behind `cfg(fuzzing)`, along with the hidden `_arbitrary_depth`
+    /// constructor that stops generating nested messages once `depth` runs out
+    pub(crate) fn generate_arbitrary_impl(&self, gen: &Generator) -> Option<TokenStream> {
+        if !self.arbitrary {
+            return None;
+        }
+        let msg_mod_name = resolve_path_elem(self.name);
+        let depth = Ident::new("depth", Span::call_site());
+        let mut stmts: Vec<_> = self
+            .fields
+            .iter()
+            .filter_map(|f| f.generate_arbitrary(gen, &depth))
+            .collect();
+        for o in &self.oneofs {
+            let name = &o.san_rust_name;
+            match &o.otype {
+                OneofType::Enum { type_name, .. } => {
+                    let val = gen.wrapped_value(
+                        quote! { #msg_mod_name::#type_name::_arbitrary_depth(u, #depth)? },
+                        o.boxed,
+                        true,
+                    );
+                    stmts.push(quote! {
+                        msg.#name = if u.arbitrary()? {
+                            #val
+                        } else {
+                            ::core::option::Option::None
+                        };
+                    });
+                }
+                OneofType::Custom {
+                    field: CustomField::Type(_),
+                    ..
+                } => stmts.push(quote! { msg.#name = u.arbitrary()?; }),
+                OneofType::Custom { .. } => {}
+            }
+        }
+
+        let name = &self.rust_name;
+        let lifetime = &self.lifetime;
+        let bound = lifetime.as_ref().map(|lt| quote! { : #lt });
+        let msg_mut = (!stmts.is_empty()).then(|| quote! { mut });
+        Some(quote! {
+            #[cfg(fuzzing)]
+            impl<'arbitrary #bound, #lifetime> ::arbitrary::Arbitrary<'arbitrary> for #name<#lifetime> {
+                fn arbitrary(u: &mut ::arbitrary::Unstructured<'arbitrary>) -> ::arbitrary::Result<Self> {
+                    Self::_arbitrary_depth(u, #ARBITRARY_DEPTH)
+                }
+            }
+
+            #[cfg(fuzzing)]
+            impl<#lifetime> #name<#lifetime> {
+                #[doc(hidden)]
+                #[allow(unused_variables)]
+                pub fn _arbitrary_depth<'arbitrary #bound>(u: &mut ::arbitrary::Unstructured<'arbitrary>, #depth: usize) -> ::arbitrary::Result<Self> {
+                    let #msg_mut msg = <Self as ::core::default::Default>::default();
+                    if #depth == 0 {
+                        return ::core::result::Result::Ok(msg);
+                    }
+                    #(#stmts)*
+                    ::core::result::Result::Ok(msg)
+                }
+            }
+        })
+    }
+
+    /// Whether round-trip tests are generated for the message, which needs both the encoding and
+    /// decoding logic and a `Default` impl
+    pub(crate) fn has_roundtrip_test(&self, gen: &Generator) -> bool {
+        gen.roundtrip_tests
+            && gen.encode_decode.is_encode()
+            && gen.encode_decode.is_decode()
+            && self.impl_default
+            && self.lifetime.is_none()
+    }
+
+    /// Generate the hidden `_roundtrip_sample` constructor used by round-trip tests, which sets
+    /// every field with boundary values, or fills every container if `fill` is true, down to
+    /// `depth` levels of nested messages
+    pub(crate) fn generate_sample_impl(&self, gen: &Generator) -> Option<TokenStream> {
+        if !self.has_roundtrip_test(gen) {
+            return None;
+        }
+        let msg_mod_name = resolve_path_elem(self.name);
+        let fill = Ident::new("fill", Span::call_site());
+        let depth = Ident::new("depth", Span::call_site());
+        let mut stmts: Vec<_> = self
+            .fields
+            .iter()
+            .filter_map(|f| f.generate_sample(gen, &fill, &depth))
+            .collect();
+        for o in &self.oneofs {
+            if let OneofType::Enum { type_name, .. } = &o.otype {
+                let name = &o.san_rust_name;
+                let val = gen.wrapped_value(
+                    quote! { #msg_mod_name::#type_name::_roundtrip_sample(#fill, #depth) },
+                    o.boxed,
+                    true,
+                );
+                stmts.push(quote! { msg.#name = #val; });
+            }
+        }
+
+        let name = &self.rust_name;
+        let msg_mut = (!stmts.is_empty()).then(|| quote! { mut });
+        Some(quote! {
+            #[cfg(test)]
+            impl #name {
+                #[doc(hidden)]
+                #[allow(unused_variables)]
+                pub fn _roundtrip_sample(#fill: bool, #depth: usize) -> Self {
+                    let #msg_mut msg = <Self as ::core::default::Default>::default();
+                    if #depth == 0 {
+                        return msg;
+                    }
+                    #(#stmts)*
+                    msg
+                }
+            }
+        })
+    }
 
-                        #[doc = #setter_doc]
-                        #[inline]
-                        pub fn #setter_name(&mut self, value: #type_name) {
-                            self._has.#setter_name();
-                            self.#fname = value.into();
-                        }
+    /// Generate the round-trip test of the message, which goes in the `roundtrip_tests` module at
+    /// the root of the output. `path` is the path of the message relative to that module.
+    pub(crate) fn generate_roundtrip_test(
+        &self,
+        gen: &Generator,
+        path: TokenStream,
+        test_name: &Ident,
+    ) -> Option<TokenStream> {
+        if !self.has_roundtrip_test(gen) {
+            return None;
+        }
+        // Without `PartialEq`, only the sizes are checked
+        let check = if self.derive_partial_eq {
+            quote! {
+                let decoded = round_trip(&msg);
+                assert!(decoded == msg, "decoded message differs from the original");
+            }
+        } else {
+            quote! { round_trip(&msg); }
+        };
+        Some(quote! {
+            #[test]
+            fn #test_name() {
+                for msg in [
+                    <#path as ::core::default::Default>::default(),
+                    #path::_roundtrip_sample(false, #ROUNDTRIP_DEPTH),
+                    #path::_roundtrip_sample(true, #ROUNDTRIP_DEPTH),
+                ] {
+                    #check
+                }
+            }
+        })
+    }
 
-                        #[doc = #clearer_doc]
-                        #[inline]
-                        pub fn #clearer_name(&mut self) {
-                            self._has.#clearer_name();
-                        }
-                    }
-                } else {
-                    let (deref, deref_mut) = if f.boxed {
-                        (format_ident!("as_deref"), format_ident!("as_deref_mut"))
-                    } else {
-                        (format_ident!("as_ref"), format_ident!("as_mut"))
-                    };
-                    quote! {
-                        #[doc = #getter_doc]
-                        #[inline]
-                        pub fn #fname(&self) -> ::core::option::Option<&#type_name> {
-                            self.#fname.#deref()
-                        }
+    /// Generate `TryFrom` impls between the message and its domain type. `error` is the path of
+    /// the generated `DomainError`, and `full_name` is the message's fully-qualified name.
+    pub(crate) fn generate_domain_impls(
+        &self,
+        gen: &Generator,
+        error: TokenStream,
+        full_name: &str,
+    ) -> Option<TokenStream> {
+        let domain = self.domain_type.as_ref()?;
+        let name = &self.rust_name;
+        let err = |field: &str| quote! { |_| #error { message: #full_name, field: #field } };
 
-                        #[doc = #muter_doc]
-                        #[inline]
-                        pub fn #muter_name(&mut self) -> ::core::option::Option<&mut #type_name> {
-                            self.#fname.#deref_mut()
-                        }
+        let mut inits: Vec<_> = self
+            .fields
+            .iter()
+            .filter_map(|f| f.generate_from_domain_init(&err(f.name)))
+            .collect();
+        inits.extend(
+            self.oneofs
+                .iter()
+                .filter_map(|o| o.generate_from_domain_init(&err(o.name))),
+        );
+        let extra = &self.domain_extra_fields;
 
-                        #[doc = #setter_doc]
-                        #[inline]
-                        pub fn #setter_name(&mut self, value: #type_name) {
-                            self.#fname = ::core::option::Option::Some(value.into());
-                        }
+        let mut stmts: Vec<_> = self
+            .fields
+            .iter()
+            .filter_map(|f| f.generate_into_domain_stmt(gen, &err(f.name)))
+            .collect();
+        stmts.extend(
+            self.oneofs
+                .iter()
+                .filter_map(|o| o.generate_into_domain_stmt(gen, &err(o.name))),
+        );
+        let msg_mut = (!stmts.is_empty()).then(|| quote! { mut });
+
+        Some(quote! {
+            impl ::core::convert::TryFrom<#name> for #domain {
+                type Error = #error;
+
+                #[allow(unused_variables)]
+                fn try_from(value: #name) -> ::core::result::Result<Self, Self::Error> {
+                    ::core::result::Result::Ok(Self {
+                        #(#inits)*
+                        #(#extra: ::core::default::Default::default(),)*
+                    })
+                }
+            }
 
-                        #[doc = #clearer_doc]
-                        #[inline]
-                        pub fn #clearer_name(&mut self) {
-                            self.#fname = ::core::option::Option::None;
-                        }
-                    }
+            impl ::core::convert::TryFrom<#domain> for #name {
+                type Error = #error;
+
+                #[allow(unused_variables)]
+                fn try_from(value: #domain) -> ::core::result::Result<Self, Self::Error> {
+                    let #msg_mut msg = <Self as ::core::default::Default>::default();
+                    #(#stmts)*
+                    ::core::result::Result::Ok(msg)
                 }
-            } else {
-                quote! {}
             }
-        });
+        })
+    }
 
+    /// Generate a `From` impl into `prost_type`, the type that `prost-build` generates for the
+    /// message, and a `TryFrom` impl back from it
+    pub(crate) fn generate_prost_impls(
+        &self,
+        gen: &Generator,
+        prost_type: &TokenStream,
+    ) -> TokenStream {
         let name = &self.rust_name;
-        let lifetime = &self.lifetime;
+        let mut to_prost: Vec<_> = self
+            .fields
+            .iter()
+            .map(|f| f.generate_to_prost_stmt(gen))
+            .collect();
+        let mut from_prost: Vec<_> = self
+            .fields
+            .iter()
+            .map(|f| f.generate_from_prost_stmt(gen))
+            .collect();
+        for o in &self.oneofs {
+            let fname = &o.san_rust_name;
+            let pname = prost_ident(o.name, Case::Snake);
+            let deref = o.boxed.then(|| quote! { * });
+            let val = gen.wrapped_value(
+                quote! { ::core::convert::TryFrom::try_from(v)? },
+                o.boxed,
+                true,
+            );
+            to_prost.push(quote! {
+                msg.#pname = value.#fname.map(|v| ::core::convert::Into::into(#deref v));
+            });
+            from_prost.push(quote! {
+                if let ::core::option::Option::Some(v) = value.#pname {
+                    msg.#fname = #val;
+                }
+            });
+        }
+        let to_mut = (!to_prost.is_empty()).then(|| quote! { mut });
+        let from_mut = (!from_prost.is_empty()).then(|| quote! { mut });
+
         quote! {
-            impl<#lifetime> #name<#lifetime> {
-                #(#accessors)*
+            impl ::core::convert::From<#name> for #prost_type {
+                #[allow(unused_variables)]
+                fn from(value: #name) -> Self {
+                    let #to_mut msg = <Self as ::core::default::Default>::default();
+                    #(#to_prost)*
+                    msg
+                }
+            }
+
+            impl ::core::convert::TryFrom<#prost_type> for #name {
+                type Error = ::micropb::ConversionError;
+
+                #[allow(unused_variables)]
+                fn try_from(value: #prost_type) -> ::core::result::Result<Self, Self::Error> {
+                    let #from_mut msg = <Self as ::core::default::Default>::default();
+                    #(#from_prost)*
+                    ::core::result::Result::Ok(msg)
+                }
             }
         }
     }
 
-    pub(crate) fn generate_decode_trait(&self, gen: &Generator) -> TokenStream {
+    pub(crate) fn generate_decode_trait(
+        &self,
+        gen: &Generator,
+        use_hazzer: bool,
+    ) -> io::Result<TokenStream> {
         let name = &self.rust_name;
         let lifetime = &self.lifetime;
         let tag = Ident::new("tag", Span::call_site());
@@ -434,15 +1858,65 @@ impl<'a> Message<'a> {
             .iter()
             .map(|o| o.generate_decode_branches(gen, &mod_name, &tag, &decoder));
 
+        // Only generate the in-place clear if the message has a Default impl, since the clear
+        // logic relies on the same default values
+        let clear_func = if gen.in_place_clear && self.impl_default {
+            let mut field_clears = TokenStream::new();
+            for f in &self.fields {
+                let clear = f
+                    .generate_clear(gen)
+                    .map_err(|e| gen.field_error(self.name, f.name, &e))?;
+                field_clears.extend(clear);
+            }
+            let oneof_clears = self.oneofs.iter().map(|o| o.generate_clear());
+            let hazzer_clear =
+                use_hazzer.then(|| quote! { self._has = ::core::default::Default::default(); });
+            let unknown_clear = self
+                .unknown_handler
+                .as_ref()
+                .map(|_| quote! { self._unknown = ::core::default::Default::default(); });
+            quote! {
+                fn clear(&mut self) {
+                    use ::micropb::{PbContainer, PbMap};
+
+                    #field_clears
+                    #(#oneof_clears)*
+                    #hazzer_clear
+                    #unknown_clear
+                }
+            }
+        } else {
+            quote! {}
+        };
+
+        let visitor_use = self
+            .visitor
+            .as_ref()
+            .map(|_| quote! { use #mod_name::_Visitor as _; });
+
         let unknown_branch = if self.unknown_handler.is_some() {
             // If the unknown handler can't handle a field, skip it
-            quote! { if !self._unknown.decode_field(#tag, #decoder)? { #decoder.skip_wire_value(#tag.wire_type())?; } }
+            quote! { if !self._unknown.decode_field(#tag, #decoder)? { #decoder.skip_unknown_field(#tag)?; } }
         } else {
-            quote! { #decoder.skip_wire_value(#tag.wire_type())?; }
+            quote! { #decoder.skip_unknown_field(#tag)?; }
         };
 
-        quote! {
+        let (field_start, record_field) = if gen.field_stats {
+            (
+                quote! { let field_start = #decoder.bytes_read(); },
+                quote! { #decoder.record_field(#tag.field_num(), field_start); },
+            )
+        } else {
+            (quote! {}, quote! {})
+        };
+
+        let inline = gen.message_inline_attr();
+        let cfg = gen.decode_cfg_attr();
+
+        Ok(quote! {
+            #cfg
             impl<#lifetime> ::micropb::MessageDecode for #name<#lifetime> {
+                #inline
                 fn decode<IMPL_MICROPB_READ: ::micropb::PbRead>(
                     &mut self,
                     #decoder: &mut ::micropb::PbDecoder<IMPL_MICROPB_READ>,
@@ -450,9 +1924,12 @@ impl<'a> Message<'a> {
                 ) -> Result<(), ::micropb::DecodeError<IMPL_MICROPB_READ::Error>>
                 {
                     use ::micropb::{PbVec, PbMap, PbString, FieldDecode};
+                    #visitor_use
 
+                    #decoder.check_size_limit(len)?;
                     let before = #decoder.bytes_read();
                     while #decoder.bytes_read() - before < len {
+                        #field_start
                         let #tag = #decoder.decode_tag()?;
                         match #tag.field_num() {
                             0 => return Err(::micropb::DecodeError::ZeroField),
@@ -460,14 +1937,91 @@ impl<'a> Message<'a> {
                             #(#oneof_branches)*
                             _ => { #unknown_branch }
                         }
+                        #record_field
                     }
                     Ok(())
                 }
+
+                #clear_func
+            }
+        })
+    }
+
+    /// Fields and oneof variants of the message in order of field number. Custom oneofs are
+    /// positioned at their lowest field number.
+    fn canonical_items(&self) -> Vec<(u32, EncodeItem<'_, 'a>)> {
+        let fields = self.fields.iter().map(|f| (f.num, EncodeItem::Field(f)));
+        let oneofs = self.oneofs.iter().flat_map(|o| match &o.otype {
+            OneofType::Enum { fields, .. } => fields
+                .iter()
+                .map(|f| (f.num, EncodeItem::Variant(o, f)))
+                .collect::<Vec<_>>(),
+            OneofType::Custom { nums, .. } => {
+                let num = nums.iter().min().copied().unwrap_or(0) as u32;
+                vec![(num, EncodeItem::CustomOneof(o))]
+            }
+        });
+        let mut items: Vec<_> = fields.chain(oneofs).collect();
+        items.sort_by_key(|(num, _)| *num);
+        items
+    }
+
+    /// Generate encoding logic that writes everything in order of field number, with the unknown
+    /// fields in between
+    fn generate_canonical_encode(
+        &self,
+        gen: &Generator,
+        encoder: &Ident,
+        reverse: bool,
+    ) -> TokenStream {
+        let mod_name = resolve_path_elem(self.name);
+        let func_type = EncodeFunc::Encode(encoder.clone());
+        let mut items: Vec<_> = self
+            .canonical_items()
+            .into_iter()
+            .map(|(num, item)| {
+                let code = match item {
+                    EncodeItem::Field(f) if reverse => f.generate_encode_reverse(gen, encoder),
+                    EncodeItem::Field(f) => f.generate_encode(gen, &func_type),
+                    EncodeItem::Variant(o, f) => {
+                        o.generate_encode_variant(f, gen, &mod_name, encoder, reverse)
+                    }
+                    EncodeItem::CustomOneof(o) if reverse => {
+                        o.generate_encode_reverse(gen, &mod_name, encoder)
+                    }
+                    EncodeItem::CustomOneof(o) => o.generate_encode(gen, &mod_name, &func_type),
+                };
+                (num, code)
+            })
+            .collect();
+
+        if reverse {
+            // Unknown fields can't be encoded back-to-front in order, so messages with unknown
+            // handlers don't generate this
+            items.reverse();
+            let code = items.into_iter().map(|(_, code)| code);
+            return quote! { #(#code)* };
+        }
+
+        let mut code = vec![];
+        let mut next_num = 1u32;
+        for (num, item_code) in items {
+            if self.unknown_handler.is_some() && num > next_num {
+                code.push(quote! { self._unknown.encode_fields_in(#encoder, #next_num..#num)?; });
             }
+            code.push(item_code);
+            next_num = next_num.max(num + 1);
+        }
+        if self.unknown_handler.is_some() {
+            code.push(quote! { self._unknown.encode_fields_in(#encoder, #next_num..::core::primitive::u32::MAX)?; });
         }
+        quote! { #(#code)* }
     }
 
     fn generate_encode_func(&self, gen: &Generator, func_type: &EncodeFunc) -> TokenStream {
+        if let (true, EncodeFunc::Encode(encoder)) = (self.canonical_order, func_type) {
+            return self.generate_canonical_encode(gen, encoder, false);
+        }
         let mod_name = resolve_path_elem(self.name);
 
         let field_logic = self
@@ -482,7 +2036,7 @@ impl<'a> Message<'a> {
         let unknown_logic = if self.unknown_handler.is_some() {
             match func_type {
                 EncodeFunc::Sizeof(size) => {
-                    quote! { #size += self._unknown.compute_fields_size(); }
+                    quote! { #size = #size.saturating_add(self._unknown.compute_fields_size()); }
                 }
                 EncodeFunc::Encode(encoder) => quote! { self._unknown.encode_fields(#encoder)?; },
             }
@@ -497,9 +2051,90 @@ impl<'a> Message<'a> {
         }
     }
 
+    fn max_size(&self, gen: &Generator) -> Option<MaxSize> {
+        // Unknown fields aren't bounded by anything
+        if self.unknown_handler.is_some() {
+            return None;
+        }
+        let fields = self.fields.iter().map(|f| f.max_size(gen));
+        let oneofs = self.oneofs.iter().map(|o| o.max_size(gen));
+        fields
+            .chain(oneofs)
+            .try_fold(MaxSize::Known(0), |total, size| Some(total.add(size?)))
+    }
+
+    /// Generate `encode_reverse` if enabled, which writes the fields in the reverse of their
+    /// encoding order. Visited fields can only be iterated forwards, so messages with a visitor
+    /// use the default implementation instead.
+    fn generate_encode_reverse(&self, gen: &Generator) -> Option<TokenStream> {
+        if !gen.encode_reverse || self.visitor.is_some() {
+            return None;
+        }
+        let mod_name = resolve_path_elem(self.name);
+        let encoder = Ident::new("encoder", Span::call_site());
+        let encode_logic = if self.canonical_order {
+            // Unknown fields may need to be interleaved with the other fields, which can only be
+            // done going forwards
+            if self.unknown_handler.is_some() {
+                return None;
+            }
+            self.generate_canonical_encode(gen, &encoder, true)
+        } else {
+            self.generate_encode_reverse_fields(gen, &mod_name, &encoder)
+        };
+
+        let inline = gen.message_inline_attr();
+
+        Some(quote! {
+            #inline
+            fn encode_reverse(
+                &self,
+                encoder: &mut ::micropb::ReverseEncoder<'_>,
+            ) -> Result<(), ::micropb::BufferTooSmall>
+            {
+                use ::micropb::{PbVec, PbMap, PbString, FieldEncode};
+                #encode_logic
+                Ok(())
+            }
+        })
+    }
+
+    fn generate_encode_reverse_fields(
+        &self,
+        gen: &Generator,
+        mod_name: &Ident,
+        encoder: &Ident,
+    ) -> TokenStream {
+        let unknown_logic = self
+            .unknown_handler
+            .is_some()
+            .then(|| quote! { self._unknown.encode_fields_reverse(#encoder)?; });
+        let oneof_logic = self
+            .oneofs
+            .iter()
+            .rev()
+            .map(|o| o.generate_encode_reverse(gen, mod_name, encoder));
+        let field_logic = self
+            .fields
+            .iter()
+            .rev()
+            .map(|f| f.generate_encode_reverse(gen, encoder));
+
+        quote! {
+            #unknown_logic
+            #(#oneof_logic)*
+            #(#field_logic)*
+        }
+    }
+
     pub(crate) fn generate_encode_trait(&self, gen: &Generator) -> TokenStream {
         let name = &self.rust_name;
         let lifetime = &self.lifetime;
+        let mod_name = resolve_path_elem(self.name);
+        let visitor_use = self
+            .visitor
+            .as_ref()
+            .map(|_| quote! { use #mod_name::_Visitor as _; });
         let sizeof = self.generate_encode_func(
             gen,
             &EncodeFunc::Sizeof(Ident::new("size", Span::call_site())),
@@ -508,36 +2143,101 @@ impl<'a> Message<'a> {
             gen,
             &EncodeFunc::Encode(Ident::new("encoder", Span::call_site())),
         );
+        let max_size = gen.max_size.then(|| {
+            let max_size = MaxSize::generate(self.max_size(gen));
+            quote! { const MAX_SIZE: ::core::option::Option<usize> = #max_size; }
+        });
+        let encode_reverse = self.generate_encode_reverse(gen);
+        let inline = gen.message_inline_attr();
+        let cfg = gen.encode_cfg_attr();
+
+        // In dyn mode, the encoding logic lives in an inherent method that's only compiled once
+        let (encode, dyn_encode) = if gen.dyn_encode {
+            let dyn_encode = quote! {
+                #cfg
+                impl<#lifetime> #name<#lifetime> {
+                    #[doc(hidden)]
+                    #inline
+                    fn _micropb_encode_dyn(
+                        &self,
+                        encoder: &mut ::micropb::PbEncoder<&mut ::micropb::DynPbWrite<'_>>,
+                    ) -> Result<(), ::micropb::EncodeError<::micropb::DynWriteError>>
+                    {
+                        use ::micropb::{PbVec, PbMap, PbString, FieldEncode, MessageEncode as _};
+                        #visitor_use
+                        #encode
+                        Ok(())
+                    }
+                }
+            };
+            let encode = quote! {
+                encoder.encode_dyn(|encoder| self._micropb_encode_dyn(encoder))
+            };
+            (encode, dyn_encode)
+        } else {
+            let encode = quote! {
+                use ::micropb::{PbVec, PbMap, PbString, FieldEncode};
+                #visitor_use
+                #encode
+                Ok(())
+            };
+            (encode, quote! {})
+        };
 
         quote! {
+            #dyn_encode
+
+            #cfg
             impl<#lifetime> ::micropb::MessageEncode for #name<#lifetime> {
+                #max_size
+
+                #inline
                 fn encode<IMPL_MICROPB_WRITE: ::micropb::PbWrite>(
                     &self,
                     encoder: &mut ::micropb::PbEncoder<IMPL_MICROPB_WRITE>,
-                ) -> Result<(), IMPL_MICROPB_WRITE::Error>
+                ) -> Result<(), ::micropb::EncodeError<IMPL_MICROPB_WRITE::Error>>
                 {
-                    use ::micropb::{PbVec, PbMap, PbString, FieldEncode};
                     #encode
-                    Ok(())
                 }
 
+                #inline
                 fn compute_size(&self) -> usize {
                     use ::micropb::{PbVec, PbMap, PbString, FieldEncode};
-                    let mut size = 0;
+                    #visitor_use
+                    let mut size = 0usize;
                     #sizeof
                     size
                 }
+
+                #encode_reverse
             }
         }
     }
 }
 
+/// Combine strategies and their patterns into nested tuples, since `proptest` only implements
+/// `Strategy` for tuples of up to 12 elements
+fn nest_strategies(mut items: Vec<(TokenStream, TokenStream)>) -> (TokenStream, TokenStream) {
+    let tuple = |items: &[(TokenStream, TokenStream)]| {
+        if let [item] = items {
+            return item.clone();
+        }
+        let strategies = items.iter().map(|(s, _)| s);
+        let pats = items.iter().map(|(_, p)| p);
+        (quote! { (#(#strategies,)*) }, quote! { (#(#pats,)*) })
+    };
+    while items.len() > 10 {
+        items = items.chunks(10).map(tuple).collect();
+    }
+    tuple(&items)
+}
+
 #[cfg(test)]
 mod tests {
     use std::borrow::Cow;
 
     use crate::{
-        config::{parse_attributes, Config, IntSize, OptionalRepr},
+        config::{parse_attributes, Config, IntSize, OptionalRepr, OverflowPolicy},
         descriptor::{
             FieldDescriptorProto,
             FieldDescriptorProto_::{Label, Type},
@@ -636,6 +2336,28 @@ mod tests {
             .is_none());
     }
 
+    #[test]
+    fn from_proto_no_alloc() {
+        let mut gen = Generator::new();
+        gen.target(Target::NoAlloc);
+        let proto = test_msg_proto();
+        let config = Box::new(Config::new().map_type("Map"));
+        let msg_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        assert!(Message::from_proto(&proto, &gen, &msg_conf).is_ok());
+
+        let mut node = Node::default();
+        *node.add_path(std::iter::once("oneof_field")).value_mut() =
+            Some(Box::new(Config::new().boxed(true)));
+        let msg_conf = CurrentConfig {
+            node: Some(&node),
+            config: Cow::Borrowed(&config),
+        };
+        assert!(Message::from_proto(&proto, &gen, &msg_conf).is_err());
+    }
+
     #[test]
     fn from_proto_skip_fields() {
         let gen = Generator::new();
@@ -651,7 +2373,21 @@ mod tests {
             derive_clone: true,
             attrs: vec![],
             unknown_handler: None,
+            visitor: None,
             lifetime: None,
+            canonical_order: false,
+            serde: false,
+            defmt: false,
+            json: false,
+            field_name_consts: false,
+            field_descriptors: None,
+            text: false,
+            field_mask: false,
+            arbitrary: false,
+            proptest: false,
+            domain_type: None,
+            domain_extra_fields: vec![],
+            prost_path: None,
         };
         let config = Box::new(Config::new());
         let mut node = Node::default();
@@ -754,6 +2490,11 @@ mod tests {
                     derive_dbg: false,
                     derive_partial_eq: true,
                     derive_clone: true,
+                    serde: false,
+                    defmt: false,
+                    arbitrary: false,
+                    proptest: false,
+                    domain_skip: false,
                     idx: 0
                 }],
                 fields: vec![
@@ -771,7 +2512,9 @@ mod tests {
                             key: TypeSpec::Int(PbInt::Int64, IntSize::S16),
                             val: TypeSpec::Int(PbInt::Uint64, IntSize::S16),
                             type_path: syn::parse_str("Map").unwrap(),
-                            max_len: None
+                            max_len: None,
+                            overflow_policy: OverflowPolicy::Error,
+                            sorted: false
                         }
                     ),
                 ],
@@ -781,7 +2524,21 @@ mod tests {
                 derive_clone: true,
                 attrs: parse_attributes("#[derive(Self)]").unwrap(),
                 unknown_handler: Some(syn::parse_str("UnknownType").unwrap()),
-                lifetime: None
+                visitor: None,
+                lifetime: None,
+                canonical_order: false,
+                serde: false,
+                defmt: false,
+                json: false,
+                field_name_consts: false,
+                field_descriptors: None,
+                text: false,
+                field_mask: false,
+                arbitrary: false,
+                proptest: false,
+                domain_type: None,
+                domain_extra_fields: vec![],
+                prost_path: None
             }
         )
     }
@@ -832,11 +2589,78 @@ mod tests {
                 derive_clone: true,
                 attrs: vec![],
                 unknown_handler: None,
-                lifetime: None
+                visitor: None,
+                lifetime: None,
+                canonical_order: false,
+                serde: false,
+                defmt: false,
+                json: false,
+                field_name_consts: false,
+                field_descriptors: None,
+                text: false,
+                field_mask: false,
+                arbitrary: false,
+                proptest: false,
+                domain_type: None,
+                domain_extra_fields: vec![],
+                prost_path: None
             }
         )
     }
 
+    #[test]
+    fn oneof_variant_path() {
+        let gen = Generator::new();
+        let mut proto = DescriptorProto::default();
+        proto.set_name("Msg".to_owned());
+        for (num, name, typ) in [(1, "num", Type::Sint32), (2, "flt", Type::Float)] {
+            let mut f = FieldDescriptorProto::default();
+            f.set_number(num);
+            f.set_name(name.to_owned());
+            f.set_type(typ);
+            f.set_label(Label::Optional);
+            f.set_oneof_index(0);
+            proto.field.push(f);
+        }
+        proto.oneof_decl.push({
+            let mut o = OneofDescriptorProto::default();
+            o.set_name("kind".to_owned());
+            o
+        });
+
+        let config = Box::new(Config::new());
+        let mut node = Node::default();
+        // Boxing the oneof doesn't box its variants
+        *node.add_path(["kind"].into_iter()).value_mut() =
+            Some(Box::new(Config::new().boxed(true)));
+        *node.add_path(["num"].into_iter()).value_mut() =
+            Some(Box::new(Config::new().rename_field("number")));
+        *node.add_path(["kind", "num"].into_iter()).value_mut() =
+            Some(Box::new(Config::new().int_size(IntSize::S8)));
+        *node.add_path(["kind", "flt"].into_iter()).value_mut() =
+            Some(Box::new(Config::new().boxed(true)));
+        let msg_conf = CurrentConfig {
+            node: Some(&node),
+            config: Cow::Borrowed(&config),
+        };
+
+        let msg = Message::from_proto(&proto, &gen, &msg_conf)
+            .unwrap()
+            .unwrap();
+        assert!(msg.oneofs[0].boxed);
+        let OneofType::Enum { fields, .. } = &msg.oneofs[0].otype else {
+            panic!("oneof should be an enum");
+        };
+        let mut num =
+            make_test_oneof_field(1, "num", false, TypeSpec::Int(PbInt::Sint32, IntSize::S8));
+        // Renamed through the message path, narrowed through the oneof path
+        num.rust_name = Ident::new("Number", Span::call_site());
+        assert_eq!(
+            fields,
+            &vec![num, make_test_oneof_field(2, "flt", true, TypeSpec::Float)]
+        );
+    }
+
     #[test]
     fn hazzer_empty() {
         let config = CurrentConfig {
@@ -862,8 +2686,87 @@ mod tests {
             derive_clone: true,
             attrs: vec![],
             unknown_handler: None,
+            visitor: None,
+            lifetime: None,
+            canonical_order: false,
+            serde: false,
+            defmt: false,
+            json: false,
+            field_name_consts: false,
+            field_descriptors: None,
+            text: false,
+            field_mask: false,
+            arbitrary: false,
+            proptest: false,
+            domain_type: None,
+            domain_extra_fields: vec![],
+            prost_path: None,
+        };
+        assert!(msg
+            .generate_hazzer_decl(&Generator::new(), config)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn opt_in_items() {
+        let msg = Message {
+            name: "msg",
+            rust_name: Ident::new("msg", Span::call_site()),
+            oneofs: vec![],
+            fields: vec![make_test_field(
+                4,
+                "field4",
+                true,
+                FieldType::Optional(TypeSpec::Bool, OptionalRepr::Option),
+            )],
+            derive_dbg: true,
+            impl_default: true,
+            derive_partial_eq: true,
+            derive_clone: true,
+            attrs: vec![],
+            unknown_handler: None,
+            visitor: None,
             lifetime: None,
+            canonical_order: false,
+            serde: false,
+            defmt: false,
+            json: false,
+            field_name_consts: false,
+            field_descriptors: None,
+            text: false,
+            field_mask: false,
+            arbitrary: false,
+            proptest: false,
+            domain_type: None,
+            domain_extra_fields: vec![],
+            prost_path: None,
         };
-        assert!(msg.generate_hazzer_decl(config).unwrap().is_none());
+        let generated = |gen: &Generator| {
+            format!(
+                "{} {} {}",
+                msg.generate_impl(gen),
+                msg.generate_decode_trait(gen, false).unwrap(),
+                msg.generate_encode_trait(gen)
+            )
+        };
+
+        // None of the optional items are generated by default
+        let out = generated(&Generator::new());
+        assert!(!out.contains("missing_fields"));
+        assert!(!out.contains("fn clear ("));
+        assert!(!out.contains("MAX_SIZE"));
+        assert!(!out.contains("encode_reverse"));
+
+        let mut gen = Generator::new();
+        gen.missing_fields(true)
+            .in_place_clear(true)
+            .max_size(true)
+            .encode_reverse(true);
+        let out = generated(&gen);
+        assert!(out.contains("missing_fields"));
+        assert!(out.contains("fn clear ("));
+        assert!(out.contains("MAX_SIZE"));
+        assert!(out.contains("encode_reverse"));
     }
 }
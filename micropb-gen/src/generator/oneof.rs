@@ -4,24 +4,26 @@ use quote::quote;
 use syn::{Ident, Lifetime};
 
 use super::{
-    derive_msg_attr,
-    field::CustomField,
-    sanitized_ident,
-    type_spec::{find_lifetime_from_type, TypeSpec},
-    CurrentConfig, EncodeFunc, Generator,
+    derive_defmt_attr, derive_msg_attr,
+    field::{json_key_pattern, text_parse_method, CustomField},
+    prost_ident, sanitized_ident,
+    type_spec::{find_lifetime_from_type, TypeSpec, ARBITRARY_DEPTH},
+    CurrentConfig, EncodeFunc, Generator, MaxSize,
 };
 
 use crate::descriptor::{FieldDescriptorProto, OneofDescriptorProto};
+use crate::utils::json_name;
 
 #[cfg_attr(test, derive(Debug, PartialEq, Eq))]
 pub(crate) struct OneofField<'a> {
     pub(crate) num: u32,
     pub(crate) tspec: TypeSpec,
-    #[allow(unused)]
     /// Protobuf name
     pub(crate) name: &'a str,
     /// Sanitized Rust ident after renaming, used for field name
     pub(crate) rust_name: Ident,
+    /// JSON name, used as the key when writing JSON
+    pub(crate) json_name: String,
     pub(crate) boxed: bool,
     pub(crate) attrs: Vec<syn::Attribute>,
 }
@@ -46,6 +48,9 @@ impl<'a> OneofField<'a> {
         );
         let num = proto.number as u32;
         let tspec = TypeSpec::from_proto(proto, field_conf)?;
+        if matches!(tspec, TypeSpec::BytesRange) {
+            return Err("bytes_range is only supported for singular bytes fields".to_owned());
+        }
         let attrs = field_conf.config.field_attr_parsed()?;
 
         Ok(Some(OneofField {
@@ -53,6 +58,10 @@ impl<'a> OneofField<'a> {
             tspec,
             name,
             rust_name,
+            json_name: proto
+                .json_name()
+                .cloned()
+                .unwrap_or_else(|| json_name(name)),
             boxed: field_conf.config.boxed.unwrap_or(false),
             attrs,
         }))
@@ -71,37 +80,61 @@ impl<'a> OneofField<'a> {
         oneof_type: &TokenStream,
         oneof_boxed: bool,
         gen: &Generator,
+        tag: &Ident,
         decoder: &Ident,
     ) -> TokenStream {
         let fnum = self.num;
         let mut_ref = Ident::new("mut_ref", Span::call_site());
+        let wire_type = self.tspec.wire_type();
         let variant_name = &self.rust_name;
         let extra_deref_of = oneof_boxed.then(|| quote! { * });
         let extra_deref_var = self.boxed.then(|| quote! { * });
 
-        let decode_stmts = self
-            .tspec
-            .generate_decode_mut(gen, false, decoder, &mut_ref);
+        let decode_stmts = self.tspec.generate_decode_field_mut(gen, decoder, &mut_ref);
         let value = gen.wrapped_value(
             quote! { #oneof_type::#variant_name(::core::default::Default::default()) },
             oneof_boxed,
             true,
         );
+        let mut decode_code = quote! {
+            let #mut_ref = loop {
+                if let ::core::option::Option::Some(variant) = &mut self.#oneof_name {
+                    if let #oneof_type::#variant_name(variant) = &mut #extra_deref_of *variant {
+                        break &mut #extra_deref_var *variant;
+                    }
+                }
+                ::micropb::__private::set_outlined(&mut self.#oneof_name, || #value);
+            };
+            #decode_stmts;
+        };
+        // Unknown enum values are dropped before the oneof is touched, so that the oneof keeps
+        // its previous variant
+        if self.tspec.drops_unknown_enum() {
+            let val = self.tspec.generate_decode_val(gen, decoder).unwrap();
+            decode_code = quote! {
+                let val = #val?;
+                if val.is_known() { #decode_code }
+            };
+        }
         quote! {
             #fnum => {
-                let #mut_ref = loop {
-                    if let ::core::option::Option::Some(variant) = &mut self.#oneof_name {
-                        if let #oneof_type::#variant_name(variant) = &mut #extra_deref_of *variant {
-                            break &mut #extra_deref_var *variant;
-                        }
-                    }
-                    self.#oneof_name = #value;
-                };
-                #decode_stmts;
+                if #tag.wire_type() != #wire_type {
+                    return Err(::micropb::DecodeError::WrongWireType);
+                }
+                #decode_code
             }
         }
     }
 
+    fn max_size(&self, gen: &Generator) -> Option<MaxSize> {
+        // Boxed variants can be recursive, so the size of the boxed type isn't referenced
+        if self.boxed {
+            return None;
+        }
+        let tag = micropb::Tag::from_parts(self.num, self.tspec.wire_type());
+        Some(MaxSize::Known(::micropb::size::sizeof_tag(tag)).add(self.tspec.max_size(gen)?))
+    }
+
     fn generate_encode_branch(
         &self,
         oneof_type: &TokenStream,
@@ -119,7 +152,7 @@ impl<'a> OneofField<'a> {
         let stmts = match &func_type {
             EncodeFunc::Sizeof(size) => {
                 let sizeof_expr = self.tspec.generate_sizeof(gen, &val_ref);
-                quote! { #size += #tag_len + #sizeof_expr; }
+                quote! { #size = #size.saturating_add(#tag_len).saturating_add(#sizeof_expr); }
             }
             EncodeFunc::Encode(encoder) => {
                 let encode_expr = self.tspec.generate_encode_expr(gen, encoder, &val_ref);
@@ -137,6 +170,29 @@ impl<'a> OneofField<'a> {
             }
         }
     }
+
+    fn generate_encode_reverse_branch(
+        &self,
+        oneof_type: &TokenStream,
+        gen: &Generator,
+        encoder: &Ident,
+    ) -> TokenStream {
+        let val_ref = Ident::new("val_ref", Span::call_site());
+        let variant_name = &self.rust_name;
+        let extra_deref = self.boxed.then(|| quote! { * });
+        let tag_val = micropb::Tag::from_parts(self.num, self.tspec.wire_type()).varint();
+        let encode_expr = self
+            .tspec
+            .generate_encode_reverse_expr(gen, encoder, &val_ref);
+
+        quote! {
+            #oneof_type::#variant_name(#val_ref) => {
+                let #val_ref = &* #extra_deref #val_ref;
+                #encode_expr?;
+                #encoder.encode_varint32(#tag_val)?;
+            }
+        }
+    }
 }
 
 #[cfg_attr(test, derive(Debug, PartialEq, Eq))]
@@ -165,6 +221,16 @@ pub(crate) struct Oneof<'a> {
     pub(crate) derive_dbg: bool,
     pub(crate) derive_partial_eq: bool,
     pub(crate) derive_clone: bool,
+    /// Derive the Serde traits
+    pub(crate) serde: bool,
+    /// Derive `defmt::Format` behind the `defmt` feature
+    pub(crate) defmt: bool,
+    /// Implement `Arbitrary` when fuzzing
+    pub(crate) arbitrary: bool,
+    /// Generate `proptest` strategies
+    pub(crate) proptest: bool,
+    /// Leave the oneof out of the conversions to and from the domain type
+    pub(crate) domain_skip: bool,
     pub(crate) idx: usize,
 }
 
@@ -214,6 +280,11 @@ impl<'a> Oneof<'a> {
             derive_dbg: oneof_conf.derive_dbg(),
             derive_partial_eq: oneof_conf.derive_partial_eq(),
             derive_clone: oneof_conf.derive_clone(),
+            serde: oneof_conf.config.serde.unwrap_or(false),
+            defmt: oneof_conf.config.defmt.unwrap_or(false),
+            arbitrary: oneof_conf.config.arbitrary.unwrap_or(false),
+            proptest: oneof_conf.config.proptest.unwrap_or(false),
+            domain_skip: oneof_conf.config.domain_skip.unwrap_or(false),
             field_attrs,
             type_attrs,
         }))
@@ -229,10 +300,16 @@ impl<'a> Oneof<'a> {
                 self.derive_partial_eq,
                 self.derive_clone,
             );
+            let derive_serde = self
+                .serde
+                .then(|| quote! { #[derive(::serde::Serialize, ::serde::Deserialize)] });
+            let derive_defmt = derive_defmt_attr(self.defmt);
             let attrs = &self.type_attrs;
 
             quote! {
                 #derive_msg
+                #derive_serde
+                #derive_defmt
                 #(#attrs)*
                 pub enum #type_name {
                     #(#fields)*
@@ -243,25 +320,64 @@ impl<'a> Oneof<'a> {
         }
     }
 
-    pub(crate) fn generate_field(&self, gen: &Generator, msg_mod_name: &Ident) -> TokenStream {
-        let name = &self.san_rust_name;
-        let oneof_type = match &self.otype {
+    /// Compile-time checks that the containers of the oneof's variants can hold their configured
+    /// bounds
+    pub(crate) fn generate_capacity_asserts(&self, gen: &Generator, msg_name: &str) -> TokenStream {
+        let OneofType::Enum { fields, .. } = &self.otype else {
+            return quote! {};
+        };
+        let asserts = fields.iter().filter_map(|f| {
+            f.tspec
+                .generate_capacity_assert(gen, &format!("{msg_name}.{}", f.name))
+        });
+        quote! { #(#asserts)* }
+    }
+
+    /// Type of the oneof's field in the message struct, if it has one
+    pub(crate) fn generate_rust_type(
+        &self,
+        gen: &Generator,
+        msg_mod_name: &Ident,
+    ) -> Option<TokenStream> {
+        match &self.otype {
             OneofType::Enum { type_name, .. } => {
-                gen.wrapped_type(quote! { #msg_mod_name::#type_name }, self.boxed, true)
+                Some(gen.wrapped_type(quote! { #msg_mod_name::#type_name }, self.boxed, true))
             }
             OneofType::Custom {
                 field: CustomField::Type(type_path),
                 ..
-            } => quote! { #type_path },
+            } => Some(quote! { #type_path }),
             OneofType::Custom {
                 field: CustomField::Delegate(_),
                 ..
-            } => return quote! {},
+            } => None,
+        }
+    }
+
+    pub(crate) fn generate_field(&self, gen: &Generator, msg_mod_name: &Ident) -> TokenStream {
+        let name = &self.san_rust_name;
+        let Some(oneof_type) = self.generate_rust_type(gen, msg_mod_name) else {
+            return quote! {};
         };
         let attrs = &self.field_attrs;
         quote! { #(#attrs)* pub #name: #oneof_type, }
     }
 
+    pub(crate) fn generate_clear(&self) -> TokenStream {
+        let name = &self.san_rust_name;
+        match &self.otype {
+            OneofType::Enum { .. } => quote! { self.#name = ::core::option::Option::None; },
+            OneofType::Custom {
+                field: CustomField::Type(_),
+                ..
+            } => quote! { self.#name = ::core::default::Default::default(); },
+            OneofType::Custom {
+                field: CustomField::Delegate(_),
+                ..
+            } => quote! {},
+        }
+    }
+
     pub(crate) fn generate_decode_branches(
         &self,
         gen: &Generator,
@@ -273,9 +389,9 @@ impl<'a> Oneof<'a> {
         match &self.otype {
             OneofType::Enum { fields, type_name } => {
                 let oneof_type = quote! { #msg_mod_name::#type_name };
-                let branches = fields
-                    .iter()
-                    .map(|f| f.generate_decode_branch(name, &oneof_type, self.boxed, gen, decoder));
+                let branches = fields.iter().map(|f| {
+                    f.generate_decode_branch(name, &oneof_type, self.boxed, gen, tag, decoder)
+                });
                 quote! {
                     #(#branches)*
                 }
@@ -301,6 +417,27 @@ impl<'a> Oneof<'a> {
         }
     }
 
+    /// Maximum size of the oneof on the wire, which is the maximum size of its variants. Returns
+    /// `None` if the size is unbounded.
+    pub(crate) fn max_size(&self, gen: &Generator) -> Option<MaxSize> {
+        if self.boxed {
+            return None;
+        }
+        match &self.otype {
+            OneofType::Enum { fields, .. } => fields
+                .iter()
+                .try_fold(MaxSize::Known(0), |max, f| Some(max.max(f.max_size(gen)?))),
+            OneofType::Custom {
+                field: CustomField::Type(_),
+                ..
+            } => None,
+            OneofType::Custom {
+                field: CustomField::Delegate(_),
+                ..
+            } => Some(MaxSize::Known(0)),
+        }
+    }
+
     pub(crate) fn generate_encode(
         &self,
         gen: &Generator,
@@ -328,7 +465,9 @@ impl<'a> Oneof<'a> {
                 field: CustomField::Type(_),
                 ..
             } => match &func_type {
-                EncodeFunc::Sizeof(size) => quote! { #size += self.#name.compute_fields_size(); },
+                EncodeFunc::Sizeof(size) => {
+                    quote! { #size = #size.saturating_add(self.#name.compute_fields_size()); }
+                }
                 EncodeFunc::Encode(encoder) => quote! { self.#name.encode_fields(#encoder)?; },
             },
 
@@ -338,6 +477,510 @@ impl<'a> Oneof<'a> {
             } => quote! {},
         }
     }
+
+    /// Generate logic that encodes a single variant of the oneof if it's set, so that each variant
+    /// can be encoded at the position of its own field number
+    pub(crate) fn generate_encode_variant(
+        &self,
+        variant: &OneofField,
+        gen: &Generator,
+        msg_mod_name: &Ident,
+        encoder: &Ident,
+        reverse: bool,
+    ) -> TokenStream {
+        let OneofType::Enum { type_name, .. } = &self.otype else {
+            unreachable!("custom oneofs have no variants");
+        };
+        let name = &self.san_rust_name;
+        let oneof_type = quote! { #msg_mod_name::#type_name };
+        let extra_deref = self.boxed.then(|| quote! { * });
+        let branch = if reverse {
+            variant.generate_encode_reverse_branch(&oneof_type, gen, encoder)
+        } else {
+            variant.generate_encode_branch(&oneof_type, gen, &EncodeFunc::Encode(encoder.clone()))
+        };
+        quote! {
+            if let Some(oneof) = & self.#name {
+                match &#extra_deref *oneof {
+                    #branch
+                    #[allow(unreachable_patterns)]
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    pub(crate) fn generate_encode_reverse(
+        &self,
+        gen: &Generator,
+        msg_mod_name: &Ident,
+        encoder: &Ident,
+    ) -> TokenStream {
+        let name = &self.san_rust_name;
+        match &self.otype {
+            OneofType::Enum { type_name, fields } => {
+                let oneof_type = quote! { #msg_mod_name::#type_name };
+                let extra_deref = self.boxed.then(|| quote! { * });
+                let branches = fields
+                    .iter()
+                    .map(|f| f.generate_encode_reverse_branch(&oneof_type, gen, encoder));
+                quote! {
+                    if let Some(oneof) = & self.#name {
+                        match &#extra_deref *oneof {
+                            #(#branches)*
+                        }
+                    }
+                }
+            }
+
+            OneofType::Custom {
+                field: CustomField::Type(_),
+                ..
+            } => quote! { self.#name.encode_fields_reverse(#encoder)?; },
+
+            OneofType::Custom {
+                field: CustomField::Delegate(_),
+                ..
+            } => quote! {},
+        }
+    }
+
+    /// Generate code that writes the set variant of the oneof into the JSON object `obj`. Returns
+    /// `None` if the oneof can't be written as JSON.
+    pub(crate) fn generate_json(&self, msg_mod_name: &Ident, obj: &Ident) -> Option<TokenStream> {
+        let OneofType::Enum { type_name, fields } = &self.otype else {
+            return None;
+        };
+        let name = &self.san_rust_name;
+        let oneof_type = quote! { #msg_mod_name::#type_name };
+        let extra_deref = self.boxed.then(|| quote! { * });
+        let val_ref = Ident::new("val_ref", Span::call_site());
+        let writer = Ident::new("w", Span::call_site());
+        let branches = fields.iter().filter_map(|f| {
+            let variant_name = &f.rust_name;
+            let json_name = &f.json_name;
+            let extra_deref_var = f.boxed.then(|| quote! { * });
+            let json_expr = f.tspec.generate_json_expr(&writer, &val_ref)?;
+            Some(quote! {
+                #oneof_type::#variant_name(#val_ref) => {
+                    let #val_ref = &* #extra_deref_var #val_ref;
+                    let #writer = #obj.key(#json_name)?;
+                    #json_expr?;
+                }
+            })
+        });
+        Some(quote! {
+            if let Some(oneof) = &self.#name {
+                match &#extra_deref *oneof {
+                    #(#branches)*
+                    #[allow(unreachable_patterns)]
+                    _ => {}
+                }
+            }
+        })
+    }
+
+    /// Generate the statements that copy each variant of the oneof from `src` for
+    /// `ApplyFieldMask`, along with the Protobuf names of the variants. If `src` holds a
+    /// different variant, the oneof is cleared only if it holds the named variant.
+    pub(crate) fn generate_field_mask_copies(
+        &self,
+        msg_mod_name: &Ident,
+    ) -> Vec<(&'a str, TokenStream)> {
+        let OneofType::Enum { type_name, fields } = &self.otype else {
+            return vec![];
+        };
+        let name = &self.san_rust_name;
+        let oneof_type = quote! { #msg_mod_name::#type_name };
+        let as_ref = if self.boxed {
+            quote! { as_deref }
+        } else {
+            quote! { as_ref }
+        };
+        fields
+            .iter()
+            .map(|f| {
+                let variant_name = &f.rust_name;
+                let pat = quote! { ::core::option::Option::Some(#oneof_type::#variant_name(_)) };
+                let copy = quote! {
+                    if ::core::matches!(src.#name.#as_ref(), #pat) {
+                        self.#name = ::core::clone::Clone::clone(&src.#name);
+                    } else if ::core::matches!(self.#name.#as_ref(), #pat) {
+                        self.#name = ::core::option::Option::None;
+                    }
+                };
+                (f.name, copy)
+            })
+            .collect()
+    }
+
+    /// Implement `Arbitrary` on the oneof enum behind `cfg(fuzzing)`, along with the hidden
+    /// `_arbitrary_depth` constructor that messages use to bound the depth of nested messages
+    pub(crate) fn generate_arbitrary_impl(&self, gen: &Generator) -> Option<TokenStream> {
+        if !self.arbitrary {
+            return None;
+        }
+        let OneofType::Enum { type_name, fields } = &self.otype else {
+            return None;
+        };
+        let depth = Ident::new("depth", Span::call_site());
+        let last = fields.len() - 1;
+        let arms = fields.iter().enumerate().map(|(i, f)| {
+            let variant_name = &f.rust_name;
+            let val = gen.wrapped_value(f.tspec.generate_arbitrary(gen, &depth), f.boxed, false);
+            // The last variant also covers the unreachable indices
+            let pat = if i == last {
+                quote! { _ }
+            } else {
+                let i = Literal::usize_unsuffixed(i);
+                quote! { #i }
+            };
+            quote! { #pat => Self::#variant_name(#val), }
+        });
+        let len = fields.len();
+
+        Some(quote! {
+            #[cfg(fuzzing)]
+            impl<'arbitrary> ::arbitrary::Arbitrary<'arbitrary> for #type_name {
+                fn arbitrary(u: &mut ::arbitrary::Unstructured<'arbitrary>) -> ::arbitrary::Result<Self> {
+                    Self::_arbitrary_depth(u, #ARBITRARY_DEPTH)
+                }
+            }
+
+            #[cfg(fuzzing)]
+            impl #type_name {
+                #[doc(hidden)]
+                #[allow(unused_variables)]
+                pub fn _arbitrary_depth(u: &mut ::arbitrary::Unstructured<'_>, #depth: usize) -> ::arbitrary::Result<Self> {
+                    ::core::result::Result::Ok(match u.choose_index(#len)? {
+                        #(#arms)*
+                    })
+                }
+            }
+        })
+    }
+
+    /// Generate the `arbitrary_strategy` constructor of the oneof enum behind the `proptest`
+    /// feature, along with the hidden `_strategy_depth` constructor that messages use to bound
+    /// the depth of nested messages
+    pub(crate) fn generate_proptest_impl(&self, gen: &Generator) -> Option<TokenStream> {
+        if !self.proptest {
+            return None;
+        }
+        let OneofType::Enum { type_name, fields } = &self.otype else {
+            return None;
+        };
+        let depth = Ident::new("depth", Span::call_site());
+        let mut variants = fields.iter().map(|f| {
+            let variant_name = &f.rust_name;
+            let strategy = f.tspec.generate_strategy(gen, &depth);
+            let val = gen.wrapped_value(quote! { v }, f.boxed, false);
+            quote! { #strategy.prop_map(|v| Self::#variant_name(#val)) }
+        });
+        let strategy = if fields.len() == 1 {
+            variants.next().unwrap()
+        } else {
+            quote! { ::proptest::prop_oneof![#(#variants),*] }
+        };
+
+        Some(quote! {
+            #[cfg(feature = "proptest")]
+            impl #type_name {
+                /// Strategy that generates arbitrary variants of the oneof, for property tests with `proptest`
+                pub fn arbitrary_strategy() -> impl ::proptest::strategy::Strategy<Value = Self> {
+                    Self::_strategy_depth(#ARBITRARY_DEPTH)
+                }
+
+                #[doc(hidden)]
+                #[allow(unused_variables)]
+                pub fn _strategy_depth(#depth: usize) -> ::proptest::strategy::BoxedStrategy<Self> {
+                    use ::proptest::strategy::Strategy as _;
+                    #strategy.boxed()
+                }
+            }
+        })
+    }
+
+    /// Generate the hidden `_roundtrip_sample` constructor used by round-trip tests, which
+    /// builds the first variant, or the last variant if `fill` is true
+    pub(crate) fn generate_sample_impl(&self, gen: &Generator) -> Option<TokenStream> {
+        let OneofType::Enum { type_name, fields } = &self.otype else {
+            return None;
+        };
+        let fill = Ident::new("fill", Span::call_site());
+        let depth = Ident::new("depth", Span::call_site());
+        let variant = |f: &OneofField| {
+            let variant_name = &f.rust_name;
+            let val =
+                gen.wrapped_value(f.tspec.generate_sample(gen, &fill, &depth), f.boxed, false);
+            quote! { Self::#variant_name(#val) }
+        };
+        let first = variant(fields.first()?);
+        let last = variant(fields.last()?);
+
+        Some(quote! {
+            #[cfg(test)]
+            impl #type_name {
+                #[doc(hidden)]
+                #[allow(unused_variables)]
+                pub fn _roundtrip_sample(#fill: bool, #depth: usize) -> Self {
+                    if #fill {
+                        #last
+                    } else {
+                        #first
+                    }
+                }
+            }
+        })
+    }
+
+    /// Generate the initializer of the domain type's field in the conversion from the message
+    /// `value`. Returns `None` for oneofs that aren't converted.
+    pub(crate) fn generate_from_domain_init(&self, err: &TokenStream) -> Option<TokenStream> {
+        if self.domain_skip {
+            return None;
+        }
+        let name = &self.san_rust_name;
+        let val = match &self.otype {
+            OneofType::Enum { .. } => {
+                let deref = self.boxed.then(|| quote! { * });
+                quote! {
+                    value.#name
+                        .map(|v| ::core::convert::TryInto::try_into(#deref v))
+                        .transpose()
+                        .map_err(#err)?
+                }
+            }
+            OneofType::Custom {
+                field: CustomField::Type(_),
+                ..
+            } => quote! { ::core::convert::TryInto::try_into(value.#name).map_err(#err)? },
+            OneofType::Custom {
+                field: CustomField::Delegate(_),
+                ..
+            } => return None,
+        };
+        Some(quote! { #name: #val, })
+    }
+
+    /// Generate the statements that set the oneof of `msg` in the conversion from the domain
+    /// type `value`. Returns `None` for oneofs that aren't converted.
+    pub(crate) fn generate_into_domain_stmt(
+        &self,
+        gen: &Generator,
+        err: &TokenStream,
+    ) -> Option<TokenStream> {
+        if self.domain_skip {
+            return None;
+        }
+        let name = &self.san_rust_name;
+        match &self.otype {
+            OneofType::Enum { .. } => {
+                let val = gen.wrapped_value(
+                    quote! { ::core::convert::TryInto::try_into(v).map_err(#err)? },
+                    self.boxed,
+                    true,
+                );
+                Some(quote! {
+                    if let ::core::option::Option::Some(v) = value.#name {
+                        msg.#name = #val;
+                    }
+                })
+            }
+            OneofType::Custom {
+                field: CustomField::Type(_),
+                ..
+            } => Some(quote! {
+                msg.#name = ::core::convert::TryInto::try_into(value.#name).map_err(#err)?;
+            }),
+            OneofType::Custom {
+                field: CustomField::Delegate(_),
+                ..
+            } => None,
+        }
+    }
+
+    /// Generate a `From` impl from the oneof enum into its counterpart in `prost_mod`, the module
+    /// that `prost-build` generates for the message's nested types, and a `TryFrom` impl back
+    pub(crate) fn generate_prost_impls(
+        &self,
+        gen: &Generator,
+        prost_mod: &TokenStream,
+    ) -> Option<TokenStream> {
+        let OneofType::Enum { type_name, fields } = &self.otype else {
+            return None;
+        };
+        let prost_type = prost_ident(self.name, Case::Pascal);
+        let prost_type = quote! { #prost_mod::#prost_type };
+        let (to_arms, from_arms): (Vec<_>, Vec<_>) = fields
+            .iter()
+            .map(|f| {
+                let variant = &f.rust_name;
+                let prost_variant = prost_ident(f.name, Case::Pascal);
+                let deref = f.boxed.then(|| quote! { * });
+                let to_val = gen.wrapped_value(
+                    f.tspec.generate_to_prost(gen, quote! { (#deref v) }),
+                    f.boxed,
+                    false,
+                );
+                let from_val = gen.wrapped_value(
+                    f.tspec.generate_from_prost(gen, quote! { (#deref v) }),
+                    f.boxed,
+                    false,
+                );
+                (
+                    quote! { #type_name::#variant(v) => Self::#prost_variant(#to_val), },
+                    quote! { #prost_type::#prost_variant(v) => Self::#variant(#from_val), },
+                )
+            })
+            .unzip();
+
+        Some(quote! {
+            impl ::core::convert::From<#type_name> for #prost_type {
+                fn from(value: #type_name) -> Self {
+                    match value {
+                        #(#to_arms)*
+                    }
+                }
+            }
+
+            impl ::core::convert::TryFrom<#prost_type> for #type_name {
+                type Error = ::micropb::ConversionError;
+
+                fn try_from(value: #prost_type) -> ::core::result::Result<Self, Self::Error> {
+                    ::core::result::Result::Ok(match value {
+                        #(#from_arms)*
+                    })
+                }
+            }
+        })
+    }
+
+    /// Generate the match arms that parse the variants of the oneof from the values of JSON
+    /// object entries. A `null` value leaves the oneof unchanged.
+    pub(crate) fn generate_json_parse_arms(
+        &self,
+        gen: &Generator,
+        msg_mod_name: &Ident,
+        parser: &Ident,
+    ) -> Vec<TokenStream> {
+        let OneofType::Enum { type_name, fields } = &self.otype else {
+            return vec![];
+        };
+        let name = &self.san_rust_name;
+        let oneof_type = quote! { #msg_mod_name::#type_name };
+        let mut_ref = Ident::new("mut_ref", Span::call_site());
+        let extra_deref_of = self.boxed.then(|| quote! { * });
+        fields
+            .iter()
+            .filter_map(|f| {
+                let parse_stmts = f.tspec.generate_json_parse_mut(parser, &mut_ref)?;
+                let variant_name = &f.rust_name;
+                let extra_deref_var = f.boxed.then(|| quote! { * });
+                let value = gen.wrapped_value(
+                    quote! { #oneof_type::#variant_name(::core::default::Default::default()) },
+                    self.boxed,
+                    true,
+                );
+                let pattern = json_key_pattern(f.name, &f.json_name);
+                Some(quote! {
+                    #pattern => {
+                        if !#parser.parse_null()? {
+                            let #mut_ref = loop {
+                                if let ::core::option::Option::Some(variant) = &mut self.#name {
+                                    if let #oneof_type::#variant_name(variant) = &mut #extra_deref_of *variant {
+                                        break &mut #extra_deref_var *variant;
+                                    }
+                                }
+                                self.#name = #value;
+                            };
+                            #parse_stmts
+                        }
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// Generate the match arms that parse the variants of the oneof from their values in text
+    /// format.
+    pub(crate) fn generate_text_parse_arms(
+        &self,
+        gen: &Generator,
+        msg_mod_name: &Ident,
+        parser: &Ident,
+    ) -> Vec<TokenStream> {
+        let OneofType::Enum { type_name, fields } = &self.otype else {
+            return vec![];
+        };
+        let name = &self.san_rust_name;
+        let oneof_type = quote! { #msg_mod_name::#type_name };
+        let mut_ref = Ident::new("mut_ref", Span::call_site());
+        let extra_deref_of = self.boxed.then(|| quote! { * });
+        fields
+            .iter()
+            .filter_map(|f| {
+                let (parse_stmts, message) = f.tspec.generate_text_parse_mut(parser, &mut_ref)?;
+                let method = text_parse_method(message, false);
+                let variant_name = &f.rust_name;
+                let extra_deref_var = f.boxed.then(|| quote! { * });
+                let value = gen.wrapped_value(
+                    quote! { #oneof_type::#variant_name(::core::default::Default::default()) },
+                    self.boxed,
+                    true,
+                );
+                let field_name = f.name;
+                Some(quote! {
+                    #field_name => {
+                        let #mut_ref = loop {
+                            if let ::core::option::Option::Some(variant) = &mut self.#name {
+                                if let #oneof_type::#variant_name(variant) = &mut #extra_deref_of *variant {
+                                    break &mut #extra_deref_var *variant;
+                                }
+                            }
+                            self.#name = #value;
+                        };
+                        #parser.#method(|#parser| {
+                            #parse_stmts
+                            ::core::result::Result::Ok(())
+                        })?;
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// Generate code that writes the set variant of the oneof in text format into the text writer
+    /// `t`. Returns `None` for custom oneofs.
+    pub(crate) fn generate_text(&self, msg_mod_name: &Ident, t: &Ident) -> Option<TokenStream> {
+        let OneofType::Enum { type_name, fields } = &self.otype else {
+            return None;
+        };
+        let name = &self.san_rust_name;
+        let oneof_type = quote! { #msg_mod_name::#type_name };
+        let extra_deref = self.boxed.then(|| quote! { * });
+        let val_ref = Ident::new("val_ref", Span::call_site());
+        let branches = fields.iter().filter_map(|f| {
+            let variant_name = &f.rust_name;
+            let extra_deref_var = f.boxed.then(|| quote! { * });
+            let text_expr = f.tspec.generate_text_expr(t, f.name, &val_ref)?;
+            Some(quote! {
+                #oneof_type::#variant_name(#val_ref) => {
+                    let #val_ref = &* #extra_deref_var #val_ref;
+                    #text_expr?;
+                }
+            })
+        });
+        Some(quote! {
+            if let Some(oneof) = &self.#name {
+                match &#extra_deref *oneof {
+                    #(#branches)*
+                    #[allow(unreachable_patterns)]
+                    _ => {}
+                }
+            }
+        })
+    }
 }
 
 #[cfg(test)]
@@ -346,12 +989,13 @@ pub(crate) fn make_test_oneof_field(
     name: &str,
     boxed: bool,
     tspec: TypeSpec,
-) -> OneofField {
+) -> OneofField<'_> {
     OneofField {
         num,
         name,
         tspec,
         rust_name: Ident::new(&name.to_case(Case::Pascal), Span::call_site()),
+        json_name: json_name(name),
         boxed,
         attrs: vec![],
     }
@@ -407,6 +1051,7 @@ mod tests {
                 tspec: TypeSpec::Bool,
                 name: "field",
                 rust_name: Ident::new("Field", Span::call_site()),
+                json_name: "field".to_owned(),
                 boxed: false,
                 attrs: vec![]
             }
@@ -428,6 +1073,7 @@ mod tests {
                 tspec: TypeSpec::Bool,
                 name: "field",
                 rust_name: Ident::new("Renamed", Span::call_site()),
+                json_name: "field".to_owned(),
                 boxed: true,
                 attrs: parse_attributes("#[attr]").unwrap()
             }
@@ -458,6 +1104,11 @@ mod tests {
                 derive_dbg: true,
                 derive_partial_eq: true,
                 derive_clone: true,
+                serde: false,
+                defmt: false,
+                arbitrary: false,
+                proptest: false,
+                domain_skip: false,
                 idx: 0
             }
         );
@@ -485,6 +1136,11 @@ mod tests {
                 derive_dbg: false,
                 derive_partial_eq: true,
                 derive_clone: true,
+                serde: false,
+                defmt: false,
+                arbitrary: false,
+                proptest: false,
+                domain_skip: false,
                 idx: 0
             }
         );
@@ -506,6 +1162,11 @@ mod tests {
             derive_dbg: true,
             derive_partial_eq: true,
             derive_clone: true,
+            serde: false,
+            defmt: false,
+            arbitrary: false,
+            proptest: false,
+            domain_skip: false,
             idx: 0,
         };
         assert!(oneof.generate_decl(&gen).is_empty());
@@ -529,6 +1190,11 @@ mod tests {
             derive_dbg: true,
             derive_partial_eq: true,
             derive_clone: true,
+            serde: false,
+            defmt: false,
+            arbitrary: false,
+            proptest: false,
+            domain_skip: false,
             idx: 0,
         };
         assert!(oneof.generate_decl(&gen).is_empty());
@@ -1,16 +1,16 @@
 use convert_case::{Case, Casing};
 use proc_macro2::{Literal, Span, TokenStream};
-use quote::quote;
+use quote::{format_ident, quote, ToTokens};
 use syn::{Ident, Lifetime};
 
 use crate::{
-    config::IntSize,
+    config::{IntSize, OnOverflow, OnUnknownEnum},
     descriptor::{FieldDescriptorProto, FieldDescriptorProto_::Type},
     generator::sanitized_ident,
     utils::{path_suffix, unescape_c_escape_string},
 };
 
-use super::{CurrentConfig, Generator};
+use super::{container::ContainerType, CurrentConfig, Generator, MaxSize};
 
 #[cfg_attr(test, derive(Debug, PartialEq, Eq))]
 pub(crate) enum PbInt {
@@ -39,13 +39,26 @@ impl PbInt {
         )
     }
 
+    /// Rust type that `prost` uses for the integer type
+    fn prost_type(&self) -> Ident {
+        let typ = match self {
+            PbInt::Int32 | PbInt::Sint32 | PbInt::Sfixed32 => "i32",
+            PbInt::Int64 | PbInt::Sint64 | PbInt::Sfixed64 => "i64",
+            PbInt::Uint32 | PbInt::Fixed32 => "u32",
+            PbInt::Uint64 | PbInt::Fixed64 => "u64",
+        };
+        Ident::new(typ, Span::call_site())
+    }
+
     fn generate_decode_func(&self, int_size: &IntSize) -> Ident {
         let func = match self {
-            PbInt::Int64 if matches!(int_size, IntSize::S64) => "decode_int64",
-            PbInt::Uint64 if matches!(int_size, IntSize::S64) => "decode_varint64",
-            PbInt::Sint64 if matches!(int_size, IntSize::S64) => "decode_sint64",
-            PbInt::Fixed64 if matches!(int_size, IntSize::S64) => "decode_fixed64",
-            PbInt::Sfixed64 if matches!(int_size, IntSize::S64) => "decode_sfixed64",
+            PbInt::Int64 if matches!(int_size, IntSize::S64 | IntSize::Size) => "decode_int64",
+            PbInt::Uint64 if matches!(int_size, IntSize::S64 | IntSize::Size) => "decode_varint64",
+            PbInt::Sint64 if matches!(int_size, IntSize::S64 | IntSize::Size) => "decode_sint64",
+            PbInt::Fixed64 if matches!(int_size, IntSize::S64 | IntSize::Size) => "decode_fixed64",
+            PbInt::Sfixed64 if matches!(int_size, IntSize::S64 | IntSize::Size) => {
+                "decode_sfixed64"
+            }
 
             PbInt::Int32 | PbInt::Int64 => "decode_int32",
             PbInt::Uint32 | PbInt::Uint64 => "decode_varint32",
@@ -60,13 +73,13 @@ impl PbInt {
 
     fn generate_sizeof(&self, int_size: &IntSize, val_ref: &Ident) -> TokenStream {
         match self {
-            PbInt::Int64 if matches!(int_size, IntSize::S64) => {
+            PbInt::Int64 if matches!(int_size, IntSize::S64 | IntSize::Size) => {
                 quote! { ::micropb::size::sizeof_int64(* #val_ref as _) }
             }
-            PbInt::Uint64 if matches!(int_size, IntSize::S64) => {
+            PbInt::Uint64 if matches!(int_size, IntSize::S64 | IntSize::Size) => {
                 quote! { ::micropb::size::sizeof_varint64(* #val_ref as _) }
             }
-            PbInt::Sint64 if matches!(int_size, IntSize::S64) => {
+            PbInt::Sint64 if matches!(int_size, IntSize::S64 | IntSize::Size) => {
                 quote! { ::micropb::size::sizeof_sint64(* #val_ref as _) }
             }
 
@@ -87,13 +100,38 @@ impl PbInt {
         }
     }
 
+    /// Maximum size of the integer on the wire, for any value of the Rust type
+    fn max_size(&self, int_size: &IntSize) -> usize {
+        let bits: usize = match int_size {
+            IntSize::S8 => 8,
+            IntSize::S16 => 16,
+            IntSize::S32 => 32,
+            // The pointer width of the target is unknown, so assume the widest
+            IntSize::S64 | IntSize::Size => 64,
+        };
+        match self {
+            // Negative values are always encoded as 10 bytes
+            PbInt::Int32 | PbInt::Int64 => 10,
+            PbInt::Uint64 | PbInt::Sint64 if matches!(int_size, IntSize::S64 | IntSize::Size) => 10,
+            // Everything else is encoded as a 32-bit varint, which can't be wider than the Rust
+            // type
+            PbInt::Uint32 | PbInt::Uint64 | PbInt::Sint32 | PbInt::Sint64 => {
+                bits.min(32).div_ceil(7)
+            }
+            PbInt::Sfixed32 | PbInt::Fixed32 => 4,
+            PbInt::Sfixed64 | PbInt::Fixed64 => 8,
+        }
+    }
+
     fn generate_encode_func(&self, int_size: &IntSize) -> Ident {
         let func = match self {
-            PbInt::Int64 if matches!(int_size, IntSize::S64) => "encode_int64",
-            PbInt::Uint64 if matches!(int_size, IntSize::S64) => "encode_varint64",
-            PbInt::Sint64 if matches!(int_size, IntSize::S64) => "encode_sint64",
-            PbInt::Sfixed64 if matches!(int_size, IntSize::S64) => "encode_sfixed64",
-            PbInt::Fixed64 if matches!(int_size, IntSize::S64) => "encode_fixed64",
+            PbInt::Int64 if matches!(int_size, IntSize::S64 | IntSize::Size) => "encode_int64",
+            PbInt::Uint64 if matches!(int_size, IntSize::S64 | IntSize::Size) => "encode_varint64",
+            PbInt::Sint64 if matches!(int_size, IntSize::S64 | IntSize::Size) => "encode_sint64",
+            PbInt::Sfixed64 if matches!(int_size, IntSize::S64 | IntSize::Size) => {
+                "encode_sfixed64"
+            }
+            PbInt::Fixed64 if matches!(int_size, IntSize::S64 | IntSize::Size) => "encode_fixed64",
 
             PbInt::Int32 | PbInt::Int64 => "encode_int32",
             PbInt::Uint32 | PbInt::Uint64 => "encode_varint32",
@@ -107,6 +145,44 @@ impl PbInt {
     }
 }
 
+/// Levels of nested messages generated by the `Arbitrary` impls and `proptest` strategies of
+/// messages and oneofs
+pub(crate) const ARBITRARY_DEPTH: usize = 4;
+
+/// Max number of elements generated by `proptest` strategies for unbounded containers
+pub(crate) const PROPTEST_MAX_LEN: u32 = 8;
+
+/// Levels of nested messages in the messages built by round-trip tests
+pub(crate) const ROUNDTRIP_DEPTH: usize = 3;
+
+/// Number of elements in the unbounded containers of the messages built by round-trip tests
+pub(crate) const ROUNDTRIP_FILL_LEN: u32 = 3;
+
+/// Generate a block that fits the string `s` into a new string container of type `typ`, cutting
+/// it at the last character boundary that fits. Evaluates to the result of `PbString::pb_from_str`.
+fn generate_fit_str(typ: &TokenStream, s: &Ident) -> TokenStream {
+    quote! {{
+        let mut v: #typ = ::core::default::Default::default();
+        ::micropb::PbContainer::pb_reserve(&mut v, #s.len());
+        let mut end = #s.len().min(::micropb::PbString::pb_spare_cap(&mut v).len());
+        while !#s.is_char_boundary(end) {
+            end -= 1;
+        }
+        ::micropb::PbString::pb_from_str(&#s[..end])
+    }}
+}
+
+/// Generate a block that fits the bytes `b` into a new byte container of type `typ`, dropping
+/// the bytes that don't fit. Evaluates to the result of `PbVec::pb_from_slice`.
+fn generate_fit_bytes(typ: &TokenStream, b: &Ident) -> TokenStream {
+    quote! {{
+        let mut v: #typ = ::core::default::Default::default();
+        ::micropb::PbContainer::pb_reserve(&mut v, #b.len());
+        let end = #b.len().min(::micropb::PbVec::pb_spare_cap(&mut v).len());
+        ::micropb::PbVec::pb_from_slice(&#b[..end])
+    }}
+}
+
 /// Find the first lifetime embedded in a type
 pub(crate) fn find_lifetime_from_type(ty: &syn::Type) -> Option<&Lifetime> {
     match ty {
@@ -138,19 +214,51 @@ pub(crate) fn find_lifetime_from_path(tpath: &syn::Path) -> Option<&Lifetime> {
 #[cfg_attr(test, derive(Debug, PartialEq, Eq))]
 pub(crate) enum TypeSpec {
     Message(String),
-    Enum(String),
+    Enum(String, OnUnknownEnum),
     Float,
     Double,
     Bool,
     Int(PbInt, IntSize),
     String {
-        type_path: syn::Path,
+        type_path: ContainerType,
         max_bytes: Option<u32>,
+        on_overflow: OnOverflow,
+        /// Represented by a byte container, so UTF-8 isn't validated on decode
+        as_bytes: bool,
     },
     Bytes {
-        type_path: syn::Path,
+        type_path: ContainerType,
+        max_bytes: Option<u32>,
+        on_overflow: OnOverflow,
+    },
+    /// Bytes stored as a range into the decoder's input
+    BytesRange,
+    /// Message stored as its encoded bytes, which are decoded on demand
+    LazyMessage {
+        name: String,
+        type_path: ContainerType,
         max_bytes: Option<u32>,
     },
+    /// Wrapper well-known type, such as `google.protobuf.Int32Value`, represented as the value it
+    /// wraps
+    Wrapper(Box<TypeSpec>),
+}
+
+/// Type of the `value` field of a wrapper well-known type
+fn wrapped_type(type_name: &str) -> Option<Type> {
+    let typ = match type_name.strip_prefix(".google.protobuf.")? {
+        "DoubleValue" => Type::Double,
+        "FloatValue" => Type::Float,
+        "Int64Value" => Type::Int64,
+        "UInt64Value" => Type::Uint64,
+        "Int32Value" => Type::Int32,
+        "UInt32Value" => Type::Uint32,
+        "BoolValue" => Type::Bool,
+        "StringValue" => Type::String,
+        "BytesValue" => Type::Bytes,
+        _ => return None,
+    };
+    Some(typ)
 }
 
 impl TypeSpec {
@@ -163,6 +271,31 @@ impl TypeSpec {
         }
     }
 
+    /// Whether the type is a 64-bit integer that's stored as a pointer-sized integer, which can't
+    /// hold every value on 32-bit targets
+    pub(crate) fn is_64bit_as_size(&self) -> bool {
+        match self {
+            TypeSpec::Int(pbint, IntSize::Size) => matches!(
+                pbint,
+                PbInt::Int64 | PbInt::Uint64 | PbInt::Sint64 | PbInt::Fixed64 | PbInt::Sfixed64
+            ),
+            TypeSpec::Wrapper(value) => value.is_64bit_as_size(),
+            _ => false,
+        }
+    }
+
+    /// Whether the type is a fixed-size type whose Rust type has the same size as its wire
+    /// representation, so packed fields of the type can be copied in bulk
+    pub(crate) fn is_bulk_fixed(&self) -> bool {
+        matches!(
+            self,
+            TypeSpec::Float
+                | TypeSpec::Double
+                | TypeSpec::Int(PbInt::Fixed32 | PbInt::Sfixed32, IntSize::S32)
+                | TypeSpec::Int(PbInt::Fixed64 | PbInt::Sfixed64, IntSize::S64)
+        )
+    }
+
     pub(crate) fn from_proto(
         proto: &FieldDescriptorProto,
         type_conf: &CurrentConfig,
@@ -173,21 +306,73 @@ impl TypeSpec {
             Type::Double => TypeSpec::Double,
             Type::Float => TypeSpec::Float,
             Type::Bool => TypeSpec::Bool,
+            Type::String if conf.string_as_bytes.unwrap_or(false) => TypeSpec::String {
+                type_path: conf.vec_type_parsed(conf.max_bytes)?.ok_or_else(|| {
+                    "Field is of type `string` and string_as_bytes is set, but vec_type was not configured for it"
+                        .to_owned()
+                })?,
+                max_bytes: conf.container_capacity(
+                    "vec_type",
+                    conf.vec_type.as_deref().unwrap_or_default(),
+                    "max_bytes",
+                    conf.max_bytes,
+                )?,
+                on_overflow: conf.on_overflow.unwrap_or(OnOverflow::Error),
+                as_bytes: true,
+            },
             Type::String => TypeSpec::String {
                 type_path: conf.string_type_parsed()?.ok_or_else(|| {
                     "Field is of type `string`, but string_type was not configured for it"
                         .to_owned()
                 })?,
-                max_bytes: conf.max_bytes,
+                max_bytes: conf.container_capacity(
+                    "string_type",
+                    conf.string_type.as_deref().unwrap_or_default(),
+                    "max_bytes",
+                    conf.max_bytes,
+                )?,
+                on_overflow: conf.on_overflow.unwrap_or(OnOverflow::Error),
+                as_bytes: false,
             },
+            Type::Bytes if conf.bytes_range.unwrap_or(false) => TypeSpec::BytesRange,
             Type::Bytes => TypeSpec::Bytes {
-                type_path: conf.vec_type_parsed()?.ok_or_else(|| {
+                type_path: conf.vec_type_parsed(conf.max_bytes)?.ok_or_else(|| {
                     "Field is of type `bytes`, but vec_type was not configured for it".to_owned()
                 })?,
-                max_bytes: conf.max_bytes,
+                max_bytes: conf.container_capacity(
+                    "vec_type",
+                    conf.vec_type.as_deref().unwrap_or_default(),
+                    "max_bytes",
+                    conf.max_bytes,
+                )?,
+                on_overflow: conf.on_overflow.unwrap_or(OnOverflow::Error),
+            },
+            Type::Message
+                if conf.unwrap_wrappers.unwrap_or(false)
+                    && wrapped_type(&proto.type_name).is_some() =>
+            {
+                // The wrapped value is configured the same way as the field itself
+                let mut value = FieldDescriptorProto::default();
+                value.set_type(wrapped_type(&proto.type_name).unwrap());
+                TypeSpec::Wrapper(Box::new(TypeSpec::from_proto(&value, type_conf)?))
+            }
+            Type::Message if conf.lazy.unwrap_or(false) => TypeSpec::LazyMessage {
+                name: proto.type_name.clone(),
+                type_path: conf.vec_type_parsed(conf.max_bytes)?.ok_or_else(|| {
+                    "Field is a lazy message, but vec_type was not configured for it".to_owned()
+                })?,
+                max_bytes: conf.container_capacity(
+                    "vec_type",
+                    conf.vec_type.as_deref().unwrap_or_default(),
+                    "max_bytes",
+                    conf.max_bytes,
+                )?,
             },
             Type::Message => TypeSpec::Message(proto.type_name.clone()),
-            Type::Enum => TypeSpec::Enum(proto.type_name.clone()),
+            Type::Enum => TypeSpec::Enum(
+                proto.type_name.clone(),
+                conf.on_unknown_enum.unwrap_or(OnUnknownEnum::Preserve),
+            ),
             Type::Uint32 => TypeSpec::Int(PbInt::Uint32, conf.int_size.unwrap_or(IntSize::S32)),
             Type::Int64 => TypeSpec::Int(PbInt::Int64, conf.int_size.unwrap_or(IntSize::S64)),
             Type::Uint64 => TypeSpec::Int(PbInt::Uint64, conf.int_size.unwrap_or(IntSize::S64)),
@@ -200,6 +385,12 @@ impl TypeSpec {
             Type::Sint64 => TypeSpec::Int(PbInt::Sint64, conf.int_size.unwrap_or(IntSize::S64)),
             t => return Err(format!("Unknown type specifier {}", t.0)),
         };
+        if conf.lazy.unwrap_or(false) && !matches!(res, TypeSpec::LazyMessage { .. }) {
+            return Err("lazy is only supported for message fields".to_owned());
+        }
+        if conf.bytes_range.unwrap_or(false) && !matches!(res, TypeSpec::BytesRange) {
+            return Err("bytes_range is only supported for bytes fields".to_owned());
+        }
         Ok(res)
     }
 
@@ -215,40 +406,96 @@ impl TypeSpec {
             TypeSpec::String {
                 type_path,
                 max_bytes,
+                as_bytes: false,
+                ..
             } => {
-                let max_bytes = max_bytes.map(Literal::u32_unsuffixed).into_iter();
-                quote! { #type_path #(<#max_bytes>)* }
+                let max_bytes = max_bytes.map(|n| Literal::u32_unsuffixed(n).into_token_stream());
+                type_path.generate(&[("N", max_bytes)])
             }
-            TypeSpec::Bytes {
+            TypeSpec::String {
                 type_path,
                 max_bytes,
+                as_bytes: true,
+                ..
+            }
+            | TypeSpec::Bytes {
+                type_path,
+                max_bytes,
+                ..
+            }
+            | TypeSpec::LazyMessage {
+                type_path,
+                max_bytes,
+                ..
             } => {
-                let max_bytes = max_bytes.map(Literal::u32_unsuffixed).into_iter();
-                quote! { #type_path <u8 #(, #max_bytes)* > }
+                let max_bytes = max_bytes.map(|n| Literal::u32_unsuffixed(n).into_token_stream());
+                type_path.generate(&[("T", Some(quote! { u8 })), ("N", max_bytes)])
             }
-            TypeSpec::Message(tname) | TypeSpec::Enum(tname) => {
+            TypeSpec::BytesRange => quote! { ::core::ops::Range<usize> },
+            TypeSpec::Message(tname) | TypeSpec::Enum(tname, _) => {
                 let rust_type = gen.resolve_type_name(tname);
                 quote! { #rust_type }
             }
+            TypeSpec::Wrapper(value) => value.generate_rust_type(gen),
         }
     }
 
+    /// Compile-time check that the container of the type can hold its configured `max_bytes`
+    pub(crate) fn generate_capacity_assert(
+        &self,
+        gen: &Generator,
+        field_path: &str,
+    ) -> Option<TokenStream> {
+        let (container_trait, max_bytes) = match self {
+            TypeSpec::String {
+                max_bytes: Some(max_bytes),
+                as_bytes: false,
+                ..
+            } => (quote! { ::micropb::PbString }, *max_bytes),
+            TypeSpec::String {
+                max_bytes: Some(max_bytes),
+                as_bytes: true,
+                ..
+            }
+            | TypeSpec::Bytes {
+                max_bytes: Some(max_bytes),
+                ..
+            }
+            | TypeSpec::LazyMessage {
+                max_bytes: Some(max_bytes),
+                ..
+            } => (quote! { ::micropb::PbVec<u8> }, *max_bytes),
+            TypeSpec::Wrapper(value) => return value.generate_capacity_assert(gen, field_path),
+            _ => return None,
+        };
+        Some(capacity_assert(
+            self.generate_rust_type(gen),
+            container_trait,
+            max_bytes,
+            &format!("max_bytes of `{field_path}` exceeds the capacity of its container"),
+        ))
+    }
+
     pub(crate) fn generate_default(
         &self,
         default: &str,
         gen: &Generator,
     ) -> Result<TokenStream, String> {
         let out = match self {
-            TypeSpec::String { max_bytes, .. } => {
+            TypeSpec::String { max_bytes, as_bytes, .. } => {
                 match *max_bytes {
                     Some(max_bytes) if default.len() > max_bytes as usize =>
                         return Err(format!("String field is limited to {max_bytes} bytes, but its default value is {} bytes", default.len())),
+                    _ if *as_bytes => {
+                        let default_bytes = Literal::byte_string(default.as_bytes());
+                        quote! { ::micropb::PbVec::pb_from_slice(#default_bytes).unwrap_or_default() }
+                    }
                     _ => quote! { ::micropb::PbString::pb_from_str(#default).unwrap_or_default() }
                 }
             }
 
             TypeSpec::Bytes { max_bytes, .. } => {
-                let bytes = unescape_c_escape_string(default);
+                let bytes = unescape_c_escape_string(default)?;
                 let default_bytes = Literal::byte_string(&bytes);
                 match *max_bytes {
                     Some(max_bytes) if bytes.len() > max_bytes as usize =>
@@ -257,11 +504,15 @@ impl TypeSpec {
                 }
             }
 
-            TypeSpec::Message(_) => {
+            TypeSpec::Message(_) | TypeSpec::LazyMessage { .. } | TypeSpec::Wrapper(_) => {
                 unreachable!("message fields shouldn't have custom defaults")
             }
 
-            TypeSpec::Enum(tpath) => {
+            TypeSpec::BytesRange => {
+                return Err("Field is represented as a range, so it can't have a default value".to_owned())
+            }
+
+            TypeSpec::Enum(tpath, _) => {
                 let enum_path = gen.resolve_type_name(tpath);
                 let enum_name =
                     sanitized_ident(&path_suffix(tpath).to_case(Case::Pascal));
@@ -278,6 +529,12 @@ impl TypeSpec {
         Ok(out)
     }
 
+    /// Whether unknown values of this enum are dropped, which is handled by the decode logic of
+    /// the field rather than by the value decoding
+    pub(crate) fn drops_unknown_enum(&self) -> bool {
+        matches!(self, TypeSpec::Enum(_, OnUnknownEnum::Drop))
+    }
+
     pub(crate) fn wire_type(&self) -> u8 {
         match self {
             TypeSpec::Float | TypeSpec::Int(PbInt::Fixed32 | PbInt::Sfixed32, _) => {
@@ -286,7 +543,7 @@ impl TypeSpec {
             TypeSpec::Double | TypeSpec::Int(PbInt::Fixed64 | PbInt::Sfixed64, _) => {
                 micropb::WIRE_TYPE_I64
             }
-            TypeSpec::Enum(_)
+            TypeSpec::Enum(..)
             | TypeSpec::Bool
             | TypeSpec::Int(
                 PbInt::Int32
@@ -297,21 +554,25 @@ impl TypeSpec {
                 | PbInt::Sint64,
                 _,
             ) => micropb::WIRE_TYPE_VARINT,
-            TypeSpec::Message(_) | TypeSpec::String { .. } | TypeSpec::Bytes { .. } => {
-                micropb::WIRE_TYPE_LEN
-            }
+            TypeSpec::Message(_)
+            | TypeSpec::LazyMessage { .. }
+            | TypeSpec::Wrapper(_)
+            | TypeSpec::String { .. }
+            | TypeSpec::Bytes { .. }
+            | TypeSpec::BytesRange => micropb::WIRE_TYPE_LEN,
         }
     }
 
     pub(crate) fn generate_implicit_presence_check(&self, val_ref: &Ident) -> TokenStream {
         match self {
-            TypeSpec::Message(_) => quote! {},
-            TypeSpec::Enum(_) => quote! { if #val_ref.0 != 0 },
-            TypeSpec::Float | TypeSpec::Double => quote! { if *#val_ref != 0.0 },
+            TypeSpec::Message(_) | TypeSpec::LazyMessage { .. } | TypeSpec::Wrapper(_) => quote! {},
+            TypeSpec::Enum(..) => quote! { if #val_ref.0 != 0 },
+            // Negative zero isn't the default value, so it must be encoded
+            TypeSpec::Float | TypeSpec::Double => quote! { if #val_ref.to_bits() != 0 },
             TypeSpec::Bool => quote! { if *#val_ref },
             TypeSpec::Int(_, _) => quote! { if *#val_ref != 0 },
             TypeSpec::String { .. } => quote! { if !#val_ref.is_empty() },
-            TypeSpec::Bytes { .. } => quote! { if !#val_ref.is_empty() },
+            TypeSpec::Bytes { .. } | TypeSpec::BytesRange => quote! { if !#val_ref.is_empty() },
         }
     }
 
@@ -325,36 +586,90 @@ impl TypeSpec {
             TypeSpec::Float => Some(quote! { #decoder.decode_float() }),
             TypeSpec::Double => Some(quote! { #decoder.decode_double() }),
             TypeSpec::Bool => Some(quote! { #decoder.decode_bool() }),
+            // Pointer-sized ints are checked instead of truncated, since their width varies
+            TypeSpec::Int(pbint, IntSize::Size) => {
+                let func = pbint.generate_decode_func(&IntSize::Size);
+                let typ = IntSize::Size.type_name(pbint.is_signed());
+                Some(quote! {
+                    #decoder.#func().and_then(|n| {
+                        <#typ as ::core::convert::TryFrom<_>>::try_from(n)
+                            .map_err(|_| ::micropb::DecodeError::IntOverflow)
+                    })
+                })
+            }
             TypeSpec::Int(pbint, int_size) => {
                 let func = pbint.generate_decode_func(int_size);
                 Some(quote! { #decoder.#func() })
             }
             // Enum is actually packable due to https://github.com/protocolbuffers/protobuf/issues/15480
-            TypeSpec::Enum(tpath) => {
+            TypeSpec::Enum(tpath, on_unknown) => {
                 let enum_path = gen.resolve_type_name(tpath);
-                Some(quote! { #decoder.decode_int32().map(|n| #enum_path(n as _)) })
+                let val = quote! { #enum_path(n as _) };
+                Some(match on_unknown {
+                    // Dropped values are discarded by the field's decode logic
+                    OnUnknownEnum::Preserve | OnUnknownEnum::Drop => {
+                        quote! { #decoder.decode_int32().map(|n| #val) }
+                    }
+                    OnUnknownEnum::Error => quote! {
+                        #decoder.decode_int32().and_then(|n| {
+                            let val = #val;
+                            if val.is_known() { Ok(val) } else { Err(::micropb::DecodeError::UnknownEnumValue) }
+                        })
+                    },
+                    OnUnknownEnum::Default => quote! {
+                        #decoder.decode_int32().map(|n| {
+                            let val = #val;
+                            if val.is_known() { val } else { ::core::default::Default::default() }
+                        })
+                    },
+                })
             }
             _ => None,
         }
     }
 
-    pub(crate) fn generate_decode_mut(
+    /// Same as [`generate_decode_mut`](Self::generate_decode_mut), except that enums that drop
+    /// unknown values are expected to be decoded into `val` and checked by the caller beforehand,
+    /// so `val` is just written to `mut_ref`.
+    pub(crate) fn generate_decode_field_mut(
         &self,
         gen: &Generator,
-        implicit_presence: bool,
         decoder: &Ident,
         mut_ref: &Ident,
     ) -> TokenStream {
-        let presence = if implicit_presence {
-            "Implicit"
+        if self.drops_unknown_enum() {
+            quote! { *#mut_ref = val; }
         } else {
-            "Explicit"
-        };
-        let presence_ident = Ident::new(presence, Span::call_site());
+            self.generate_decode_mut(gen, decoder, mut_ref)
+        }
+    }
 
+    /// Generate statements that decode a value and write it to `mut_ref`.
+    ///
+    /// Values are always written, even for fields with implicit presence, since the last value on
+    /// the wire must win, even if it's the default value.
+    pub(crate) fn generate_decode_mut(
+        &self,
+        gen: &Generator,
+        decoder: &Ident,
+        mut_ref: &Ident,
+    ) -> TokenStream {
         match self {
             TypeSpec::Message(_) => quote! { #mut_ref.decode_len_delimited(#decoder)?; },
-            TypeSpec::Enum(_)
+            // Appending the bytes is equivalent to merging the messages
+            TypeSpec::LazyMessage { .. } => quote! { #decoder.decode_bytes_append(#mut_ref)?; },
+            TypeSpec::BytesRange => quote! { #decoder.decode_bytes_range(#mut_ref)?; },
+            TypeSpec::Wrapper(value) => {
+                let wire_type = value.wire_type();
+                let decode_value = value.generate_decode_mut(gen, decoder, mut_ref);
+                quote! {
+                    #decoder.decode_wrapper(#wire_type, |#decoder| {
+                        #decode_value
+                        Ok(())
+                    })?;
+                }
+            }
+            TypeSpec::Enum(..)
             | TypeSpec::Float
             | TypeSpec::Double
             | TypeSpec::Bool
@@ -362,38 +677,45 @@ impl TypeSpec {
                 let val_expr = self
                     .generate_decode_val(gen, decoder)
                     .expect("ints should be packable");
-                let setter = if implicit_presence {
-                    let val_ref = Ident::new("val_ref", Span::call_site());
-                    let presence_check = self.generate_implicit_presence_check(&val_ref);
-                    quote! {
-                        let #val_ref = &val;
-                        #presence_check {
-                            *#mut_ref = val as _;
-                        }
-                    }
-                } else {
-                    quote! { *#mut_ref = val as _; }
-                };
                 quote! {
                     let val = #val_expr?;
-                    #setter
+                    *#mut_ref = val as _;
                 }
             }
-            TypeSpec::String { .. } => {
-                quote! { #decoder.decode_string(#mut_ref, ::micropb::Presence::#presence_ident)?; }
-            }
-            TypeSpec::Bytes { .. } => {
-                quote! { #decoder.decode_bytes(#mut_ref, ::micropb::Presence::#presence_ident)?; }
+            TypeSpec::String {
+                on_overflow,
+                as_bytes: false,
+                ..
+            } => match on_overflow.generate_policy() {
+                Some(policy) => quote! {
+                    #decoder.decode_string_with_policy(#mut_ref, ::micropb::Presence::Explicit, #policy)?;
+                },
+                None => {
+                    quote! { #decoder.decode_string(#mut_ref, ::micropb::Presence::Explicit)?; }
+                }
+            },
+            TypeSpec::String {
+                on_overflow,
+                as_bytes: true,
+                ..
             }
+            | TypeSpec::Bytes { on_overflow, .. } => match on_overflow.generate_policy() {
+                Some(policy) => quote! {
+                    #decoder.decode_bytes_with_policy(#mut_ref, ::micropb::Presence::Explicit, #policy)?;
+                },
+                None => {
+                    quote! { #decoder.decode_bytes(#mut_ref, ::micropb::Presence::Explicit)?; }
+                }
+            },
         }
     }
 
-    pub(crate) fn generate_sizeof(&self, _gen: &Generator, val_ref: &Ident) -> TokenStream {
+    pub(crate) fn generate_sizeof(&self, gen: &Generator, val_ref: &Ident) -> TokenStream {
         match self {
             TypeSpec::Message(_) => {
                 quote! { ::micropb::size::sizeof_len_record(#val_ref.compute_size()) }
             }
-            TypeSpec::Enum(_) => quote! { ::micropb::size::sizeof_int32(#val_ref.0 as _) },
+            TypeSpec::Enum(..) => quote! { ::micropb::size::sizeof_int32(#val_ref.0 as _) },
             TypeSpec::Float => quote! { 4 },
             TypeSpec::Double => quote! { 8 },
             TypeSpec::Bool => quote! { 1 },
@@ -401,19 +723,65 @@ impl TypeSpec {
             TypeSpec::String { .. } => {
                 quote! { ::micropb::size::sizeof_len_record(#val_ref.len()) }
             }
-            TypeSpec::Bytes { .. } => quote! { ::micropb::size::sizeof_len_record(#val_ref.len()) },
+            TypeSpec::Bytes { .. } | TypeSpec::LazyMessage { .. } => {
+                quote! { ::micropb::size::sizeof_len_record(#val_ref.len()) }
+            }
+            TypeSpec::Wrapper(value) => {
+                let len = value.generate_wrapper_len(gen, val_ref);
+                quote! { ::micropb::size::sizeof_len_record(#len) }
+            }
+            TypeSpec::BytesRange => unreachable!("range fields can't be encoded"),
         }
     }
 
+    /// Generate an expression for the length of a wrapper message containing this value. The
+    /// `value` field of the wrapper is omitted if it's zero.
+    fn generate_wrapper_len(&self, gen: &Generator, val_ref: &Ident) -> TokenStream {
+        let check = self.generate_implicit_presence_check(val_ref);
+        let sizeof_value = self.generate_sizeof(gen, val_ref);
+        quote! {{
+            let mut len = 0;
+            #check { len = 1 + #sizeof_value; }
+            len
+        }}
+    }
+
+    /// Maximum size of the value on the wire, without the tag. Returns `None` if the size is
+    /// unbounded.
+    pub(crate) fn max_size(&self, gen: &Generator) -> Option<MaxSize> {
+        let size = match self {
+            TypeSpec::Message(tname) => {
+                let rust_type = gen.resolve_type_name(tname);
+                let size = quote! { <#rust_type as ::micropb::MessageEncode>::MAX_SIZE };
+                return Some(MaxSize::Expr(size).len_record());
+            }
+            TypeSpec::Enum(..) => 10,
+            TypeSpec::Float => 4,
+            TypeSpec::Double => 8,
+            TypeSpec::Bool => 1,
+            TypeSpec::Int(pbint, int_size) => pbint.max_size(int_size),
+            TypeSpec::String { max_bytes, .. }
+            | TypeSpec::Bytes { max_bytes, .. }
+            | TypeSpec::LazyMessage { max_bytes, .. } => {
+                ::micropb::size::sizeof_len_record((*max_bytes)? as usize)
+            }
+            TypeSpec::Wrapper(value) => {
+                return Some(MaxSize::Known(1).add(value.max_size(gen)?).len_record());
+            }
+            TypeSpec::BytesRange => return None,
+        };
+        Some(MaxSize::Known(size))
+    }
+
     pub(crate) fn generate_encode_expr(
         &self,
-        _gen: &Generator,
+        gen: &Generator,
         encoder: &Ident,
         val_ref: &Ident,
     ) -> TokenStream {
         match self {
             TypeSpec::Message(_) => quote! { #val_ref.encode_len_delimited(#encoder) },
-            TypeSpec::Enum(_) => quote! { #encoder.encode_int32(#val_ref.0 as _) },
+            TypeSpec::Enum(..) => quote! { #encoder.encode_int32(#val_ref.0 as _) },
             TypeSpec::Float => quote! { #encoder.encode_float(* #val_ref) },
             TypeSpec::Double => quote! { #encoder.encode_double(* #val_ref) },
             TypeSpec::Bool => quote! { #encoder.encode_bool(* #val_ref) },
@@ -421,12 +789,609 @@ impl TypeSpec {
                 let func = pbint.generate_encode_func(int_size);
                 quote! { #encoder.#func(* #val_ref as _) }
             }
-            TypeSpec::String { .. } => quote! { #encoder.encode_string(#val_ref) },
-            TypeSpec::Bytes { .. } => quote! { #encoder.encode_bytes(#val_ref) },
+            TypeSpec::String {
+                as_bytes: false, ..
+            } => quote! { #encoder.encode_string(#val_ref) },
+            TypeSpec::String { as_bytes: true, .. } => quote! { #encoder.encode_bytes(#val_ref) },
+            TypeSpec::Bytes { .. } | TypeSpec::LazyMessage { .. } => {
+                quote! { #encoder.encode_bytes(#val_ref) }
+            }
+            TypeSpec::Wrapper(value) => {
+                let tag_val = (1 << 3) | value.wire_type() as u32;
+                let check = value.generate_implicit_presence_check(val_ref);
+                let len = value.generate_wrapper_len(gen, val_ref);
+                let encode_value = value.generate_encode_expr(gen, encoder, val_ref);
+                quote! {
+                    #encoder.encode_len_delimited(
+                        || #len,
+                        |#encoder| {
+                            #check {
+                                #encoder.encode_varint32(#tag_val)?;
+                                #encode_value?;
+                            }
+                            Ok(())
+                        },
+                    )
+                }
+            }
+            TypeSpec::BytesRange => unreachable!("range fields can't be encoded"),
+        }
+    }
+
+    /// Generate an expression that writes the value as JSON into `writer`. Returns `None` for
+    /// types that can't be written as JSON.
+    pub(crate) fn generate_json_expr(
+        &self,
+        writer: &Ident,
+        val_ref: &Ident,
+    ) -> Option<TokenStream> {
+        let expr = match self {
+            TypeSpec::Message(_) => {
+                quote! { ::micropb::json::MessageToJson::write_json(#val_ref, #writer) }
+            }
+            TypeSpec::Enum(..) => quote! { ::micropb::json::write_enum(#writer, * #val_ref) },
+            TypeSpec::Float => quote! { ::micropb::json::write_f32(#writer, * #val_ref) },
+            TypeSpec::Double => quote! { ::micropb::json::write_f64(#writer, * #val_ref) },
+            TypeSpec::Bool => quote! { ::micropb::json::write_bool(#writer, * #val_ref) },
+            // 64-bit integers are written as strings, even if they're stored in smaller types
+            TypeSpec::Int(
+                PbInt::Int64 | PbInt::Uint64 | PbInt::Sint64 | PbInt::Fixed64 | PbInt::Sfixed64,
+                _,
+            ) => quote! { ::micropb::json::write_num_str(#writer, * #val_ref) },
+            TypeSpec::Int(..) => quote! { ::micropb::json::write_num(#writer, * #val_ref) },
+            TypeSpec::String {
+                as_bytes: false, ..
+            } => quote! { ::micropb::json::write_str(#writer, #val_ref) },
+            TypeSpec::String { as_bytes: true, .. } => {
+                quote! { ::micropb::json::write_str_bytes(#writer, #val_ref) }
+            }
+            TypeSpec::Bytes { .. } => quote! { ::micropb::json::write_bytes(#writer, #val_ref) },
+            // Wrappers are written as their values in JSON
+            TypeSpec::Wrapper(value) => return value.generate_json_expr(writer, val_ref),
+            TypeSpec::BytesRange | TypeSpec::LazyMessage { .. } => return None,
+        };
+        Some(expr)
+    }
+
+    /// Generate an expression that writes the key of a JSON map entry into the JSON object `obj`,
+    /// returning the writer for the value. Returns `None` for keys that can't be written as JSON.
+    pub(crate) fn generate_json_key_expr(
+        &self,
+        obj: &Ident,
+        val_ref: &Ident,
+    ) -> Option<TokenStream> {
+        match self {
+            TypeSpec::String {
+                as_bytes: false, ..
+            } => Some(quote! { #obj.key(#val_ref) }),
+            TypeSpec::Bool | TypeSpec::Int(..) => Some(quote! { #obj.key_display(#val_ref) }),
+            _ => None,
+        }
+    }
+
+    /// Generate statements that parse a JSON value into `mut_ref`. Returns `None` for types that
+    /// can't be parsed from JSON, including strings and bytes that borrow their data.
+    pub(crate) fn generate_json_parse_mut(
+        &self,
+        parser: &Ident,
+        mut_ref: &Ident,
+    ) -> Option<TokenStream> {
+        let stmt = match self {
+            TypeSpec::Message(_) => {
+                quote! { ::micropb::json::MessageFromJson::merge_json(#mut_ref, #parser)?; }
+            }
+            TypeSpec::Enum(..) => quote! { *#mut_ref = #parser.parse_enum()?; },
+            TypeSpec::Float => quote! { *#mut_ref = #parser.parse_f32()?; },
+            TypeSpec::Double => quote! { *#mut_ref = #parser.parse_f64()?; },
+            TypeSpec::Bool => quote! { *#mut_ref = #parser.parse_bool()?; },
+            TypeSpec::Int(..) => quote! { *#mut_ref = #parser.parse_int()?; },
+            TypeSpec::String { type_path, .. } | TypeSpec::Bytes { type_path, .. }
+                if type_path.find_lifetime().is_some() =>
+            {
+                return None
+            }
+            TypeSpec::String {
+                as_bytes: false, ..
+            } => quote! { #parser.parse_str(#mut_ref)?; },
+            TypeSpec::String { as_bytes: true, .. } => {
+                quote! { #parser.parse_str_bytes(#mut_ref)?; }
+            }
+            TypeSpec::Bytes { .. } => quote! { #parser.parse_bytes(#mut_ref)?; },
+            TypeSpec::Wrapper(value) => return value.generate_json_parse_mut(parser, mut_ref),
+            TypeSpec::BytesRange | TypeSpec::LazyMessage { .. } => return None,
+        };
+        Some(stmt)
+    }
+
+    /// Generate an expression that parses the key of a JSON map entry. Returns `None` for keys
+    /// that can't be parsed from JSON.
+    pub(crate) fn generate_json_key_parse(
+        &self,
+        parser: &Ident,
+        key: &Ident,
+    ) -> Option<TokenStream> {
+        match self {
+            TypeSpec::String {
+                type_path,
+                as_bytes: false,
+                ..
+            } if type_path.find_lifetime().is_none() => {
+                Some(quote! { #parser.parse_key_str(#key)? })
+            }
+            TypeSpec::Bool => Some(quote! { #parser.parse_key_bool(#key)? }),
+            TypeSpec::Int(..) => Some(quote! { #parser.parse_key_int(#key)? }),
+            _ => None,
+        }
+    }
+
+    /// Generate an expression that writes the value in text format as the field `name` into the
+    /// text writer `t`. Returns `None` for types that can't be written in text format.
+    pub(crate) fn generate_text_expr(
+        &self,
+        t: &Ident,
+        name: &str,
+        val_ref: &Ident,
+    ) -> Option<TokenStream> {
+        let write = match self {
+            TypeSpec::Message(_) => {
+                return Some(quote! {
+                    #t.message(#name, |t| ::micropb::text::MessageToText::write_text_fields(#val_ref, t))
+                });
+            }
+            // Wrappers are written as messages with a `value` field that's omitted if it's zero
+            TypeSpec::Wrapper(value) => {
+                let inner = Ident::new("t", Span::call_site());
+                let check = value.generate_implicit_presence_check(val_ref);
+                let value_expr = value.generate_text_expr(&inner, "value", val_ref)?;
+                return Some(quote! {
+                    #t.message(#name, |#inner| {
+                        #check { #value_expr?; }
+                        Ok(())
+                    })
+                });
+            }
+            TypeSpec::Enum(..) => quote! { ::micropb::text::write_enum(w, * #val_ref) },
+            TypeSpec::Float => quote! { ::micropb::text::write_f32(w, * #val_ref) },
+            TypeSpec::Double => quote! { ::micropb::text::write_f64(w, * #val_ref) },
+            TypeSpec::Bool => quote! { ::micropb::text::write_bool(w, * #val_ref) },
+            TypeSpec::Int(..) => quote! { ::micropb::text::write_num(w, * #val_ref) },
+            TypeSpec::String {
+                as_bytes: false, ..
+            } => quote! { ::micropb::text::write_str(w, #val_ref) },
+            // Strings stored as bytes may not be valid UTF-8, so they're escaped like bytes
+            TypeSpec::String { as_bytes: true, .. } | TypeSpec::Bytes { .. } => {
+                quote! { ::micropb::text::write_bytes(w, #val_ref) }
+            }
+            TypeSpec::BytesRange | TypeSpec::LazyMessage { .. } => return None,
+        };
+        Some(quote! {{
+            let w = #t.field(#name)?;
+            #write
+        }})
+    }
+
+    /// Generate statements that parse a text format value into `mut_ref`, along with whether the
+    /// value is a message in braces. Returns `None` for types that can't be parsed from text
+    /// format, including strings and bytes that borrow their data.
+    pub(crate) fn generate_text_parse_mut(
+        &self,
+        parser: &Ident,
+        mut_ref: &Ident,
+    ) -> Option<(TokenStream, bool)> {
+        let stmt = match self {
+            TypeSpec::Message(_) => {
+                let stmt =
+                    quote! { ::micropb::text::MessageFromText::merge_text(#mut_ref, #parser)?; };
+                return Some((stmt, true));
+            }
+            TypeSpec::Enum(..) => quote! { *#mut_ref = #parser.parse_enum()?; },
+            TypeSpec::Float => quote! { *#mut_ref = #parser.parse_f32()?; },
+            TypeSpec::Double => quote! { *#mut_ref = #parser.parse_f64()?; },
+            TypeSpec::Bool => quote! { *#mut_ref = #parser.parse_bool()?; },
+            TypeSpec::Int(..) => quote! { *#mut_ref = #parser.parse_int()?; },
+            TypeSpec::String { type_path, .. } | TypeSpec::Bytes { type_path, .. }
+                if type_path.find_lifetime().is_some() =>
+            {
+                return None
+            }
+            TypeSpec::String {
+                as_bytes: false, ..
+            } => quote! { #parser.parse_str(#mut_ref)?; },
+            TypeSpec::String { as_bytes: true, .. } | TypeSpec::Bytes { .. } => {
+                quote! { #parser.parse_bytes(#mut_ref)?; }
+            }
+            TypeSpec::Wrapper(value) => {
+                let (value_stmts, _) = value.generate_text_parse_mut(parser, mut_ref)?;
+                let stmt = quote! {
+                    #parser.parse_fields(|#parser, name| match name {
+                        "value" => #parser.parse_field(|#parser| {
+                            #value_stmts
+                            ::core::result::Result::Ok(())
+                        }),
+                        _ => #parser.unknown_field(),
+                    })?;
+                };
+                return Some((stmt, true));
+            }
+            TypeSpec::BytesRange | TypeSpec::LazyMessage { .. } => return None,
+        };
+        Some((stmt, false))
+    }
+
+    /// Generate an expression that builds an arbitrary value from the `arbitrary::Unstructured`
+    /// named `u`. Strings and bytes are truncated to fit their containers, and nested messages
+    /// are generated one level deeper than `depth`.
+    pub(crate) fn generate_arbitrary(&self, gen: &Generator, depth: &Ident) -> TokenStream {
+        match self {
+            // Extern types aren't expected to implement `Arbitrary`
+            TypeSpec::Message(tname) | TypeSpec::Enum(tname, _)
+                if gen.extern_paths.contains_key(tname) =>
+            {
+                quote! { ::core::default::Default::default() }
+            }
+            TypeSpec::Message(tname) => {
+                let typ = gen.resolve_type_name(tname);
+                quote! { #typ::_arbitrary_depth(u, #depth.saturating_sub(1))? }
+            }
+            TypeSpec::Enum(..)
+            | TypeSpec::Float
+            | TypeSpec::Double
+            | TypeSpec::Bool
+            | TypeSpec::Int(..) => quote! { u.arbitrary()? },
+            TypeSpec::String { type_path, .. } | TypeSpec::Bytes { type_path, .. }
+                if type_path.find_lifetime().is_some() =>
+            {
+                quote! { u.arbitrary()? }
+            }
+            TypeSpec::String {
+                as_bytes: false, ..
+            } => {
+                let typ = self.generate_rust_type(gen);
+                let s = Ident::new("s", Span::call_site());
+                let fit = generate_fit_str(&typ, &s);
+                quote! {{
+                    let #s: &str = u.arbitrary()?;
+                    #fit.map_err(|_| ::arbitrary::Error::IncorrectFormat)?
+                }}
+            }
+            TypeSpec::String { as_bytes: true, .. }
+            | TypeSpec::Bytes { .. }
+            | TypeSpec::LazyMessage { .. } => {
+                let typ = self.generate_rust_type(gen);
+                // Strings stored as bytes are still generated as UTF-8
+                let bytes = if let TypeSpec::String { .. } = self {
+                    quote! { u.arbitrary::<&str>()?.as_bytes() }
+                } else {
+                    quote! { u.arbitrary()? }
+                };
+                let b = Ident::new("b", Span::call_site());
+                let fit = generate_fit_bytes(&typ, &b);
+                quote! {{
+                    let #b: &[u8] = #bytes;
+                    #fit.map_err(|_| ::arbitrary::Error::IncorrectFormat)?
+                }}
+            }
+            TypeSpec::BytesRange => quote! { ::core::default::Default::default() },
+            TypeSpec::Wrapper(value) => value.generate_arbitrary(gen, depth),
+        }
+    }
+
+    /// Generate an expression that evaluates to a `proptest` strategy for values of the type.
+    /// Strings and bytes stay within `max_bytes`, and nested messages are generated one level
+    /// deeper than `depth`.
+    pub(crate) fn generate_strategy(&self, gen: &Generator, depth: &Ident) -> TokenStream {
+        let typ = self.generate_rust_type(gen);
+        let max = |max_bytes: &Option<u32>| {
+            Literal::usize_suffixed(max_bytes.unwrap_or(PROPTEST_MAX_LEN) as usize)
+        };
+        let alloc = gen.alloc_crate();
+        let chars = |max_bytes: &Option<u32>| {
+            let max = max(max_bytes);
+            quote! {
+                ::proptest::collection::vec(::proptest::prelude::any::<char>(), 0..=#max)
+                    .prop_map(|chars| chars.into_iter().collect::<#alloc::string::String>())
+            }
+        };
+        match self {
+            // Extern types aren't expected to have strategies
+            TypeSpec::Message(tname) | TypeSpec::Enum(tname, _)
+                if gen.extern_paths.contains_key(tname) =>
+            {
+                quote! { ::proptest::strategy::LazyJust::new(<#typ as ::core::default::Default>::default) }
+            }
+            TypeSpec::Message(_) => {
+                quote! { #typ::_strategy_depth(#depth.saturating_sub(1)) }
+            }
+            TypeSpec::Enum(..) => quote! { #typ::arbitrary_strategy() },
+            TypeSpec::Float | TypeSpec::Double | TypeSpec::Bool | TypeSpec::Int(..) => {
+                quote! { ::proptest::prelude::any::<#typ>() }
+            }
+            TypeSpec::String { type_path, .. } | TypeSpec::Bytes { type_path, .. }
+                if type_path.find_lifetime().is_some() =>
+            {
+                quote! { ::proptest::prelude::any::<#typ>() }
+            }
+            TypeSpec::String {
+                as_bytes: false,
+                max_bytes,
+                ..
+            } => {
+                let chars = chars(max_bytes);
+                let s = Ident::new("s", Span::call_site());
+                let fit = generate_fit_str(&typ, &s);
+                // Characters can take up to 4 bytes, so the string may still need to be cut
+                quote! { #chars.prop_map(|#s| #fit.unwrap_or_default()) }
+            }
+            TypeSpec::String {
+                as_bytes: true,
+                max_bytes,
+                ..
+            } => {
+                let chars = chars(max_bytes);
+                let b = Ident::new("b", Span::call_site());
+                let fit = generate_fit_bytes(&typ, &b);
+                quote! { #chars.prop_map(|s| { let #b = s.as_bytes(); #fit.unwrap_or_default() }) }
+            }
+            TypeSpec::Bytes { max_bytes, .. } | TypeSpec::LazyMessage { max_bytes, .. } => {
+                let max = max(max_bytes);
+                let b = Ident::new("b", Span::call_site());
+                let fit = generate_fit_bytes(&typ, &b);
+                quote! {
+                    ::proptest::collection::vec(::proptest::prelude::any::<u8>(), 0..=#max)
+                        .prop_map(|#b| #fit.unwrap_or_default())
+                }
+            }
+            TypeSpec::BytesRange => {
+                quote! { ::proptest::strategy::LazyJust::new(<#typ as ::core::default::Default>::default) }
+            }
+            TypeSpec::Wrapper(value) => value.generate_strategy(gen, depth),
+        }
+    }
+
+    /// Generate an expression that evaluates to a deterministic value of the type for round-trip
+    /// tests. If `fill` is true, strings and bytes fill their containers and numbers are at their
+    /// minimum, otherwise they hold a single element and numbers are at their maximum.
+    pub(crate) fn generate_sample(
+        &self,
+        gen: &Generator,
+        fill: &Ident,
+        depth: &Ident,
+    ) -> TokenStream {
+        let typ = self.generate_rust_type(gen);
+        let fill_len = |max_bytes: &Option<u32>| max_bytes.unwrap_or(ROUNDTRIP_FILL_LEN) as usize;
+        match self {
+            // Extern types aren't expected to have samples
+            TypeSpec::Message(tname) if gen.extern_paths.contains_key(tname) => {
+                quote! { ::core::default::Default::default() }
+            }
+            TypeSpec::Message(_) => {
+                quote! { #typ::_roundtrip_sample(#fill, #depth.saturating_sub(1)) }
+            }
+            // Unknown enum values may be dropped when decoding, so stick to the default variant
+            TypeSpec::Enum(..) | TypeSpec::BytesRange | TypeSpec::LazyMessage { .. } => {
+                quote! { ::core::default::Default::default() }
+            }
+            TypeSpec::Float | TypeSpec::Double | TypeSpec::Int(..) => {
+                quote! { if #fill { <#typ>::MIN } else { <#typ>::MAX } }
+            }
+            TypeSpec::Bool => quote! { true },
+            TypeSpec::String {
+                as_bytes,
+                max_bytes,
+                ..
+            } => {
+                let full = Literal::string(&"x".repeat(fill_len(max_bytes)));
+                // Multi-byte characters check that strings are cut at character boundaries
+                let single = Literal::string("\u{10FFFF}");
+                let s = Ident::new("s", Span::call_site());
+                let fit = if *as_bytes {
+                    let b = Ident::new("b", Span::call_site());
+                    let fit = generate_fit_bytes(&typ, &b);
+                    quote! {{ let #b = #s.as_bytes(); #fit }}
+                } else {
+                    generate_fit_str(&typ, &s)
+                };
+                quote! {{
+                    let #s: &str = if #fill { #full } else { #single };
+                    #fit.unwrap_or_default()
+                }}
+            }
+            TypeSpec::Bytes { max_bytes, .. } => {
+                let len = fill_len(max_bytes);
+                let b = Ident::new("b", Span::call_site());
+                let fit = generate_fit_bytes(&typ, &b);
+                quote! {{
+                    let #b: &[u8] = if #fill { &[0xFF; #len] } else { &[0x80] };
+                    #fit.unwrap_or_default()
+                }}
+            }
+            TypeSpec::Wrapper(value) => value.generate_sample(gen, fill, depth),
+        }
+    }
+
+    /// Generate an expression that evaluates to a map key for round-trip tests, which is distinct
+    /// for each value of the index `i`, as long as the key type allows it
+    pub(crate) fn generate_sample_key(
+        &self,
+        gen: &Generator,
+        fill: &Ident,
+        depth: &Ident,
+        i: &Ident,
+    ) -> TokenStream {
+        let typ = self.generate_rust_type(gen);
+        match self {
+            TypeSpec::Int(..) => quote! { #i as #typ },
+            TypeSpec::Bool => quote! { #i % 2 == 1 },
+            TypeSpec::String { as_bytes, .. } => {
+                let s = Ident::new("s", Span::call_site());
+                let fit = if *as_bytes {
+                    let b = Ident::new("b", Span::call_site());
+                    let fit = generate_fit_bytes(&typ, &b);
+                    quote! {{ let #b = #s.as_bytes(); #fit }}
+                } else {
+                    generate_fit_str(&typ, &s)
+                };
+                // Spell out the index in base 26 with lowercase letters
+                quote! {{
+                    let mut buf = [0u8; 16];
+                    let mut len = 0;
+                    let mut n = #i;
+                    loop {
+                        buf[len] = b'a' + (n % 26) as u8;
+                        len += 1;
+                        n /= 26;
+                        if n == 0 {
+                            break;
+                        }
+                    }
+                    let #s = ::core::str::from_utf8(&buf[..len]).unwrap();
+                    #fit.unwrap_or_default()
+                }}
+            }
+            _ => self.generate_sample(gen, fill, depth),
+        }
+    }
+
+    /// Generate an expression that converts the owned value `val` of this type into the type that
+    /// `prost` uses to represent it
+    pub(crate) fn generate_to_prost(&self, gen: &Generator, val: TokenStream) -> TokenStream {
+        match self {
+            TypeSpec::Message(_) => quote! { ::core::convert::Into::into(#val) },
+            TypeSpec::Enum(..) => quote! { #val.0 as i32 },
+            TypeSpec::Float | TypeSpec::Double | TypeSpec::Bool => val,
+            TypeSpec::Int(pbint, _) => {
+                let typ = pbint.prost_type();
+                quote! { #val as #typ }
+            }
+            TypeSpec::String {
+                as_bytes: false, ..
+            }
+            | TypeSpec::Bytes { .. } => quote! { ::core::convert::From::from(&*#val) },
+            // The bytes may not be valid UTF-8, unlike `String`
+            TypeSpec::String { as_bytes: true, .. } => {
+                let alloc = gen.alloc_crate();
+                quote! { #alloc::string::String::from_utf8_lossy(&#val).into_owned() }
+            }
+            TypeSpec::Wrapper(inner) => inner.generate_to_prost(gen, val),
+            TypeSpec::BytesRange | TypeSpec::LazyMessage { .. } => {
+                unreachable!("unsupported types are rejected when parsing the message")
+            }
+        }
+    }
+
+    /// Generate an expression that converts the owned `prost` value `val` into this type.
+    /// The expression uses `?` to return `ConversionError` if the value doesn't fit in a
+    /// fixed-capacity container or in a narrowed integer type.
+    pub(crate) fn generate_from_prost(&self, gen: &Generator, val: TokenStream) -> TokenStream {
+        let int_err = quote! { .map_err(|_| ::micropb::ConversionError::IntOverflow)? };
+        let cap_err = quote! { .map_err(|_| ::micropb::ConversionError::Capacity)? };
+        match self {
+            TypeSpec::Message(_) => quote! { ::core::convert::TryFrom::try_from(#val)? },
+            TypeSpec::Enum(..) => {
+                let typ = self.generate_rust_type(gen);
+                quote! { #typ(::core::convert::TryFrom::try_from(#val)#int_err) }
+            }
+            TypeSpec::Float | TypeSpec::Double | TypeSpec::Bool => val,
+            TypeSpec::Int(..) => quote! { ::core::convert::TryFrom::try_from(#val)#int_err },
+            TypeSpec::String {
+                as_bytes: false, ..
+            } => quote! { ::micropb::PbString::pb_from_str(&#val)#cap_err },
+            TypeSpec::String { as_bytes: true, .. } => {
+                quote! { ::micropb::PbVec::pb_from_slice(#val.as_bytes())#cap_err }
+            }
+            TypeSpec::Bytes { .. } => quote! { ::micropb::PbVec::pb_from_slice(&#val)#cap_err },
+            TypeSpec::Wrapper(inner) => inner.generate_from_prost(gen, val),
+            TypeSpec::BytesRange | TypeSpec::LazyMessage { .. } => {
+                unreachable!("unsupported types are rejected when parsing the message")
+            }
+        }
+    }
+
+    /// Generate an expression that encodes the tag and value of a scalar field through
+    /// `PbEncoder::encode_tagged`, which is shared between all fields of the same type. Returns
+    /// `None` for other types.
+    pub(crate) fn generate_encode_tagged_expr(
+        &self,
+        encoder: &Ident,
+        tag_val: u32,
+        val_ref: &Ident,
+    ) -> Option<TokenStream> {
+        let (func, val) = match self {
+            TypeSpec::Enum(..) => (format_ident!("encode_int32"), quote! { #val_ref.0 }),
+            TypeSpec::Float => (format_ident!("encode_float"), quote! { * #val_ref }),
+            TypeSpec::Double => (format_ident!("encode_double"), quote! { * #val_ref }),
+            TypeSpec::Bool => (format_ident!("encode_bool"), quote! { * #val_ref }),
+            TypeSpec::Int(pbint, int_size) => (
+                pbint.generate_encode_func(int_size),
+                quote! { * #val_ref as _ },
+            ),
+            _ => return None,
+        };
+        Some(quote! { #encoder.encode_tagged(#tag_val, #val, ::micropb::PbEncoder::#func) })
+    }
+
+    /// Generate an expression that checks the wire type of `tag` and decodes the value of a
+    /// scalar field through `PbDecoder::decode_tagged`, which is shared between all fields of the
+    /// same type. Returns `None` for other types, including enums.
+    pub(crate) fn generate_decode_tagged_expr(
+        &self,
+        decoder: &Ident,
+        tag: &Ident,
+    ) -> Option<TokenStream> {
+        let func = match self {
+            TypeSpec::Float => format_ident!("decode_float"),
+            TypeSpec::Double => format_ident!("decode_double"),
+            TypeSpec::Bool => format_ident!("decode_bool"),
+            // Pointer-sized ints need a checked conversion after decoding
+            TypeSpec::Int(_, IntSize::Size) => return None,
+            TypeSpec::Int(pbint, int_size) => pbint.generate_decode_func(int_size),
+            _ => return None,
+        };
+        let wire_type = self.wire_type();
+        Some(quote! { #decoder.decode_tagged(#tag, #wire_type, ::micropb::PbDecoder::#func) })
+    }
+
+    pub(crate) fn generate_encode_reverse_expr(
+        &self,
+        gen: &Generator,
+        encoder: &Ident,
+        val_ref: &Ident,
+    ) -> TokenStream {
+        match self {
+            TypeSpec::Message(_) => quote! { #val_ref.encode_reverse_len_delimited(#encoder) },
+            TypeSpec::Wrapper(value) => {
+                let tag_val = (1 << 3) | value.wire_type() as u32;
+                let check = value.generate_implicit_presence_check(val_ref);
+                let encode_value = value.generate_encode_reverse_expr(gen, encoder, val_ref);
+                quote! {
+                    #encoder.encode_len_delimited(|#encoder| {
+                        #check {
+                            #encode_value?;
+                            #encoder.encode_varint32(#tag_val)?;
+                        }
+                        Ok(())
+                    })
+                }
+            }
+            // The encoding methods of `ReverseEncoder` have the same names as those of `PbEncoder`
+            _ => self.generate_encode_expr(gen, encoder, val_ref),
         }
     }
 }
 
+/// Assert at compile time that a container type's `CAPACITY` is at least the configured bound
+pub(crate) fn capacity_assert(
+    typ: TokenStream,
+    container_trait: TokenStream,
+    bound: u32,
+    msg: &str,
+) -> TokenStream {
+    let bound = Literal::usize_unsuffixed(bound as usize);
+    quote! {
+        const _: () = ::core::assert!(<#typ as #container_trait>::CAPACITY >= #bound, #msg);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::borrow::Cow;
@@ -491,14 +1456,17 @@ mod tests {
             TypeSpec::from_proto(&field_proto(Type::String, ""), &type_conf).unwrap(),
             TypeSpec::String {
                 type_path: syn::parse_str("string::String").unwrap(),
-                max_bytes: Some(10)
+                max_bytes: Some(10),
+                on_overflow: OnOverflow::Error,
+                as_bytes: false
             }
         );
         assert_eq!(
             TypeSpec::from_proto(&field_proto(Type::Bytes, ""), &type_conf).unwrap(),
             TypeSpec::Bytes {
                 type_path: syn::parse_str("vec::Vec").unwrap(),
-                max_bytes: Some(10)
+                max_bytes: Some(10),
+                on_overflow: OnOverflow::Error,
             }
         );
         assert_eq!(
@@ -507,28 +1475,117 @@ mod tests {
         );
         assert_eq!(
             TypeSpec::from_proto(&field_proto(Type::Enum, ".Enum"), &type_conf).unwrap(),
-            TypeSpec::Enum(".Enum".to_owned())
+            TypeSpec::Enum(".Enum".to_owned(), OnUnknownEnum::Preserve)
         );
 
         config.max_bytes = None;
+        config.on_unknown_enum = Some(OnUnknownEnum::Drop);
         let type_conf = CurrentConfig {
             node: None,
             config: Cow::Borrowed(&config),
         };
+        assert_eq!(
+            TypeSpec::from_proto(&field_proto(Type::Enum, ".Enum"), &type_conf).unwrap(),
+            TypeSpec::Enum(".Enum".to_owned(), OnUnknownEnum::Drop)
+        );
         assert_eq!(
             TypeSpec::from_proto(&field_proto(Type::String, ""), &type_conf).unwrap(),
             TypeSpec::String {
                 type_path: syn::parse_str("string::String").unwrap(),
-                max_bytes: None
+                max_bytes: None,
+                on_overflow: OnOverflow::Error,
+                as_bytes: false
             }
         );
         assert_eq!(
             TypeSpec::from_proto(&field_proto(Type::Bytes, ""), &type_conf).unwrap(),
             TypeSpec::Bytes {
                 type_path: syn::parse_str("vec::Vec").unwrap(),
-                max_bytes: None
+                max_bytes: None,
+                on_overflow: OnOverflow::Error,
             }
         );
+
+        config.string_as_bytes = Some(true);
+        let type_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        assert_eq!(
+            TypeSpec::from_proto(&field_proto(Type::String, ""), &type_conf).unwrap(),
+            TypeSpec::String {
+                type_path: syn::parse_str("vec::Vec").unwrap(),
+                max_bytes: None,
+                on_overflow: OnOverflow::Error,
+                as_bytes: true
+            }
+        );
+
+        config.lazy = Some(true);
+        let type_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        assert_eq!(
+            TypeSpec::from_proto(&field_proto(Type::Message, ".msg.Message"), &type_conf).unwrap(),
+            TypeSpec::LazyMessage {
+                name: ".msg.Message".to_owned(),
+                type_path: syn::parse_str("vec::Vec").unwrap(),
+                max_bytes: None,
+            }
+        );
+        TypeSpec::from_proto(&field_proto(Type::Bool, ""), &type_conf).unwrap_err();
+
+        config.lazy = None;
+        config.bytes_range = Some(true);
+        let type_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        assert_eq!(
+            TypeSpec::from_proto(&field_proto(Type::Bytes, ""), &type_conf).unwrap(),
+            TypeSpec::BytesRange
+        );
+        TypeSpec::from_proto(&field_proto(Type::String, ""), &type_conf).unwrap_err();
+
+        config.bytes_range = None;
+        config.string_as_bytes = None;
+        config.max_bytes = Some(8);
+        config.unwrap_wrappers = Some(true);
+        let type_conf = CurrentConfig {
+            node: None,
+            config: Cow::Borrowed(&config),
+        };
+        assert_eq!(
+            TypeSpec::from_proto(
+                &field_proto(Type::Message, ".google.protobuf.UInt32Value"),
+                &type_conf
+            )
+            .unwrap(),
+            TypeSpec::Wrapper(Box::new(TypeSpec::Int(PbInt::Uint32, IntSize::S32)))
+        );
+        assert_eq!(
+            TypeSpec::from_proto(
+                &field_proto(Type::Message, ".google.protobuf.StringValue"),
+                &type_conf
+            )
+            .unwrap(),
+            TypeSpec::Wrapper(Box::new(TypeSpec::String {
+                type_path: syn::parse_str("string::String").unwrap(),
+                max_bytes: Some(8),
+                on_overflow: OnOverflow::Error,
+                as_bytes: false
+            }))
+        );
+        // Other messages aren't affected
+        assert_eq!(
+            TypeSpec::from_proto(
+                &field_proto(Type::Message, ".google.protobuf.Timestamp"),
+                &type_conf
+            )
+            .unwrap(),
+            TypeSpec::Message(".google.protobuf.Timestamp".to_owned())
+        );
     }
 
     #[test]
@@ -611,7 +1668,9 @@ mod tests {
         assert_eq!(
             TypeSpec::String {
                 type_path: syn::parse_str("Vec").unwrap(),
-                max_bytes: None
+                max_bytes: None,
+                on_overflow: OnOverflow::Error,
+                as_bytes: false
             }
             .generate_default("abc\n\tddd", &gen)
             .unwrap()
@@ -622,7 +1681,8 @@ mod tests {
         assert_eq!(
             TypeSpec::Bytes {
                 type_path: syn::parse_str("Vec").unwrap(),
-                max_bytes: None
+                max_bytes: None,
+                on_overflow: OnOverflow::Error,
             }
             .generate_default("abc\\n\\t\\a\\xA0ddd", &gen)
             .unwrap()
@@ -631,4 +1691,34 @@ mod tests {
                 .to_string()
         );
     }
+
+    #[test]
+    fn size_int() {
+        let gen = Generator::new();
+        let decoder = Ident::new("decoder", Span::call_site());
+        let tspec = TypeSpec::Int(PbInt::Uint64, IntSize::Size);
+        assert_eq!(
+            tspec.generate_rust_type(&gen).to_string(),
+            quote! { usize }.to_string()
+        );
+        assert_eq!(
+            tspec
+                .generate_decode_val(&gen, &decoder)
+                .unwrap()
+                .to_string(),
+            quote! {
+                decoder.decode_varint64().and_then(|n| {
+                    <usize as ::core::convert::TryFrom<_>>::try_from(n)
+                        .map_err(|_| ::micropb::DecodeError::IntOverflow)
+                })
+            }
+            .to_string()
+        );
+        assert!(tspec
+            .generate_decode_tagged_expr(&decoder, &decoder)
+            .is_none());
+        assert!(tspec.is_64bit_as_size());
+        assert!(!TypeSpec::Int(PbInt::Sint32, IntSize::Size).is_64bit_as_size());
+        assert!(!TypeSpec::Int(PbInt::Uint64, IntSize::S32).is_64bit_as_size());
+    }
 }
@@ -25,7 +25,7 @@ use std::{
 };
 
 pub use config::Config;
-pub use generator::Generator;
+pub use generator::{GenerateError, Generator};
 use micropb::{MessageDecode, PbDecoder};
 use pathtree::PathTree;
 
@@ -51,6 +51,38 @@ impl EncodeDecode {
     }
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// Whether generated code is optimized for code size or speed
+pub enum OptimizeFor {
+    /// Small helpers such as accessors are marked `#[inline]`, and everything else is left to the
+    /// compiler
+    #[default]
+    Balanced,
+    /// The encoding and decoding logic of messages is marked `#[inline]`, in addition to small
+    /// helpers
+    Speed,
+    /// The encoding and decoding logic of messages is marked `#[inline(never)]` and nothing is
+    /// marked `#[inline]`. Encoding and decoding of scalar fields is factored into shared helpers
+    /// from `micropb`, rather than being expanded for each field.
+    Size,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Parts of the Rust standard library available to the crate that includes the generated code
+pub enum Target {
+    /// Only `core` is available, so the generated code can't allocate. Boxed fields are rejected,
+    /// and containers must be configured explicitly, such as with
+    /// [`use_container_heapless`](Generator::use_container_heapless).
+    NoAlloc,
+    /// `core` and `alloc` are available, as in `no_std` crates with an allocator. Heap types are
+    /// referred to through `::alloc`, so the crate needs `extern crate alloc`. `alloc` containers
+    /// are used by default.
+    Alloc,
+    /// The full standard library is available. Heap types are referred to through `::std`, so
+    /// the crate doesn't need `extern crate alloc`. `std` containers are used by default.
+    Std,
+}
+
 type WarningCb = fn(fmt::Arguments);
 
 fn warn_cargo_build(args: fmt::Arguments) {
@@ -76,17 +108,34 @@ impl Generator {
             pkg_path: Default::default(),
             pkg: Default::default(),
             type_path: Default::default(),
+            file: Default::default(),
 
             warning_cb,
 
             encode_decode: Default::default(),
+            feature_gates: Default::default(),
+            dyn_encode: Default::default(),
+            optimize: Default::default(),
+            field_stats: Default::default(),
+            roundtrip_tests: Default::default(),
+            services: Default::default(),
+            capacity_asserts: Default::default(),
+            encode_reverse: Default::default(),
+            max_size: Default::default(),
+            missing_fields: Default::default(),
+            message_names: Default::default(),
+            in_place_clear: Default::default(),
+            roundtrip_test_fns: Default::default(),
+            domain_error_used: Default::default(),
             retain_enum_prefix: Default::default(),
+            target: Default::default(),
             format: true,
             fdset_path: Default::default(),
             protoc_args: Default::default(),
 
             config_tree,
             extern_paths: Default::default(),
+            inline_fields: Default::default(),
         }
     }
 
@@ -97,6 +146,15 @@ impl Generator {
     /// type, or field in the compiled `.proto` files. The configurations are applied to the
     /// element specified by `proto_path`, as well as its children.
     ///
+    /// Oneofs and their variants are addressed as follows:
+    /// - `.pkg.Message.my_oneof` configures the oneof itself, such as its boxing or the attributes
+    ///   of its enum. This isn't inherited by the variants.
+    /// - `.pkg.Message.my_variant` or `.pkg.Message.my_oneof.my_variant` configures a variant. If
+    ///   both are configured, the options set through the oneof take precedence.
+    ///
+    /// Paths that don't match anything in the compiled files, such as a variant that isn't in the
+    /// oneof, cause an "Unused configuration path" warning.
+    ///
     /// # Example
     /// ```
     /// # use micropb_gen::{Generator, Config, config::IntSize};
@@ -121,6 +179,8 @@ impl Generator {
     ///
     /// // Configure the int size on a variant of an oneof
     /// gen.configure(".pkg.Message.my_oneof_variant", Config::new().int_size(IntSize::S8));
+    /// // Same as above, but through the oneof
+    /// gen.configure(".pkg.Message.my_oneof.my_oneof_variant", Config::new().int_size(IntSize::S8));
     ///
     /// // Configure the int size of an enum
     /// // Note that enum variants cannot be configured
@@ -268,8 +328,41 @@ impl Generator {
         self
     }
 
+    /// Substitute `google.protobuf.Timestamp`, `google.protobuf.Duration`, and
+    /// `google.protobuf.Empty` with the runtime types in `micropb::well_known`.
+    ///
+    /// If using this option, `micropb` should have the `well-known` feature enabled.
+    ///
+    /// The runtime `Timestamp` and `Duration` have the same fields as the generated messages, but
+    /// also provide normalization, range checks, and conversions to and from
+    /// `core::time::Duration` and `std::time::SystemTime`. The runtime `Empty` is a unit struct,
+    /// so `Empty` fields cost nothing beyond their presence. This uses
+    /// [`extern_type_path`](Self::extern_type_path) under the hood, so `timestamp.proto`,
+    /// `duration.proto`, and `empty.proto` don't need to be compiled.
+    ///
+    /// # Note
+    /// The runtime types implement the JSON and text format traits of `micropb`, but not the
+    /// Serde or `defmt` traits, so messages containing them can't use [`Config::serde`] or
+    /// [`Config::defmt`].
+    pub fn use_well_known_types(&mut self) -> &mut Self {
+        self.extern_type_path(
+            ".google.protobuf.Timestamp",
+            "::micropb::well_known::Timestamp",
+        )
+        .extern_type_path(
+            ".google.protobuf.Duration",
+            "::micropb::well_known::Duration",
+        )
+        .extern_type_path(".google.protobuf.Empty", "::micropb::well_known::Empty")
+    }
+
     /// Compile `.proto` files into a single Rust file.
     ///
+    /// # Errors
+    /// If a Protobuf element can't be generated, such as a proto2 group, a file using editions,
+    /// or a message that contains itself without a boxed field, the returned error wraps a
+    /// [`GenerateError`] naming the file and element, along with a suggestion if there is one.
+    ///
     /// # Example
     /// ```no_run
     /// // build.rs
@@ -301,10 +394,10 @@ impl Generator {
 
         let output = cmd.output()?;
         if !output.status.success() {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                format!("protoc failed: {}", String::from_utf8_lossy(&output.stderr)),
-            ));
+            return Err(io::Error::other(format!(
+                "protoc failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
         }
 
         self.compile_fdset_file(fdset_file, out_filename)
@@ -322,9 +415,12 @@ impl Generator {
         let bytes = fs::read(fdset_file)?;
         let mut decoder = PbDecoder::new(bytes.as_slice());
         let mut fdset = descriptor::FileDescriptorSet::default();
-        fdset
-            .decode(&mut decoder, bytes.len())
-            .expect("file descriptor set decode failed");
+        fdset.decode(&mut decoder, bytes.len()).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Failed to decode file descriptor set: {e}"),
+            )
+        })?;
         let code = self.generate_fdset(&fdset)?;
 
         self.warn_unused_configs();
@@ -364,6 +460,34 @@ impl Generator {
         self
     }
 
+    /// Set the parts of the Rust standard library that the generated code can use.
+    ///
+    /// This decides how heap-allocated types such as `Box` are referred to, and which containers
+    /// are used for `string`, `bytes`, repeated, and `map` fields that don't have containers
+    /// configured. With [`Target::Alloc`] and [`Target::Std`], the defaults are the same as
+    /// [`use_container_alloc`](Self::use_container_alloc) and
+    /// [`use_container_std`](Self::use_container_std) respectively, but they only apply to
+    /// container types that aren't configured at the root, so calling this in any order with other
+    /// configurations is fine. With [`Target::NoAlloc`], boxed fields are rejected.
+    ///
+    /// The `micropb` features should match the target: `alloc` for [`Target::Alloc`], and `std`
+    /// for [`Target::Std`].
+    ///
+    /// By default, no target is set. Heap-allocated types are referred to through `::alloc`, and no
+    /// containers are configured.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use micropb_gen::{Generator, Target};
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Bootloader with an allocator, but without `std`
+    /// gen.target(Target::Alloc);
+    /// ```
+    pub fn target(&mut self, target: Target) -> &mut Self {
+        self.target = Some(target);
+        self
+    }
+
     /// Determine whether to generate logic for encoding and decoding Protobuf messages.
     ///
     /// Some applications don't need to support both encoding and decoding. This setting allows
@@ -376,6 +500,248 @@ impl Generator {
         self
     }
 
+    /// Gate the generated encoding and decoding logic behind Cargo features of the crate that
+    /// includes the generated code.
+    ///
+    /// When set, the encoding logic of each message, including its `MessageEncode` impl, is
+    /// wrapped in `#[cfg(feature = "<encode_feature>")]`, and the decoding logic is wrapped in
+    /// `#[cfg(feature = "<decode_feature>")]`. Message structs and their accessors are always
+    /// generated. This allows a single generated file to serve crates that need different subsets
+    /// of the logic, with each crate choosing via features. See
+    /// [`cargo_features`](Self::cargo_features) for the matching feature declarations. Disabled by
+    /// default.
+    ///
+    /// Logic omitted by [`encode_decode`](Self::encode_decode) isn't generated at all, regardless
+    /// of this setting.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Generated code is gated behind the `encode` and `decode` features
+    /// gen.feature_gates("encode", "decode");
+    /// ```
+    pub fn feature_gates(&mut self, encode_feature: &str, decode_feature: &str) -> &mut Self {
+        self.feature_gates = Some((encode_feature.to_owned(), decode_feature.to_owned()));
+        self
+    }
+
+    /// Return the `[features]` table that declares the features set by
+    /// [`feature_gates`](Self::feature_gates), for the `Cargo.toml` of the crate that includes the
+    /// generated code. Each feature enables the corresponding feature of `micropb`.
+    ///
+    /// Returns `None` if feature gates aren't set.
+    pub fn cargo_features(&self) -> Option<String> {
+        self.feature_gates.as_ref().map(|(encode, decode)| {
+            format!(
+                "[features]\n{encode} = [\"micropb/encode\"]\n{decode} = [\"micropb/decode\"]\n"
+            )
+        })
+    }
+
+    /// Determine whether generated encoding logic is compiled once per message, rather than once
+    /// per message and writer type.
+    ///
+    /// When enabled, the encoding logic of each message is generated against
+    /// `micropb::DynPbWrite`, and `MessageEncode::encode` becomes a thin wrapper that calls it via
+    /// `micropb::PbEncoder::encode_dyn`. This reduces code size when messages are encoded into
+    /// several different writer types, at the cost of a dynamic call for every write. Disabled by
+    /// default. Computing sizes and reverse encoding are unaffected.
+    pub fn dyn_encode(&mut self, dyn_encode: bool) -> &mut Self {
+        self.dyn_encode = dyn_encode;
+        self
+    }
+
+    /// Determine whether generated code is optimized for code size or speed.
+    ///
+    /// By default, generated code is [`OptimizeFor::Balanced`]. With [`OptimizeFor::Speed`], the
+    /// encoding and decoding logic of each message may be inlined into its callers, which avoids
+    /// call overhead for small messages but duplicates the logic at every call site. With
+    /// [`OptimizeFor::Size`], the logic of each message is kept out of line, and scalar fields are
+    /// encoded and decoded through shared helpers instead of inline code. This makes each field
+    /// cheaper in terms of flash, at the cost of an extra function call per field.
+    ///
+    /// Combine with [`dyn_encode`](Self::dyn_encode) to further reduce code size if messages are
+    /// encoded into multiple writer types.
+    pub fn optimize(&mut self, optimize: OptimizeFor) -> &mut Self {
+        self.optimize = optimize;
+        self
+    }
+
+    /// Determine whether generated decoding logic reports the size of each field it decodes.
+    ///
+    /// When enabled, the decoder calls `micropb::PbRead::pb_record_field` after each
+    /// field, including unknown fields and the fields of nested messages. Wrap the reader in a
+    /// `micropb::FieldStatsReader` to collect the statistics, which is useful for finding out
+    /// which fields dominate the encoded size of real traffic. Disabled by default, in which case
+    /// the generated code is unaffected.
+    pub fn field_stats(&mut self, field_stats: bool) -> &mut Self {
+        self.field_stats = field_stats;
+        self
+    }
+
+    /// Determine whether round-trip tests are generated for every message.
+    ///
+    /// When enabled, a `#[cfg(test)] mod roundtrip_tests` is added to the root of the output, with
+    /// a test for each message that encodes a few deterministic instances, decodes them into new
+    /// instances, and checks that the results are equal to the originals and that the sizes match
+    /// `compute_size`. The instances are the default message, a message with every field set to
+    /// boundary values, and a message whose containers are filled to their capacity, so the
+    /// tests cover the configured integer types and containers of the schema. Disabled by
+    /// default.
+    ///
+    /// The tests need both the encoding and decoding logic, as well as the `std` crate of the test
+    /// harness, so they still compile in `no_std` crates. Messages without `PartialEq` are only
+    /// checked for their sizes, while messages without `Default` or with lifetimes are skipped.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Generated messages are tested by `cargo test`
+    /// gen.roundtrip_tests(true);
+    /// ```
+    pub fn roundtrip_tests(&mut self, roundtrip_tests: bool) -> &mut Self {
+        self.roundtrip_tests = roundtrip_tests;
+        self
+    }
+
+    /// Determine whether code is generated for the services in the Protobuf files.
+    ///
+    /// When enabled, each service becomes a trait with one method per RPC, which takes the request
+    /// message and returns the response message or the implementation's error. Alongside the
+    /// trait, a module named after the service with an underscore suffix contains `METHODS`, a
+    /// table of `micropb::service::MethodDescriptor`, and a `dispatch` function that decodes a
+    /// request for the method at a given index in the table, calls the trait method, and encodes
+    /// the response. Since methods are addressed by index, the generated code works with any
+    /// transport. `dispatch` is only generated if both encoding and decoding are enabled, and it
+    /// requires the request messages to implement `Default`. Disabled by default.
+    ///
+    /// Streaming RPCs aren't supported, and cause a generation error.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Generate a trait for every service
+    /// gen.services(true);
+    /// ```
+    pub fn services(&mut self, services: bool) -> &mut Self {
+        self.services = services;
+        self
+    }
+
+    /// Determine whether compile-time checks are generated for the capacities of containers.
+    ///
+    /// When enabled, each field with [`max_len`](Config::max_len) or
+    /// [`max_bytes`](Config::max_bytes) configured gets a `const` assertion that the `CAPACITY` of
+    /// its container type, as reported by `PbVec`, `PbString`, or `PbMap`, is at least the
+    /// configured bound. This catches custom containers whose actual capacity differs from the
+    /// configured one, such as type aliases that ignore their capacity parameter, which would
+    /// otherwise only show up as capacity errors when decoding. Messages with lifetimes aren't
+    /// checked. Disabled by default.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Check that every bounded container can hold as much as configured
+    /// gen.capacity_asserts(true);
+    /// ```
+    pub fn capacity_asserts(&mut self, capacity_asserts: bool) -> &mut Self {
+        self.capacity_asserts = capacity_asserts;
+        self
+    }
+
+    /// Determine whether messages get a generated `MessageEncode::encode_reverse`.
+    ///
+    /// When enabled, each message writes its fields back-to-front in `encode_reverse`, so
+    /// `MessageEncode::encode_to_slice_reverse` encodes nested messages in a single pass without
+    /// computing their sizes. Disabled by default, in which case the default implementation of
+    /// `encode_reverse` computes the size of the message and encodes it forwards, so reverse
+    /// encoding still works, but without the single-pass benefit.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Encode messages in a single pass with `encode_to_slice_reverse`
+    /// gen.encode_reverse(true);
+    /// ```
+    pub fn encode_reverse(&mut self, encode_reverse: bool) -> &mut Self {
+        self.encode_reverse = encode_reverse;
+        self
+    }
+
+    /// Determine whether the maximum encoded size of each message is computed during generation.
+    ///
+    /// When enabled, messages whose fields all have bounded sizes get their largest possible
+    /// encoded size as `MessageEncode::MAX_SIZE`, which can be used to size encode buffers at
+    /// compile time. Disabled by default, in which case `MAX_SIZE` is `None` for every generated
+    /// message.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Compute `MAX_SIZE` of messages with bounded fields
+    /// gen.max_size(true);
+    /// ```
+    pub fn max_size(&mut self, max_size: bool) -> &mut Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Determine whether messages get a generated `missing_fields` method.
+    ///
+    /// When enabled, every message with optional fields or oneofs gets a `missing_fields` method
+    /// that iterates over the numbers of the optional fields that aren't present. Disabled by
+    /// default.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Report which optional fields were absent after decoding
+    /// gen.missing_fields(true);
+    /// ```
+    pub fn missing_fields(&mut self, missing_fields: bool) -> &mut Self {
+        self.missing_fields = missing_fields;
+        self
+    }
+
+    /// Determine whether messages implement `MessageName` with their fully-qualified Protobuf
+    /// names.
+    ///
+    /// `MessageName` is needed to pack messages into a `google.protobuf.Any` and to unpack them
+    /// from it. Disabled by default, although messages with
+    /// [`field_descriptors`](Config::field_descriptors) configured always implement
+    /// `MessageName`, since it's a supertrait of `MessageFields`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Allow messages to be packed into `Any`
+    /// gen.message_names(true);
+    /// ```
+    pub fn message_names(&mut self, message_names: bool) -> &mut Self {
+        self.message_names = message_names;
+        self
+    }
+
+    /// Determine whether messages get a generated `MessageDecode::clear` that retains the
+    /// allocations of their containers.
+    ///
+    /// When enabled, `clear` resets each field in place, so clearing a message before decoding
+    /// into it again reuses the existing `string`, `bytes`, repeated, and `map` containers rather
+    /// than reallocating them. Only messages that implement `Default` get the generated `clear`.
+    /// Disabled by default, in which case the default implementation of `clear` replaces the
+    /// message with its default value.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # let mut gen = micropb_gen::Generator::new();
+    /// // Reuse allocations when decoding into the same message repeatedly
+    /// gen.in_place_clear(true);
+    /// ```
+    pub fn in_place_clear(&mut self, in_place_clear: bool) -> &mut Self {
+        self.in_place_clear = in_place_clear;
+        self
+    }
+
     /// When set, the file descriptor set generated by `protoc` is written to the provided path,
     /// instead of a temporary directory.
     pub fn file_descriptor_set_path<P: Into<PathBuf>>(&mut self, path: P) -> &mut Self {
@@ -1,4 +1,4 @@
-pub(crate) fn unescape_c_escape_string(s: &str) -> Vec<u8> {
+pub(crate) fn unescape_c_escape_string(s: &str) -> Result<Vec<u8>, String> {
     let src = s.as_bytes();
     let len = src.len();
     let mut dst = Vec::new();
@@ -12,10 +12,10 @@ pub(crate) fn unescape_c_escape_string(s: &str) -> Vec<u8> {
         } else {
             p += 1;
             if p == len {
-                panic!(
-                    "invalid c-escaped default binary value ({}): ends with '\'",
+                return Err(format!(
+                    "invalid c-escaped default binary value ({}): ends with '\\'",
                     s
-                )
+                ));
             }
             match src[p] {
                 b'a' => {
@@ -76,28 +76,32 @@ pub(crate) fn unescape_c_escape_string(s: &str) -> Vec<u8> {
                 }
                 b'x' | b'X' => {
                     if p + 3 > len {
-                        panic!(
+                        return Err(format!(
                             "invalid c-escaped default binary value ({}): incomplete hex value",
                             s
-                        )
+                        ));
                     }
                     match u8::from_str_radix(&s[p + 1..p + 3], 16) {
                         Ok(b) => dst.push(b),
-                        _ => panic!(
-                            "invalid c-escaped default binary value ({}): invalid hex value",
-                            &s[p..p + 2]
-                        ),
+                        _ => {
+                            return Err(format!(
+                                "invalid c-escaped default binary value ({}): invalid hex value",
+                                &s[p..p + 2]
+                            ))
+                        }
                     }
                     p += 3;
                 }
-                _ => panic!(
-                    "invalid c-escaped default binary value ({}): invalid escape",
-                    s
-                ),
+                _ => {
+                    return Err(format!(
+                        "invalid c-escaped default binary value ({}): invalid escape",
+                        s
+                    ))
+                }
             }
         }
     }
-    dst
+    Ok(dst)
 }
 
 pub(crate) fn path_suffix(path: &str) -> &str {
@@ -106,6 +110,23 @@ pub(crate) fn path_suffix(path: &str) -> &str {
         .unwrap_or(path)
 }
 
+/// Default JSON name of a field, computed the same way as `protoc`
+pub(crate) fn json_name(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut capitalize_next = false;
+    for c in name.chars() {
+        if c == '_' {
+            capitalize_next = true;
+        } else if capitalize_next {
+            out.push(c.to_ascii_uppercase());
+            capitalize_next = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -116,24 +137,40 @@ mod tests {
         assert_eq!(path_suffix("a.b.c"), "c");
     }
 
+    #[test]
+    fn json_field_name() {
+        assert_eq!(json_name("field"), "field");
+        assert_eq!(json_name("foo_bar_baz"), "fooBarBaz");
+        assert_eq!(json_name("foo_bar2baz"), "fooBar2baz");
+        assert_eq!(json_name("_leading__double"), "LeadingDouble");
+        assert_eq!(json_name("Upper_case"), "UpperCase");
+    }
+
     #[test]
     fn unescape_c_string() {
         assert_eq!(
             &b"hello world"[..],
-            &unescape_c_escape_string("hello world")[..]
+            &unescape_c_escape_string("hello world").unwrap()[..]
         );
 
-        assert_eq!(&b"\0"[..], &unescape_c_escape_string(r#"\0"#)[..]);
+        assert_eq!(&b"\0"[..], &unescape_c_escape_string(r#"\0"#).unwrap()[..]);
 
         assert_eq!(
             &[0o012, 0o156],
-            &unescape_c_escape_string(r#"\012\156"#)[..]
+            &unescape_c_escape_string(r#"\012\156"#).unwrap()[..]
+        );
+        assert_eq!(
+            &[0x01, 0x02],
+            &unescape_c_escape_string(r#"\x01\x02"#).unwrap()[..]
         );
-        assert_eq!(&[0x01, 0x02], &unescape_c_escape_string(r#"\x01\x02"#)[..]);
 
         assert_eq!(
             &b"\0\x01\x07\x08\x0C\n\r\t\x0B\\\'\"\xFE"[..],
-            &unescape_c_escape_string(r#"\0\001\a\b\f\n\r\t\v\\\'\"\xfe"#)[..]
+            &unescape_c_escape_string(r#"\0\001\a\b\f\n\r\t\v\\\'\"\xfe"#).unwrap()[..]
         );
+
+        assert!(unescape_c_escape_string(r#"ab\"#).is_err());
+        assert!(unescape_c_escape_string(r#"\q"#).is_err());
+        assert!(unescape_c_escape_string(r#"\xzz"#).is_err());
     }
 }
@@ -1,6 +1,9 @@
 use std::fmt::Arguments;
 
-use micropb_gen::{config::CustomField, Config, Generator};
+use micropb_gen::{
+    config::{CustomField, IntSize},
+    Config, EncodeDecode, Generator,
+};
 
 use tempfile::NamedTempFile;
 
@@ -63,6 +66,7 @@ fn no_map() {
 fn long_default_string() {
     let mut gen = Generator::with_warning_callback(warn_panic);
     gen.use_container_heapless();
+    gen.configure(".test.Msg", Config::new().max_len(4).max_bytes(4));
     gen.configure(".test.Msg.st", Config::new().max_bytes(2));
     let err = compile(gen);
     dbg!(&err);
@@ -75,6 +79,7 @@ fn long_default_string() {
 fn long_default_bytes() {
     let mut gen = Generator::with_warning_callback(warn_panic);
     gen.use_container_heapless();
+    gen.configure(".test.Msg", Config::new().max_len(4).max_bytes(4));
     gen.configure(".test.Msg.bt", Config::new().max_bytes(2));
     let err = compile(gen);
     dbg!(&err);
@@ -83,6 +88,173 @@ fn long_default_bytes() {
     assert!(err.contains("default value is 3 bytes"));
 }
 
+#[test]
+fn missing_max_len() {
+    let mut gen = Generator::with_warning_callback(warn_panic);
+    gen.use_container_heapless();
+    gen.configure(".test.Msg", Config::new().max_bytes(4));
+    gen.configure(".test.Msg.kv", Config::new().max_len(4));
+    let err = compile(gen);
+    dbg!(&err);
+    assert!(err.contains("(.test.Msg.list)"));
+    assert!(err.contains("vec_type `::micropb::heapless::Vec` needs a capacity"));
+    assert!(err.contains("max_len was not configured"));
+}
+
+#[test]
+fn missing_max_bytes_custom() {
+    let mut gen = Generator::with_warning_callback(warn_panic);
+    gen.use_container_alloc();
+    gen.configure(
+        ".test.Msg.st",
+        Config::new()
+            .string_type("MyString")
+            .container_needs_capacity(true),
+    );
+    let err = compile(gen);
+    dbg!(&err);
+    assert!(err.contains("(.test.Msg.st)"));
+    assert!(err.contains("string_type `MyString` needs a capacity"));
+    assert!(err.contains("max_bytes was not configured"));
+}
+
+#[test]
+fn map_template_missing_max_len() {
+    let mut gen = Generator::with_warning_callback(warn_panic);
+    gen.use_container_alloc();
+    gen.configure(".test.Msg.kv", Config::new().map_type("MyMap<$N, $K, $V>"));
+    let err = compile(gen);
+    dbg!(&err);
+    assert!(err.contains("(.test.Msg.kv)"));
+    assert!(err.contains("map_type `MyMap<$N, $K, $V>` needs a capacity"));
+}
+
+#[test]
+fn map_template_unknown_placeholder() {
+    let mut gen = Generator::with_warning_callback(warn_panic);
+    gen.use_container_alloc();
+    gen.configure(".test.Msg.kv", Config::new().map_type("MyMap<$K, $V, $S>"));
+    let err = compile(gen);
+    dbg!(&err);
+    assert!(err.contains("(.test.Msg.kv)"));
+    assert!(err.contains("Unknown placeholder `$S`"));
+}
+
+#[test]
+fn vec_template_parse_error() {
+    let mut gen = Generator::with_warning_callback(warn_panic);
+    gen.use_container_alloc();
+    gen.configure(
+        ".test.Msg.list",
+        Config::new().vec_type("MyVec<$N, $T").max_len(3),
+    );
+    let err = compile(gen);
+    dbg!(&err);
+    assert!(err.contains("(.test.Msg.list)"));
+    assert!(err.contains("Failed to parse vec_type \"MyVec<3, $T\" as type template"));
+}
+
+#[test]
+fn invalid_field_attribute() {
+    let mut gen = Generator::with_warning_callback(warn_panic);
+    gen.use_container_alloc();
+    gen.configure(
+        ".test.Msg.st",
+        Config::new().field_attributes("#[serde(skip)] #[cfg(feature = \"x\") typo]"),
+    );
+    let err = compile(gen);
+    dbg!(&err);
+    assert!(err.contains("(.test.Msg.st)"));
+    assert!(err.contains("invalid attribute `#[cfg(feature = \"x\") typo]` at column 16"));
+}
+
+#[test]
+fn inner_hazzer_attribute() {
+    let mut gen = Generator::with_warning_callback(warn_panic);
+    gen.use_container_alloc();
+    gen.configure(
+        ".test.Msg._has",
+        Config::new().type_attributes("#![allow(unused)]"),
+    );
+    let err = compile(gen);
+    dbg!(&err);
+    assert!(err.contains("(.test.Msg._has)"));
+    assert!(err.contains("inner attribute at column 1 isn't allowed"));
+}
+
+#[test]
+#[should_panic(expected = "(.test.Msg.list) max_len is ignored")]
+fn ignored_max_len_template() {
+    let mut gen = Generator::with_warning_callback(warn_panic);
+    gen.use_container_alloc();
+    gen.configure(
+        ".test.Msg.list",
+        Config::new().vec_type("MyVec<$T>").max_len(3),
+    );
+    let file = NamedTempFile::new().unwrap();
+    let _ = gen.compile_protos(&["tests/test.proto"], file.path());
+}
+
+#[test]
+#[should_panic(expected = "(.test.Msg.list) max_len is ignored")]
+fn ignored_max_len() {
+    let mut gen = Generator::with_warning_callback(warn_panic);
+    gen.use_container_alloc();
+    gen.configure(".test.Msg.list", Config::new().max_len(4));
+    let file = NamedTempFile::new().unwrap();
+    let _ = gen.compile_protos(&["tests/test.proto"], file.path());
+}
+
+#[test]
+#[should_panic(expected = "(.test.Msg.big) 64-bit integer is stored as usize or isize")]
+fn size_int_64bit() {
+    let mut gen = Generator::with_warning_callback(warn_panic);
+    gen.use_container_alloc();
+    gen.configure(".test.Msg.big", Config::new().int_size(IntSize::Size));
+    let file = NamedTempFile::new().unwrap();
+    let _ = gen.compile_protos(&["tests/test.proto"], file.path());
+}
+
+#[test]
+fn oneof_variant_path() {
+    let mut gen = Generator::with_warning_callback(warn_panic);
+    gen.use_container_alloc();
+    gen.configure(".test.Msg.of.int", Config::new().int_size(IntSize::S8));
+    gen.configure(
+        ".test.Msg.kv.key",
+        Config::new().field_attributes("#[allow(unused)]"),
+    );
+    gen.configure(
+        ".test.Msg.kv.value",
+        Config::new().field_attributes("#[allow(unused)]"),
+    );
+    let file = NamedTempFile::new().unwrap();
+    gen.compile_protos(&["tests/test.proto"], file.path())
+        .unwrap();
+    let out = std::fs::read_to_string(file.path()).unwrap();
+    assert!(out.contains("Int(i8)"));
+}
+
+#[test]
+#[should_panic(expected = "Unused configuration path: \".test.Msg.of.missing\"")]
+fn unused_oneof_variant_path() {
+    let mut gen = Generator::with_warning_callback(warn_panic);
+    gen.use_container_alloc();
+    gen.configure(".test.Msg.of.missing", Config::new().int_size(IntSize::S8));
+    let file = NamedTempFile::new().unwrap();
+    let _ = gen.compile_protos(&["tests/test.proto"], file.path());
+}
+
+#[test]
+#[should_panic(expected = "Unused configuration path: \".test.Msg.list.key\"")]
+fn unused_key_path() {
+    let mut gen = Generator::with_warning_callback(warn_panic);
+    gen.use_container_alloc();
+    gen.configure(".test.Msg.list.key", Config::new().int_size(IntSize::S8));
+    let file = NamedTempFile::new().unwrap();
+    let _ = gen.compile_protos(&["tests/test.proto"], file.path());
+}
+
 #[test]
 fn parse_string_type() {
     let mut gen = Generator::with_warning_callback(warn_panic);
@@ -303,6 +475,74 @@ fn parse_custom_type_delegate() {
     assert!(err.contains("Failed to parse custom delegate"));
 }
 
+#[test]
+fn lazy_not_message() {
+    let mut gen = Generator::with_warning_callback(warn_panic);
+    gen.use_container_alloc();
+    gen.configure(".test.Msg.st", Config::new().lazy(true));
+    let err = compile(gen);
+    dbg!(&err);
+    assert!(err.contains("(.test.Msg.st)"));
+    assert!(err.contains("lazy is only supported"));
+}
+
+#[test]
+fn bytes_range_encode() {
+    let mut gen = Generator::with_warning_callback(warn_panic);
+    gen.use_container_alloc();
+    gen.configure(".test.Msg.bt", Config::new().bytes_range(true));
+    let err = compile(gen);
+    dbg!(&err);
+    assert!(err.contains("(.test.Msg.bt)"));
+    assert!(err.contains("must be DecodeOnly"));
+}
+
+#[test]
+fn bytes_range_not_bytes() {
+    let mut gen = Generator::with_warning_callback(warn_panic);
+    gen.use_container_alloc();
+    gen.encode_decode(EncodeDecode::DecodeOnly);
+    gen.configure(".test.Msg.st", Config::new().bytes_range(true));
+    let err = compile(gen);
+    dbg!(&err);
+    assert!(err.contains("(.test.Msg.st)"));
+    assert!(err.contains("bytes_range is only supported"));
+}
+
+#[test]
+fn visit_not_repeated() {
+    let mut gen = Generator::with_warning_callback(warn_panic);
+    gen.use_container_alloc();
+    gen.configure(".test.Msg.kv", Config::new().visit(true));
+    let err = compile(gen);
+    dbg!(&err);
+    assert!(err.contains("(.test.Msg.kv)"));
+    assert!(err.contains("visit is only supported"));
+}
+
+#[test]
+fn no_visitor() {
+    let mut gen = Generator::with_warning_callback(warn_panic);
+    gen.use_container_alloc();
+    gen.configure(".test.Msg.list", Config::new().visit(true));
+    let err = compile(gen);
+    dbg!(&err);
+    assert!(err.contains("(.test.Msg)"));
+    assert!(err.contains("visitor was not configured"));
+}
+
+#[test]
+fn parse_visitor() {
+    let mut gen = Generator::with_warning_callback(warn_panic);
+    gen.use_container_alloc();
+    gen.configure(".test.Msg.list", Config::new().visit(true));
+    gen.configure(".test.Msg", Config::new().visitor("Type<"));
+    let err = compile(gen);
+    dbg!(&err);
+    assert!(err.contains("(.test.Msg)"));
+    assert!(err.contains("Failed to parse visitor"));
+}
+
 #[test]
 #[should_panic = "Unused configuration path: \".Msg\""]
 fn warn_unused_config() {
@@ -25,6 +25,9 @@
 
 use core::{mem::MaybeUninit, ops::Deref};
 
+/// Value of `CAPACITY` for containers without a fixed capacity.
+pub const UNBOUNDED_CAPACITY: usize = usize::MAX;
+
 /// Basic container trait required for all multi-element containers, except for maps.
 pub trait PbContainer: Sized {
     /// Sets length of container (number of elements).
@@ -36,6 +39,9 @@ pub trait PbContainer: Sized {
 
     /// Reserves capacity for at least `additional` more elements to be inserted. No-op for
     /// fixed-capacity containers.
+    ///
+    /// Since `additional` may come from untrusted input, implementations should not panic or
+    /// abort if the reservation fails. The decoder detects the lack of space on its own.
     fn pb_reserve(&mut self, _additional: usize) {}
 
     /// Clear all elements of the container
@@ -46,6 +52,11 @@ pub trait PbContainer: Sized {
 ///
 /// Represents repeated field. If `PbVec<u8>` is implemented, also represents `bytes` field.
 pub trait PbVec<T>: PbContainer + Deref<Target = [T]> {
+    /// Maximum number of elements the vector can hold, or [`UNBOUNDED_CAPACITY`] if it grows
+    /// dynamically. Defaults to [`UNBOUNDED_CAPACITY`], so only fixed-capacity containers need to
+    /// set it.
+    const CAPACITY: usize = UNBOUNDED_CAPACITY;
+
     /// Push a new element to the back of the vector.
     ///
     /// Returns error if the fixed capacity is already full.
@@ -69,6 +80,11 @@ pub trait PbVec<T>: PbContainer + Deref<Target = [T]> {
 ///
 /// Represents Protobuf `string` field.
 pub trait PbString: PbContainer + Deref<Target = str> {
+    /// Maximum number of bytes the string can hold, or [`UNBOUNDED_CAPACITY`] if it grows
+    /// dynamically. Defaults to [`UNBOUNDED_CAPACITY`], so only fixed-capacity containers need to
+    /// set it.
+    const CAPACITY: usize = UNBOUNDED_CAPACITY;
+
     /// Returns the remaining spare capacity of the string as a slice of `MaybeUninit<u8>`.
     ///
     /// The returned slice can be filled with bytes before marking the data as initialized using
@@ -89,6 +105,11 @@ pub trait PbString: PbContainer + Deref<Target = str> {
 ///
 /// Represents Protobuf `map` field.
 pub trait PbMap<K, V> {
+    /// Maximum number of key-value pairs the map can hold, or [`UNBOUNDED_CAPACITY`] if it grows
+    /// dynamically. Defaults to [`UNBOUNDED_CAPACITY`], so only fixed-capacity containers need to
+    /// set it.
+    const CAPACITY: usize = UNBOUNDED_CAPACITY;
+
     /// Iterator for looping through each key-value pair in the map
     type Iter<'a>: Iterator<Item = (&'a K, &'a V)>
     where
@@ -96,13 +117,164 @@ pub trait PbMap<K, V> {
         K: 'a,
         V: 'a;
 
-    /// Inserts a new key-value pair into the map.
+    /// Inserts a new key-value pair into the map, overwriting the value if the key already exists.
     ///
-    /// Returns error if the new pair would make the map go over its fixed capacity.
+    /// Returns error if the new pair would make the map go over its fixed capacity. Overwriting
+    /// an existing key doesn't need any extra space, so it must succeed even if the map is full.
     fn pb_insert(&mut self, key: K, val: V) -> Result<(), ()>;
 
+    /// Gets a mutable reference to the value of `key`, inserting a default value if the key isn't
+    /// in the map yet.
+    ///
+    /// Returns error if the key isn't in the map and the map is already at its fixed capacity.
+    /// The key is only cloned if it needs to be inserted.
+    fn pb_get_or_insert(&mut self, key: &K) -> Result<&mut V, ()>
+    where
+        K: Clone,
+        V: Default;
+
+    /// Removes a key from the map, returning its value if the key was in the map.
+    fn pb_remove(&mut self, key: &K) -> Option<V>;
+
     /// Iterates through each key-value pair in the map. Order is unspecified.
     fn pb_iter(&self) -> Self::Iter<'_>;
+
+    /// Removes all key-value pairs from the map.
+    ///
+    /// This should retain the map's existing allocation, if any. The default implementation
+    /// replaces the map with an empty one, which drops the allocation.
+    fn pb_clear(&mut self)
+    where
+        Self: Default,
+    {
+        *self = Self::default();
+    }
+
+    /// Returns the number of key-value pairs in the map.
+    ///
+    /// Only used to report the capacity of full maps in decode errors. The default implementation
+    /// counts the pairs via [`pb_iter`](Self::pb_iter).
+    fn pb_len(&self) -> usize {
+        self.pb_iter().count()
+    }
+}
+
+#[derive(Debug)]
+/// Iterator over the key-value pairs of a map in ascending key order, without any scratch space.
+///
+/// Each step scans the entire map for the next smallest key, so iterating through the whole map
+/// takes quadratic time. This is only worthwhile for small maps. Larger maps should use an
+/// ordered container such as `BTreeMap`, or [`SortedMapEntries`] if the map has a fixed capacity.
+///
+/// Used by generated code to encode `map` fields deterministically.
+pub struct SortedMapIter<'a, K, V, M> {
+    map: &'a M,
+    /// Last key returned from the front
+    front: Option<&'a K>,
+    /// Last key returned from the back
+    back: Option<&'a K>,
+    _val: core::marker::PhantomData<&'a V>,
+}
+
+impl<'a, K: Ord, V, M: PbMap<K, V>> SortedMapIter<'a, K, V, M> {
+    /// Iterate over `map` in ascending key order.
+    pub fn new(map: &'a M) -> Self {
+        Self {
+            map,
+            front: None,
+            back: None,
+            _val: core::marker::PhantomData,
+        }
+    }
+
+    /// Find the smallest or largest key that hasn't been returned yet
+    fn find_next(&self, largest: bool) -> Option<(&'a K, &'a V)> {
+        let mut found: Option<(&'a K, &'a V)> = None;
+        for (k, v) in self.map.pb_iter() {
+            let remaining = self.front.map_or(true, |f| k > f) && self.back.map_or(true, |b| k < b);
+            let better = found.map_or(true, |(f, _)| if largest { k > f } else { k < f });
+            if remaining && better {
+                found = Some((k, v));
+            }
+        }
+        found
+    }
+}
+
+impl<'a, K: Ord, V, M: PbMap<K, V>> Iterator for SortedMapIter<'a, K, V, M> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.find_next(false)?;
+        self.front = Some(entry.0);
+        Some(entry)
+    }
+}
+
+impl<K: Ord, V, M: PbMap<K, V>> DoubleEndedIterator for SortedMapIter<'_, K, V, M> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let entry = self.find_next(true)?;
+        self.back = Some(entry.0);
+        Some(entry)
+    }
+}
+
+#[derive(Debug)]
+/// Iterator over the key-value pairs of a fixed-capacity map in ascending key order.
+///
+/// References to all entries of the map are sorted in a scratch array of `N` elements, where `N`
+/// is the capacity of the map, so sorting takes `N` times two pointers of stack space.
+///
+/// Used by generated code to encode fixed-capacity `map` fields deterministically.
+pub struct SortedMapEntries<'a, K, V, const N: usize> {
+    entries: [Option<(&'a K, &'a V)>; N],
+    front: usize,
+    back: usize,
+}
+
+impl<'a, K: Ord, V, const N: usize> SortedMapEntries<'a, K, V, N> {
+    /// Sort the entries of `map` by key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the map has more than `N` entries.
+    pub fn new<M: PbMap<K, V>>(map: &'a M) -> Self {
+        let mut entries = [None; N];
+        let mut len = 0;
+        for entry in map.pb_iter() {
+            assert!(len < N, "map has more entries than its capacity");
+            entries[len] = Some(entry);
+            len += 1;
+        }
+        entries[..len].sort_unstable_by_key(|e| e.map(|(k, _)| k));
+        Self {
+            entries,
+            front: 0,
+            back: len,
+        }
+    }
+}
+
+impl<'a, K, V, const N: usize> Iterator for SortedMapEntries<'a, K, V, N> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.front += 1;
+        self.entries[self.front - 1]
+    }
+}
+
+impl<K, V, const N: usize> DoubleEndedIterator for SortedMapEntries<'_, K, V, N> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.back -= 1;
+        self.entries[self.back]
+    }
 }
 
 #[cfg(feature = "container-arrayvec")]
@@ -138,6 +310,8 @@ mod impl_arrayvec {
     }
 
     impl<T, const N: usize> PbVec<T> for ArrayVec<T, N> {
+        const CAPACITY: usize = N;
+
         #[inline]
         fn pb_push(&mut self, elem: T) -> Result<(), ()> {
             self.try_push(elem).map_err(drop)
@@ -164,6 +338,8 @@ mod impl_arrayvec {
     }
 
     impl<const N: usize> PbString for ArrayString<N> {
+        const CAPACITY: usize = N;
+
         #[inline]
         fn pb_spare_cap(&mut self) -> &mut [MaybeUninit<u8>] {
             let len = self.len();
@@ -189,7 +365,7 @@ mod impl_heapless {
 
     use core::hash::{BuildHasher, Hash};
 
-    use heapless::{IndexMap, IndexMapIter, String, Vec};
+    use heapless::{Entry, IndexMap, IndexMapIter, String, Vec};
 
     impl<T, const N: usize> PbContainer for Vec<T, N> {
         #[inline]
@@ -216,6 +392,8 @@ mod impl_heapless {
     }
 
     impl<T, const N: usize> PbVec<T> for Vec<T, N> {
+        const CAPACITY: usize = N;
+
         #[inline]
         fn pb_push(&mut self, elem: T) -> Result<(), ()> {
             self.push(elem).map_err(drop)
@@ -242,6 +420,8 @@ mod impl_heapless {
     }
 
     impl<const N: usize> PbString for String<N> {
+        const CAPACITY: usize = N;
+
         #[inline]
         fn pb_spare_cap(&mut self) -> &mut [MaybeUninit<u8>] {
             let len = self.len();
@@ -262,6 +442,8 @@ mod impl_heapless {
     }
 
     impl<K: Eq + Hash, V, S: BuildHasher, const N: usize> PbMap<K, V> for IndexMap<K, V, S, N> {
+        const CAPACITY: usize = N;
+
         type Iter<'a> = IndexMapIter<'a, K, V> where S: 'a, K: 'a, V: 'a;
 
         #[inline]
@@ -270,10 +452,37 @@ mod impl_heapless {
             Ok(())
         }
 
+        #[inline]
+        fn pb_get_or_insert(&mut self, key: &K) -> Result<&mut V, ()>
+        where
+            K: Clone,
+            V: Default,
+        {
+            match self.entry(key.clone()) {
+                Entry::Occupied(entry) => Ok(entry.into_mut()),
+                Entry::Vacant(entry) => entry.insert(V::default()).map_err(drop),
+            }
+        }
+
+        #[inline]
+        fn pb_remove(&mut self, key: &K) -> Option<V> {
+            self.remove(key)
+        }
+
         #[inline]
         fn pb_iter(&self) -> Self::Iter<'_> {
             self.iter()
         }
+
+        #[inline]
+        fn pb_clear(&mut self) {
+            self.clear()
+        }
+
+        #[inline]
+        fn pb_len(&self) -> usize {
+            self.len()
+        }
     }
 }
 
@@ -301,7 +510,9 @@ mod impl_alloc {
 
         #[inline]
         fn pb_reserve(&mut self, additional: usize) {
-            self.reserve(additional)
+            // Lengths come from untrusted input, so allocation failures must not abort. If the
+            // reservation fails, the decoder reports a capacity error due to the lack of space.
+            let _ = self.try_reserve(additional);
         }
     }
 
@@ -335,7 +546,8 @@ mod impl_alloc {
 
         #[inline]
         fn pb_reserve(&mut self, additional: usize) {
-            self.reserve(additional)
+            // Same as Vec, allocation failures are reported as capacity errors by the decoder
+            let _ = self.try_reserve(additional);
         }
     }
 
@@ -417,15 +629,41 @@ mod impl_alloc {
             Ok(())
         }
 
+        #[inline]
+        fn pb_get_or_insert(&mut self, key: &K) -> Result<&mut V, ()>
+        where
+            K: Clone,
+            V: Default,
+        {
+            Ok(self.entry(key.clone()).or_default())
+        }
+
+        #[inline]
+        fn pb_remove(&mut self, key: &K) -> Option<V> {
+            self.remove(key)
+        }
+
         #[inline]
         fn pb_iter(&self) -> Self::Iter<'_> {
             self.iter()
         }
+
+        #[inline]
+        fn pb_clear(&mut self) {
+            self.clear()
+        }
+
+        #[inline]
+        fn pb_len(&self) -> usize {
+            self.len()
+        }
     }
 
     #[cfg(feature = "std")]
-    impl<K: Eq + core::hash::Hash, V> PbMap<K, V> for std::collections::HashMap<K, V> {
-        type Iter<'a> = std::collections::hash_map::Iter<'a, K, V> where K: 'a, V: 'a;
+    impl<K: Eq + core::hash::Hash, V, S: core::hash::BuildHasher> PbMap<K, V>
+        for std::collections::HashMap<K, V, S>
+    {
+        type Iter<'a> = std::collections::hash_map::Iter<'a, K, V> where S: 'a, K: 'a, V: 'a;
 
         #[inline]
         fn pb_insert(&mut self, key: K, val: V) -> Result<(), ()> {
@@ -433,9 +671,33 @@ mod impl_alloc {
             Ok(())
         }
 
+        #[inline]
+        fn pb_get_or_insert(&mut self, key: &K) -> Result<&mut V, ()>
+        where
+            K: Clone,
+            V: Default,
+        {
+            Ok(self.entry(key.clone()).or_default())
+        }
+
+        #[inline]
+        fn pb_remove(&mut self, key: &K) -> Option<V> {
+            self.remove(key)
+        }
+
         #[inline]
         fn pb_iter(&self) -> Self::Iter<'_> {
             self.iter()
         }
+
+        #[inline]
+        fn pb_clear(&mut self) {
+            self.clear()
+        }
+
+        #[inline]
+        fn pb_len(&self) -> usize {
+            self.len()
+        }
     }
 }
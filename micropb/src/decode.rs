@@ -1,25 +1,38 @@
 use core::{
+    fmt::{self, Display},
     mem::MaybeUninit,
+    ops::Range,
     str::{from_utf8, Utf8Error},
 };
 
 use crate::{
-    container::{PbString, PbVec},
+    container::{PbContainer, PbMap, PbString, PbVec, UNBOUNDED_CAPACITY},
     misc::{
         maybe_uninit_slice_assume_init_ref, maybe_uninit_write_slice,
         maybe_ununit_array_assume_init,
     },
-    MessageDecode, Presence, Tag, WIRE_TYPE_I32, WIRE_TYPE_I64, WIRE_TYPE_LEN, WIRE_TYPE_VARINT,
+    FixedSize, MessageDecode, Presence, Tag, VarInt, WIRE_TYPE_EGROUP, WIRE_TYPE_I32,
+    WIRE_TYPE_I64, WIRE_TYPE_LEN, WIRE_TYPE_SGROUP, WIRE_TYPE_VARINT,
 };
 
-use never::Never;
+use crate::Never;
+
+/// Maximum number of bytes reserved at once when reading a value from a non-contiguous reader into
+/// a dynamically-sized container
+const RESERVE_CHUNK: usize = 1024;
 
 #[non_exhaustive]
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 /// Protobuf [decoder](PbDecoder) error.
 ///
 /// The error is parametrized by the underlying reader's error type `E`. Most of the error variants
 /// use simple enums to minimize the memory footprint.
+///
+/// The variants distinguish between malformed input ([`UnexpectedEof`](Self::UnexpectedEof),
+/// [`VarIntLimit`](Self::VarIntLimit), [`WrongWireType`](Self::WrongWireType), etc.), valid input
+/// that exceeds the limits of the application ([`Capacity`](Self::Capacity),
+/// [`SizeLimit`](Self::SizeLimit), [`DepthLimit`](Self::DepthLimit)), and errors from the
+/// underlying [`Reader`](Self::Reader).
 pub enum DecodeError<E> {
     /// Varint exceeded max length of 10 bytes
     VarIntLimit,
@@ -29,6 +42,11 @@ pub enum DecodeError<E> {
     Deprecation,
     /// Unknown Protobuf wire type encountered
     UnknownWireType,
+    /// Wire type of a field doesn't match the type of the field
+    WrongWireType,
+    /// End-group tag doesn't match the start-group tag of the enclosing group, or isn't inside a
+    /// group at all
+    GroupMismatch,
     /// Field number of 0, which is not allowed
     ZeroField,
     /// Custom field decoding returned false for field number that should be recognized
@@ -36,19 +54,164 @@ pub enum DecodeError<E> {
     /// Decoded string is not valid UTF8
     Utf8,
     /// Exceeded capcity of fixed container for `string`, `bytes`, repeated, or `map` field
-    Capacity,
+    Capacity {
+        /// Number of additional bytes or elements that didn't fit into the container, saturating
+        /// at `u32::MAX`. For repeated and `map` fields, this only counts the elements of the
+        /// current record, so more elements may follow.
+        needed: u32,
+        /// Capacity of the container in bytes or elements, saturating at `u32::MAX`
+        capacity: u32,
+    },
     /// Actual length of length-delimited record differs from value of length prefix
     WrongLen,
+    /// Length of the message exceeded the configured limit, such as the decoder's `max_size`
+    SizeLimit,
+    /// Nesting depth of messages exceeded the decoder's `max_depth`
+    DepthLimit,
+    /// Reader doesn't read from a contiguous buffer, so `bytes` values can't be decoded as ranges
+    /// into the input
+    NonContiguous,
+    /// Enum field received a value that isn't declared in the Protobuf enum, and the field is
+    /// configured to reject unknown enum values
+    UnknownEnumValue,
+    /// Checksum of a frame didn't match its contents
+    CrcMismatch,
+    /// gRPC frame has its compressed flag set, but compressed frames aren't supported
+    CompressedFrame,
+    /// Integer on the wire doesn't fit into the pointer-sized integer type of the field
+    IntOverflow,
     /// Error returned from reader
     Reader(E),
 }
 
+impl<E> DecodeError<E> {
+    /// Construct a [`Capacity`](Self::Capacity) error, saturating the numbers at `u32::MAX`.
+    pub fn capacity(needed: usize, capacity: usize) -> Self {
+        let saturate = |n: usize| u32::try_from(n).unwrap_or(u32::MAX);
+        Self::Capacity {
+            needed: saturate(needed),
+            capacity: saturate(capacity),
+        }
+    }
+}
+
 impl<E> From<Utf8Error> for DecodeError<E> {
     fn from(_: Utf8Error) -> Self {
         Self::Utf8
     }
 }
 
+impl<E: Display> Display for DecodeError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::VarIntLimit => f.write_str("varint longer than 10 bytes"),
+            DecodeError::UnexpectedEof => f.write_str("unexpected end of input"),
+            DecodeError::Deprecation => f.write_str("deprecated wire type"),
+            DecodeError::UnknownWireType => f.write_str("unknown wire type"),
+            DecodeError::WrongWireType => f.write_str("wire type doesn't match field type"),
+            DecodeError::GroupMismatch => {
+                f.write_str("end-group tag doesn't match start-group tag")
+            }
+            DecodeError::ZeroField => f.write_str("field number 0 is not allowed"),
+            DecodeError::CustomField => f.write_str("custom field rejected its field number"),
+            DecodeError::Utf8 => f.write_str("string is not valid UTF-8"),
+            DecodeError::Capacity { needed, capacity } => write!(
+                f,
+                "field exceeded capacity of fixed container: needed {needed} more, capacity is {capacity}"
+            ),
+            DecodeError::WrongLen => f.write_str("record length doesn't match length prefix"),
+            DecodeError::SizeLimit => f.write_str("message length exceeded limit"),
+            DecodeError::DepthLimit => f.write_str("message nesting depth exceeded limit"),
+            DecodeError::NonContiguous => f.write_str("reader input is not contiguous"),
+            DecodeError::UnknownEnumValue => f.write_str("enum value not declared in enum"),
+            DecodeError::CrcMismatch => f.write_str("frame checksum mismatch"),
+            DecodeError::CompressedFrame => f.write_str("compressed frames are not supported"),
+            DecodeError::IntOverflow => f.write_str("integer doesn't fit into pointer-sized field"),
+            DecodeError::Reader(e) => write!(f, "reader error: {e}"),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", feature = "core-error"))]
+impl<E: crate::ErrorTrait + 'static> crate::ErrorTrait for DecodeError<E> {
+    fn source(&self) -> Option<&(dyn crate::ErrorTrait + 'static)> {
+        match self {
+            DecodeError::Reader(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// Policy for when a fixed-capacity container runs out of space during decoding.
+///
+/// With the drop policies, excess elements are still fully decoded from the wire before being
+/// discarded, so the rest of the message is parsed correctly. For `string` and `bytes` values,
+/// the excess bytes are dropped, truncating the value to fit into the container.
+pub enum OverflowPolicy {
+    /// Return [`DecodeError::Capacity`], unless the decoder's `ignore_repeated_cap_err` flag is
+    /// set.
+    #[default]
+    Error,
+    /// Silently discard the excess elements.
+    DropExtra,
+    /// Discard the excess elements and set the decoder's [`truncated`](PbDecoder::truncated)
+    /// flag.
+    DropExtraWithFlag,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Location of the [decoder](PbDecoder) within the input, returned by [`PbDecoder::location`].
+pub struct DecodeLocation {
+    /// Number of bytes consumed from the input
+    pub offset: usize,
+    /// Number of the innermost field being decoded, or 0 if no field has been decoded yet
+    pub field_num: u32,
+    /// Nesting depth of the innermost message being decoded, with 0 being the top-level message
+    pub depth: usize,
+}
+
+impl Display for DecodeLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Field number is 0 if the error occurred before the first tag was decoded
+        if self.field_num != 0 {
+            write!(f, "field {}, ", self.field_num)?;
+        }
+        write!(f, "offset {}", self.offset)?;
+        if self.depth > 0 {
+            write!(f, ", depth {}", self.depth)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// [`DecodeError`] along with the location in the input where it occurred.
+///
+/// Created by [`PbDecoder::locate`]. Displays as `<error> at field <num>, offset <offset>`. The
+/// field number is left out if no field has been decoded yet, and the depth is added for errors
+/// inside nested messages.
+pub struct LocatedDecodeError<E> {
+    /// Decode error
+    pub error: DecodeError<E>,
+    /// Location of the error
+    pub location: DecodeLocation,
+}
+
+impl<E: Display> Display for LocatedDecodeError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}", self.error, self.location)
+    }
+}
+
+#[cfg(any(feature = "std", feature = "core-error"))]
+impl<E: crate::ErrorTrait + 'static> crate::ErrorTrait for LocatedDecodeError<E> {
+    // The decode error is already part of the message, so skip straight to the reader error
+    fn source(&self) -> Option<&(dyn crate::ErrorTrait + 'static)> {
+        self.error.source()
+    }
+}
+
 /// A reader from which Protobuf data is read, similar to [`std::io::BufRead`].
 ///
 /// Like [`std::io::BufRead`], this trait assumes that the reader uses an underlying buffer.
@@ -57,6 +220,13 @@ pub trait PbRead {
     /// I/O error returned on read failure.
     type Error;
 
+    /// Whether the reader reads from a single contiguous buffer, so that the number of bytes read
+    /// is always an offset into that buffer.
+    ///
+    /// Only contiguous readers support decoding `bytes` values as ranges into the input via
+    /// [`PbDecoder::decode_bytes_range`].
+    const CONTIGUOUS: bool = false;
+
     /// Returns the internal buffer, filling it with more data if necessary.
     ///
     /// This call does not consume the underlying buffer, so calling it consecutively may yield the
@@ -103,11 +273,30 @@ pub trait PbRead {
         debug_assert_eq!(pos, buf.len());
         Ok(pos)
     }
+
+    /// Called by the decoder after skipping an unknown field, with the field's tag and the number
+    /// of bytes its value occupied on the wire, not counting the tag.
+    ///
+    /// Does nothing by default, so readers that don't override it have no overhead. Wrap a reader
+    /// in [`UnknownFieldHook`] to observe unknown fields.
+    #[inline]
+    fn pb_on_unknown_field(&mut self, _tag: Tag, _len: usize) {}
+
+    /// Called by the decoder after decoding a field, with the nesting depth of the message
+    /// containing the field, the field number, and the number of bytes the field occupied on the
+    /// wire, including its tag.
+    ///
+    /// Only called by generated code that was generated with field statistics enabled. Does
+    /// nothing by default. Wrap a reader in [`FieldStatsReader`] to collect the statistics.
+    #[inline]
+    fn pb_record_field(&mut self, _depth: u8, _field_num: u32, _bytes: usize) {}
 }
 
 impl<T: PbRead> PbRead for &mut T {
     type Error = T::Error;
 
+    const CONTIGUOUS: bool = T::CONTIGUOUS;
+
     #[inline]
     fn pb_read_chunk(&mut self) -> Result<&[u8], Self::Error> {
         (*self).pb_read_chunk()
@@ -122,11 +311,23 @@ impl<T: PbRead> PbRead for &mut T {
     fn pb_read_exact(&mut self, buf: &mut [MaybeUninit<u8>]) -> Result<usize, Self::Error> {
         (*self).pb_read_exact(buf)
     }
+
+    #[inline]
+    fn pb_on_unknown_field(&mut self, tag: Tag, len: usize) {
+        (*self).pb_on_unknown_field(tag, len)
+    }
+
+    #[inline]
+    fn pb_record_field(&mut self, depth: u8, field_num: u32, bytes: usize) {
+        (*self).pb_record_field(depth, field_num, bytes)
+    }
 }
 
 impl PbRead for &[u8] {
     type Error = Never;
 
+    const CONTIGUOUS: bool = true;
+
     #[inline]
     fn pb_read_chunk(&mut self) -> Result<&[u8], Self::Error> {
         Ok(*self)
@@ -145,214 +346,840 @@ impl PbRead for &[u8] {
     }
 }
 
-#[cfg(feature = "std")]
-#[derive(Debug, Clone)]
-/// Adapter that implements [`PbRead`] for all implementers of [`std::io::BufRead`], allowing the
-/// decoder to read from `std` readers.
-pub struct StdReader<R>(pub R);
-
-#[cfg(feature = "std")]
-impl<R: std::io::BufRead> PbRead for StdReader<R> {
-    type Error = std::io::Error;
+/// Reader over two consecutive slices, such as the two halves of a wrapped-around ring buffer.
+///
+/// All bytes from the first slice are read before the bytes from the second slice. Values that
+/// straddle the boundary between the two slices are handled correctly.
+impl PbRead for (&[u8], &[u8]) {
+    type Error = Never;
 
     #[inline]
     fn pb_read_chunk(&mut self) -> Result<&[u8], Self::Error> {
-        self.0.fill_buf()
+        if self.0.is_empty() {
+            Ok(self.1)
+        } else {
+            Ok(self.0)
+        }
     }
 
     #[inline]
     fn pb_advance(&mut self, bytes: usize) {
-        self.0.consume(bytes)
+        if self.0.is_empty() {
+            self.1.pb_advance(bytes)
+        } else {
+            self.0.pb_advance(bytes)
+        }
+    }
+
+    #[inline]
+    fn pb_read_exact(&mut self, buf: &mut [MaybeUninit<u8>]) -> Result<usize, Self::Error> {
+        let n = self.0.pb_read_exact(buf)?;
+        let m = self.1.pb_read_exact(&mut buf[n..])?;
+        Ok(n + m)
     }
 }
 
-#[derive(Debug)]
-/// Decoder that reads Protobuf bytes and decodes them into Rust types.
-///
-/// Main interface for decoding Protobuf messages. Reads bytes from an underlying [`PbRead`]
-/// instance.
-///
-/// # Example
+#[derive(Debug, Clone)]
+/// Adapter that wraps a [`PbRead`] and calls `hook` whenever the decoder skips an unknown field.
 ///
-/// Decoding a Protobuf message:
-/// ```no_run
-/// use micropb::{PbRead, PbDecoder, MessageDecode, DecodeError};
+/// The hook is called with the field number, wire type, and length in bytes of the skipped value,
+/// not counting the tag. This includes unknown fields inside nested messages, so the hook can be
+/// used to cheaply detect schema drift without preserving the unknown fields. The hook only
+/// observes the fields, so it can't affect how the rest of the input is decoded. Unknown fields
+/// inside skipped groups are not reported individually.
 ///
-/// # #[derive(Default)]
-/// # struct ProtoMessage;
-/// # impl micropb::MessageDecode for ProtoMessage {
-/// #   fn decode<R: PbRead>(&mut self, decoder: &mut PbDecoder<R>, len: usize) -> Result<(), micropb::DecodeError<R::Error>> { todo!() }
-/// # }
+/// Since the hook is invoked through the reader, decoders over other readers don't pay for it.
 ///
-/// let data = [0x08, 0x96, 0x01];
-/// // Slices implement `PbRead` out of the box
-/// let mut decoder = PbDecoder::new(data.as_slice());
+/// # Example
 ///
-/// let mut message = ProtoMessage::default();
-/// message.decode(&mut decoder, data.len())?;
-/// # Ok::<(), DecodeError<never::Never>>(())
 /// ```
+/// use micropb::{PbDecoder, UnknownFieldHook};
 ///
-/// # Reducing Code Size
-///
-/// To prevent multiple monomorphizations and increased code size, make sure you instantiate
-/// `PbDecoder` with only one reader type across the whole application. If multiple readers need to
-/// be supported, wrap them in an enum or use a trait object.
-///
-/// Likewise, try to only use one set of string, vec, and map types across the application to
-/// reduce code size.
-pub struct PbDecoder<R: PbRead> {
+/// let mut unknown = vec![];
+/// let reader = UnknownFieldHook::new([0x08, 0x01, 0x12, 0x01, 0xFF].as_slice(), |num, wire_type, len| {
+///     unknown.push((num, wire_type, len));
+/// });
+/// let mut decoder = PbDecoder::new(reader);
+/// while !decoder.is_eof().unwrap() {
+///     let tag = decoder.decode_tag().unwrap();
+///     decoder.skip_unknown_field(tag).unwrap();
+/// }
+/// assert_eq!(unknown, [(1, 0, 1), (2, 2, 2)]);
+/// ```
+pub struct UnknownFieldHook<R, F> {
     reader: R,
-    idx: usize,
-    /// If this flag is set, then the decoder will never report a capacity error when decoding
-    /// repeated fields. When the container is filled, the decoder will instead ignore excess
-    /// elements on the wire. The decoder will still report capacity errors when decoding `bytes`
-    /// and `string` values that exceed their fixed containers.
-    pub ignore_repeated_cap_err: bool,
+    hook: F,
 }
 
-impl<R: PbRead> PbDecoder<R> {
-    #[inline]
-    /// Construct a new decoder from a [`PbRead`].
-    pub fn new(reader: R) -> Self {
-        Self {
-            reader,
-            idx: 0,
-            ignore_repeated_cap_err: false,
-        }
+impl<R: PbRead, F: FnMut(u32, u8, usize)> UnknownFieldHook<R, F> {
+    /// Wrap `reader` with a hook for unknown fields.
+    pub fn new(reader: R, hook: F) -> Self {
+        Self { reader, hook }
     }
 
-    #[inline]
-    /// Transform the decoder into the underlying reader.
-    pub fn into_reader(self) -> R {
+    /// Transform the adapter into the underlying reader.
+    pub fn into_inner(self) -> R {
         self.reader
     }
+}
+
+impl<R: PbRead, F: FnMut(u32, u8, usize)> PbRead for UnknownFieldHook<R, F> {
+    type Error = R::Error;
+
+    const CONTIGUOUS: bool = R::CONTIGUOUS;
 
     #[inline]
-    /// Get reference to underlying reader.
-    pub fn as_reader(&self) -> &R {
-        &self.reader
+    fn pb_read_chunk(&mut self) -> Result<&[u8], Self::Error> {
+        self.reader.pb_read_chunk()
     }
 
     #[inline]
-    /// Get the number of bytes that the decoder has consumed from the reader.
-    pub fn bytes_read(&self) -> usize {
-        self.idx
+    fn pb_advance(&mut self, bytes: usize) {
+        self.reader.pb_advance(bytes)
     }
 
     #[inline]
-    fn advance(&mut self, bytes: usize) {
-        self.reader.pb_advance(bytes);
-        self.idx += bytes;
+    fn pb_read_exact(&mut self, buf: &mut [MaybeUninit<u8>]) -> Result<usize, Self::Error> {
+        self.reader.pb_read_exact(buf)
     }
 
     #[inline]
-    fn get_byte(&mut self) -> Result<u8, DecodeError<R::Error>> {
-        let chunk = self.reader.pb_read_chunk().map_err(DecodeError::Reader)?;
-        let b = chunk.first().copied().ok_or(DecodeError::UnexpectedEof)?;
-        self.advance(1);
-        Ok(b)
+    fn pb_on_unknown_field(&mut self, tag: Tag, len: usize) {
+        self.reader.pb_on_unknown_field(tag, len);
+        (self.hook)(tag.field_num(), tag.wire_type(), len)
     }
 
-    /// Decode an `uint32`.
-    pub fn decode_varint32(&mut self) -> Result<u32, DecodeError<R::Error>> {
-        let b = self.get_byte()?;
-        // Single byte case
-        if b & 0x80 == 0 {
-            return Ok(b as u32);
-        }
+    #[inline]
+    fn pb_record_field(&mut self, depth: u8, field_num: u32, bytes: usize) {
+        self.reader.pb_record_field(depth, field_num, bytes)
+    }
+}
 
-        let mut varint: u32 = b as u32 & !0x80;
-        let mut bitpos = 7;
-        for i in 1..10 {
-            let b = self.get_byte()?;
-            // Take the first 5 bytes into account, but ignore the later 5 bytes since they're
-            // going to be truncated anyways
-            if i < 5 {
-                let u = b & !0x80;
-                varint |= (u as u32) << bitpos;
-                bitpos += 7;
-            }
-            if b & 0x80 == 0 {
-                return Ok(varint);
-            }
-        }
-        Err(DecodeError::VarIntLimit)
+/// Sink for the number of bytes taken up by each decoded field.
+///
+/// Used with [`FieldStatsReader`].
+pub trait FieldStats {
+    /// Record a decoded field.
+    ///
+    /// `depth` is the nesting depth of the message containing the field, starting from 0 for the
+    /// top-level message. `bytes` is the size of the field on the wire, including its tag. Fields
+    /// of nested messages are recorded individually at the next depth, and the field containing
+    /// the nested message is recorded with the total size of the nested message.
+    fn record(&mut self, depth: u8, field_num: u32, bytes: usize);
+}
+
+impl<T: FieldStats> FieldStats for &mut T {
+    #[inline]
+    fn record(&mut self, depth: u8, field_num: u32, bytes: usize) {
+        (*self).record(depth, field_num, bytes)
     }
+}
 
-    #[cfg(feature = "enable-64bit")]
-    /// Decode an `uint64`.
-    pub fn decode_varint64(&mut self) -> Result<u64, DecodeError<R::Error>> {
-        let b = self.get_byte()?;
-        // Single byte case
-        if b & 0x80 == 0 {
-            return Ok(b as u64);
-        }
+#[derive(Debug, Clone)]
+/// Adapter that wraps a [`PbRead`] and reports the size of every decoded field to a
+/// [`FieldStats`] sink.
+///
+/// Fields are only reported by messages generated with
+/// `micropb_gen::Generator::field_stats` enabled, so decoding code without field statistics
+/// has no overhead. Unknown fields are reported as well.
+///
+/// # Example
+///
+/// ```
+/// use micropb::{FieldStats, FieldStatsReader, PbDecoder};
+///
+/// #[derive(Default)]
+/// struct Total(usize);
+///
+/// impl FieldStats for Total {
+///     fn record(&mut self, depth: u8, _field_num: u32, bytes: usize) {
+///         if depth == 0 {
+///             self.0 += bytes;
+///         }
+///     }
+/// }
+///
+/// let mut total = Total::default();
+/// let data = [0x08, 0x01];
+/// let mut decoder = PbDecoder::new(FieldStatsReader::new(data.as_slice(), &mut total));
+/// // Generated code with field statistics does this for every field
+/// let start = decoder.bytes_read();
+/// let tag = decoder.decode_tag()?;
+/// decoder.decode_varint32()?;
+/// decoder.record_field(tag.field_num(), start);
+///
+/// assert_eq!(total.0, 2);
+/// # Ok::<(), micropb::DecodeError<micropb::Never>>(())
+/// ```
+pub struct FieldStatsReader<R, S> {
+    reader: R,
+    stats: S,
+}
 
-        let mut varint: u64 = b as u64 & !0x80;
-        let mut bitpos = 7;
-        for _ in 1..10 {
-            let b = self.get_byte()?;
-            let u = b & !0x80;
-            varint |= (u as u64) << bitpos;
-            bitpos += 7;
-            if b & 0x80 == 0 {
-                return Ok(varint);
-            }
-        }
-        Err(DecodeError::VarIntLimit)
+impl<R: PbRead, S: FieldStats> FieldStatsReader<R, S> {
+    /// Wrap `reader` with a sink for field statistics.
+    pub fn new(reader: R, stats: S) -> Self {
+        Self { reader, stats }
+    }
+
+    /// Transform the adapter into the underlying reader and the statistics sink.
+    pub fn into_inner(self) -> (R, S) {
+        (self.reader, self.stats)
     }
+}
+
+impl<R: PbRead, S: FieldStats> PbRead for FieldStatsReader<R, S> {
+    type Error = R::Error;
+
+    const CONTIGUOUS: bool = R::CONTIGUOUS;
 
     #[inline]
-    #[cfg(feature = "enable-64bit")]
-    /// Decode an `int64`.
-    pub fn decode_int64(&mut self) -> Result<i64, DecodeError<R::Error>> {
-        self.decode_varint64().map(|u| u as i64)
+    fn pb_read_chunk(&mut self) -> Result<&[u8], Self::Error> {
+        self.reader.pb_read_chunk()
     }
 
     #[inline]
-    /// Decode an `int32`.
-    pub fn decode_int32(&mut self) -> Result<i32, DecodeError<R::Error>> {
-        self.decode_varint32().map(|u| u as i32)
+    fn pb_advance(&mut self, bytes: usize) {
+        self.reader.pb_advance(bytes)
     }
 
     #[inline]
-    /// Decode an `sint32`.
-    pub fn decode_sint32(&mut self) -> Result<i32, DecodeError<R::Error>> {
-        self.decode_varint32()
-            .map(|u| ((u >> 1) as i32) ^ -((u & 1) as i32))
+    fn pb_read_exact(&mut self, buf: &mut [MaybeUninit<u8>]) -> Result<usize, Self::Error> {
+        self.reader.pb_read_exact(buf)
     }
 
     #[inline]
-    #[cfg(feature = "enable-64bit")]
-    /// Decode an `sint64`.
-    pub fn decode_sint64(&mut self) -> Result<i64, DecodeError<R::Error>> {
-        self.decode_varint64()
-            .map(|u| ((u >> 1) as i64) ^ -((u & 1) as i64))
+    fn pb_on_unknown_field(&mut self, tag: Tag, len: usize) {
+        self.reader.pb_on_unknown_field(tag, len)
     }
 
     #[inline]
-    /// Decode a `bool`.
-    pub fn decode_bool(&mut self) -> Result<bool, DecodeError<R::Error>> {
-        Ok(self.decode_varint32()? != 0)
+    fn pb_record_field(&mut self, depth: u8, field_num: u32, bytes: usize) {
+        self.reader.pb_record_field(depth, field_num, bytes);
+        self.stats.record(depth, field_num, bytes)
     }
+}
 
-    fn read_exact(&mut self, buf: &mut [MaybeUninit<u8>]) -> Result<(), DecodeError<R::Error>> {
-        let bytes_read = self
-            .reader
-            .pb_read_exact(buf)
-            .map_err(DecodeError::Reader)?;
-        self.idx += bytes_read;
+#[derive(Debug, Clone)]
+/// Adapter that implements [`PbRead`] on top of a `read` callback, using a fixed-size internal
+/// buffer of `N` bytes.
+///
+/// The callback has the same semantics as [`std::io::Read::read`] or `embedded_io::Read::read`:
+/// it fills the start of the provided buffer and returns the number of bytes written, with 0
+/// indicating EOF. This allows the decoder to read from any transport, such as a UART driver,
+/// without copying the whole message into an intermediate buffer first.
+///
+/// Short reads are handled transparently. Errors returned by the callback, including
+/// `WouldBlock`-style errors from non-blocking drivers, are surfaced immediately as
+/// [`DecodeError::Reader`] without retrying, so the transport's error type is preserved. Note
+/// that the decoder can't resume a value that was interrupted by an error, so non-blocking
+/// transports should only return errors between messages.
+///
+/// # Example
+///
+/// ```
+/// use micropb::{BufferedReader, PbDecoder};
+///
+/// let mut data = [0x96, 0x01].as_slice();
+/// let reader = BufferedReader::<_, 16>::new(|buf: &mut [u8]| {
+///     // Deliver one byte at a time
+///     let n = data.len().min(buf.len()).min(1);
+///     buf[..n].copy_from_slice(&data[..n]);
+///     data = &data[n..];
+///     Ok::<_, ()>(n)
+/// });
+/// let mut decoder = PbDecoder::new(reader);
+/// assert_eq!(decoder.decode_varint32(), Ok(150));
+/// ```
+pub struct BufferedReader<F, const N: usize> {
+    read_fn: F,
+    buf: ReadBuf<N>,
+}
 
-        if bytes_read < buf.len() {
-            return Err(DecodeError::UnexpectedEof);
+impl<E, F: FnMut(&mut [u8]) -> Result<usize, E>, const N: usize> BufferedReader<F, N> {
+    /// Construct an adapter from a `read` callback.
+    pub fn new(read_fn: F) -> Self {
+        Self {
+            read_fn,
+            buf: ReadBuf::new(),
         }
-        Ok(())
     }
 
-    #[inline]
-    /// Decode a `fixed32`.
-    pub fn decode_fixed32(&mut self) -> Result<u32, DecodeError<R::Error>> {
+    /// Get the bytes that have been read from the callback but not yet consumed.
+    pub fn buffer(&self) -> &[u8] {
+        self.buf.buffer()
+    }
+
+    /// Transform the adapter into the underlying callback. Any buffered data will be lost.
+    pub fn into_inner(self) -> F {
+        self.read_fn
+    }
+}
+
+impl<E, F: FnMut(&mut [u8]) -> Result<usize, E>, const N: usize> PbRead for BufferedReader<F, N> {
+    type Error = E;
+
+    #[inline]
+    fn pb_read_chunk(&mut self) -> Result<&[u8], Self::Error> {
+        self.buf.fill(&mut self.read_fn)
+    }
+
+    #[inline]
+    fn pb_advance(&mut self, bytes: usize) {
+        self.buf.advance(bytes)
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Fixed-size buffer of bytes that have been read from a transport but not yet consumed
+struct ReadBuf<const N: usize> {
+    buf: [u8; N],
+    start: usize,
+    end: usize,
+}
+
+impl<const N: usize> ReadBuf<N> {
+    fn new() -> Self {
+        Self {
+            buf: [0; N],
+            start: 0,
+            end: 0,
+        }
+    }
+
+    fn buffer(&self) -> &[u8] {
+        &self.buf[self.start..self.end]
+    }
+
+    /// Return the unconsumed bytes, refilling the buffer with `read` once all bytes are consumed
+    #[inline]
+    fn fill<E>(&mut self, read: impl FnOnce(&mut [u8]) -> Result<usize, E>) -> Result<&[u8], E> {
+        if self.start >= self.end {
+            let n = read(&mut self.buf)?;
+            self.start = 0;
+            self.end = n.min(N);
+        }
+        Ok(self.buffer())
+    }
+
+    #[inline]
+    fn advance(&mut self, bytes: usize) {
+        self.start = (self.start + bytes).min(self.end);
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+#[derive(Debug, Clone)]
+/// Adapter that implements [`PbRead`] for all implementers of [`embedded_io::Read`], using a
+/// fixed-size internal buffer of `N` bytes.
+///
+/// This allows the decoder to read directly from drivers such as UARTs, without copying the whole
+/// message into an intermediate buffer first. Short reads are handled transparently. Errors from
+/// the driver, including `WouldBlock`-style errors from non-blocking drivers, are surfaced
+/// immediately as [`DecodeError::Reader`] without retrying, so the driver's error type is
+/// preserved. Note that the decoder can't resume a value that was interrupted by an error, so
+/// non-blocking drivers should only return errors between messages.
+///
+/// Since the adapter buffers data internally, it may read past the end of the message being
+/// decoded. For drivers that implement [`embedded_io::BufRead`], use [`EmbeddedIoBufReader`]
+/// instead, which reads straight from the driver's buffer.
+pub struct EmbeddedIoReader<R, const N: usize> {
+    reader: R,
+    buf: ReadBuf<N>,
+}
+
+#[cfg(feature = "embedded-io")]
+impl<R: embedded_io::Read, const N: usize> EmbeddedIoReader<R, N> {
+    /// Wrap a reader with an internal buffer of `N` bytes.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: ReadBuf::new(),
+        }
+    }
+
+    /// Get reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Get the bytes that have been read from the underlying reader but not yet consumed.
+    pub fn buffer(&self) -> &[u8] {
+        self.buf.buffer()
+    }
+
+    /// Transform the adapter into the underlying reader. Any buffered data will be lost.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl<R: embedded_io::Read, const N: usize> PbRead for EmbeddedIoReader<R, N> {
+    type Error = R::Error;
+
+    #[inline]
+    fn pb_read_chunk(&mut self) -> Result<&[u8], Self::Error> {
+        self.buf.fill(|buf| self.reader.read(buf))
+    }
+
+    #[inline]
+    fn pb_advance(&mut self, bytes: usize) {
+        self.buf.advance(bytes)
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+#[derive(Debug, Clone)]
+/// Adapter that implements [`PbRead`] for all implementers of [`embedded_io::BufRead`], which
+/// decodes straight from the driver's own buffer without any copies.
+///
+/// Errors from the driver are surfaced immediately as [`DecodeError::Reader`] without retrying.
+pub struct EmbeddedIoBufReader<R>(pub R);
+
+#[cfg(feature = "embedded-io")]
+impl<R: embedded_io::BufRead> PbRead for EmbeddedIoBufReader<R> {
+    type Error = R::Error;
+
+    #[inline]
+    fn pb_read_chunk(&mut self) -> Result<&[u8], Self::Error> {
+        self.0.fill_buf()
+    }
+
+    #[inline]
+    fn pb_advance(&mut self, bytes: usize) {
+        self.0.consume(bytes)
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+/// Adapter that implements [`PbRead`] for all implementers of [`std::io::BufRead`], allowing the
+/// decoder to read from `std` readers.
+pub struct StdReader<R>(pub R);
+
+#[cfg(feature = "std")]
+impl<R: std::io::BufRead> PbRead for StdReader<R> {
+    type Error = std::io::Error;
+
+    #[inline]
+    fn pb_read_chunk(&mut self) -> Result<&[u8], Self::Error> {
+        std_fill_buf(&mut self.0)
+    }
+
+    #[inline]
+    fn pb_advance(&mut self, bytes: usize) {
+        self.0.consume(bytes)
+    }
+}
+
+#[cfg(feature = "std")]
+/// Fill the buffer of a [`std::io::BufRead`], retrying on [`std::io::ErrorKind::Interrupted`].
+fn std_fill_buf<R: std::io::BufRead>(reader: &mut R) -> Result<&[u8], std::io::Error> {
+    loop {
+        match reader.fill_buf() {
+            // Reached EOF, so don't try reading again
+            Ok([]) => return Ok(&[]),
+            Ok(_) => break,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    // The buffer has already been filled and is non-empty, so this call won't perform any I/O
+    reader.fill_buf()
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug)]
+/// Adapter that implements [`PbRead`] for all implementers of [`std::io::Read`], allowing the
+/// decoder to read directly from unbuffered sources such as files and sockets.
+///
+/// Data is read from the underlying reader into an internal buffer, so short reads from the
+/// reader are handled transparently. Reads that fail with [`std::io::ErrorKind::Interrupted`] are
+/// retried, while all other I/O errors are returned as [`DecodeError::Reader`]. If the reader
+/// reaches EOF in the middle of a value, the decoder returns [`DecodeError::UnexpectedEof`].
+///
+/// Since the adapter buffers data internally, it may read past the end of the message being
+/// decoded. Use [`into_inner`](Self::into_inner) with care.
+pub struct StdBufReader<R>(std::io::BufReader<R>);
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read> StdBufReader<R> {
+    /// Wrap a reader with a default-sized internal buffer.
+    pub fn new(reader: R) -> Self {
+        Self(std::io::BufReader::new(reader))
+    }
+
+    /// Wrap a reader with an internal buffer of the specified capacity.
+    pub fn with_capacity(capacity: usize, reader: R) -> Self {
+        Self(std::io::BufReader::with_capacity(capacity, reader))
+    }
+
+    /// Get reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        self.0.get_ref()
+    }
+
+    /// Get the bytes that have been read from the underlying reader but not yet consumed.
+    pub fn buffer(&self) -> &[u8] {
+        self.0.buffer()
+    }
+
+    /// Transform the adapter into the underlying reader. Any buffered data will be lost.
+    pub fn into_inner(self) -> R {
+        self.0.into_inner()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read> PbRead for StdBufReader<R> {
+    type Error = std::io::Error;
+
+    #[inline]
+    fn pb_read_chunk(&mut self) -> Result<&[u8], Self::Error> {
+        std_fill_buf(&mut self.0)
+    }
+
+    #[inline]
+    fn pb_advance(&mut self, bytes: usize) {
+        std::io::BufRead::consume(&mut self.0, bytes)
+    }
+}
+
+#[derive(Debug)]
+/// Decoder that reads Protobuf bytes and decodes them into Rust types.
+///
+/// Main interface for decoding Protobuf messages. Reads bytes from an underlying [`PbRead`]
+/// instance.
+///
+/// # Example
+///
+/// Decoding a Protobuf message:
+/// ```no_run
+/// use micropb::{PbRead, PbDecoder, MessageDecode, DecodeError};
+///
+/// # #[derive(Default)]
+/// # struct ProtoMessage;
+/// # impl micropb::MessageDecode for ProtoMessage {
+/// #   fn decode<R: PbRead>(&mut self, decoder: &mut PbDecoder<R>, len: usize) -> Result<(), micropb::DecodeError<R::Error>> { todo!() }
+/// # }
+///
+/// let data = [0x08, 0x96, 0x01];
+/// // Slices implement `PbRead` out of the box
+/// let mut decoder = PbDecoder::new(data.as_slice());
+///
+/// let mut message = ProtoMessage::default();
+/// message.decode(&mut decoder, data.len())?;
+/// # Ok::<(), DecodeError<micropb::Never>>(())
+/// ```
+///
+/// # Manual Decoding
+///
+/// The primitives used by the generated code are public, so messages can also be decoded by hand.
+/// This is useful for routing on a field before decoding the rest of the message:
+/// ```
+/// use micropb::{PbDecoder, DecodeError, Never, WIRE_TYPE_LEN, WIRE_TYPE_VARINT};
+///
+/// // Field 1 is the message kind, and field 2 is a nested message with a varint in field 1
+/// let data = [0x08, 0x02, 0x12, 0x02, 0x08, 0x05];
+/// let mut decoder = PbDecoder::new(data.as_slice());
+///
+/// let (mut kind, mut inner) = (0, 0);
+/// while !decoder.is_eof()? {
+///     let tag = decoder.decode_tag()?;
+///     match (tag.field_num(), tag.wire_type()) {
+///         (1, WIRE_TYPE_VARINT) => kind = decoder.decode_varint32()?,
+///         (2, WIRE_TYPE_LEN) => decoder.decode_len_delimited(|decoder, len| {
+///             let end = decoder.bytes_read() + len;
+///             while decoder.bytes_read() < end {
+///                 let tag = decoder.decode_tag()?;
+///                 match tag.field_num() {
+///                     1 => inner = decoder.decode_varint32()?,
+///                     _ => decoder.skip_wire_value(tag.wire_type())?,
+///                 }
+///             }
+///             Ok(())
+///         })?,
+///         // Skip unknown fields
+///         _ => decoder.skip_wire_value(tag.wire_type())?,
+///     }
+/// }
+/// assert_eq!((kind, inner), (2, 5));
+/// # Ok::<(), DecodeError<Never>>(())
+/// ```
+///
+/// # Reducing Code Size
+///
+/// To prevent multiple monomorphizations and increased code size, make sure you instantiate
+/// `PbDecoder` with only one reader type across the whole application. If multiple readers need to
+/// be supported, wrap them in an enum or use a trait object.
+///
+/// Likewise, try to only use one set of string, vec, and map types across the application to
+/// reduce code size.
+pub struct PbDecoder<R: PbRead> {
+    reader: R,
+    idx: usize,
+    /// If this flag is set, then the decoder will never report a capacity error when decoding
+    /// repeated fields. When the container is filled, the decoder will instead ignore excess
+    /// elements on the wire. The decoder will still report capacity errors when decoding `bytes`
+    /// and `string` values that exceed their fixed containers.
+    pub ignore_repeated_cap_err: bool,
+    /// Maximum nesting depth of messages. Decoding a message nested deeper than this returns
+    /// [`DecodeError::DepthLimit`], which prevents malicious inputs from overflowing the stack.
+    ///
+    /// Defaults to [`DEFAULT_MAX_DEPTH`](Self::DEFAULT_MAX_DEPTH).
+    pub max_depth: usize,
+    /// Maximum number of input bytes processed by the decoder, counted from the start of the
+    /// input and including all nested content. Decoding a message or length-delimited value that
+    /// would extend past this limit returns [`DecodeError::SizeLimit`] before any of it is read,
+    /// which bounds the time and memory spent on oversized inputs.
+    ///
+    /// Defaults to `usize::MAX`, which means there's no limit.
+    pub max_size: usize,
+    /// Set when elements or bytes are discarded from a field with the
+    /// [`DropExtraWithFlag`](OverflowPolicy::DropExtraWithFlag) policy. The decoder never clears
+    /// this flag, so it reports whether any truncation happened since it was last reset.
+    pub truncated: bool,
+    depth: usize,
+    field_num: u32,
+    /// Location of the innermost error, captured before the location is restored on the way out
+    /// of nested records
+    error_location: Option<DecodeLocation>,
+}
+
+impl<R: PbRead> PbDecoder<R> {
+    /// Default value of [`max_depth`](Self::max_depth).
+    pub const DEFAULT_MAX_DEPTH: usize = 32;
+
+    #[inline]
+    /// Construct a new decoder from a [`PbRead`].
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            idx: 0,
+            ignore_repeated_cap_err: false,
+            max_depth: Self::DEFAULT_MAX_DEPTH,
+            max_size: usize::MAX,
+            truncated: false,
+            depth: 0,
+            field_num: 0,
+            error_location: None,
+        }
+    }
+
+    #[inline]
+    /// Transform the decoder into the underlying reader.
+    pub fn into_reader(self) -> R {
+        self.reader
+    }
+
+    #[inline]
+    /// Get reference to underlying reader.
+    pub fn as_reader(&self) -> &R {
+        &self.reader
+    }
+
+    #[inline]
+    /// Get the number of bytes that the decoder has consumed from the reader.
+    ///
+    /// This is the decoder's position within the input, so it can be used to find where decoding
+    /// stopped, such as after a length-delimited message that's followed by other data.
+    pub fn bytes_read(&self) -> usize {
+        self.idx
+    }
+
+    /// Get the current location of the decoder within the input.
+    ///
+    /// After a decoding error, this returns the location where the error occurred, including the
+    /// innermost field number and nesting depth.
+    pub fn location(&self) -> DecodeLocation {
+        self.error_location.unwrap_or(DecodeLocation {
+            offset: self.idx,
+            field_num: self.field_num,
+            depth: self.depth,
+        })
+    }
+
+    /// Attach the decoder's current [location](Self::location) to a decode error.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use micropb::PbDecoder;
+    ///
+    /// let data = [0x08, 0x96];
+    /// let mut decoder = PbDecoder::new(data.as_slice());
+    /// decoder.decode_tag().unwrap();
+    /// let err = decoder.decode_varint32().map_err(|e| decoder.locate(e)).unwrap_err();
+    /// assert_eq!(err.to_string(), "unexpected end of input at field 1, offset 2");
+    /// ```
+    pub fn locate(&self, error: DecodeError<R::Error>) -> LocatedDecodeError<R::Error> {
+        LocatedDecodeError {
+            error,
+            location: self.location(),
+        }
+    }
+
+    /// Check if the reader has reached EOF.
+    ///
+    /// This may perform I/O on the underlying reader, but won't consume any bytes.
+    pub fn is_eof(&mut self) -> Result<bool, DecodeError<R::Error>> {
+        let chunk = self.reader.pb_read_chunk().map_err(DecodeError::Reader)?;
+        Ok(chunk.is_empty())
+    }
+
+    #[inline]
+    fn advance(&mut self, bytes: usize) {
+        self.reader.pb_advance(bytes);
+        self.idx += bytes;
+    }
+
+    #[inline]
+    pub(crate) fn get_byte(&mut self) -> Result<u8, DecodeError<R::Error>> {
+        let chunk = self.reader.pb_read_chunk().map_err(DecodeError::Reader)?;
+        let b = chunk.first().copied().ok_or(DecodeError::UnexpectedEof)?;
+        self.advance(1);
+        Ok(b)
+    }
+
+    /// Decode a varint directly from the reader's current chunk if it holds at least 10 bytes, so
+    /// the varint can't cross a chunk boundary. Returns `None` if the chunk is too short, in which
+    /// case the varint is decoded byte-by-byte instead.
+    #[inline]
+    fn decode_varint_fast<U: VarInt>(&mut self) -> Option<Result<U, DecodeError<R::Error>>> {
+        let chunk = match self.reader.pb_read_chunk() {
+            Ok(chunk) => chunk,
+            Err(e) => return Some(Err(DecodeError::Reader(e))),
+        };
+        let bytes: [u8; 10] = chunk.get(..10)?.try_into().ok()?;
+        // Bits past the width of `U` are truncated, but the continuation bits of all 10 bytes
+        // still need to be checked
+        let mut varint = U::zero();
+        for (i, &b) in bytes.iter().enumerate() {
+            if i < U::BYTES as usize {
+                varint = varint | <U as From<u8>>::from(b & 0x7F) << (7 * i);
+            }
+            if b & 0x80 == 0 {
+                self.advance(i + 1);
+                return Some(Ok(varint));
+            }
+        }
+        // Consume the 10 bytes like the byte-by-byte decoding does
+        self.advance(bytes.len());
+        Some(Err(DecodeError::VarIntLimit))
+    }
+
+    /// Decode an `uint32`.
+    ///
+    /// Like all varint decoding, this reads at most 10 bytes, returning [`DecodeError::VarIntLimit`]
+    /// if the 10th byte still has a continuation bit. Bits beyond the first 32 are truncated.
+    pub fn decode_varint32(&mut self) -> Result<u32, DecodeError<R::Error>> {
+        if let Some(res) = self.decode_varint_fast() {
+            return res;
+        }
+        let b = self.get_byte()?;
+        // Single byte case
+        if b & 0x80 == 0 {
+            return Ok(b as u32);
+        }
+
+        let mut varint: u32 = b as u32 & !0x80;
+        let mut bitpos = 7;
+        for i in 1..u64::BYTES {
+            let b = self.get_byte()?;
+            // Take the first 5 bytes into account, but ignore the later 5 bytes since they're
+            // going to be truncated anyways
+            if i < u32::BYTES {
+                let u = b & !0x80;
+                varint |= (u as u32) << bitpos;
+                bitpos += 7;
+            }
+            if b & 0x80 == 0 {
+                return Ok(varint);
+            }
+        }
+        Err(DecodeError::VarIntLimit)
+    }
+
+    #[cfg(feature = "enable-64bit")]
+    /// Decode an `uint64`.
+    ///
+    /// Reads at most 10 bytes, returning [`DecodeError::VarIntLimit`] if the 10th byte still has a
+    /// continuation bit.
+    pub fn decode_varint64(&mut self) -> Result<u64, DecodeError<R::Error>> {
+        if let Some(res) = self.decode_varint_fast() {
+            return res;
+        }
+        let b = self.get_byte()?;
+        // Single byte case
+        if b & 0x80 == 0 {
+            return Ok(b as u64);
+        }
+
+        let mut varint: u64 = b as u64 & !0x80;
+        let mut bitpos = 7;
+        for _ in 1..u64::BYTES {
+            let b = self.get_byte()?;
+            let u = b & !0x80;
+            varint |= (u as u64) << bitpos;
+            bitpos += 7;
+            if b & 0x80 == 0 {
+                return Ok(varint);
+            }
+        }
+        Err(DecodeError::VarIntLimit)
+    }
+
+    #[inline]
+    #[cfg(feature = "enable-64bit")]
+    /// Decode an `int64`.
+    pub fn decode_int64(&mut self) -> Result<i64, DecodeError<R::Error>> {
+        self.decode_varint64().map(|u| u as i64)
+    }
+
+    #[inline]
+    /// Decode an `int32`.
+    pub fn decode_int32(&mut self) -> Result<i32, DecodeError<R::Error>> {
+        self.decode_varint32().map(|u| u as i32)
+    }
+
+    #[inline]
+    /// Decode an `sint32`.
+    pub fn decode_sint32(&mut self) -> Result<i32, DecodeError<R::Error>> {
+        self.decode_varint32()
+            .map(|u| ((u >> 1) as i32) ^ -((u & 1) as i32))
+    }
+
+    #[inline]
+    #[cfg(feature = "enable-64bit")]
+    /// Decode an `sint64`.
+    pub fn decode_sint64(&mut self) -> Result<i64, DecodeError<R::Error>> {
+        self.decode_varint64()
+            .map(|u| ((u >> 1) as i64) ^ -((u & 1) as i64))
+    }
+
+    #[inline]
+    /// Decode a `bool`.
+    pub fn decode_bool(&mut self) -> Result<bool, DecodeError<R::Error>> {
+        Ok(self.decode_varint32()? != 0)
+    }
+
+    fn read_exact(&mut self, buf: &mut [MaybeUninit<u8>]) -> Result<(), DecodeError<R::Error>> {
+        let bytes_read = self
+            .reader
+            .pb_read_exact(buf)
+            .map_err(DecodeError::Reader)?;
+        self.idx += bytes_read;
+
+        if bytes_read < buf.len() {
+            return Err(DecodeError::UnexpectedEof);
+        }
+        Ok(())
+    }
+
+    #[inline]
+    /// Decode a `fixed32`.
+    pub fn decode_fixed32(&mut self) -> Result<u32, DecodeError<R::Error>> {
         let mut data = [MaybeUninit::uninit(); 4];
         self.read_exact(&mut data)?;
         // SAFETY: read_exact is guaranteed to write to the whole buffer
@@ -425,7 +1252,27 @@ impl<R: PbRead> PbDecoder<R> {
     #[inline(always)]
     /// Decode a Protobuf tag.
     pub fn decode_tag(&mut self) -> Result<Tag, DecodeError<R::Error>> {
-        self.decode_varint32().map(Tag)
+        let tag = Tag(self.decode_varint32()?);
+        self.field_num = tag.field_num();
+        self.error_location = None;
+        Ok(tag)
+    }
+
+    /// Check that `tag` has the wire type `wire_type`, then decode a value with `decode`.
+    ///
+    /// This is never inlined, so all fields decoded with the same `decode` function share a single
+    /// copy of the decoding logic. Generated code uses this when optimizing for size.
+    #[inline(never)]
+    pub fn decode_tagged<T>(
+        &mut self,
+        tag: Tag,
+        wire_type: u8,
+        decode: fn(&mut Self) -> Result<T, DecodeError<R::Error>>,
+    ) -> Result<T, DecodeError<R::Error>> {
+        if tag.wire_type() != wire_type {
+            return Err(DecodeError::WrongWireType);
+        }
+        decode(self)
     }
 
     #[inline]
@@ -435,7 +1282,7 @@ impl<R: PbRead> PbDecoder<R> {
         len: usize,
     ) -> Result<&'a [u8], DecodeError<R::Error>> {
         if buf.len() < len {
-            return Err(DecodeError::Capacity);
+            return Err(DecodeError::capacity(len - buf.len(), buf.len()));
         }
         let target = &mut buf[..len];
         self.read_exact(target)?;
@@ -459,23 +1306,44 @@ impl<R: PbRead> PbDecoder<R> {
         string: &mut S,
         presence: Presence,
     ) -> Result<(), DecodeError<R::Error>> {
-        let len = self.decode_varint32()? as usize;
+        self.decode_string_with_policy(string, presence, OverflowPolicy::Error)
+    }
+
+    /// Decode a `string` into a [`PbString`] container, handling strings that exceed the fixed
+    /// capacity of the container according to `policy`.
+    ///
+    /// With the drop policies, an over-long string is truncated to the last UTF-8 character
+    /// boundary that fits into the container, and the rest of the string is skipped. The skipped
+    /// bytes are not checked for UTF-8 validity. The decoder's `ignore_repeated_cap_err` flag has
+    /// no effect on strings.
+    pub fn decode_string_with_policy<S: PbString>(
+        &mut self,
+        string: &mut S,
+        presence: Presence,
+        policy: OverflowPolicy,
+    ) -> Result<(), DecodeError<R::Error>> {
+        let len = self.decode_len()?;
         // With implicit presence, ignore empty strings
         if len == 0 && presence == Presence::Implicit {
             return Ok(());
         }
 
         string.pb_clear();
-        string.pb_reserve(len);
-        let spare_cap = string.pb_spare_cap();
-        let written = self.read_into_buf(spare_cap, len)?;
-
-        // Check UTF8 validity
-        from_utf8(written)?;
-        // SAFETY: read_into_buf guarantees that `len` bytes have been written into the string.
-        // Also, we just checked the UTF-8 validity of the written bytes, so the string is valid.
-        unsafe { string.pb_set_len(len) };
-        Ok(())
+        let read_len = self.read_growing(
+            string,
+            (0, S::CAPACITY),
+            len,
+            policy,
+            S::pb_spare_cap,
+            // Only keep whole characters, so that the string is always valid. A partial character
+            // at the end is kept for the next read.
+            |bytes| match from_utf8(bytes) {
+                Ok(_) => Ok(bytes.len()),
+                Err(e) if e.error_len().is_none() => Ok(e.valid_up_to()),
+                Err(e) => Err(e),
+            },
+        )?;
+        self.skip_bytes(len - read_len)
     }
 
     /// Decode a `bytes` into a [`PbVec<u8>`](crate::PbVec<u8>) container.
@@ -490,22 +1358,227 @@ impl<R: PbRead> PbDecoder<R> {
     /// return [`DecodeError::Capacity`].
     pub fn decode_bytes<S: PbVec<u8>>(
         &mut self,
-        bytes: &mut S,
-        presence: Presence,
-    ) -> Result<(), DecodeError<R::Error>> {
-        let len = self.decode_varint32()? as usize;
-        // With implicit presence, ignore empty strings
-        if len == 0 && presence == Presence::Implicit {
-            return Ok(());
+        bytes: &mut S,
+        presence: Presence,
+    ) -> Result<(), DecodeError<R::Error>> {
+        self.decode_bytes_with_policy(bytes, presence, OverflowPolicy::Error)
+    }
+
+    /// Decode a `bytes` into a [`PbVec<u8>`](crate::PbVec<u8>) container, handling values that
+    /// exceed the fixed capacity of the container according to `policy`.
+    ///
+    /// With the drop policies, an over-long value is truncated to the capacity of the container,
+    /// and the rest of the value is skipped. The decoder's `ignore_repeated_cap_err` flag has no
+    /// effect on `bytes` values.
+    pub fn decode_bytes_with_policy<S: PbVec<u8>>(
+        &mut self,
+        bytes: &mut S,
+        presence: Presence,
+        policy: OverflowPolicy,
+    ) -> Result<(), DecodeError<R::Error>> {
+        let len = self.decode_len()?;
+        // With implicit presence, ignore empty strings
+        if len == 0 && presence == Presence::Implicit {
+            return Ok(());
+        }
+
+        bytes.pb_clear();
+        let read_len = self.read_growing(
+            bytes,
+            (0, S::CAPACITY),
+            len,
+            policy,
+            S::pb_spare_cap,
+            |bytes| Ok(bytes.len()),
+        )?;
+        self.skip_bytes(len - read_len)
+    }
+
+    /// Decode a length-delimited record and append its raw bytes to a
+    /// [`PbVec<u8>`](crate::PbVec<u8>) container, without clearing the container beforehand.
+    ///
+    /// Used for lazily-decoded message fields, since concatenating the encodings of two messages
+    /// is equivalent to merging them.
+    ///
+    /// # Errors
+    ///
+    /// If the container doesn't have enough spare capacity for the record, return
+    /// [`DecodeError::Capacity`].
+    pub fn decode_bytes_append<S: PbVec<u8>>(
+        &mut self,
+        bytes: &mut S,
+    ) -> Result<(), DecodeError<R::Error>> {
+        let len = self.decode_len()?;
+        self.read_append(bytes, len)
+    }
+
+    /// Read `len` bytes and append them to a container.
+    fn read_append<S: PbVec<u8>>(
+        &mut self,
+        bytes: &mut S,
+        len: usize,
+    ) -> Result<(), DecodeError<R::Error>> {
+        self.read_growing(
+            bytes,
+            (bytes.len(), S::CAPACITY),
+            len,
+            OverflowPolicy::Error,
+            S::pb_spare_cap,
+            |bytes| Ok(bytes.len()),
+        )?;
+        Ok(())
+    }
+
+    /// Decode a `bytes` as the range of its contents within the input, without copying it.
+    ///
+    /// The range is relative to the start of the decoder's input, so the contents can be
+    /// retrieved by indexing the original input buffer with the range.
+    ///
+    /// # Errors
+    ///
+    /// If the reader isn't [contiguous](PbRead::CONTIGUOUS), return
+    /// [`DecodeError::NonContiguous`] without reading anything.
+    pub fn decode_bytes_range(
+        &mut self,
+        range: &mut Range<usize>,
+    ) -> Result<(), DecodeError<R::Error>> {
+        if !R::CONTIGUOUS {
+            return Err(DecodeError::NonContiguous);
+        }
+        let len = self.decode_len()?;
+        let start = self.bytes_read();
+        self.skip_bytes(len)?;
+        *range = start..start + len;
+        Ok(())
+    }
+
+    /// Read a `len`-byte value into a container after its first `base` bytes, returning the
+    /// number of bytes read. `capacity` is the fixed capacity of the container.
+    ///
+    /// With contiguous readers, lengths that exceed the rest of the input are rejected before
+    /// reserving anything, so the whole value is reserved at once. Other readers can't tell how
+    /// much input is left, so dynamically-sized containers are grown by at most
+    /// [`RESERVE_CHUNK`] bytes at a time as the value is read. Either way, malformed lengths
+    /// can't cause large allocations. Values that don't fit into the container are truncated
+    /// according to `policy`.
+    ///
+    /// After each read, `keep` returns how many of the bytes read into the spare capacity are
+    /// added to the container. The rest, which can be at most 3 bytes, are moved to the start of
+    /// the spare capacity for the next read. If bytes are left over after the whole value is read,
+    /// the value ended in a partial UTF-8 character, which returns [`DecodeError::Utf8`] unless the
+    /// value was truncated.
+    fn read_growing<C: PbContainer>(
+        &mut self,
+        container: &mut C,
+        (base, capacity): (usize, usize),
+        len: usize,
+        policy: OverflowPolicy,
+        spare_cap: fn(&mut C) -> &mut [MaybeUninit<u8>],
+        keep: fn(&[u8]) -> Result<usize, Utf8Error>,
+    ) -> Result<usize, DecodeError<R::Error>> {
+        self.check_remaining(len)?;
+        let grow = !R::CONTIGUOUS && capacity == UNBOUNDED_CAPACITY;
+        let mut committed = base;
+        let mut read = 0;
+        let mut carry = [0; 3];
+        let mut carried = 0;
+        loop {
+            let want = len - read;
+            container.pb_reserve(carried + if grow { want.min(RESERVE_CHUNK) } else { want });
+            let spare = spare_cap(container);
+            let avail = spare.len().saturating_sub(carried);
+            let (n, truncated) = if avail >= want || (grow && avail >= RESERVE_CHUNK) {
+                (avail.min(want), false)
+            } else {
+                // The container can't grow any further
+                let used = committed + carried;
+                let cap = self.truncated_len(used + avail, used + want, policy)?;
+                (cap - used, true)
+            };
+
+            maybe_uninit_write_slice(spare, &carry[..carried]);
+            self.read_into_buf(spare.get_mut(carried..).unwrap_or(&mut []), n)?;
+            let filled = spare.get(..carried + n).unwrap_or(&[]);
+            // SAFETY: the carried bytes and the `n` bytes after them have just been written
+            let filled = unsafe { maybe_uninit_slice_assume_init_ref(filled) };
+            let kept = keep(filled)?;
+            let rest = filled.get(kept..).unwrap_or(&[]);
+            carried = rest.len().min(carry.len());
+            carry[..carried].copy_from_slice(&rest[..carried]);
+            committed += kept;
+            // SAFETY: the first `kept` bytes of the spare capacity have been written, and `keep`
+            // has checked that they're valid for the container
+            unsafe { container.pb_set_len(committed) };
+
+            read += n;
+            if truncated {
+                // Drop the partial character at the end of a truncated value
+                return Ok(read);
+            } else if read == len {
+                break;
+            }
+        }
+        if carried > 0 {
+            return Err(DecodeError::Utf8);
+        }
+        Ok(read)
+    }
+
+    /// With contiguous readers, return [`DecodeError::UnexpectedEof`] if fewer than `len` bytes
+    /// are left in the input. Other readers can't tell how much input is left, so they always
+    /// pass this check.
+    fn check_remaining(&mut self, len: usize) -> Result<(), DecodeError<R::Error>> {
+        if R::CONTIGUOUS {
+            let chunk = self.reader.pb_read_chunk().map_err(DecodeError::Reader)?;
+            if chunk.len() < len {
+                return Err(DecodeError::UnexpectedEof);
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of bytes of a `len`-byte value to read into a container with `cap` bytes of space,
+    /// according to the overflow policy
+    fn truncated_len(
+        &mut self,
+        cap: usize,
+        len: usize,
+        policy: OverflowPolicy,
+    ) -> Result<usize, DecodeError<R::Error>> {
+        if len <= cap {
+            return Ok(len);
+        }
+        match policy {
+            OverflowPolicy::Error => return Err(DecodeError::capacity(len - cap, cap)),
+            OverflowPolicy::DropExtra => {}
+            OverflowPolicy::DropExtraWithFlag => self.truncated = true,
         }
+        Ok(cap)
+    }
 
-        bytes.pb_clear();
-        bytes.pb_reserve(len);
-        let spare_cap = bytes.pb_spare_cap();
-        self.read_into_buf(spare_cap, len)?;
-        // SAFETY: read_into_buf guarantees that `len` bytes have been written into the buffer
-        unsafe { bytes.pb_set_len(len) };
-        Ok(())
+    /// Decode the length prefix of a length-delimited record.
+    ///
+    /// On targets where `usize` is narrower than 32 bits, lengths that don't fit in a `usize`
+    /// can't possibly be satisfied by the input, so they're rejected instead of being truncated.
+    pub(crate) fn decode_len(&mut self) -> Result<usize, DecodeError<R::Error>> {
+        let len = self.decode_varint32()?;
+        let len = usize::try_from(len).map_err(|_| DecodeError::UnexpectedEof)?;
+        self.check_size_limit(len)?;
+        Ok(len)
+    }
+
+    /// Check that a `len`-byte value starting at the current position doesn't extend past
+    /// [`max_size`](Self::max_size), returning [`DecodeError::SizeLimit`] otherwise.
+    ///
+    /// Length prefixes are checked automatically. Generated code also calls this with the length
+    /// of each message before decoding it.
+    #[inline]
+    pub fn check_size_limit(&self, len: usize) -> Result<(), DecodeError<R::Error>> {
+        if self.idx.saturating_add(len) > self.max_size {
+            Err(DecodeError::SizeLimit)
+        } else {
+            Ok(())
+        }
     }
 
     pub(crate) fn decode_len_record<
@@ -515,7 +1588,9 @@ impl<R: PbRead> PbDecoder<R> {
         &mut self,
         decoder: F,
     ) -> Result<T, DecodeError<R::Error>> {
-        let len = self.decode_varint32()? as usize;
+        let len = self.decode_len()?;
+        // Fail early if the record is cut off, rather than after decoding part of it
+        self.check_remaining(len)?;
         let before = self.bytes_read();
         let val = decoder(len, before, self)?;
         let actual_len = self.bytes_read() - before;
@@ -540,52 +1615,139 @@ impl<R: PbRead> PbDecoder<R> {
         vec: &mut S,
         decoder: F,
     ) -> Result<(), DecodeError<R::Error>> {
-        let ignore_repeated_cap_err = self.ignore_repeated_cap_err;
+        self.decode_packed_with_policy(vec, OverflowPolicy::Error, decoder)
+    }
+
+    /// Decode a repeated packed field and append the elements to a [`PbVec`] container, handling
+    /// excess elements according to `policy`.
+    ///
+    /// Elements that don't fit into the container are still decoded, so the whole packed record
+    /// is consumed regardless of the policy.
+    pub fn decode_packed_with_policy<
+        T: Copy,
+        S: PbVec<T>,
+        F: Fn(&mut Self) -> Result<T, DecodeError<R::Error>>,
+    >(
+        &mut self,
+        vec: &mut S,
+        policy: OverflowPolicy,
+        decoder: F,
+    ) -> Result<(), DecodeError<R::Error>> {
         self.decode_len_record(|len, before, this| {
             while this.bytes_read() - before < len {
                 let val = decoder(this)?;
-                if let (Err(_), false) = (vec.pb_push(val), ignore_repeated_cap_err) {
-                    return Err(DecodeError::Capacity);
+                if vec.pb_push(val).is_err() {
+                    this.handle_overflow(policy, 1, vec.len())?;
                 }
             }
             Ok(())
         })
     }
 
-    //#[cfg(target_endian = "little")]
-    //pub fn decode_packed_fixed<T: DecodeFixedSize, S: PbVec<T>>(
-    //&mut self,
-    //vec: &mut S,
-    //) -> Result<(), DecodeError<R::Error>> {
-    //let len = self.decode_varint32()? as usize;
-    //let elem_size = core::mem::size_of::<T>();
-    //let modulo = len % elem_size;
-    //// Length must be a multiple of elem_size
-    //if modulo > 0 {
-    //return Err(DecodeError::WrongLen {
-    //expected: len,
-    //// Previous multiple of elem_size
-    //actual: len - modulo,
-    //});
-    //}
-    //let elem_num = len / elem_size;
-    //vec.pb_reserve(elem_num);
-    //let spare_cap = vec.pb_spare_cap();
-    //if spare_cap.len() < elem_num {
-    //return Err(DecodeError::Capacity);
-    //}
-    //// SAFETY: Converting slice into uninitialized bytes is always valid. Moreover, we know
-    //// that `spare_cap` has equal or more than `elem_num` values, so its size in bytes can't
-    //// be less than `len`, because `len` is equal to `elem_num * size_of<T>()`.
-    //let spare_bytes = unsafe {
-    //core::slice::from_raw_parts_mut(spare_cap.as_mut_ptr() as *mut MaybeUninit<u8>, len)
-    //};
-    //self.read_exact(spare_bytes)?;
-    //// SAFETY: We just wrote elem_num of elements into the spare space, so we can
-    //// increase the length by that much
-    //unsafe { vec.pb_set_len(vec.len() + elem_num) };
-    //Ok(())
-    //}
+    /// Decode a repeated packed field and pass each element to `visit` instead of storing it.
+    ///
+    /// The `decoder` callback determines how each element is decoded from the wire.
+    pub fn visit_packed<T, F: Fn(&mut Self) -> Result<T, DecodeError<R::Error>>, V: FnMut(T)>(
+        &mut self,
+        decoder: F,
+        mut visit: V,
+    ) -> Result<(), DecodeError<R::Error>> {
+        self.decode_len_record(|len, before, this| {
+            while this.bytes_read() - before < len {
+                visit(decoder(this)?);
+            }
+            Ok(())
+        })
+    }
+
+    #[inline]
+    /// Handle an element that doesn't fit into a fixed-capacity repeated or `map` field.
+    ///
+    /// Returns [`DecodeError::Capacity`] with `needed` and `capacity` if `policy` is
+    /// [`OverflowPolicy::Error`] and the `ignore_repeated_cap_err` flag is not set. Sets the
+    /// [`truncated`](Self::truncated) flag if `policy` is [`OverflowPolicy::DropExtraWithFlag`].
+    pub fn handle_overflow(
+        &mut self,
+        policy: OverflowPolicy,
+        needed: usize,
+        capacity: usize,
+    ) -> Result<(), DecodeError<R::Error>> {
+        match policy {
+            OverflowPolicy::Error if !self.ignore_repeated_cap_err => {
+                Err(DecodeError::capacity(needed, capacity))
+            }
+            OverflowPolicy::DropExtraWithFlag => {
+                self.truncated = true;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Decode a repeated packed field of fixed-size elements and append them to a [`PbVec`]
+    /// container, handling excess elements according to `policy`.
+    ///
+    /// Produces the same result as [`decode_packed_with_policy`](Self::decode_packed_with_policy)
+    /// with the matching `decode_fixed*` method. On little-endian targets, the elements are read
+    /// directly into the container's spare capacity in one go, since their in-memory
+    /// representation matches the wire format. On big-endian targets, each element is
+    /// byte-swapped instead.
+    pub fn decode_packed_fixed<T: FixedSize, S: PbVec<T>>(
+        &mut self,
+        vec: &mut S,
+        policy: OverflowPolicy,
+    ) -> Result<(), DecodeError<R::Error>> {
+        self.decode_len_record(|len, _, this| {
+            let elem_size = core::mem::size_of::<T>();
+            if len % elem_size != 0 {
+                return Err(DecodeError::WrongLen);
+            }
+            let elem_num = len / elem_size;
+            vec.pb_reserve(elem_num);
+            let spare_cap = vec.pb_spare_cap();
+            let fit = elem_num.min(spare_cap.len());
+            this.read_fixed_elems(&mut spare_cap[..fit])?;
+            // SAFETY: The first `fit` elements of the spare capacity were just initialized
+            unsafe { vec.pb_set_len(vec.len() + fit) };
+            if fit < elem_num {
+                this.handle_overflow(policy, elem_num - fit, vec.len())?;
+                this.skip_bytes((elem_num - fit) * elem_size)?;
+            }
+            Ok(())
+        })
+    }
+
+    #[cfg(target_endian = "little")]
+    fn read_fixed_elems<T: FixedSize>(
+        &mut self,
+        elems: &mut [MaybeUninit<T>],
+    ) -> Result<(), DecodeError<R::Error>> {
+        // SAFETY: `FixedSize` types are primitives without padding, so their memory can be
+        // viewed as bytes. Any bit pattern is a valid value, so the elements are initialized once
+        // all of their bytes are written.
+        let bytes = unsafe {
+            core::slice::from_raw_parts_mut(
+                elems.as_mut_ptr() as *mut MaybeUninit<u8>,
+                core::mem::size_of_val(elems),
+            )
+        };
+        self.read_exact(bytes)
+    }
+
+    #[cfg(not(target_endian = "little"))]
+    fn read_fixed_elems<T: FixedSize>(
+        &mut self,
+        elems: &mut [MaybeUninit<T>],
+    ) -> Result<(), DecodeError<R::Error>> {
+        for elem in elems {
+            let mut bytes = T::Bytes::default();
+            for b in bytes.as_mut() {
+                *b = self.get_byte()?;
+            }
+            elem.write(T::from_le(bytes));
+        }
+        Ok(())
+    }
 
     /// Decode a Protobuf map key-value pair from the decoder.
     ///
@@ -593,7 +1755,14 @@ impl<R: PbRead> PbDecoder<R> {
     /// the key in field 1 and the value in field 2. Other field numbers are ignored.
     ///
     /// The `key_update` and `val_update` callbacks are expected to decode the key and value
-    /// respectively. If either key or value field is not found, return `None`.
+    /// respectively. If the key or value field is not found, the default key or value is
+    /// returned. If either field appears multiple times, the last occurrence wins. Afterwards,
+    /// the decoder's [location](Self::location) points to the map field again, so that errors
+    /// from inserting the pair into the map are attributed to the map field.
+    ///
+    /// This function is never inlined, so the key and value don't take up space in the caller's
+    /// stack frame.
+    #[inline(never)]
     pub fn decode_map_elem<
         K: Default,
         V: Default,
@@ -603,30 +1772,54 @@ impl<R: PbRead> PbDecoder<R> {
         &mut self,
         key_update: UK,
         val_update: UV,
-    ) -> Result<Option<(K, V)>, DecodeError<R::Error>> {
-        let mut key = None;
-        let mut val = None;
+    ) -> Result<(K, V), DecodeError<R::Error>> {
+        let mut key = K::default();
+        let mut val = V::default();
+        let field_num = self.field_num;
         self.decode_len_record(|len, before, this| {
             while this.bytes_read() - before < len {
                 let tag = this.decode_tag()?;
                 match tag.field_num() {
-                    1 => key_update(key.get_or_insert_with(K::default), this)?,
-                    2 => val_update(val.get_or_insert_with(V::default), this)?,
-                    _ => this.skip_wire_value(tag.wire_type())?,
+                    1 => key_update(&mut key, this)?,
+                    2 => val_update(&mut val, this)?,
+                    _ => this.skip_unknown_field(tag)?,
                 }
             }
             Ok(())
         })?;
+        self.field_num = field_num;
+        Ok((key, val))
+    }
 
-        if let (Some(key), Some(val)) = (key, val) {
-            Ok(Some((key, val)))
-        } else {
-            Ok(None)
+    /// Decode a Protobuf map key-value pair and insert it into a map.
+    ///
+    /// The key and value are decoded with [`decode_map_elem`](Self::decode_map_elem), and the
+    /// map is only modified once the whole entry has been read, so a malformed or reordered entry
+    /// never affects other entries of the map. If the key isn't in the map and the map is full,
+    /// the overflow is handled according to `policy`.
+    #[inline(never)]
+    pub fn decode_map_entry<
+        K: Default,
+        V: Default,
+        M: PbMap<K, V>,
+        UK: Fn(&mut K, &mut Self) -> Result<(), DecodeError<R::Error>>,
+        UV: Fn(&mut V, &mut Self) -> Result<(), DecodeError<R::Error>>,
+    >(
+        &mut self,
+        map: &mut M,
+        key_update: UK,
+        val_update: UV,
+        policy: OverflowPolicy,
+    ) -> Result<(), DecodeError<R::Error>> {
+        let (key, val) = self.decode_map_elem(key_update, val_update)?;
+        if map.pb_insert(key, val).is_err() {
+            self.handle_overflow(policy, 1, map.pb_len())?;
         }
+        Ok(())
     }
 
     fn skip_varint(&mut self) -> Result<(), DecodeError<R::Error>> {
-        for _ in 0..10 {
+        for _ in 0..u64::BYTES {
             let b = self.get_byte()?;
             if b & 0x80 == 0 {
                 return Ok(());
@@ -658,21 +1851,249 @@ impl<R: PbRead> PbDecoder<R> {
     ///
     /// The type of the Protobuf payload is determined by `wire_type`, which must be a valid
     /// Protobuf wire type. This is mainly used to skip unknown fields.
+    ///
+    /// If `wire_type` is [`WIRE_TYPE_SGROUP`], the entire group is skipped, up to and including the
+    /// matching end-group tag. The group's field number is taken from the last tag decoded by
+    /// [`decode_tag`](Self::decode_tag), so this should be called right after decoding the tag.
     pub fn skip_wire_value(&mut self, wire_type: u8) -> Result<(), DecodeError<R::Error>> {
         match wire_type {
             WIRE_TYPE_VARINT => self.skip_varint()?,
             WIRE_TYPE_I64 => self.skip_bytes(8)?,
             WIRE_TYPE_LEN => {
-                let len = self.decode_varint32()? as usize;
+                let len = self.decode_len()?;
                 self.skip_bytes(len)?;
             }
-            3 | 4 => return Err(DecodeError::Deprecation),
+            WIRE_TYPE_SGROUP => self.skip_group(self.field_num)?,
+            WIRE_TYPE_EGROUP => return Err(DecodeError::GroupMismatch),
             WIRE_TYPE_I32 => self.skip_bytes(4)?,
             _ => return Err(DecodeError::UnknownWireType),
         }
         Ok(())
     }
 
+    /// Append the field whose tag was just decoded to `bytes`, in the same form as on the wire.
+    ///
+    /// The tag is re-encoded, while the value, including any length prefix, is copied as-is.
+    /// Groups aren't copied, in which case `false` is returned without reading anything. If
+    /// decoding fails, `bytes` is left unchanged.
+    #[cfg(feature = "encode")]
+    pub(crate) fn decode_raw_field_append<S: PbVec<u8>>(
+        &mut self,
+        tag: Tag,
+        bytes: &mut S,
+    ) -> Result<bool, DecodeError<R::Error>> {
+        if !matches!(
+            tag.wire_type(),
+            WIRE_TYPE_VARINT | WIRE_TYPE_I64 | WIRE_TYPE_LEN | WIRE_TYPE_I32
+        ) {
+            return Ok(false);
+        }
+        let old_len = bytes.len();
+        let res = self.read_raw_field(tag, bytes);
+        if res.is_err() {
+            // SAFETY: shrinking the container only drops bytes that have been initialized
+            unsafe { bytes.pb_set_len(old_len) };
+        }
+        res.map(|()| true)
+    }
+
+    #[cfg(feature = "encode")]
+    fn read_raw_field<S: PbVec<u8>>(
+        &mut self,
+        tag: Tag,
+        bytes: &mut S,
+    ) -> Result<(), DecodeError<R::Error>> {
+        let mut tag_varint = tag.varint();
+        while tag_varint >= 0x80 {
+            bytes
+                .pb_push(tag_varint as u8 | 0x80)
+                .map_err(|_| DecodeError::capacity(1, bytes.len()))?;
+            tag_varint >>= 7;
+        }
+        bytes
+            .pb_push(tag_varint as u8)
+            .map_err(|_| DecodeError::capacity(1, bytes.len()))?;
+
+        match tag.wire_type() {
+            WIRE_TYPE_VARINT => {
+                self.copy_varint(bytes)?;
+            }
+            WIRE_TYPE_I64 => self.read_append(bytes, 8)?,
+            WIRE_TYPE_LEN => {
+                let len = self.copy_varint(bytes)?;
+                let len = u32::try_from(len)
+                    .ok()
+                    .and_then(|len| usize::try_from(len).ok())
+                    .ok_or(DecodeError::UnexpectedEof)?;
+                self.check_size_limit(len)?;
+                self.read_append(bytes, len)?;
+            }
+            _ => self.read_append(bytes, 4)?,
+        }
+        Ok(())
+    }
+
+    /// Copy a varint byte-by-byte into a container, so that its exact encoding is kept, and
+    /// return its value.
+    #[cfg(feature = "encode")]
+    fn copy_varint<S: PbVec<u8>>(&mut self, bytes: &mut S) -> Result<u64, DecodeError<R::Error>> {
+        let mut varint = 0;
+        for i in 0..10 {
+            let b = self.get_byte()?;
+            bytes
+                .pb_push(b)
+                .map_err(|_| DecodeError::capacity(1, bytes.len()))?;
+            varint |= u64::from(b & 0x7F) << (7 * i);
+            if b & 0x80 == 0 {
+                return Ok(varint);
+            }
+        }
+        Err(DecodeError::VarIntLimit)
+    }
+
+    /// Skip the value of an unknown field whose tag was just decoded.
+    ///
+    /// Behaves like [`skip_wire_value`](Self::skip_wire_value), then reports the skipped field to
+    /// the reader via [`PbRead::pb_on_unknown_field`]. Generated code calls this for all fields
+    /// that aren't recognized or preserved.
+    pub fn skip_unknown_field(&mut self, tag: Tag) -> Result<(), DecodeError<R::Error>> {
+        let start = self.bytes_read();
+        self.skip_wire_value(tag.wire_type())?;
+        self.reader
+            .pb_on_unknown_field(tag, self.bytes_read() - start);
+        Ok(())
+    }
+
+    /// Report the size of a decoded field to the reader via [`PbRead::pb_record_field`].
+    ///
+    /// `start` is the number of bytes read before the field's tag was decoded. Generated code
+    /// calls this after each field when field statistics are enabled.
+    #[inline]
+    pub fn record_field(&mut self, field_num: u32, start: usize) {
+        let depth = self.depth.min(u8::MAX as usize) as u8;
+        let bytes = self.bytes_read() - start;
+        self.reader.pb_record_field(depth, field_num, bytes);
+    }
+
+    /// Skip the fields of a group until the end-group tag with the same field number.
+    ///
+    /// Groups count towards the nesting depth just like messages.
+    fn skip_group(&mut self, field_num: u32) -> Result<(), DecodeError<R::Error>> {
+        self.nested(|this| loop {
+            let tag = this.decode_tag()?;
+            if tag.wire_type() == WIRE_TYPE_EGROUP {
+                if tag.field_num() != field_num {
+                    return Err(DecodeError::GroupMismatch);
+                }
+                return Ok(());
+            }
+            this.skip_wire_value(tag.wire_type())?;
+        })
+    }
+
+    /// Call `f` one level of nesting deeper, returning [`DecodeError::DepthLimit`] if that exceeds
+    /// `max_depth`.
+    ///
+    /// The depth and field number are restored afterwards, even if `f` fails. On failure, the
+    /// location of the error is captured first, so that [`location`](Self::location) still points
+    /// at the innermost field.
+    fn nested<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, DecodeError<R::Error>>,
+    ) -> Result<T, DecodeError<R::Error>> {
+        if self.depth >= self.max_depth {
+            return Err(DecodeError::DepthLimit);
+        }
+        let field_num = self.field_num;
+        self.error_location = None;
+        self.depth += 1;
+        let res = f(self);
+        if res.is_err() && self.error_location.is_none() {
+            self.error_location = Some(self.location());
+        }
+        self.depth -= 1;
+        self.field_num = field_num;
+        res
+    }
+
+    /// Decode a length-delimited record nested inside the current message.
+    ///
+    /// The length prefix is decoded first, then `f` is called with the decoder and the length of
+    /// the record. `f` is expected to consume exactly that many bytes, otherwise
+    /// [`DecodeError::WrongLen`] is returned. With contiguous readers, a length that exceeds the
+    /// rest of the input returns [`DecodeError::UnexpectedEof`] before `f` is called. The record
+    /// counts as one level of nesting, so [`DecodeError::DepthLimit`] is returned if the nesting
+    /// depth exceeds `max_depth`.
+    pub fn decode_len_delimited<
+        T,
+        F: FnOnce(&mut Self, usize) -> Result<T, DecodeError<R::Error>>,
+    >(
+        &mut self,
+        f: F,
+    ) -> Result<T, DecodeError<R::Error>> {
+        self.decode_len_delimited_max(usize::MAX, f)
+    }
+
+    /// Decode a length-delimited record whose length is at most `max_len` bytes.
+    ///
+    /// Same as [`decode_len_delimited`](Self::decode_len_delimited), except that a length prefix
+    /// exceeding `max_len` returns [`DecodeError::SizeLimit`] before any of the record is read.
+    pub fn decode_len_delimited_max<
+        T,
+        F: FnOnce(&mut Self, usize) -> Result<T, DecodeError<R::Error>>,
+    >(
+        &mut self,
+        max_len: usize,
+        f: F,
+    ) -> Result<T, DecodeError<R::Error>> {
+        self.nested(|this| {
+            this.decode_len_record(|len, _, this| {
+                if len > max_len {
+                    return Err(DecodeError::SizeLimit);
+                }
+                f(this, len)
+            })
+        })
+    }
+
+    /// Decode a length-delimited message nested inside the current one and merge it into `msg`.
+    ///
+    /// Returns [`DecodeError::DepthLimit`] if the nesting depth exceeds `max_depth`.
+    pub(crate) fn decode_nested<M: MessageDecode + ?Sized>(
+        &mut self,
+        msg: &mut M,
+    ) -> Result<(), DecodeError<R::Error>> {
+        self.decode_len_delimited(|this, len| msg.decode(this, len))
+    }
+
+    /// Decode a wrapper message, such as `google.protobuf.Int32Value`, calling `decode_value` for
+    /// each occurrence of its `value` field.
+    ///
+    /// The `value` field must have the wire type `wire_type`, otherwise
+    /// [`DecodeError::WrongWireType`] is returned. Other fields are skipped. If the wrapper has no
+    /// `value` field, `decode_value` isn't called at all, so the caller is responsible for
+    /// resetting the value to zero beforehand. Generated code uses this for fields with
+    /// `unwrap_wrappers` set.
+    pub fn decode_wrapper<F: FnMut(&mut Self) -> Result<(), DecodeError<R::Error>>>(
+        &mut self,
+        wire_type: u8,
+        mut decode_value: F,
+    ) -> Result<(), DecodeError<R::Error>> {
+        self.decode_len_delimited(|this, len| {
+            let before = this.bytes_read();
+            while this.bytes_read() - before < len {
+                let tag = this.decode_tag()?;
+                match tag.field_num() {
+                    0 => return Err(DecodeError::ZeroField),
+                    1 if tag.wire_type() == wire_type => decode_value(this)?,
+                    1 => return Err(DecodeError::WrongWireType),
+                    _ => this.skip_unknown_field(tag)?,
+                }
+            }
+            Ok(())
+        })
+    }
+
     /// Decode a new message from the wire.
     pub fn decode_message<M: MessageDecode + Default>(
         &mut self,
@@ -682,12 +2103,54 @@ impl<R: PbRead> PbDecoder<R> {
         msg.decode(self, len)?;
         Ok(msg)
     }
+
+    /// Decode a length-delimited message and append it to a [`PbVec`] container.
+    ///
+    /// If the container is already full, the message is handled according to `policy`. This
+    /// function is never inlined, so the message being decoded doesn't take up space in the
+    /// caller's stack frame.
+    #[inline(never)]
+    pub fn decode_repeated_message<M: MessageDecode + Default, S: PbVec<M>>(
+        &mut self,
+        vec: &mut S,
+        policy: OverflowPolicy,
+    ) -> Result<(), DecodeError<R::Error>> {
+        let mut msg = M::default();
+        msg.decode_len_delimited(self)?;
+        if vec.pb_push(msg).is_err() {
+            self.handle_overflow(policy, 1, vec.len())?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> PbDecoder<&'a [u8]> {
+    /// Get the part of the input slice that hasn't been consumed by the decoder.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use micropb::PbDecoder;
+    ///
+    /// // Length-delimited record followed by a checksum
+    /// let data = [0x02, 0x08, 0x01, 0xAB, 0xCD];
+    /// let mut decoder = PbDecoder::new(data.as_slice());
+    /// decoder.decode_len_delimited(|d, len| d.skip_bytes(len)).unwrap();
+    /// assert_eq!(decoder.bytes_read(), 3);
+    /// assert_eq!(decoder.remaining(), &[0xAB, 0xCD]);
+    /// ```
+    #[inline]
+    pub fn remaining(&self) -> &'a [u8] {
+        self.reader
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use arrayvec::{ArrayString, ArrayVec};
 
+    use crate::IterReader;
+
     use super::*;
 
     struct Multichunk<'a>(&'a [u8]);
@@ -765,6 +2228,23 @@ mod tests {
             [0x81, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00],
             decode_varint32()
         );
+        // Negative int32 is sign-extended to 10 bytes
+        assert_decode!(
+            Ok(u32::MAX),
+            [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01],
+            decode_varint32()
+        );
+        assert_decode!(
+            Err(DecodeError::VarIntLimit),
+            [0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01],
+            decode_varint32()
+        );
+
+        // Long runs of continuation bytes shouldn't be consumed past the 10-byte limit
+        let data = [0xFF; 32];
+        let mut decoder = PbDecoder::new(data.as_slice());
+        assert_eq!(decoder.decode_tag(), Err(DecodeError::VarIntLimit));
+        assert_eq!(decoder.bytes_read(), 10);
     }
 
     #[test]
@@ -783,8 +2263,20 @@ mod tests {
             decode_varint64()
         );
 
+        // Non-canonical encoding of a small number
+        assert_decode!(
+            Ok(1),
+            [0x81, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00],
+            decode_varint64()
+        );
+
         assert_decode!(Err(DecodeError::UnexpectedEof), [0x80], decode_varint64());
         assert_decode!(Err(DecodeError::UnexpectedEof), [], decode_varint64());
+        assert_decode!(
+            Err(DecodeError::UnexpectedEof),
+            [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
+            decode_varint64()
+        );
         assert_decode!(
             Err(DecodeError::VarIntLimit),
             [0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01],
@@ -792,6 +2284,55 @@ mod tests {
         );
     }
 
+    #[test]
+    fn varint_fast_path() {
+        let cases: [&[u8]; 7] = [
+            &[5],
+            &[0x96, 0x01],
+            &[0xFF, 0xFF, 0xFF, 0xFF, 0x0F],
+            &[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01],
+            &[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01],
+            &[
+                0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01,
+            ],
+            &[0x80, 0x80, 0x80],
+        ];
+        for case in cases {
+            // Padding the input lets the varint be decoded straight from the slice, which should
+            // match the byte-by-byte decoding of the multi-chunk reader
+            let mut data = case.to_vec();
+            data.resize(16, 0x7F);
+            let mut fast = PbDecoder::new(data.as_slice());
+            let mut slow = PbDecoder::new(Multichunk(data.as_slice()));
+            assert_eq!(fast.decode_varint32(), slow.decode_varint32());
+            assert_eq!(fast.bytes_read(), slow.bytes_read());
+
+            let mut fast = PbDecoder::new(data.as_slice());
+            let mut slow = PbDecoder::new(Multichunk(data.as_slice()));
+            assert_eq!(fast.decode_varint64(), slow.decode_varint64());
+            assert_eq!(fast.bytes_read(), slow.bytes_read());
+        }
+    }
+
+    #[test]
+    fn decode_tagged() {
+        let mut decoder = PbDecoder::new([0x96, 0x01].as_slice());
+        let tag = Tag::from_parts(1, WIRE_TYPE_VARINT);
+        assert_eq!(
+            decoder.decode_tagged(tag, WIRE_TYPE_VARINT, PbDecoder::decode_varint32),
+            Ok(150)
+        );
+
+        // Nothing is consumed if the wire type is wrong
+        let mut decoder = PbDecoder::new([0x96, 0x01].as_slice());
+        let tag = Tag::from_parts(1, WIRE_TYPE_I32);
+        assert_eq!(
+            decoder.decode_tagged(tag, WIRE_TYPE_VARINT, PbDecoder::decode_varint32),
+            Err(DecodeError::WrongWireType)
+        );
+        assert_eq!(decoder.bytes_read(), 0);
+    }
+
     #[test]
     fn skip_varint() {
         assert_decode!(Ok(()), [5], skip_varint());
@@ -809,6 +2350,11 @@ mod tests {
             [0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01],
             skip_varint()
         );
+        assert_decode!(
+            Err(DecodeError::VarIntLimit),
+            [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01],
+            skip_varint()
+        );
     }
 
     #[test]
@@ -1032,11 +2578,119 @@ mod tests {
             skip_wire_value(WIRE_TYPE_LEN)
         );
 
-        assert_decode!(Err(DecodeError::Deprecation), [], skip_wire_value(3));
-        assert_decode!(Err(DecodeError::Deprecation), [], skip_wire_value(4));
+        assert_decode!(
+            Err(DecodeError::GroupMismatch),
+            [],
+            skip_wire_value(WIRE_TYPE_EGROUP)
+        );
+        assert_decode!(Err(DecodeError::UnknownWireType), [], skip_wire_value(6));
+        assert_decode!(Err(DecodeError::UnknownWireType), [], skip_wire_value(7));
         assert_decode!(Err(DecodeError::UnknownWireType), [], skip_wire_value(10));
     }
 
+    #[test]
+    fn skip_group() {
+        // Group with field 1 containing a varint and a nested group with field 2
+        let data = [
+            0x0B, 0x08, 0x96, 0x01, 0x13, 0x1A, 0x01, 0xFF, 0x14, 0x0C, 0x10, 0x01,
+        ];
+        let mut decoder = PbDecoder::new(data.as_slice());
+        let tag = decoder.decode_tag().unwrap();
+        assert_eq!(tag.wire_type(), WIRE_TYPE_SGROUP);
+        decoder.skip_wire_value(tag.wire_type()).unwrap();
+        assert_eq!(decoder.bytes_read(), 10);
+        assert_eq!(decoder.location().field_num, 1);
+        // Decoding continues after the group
+        assert_eq!(
+            decoder.decode_tag(),
+            Ok(Tag::from_parts(2, WIRE_TYPE_VARINT))
+        );
+
+        // Empty group
+        let mut decoder = PbDecoder::new([0x0B, 0x0C].as_slice());
+        let tag = decoder.decode_tag().unwrap();
+        assert_eq!(decoder.skip_wire_value(tag.wire_type()), Ok(()));
+
+        // Truncated group
+        let mut decoder = PbDecoder::new([0x0B, 0x08, 0x01].as_slice());
+        let tag = decoder.decode_tag().unwrap();
+        assert_eq!(
+            decoder.skip_wire_value(tag.wire_type()),
+            Err(DecodeError::UnexpectedEof)
+        );
+
+        // End-group tag with the wrong field number
+        let mut decoder = PbDecoder::new([0x0B, 0x08, 0x01, 0x14].as_slice());
+        let tag = decoder.decode_tag().unwrap();
+        assert_eq!(
+            decoder.skip_wire_value(tag.wire_type()),
+            Err(DecodeError::GroupMismatch)
+        );
+
+        // Deeply nested groups hit the depth limit
+        let data = [0x0B; 64];
+        let mut decoder = PbDecoder::new(data.as_slice());
+        let tag = decoder.decode_tag().unwrap();
+        assert_eq!(
+            decoder.skip_wire_value(tag.wire_type()),
+            Err(DecodeError::DepthLimit)
+        );
+    }
+
+    #[test]
+    fn unknown_field_hook() {
+        let data = [
+            0x08, 0x96, 0x01, // field 1, varint
+            0x12, 0x02, 0xAA, 0xBB, // field 2, len
+            0x1B, 0x08, 0x01, 0x1C, // field 3, group
+            0x25, 0x01, 0x02, 0x03, 0x04, // field 4, fixed32
+        ];
+        let mut unknown = Vec::new();
+        let reader = UnknownFieldHook::new(data.as_slice(), |num, wire_type, len| {
+            unknown.push((num, wire_type, len))
+        });
+        let mut decoder = PbDecoder::new(reader);
+        while !decoder.is_eof().unwrap() {
+            let tag = decoder.decode_tag().unwrap();
+            decoder.skip_unknown_field(tag).unwrap();
+        }
+        assert_eq!(decoder.bytes_read(), data.len());
+        assert_eq!(
+            unknown,
+            [
+                (1, WIRE_TYPE_VARINT, 2),
+                (2, WIRE_TYPE_LEN, 3),
+                (3, WIRE_TYPE_SGROUP, 3),
+                (4, WIRE_TYPE_I32, 4)
+            ]
+        );
+
+        // Fields that fail to be skipped aren't reported
+        let mut count = 0;
+        let reader = UnknownFieldHook::new([0x12, 0x05, 0xAA].as_slice(), |_, _, _| count += 1);
+        let mut decoder = PbDecoder::new(reader);
+        let tag = decoder.decode_tag().unwrap();
+        assert_eq!(
+            decoder.skip_unknown_field(tag),
+            Err(DecodeError::UnexpectedEof)
+        );
+        assert_eq!(count, 0);
+
+        // Unknown fields in map entries are also reported
+        let mut unknown = Vec::new();
+        let data = [0x0A, 0x08, 0x08, 0x01, 0x10, 0x02, 0x18, 0x03, 0x20, 0x04];
+        let reader = UnknownFieldHook::new(&data[1..], |num, _, _| unknown.push(num));
+        let mut decoder = PbDecoder::new(reader);
+        let entry = decoder
+            .decode_map_elem(
+                |k: &mut u32, d| d.decode_varint32().map(|v| *k = v),
+                |v: &mut u32, d| d.decode_varint32().map(|x| *v = x),
+            )
+            .unwrap();
+        assert_eq!(entry, (1, 2));
+        assert_eq!(unknown, [3, 4]);
+    }
+
     macro_rules! assert_decode_vec {
         (@testcase $pattern:pat $(if $guard:expr)?, $reader:expr, $func:ident ($container:ident $(, $($args:tt)+)?)) => {
             let mut decoder = PbDecoder::new($reader);
@@ -1104,9 +2758,49 @@ mod tests {
         );
         if fixed_cap {
             assert_decode_vec!(
-                Err(DecodeError::Capacity),
-                [5, b'a', b'b', b'c', b'd', b'e'],
-                decode_string(string, Presence::Explicit)
+                Err(DecodeError::Capacity {
+                    needed: 1,
+                    capacity: 4
+                }),
+                [5, b'a', b'b', b'c', b'd', b'e'],
+                decode_string(string, Presence::Explicit)
+            );
+            assert_decode_vec!(
+                Ok("abcd"),
+                [5, b'a', b'b', b'c', b'd', b'e'],
+                decode_string_with_policy(string, Presence::Explicit, OverflowPolicy::DropExtra)
+            );
+            // Characters straddling the capacity limit are removed
+            assert_decode_vec!(
+                Ok("abc"),
+                [5, b'a', b'b', b'c', 208, 151],
+                decode_string_with_policy(string, Presence::Explicit, OverflowPolicy::DropExtra)
+            );
+            assert_decode_vec!(
+                Ok("Зд"),
+                [6, 208, 151, 208, 180, 208, 181],
+                decode_string_with_policy(string, Presence::Explicit, OverflowPolicy::DropExtra)
+            );
+            assert_decode_vec!(
+                Ok("abc"),
+                [6, b'a', b'b', b'c', 0xE2, 0x82, 0xAC],
+                decode_string_with_policy(string, Presence::Explicit, OverflowPolicy::DropExtra)
+            );
+            assert_decode_vec!(
+                Ok("a€"),
+                [5, b'a', 0xE2, 0x82, 0xAC, b'b'],
+                decode_string_with_policy(string, Presence::Explicit, OverflowPolicy::DropExtra)
+            );
+            // Invalid UTF-8 before the truncation point is still an error
+            assert_decode_vec!(
+                Err(DecodeError::Utf8),
+                [5, 0x80, b'a', b'b', b'c', b'd'],
+                decode_string_with_policy(string, Presence::Explicit, OverflowPolicy::DropExtra)
+            );
+            assert_decode_vec!(
+                Err(DecodeError::UnexpectedEof),
+                [6, b'a', b'b', b'c', b'd', b'e'],
+                decode_string_with_policy(string, Presence::Explicit, OverflowPolicy::DropExtra)
             );
         }
         assert_decode_vec!(
@@ -1142,10 +2836,18 @@ mod tests {
         );
         if fixed_cap {
             assert_decode_vec!(
-                Err(DecodeError::Capacity),
+                Err(DecodeError::Capacity {
+                    needed: 1,
+                    capacity: 3
+                }),
                 [4, 0x10, 0x20, 0x30, 0x40],
                 decode_bytes(bytes, Presence::Explicit)
             );
+            assert_decode_vec!(
+                Ok(&[0x10, 0x20, 0x30]),
+                [5, 0x10, 0x20, 0x30, 0x40, 0x50],
+                decode_bytes_with_policy(bytes, Presence::Explicit, OverflowPolicy::DropExtra)
+            );
         }
         assert_decode_vec!(
             Err(DecodeError::UnexpectedEof),
@@ -1158,6 +2860,160 @@ mod tests {
     container_test!(bytes, bytes_heapless, heapless::Vec::<_, 3>, true);
     container_test!(bytes, bytes_alloc, Vec<_>, false);
 
+    #[test]
+    fn huge_len() {
+        // Length prefix of u32::MAX followed by a single byte
+        let data = [0xFF, 0xFF, 0xFF, 0xFF, 0x0F, 0x01];
+
+        // Nothing is allocated for lengths that exceed the input
+        let mut bytes = Vec::new();
+        let mut decoder = PbDecoder::new(data.as_slice());
+        assert_eq!(
+            decoder.decode_bytes(&mut bytes, Presence::Explicit),
+            Err(DecodeError::UnexpectedEof)
+        );
+        assert_eq!(bytes.capacity(), 0);
+
+        let mut decoder = PbDecoder::new(data.as_slice());
+        assert_eq!(
+            decoder.decode_bytes_append(&mut bytes),
+            Err(DecodeError::UnexpectedEof)
+        );
+        assert_eq!(bytes.capacity(), 0);
+
+        let mut string = String::new();
+        let mut decoder = PbDecoder::new(data.as_slice());
+        assert_eq!(
+            decoder.decode_string(&mut string, Presence::Explicit),
+            Err(DecodeError::UnexpectedEof)
+        );
+        assert_eq!(string.capacity(), 0);
+
+        // Fixed-capacity containers report the lack of space first
+        let mut string = ArrayString::<4>::new();
+        let mut decoder = PbDecoder::new((&data[..3], &data[3..]));
+        assert_eq!(
+            decoder.decode_string(&mut string, Presence::Explicit),
+            Err(DecodeError::Capacity {
+                needed: 4294967291,
+                capacity: 4
+            })
+        );
+
+        // Non-contiguous readers only reserve space as the value is read
+        let mut bytes = Vec::new();
+        let mut decoder = PbDecoder::new(IterReader::new(data.iter().copied()));
+        assert_eq!(
+            decoder.decode_bytes(&mut bytes, Presence::Explicit),
+            Err(DecodeError::UnexpectedEof)
+        );
+        assert!(bytes.capacity() <= RESERVE_CHUNK);
+
+        let mut string = String::new();
+        let mut decoder = PbDecoder::new(IterReader::new(data.iter().copied()));
+        assert_eq!(
+            decoder.decode_string(&mut string, Presence::Explicit),
+            Err(DecodeError::UnexpectedEof)
+        );
+        assert!(string.capacity() <= RESERVE_CHUNK);
+    }
+
+    #[test]
+    fn string_across_chunks() {
+        // Multi-byte characters straddle the chunks in which the string is reserved
+        let expected = "a".to_owned() + &"é".repeat(RESERVE_CHUNK);
+        let mut data = vec![0x81, 0x10];
+        data.extend_from_slice(expected.as_bytes());
+        assert_eq!(data.len(), 2 + 0x801);
+
+        let mut string = String::new();
+        let mut decoder = PbDecoder::new(IterReader::new(data.iter().copied()));
+        decoder
+            .decode_string(&mut string, Presence::Explicit)
+            .unwrap();
+        assert_eq!(string, expected);
+        assert_eq!(decoder.bytes_read(), data.len());
+
+        let mut bytes = Vec::new();
+        let mut decoder = PbDecoder::new(IterReader::new(data.iter().copied()));
+        decoder
+            .decode_bytes(&mut bytes, Presence::Explicit)
+            .unwrap();
+        assert_eq!(bytes, expected.as_bytes());
+
+        // Partial character at the end of the string
+        data[0] -= 1;
+        data.pop();
+        let mut decoder = PbDecoder::new(IterReader::new(data.iter().copied()));
+        assert_eq!(
+            decoder.decode_string(&mut string, Presence::Explicit),
+            Err(DecodeError::Utf8)
+        );
+    }
+
+    fn bytes_append<S: PbVec<u8> + Default>(fixed_cap: bool) {
+        let (mut bytes1, mut bytes2) = (S::default(), S::default());
+        assert_decode_vec!(Ok(&[]), [0], decode_bytes_append(bytes1 | bytes2));
+        assert_decode_vec!(Ok(&[0x10]), [1, 0x10], decode_bytes_append(bytes1 | bytes2));
+        assert_decode_vec!(
+            Ok(&[0x10, 0x20, 0x30]),
+            [2, 0x20, 0x30],
+            decode_bytes_append(bytes1 | bytes2)
+        );
+        if fixed_cap {
+            assert_decode_vec!(
+                Err(DecodeError::Capacity {
+                    needed: 1,
+                    capacity: 3
+                }),
+                [1, 0x40],
+                decode_bytes_append(bytes1 | bytes2)
+            );
+        }
+        let (mut bytes1, mut bytes2) = (S::default(), S::default());
+        assert_decode_vec!(
+            Err(DecodeError::UnexpectedEof),
+            [2, 0x10],
+            decode_bytes_append(bytes1 | bytes2)
+        );
+    }
+
+    container_test!(bytes_append, bytes_append_arrayvec, ArrayVec::<_, 3>, true);
+    container_test!(
+        bytes_append,
+        bytes_append_heapless,
+        heapless::Vec::<_, 3>,
+        true
+    );
+    container_test!(bytes_append, bytes_append_alloc, Vec<_>, false);
+
+    #[test]
+    fn bytes_range() {
+        let data = [0x08, 3, 0x10, 0x20, 0x30, 0];
+        let mut decoder = PbDecoder::new(data.as_slice());
+        decoder.decode_varint32().unwrap();
+        let mut range = 0..0;
+        decoder.decode_bytes_range(&mut range).unwrap();
+        assert_eq!(range, 2..5);
+        assert_eq!(&data[range.clone()], &[0x10, 0x20, 0x30]);
+        decoder.decode_bytes_range(&mut range).unwrap();
+        assert_eq!(range, 6..6);
+
+        let mut decoder = PbDecoder::new([3, 0x10].as_slice());
+        assert_eq!(
+            decoder.decode_bytes_range(&mut range),
+            Err(DecodeError::UnexpectedEof)
+        );
+
+        // Non-contiguous readers are rejected before reading anything
+        let mut decoder = PbDecoder::new(Multichunk(&data));
+        assert_eq!(
+            decoder.decode_bytes_range(&mut range),
+            Err(DecodeError::NonContiguous)
+        );
+        assert_eq!(decoder.bytes_read(), 0);
+    }
+
     fn packed<S: PbVec<u32> + Default>(fixed_cap: bool) {
         let mut vec1 = S::default();
         let mut vec2 = S::default();
@@ -1187,7 +3043,10 @@ mod tests {
         );
         if fixed_cap {
             assert_decode_vec!(
-                Err(DecodeError::Capacity),
+                Err(DecodeError::Capacity {
+                    needed: 1,
+                    capacity: 5
+                }),
                 [1, 0x01],
                 decode_packed(vec1 | vec2, |rd| rd.decode_varint32())
             );
@@ -1198,44 +3057,171 @@ mod tests {
     container_test!(packed, packed_heapless, heapless::Vec::<_, 5>, true);
     container_test!(packed, packed_alloc, Vec<_>, false);
 
-    //#[cfg(target_endian = "little")]
-    //fn packed_fixed<S: PbVec<u32>>(fixed_cap: bool) {
-    //let mut vec1 = S::default();
-    //let mut vec2 = S::default();
-    //assert_decode_vec!(Ok(&[]), [0], decode_packed_fixed(vec1 | vec2));
-    //assert_decode_vec!(
-    //Ok(&[0x04030201]),
-    //[4, 0x01, 0x02, 0x03, 0x04],
-    //decode_packed_fixed(vec1 | vec2)
-    //);
-    //assert_decode_vec!(
-    //Ok(&[0x04030201, 0x0D0C0B0A, 0x44332211]),
-    //[8, 0x0A, 0x0B, 0x0C, 0x0D, 0x11, 0x22, 0x33, 0x44],
-    //decode_packed_fixed(vec1 | vec2)
-    //);
-    //if fixed_cap {
-    //assert_decode_vec!(
-    //Err(DecodeError::Capacity),
-    //[4, 0x01, 0x02, 0x03, 0x04],
-    //decode_packed_fixed(vec1 | vec2)
-    //);
-    //}
-    //assert_decode_vec!(
-    //Err(DecodeError::WrongLen {
-    //expected: 1,
-    //actual: 0
-    //}),
-    //[1, 0x01],
-    //decode_packed_fixed(vec1 | vec2)
-    //);
-    //}
-
-    //#[cfg(target_endian = "little")]
-    //container_test!(packed_fixed, pf_arrayvec, ArrayVec::<_, 3>, true);
-    //#[cfg(target_endian = "little")]
-    //container_test!(packed_fixed, pf_heapless, heapless::Vec::<_, 3>, true);
-    //#[cfg(target_endian = "little")]
-    //container_test!(packed_fixed, pf_alloc, Vec<_>, false);
+    #[test]
+    fn visit_packed() {
+        let mut sum = 0;
+        let mut decoder = PbDecoder::new([3, 0x96, 0x01, 0x05, 0x01].as_slice());
+        decoder
+            .visit_packed(|rd| rd.decode_varint32(), |v| sum += v)
+            .unwrap();
+        assert_eq!(sum, 155);
+        assert_eq!(decoder.bytes_read(), 4);
+
+        let mut decoder = PbDecoder::new([1, 0x96, 0x01].as_slice());
+        assert_eq!(
+            decoder.visit_packed(|rd| rd.decode_varint32(), |_| ()),
+            Err(DecodeError::WrongLen)
+        );
+    }
+
+    #[test]
+    fn packed_overflow_policy() {
+        let data = [3, 0x01, 0x02, 0x03, 0x04];
+        let decode = |policy| {
+            let mut vec = ArrayVec::<u32, 2>::new();
+            let mut decoder = PbDecoder::new(data.as_slice());
+            let res =
+                decoder.decode_packed_with_policy(&mut vec, policy, |rd| rd.decode_varint32());
+            (res, vec, decoder.bytes_read(), decoder.truncated)
+        };
+
+        let (res, _, _, truncated) = decode(OverflowPolicy::Error);
+        assert_eq!(
+            res,
+            Err(DecodeError::Capacity {
+                needed: 1,
+                capacity: 2
+            })
+        );
+        assert!(!truncated);
+
+        // Excess elements are consumed from the wire without being stored
+        let (res, vec, read, truncated) = decode(OverflowPolicy::DropExtra);
+        assert_eq!(res, Ok(()));
+        assert_eq!(vec.as_slice(), &[1, 2]);
+        assert_eq!(read, 4);
+        assert!(!truncated);
+
+        let (res, vec, read, truncated) = decode(OverflowPolicy::DropExtraWithFlag);
+        assert_eq!(res, Ok(()));
+        assert_eq!(vec.as_slice(), &[1, 2]);
+        assert_eq!(read, 4);
+        assert!(truncated);
+    }
+
+    fn packed_fixed<S: PbVec<u32> + Default>(fixed_cap: bool) {
+        let mut vec1 = S::default();
+        let mut vec2 = S::default();
+        assert_decode_vec!(
+            Ok(&[]),
+            [0],
+            decode_packed_fixed(vec1 | vec2, OverflowPolicy::Error)
+        );
+        assert_decode_vec!(
+            Ok(&[0x04030201]),
+            [4, 0x01, 0x02, 0x03, 0x04],
+            decode_packed_fixed(vec1 | vec2, OverflowPolicy::Error)
+        );
+        assert_decode_vec!(
+            Ok(&[0x04030201, 0x0D0C0B0A, 0x44332211]),
+            [8, 0x0A, 0x0B, 0x0C, 0x0D, 0x11, 0x22, 0x33, 0x44],
+            decode_packed_fixed(vec1 | vec2, OverflowPolicy::Error)
+        );
+        if fixed_cap {
+            assert_decode_vec!(
+                Err(DecodeError::Capacity {
+                    needed: 1,
+                    capacity: 3
+                }),
+                [4, 0x01, 0x02, 0x03, 0x04],
+                decode_packed_fixed(vec1 | vec2, OverflowPolicy::Error)
+            );
+        }
+        vec1.pb_clear();
+        vec2.pb_clear();
+        // Length must be a multiple of the element size
+        assert_decode_vec!(
+            Err(DecodeError::WrongLen),
+            [1, 0x01],
+            decode_packed_fixed(vec1 | vec2, OverflowPolicy::Error)
+        );
+        assert_decode_vec!(
+            Err(DecodeError::UnexpectedEof),
+            [8, 0x01, 0x02, 0x03, 0x04],
+            decode_packed_fixed(vec1 | vec2, OverflowPolicy::Error)
+        );
+    }
+
+    container_test!(packed_fixed, packed_fixed_arrayvec, ArrayVec::<_, 3>, true);
+    container_test!(
+        packed_fixed,
+        packed_fixed_heapless,
+        heapless::Vec::<_, 3>,
+        true
+    );
+    container_test!(packed_fixed, packed_fixed_alloc, Vec<_>, false);
+
+    #[test]
+    fn packed_fixed_types() {
+        let data = [
+            16, 0, 0, 0, 0, 0, 0, 0xF8, 0x3F, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+        ];
+        let mut doubles = Vec::<f64>::new();
+        let mut decoder = PbDecoder::new(data.as_slice());
+        decoder
+            .decode_packed_fixed(&mut doubles, OverflowPolicy::Error)
+            .unwrap();
+        assert_eq!(doubles[0], 1.5);
+        assert!(doubles[1].is_nan());
+
+        let mut ints = Vec::<i64>::new();
+        let mut decoder = PbDecoder::new(data.as_slice());
+        decoder
+            .decode_packed_fixed(&mut ints, OverflowPolicy::Error)
+            .unwrap();
+        assert_eq!(ints, [0x3FF8000000000000, -1]);
+
+        let mut floats = Vec::<f32>::new();
+        let mut decoder = PbDecoder::new([4, 0, 0, 0xC0, 0x3F].as_slice());
+        decoder
+            .decode_packed_fixed(&mut floats, OverflowPolicy::Error)
+            .unwrap();
+        assert_eq!(floats, [1.5]);
+    }
+
+    #[test]
+    fn packed_fixed_overflow_policy() {
+        let data = [12, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 4];
+        let decode = |policy| {
+            let mut vec = ArrayVec::<u32, 2>::new();
+            let mut decoder = PbDecoder::new(data.as_slice());
+            let res = decoder.decode_packed_fixed(&mut vec, policy);
+            (res, vec, decoder.bytes_read(), decoder.truncated)
+        };
+
+        let (res, _, _, truncated) = decode(OverflowPolicy::Error);
+        assert_eq!(
+            res,
+            Err(DecodeError::Capacity {
+                needed: 1,
+                capacity: 2
+            })
+        );
+        assert!(!truncated);
+
+        // Excess elements are consumed from the wire without being stored
+        let (res, vec, read, truncated) = decode(OverflowPolicy::DropExtra);
+        assert_eq!(res, Ok(()));
+        assert_eq!(vec.as_slice(), &[1, 2]);
+        assert_eq!(read, 13);
+        assert!(!truncated);
+
+        let (res, vec, read, truncated) = decode(OverflowPolicy::DropExtraWithFlag);
+        assert_eq!(res, Ok(()));
+        assert_eq!(vec.as_slice(), &[1, 2]);
+        assert_eq!(read, 13);
+        assert!(truncated);
+    }
 
     /// Test decoding of a map element with varint32 key and string value
     macro_rules! assert_decode_map_elem {
@@ -1253,33 +3239,37 @@ mod tests {
 
     #[test]
     fn map_elem() {
-        assert_decode_map_elem!(Ok(None), [0]);
+        // Missing key and value are replaced by defaults
+        assert_decode_map_elem!(Ok((0, ArrayString::new())), [0]);
         // One key
-        assert_decode_map_elem!(Ok(None), [2, 0x08, 0x01]);
+        assert_decode_map_elem!(Ok((1, ArrayString::new())), [2, 0x08, 0x01]);
         // Two keys
-        assert_decode_map_elem!(Ok(None), [4, 0x08, 0x01, 0x08, 0x02]);
+        assert_decode_map_elem!(Ok((2, ArrayString::new())), [4, 0x08, 0x01, 0x08, 0x02]);
         // One value
-        assert_decode_map_elem!(Ok(None), [3, 0x12, 1, b'a']);
+        assert_decode_map_elem!(Ok((0, ArrayString::from("a").unwrap())), [3, 0x12, 1, b'a']);
         // Two values
-        assert_decode_map_elem!(Ok(None), [6, 0x12, 1, b'a', 0x12, 1, b'c']);
+        assert_decode_map_elem!(
+            Ok((0, ArrayString::from("c").unwrap())),
+            [6, 0x12, 1, b'a', 0x12, 1, b'c']
+        );
         // Key and value
         assert_decode_map_elem!(
-            Ok(Some((1, ArrayString::from("ac").unwrap()))),
+            Ok((1, ArrayString::from("ac").unwrap())),
             [6, 0x08, 0x01, 0x12, 2, b'a', b'c']
         );
         // Key and value, then an unknown tag which we ignore
         assert_decode_map_elem!(
-            Ok(Some((1, ArrayString::from("ac").unwrap()))),
+            Ok((1, ArrayString::from("ac").unwrap())),
             [8, 0x08, 0x01, 0x12, 2, b'a', b'c', 0x28, 0x01]
         );
         // Value and key
         assert_decode_map_elem!(
-            Ok(Some((1, ArrayString::from("ac").unwrap()))),
+            Ok((1, ArrayString::from("ac").unwrap())),
             [6, 0x12, 2, b'a', b'c', 0x08, 0x01]
         );
         // Overwrite value and key
         assert_decode_map_elem!(
-            Ok(Some((2, ArrayString::from("x").unwrap()))),
+            Ok((2, ArrayString::from("x").unwrap())),
             [11, 0x12, 2, b'a', b'c', 0x08, 0x01, 0x08, 0x02, 0x12, 1, b'x']
         );
 
@@ -1297,10 +3287,10 @@ mod tests {
     #[test]
     fn map_elem_string_key() {
         assert_decode!(
-            Ok(Some((
+            Ok((
                 ArrayString::from("ac").unwrap(),
                 ArrayString::from("bd").unwrap()
-            ))),
+            )),
             [8, 0x0A, 2, b'a', b'c', 0x12, 2, b'b', b'd'],
             decode_map_elem(
                 |v, rd| rd.decode_string::<ArrayString<5>>(v, Presence::Explicit),
@@ -1308,4 +3298,782 @@ mod tests {
             )
         );
     }
+
+    /// Decode an `UInt32Value` wrapper, returning the value, which starts at 0
+    fn decode_wrapper_u32(input: &[u8]) -> Result<u32, DecodeError<Never>> {
+        let mut decoder = PbDecoder::new(input);
+        let mut val = 0;
+        decoder.decode_wrapper(WIRE_TYPE_VARINT, |rd| {
+            val = rd.decode_varint32()?;
+            Ok(())
+        })?;
+        assert!(decoder.reader.is_empty());
+        Ok(val)
+    }
+
+    #[test]
+    fn wrapper() {
+        // Empty wrapper holds zero
+        assert_eq!(decode_wrapper_u32(&[0]), Ok(0));
+        assert_eq!(decode_wrapper_u32(&[2, 0x08, 0x05]), Ok(5));
+        // Last value wins
+        assert_eq!(decode_wrapper_u32(&[4, 0x08, 0x05, 0x08, 0x07]), Ok(7));
+        // Unknown fields are skipped
+        assert_eq!(decode_wrapper_u32(&[4, 0x10, 0x01, 0x08, 0x05]), Ok(5));
+
+        assert_eq!(
+            decode_wrapper_u32(&[2, 0x0D, 0x05]),
+            Err(DecodeError::WrongWireType)
+        );
+        assert_eq!(
+            decode_wrapper_u32(&[2, 0x00, 0x05]),
+            Err(DecodeError::ZeroField)
+        );
+        assert_eq!(
+            decode_wrapper_u32(&[2, 0x08, 0x80, 0x01]),
+            Err(DecodeError::WrongLen)
+        );
+        assert_eq!(
+            decode_wrapper_u32(&[2, 0x08]),
+            Err(DecodeError::UnexpectedEof)
+        );
+    }
+
+    /// Decode a single map entry with `u32` keys and `bytes` values into `map`
+    fn decode_map_entry_into<M: PbMap<u32, heapless::Vec<u8, 4>>>(
+        map: &mut M,
+        data: &[u8],
+        policy: OverflowPolicy,
+    ) -> Result<(), DecodeError<Never>> {
+        let mut decoder = PbDecoder::new(data);
+        decoder.decode_map_entry(
+            map,
+            |k, d| d.decode_varint32().map(|v| *k = v),
+            |v, d| d.decode_bytes(v, Presence::Explicit),
+            policy,
+        )?;
+        assert!(decoder.is_eof().unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn map_entry() {
+        let policy = OverflowPolicy::Error;
+        let mut map = heapless::FnvIndexMap::<u32, heapless::Vec<u8, 4>, 2>::new();
+        let get = |map: &heapless::FnvIndexMap<_, _, 2>, k| {
+            map.get(&k).map(|v: &heapless::Vec<u8, 4>| v.to_vec())
+        };
+
+        // Key, then value
+        decode_map_entry_into(&mut map, &[6, 0x08, 0x01, 0x12, 2, 0xA, 0xB], policy).unwrap();
+        assert_eq!(get(&map, 1), Some(vec![0xA, 0xB]));
+        // Existing value is replaced rather than merged
+        decode_map_entry_into(&mut map, &[5, 0x08, 0x01, 0x12, 1, 0xC], policy).unwrap();
+        assert_eq!(get(&map, 1), Some(vec![0xC]));
+        // Value, then key
+        decode_map_entry_into(&mut map, &[5, 0x12, 1, 0xD, 0x08, 0x02], policy).unwrap();
+        assert_eq!(get(&map, 2), Some(vec![0xD]));
+        assert_eq!(map.len(), 2);
+
+        let mut map = heapless::FnvIndexMap::<u32, heapless::Vec<u8, 4>, 2>::new();
+        // Missing key and value
+        decode_map_entry_into(&mut map, &[0], policy).unwrap();
+        assert_eq!(get(&map, 0), Some(vec![]));
+        // Missing value
+        decode_map_entry_into(&mut map, &[2, 0x08, 0x03], policy).unwrap();
+        assert_eq!(get(&map, 3), Some(vec![]));
+
+        let mut map = heapless::FnvIndexMap::<u32, heapless::Vec<u8, 4>, 2>::new();
+        // Missing key
+        decode_map_entry_into(&mut map, &[3, 0x12, 1, 0xE], policy).unwrap();
+        assert_eq!(get(&map, 0), Some(vec![0xE]));
+        // Duplicate key and value fields, last one wins
+        decode_map_entry_into(
+            &mut map,
+            &[11, 0x08, 0x04, 0x08, 0x05, 0x12, 1, 0x1, 0x12, 2, 0x2, 0x3],
+            policy,
+        )
+        .unwrap();
+        assert_eq!(get(&map, 4), None);
+        assert_eq!(get(&map, 5), Some(vec![0x2, 0x3]));
+        // Key after the value moves the value to the last key, without touching the first key
+        decode_map_entry_into(&mut map, &[7, 0x08, 0x05, 0x12, 1, 0x9, 0x08, 0x00], policy)
+            .unwrap();
+        assert_eq!(get(&map, 0), Some(vec![0x9]));
+        assert_eq!(get(&map, 5), Some(vec![0x2, 0x3]));
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn map_entry_key_after_value_keeps_existing() {
+        let mut map = heapless::FnvIndexMap::<u32, u32, 4>::new();
+        map.insert(1, 100).unwrap();
+        // key = 1, value = 7, key = 2
+        let mut decoder = PbDecoder::new([6, 0x08, 0x01, 0x10, 0x07, 0x08, 0x02].as_slice());
+        decoder
+            .decode_map_entry(
+                &mut map,
+                |k, d| d.decode_varint32().map(|v| *k = v),
+                |v, d| d.decode_varint32().map(|n| *v = n),
+                OverflowPolicy::Error,
+            )
+            .unwrap();
+        assert_eq!(map.get(&1), Some(&100));
+        assert_eq!(map.get(&2), Some(&7));
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn map_entry_overflow() {
+        let mut map = heapless::FnvIndexMap::<u32, heapless::Vec<u8, 4>, 2>::new();
+        let entry = |k: u8| [5, 0x08, k, 0x12, 1, k];
+        decode_map_entry_into(&mut map, &entry(1), OverflowPolicy::Error).unwrap();
+        decode_map_entry_into(&mut map, &entry(2), OverflowPolicy::Error).unwrap();
+
+        // New keys don't fit, but existing keys can still be overwritten
+        assert_eq!(
+            decode_map_entry_into(&mut map, &entry(3), OverflowPolicy::Error),
+            Err(DecodeError::Capacity {
+                needed: 1,
+                capacity: 2
+            })
+        );
+        decode_map_entry_into(&mut map, &entry(3), OverflowPolicy::DropExtra).unwrap();
+        decode_map_entry_into(&mut map, &[5, 0x08, 2, 0x12, 1, 0xF], OverflowPolicy::Error)
+            .unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(&2).unwrap(), &[0xF]);
+        // Values that don't fit into the container are still capacity errors
+        assert_eq!(
+            decode_map_entry_into(
+                &mut map,
+                &[8, 0x08, 1, 0x12, 5, 1, 2, 3, 4, 5],
+                OverflowPolicy::Error
+            ),
+            Err(DecodeError::Capacity {
+                needed: 1,
+                capacity: 4
+            })
+        );
+
+        // Alloc maps never overflow
+        let mut map = std::collections::BTreeMap::new();
+        for k in 0..4 {
+            decode_map_entry_into(&mut map, &entry(k), OverflowPolicy::Error).unwrap();
+        }
+        assert_eq!(map.len(), 4);
+        let mut map = std::collections::HashMap::new();
+        decode_map_entry_into(&mut map, &[5, 0x12, 1, 0x7, 0x08, 9], OverflowPolicy::Error)
+            .unwrap();
+        assert_eq!(map[&9], &[0x7]);
+    }
+
+    #[test]
+    fn split_slices() {
+        let data = [
+            0xAC, 0x02, 0x01, 0x02, 0x03, 0x04, 3, b'a', b'b', b'c', 0xFF, 0x01,
+        ];
+        // Split the data at every possible boundary
+        for i in 0..=data.len() {
+            let (first, second) = data.split_at(i);
+            let mut decoder = PbDecoder::new((first, second));
+            assert_eq!(decoder.decode_varint32(), Ok(300));
+            assert_eq!(decoder.decode_fixed32(), Ok(0x04030201));
+            let mut string = ArrayString::<5>::new();
+            assert_eq!(
+                decoder.decode_string(&mut string, Presence::Explicit),
+                Ok(())
+            );
+            assert_eq!(string.as_str(), "abc");
+            assert_eq!(decoder.skip_wire_value(WIRE_TYPE_VARINT), Ok(()));
+            assert_eq!(decoder.bytes_read(), data.len());
+            assert_eq!(decoder.decode_varint32(), Err(DecodeError::UnexpectedEof));
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum MockErr {
+        WouldBlock,
+    }
+
+    #[test]
+    fn buffered_reader() {
+        // Multi-byte varints, fixed32, and a string, delivered in chunks that straddle values
+        let data = [
+            0xAC, 0x02, 0xFF, 0xFF, 0xFF, 0xFF, 0x0F, 0x01, 0x02, 0x03, 0x04, 3, b'a', b'b', b'c',
+        ];
+        let chunk_sizes = [1, 3, 2, 5, 1, 4];
+        let mut pos = 0;
+        let mut calls = 0;
+        let reader = BufferedReader::<_, 4>::new(|buf: &mut [u8]| {
+            // First read reports that the driver would block
+            if calls == 0 {
+                calls += 1;
+                return Err(MockErr::WouldBlock);
+            }
+            let n = chunk_sizes[calls % chunk_sizes.len()]
+                .min(buf.len())
+                .min(data.len() - pos);
+            buf[..n].copy_from_slice(&data[pos..pos + n]);
+            pos += n;
+            calls += 1;
+            Ok(n)
+        });
+        let mut decoder = PbDecoder::new(reader);
+
+        // Errors from the callback are surfaced rather than retried
+        assert_eq!(
+            decoder.decode_varint32(),
+            Err(DecodeError::Reader(MockErr::WouldBlock))
+        );
+        assert_eq!(decoder.bytes_read(), 0);
+        assert_eq!(decoder.decode_varint32(), Ok(300));
+        assert_eq!(decoder.decode_varint32(), Ok(u32::MAX));
+        assert_eq!(decoder.decode_fixed32(), Ok(0x04030201));
+        let mut string = ArrayString::<5>::new();
+        assert_eq!(
+            decoder.decode_string(&mut string, Presence::Explicit),
+            Ok(())
+        );
+        assert_eq!(string.as_str(), "abc");
+        assert_eq!(decoder.bytes_read(), data.len());
+        assert_eq!(decoder.decode_varint32(), Err(DecodeError::UnexpectedEof));
+    }
+
+    #[cfg(feature = "embedded-io")]
+    /// Driver that delivers its data in chunks of varying sizes
+    struct ChunkedReader<'a> {
+        data: &'a [u8],
+        chunk_sizes: &'a [usize],
+        calls: usize,
+    }
+
+    #[cfg(feature = "embedded-io")]
+    impl ChunkedReader<'_> {
+        fn next_chunk(&mut self) -> Result<usize, embedded_io::ErrorKind> {
+            self.calls += 1;
+            // First read reports that the driver would block
+            if self.calls == 1 {
+                return Err(embedded_io::ErrorKind::Other);
+            }
+            Ok(self.chunk_sizes[self.calls % self.chunk_sizes.len()])
+        }
+    }
+
+    #[cfg(feature = "embedded-io")]
+    impl embedded_io::ErrorType for ChunkedReader<'_> {
+        type Error = embedded_io::ErrorKind;
+    }
+
+    #[cfg(feature = "embedded-io")]
+    impl embedded_io::Read for ChunkedReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            let chunk = self.next_chunk()?;
+            let n = chunk.min(buf.len()).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    #[cfg(feature = "embedded-io")]
+    impl embedded_io::BufRead for ChunkedReader<'_> {
+        fn fill_buf(&mut self) -> Result<&[u8], Self::Error> {
+            let chunk = self.next_chunk()?;
+            Ok(&self.data[..chunk.min(self.data.len())])
+        }
+
+        fn consume(&mut self, amt: usize) {
+            self.data = &self.data[amt..];
+        }
+    }
+
+    #[cfg(feature = "embedded-io")]
+    #[test]
+    fn embedded_io_reader() {
+        // Multi-byte varints, fixed32, and a string, delivered in chunks that straddle values
+        let data = [
+            0xAC, 0x02, 0xFF, 0xFF, 0xFF, 0xFF, 0x0F, 0x01, 0x02, 0x03, 0x04, 3, b'a', b'b', b'c',
+        ];
+        fn check<R: PbRead<Error = embedded_io::ErrorKind>>(reader: R, len: usize) {
+            let mut decoder = PbDecoder::new(reader);
+            // Errors from the driver are surfaced rather than retried
+            assert_eq!(
+                decoder.decode_varint32(),
+                Err(DecodeError::Reader(embedded_io::ErrorKind::Other))
+            );
+            assert_eq!(decoder.bytes_read(), 0);
+            assert_eq!(decoder.decode_varint32(), Ok(300));
+            assert_eq!(decoder.decode_varint32(), Ok(u32::MAX));
+            assert_eq!(decoder.decode_fixed32(), Ok(0x04030201));
+            let mut string = ArrayString::<5>::new();
+            assert_eq!(
+                decoder.decode_string(&mut string, Presence::Explicit),
+                Ok(())
+            );
+            assert_eq!(string.as_str(), "abc");
+            assert_eq!(decoder.bytes_read(), len);
+            assert_eq!(decoder.decode_varint32(), Err(DecodeError::UnexpectedEof));
+        }
+
+        let reader = |chunk_sizes| ChunkedReader {
+            data: &data,
+            chunk_sizes,
+            calls: 0,
+        };
+        check(
+            EmbeddedIoReader::<_, 4>::new(reader(&[1, 3, 2, 5, 1, 4])),
+            data.len(),
+        );
+        check(EmbeddedIoBufReader(reader(&[1, 3, 2, 5, 1, 4])), data.len());
+    }
+
+    #[cfg(feature = "std")]
+    struct OneByteReader<'a> {
+        data: &'a [u8],
+        interrupt: bool,
+    }
+
+    #[cfg(feature = "std")]
+    impl<'a> std::io::Read for OneByteReader<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            // Interrupt every other read to exercise the retry logic
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                return Err(std::io::ErrorKind::Interrupted.into());
+            }
+            match (self.data.split_first(), buf.first_mut()) {
+                (Some((&b, rest)), Some(out)) => {
+                    *out = b;
+                    self.data = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn std_buf_reader<R: std::io::Read>(reader: R) {
+        let mut decoder = PbDecoder::new(StdBufReader::with_capacity(4, reader));
+        assert_eq!(decoder.decode_varint32().unwrap(), 150);
+        let mut string = String::new();
+        decoder
+            .decode_string(&mut string, Presence::Explicit)
+            .unwrap();
+        assert_eq!(string, "hello");
+        assert_eq!(decoder.decode_fixed32().unwrap(), 0x04030201);
+        assert_eq!(decoder.bytes_read(), 12);
+        // EOF in the middle of a varint
+        assert!(matches!(
+            decoder.decode_varint64(),
+            Err(DecodeError::UnexpectedEof)
+        ));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn std_reader() {
+        let data = [
+            0x96, 0x01, 5, b'h', b'e', b'l', b'l', b'o', 0x01, 0x02, 0x03, 0x04, 0x80,
+        ];
+        std_buf_reader(std::io::Cursor::new(data));
+        std_buf_reader(OneByteReader {
+            data: &data,
+            interrupt: false,
+        });
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn std_reader_error() {
+        struct ErrReader;
+        impl std::io::Read for ErrReader {
+            fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::ConnectionReset.into())
+            }
+        }
+
+        let mut decoder = PbDecoder::new(StdBufReader::new(ErrReader));
+        match decoder.decode_varint32() {
+            Err(DecodeError::Reader(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::ConnectionReset)
+            }
+            res => panic!("unexpected result {res:?}"),
+        }
+    }
+
+    /// Message that contains itself in field 1, and records how deeply it's nested
+    #[derive(Default)]
+    struct Node {
+        depth: usize,
+    }
+
+    impl MessageDecode for Node {
+        fn decode<R: PbRead>(
+            &mut self,
+            decoder: &mut PbDecoder<R>,
+            len: usize,
+        ) -> Result<(), DecodeError<R::Error>> {
+            let before = decoder.bytes_read();
+            while decoder.bytes_read() - before < len {
+                let tag = decoder.decode_tag()?;
+                match tag.field_num() {
+                    1 => {
+                        let mut child = Node::default();
+                        child.decode_len_delimited(decoder)?;
+                        self.depth = child.depth + 1;
+                    }
+                    _ => decoder.skip_wire_value(tag.wire_type())?,
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Encode `levels` of nested `Node`s into the end of `buf`, returning the encoded slice
+    fn nested_nodes(buf: &mut [u8], levels: usize) -> &[u8] {
+        let mut start = buf.len();
+        for _ in 0..levels {
+            let len = buf.len() - start;
+            let mut varint = ArrayVec::<u8, 2>::new();
+            varint.push((len & 0x7F) as u8);
+            if len >= 0x80 {
+                varint[0] |= 0x80;
+                varint.push((len >> 7) as u8);
+            }
+            start -= varint.len() + 1;
+            buf[start] = 0x0A;
+            buf[start + 1..start + 1 + varint.len()].copy_from_slice(&varint);
+        }
+        &buf[start..]
+    }
+
+    #[test]
+    fn depth_limit() {
+        let mut buf = [0; 4096];
+        let data = nested_nodes(&mut buf, 32);
+        let mut node = Node::default();
+        let mut decoder = PbDecoder::new(data);
+        node.decode(&mut decoder, data.len()).unwrap();
+        assert_eq!(node.depth, 32);
+
+        let data = nested_nodes(&mut buf, 33);
+        let mut decoder = PbDecoder::new(data);
+        assert_eq!(
+            Node::default().decode(&mut decoder, data.len()),
+            Err(DecodeError::DepthLimit)
+        );
+
+        // Malicious input that's deeply nested should fail cleanly
+        let data = nested_nodes(&mut buf, 1000);
+        let mut decoder = PbDecoder::new(data);
+        assert_eq!(
+            Node::default().decode(&mut decoder, data.len()),
+            Err(DecodeError::DepthLimit)
+        );
+
+        // Depth is restored after each nested message, so siblings don't count towards the limit
+        let data = [0x0A, 0x00, 0x0A, 0x02, 0x0A, 0x00];
+        let mut decoder = PbDecoder::new(data.as_slice());
+        decoder.max_depth = 2;
+        let mut node = Node::default();
+        node.decode(&mut decoder, data.len()).unwrap();
+        assert_eq!(node.depth, 2);
+        let mut decoder = PbDecoder::new(data.as_slice());
+        decoder.max_depth = 1;
+        assert_eq!(
+            Node::default().decode(&mut decoder, data.len()),
+            Err(DecodeError::DepthLimit)
+        );
+    }
+
+    #[test]
+    fn len_delimited() {
+        // Route on field 1, then decode the nested record in field 2 by hand
+        let data = [0x08, 0x01, 0x12, 0x04, 0x08, 0x96, 0x01, 0x00];
+        let mut decoder = PbDecoder::new(data.as_slice());
+        let tag = decoder.decode_tag().unwrap();
+        assert_eq!((tag.field_num(), tag.wire_type()), (1, WIRE_TYPE_VARINT));
+        assert_eq!(decoder.decode_varint32(), Ok(1));
+        let tag = decoder.decode_tag().unwrap();
+        assert_eq!((tag.field_num(), tag.wire_type()), (2, WIRE_TYPE_LEN));
+        let inner = decoder
+            .decode_len_delimited(|decoder, len| {
+                assert_eq!(len, 4);
+                let tag = decoder.decode_tag()?;
+                let val = decoder.decode_varint32()?;
+                decoder.skip_wire_value(WIRE_TYPE_VARINT)?;
+                Ok((tag.field_num(), val))
+            })
+            .unwrap();
+        assert_eq!(inner, (1, 150));
+        assert!(decoder.is_eof().unwrap());
+
+        // Callback must consume exactly the length of the record
+        let mut decoder = PbDecoder::new(data.as_slice());
+        decoder.skip_bytes(2).unwrap();
+        decoder.decode_tag().unwrap();
+        assert_eq!(
+            decoder.decode_len_delimited(|decoder, _| decoder.decode_tag()),
+            Err(DecodeError::WrongLen)
+        );
+
+        let mut decoder = PbDecoder::new([0x00].as_slice());
+        decoder.max_depth = 0;
+        assert_eq!(
+            decoder.decode_len_delimited(|_, _| Ok(())),
+            Err(DecodeError::DepthLimit)
+        );
+    }
+
+    #[test]
+    fn max_size() {
+        let data = [0x03, 0x08, 0x96, 0x01, 0x02, b'a', b'b'];
+
+        // Length prefixes that end exactly at the limit are fine
+        let mut decoder = PbDecoder::new(data.as_slice());
+        decoder.max_size = 4;
+        decoder
+            .decode_len_delimited(|decoder, len| decoder.skip_bytes(len))
+            .unwrap();
+        // Any length past the limit fails before reading the value
+        assert_eq!(
+            decoder.decode_string(&mut String::new(), Presence::Explicit),
+            Err(DecodeError::SizeLimit)
+        );
+        assert_eq!(decoder.bytes_read(), 5);
+
+        let mut decoder = PbDecoder::new(data.as_slice());
+        decoder.max_size = 3;
+        assert_eq!(
+            decoder.decode_len_delimited(|_, _| -> Result<(), _> { unreachable!() }),
+            Err(DecodeError::SizeLimit)
+        );
+        assert_eq!(decoder.check_size_limit(2), Ok(()));
+        assert_eq!(decoder.check_size_limit(3), Err(DecodeError::SizeLimit));
+
+        // No limit by default
+        let mut decoder = PbDecoder::new(data.as_slice());
+        assert_eq!(decoder.check_size_limit(usize::MAX), Ok(()));
+        decoder.skip_bytes(4).unwrap();
+        assert_eq!(decoder.check_size_limit(usize::MAX), Ok(()));
+    }
+
+    #[test]
+    fn len_delimited_max() {
+        let data = [0x03, 0x08, 0x96, 0x01, 0xFF];
+        let mut decoder = PbDecoder::new(data.as_slice());
+        let val = decoder
+            .decode_len_delimited_max(3, |decoder, _| {
+                decoder.decode_tag()?;
+                decoder.decode_varint32()
+            })
+            .unwrap();
+        assert_eq!(val, 150);
+        assert_eq!(decoder.remaining(), &[0xFF]);
+
+        // Length over the limit is rejected before reading the record
+        let mut decoder = PbDecoder::new(data.as_slice());
+        assert_eq!(
+            decoder.decode_len_delimited_max(2, |_, _| -> Result<(), _> { unreachable!() }),
+            Err(DecodeError::SizeLimit)
+        );
+        assert_eq!(decoder.bytes_read(), 1);
+
+        // Truncated prefix
+        let mut decoder = PbDecoder::new([0x80].as_slice());
+        assert_eq!(
+            decoder.decode_len_delimited_max(16, |_, _| Ok(())),
+            Err(DecodeError::UnexpectedEof)
+        );
+
+        // Truncated record is rejected before reading it with contiguous readers
+        let mut decoder = PbDecoder::new([0x05, 0x08, 0x01].as_slice());
+        assert_eq!(
+            decoder.decode_len_delimited_max(16, |_, _| -> Result<(), _> { unreachable!() }),
+            Err(DecodeError::UnexpectedEof)
+        );
+        // Other readers run into the end of the input while reading the record
+        let mut decoder = PbDecoder::new(([0x05, 0x08].as_slice(), [0x01].as_slice()));
+        assert_eq!(
+            decoder.decode_len_delimited_max(16, |decoder, len| decoder.skip_bytes(len)),
+            Err(DecodeError::UnexpectedEof)
+        );
+
+        // Record must be consumed exactly
+        let mut decoder = PbDecoder::new(data.as_slice());
+        assert_eq!(
+            decoder.decode_len_delimited_max(16, |decoder, _| decoder.decode_tag()),
+            Err(DecodeError::WrongLen)
+        );
+    }
+
+    #[test]
+    fn error_location() {
+        // Field 2 of the nested message is cut off
+        let data = [0x0A, 0x03, 0x10, 0x96, 0x96];
+        let mut decoder = PbDecoder::new(data.as_slice());
+        let err = Node::default()
+            .decode(&mut decoder, data.len())
+            .map_err(|e| decoder.locate(e))
+            .unwrap_err();
+        assert_eq!(err.error, DecodeError::UnexpectedEof);
+        assert_eq!(
+            err.location,
+            DecodeLocation {
+                offset: 5,
+                field_num: 2,
+                depth: 1
+            }
+        );
+
+        // Location is restored to the outer message after the nested message is decoded
+        let data = [0x0A, 0x02, 0x10, 0x01];
+        let mut decoder = PbDecoder::new(data.as_slice());
+        Node::default().decode(&mut decoder, data.len()).unwrap();
+        assert_eq!(
+            decoder.location(),
+            DecodeLocation {
+                offset: 4,
+                field_num: 1,
+                depth: 0
+            }
+        );
+    }
+
+    #[test]
+    fn state_restored_after_error() {
+        // Field 2 of the nested message is cut off
+        let data = [0x0A, 0x03, 0x10, 0x96, 0x96];
+        let mut decoder = PbDecoder::new(data.as_slice());
+        assert_eq!(
+            Node::default().decode(&mut decoder, data.len()),
+            Err(DecodeError::UnexpectedEof)
+        );
+        assert_eq!((decoder.depth, decoder.field_num), (0, 1));
+        // Location still points at the error inside the nested message
+        assert_eq!(
+            decoder.location(),
+            DecodeLocation {
+                offset: 5,
+                field_num: 2,
+                depth: 1
+            }
+        );
+
+        // Group with an end-group tag for the wrong field
+        let data = [0x0B, 0x08, 0x01, 0x14, 0x18, 0x01];
+        let mut decoder = PbDecoder::new(data.as_slice());
+        let tag = decoder.decode_tag().unwrap();
+        assert_eq!(
+            decoder.skip_wire_value(tag.wire_type()),
+            Err(DecodeError::GroupMismatch)
+        );
+        assert_eq!((decoder.depth, decoder.field_num), (0, 1));
+        assert_eq!(
+            decoder.location(),
+            DecodeLocation {
+                offset: 4,
+                field_num: 2,
+                depth: 1
+            }
+        );
+        // Decoding the next field moves the location on
+        assert_eq!(
+            decoder.decode_tag(),
+            Ok(Tag::from_parts(3, WIRE_TYPE_VARINT))
+        );
+        assert_eq!(
+            decoder.location(),
+            DecodeLocation {
+                offset: 5,
+                field_num: 3,
+                depth: 0
+            }
+        );
+    }
+
+    #[test]
+    fn error_display() {
+        let cases: [(DecodeError<&str>, &str); 19] = [
+            (DecodeError::VarIntLimit, "varint longer than 10 bytes"),
+            (DecodeError::UnexpectedEof, "unexpected end of input"),
+            (DecodeError::Deprecation, "deprecated wire type"),
+            (DecodeError::UnknownWireType, "unknown wire type"),
+            (
+                DecodeError::WrongWireType,
+                "wire type doesn't match field type",
+            ),
+            (
+                DecodeError::GroupMismatch,
+                "end-group tag doesn't match start-group tag",
+            ),
+            (DecodeError::ZeroField, "field number 0 is not allowed"),
+            (
+                DecodeError::CustomField,
+                "custom field rejected its field number",
+            ),
+            (DecodeError::Utf8, "string is not valid UTF-8"),
+            (
+                DecodeError::Capacity {
+                    needed: 1,
+                    capacity: 4,
+                },
+                "field exceeded capacity of fixed container: needed 1 more, capacity is 4",
+            ),
+            (
+                DecodeError::WrongLen,
+                "record length doesn't match length prefix",
+            ),
+            (DecodeError::SizeLimit, "message length exceeded limit"),
+            (
+                DecodeError::DepthLimit,
+                "message nesting depth exceeded limit",
+            ),
+            (DecodeError::NonContiguous, "reader input is not contiguous"),
+            (
+                DecodeError::UnknownEnumValue,
+                "enum value not declared in enum",
+            ),
+            (DecodeError::CrcMismatch, "frame checksum mismatch"),
+            (
+                DecodeError::CompressedFrame,
+                "compressed frames are not supported",
+            ),
+            (
+                DecodeError::IntOverflow,
+                "integer doesn't fit into pointer-sized field",
+            ),
+            (
+                DecodeError::Reader("broken pipe"),
+                "reader error: broken pipe",
+            ),
+        ];
+        for (err, msg) in cases {
+            assert_eq!(err.to_string(), msg);
+        }
+
+        let mut err = LocatedDecodeError {
+            error: DecodeError::<&str>::WrongWireType,
+            location: DecodeLocation {
+                offset: 0,
+                field_num: 0,
+                depth: 0,
+            },
+        };
+        assert_eq!(
+            err.to_string(),
+            "wire type doesn't match field type at offset 0"
+        );
+        err.location = DecodeLocation {
+            offset: 12,
+            field_num: 4,
+            depth: 2,
+        };
+        assert_eq!(
+            err.to_string(),
+            "wire type doesn't match field type at field 4, offset 12, depth 2"
+        );
+    }
 }
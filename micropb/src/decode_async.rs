@@ -0,0 +1,446 @@
+use core::marker::PhantomData;
+
+use crate::{
+    DecodeError, MessageDecode, PbDecoder, PbRead, WIRE_TYPE_I32, WIRE_TYPE_I64, WIRE_TYPE_LEN,
+    WIRE_TYPE_SGROUP, WIRE_TYPE_VARINT,
+};
+
+/// Reader over a buffered field, reporting errors with the error type of the async reader.
+///
+/// The buffer only holds one field and is reused for the next one, so it isn't
+/// [contiguous](PbRead::CONTIGUOUS) with the rest of the message. Otherwise `bytes` ranges would
+/// point into the buffer rather than the input.
+struct FieldReader<'a, E>(&'a [u8], PhantomData<E>);
+
+impl<E> PbRead for FieldReader<'_, E> {
+    type Error = E;
+
+    #[inline]
+    fn pb_read_chunk(&mut self) -> Result<&[u8], Self::Error> {
+        Ok(self.0)
+    }
+
+    #[inline]
+    fn pb_advance(&mut self, bytes: usize) {
+        self.0 = self.0.get(bytes..).unwrap_or(&[]);
+    }
+}
+
+fn field_decoder<E>(buf: &[u8]) -> PbDecoder<FieldReader<'_, E>> {
+    PbDecoder::new(FieldReader(buf, PhantomData))
+}
+
+/// Buffer that pulls the bytes of a single field from an async reader
+struct FieldBuf<'r, R, const N: usize> {
+    reader: &'r mut R,
+    buf: [u8; N],
+    len: usize,
+    /// Bytes left in the message
+    remaining: usize,
+}
+
+impl<R: embedded_io_async::Read, const N: usize> FieldBuf<'_, R, N> {
+    /// Append `n` bytes from the reader to the buffer
+    async fn read(&mut self, n: usize) -> Result<(), DecodeError<R::Error>> {
+        if n > self.remaining {
+            return Err(DecodeError::WrongLen);
+        }
+        let end = self
+            .len
+            .checked_add(n)
+            .filter(|&end| end <= N)
+            .ok_or(DecodeError::SizeLimit)?;
+        self.reader
+            .read_exact(&mut self.buf[self.len..end])
+            .await
+            .map_err(|err| match err {
+                embedded_io_async::ReadExactError::UnexpectedEof => DecodeError::UnexpectedEof,
+                embedded_io_async::ReadExactError::Other(e) => DecodeError::Reader(e),
+            })?;
+        self.len = end;
+        self.remaining -= n;
+        Ok(())
+    }
+
+    /// Append a varint from the reader to the buffer, returning the position of its first byte
+    async fn read_varint(&mut self) -> Result<usize, DecodeError<R::Error>> {
+        let start = self.len;
+        for _ in 0..10 {
+            self.read(1).await?;
+            if self.buf[self.len - 1] & 0x80 == 0 {
+                return Ok(start);
+            }
+        }
+        Err(DecodeError::VarIntLimit)
+    }
+}
+
+/// Decode a message of `len` bytes from an [`embedded_io_async::Read`], such as a UART or USB
+/// receiver, and merge it into `msg`.
+///
+/// The message is read one top-level field at a time. Each field is buffered in a `N`-byte buffer
+/// and merged into `msg` with the regular [`MessageDecode::decode`] before the next field is read,
+/// so at most `N` bytes are buffered at once regardless of the size of the message. Since
+/// Protobuf merges concatenated records, the result is identical to decoding the whole message in
+/// one go. A field that doesn't fit into the buffer, including a nested message field along with
+/// its tag and length prefix, fails with [`DecodeError::SizeLimit`]. Groups can't be buffered
+/// because their length isn't known upfront, so they fail with [`DecodeError::Deprecation`].
+/// Since the input isn't kept around, `bytes` fields decoded as ranges into the input fail with
+/// [`DecodeError::NonContiguous`].
+///
+/// Errors from the reader are returned as [`DecodeError::Reader`]. If the returned future is
+/// dropped before completion, `msg` keeps the fields that were already merged into it and the
+/// rest of the message is left in the reader.
+///
+/// This requires the `embedded-io-async` feature, which needs Rust 1.75 or later.
+///
+/// # Example
+/// ```no_run
+/// # async fn recv<M: micropb::MessageDecode + Default, R: embedded_io_async::Read>(
+/// #     rx: &mut R,
+/// #     len: usize,
+/// # ) -> Result<M, micropb::DecodeError<R::Error>> {
+/// let mut msg = M::default();
+/// // Buffer at most 64 bytes at a time
+/// micropb::decode_async::<_, _, 64>(&mut msg, rx, len).await?;
+/// # Ok(msg)
+/// # }
+/// ```
+pub async fn decode_async<M, R, const N: usize>(
+    msg: &mut M,
+    reader: &mut R,
+    len: usize,
+) -> Result<(), DecodeError<R::Error>>
+where
+    M: MessageDecode + ?Sized,
+    R: embedded_io_async::Read,
+{
+    let mut field = FieldBuf {
+        reader,
+        buf: [0; N],
+        len: 0,
+        remaining: len,
+    };
+    while field.remaining > 0 {
+        field.len = 0;
+        field.read_varint().await?;
+        let tag = field_decoder(&field.buf[..field.len]).decode_tag()?;
+        match tag.wire_type() {
+            WIRE_TYPE_VARINT => {
+                field.read_varint().await?;
+            }
+            WIRE_TYPE_I64 => field.read(8).await?,
+            WIRE_TYPE_I32 => field.read(4).await?,
+            WIRE_TYPE_LEN => {
+                let start = field.read_varint().await?;
+                let n = field_decoder(&field.buf[start..field.len]).decode_len()?;
+                field.read(n).await?;
+            }
+            WIRE_TYPE_SGROUP => return Err(DecodeError::Deprecation),
+            // Let the decoder report stray end-group tags and unknown wire types
+            _ => {}
+        }
+        let buf = &field.buf[..field.len];
+        msg.decode(&mut field_decoder(buf), buf.len())?;
+    }
+    Ok(())
+}
+
+/// Decode a length-delimited message from an [`embedded_io_async::Read`] and merge it into `msg`.
+///
+/// The length prefix is read first, then the message is decoded as in [`decode_async`], buffering
+/// at most `N` bytes at once. Returns [`DecodeError::SizeLimit`] if the length prefix exceeds
+/// `max_len`, before any of the message is read.
+///
+/// This requires the `embedded-io-async` feature, which needs Rust 1.75 or later.
+pub async fn decode_len_delimited_async<M, R, const N: usize>(
+    msg: &mut M,
+    reader: &mut R,
+    max_len: usize,
+) -> Result<(), DecodeError<R::Error>>
+where
+    M: MessageDecode + ?Sized,
+    R: embedded_io_async::Read,
+{
+    let mut prefix = FieldBuf::<_, 10> {
+        reader: &mut *reader,
+        buf: [0; 10],
+        len: 0,
+        remaining: usize::MAX,
+    };
+    prefix.read_varint().await?;
+    let len = field_decoder(&prefix.buf[..prefix.len]).decode_len()?;
+    if len > max_len {
+        return Err(DecodeError::SizeLimit);
+    }
+    decode_async::<_, _, N>(msg, reader, len).await
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        future::Future,
+        pin::pin,
+        task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+    };
+
+    use crate::Tag;
+
+    use super::*;
+
+    /// Poll a future to completion, counting how many times it was pending
+    fn block_on<F: Future>(fut: F) -> (F::Output, usize) {
+        fn noop_raw_waker() -> RawWaker {
+            fn clone(_: *const ()) -> RawWaker {
+                noop_raw_waker()
+            }
+            fn noop(_: *const ()) {}
+            static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+            RawWaker::new(core::ptr::null(), &VTABLE)
+        }
+
+        // SAFETY: the vtable functions don't touch the data pointer
+        let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+        let mut cx = Context::from_waker(&waker);
+        let mut fut = pin!(fut);
+        let mut pending = 0;
+        loop {
+            match fut.as_mut().poll(&mut cx) {
+                Poll::Ready(out) => return (out, pending),
+                Poll::Pending => pending += 1,
+            }
+        }
+    }
+
+    /// Future that is pending once before completing
+    struct YieldNow(bool);
+
+    impl Future for YieldNow {
+        type Output = ();
+
+        fn poll(mut self: core::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                Poll::Ready(())
+            } else {
+                self.0 = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Async reader that returns one byte at a time, yielding before every byte
+    struct OneByteReader<'a> {
+        data: &'a [u8],
+        fail_at: Option<usize>,
+        read: usize,
+    }
+
+    impl<'a> OneByteReader<'a> {
+        fn new(data: &'a [u8]) -> Self {
+            Self {
+                data,
+                fail_at: None,
+                read: 0,
+            }
+        }
+    }
+
+    impl embedded_io_async::ErrorType for OneByteReader<'_> {
+        type Error = embedded_io_async::ErrorKind;
+    }
+
+    impl embedded_io_async::Read for OneByteReader<'_> {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            YieldNow(false).await;
+            if self.fail_at == Some(self.read) {
+                return Err(embedded_io_async::ErrorKind::Other);
+            }
+            let Some((&b, rest)) = self.data.split_first() else {
+                return Ok(0);
+            };
+            buf[0] = b;
+            self.data = rest;
+            self.read += 1;
+            Ok(1)
+        }
+    }
+
+    /// Message with a varint, a fixed32, and a list of nested messages, each with a varint
+    #[derive(Debug, Default, PartialEq)]
+    struct Nested {
+        val: u32,
+        fixed: u32,
+        children: Vec<u32>,
+    }
+
+    impl MessageDecode for Nested {
+        fn decode<R: PbRead>(
+            &mut self,
+            decoder: &mut PbDecoder<R>,
+            len: usize,
+        ) -> Result<(), DecodeError<R::Error>> {
+            let before = decoder.bytes_read();
+            while decoder.bytes_read() - before < len {
+                let tag = decoder.decode_tag()?;
+                match tag.field_num() {
+                    1 => self.val = decoder.decode_varint32()?,
+                    2 => self.fixed = decoder.decode_fixed32()?,
+                    3 => {
+                        let mut child = Nested::default();
+                        child.decode_len_delimited(decoder)?;
+                        self.children.push(child.val);
+                    }
+                    _ => decoder.skip_wire_value(tag.wire_type())?,
+                }
+            }
+            Ok(())
+        }
+    }
+
+    const DATA: &[u8] = &[
+        0x08, 0x96, 0x01, // val = 150
+        0x15, 0x01, 0x02, 0x03, 0x04, // fixed = 0x04030201
+        0x1A, 0x02, 0x08, 0x07, // children: val = 7
+        0x22, 0x01, 0xFF, // unknown bytes
+        0x1A, 0x03, 0x08, 0xAC, 0x02, // children: val = 300
+    ];
+
+    fn expected() -> Nested {
+        Nested {
+            val: 150,
+            fixed: 0x04030201,
+            children: vec![7, 300],
+        }
+    }
+
+    #[test]
+    fn decode_async_matches_sync() {
+        let mut sync = Nested::default();
+        sync.decode(&mut PbDecoder::new(DATA), DATA.len()).unwrap();
+        assert_eq!(sync, expected());
+
+        let mut reader = OneByteReader::new(DATA);
+        let mut msg = Nested::default();
+        let (res, pending) = block_on(decode_async::<_, _, 5>(&mut msg, &mut reader, DATA.len()));
+        assert_eq!(res, Ok(()));
+        assert_eq!(msg, sync);
+        // Yielded once for every byte
+        assert_eq!(pending, DATA.len());
+        assert!(reader.data.is_empty());
+    }
+
+    #[test]
+    fn decode_len_delimited_async() {
+        let mut data = vec![DATA.len() as u8];
+        data.extend_from_slice(DATA);
+        data.push(0xAA);
+
+        let mut reader = OneByteReader::new(&data);
+        let mut msg = Nested::default();
+        let (res, _) = block_on(super::decode_len_delimited_async::<_, _, 8>(
+            &mut msg,
+            &mut reader,
+            64,
+        ));
+        assert_eq!(res, Ok(()));
+        assert_eq!(msg, expected());
+        // Bytes after the message are left in the reader
+        assert_eq!(reader.data, [0xAA]);
+
+        let mut reader = OneByteReader::new(&data);
+        let (res, _) = block_on(super::decode_len_delimited_async::<_, _, 8>(
+            &mut Nested::default(),
+            &mut reader,
+            DATA.len() - 1,
+        ));
+        assert_eq!(res, Err(DecodeError::SizeLimit));
+        // Only the length prefix is read
+        assert_eq!(reader.data, &data[1..]);
+    }
+
+    #[test]
+    fn decode_async_bytes_range() {
+        /// Message with a `bytes` field decoded as a range into the input
+        #[derive(Default)]
+        struct Ranged(core::ops::Range<usize>);
+
+        impl MessageDecode for Ranged {
+            fn decode<R: PbRead>(
+                &mut self,
+                decoder: &mut PbDecoder<R>,
+                len: usize,
+            ) -> Result<(), DecodeError<R::Error>> {
+                let before = decoder.bytes_read();
+                while decoder.bytes_read() - before < len {
+                    let tag = decoder.decode_tag()?;
+                    match tag.field_num() {
+                        1 => decoder.decode_bytes_range(&mut self.0)?,
+                        _ => decoder.skip_wire_value(tag.wire_type())?,
+                    }
+                }
+                Ok(())
+            }
+        }
+
+        let data = [0x0A, 0x02, 0x01, 0x02];
+        let (res, _) = block_on(decode_async::<_, _, 8>(
+            &mut Ranged::default(),
+            &mut OneByteReader::new(&data),
+            data.len(),
+        ));
+        assert_eq!(res, Err(DecodeError::NonContiguous));
+    }
+
+    #[test]
+    fn decode_async_errors() {
+        // Field larger than the buffer
+        let mut msg = Nested::default();
+        let (res, _) = block_on(decode_async::<_, _, 4>(
+            &mut msg,
+            &mut OneByteReader::new(DATA),
+            DATA.len(),
+        ));
+        assert_eq!(res, Err(DecodeError::SizeLimit));
+        // Fields before the failing one are already merged
+        assert_eq!(msg.val, 150);
+
+        // Reader error
+        let mut reader = OneByteReader::new(DATA);
+        reader.fail_at = Some(4);
+        let (res, _) = block_on(decode_async::<_, _, 8>(
+            &mut Nested::default(),
+            &mut reader,
+            DATA.len(),
+        ));
+        assert_eq!(
+            res,
+            Err(DecodeError::Reader(embedded_io_async::ErrorKind::Other))
+        );
+
+        // EOF in the middle of a field
+        let (res, _) = block_on(decode_async::<_, _, 8>(
+            &mut Nested::default(),
+            &mut OneByteReader::new(&DATA[..6]),
+            DATA.len(),
+        ));
+        assert_eq!(res, Err(DecodeError::UnexpectedEof));
+
+        // Field crossing the end of the message
+        let (res, _) = block_on(decode_async::<_, _, 8>(
+            &mut Nested::default(),
+            &mut OneByteReader::new(DATA),
+            4,
+        ));
+        assert_eq!(res, Err(DecodeError::WrongLen));
+
+        // Groups
+        let group = [Tag::from_parts(1, WIRE_TYPE_SGROUP).varint() as u8, 0x0C];
+        let (res, _) = block_on(decode_async::<_, _, 8>(
+            &mut Nested::default(),
+            &mut OneByteReader::new(&group),
+            group.len(),
+        ));
+        assert_eq!(res, Err(DecodeError::Deprecation));
+    }
+}
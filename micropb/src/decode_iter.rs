@@ -0,0 +1,386 @@
+use crate::{
+    DecodeError, MessageDecode, Never, PbDecoder, PbRead, WIRE_TYPE_I32, WIRE_TYPE_I64,
+    WIRE_TYPE_LEN, WIRE_TYPE_SGROUP, WIRE_TYPE_VARINT,
+};
+
+#[derive(Debug, Clone)]
+/// Adapter that implements [`PbRead`] for iterators of bytes.
+///
+/// The source can either be an iterator of `u8` or an iterator of `Result<u8, E>` for fallible
+/// sources such as decompressors. Errors from fallible iterators are returned as
+/// [`DecodeError::Reader`]. Iterator exhaustion is treated as EOF.
+///
+/// Bytes are pulled from the iterator one at a time, so this reader is slower than slice-based
+/// readers.
+///
+/// # Example
+///
+/// ```
+/// use micropb::{IterReader, PbDecoder};
+///
+/// let mut decoder = PbDecoder::new(IterReader::new([0x96, 0x01].into_iter()));
+/// assert_eq!(decoder.decode_varint32(), Ok(150));
+///
+/// let mut decoder = PbDecoder::new(IterReader::new([Ok(0x96), Err("bad")].into_iter()));
+/// assert_eq!(decoder.decode_varint32(), Err(micropb::DecodeError::Reader("bad")));
+/// ```
+pub struct IterReader<I> {
+    iter: I,
+    byte: u8,
+    filled: bool,
+}
+
+impl<I: Iterator> IterReader<I> {
+    /// Construct an adapter from an iterator.
+    pub fn new(iter: I) -> Self {
+        Self {
+            iter,
+            byte: 0,
+            filled: false,
+        }
+    }
+
+    /// Transform the adapter into the underlying iterator.
+    ///
+    /// If a byte was pulled from the iterator but not consumed by the decoder, it will be lost.
+    pub fn into_inner(self) -> I {
+        self.iter
+    }
+
+    fn chunk(&self) -> &[u8] {
+        if self.filled {
+            core::slice::from_ref(&self.byte)
+        } else {
+            &[]
+        }
+    }
+}
+
+/// Item types that can be read by [`IterReader`].
+pub trait IterReaderItem {
+    /// Error returned by the iterator.
+    type Error;
+
+    /// Convert the item into a byte.
+    fn into_byte(self) -> Result<u8, Self::Error>;
+}
+
+impl IterReaderItem for u8 {
+    type Error = Never;
+
+    #[inline]
+    fn into_byte(self) -> Result<u8, Self::Error> {
+        Ok(self)
+    }
+}
+
+impl<E> IterReaderItem for Result<u8, E> {
+    type Error = E;
+
+    #[inline]
+    fn into_byte(self) -> Result<u8, Self::Error> {
+        self
+    }
+}
+
+impl<I: Iterator> PbRead for IterReader<I>
+where
+    I::Item: IterReaderItem,
+{
+    type Error = <I::Item as IterReaderItem>::Error;
+
+    #[inline]
+    fn pb_read_chunk(&mut self) -> Result<&[u8], Self::Error> {
+        if !self.filled {
+            if let Some(item) = self.iter.next() {
+                self.byte = item.into_byte()?;
+                self.filled = true;
+            }
+        }
+        Ok(self.chunk())
+    }
+
+    #[inline]
+    fn pb_advance(&mut self, bytes: usize) {
+        if bytes > 0 {
+            self.filled = false;
+        }
+    }
+}
+
+/// Reader over a single top-level field, whose header was already pulled from the iterator.
+struct FieldReader<'a, I> {
+    /// Tag of the field, followed by its length prefix or varint value
+    header: &'a [u8],
+    reader: &'a mut IterReader<I>,
+    /// Bytes of the field left in the iterator after the header
+    rest: usize,
+}
+
+impl<I: Iterator> PbRead for FieldReader<'_, I>
+where
+    I::Item: IterReaderItem,
+{
+    type Error = <I::Item as IterReaderItem>::Error;
+
+    #[inline]
+    fn pb_read_chunk(&mut self) -> Result<&[u8], Self::Error> {
+        if !self.header.is_empty() {
+            Ok(self.header)
+        } else if self.rest == 0 {
+            Ok(&[])
+        } else {
+            self.reader.pb_read_chunk()
+        }
+    }
+
+    #[inline]
+    fn pb_advance(&mut self, bytes: usize) {
+        if !self.header.is_empty() {
+            self.header = self.header.get(bytes..).unwrap_or(&[]);
+        } else {
+            self.reader.pb_advance(bytes);
+            self.rest = self.rest.saturating_sub(bytes);
+        }
+    }
+}
+
+fn field_decoder<'a, I: Iterator>(
+    header: &'a [u8],
+    reader: &'a mut IterReader<I>,
+    rest: usize,
+) -> PbDecoder<FieldReader<'a, I>>
+where
+    I::Item: IterReaderItem,
+{
+    PbDecoder::new(FieldReader {
+        header,
+        reader,
+        rest,
+    })
+}
+
+/// Tag of a field, followed by its length prefix or varint value, pulled from the iterator
+struct FieldHeader {
+    buf: [u8; 20],
+    len: usize,
+}
+
+impl FieldHeader {
+    /// Append a varint from the reader to the header, returning the position of its first byte
+    fn read_varint<I: Iterator>(
+        &mut self,
+        reader: &mut IterReader<I>,
+    ) -> Result<usize, DecodeError<<I::Item as IterReaderItem>::Error>>
+    where
+        I::Item: IterReaderItem,
+    {
+        let start = self.len;
+        for _ in 0..10 {
+            let byte = *reader
+                .pb_read_chunk()
+                .map_err(DecodeError::Reader)?
+                .first()
+                .ok_or(DecodeError::UnexpectedEof)?;
+            reader.pb_advance(1);
+            self.buf[self.len] = byte;
+            self.len += 1;
+            if byte & 0x80 == 0 {
+                return Ok(start);
+            }
+        }
+        Err(DecodeError::VarIntLimit)
+    }
+}
+
+/// Decode a message from an iterator of bytes and merge it into `msg`, reading until the iterator
+/// is exhausted.
+///
+/// The source can either be an iterator of `u8` or an iterator of `Result<u8, E>`, as with
+/// [`IterReader`]. Since the length of the message isn't known upfront, the message is decoded one
+/// top-level field at a time: the tag and length prefix of each field are pulled from the iterator
+/// first, then the rest of the field is merged into `msg` with the regular
+/// [`MessageDecode::decode`]. Only the header of a field is buffered, so nothing is allocated
+/// beyond what the message's own fields need.
+///
+/// If the iterator ends in the middle of a field, decoding fails with
+/// [`DecodeError::UnexpectedEof`]. Groups fail with [`DecodeError::Deprecation`] because their
+/// length isn't known upfront. Since the input isn't contiguous, `bytes` fields decoded as ranges
+/// into the input fail with [`DecodeError::NonContiguous`].
+///
+/// This requires the `iter-reader` feature.
+///
+/// # Example
+/// ```no_run
+/// # fn load<M: micropb::MessageDecode + Default>(
+/// #     data: impl Iterator<Item = u8>,
+/// # ) -> Result<M, micropb::DecodeError<micropb::Never>> {
+/// let mut msg = M::default();
+/// micropb::decode_from_iter(&mut msg, data)?;
+/// # Ok(msg)
+/// # }
+/// ```
+pub fn decode_from_iter<M, I>(
+    msg: &mut M,
+    iter: I,
+) -> Result<(), DecodeError<<I::Item as IterReaderItem>::Error>>
+where
+    M: MessageDecode + ?Sized,
+    I: IntoIterator,
+    I::Item: IterReaderItem,
+{
+    let mut reader = IterReader::new(iter.into_iter());
+    while !reader
+        .pb_read_chunk()
+        .map_err(DecodeError::Reader)?
+        .is_empty()
+    {
+        let mut header = FieldHeader {
+            buf: [0; 20],
+            len: 0,
+        };
+        header.read_varint(&mut reader)?;
+        let tag = field_decoder(&header.buf[..header.len], &mut reader, 0).decode_tag()?;
+        let rest = match tag.wire_type() {
+            WIRE_TYPE_VARINT => {
+                header.read_varint(&mut reader)?;
+                0
+            }
+            WIRE_TYPE_I64 => 8,
+            WIRE_TYPE_I32 => 4,
+            WIRE_TYPE_LEN => {
+                let start = header.read_varint(&mut reader)?;
+                field_decoder(&header.buf[start..header.len], &mut reader, 0).decode_len()?
+            }
+            WIRE_TYPE_SGROUP => return Err(DecodeError::Deprecation),
+            // Let the decoder report stray end-group tags and unknown wire types
+            _ => 0,
+        };
+        let len = header.len.checked_add(rest).ok_or(DecodeError::SizeLimit)?;
+        msg.decode(
+            &mut field_decoder(&header.buf[..header.len], &mut reader, rest),
+            len,
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use arrayvec::ArrayString;
+
+    use crate::Presence;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    enum MockErr {
+        WouldBlock,
+    }
+
+    #[test]
+    fn iter_reader() {
+        let data = [0xAC, 0x02, 0x01, 0x02, 0x03, 0x04, 3, b'a', b'b', b'c'];
+        let mut decoder = PbDecoder::new(IterReader::new(data.iter().copied()));
+        assert_eq!(decoder.decode_varint32(), Ok(300));
+        assert_eq!(decoder.decode_fixed32(), Ok(0x04030201));
+        let mut string = ArrayString::<5>::new();
+        assert_eq!(
+            decoder.decode_string(&mut string, Presence::Explicit),
+            Ok(())
+        );
+        assert_eq!(string.as_str(), "abc");
+        assert_eq!(decoder.decode_varint32(), Err(DecodeError::UnexpectedEof));
+
+        // Iterator ends in the middle of a value
+        let mut decoder = PbDecoder::new(IterReader::new([0xAC].into_iter()));
+        assert_eq!(decoder.decode_varint32(), Err(DecodeError::UnexpectedEof));
+        let mut decoder = PbDecoder::new(IterReader::new([2, b'a'].into_iter()));
+        assert_eq!(
+            decoder.decode_string(&mut string, Presence::Explicit),
+            Err(DecodeError::UnexpectedEof)
+        );
+
+        // Fallible iterator
+        let mut decoder = PbDecoder::new(IterReader::new(
+            [Ok(0xAC), Ok(0x02), Ok(0x80), Err(MockErr::WouldBlock)].into_iter(),
+        ));
+        assert_eq!(decoder.decode_varint32(), Ok(300));
+        assert_eq!(
+            decoder.decode_varint32(),
+            Err(DecodeError::Reader(MockErr::WouldBlock))
+        );
+    }
+
+    /// Message with a `uint32` field 1 and a `string` field 2
+    #[derive(Debug, Default)]
+    struct Msg {
+        num: u32,
+        name: ArrayString<8>,
+        fields: usize,
+    }
+
+    impl MessageDecode for Msg {
+        fn decode<R: PbRead>(
+            &mut self,
+            decoder: &mut PbDecoder<R>,
+            len: usize,
+        ) -> Result<(), DecodeError<R::Error>> {
+            let before = decoder.bytes_read();
+            while decoder.bytes_read() - before < len {
+                let tag = decoder.decode_tag()?;
+                match tag.field_num() {
+                    1 => self.num = decoder.decode_varint32()?,
+                    2 => decoder.decode_string(&mut self.name, Presence::Explicit)?,
+                    _ => decoder.skip_wire_value(tag.wire_type())?,
+                }
+                self.fields += 1;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn decode_iter() {
+        // Field 1 = 300, field 2 = "abc", and an unknown fixed32 field 3
+        let data = [
+            0x08, 0xAC, 0x02, 0x12, 3, b'a', b'b', b'c', 0x1D, 1, 2, 3, 4,
+        ];
+        let mut msg = Msg::default();
+        assert_eq!(decode_from_iter(&mut msg, data), Ok(()));
+        assert_eq!(msg.num, 300);
+        assert_eq!(msg.name.as_str(), "abc");
+        // Each field is decoded in its own call
+        assert_eq!(msg.fields, 3);
+
+        // Empty input
+        let mut msg = Msg::default();
+        assert_eq!(decode_from_iter(&mut msg, [0u8; 0]), Ok(()));
+        assert_eq!(msg.fields, 0);
+
+        // Iterator ends in the middle of a tag, a varint, a length prefix, or a value
+        for end in [1, 2, 4, 6, 10] {
+            let mut msg = Msg::default();
+            assert_eq!(
+                decode_from_iter(&mut msg, data[..end].iter().copied()),
+                Err(DecodeError::UnexpectedEof),
+                "{end}"
+            );
+        }
+
+        // Groups
+        let mut msg = Msg::default();
+        assert_eq!(
+            decode_from_iter(&mut msg, [0x0B, 0x0C]),
+            Err(DecodeError::Deprecation)
+        );
+
+        // Fallible iterator
+        let mut msg = Msg::default();
+        assert_eq!(
+            decode_from_iter(&mut msg, [Ok(0x08), Ok(1), Err(MockErr::WouldBlock)]),
+            Err(DecodeError::Reader(MockErr::WouldBlock))
+        );
+        assert_eq!(msg.num, 1);
+    }
+}
@@ -0,0 +1,1209 @@
+//! Messages whose schema is only known at runtime.
+//!
+//! [`DescriptorPool`] holds the schemas of message types, usually parsed from an encoded
+//! `google.protobuf.FileDescriptorSet`, such as one produced by `protoc -o` or reported by a
+//! device. [`DynamicMessage`] decodes any of those message types into a map of field numbers to
+//! [`DynamicValue`]s, and encodes them back, using the same [`PbDecoder`] and [`PbEncoder`]
+//! primitives as generated code.
+//!
+//! Repeated fields are decoded into [`DynamicValue::List`], accepting both packed and unpacked
+//! forms. `map` fields are lists of entry messages with the key in field 1 and the value in field
+//! 2, in the order they appear on the wire. Fields missing from the schema, as well as fields
+//! whose message types are missing from the pool, are kept as raw bytes and written back as-is
+//! when encoding. Groups are always treated as unknown fields.
+//!
+//! This module is meant for host-side tools rather than embedded targets, so it allocates freely
+//! and isn't optimized for speed. It requires the `dynamic` feature, which enables `std`.
+//!
+//! # Example
+//!
+//! ```
+//! use micropb::dynamic::{DescriptorPool, DynamicMessage, DynamicValue, FieldKind, FieldSchema, MessageSchema};
+//! use micropb::FieldLabel;
+//!
+//! let mut pool = DescriptorPool::new();
+//! pool.add_message(MessageSchema::new(
+//!     "pkg.Reading",
+//!     vec![
+//!         FieldSchema::new(1, "sensor", FieldKind::String, FieldLabel::Single),
+//!         FieldSchema::new(2, "samples", FieldKind::Sint32, FieldLabel::Repeated { packed: true }),
+//!     ],
+//! ));
+//!
+//! let msg = DynamicMessage::decode(&pool, "pkg.Reading", &[0x0A, 0x01, b'a', 0x12, 0x02, 0x01, 0x04])?;
+//! assert_eq!(msg.get(1).and_then(DynamicValue::as_str), Some("a"));
+//! assert_eq!(
+//!     msg.get(2),
+//!     Some(&DynamicValue::List(vec![DynamicValue::I32(-1), DynamicValue::I32(2)]))
+//! );
+//! assert_eq!(msg.encode(&pool)?, [0x0A, 0x01, b'a', 0x12, 0x02, 0x01, 0x04]);
+//! # Ok::<(), micropb::dynamic::DynamicError>(())
+//! ```
+
+use core::fmt::{self, Display};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::{
+    DecodeError, EncodeError, FieldLabel, Never, PbDecoder, PbEncoder, Presence, Tag,
+    WIRE_TYPE_I32, WIRE_TYPE_I64, WIRE_TYPE_LEN, WIRE_TYPE_VARINT,
+};
+
+/// Protobuf type of a field in a [`FieldSchema`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FieldKind {
+    /// `double`
+    Double,
+    /// `float`
+    Float,
+    /// `int32`
+    Int32,
+    /// `int64`
+    Int64,
+    /// `uint32`
+    Uint32,
+    /// `uint64`
+    Uint64,
+    /// `sint32`
+    Sint32,
+    /// `sint64`
+    Sint64,
+    /// `fixed32`
+    Fixed32,
+    /// `fixed64`
+    Fixed64,
+    /// `sfixed32`
+    Sfixed32,
+    /// `sfixed64`
+    Sfixed64,
+    /// `bool`
+    Bool,
+    /// `string`
+    String,
+    /// `bytes`
+    Bytes,
+    /// Any enum, whose values are kept as numbers
+    Enum,
+    /// Message with the given fully-qualified name, without the leading dot
+    Message(String),
+}
+
+impl FieldKind {
+    /// Wire type of a single value of this type.
+    pub fn wire_type(&self) -> u8 {
+        match self {
+            FieldKind::Double | FieldKind::Fixed64 | FieldKind::Sfixed64 => WIRE_TYPE_I64,
+            FieldKind::Float | FieldKind::Fixed32 | FieldKind::Sfixed32 => WIRE_TYPE_I32,
+            FieldKind::Int32
+            | FieldKind::Int64
+            | FieldKind::Uint32
+            | FieldKind::Uint64
+            | FieldKind::Sint32
+            | FieldKind::Sint64
+            | FieldKind::Bool
+            | FieldKind::Enum => WIRE_TYPE_VARINT,
+            FieldKind::String | FieldKind::Bytes | FieldKind::Message(_) => WIRE_TYPE_LEN,
+        }
+    }
+
+    /// Whether repeated fields of this type can be packed
+    fn is_packable(&self) -> bool {
+        self.wire_type() != WIRE_TYPE_LEN
+    }
+}
+
+/// Runtime description of a message field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldSchema {
+    /// Field number
+    pub num: u32,
+    /// Protobuf name of the field
+    pub name: String,
+    /// Type of the field's values
+    pub kind: FieldKind,
+    /// Shape of the field. Packing only affects encoding, since both forms are accepted when
+    /// decoding.
+    pub label: FieldLabel,
+    /// Index of the oneof containing the field, for fields labelled [`FieldLabel::Oneof`].
+    /// Setting one field of a oneof clears the others with the same index.
+    pub oneof_index: Option<u32>,
+}
+
+impl FieldSchema {
+    /// Create a field that isn't part of a oneof.
+    pub fn new(num: u32, name: impl Into<String>, kind: FieldKind, label: FieldLabel) -> Self {
+        Self {
+            num,
+            name: name.into(),
+            kind,
+            label,
+            oneof_index: None,
+        }
+    }
+
+    /// Create a member of the oneof with the given index.
+    pub fn oneof(num: u32, name: impl Into<String>, kind: FieldKind, oneof_index: u32) -> Self {
+        Self {
+            num,
+            name: name.into(),
+            kind,
+            label: FieldLabel::Oneof,
+            oneof_index: Some(oneof_index),
+        }
+    }
+}
+
+/// Runtime description of a message type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageSchema {
+    /// Fully-qualified Protobuf name of the message, without the leading dot
+    pub full_name: String,
+    /// Fields of the message
+    pub fields: Vec<FieldSchema>,
+}
+
+impl MessageSchema {
+    /// Create a message schema.
+    pub fn new(full_name: impl Into<String>, fields: Vec<FieldSchema>) -> Self {
+        Self {
+            full_name: full_name.into(),
+            fields,
+        }
+    }
+
+    /// Look up a field by number.
+    pub fn field(&self, num: u32) -> Option<&FieldSchema> {
+        self.fields.iter().find(|f| f.num == num)
+    }
+
+    /// Look up a field by its Protobuf name.
+    pub fn field_by_name(&self, name: &str) -> Option<&FieldSchema> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+}
+
+/// Collection of message schemas, which lets nested message fields refer to other messages by
+/// name.
+#[derive(Debug, Clone, Default)]
+pub struct DescriptorPool {
+    messages: HashMap<String, MessageSchema>,
+}
+
+impl DescriptorPool {
+    /// Create an empty pool.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a message schema to the pool, replacing any schema with the same name.
+    pub fn add_message(&mut self, schema: MessageSchema) {
+        self.messages.insert(schema.full_name.clone(), schema);
+    }
+
+    /// Look up a message schema by its fully-qualified name, without the leading dot.
+    pub fn message(&self, full_name: &str) -> Option<&MessageSchema> {
+        self.messages.get(full_name)
+    }
+
+    /// Iterate over the message schemas in the pool.
+    pub fn messages(&self) -> impl Iterator<Item = &MessageSchema> {
+        self.messages.values()
+    }
+
+    /// Parse an encoded `google.protobuf.FileDescriptorSet` into a pool containing every message
+    /// declared in it, including nested messages.
+    ///
+    /// Repeated scalar fields are packed by default in `proto3` and editions files, and unpacked
+    /// in `proto2` files, unless the `packed` option says otherwise. Fields of `proto3` files
+    /// without `optional` have [`FieldLabel::Single`], while all other singular fields have
+    /// [`FieldLabel::Optional`]. Group fields are left out.
+    pub fn from_file_descriptor_set(bytes: &[u8]) -> Result<Self, DynamicError> {
+        let bootstrap = descriptor_pool();
+        let set = DynamicMessage::decode(&bootstrap, "google.protobuf.FileDescriptorSet", bytes)?;
+
+        let mut pool = Self::new();
+        let mut map_entries = BTreeSet::new();
+        for file in set.get_list(1) {
+            let Some(file) = file.as_message() else {
+                continue;
+            };
+            let prefix = file.get(2).and_then(DynamicValue::as_str).unwrap_or("");
+            let syntax = file.get(12).and_then(DynamicValue::as_str).unwrap_or("");
+            let packed_default = !matches!(syntax, "" | "proto2");
+            for msg in file.get_list(4) {
+                if let Some(msg) = msg.as_message() {
+                    pool.add_descriptor(msg, prefix, packed_default, &mut map_entries);
+                }
+            }
+        }
+
+        // Map fields are repeated fields of map entry messages, which are only known once all
+        // messages have been added
+        for msg in pool.messages.values_mut() {
+            for field in &mut msg.fields {
+                if let (FieldKind::Message(name), FieldLabel::Repeated { .. }) =
+                    (&field.kind, field.label)
+                {
+                    if map_entries.contains(name) {
+                        field.label = FieldLabel::Map;
+                    }
+                }
+            }
+        }
+        Ok(pool)
+    }
+
+    /// Add a `DescriptorProto` and its nested messages
+    fn add_descriptor(
+        &mut self,
+        desc: &DynamicMessage,
+        prefix: &str,
+        packed_default: bool,
+        map_entries: &mut BTreeSet<String>,
+    ) {
+        let name = desc.get(1).and_then(DynamicValue::as_str).unwrap_or("");
+        let full_name = if prefix.is_empty() {
+            name.to_owned()
+        } else {
+            format!("{prefix}.{name}")
+        };
+
+        let is_map_entry = desc
+            .get(7)
+            .and_then(DynamicValue::as_message)
+            .and_then(|opts| opts.get(7))
+            .and_then(DynamicValue::as_bool)
+            .unwrap_or(false);
+        if is_map_entry {
+            map_entries.insert(full_name.clone());
+        }
+
+        let fields = desc
+            .get_list(2)
+            .iter()
+            .filter_map(DynamicValue::as_message)
+            .filter_map(|f| field_from_descriptor(f, packed_default))
+            .collect();
+        for nested in desc.get_list(3) {
+            if let Some(nested) = nested.as_message() {
+                self.add_descriptor(nested, &full_name, packed_default, map_entries);
+            }
+        }
+        self.add_message(MessageSchema::new(full_name, fields));
+    }
+}
+
+/// Convert a `FieldDescriptorProto` into a field schema. Returns `None` for groups and fields
+/// with unknown types.
+fn field_from_descriptor(desc: &DynamicMessage, packed_default: bool) -> Option<FieldSchema> {
+    let get_i32 = |num| desc.get(num).and_then(DynamicValue::as_i32);
+    let kind = match desc.get(5).and_then(DynamicValue::as_enum)? {
+        1 => FieldKind::Double,
+        2 => FieldKind::Float,
+        3 => FieldKind::Int64,
+        4 => FieldKind::Uint64,
+        5 => FieldKind::Int32,
+        6 => FieldKind::Fixed64,
+        7 => FieldKind::Fixed32,
+        8 => FieldKind::Bool,
+        9 => FieldKind::String,
+        11 => {
+            let type_name = desc.get(6).and_then(DynamicValue::as_str)?;
+            FieldKind::Message(type_name.trim_start_matches('.').to_owned())
+        }
+        12 => FieldKind::Bytes,
+        13 => FieldKind::Uint32,
+        14 => FieldKind::Enum,
+        15 => FieldKind::Sfixed32,
+        16 => FieldKind::Sfixed64,
+        17 => FieldKind::Sint32,
+        18 => FieldKind::Sint64,
+        _ => return None,
+    };
+    let num = u32::try_from(get_i32(3)?).ok()?;
+    let name = desc.get(1).and_then(DynamicValue::as_str).unwrap_or("");
+    let proto3_optional = desc.get(17).and_then(DynamicValue::as_bool) == Some(true);
+    let oneof_index = get_i32(9).and_then(|i| u32::try_from(i).ok());
+
+    let label = match desc.get(4).and_then(DynamicValue::as_enum) {
+        Some(3) => {
+            let packed = desc
+                .get(8)
+                .and_then(DynamicValue::as_message)
+                .and_then(|opts| opts.get(2))
+                .and_then(DynamicValue::as_bool)
+                .unwrap_or(packed_default);
+            FieldLabel::Repeated {
+                packed: packed && kind.is_packable(),
+            }
+        }
+        _ if proto3_optional => FieldLabel::Optional,
+        _ if oneof_index.is_some() => FieldLabel::Oneof,
+        // Singular fields without explicit presence only exist in proto3 and editions files,
+        // except for message fields
+        _ if packed_default && !matches!(kind, FieldKind::Message(_)) => FieldLabel::Single,
+        _ => FieldLabel::Optional,
+    };
+    Some(FieldSchema {
+        num,
+        name: name.to_owned(),
+        kind,
+        label,
+        oneof_index: (label == FieldLabel::Oneof)
+            .then_some(oneof_index)
+            .flatten(),
+    })
+}
+
+/// Schemas of the parts of `descriptor.proto` needed to parse a `FileDescriptorSet`
+fn descriptor_pool() -> DescriptorPool {
+    use FieldKind::*;
+    let single = FieldLabel::Optional;
+    let repeated = FieldLabel::Repeated { packed: false };
+    let message = |name: &str| Message(format!("google.protobuf.{name}"));
+
+    let mut pool = DescriptorPool::new();
+    pool.add_message(MessageSchema::new(
+        "google.protobuf.FileDescriptorSet",
+        vec![FieldSchema::new(
+            1,
+            "file",
+            message("FileDescriptorProto"),
+            repeated,
+        )],
+    ));
+    pool.add_message(MessageSchema::new(
+        "google.protobuf.FileDescriptorProto",
+        vec![
+            FieldSchema::new(1, "name", String, single),
+            FieldSchema::new(2, "package", String, single),
+            FieldSchema::new(4, "message_type", message("DescriptorProto"), repeated),
+            FieldSchema::new(12, "syntax", String, single),
+        ],
+    ));
+    pool.add_message(MessageSchema::new(
+        "google.protobuf.DescriptorProto",
+        vec![
+            FieldSchema::new(1, "name", String, single),
+            FieldSchema::new(2, "field", message("FieldDescriptorProto"), repeated),
+            FieldSchema::new(3, "nested_type", message("DescriptorProto"), repeated),
+            FieldSchema::new(7, "options", message("MessageOptions"), single),
+        ],
+    ));
+    pool.add_message(MessageSchema::new(
+        "google.protobuf.MessageOptions",
+        vec![FieldSchema::new(7, "map_entry", Bool, single)],
+    ));
+    pool.add_message(MessageSchema::new(
+        "google.protobuf.FieldDescriptorProto",
+        vec![
+            FieldSchema::new(1, "name", String, single),
+            FieldSchema::new(3, "number", Int32, single),
+            FieldSchema::new(4, "label", Enum, single),
+            FieldSchema::new(5, "type", Enum, single),
+            FieldSchema::new(6, "type_name", String, single),
+            FieldSchema::new(8, "options", message("FieldOptions"), single),
+            FieldSchema::new(9, "oneof_index", Int32, single),
+            FieldSchema::new(17, "proto3_optional", Bool, single),
+        ],
+    ));
+    pool.add_message(MessageSchema::new(
+        "google.protobuf.FieldOptions",
+        vec![FieldSchema::new(2, "packed", Bool, single)],
+    ));
+    pool
+}
+
+/// Dynamically-typed value of a field.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DynamicValue {
+    /// `bool`
+    Bool(bool),
+    /// `int32`, `sint32`, or `sfixed32`
+    I32(i32),
+    /// `int64`, `sint64`, or `sfixed64`
+    I64(i64),
+    /// `uint32` or `fixed32`
+    U32(u32),
+    /// `uint64` or `fixed64`
+    U64(u64),
+    /// `float`
+    F32(f32),
+    /// `double`
+    F64(f64),
+    /// `string`
+    String(String),
+    /// `bytes`
+    Bytes(Vec<u8>),
+    /// Enum value, which may not be declared in the enum
+    Enum(i32),
+    /// Nested message
+    Message(DynamicMessage),
+    /// Elements of a repeated or `map` field
+    List(Vec<DynamicValue>),
+}
+
+impl DynamicValue {
+    /// Return the value if it's a `bool`.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            DynamicValue::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// Return the value if it's a 32-bit signed integer.
+    pub fn as_i32(&self) -> Option<i32> {
+        match self {
+            DynamicValue::I32(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Return the value if it's a 64-bit signed integer.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            DynamicValue::I64(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Return the value if it's a 32-bit unsigned integer.
+    pub fn as_u32(&self) -> Option<u32> {
+        match self {
+            DynamicValue::U32(u) => Some(*u),
+            _ => None,
+        }
+    }
+
+    /// Return the value if it's a 64-bit unsigned integer.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            DynamicValue::U64(u) => Some(*u),
+            _ => None,
+        }
+    }
+
+    /// Return the value if it's a `float`.
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            DynamicValue::F32(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    /// Return the value if it's a `double`.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            DynamicValue::F64(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    /// Return the value if it's a `string`.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            DynamicValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Return the value if it's a `bytes`.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            DynamicValue::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// Return the value if it's an enum.
+    pub fn as_enum(&self) -> Option<i32> {
+        match self {
+            DynamicValue::Enum(e) => Some(*e),
+            _ => None,
+        }
+    }
+
+    /// Return the value if it's a message.
+    pub fn as_message(&self) -> Option<&DynamicMessage> {
+        match self {
+            DynamicValue::Message(m) => Some(m),
+            _ => None,
+        }
+    }
+
+    /// Return the elements if the value is a list.
+    pub fn as_list(&self) -> Option<&[DynamicValue]> {
+        match self {
+            DynamicValue::List(l) => Some(l),
+            _ => None,
+        }
+    }
+}
+
+/// Message decoded according to a runtime schema from a [`DescriptorPool`].
+///
+/// Fields are stored by number. Fields that aren't set aren't stored, so implicit-presence fields
+/// set to their default values are only present if they appeared on the wire.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DynamicMessage {
+    full_name: String,
+    fields: BTreeMap<u32, DynamicValue>,
+    unknown: Vec<u8>,
+}
+
+impl DynamicMessage {
+    /// Create an empty message of the type with the given fully-qualified name.
+    pub fn new(full_name: impl Into<String>) -> Self {
+        Self {
+            full_name: full_name.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Fully-qualified name of the message type, without the leading dot
+    pub fn full_name(&self) -> &str {
+        &self.full_name
+    }
+
+    /// Get the value of a field.
+    pub fn get(&self, num: u32) -> Option<&DynamicValue> {
+        self.fields.get(&num)
+    }
+
+    /// Get a mutable reference to the value of a field.
+    pub fn get_mut(&mut self, num: u32) -> Option<&mut DynamicValue> {
+        self.fields.get_mut(&num)
+    }
+
+    /// Get the elements of a repeated or `map` field, which is empty if the field isn't set.
+    pub fn get_list(&self, num: u32) -> &[DynamicValue] {
+        self.get(num).and_then(DynamicValue::as_list).unwrap_or(&[])
+    }
+
+    /// Set the value of a field. Repeated and `map` fields take [`DynamicValue::List`].
+    ///
+    /// The value is only checked against the schema when encoding.
+    pub fn set(&mut self, num: u32, value: DynamicValue) {
+        self.fields.insert(num, value);
+    }
+
+    /// Clear a field, returning its value if it was set.
+    pub fn clear(&mut self, num: u32) -> Option<DynamicValue> {
+        self.fields.remove(&num)
+    }
+
+    /// Iterate over the fields that are set, in order of field number.
+    pub fn fields(&self) -> impl Iterator<Item = (u32, &DynamicValue)> {
+        self.fields.iter().map(|(num, val)| (*num, val))
+    }
+
+    /// Encoded fields that weren't recognized when decoding, in the order they appeared.
+    pub fn unknown_fields(&self) -> &[u8] {
+        &self.unknown
+    }
+
+    /// Decode a message of type `full_name` from `bytes`.
+    pub fn decode(
+        pool: &DescriptorPool,
+        full_name: &str,
+        bytes: &[u8],
+    ) -> Result<Self, DynamicError> {
+        let mut msg = Self::new(full_name);
+        msg.merge(pool, bytes)?;
+        Ok(msg)
+    }
+
+    /// Decode `bytes` and merge them into this message, following the same merge semantics as
+    /// [`MessageDecode::decode`](crate::MessageDecode::decode).
+    pub fn merge(&mut self, pool: &DescriptorPool, bytes: &[u8]) -> Result<(), DynamicError> {
+        let schema = pool
+            .message(&self.full_name)
+            .ok_or_else(|| DynamicError::UnknownMessage(self.full_name.clone()))?;
+        let ctx = DecodeCtx { pool, input: bytes };
+        let mut decoder = PbDecoder::new(bytes);
+        ctx.decode_fields(self, schema, &mut decoder, bytes.len())
+            .map_err(DynamicError::Decode)
+    }
+
+    /// Encode the message into a new buffer.
+    ///
+    /// Returns [`DynamicError::TypeMismatch`] if a value doesn't match the type of its field in
+    /// the schema, and [`DynamicError::UnknownField`] if the schema doesn't have the field at all.
+    pub fn encode(&self, pool: &DescriptorPool) -> Result<Vec<u8>, DynamicError> {
+        let mut encoder = PbEncoder::new(Vec::new());
+        self.encode_fields(pool, &mut encoder)?;
+        Ok(encoder.into_writer())
+    }
+
+    fn encode_fields(
+        &self,
+        pool: &DescriptorPool,
+        encoder: &mut PbEncoder<Vec<u8>>,
+    ) -> Result<(), DynamicError> {
+        let schema = pool
+            .message(&self.full_name)
+            .ok_or_else(|| DynamicError::UnknownMessage(self.full_name.clone()))?;
+        for (&num, val) in &self.fields {
+            let field = schema
+                .field(num)
+                .ok_or_else(|| DynamicError::UnknownField {
+                    message: self.full_name.clone(),
+                    field: num,
+                })?;
+            let wire_type = field.kind.wire_type();
+            match (field.label, val) {
+                (FieldLabel::Repeated { packed: true }, DynamicValue::List(elems))
+                    if field.kind.is_packable() =>
+                {
+                    if elems.is_empty() {
+                        continue;
+                    }
+                    let mut packed = PbEncoder::new(Vec::new());
+                    for elem in elems {
+                        self.encode_value(pool, &mut packed, field, elem)?;
+                    }
+                    encoder.encode_tag(Tag::from_parts(num, WIRE_TYPE_LEN))?;
+                    encoder.encode_bytes(&packed.into_writer())?;
+                }
+                (FieldLabel::Repeated { .. } | FieldLabel::Map, DynamicValue::List(elems)) => {
+                    for elem in elems {
+                        encoder.encode_tag(Tag::from_parts(num, wire_type))?;
+                        self.encode_value(pool, encoder, field, elem)?;
+                    }
+                }
+                (FieldLabel::Repeated { .. } | FieldLabel::Map, _) => {
+                    return Err(self.type_mismatch(num))
+                }
+                (_, val) => {
+                    encoder.encode_tag(Tag::from_parts(num, wire_type))?;
+                    self.encode_value(pool, encoder, field, val)?;
+                }
+            }
+        }
+        encoder.write(&self.unknown)?;
+        Ok(())
+    }
+
+    /// Encode a single value of `field` without its tag
+    fn encode_value(
+        &self,
+        pool: &DescriptorPool,
+        encoder: &mut PbEncoder<Vec<u8>>,
+        field: &FieldSchema,
+        val: &DynamicValue,
+    ) -> Result<(), DynamicError> {
+        match (&field.kind, val) {
+            (FieldKind::Double, DynamicValue::F64(f)) => encoder.encode_double(*f)?,
+            (FieldKind::Float, DynamicValue::F32(f)) => encoder.encode_float(*f)?,
+            (FieldKind::Int32, DynamicValue::I32(i)) => encoder.encode_int32(*i)?,
+            (FieldKind::Int64, DynamicValue::I64(i)) => encoder.encode_int64(*i)?,
+            (FieldKind::Uint32, DynamicValue::U32(u)) => encoder.encode_varint32(*u)?,
+            (FieldKind::Uint64, DynamicValue::U64(u)) => encoder.encode_varint64(*u)?,
+            (FieldKind::Sint32, DynamicValue::I32(i)) => encoder.encode_sint32(*i)?,
+            (FieldKind::Sint64, DynamicValue::I64(i)) => encoder.encode_sint64(*i)?,
+            (FieldKind::Fixed32, DynamicValue::U32(u)) => encoder.encode_fixed32(*u)?,
+            (FieldKind::Fixed64, DynamicValue::U64(u)) => encoder.encode_fixed64(*u)?,
+            (FieldKind::Sfixed32, DynamicValue::I32(i)) => encoder.encode_sfixed32(*i)?,
+            (FieldKind::Sfixed64, DynamicValue::I64(i)) => encoder.encode_sfixed64(*i)?,
+            (FieldKind::Bool, DynamicValue::Bool(b)) => encoder.encode_bool(*b)?,
+            (FieldKind::String, DynamicValue::String(s)) => encoder.encode_string(s)?,
+            (FieldKind::Bytes, DynamicValue::Bytes(b)) => encoder.encode_bytes(b)?,
+            (FieldKind::Enum, DynamicValue::Enum(e)) => encoder.encode_int32(*e)?,
+            (FieldKind::Message(name), DynamicValue::Message(msg)) if &msg.full_name == name => {
+                let mut nested = PbEncoder::new(Vec::new());
+                msg.encode_fields(pool, &mut nested)?;
+                encoder.encode_bytes(&nested.into_writer())?;
+            }
+            _ => return Err(self.type_mismatch(field.num)),
+        }
+        Ok(())
+    }
+
+    fn type_mismatch(&self, field: u32) -> DynamicError {
+        DynamicError::TypeMismatch {
+            message: self.full_name.clone(),
+            field,
+        }
+    }
+}
+
+/// State shared while decoding a message and its nested messages
+struct DecodeCtx<'a> {
+    pool: &'a DescriptorPool,
+    /// Entire input, used to copy out unknown fields
+    input: &'a [u8],
+}
+
+impl DecodeCtx<'_> {
+    fn decode_fields(
+        &self,
+        msg: &mut DynamicMessage,
+        schema: &MessageSchema,
+        decoder: &mut PbDecoder<&[u8]>,
+        len: usize,
+    ) -> Result<(), DecodeError<Never>> {
+        let before = decoder.bytes_read();
+        while decoder.bytes_read() - before < len {
+            let start = decoder.bytes_read();
+            let tag = decoder.decode_tag()?;
+            let num = tag.field_num();
+            if num == 0 {
+                return Err(DecodeError::ZeroField);
+            }
+            // Fields of message types missing from the pool are treated as unknown
+            let field = schema.field(num).filter(|f| match &f.kind {
+                FieldKind::Message(name) => self.pool.message(name).is_some(),
+                _ => true,
+            });
+            let Some(field) = field else {
+                decoder.skip_unknown_field(tag)?;
+                msg.unknown
+                    .extend_from_slice(&self.input[start..decoder.bytes_read()]);
+                continue;
+            };
+
+            let wire_type = field.kind.wire_type();
+            match field.label {
+                FieldLabel::Repeated { .. } | FieldLabel::Map => {
+                    let mut list = match msg.fields.remove(&num) {
+                        Some(DynamicValue::List(list)) => list,
+                        _ => vec![],
+                    };
+                    if tag.wire_type() == WIRE_TYPE_LEN && field.kind.is_packable() {
+                        decoder.decode_len_record(|len, before, decoder| {
+                            while decoder.bytes_read() - before < len {
+                                list.push(self.decode_value(&field.kind, decoder, None)?);
+                            }
+                            Ok(())
+                        })?;
+                    } else if tag.wire_type() == wire_type {
+                        list.push(self.decode_value(&field.kind, decoder, None)?);
+                    } else {
+                        return Err(DecodeError::WrongWireType);
+                    }
+                    msg.fields.insert(num, DynamicValue::List(list));
+                }
+
+                _ => {
+                    if tag.wire_type() != wire_type {
+                        return Err(DecodeError::WrongWireType);
+                    }
+                    if let Some(index) = field.oneof_index {
+                        // Setting a oneof member clears the other members
+                        for other in &schema.fields {
+                            if other.oneof_index == Some(index) && other.num != num {
+                                msg.fields.remove(&other.num);
+                            }
+                        }
+                    }
+                    // Singular message fields are merged into the existing message
+                    let existing = match msg.fields.remove(&num) {
+                        Some(DynamicValue::Message(m)) => Some(m),
+                        _ => None,
+                    };
+                    let val = self.decode_value(&field.kind, decoder, existing)?;
+                    msg.fields.insert(num, val);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Decode a single value of type `kind`. Message values are merged into `existing` if given.
+    fn decode_value(
+        &self,
+        kind: &FieldKind,
+        decoder: &mut PbDecoder<&[u8]>,
+        existing: Option<DynamicMessage>,
+    ) -> Result<DynamicValue, DecodeError<Never>> {
+        Ok(match kind {
+            FieldKind::Double => DynamicValue::F64(decoder.decode_double()?),
+            FieldKind::Float => DynamicValue::F32(decoder.decode_float()?),
+            FieldKind::Int32 => DynamicValue::I32(decoder.decode_int32()?),
+            FieldKind::Int64 => DynamicValue::I64(decoder.decode_int64()?),
+            FieldKind::Uint32 => DynamicValue::U32(decoder.decode_varint32()?),
+            FieldKind::Uint64 => DynamicValue::U64(decoder.decode_varint64()?),
+            FieldKind::Sint32 => DynamicValue::I32(decoder.decode_sint32()?),
+            FieldKind::Sint64 => DynamicValue::I64(decoder.decode_sint64()?),
+            FieldKind::Fixed32 => DynamicValue::U32(decoder.decode_fixed32()?),
+            FieldKind::Fixed64 => DynamicValue::U64(decoder.decode_fixed64()?),
+            FieldKind::Sfixed32 => DynamicValue::I32(decoder.decode_sfixed32()?),
+            FieldKind::Sfixed64 => DynamicValue::I64(decoder.decode_sfixed64()?),
+            FieldKind::Bool => DynamicValue::Bool(decoder.decode_bool()?),
+            FieldKind::Enum => DynamicValue::Enum(decoder.decode_int32()?),
+            FieldKind::String => {
+                let mut s = String::new();
+                decoder.decode_string(&mut s, Presence::Explicit)?;
+                DynamicValue::String(s)
+            }
+            FieldKind::Bytes => {
+                let mut b = Vec::new();
+                decoder.decode_bytes(&mut b, Presence::Explicit)?;
+                DynamicValue::Bytes(b)
+            }
+            FieldKind::Message(name) => {
+                // Callers have already checked that the schema exists
+                let Some(schema) = self.pool.message(name) else {
+                    return Err(DecodeError::WrongWireType);
+                };
+                let mut msg = existing.unwrap_or_else(|| DynamicMessage::new(name.clone()));
+                decoder.decode_len_delimited(|decoder, len| {
+                    self.decode_fields(&mut msg, schema, decoder, len)
+                })?;
+                DynamicValue::Message(msg)
+            }
+        })
+    }
+}
+
+/// Error from decoding or encoding a [`DynamicMessage`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DynamicError {
+    /// Message type isn't in the descriptor pool
+    UnknownMessage(String),
+    /// Field being encoded isn't in the message's schema
+    UnknownField {
+        /// Fully-qualified name of the message
+        message: String,
+        /// Field number
+        field: u32,
+    },
+    /// Value being encoded doesn't match the type of its field
+    TypeMismatch {
+        /// Fully-qualified name of the message
+        message: String,
+        /// Field number
+        field: u32,
+    },
+    /// Error from decoding the input
+    Decode(DecodeError<Never>),
+    /// Encoded message is too large
+    TooLarge,
+}
+
+impl From<EncodeError<Never>> for DynamicError {
+    fn from(err: EncodeError<Never>) -> Self {
+        match err {
+            EncodeError::TooLarge => Self::TooLarge,
+            EncodeError::Writer(never) => match never {},
+        }
+    }
+}
+
+impl Display for DynamicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DynamicError::UnknownMessage(name) => write!(f, "unknown message type `{name}`"),
+            DynamicError::UnknownField { message, field } => {
+                write!(f, "field {field} is not in the schema of `{message}`")
+            }
+            DynamicError::TypeMismatch { message, field } => {
+                write!(
+                    f,
+                    "value of field {field} of `{message}` has the wrong type"
+                )
+            }
+            DynamicError::Decode(e) => e.fmt(f),
+            DynamicError::TooLarge => EncodeError::<Never>::TooLarge.fmt(f),
+        }
+    }
+}
+
+impl crate::ErrorTrait for DynamicError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool() -> DescriptorPool {
+        let mut pool = DescriptorPool::new();
+        pool.add_message(MessageSchema::new(
+            "test.Scalars",
+            vec![
+                FieldSchema::new(1, "i32", FieldKind::Int32, FieldLabel::Single),
+                FieldSchema::new(2, "s64", FieldKind::Sint64, FieldLabel::Single),
+                FieldSchema::new(3, "f32", FieldKind::Fixed32, FieldLabel::Optional),
+                FieldSchema::new(4, "dbl", FieldKind::Double, FieldLabel::Single),
+                FieldSchema::new(5, "flag", FieldKind::Bool, FieldLabel::Single),
+                FieldSchema::new(6, "text", FieldKind::String, FieldLabel::Single),
+                FieldSchema::new(7, "raw", FieldKind::Bytes, FieldLabel::Single),
+                FieldSchema::new(8, "kind", FieldKind::Enum, FieldLabel::Single),
+            ],
+        ));
+        pool.add_message(MessageSchema::new(
+            "test.Outer",
+            vec![
+                FieldSchema::new(
+                    1,
+                    "inner",
+                    FieldKind::Message("test.Scalars".to_owned()),
+                    FieldLabel::Optional,
+                ),
+                FieldSchema::new(
+                    2,
+                    "packed",
+                    FieldKind::Uint32,
+                    FieldLabel::Repeated { packed: true },
+                ),
+                FieldSchema::new(
+                    3,
+                    "unpacked",
+                    FieldKind::Sint32,
+                    FieldLabel::Repeated { packed: false },
+                ),
+                FieldSchema::oneof(4, "num", FieldKind::Uint64, 0),
+                FieldSchema::oneof(5, "msg", FieldKind::Message("test.Scalars".to_owned()), 0),
+                FieldSchema::new(
+                    6,
+                    "missing",
+                    FieldKind::Message("test.Missing".to_owned()),
+                    FieldLabel::Optional,
+                ),
+            ],
+        ));
+        pool
+    }
+
+    #[test]
+    fn scalars() {
+        let pool = pool();
+        let bytes = [
+            0x08, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, // i32 = -1
+            0x10, 0x03, // s64 = -2
+            0x1D, 0x01, 0x00, 0x00, 0x00, // f32 = 1
+            0x21, 0, 0, 0, 0, 0, 0, 0xF0, 0x3F, // dbl = 1.0
+            0x28, 0x01, // flag = true
+            0x32, 0x02, b'h', b'i', // text = "hi"
+            0x3A, 0x01, 0xAA, // raw = [0xAA]
+            0x40, 0x05, // kind = 5
+        ];
+        let msg = DynamicMessage::decode(&pool, "test.Scalars", &bytes).unwrap();
+        assert_eq!(msg.full_name(), "test.Scalars");
+        assert_eq!(
+            msg.fields().collect::<Vec<_>>(),
+            [
+                (1, &DynamicValue::I32(-1)),
+                (2, &DynamicValue::I64(-2)),
+                (3, &DynamicValue::U32(1)),
+                (4, &DynamicValue::F64(1.0)),
+                (5, &DynamicValue::Bool(true)),
+                (6, &DynamicValue::String("hi".to_owned())),
+                (7, &DynamicValue::Bytes(vec![0xAA])),
+                (8, &DynamicValue::Enum(5)),
+            ]
+        );
+        assert_eq!(msg.encode(&pool).unwrap(), bytes);
+    }
+
+    #[test]
+    fn repeated() {
+        let pool = pool();
+        // Both fields are sent in the opposite form to how they're declared
+        let bytes = [0x10, 0x01, 0x10, 0x02, 0x1A, 0x02, 0x01, 0x02];
+        let msg = DynamicMessage::decode(&pool, "test.Outer", &bytes).unwrap();
+        assert_eq!(
+            msg.get_list(2),
+            [DynamicValue::U32(1), DynamicValue::U32(2)]
+        );
+        assert_eq!(
+            msg.get_list(3),
+            [DynamicValue::I32(-1), DynamicValue::I32(1)]
+        );
+        assert_eq!(
+            msg.encode(&pool).unwrap(),
+            [0x12, 0x02, 0x01, 0x02, 0x18, 0x01, 0x18, 0x02]
+        );
+
+        // Empty packed fields aren't written
+        let mut msg = DynamicMessage::new("test.Outer");
+        msg.set(2, DynamicValue::List(vec![]));
+        assert_eq!(msg.encode(&pool).unwrap(), []);
+    }
+
+    #[test]
+    fn merge() {
+        let pool = pool();
+        // Nested message is sent in two parts, which are merged
+        let bytes = [0x0A, 0x02, 0x08, 0x01, 0x0A, 0x02, 0x28, 0x01];
+        let msg = DynamicMessage::decode(&pool, "test.Outer", &bytes).unwrap();
+        let inner = msg.get(1).and_then(DynamicValue::as_message).unwrap();
+        assert_eq!(inner.get(1), Some(&DynamicValue::I32(1)));
+        assert_eq!(inner.get(5), Some(&DynamicValue::Bool(true)));
+
+        // Setting one oneof member clears the other
+        let mut msg = DynamicMessage::decode(&pool, "test.Outer", &[0x20, 0x07]).unwrap();
+        assert_eq!(msg.get(4), Some(&DynamicValue::U64(7)));
+        msg.merge(&pool, &[0x2A, 0x00]).unwrap();
+        assert_eq!(msg.get(4), None);
+        assert_eq!(
+            msg.get(5),
+            Some(&DynamicValue::Message(DynamicMessage::new("test.Scalars")))
+        );
+    }
+
+    #[test]
+    fn unknown_fields() {
+        let pool = pool();
+        // Field 9 isn't in the schema, and the type of field 6 isn't in the pool
+        let bytes = [0x48, 0x01, 0x32, 0x02, 0x08, 0x01, 0x20, 0x03];
+        let msg = DynamicMessage::decode(&pool, "test.Outer", &bytes).unwrap();
+        assert_eq!(msg.fields().count(), 1);
+        assert_eq!(msg.unknown_fields(), &bytes[..6]);
+        // Unknown fields are written after the known ones
+        assert_eq!(
+            msg.encode(&pool).unwrap(),
+            [0x20, 0x03, 0x48, 0x01, 0x32, 0x02, 0x08, 0x01]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let pool = pool();
+        assert_eq!(
+            DynamicMessage::decode(&pool, "test.Other", &[]),
+            Err(DynamicError::UnknownMessage("test.Other".to_owned()))
+        );
+        assert_eq!(
+            DynamicMessage::decode(&pool, "test.Scalars", &[0x0D, 0, 0, 0, 0]),
+            Err(DynamicError::Decode(DecodeError::WrongWireType))
+        );
+        assert_eq!(
+            DynamicMessage::decode(&pool, "test.Scalars", &[0x32, 0x01, 0xFF]),
+            Err(DynamicError::Decode(DecodeError::Utf8))
+        );
+        assert_eq!(
+            DynamicMessage::decode(&pool, "test.Outer", &[0x0A, 0x05, 0x08]),
+            Err(DynamicError::Decode(DecodeError::UnexpectedEof))
+        );
+
+        let mut msg = DynamicMessage::new("test.Outer");
+        msg.set(2, DynamicValue::U32(1));
+        assert_eq!(
+            msg.encode(&pool),
+            Err(DynamicError::TypeMismatch {
+                message: "test.Outer".to_owned(),
+                field: 2
+            })
+        );
+        msg.set(2, DynamicValue::List(vec![DynamicValue::I32(1)]));
+        assert!(msg.encode(&pool).is_err());
+        msg.set(
+            2,
+            DynamicValue::List(vec![DynamicValue::Message(DynamicMessage::new(
+                "test.Outer",
+            ))]),
+        );
+        assert!(msg.encode(&pool).is_err());
+
+        let mut msg = DynamicMessage::new("test.Outer");
+        msg.set(10, DynamicValue::U32(1));
+        assert_eq!(
+            msg.encode(&pool).unwrap_err().to_string(),
+            "field 10 is not in the schema of `test.Outer`"
+        );
+    }
+
+    /// Build a `FieldDescriptorProto`
+    fn field_desc(
+        name: &str,
+        num: i32,
+        label: i32,
+        typ: i32,
+        type_name: Option<&str>,
+    ) -> DynamicValue {
+        let mut field = DynamicMessage::new("google.protobuf.FieldDescriptorProto");
+        field.set(1, DynamicValue::String(name.to_owned()));
+        field.set(3, DynamicValue::I32(num));
+        field.set(4, DynamicValue::Enum(label));
+        field.set(5, DynamicValue::Enum(typ));
+        if let Some(type_name) = type_name {
+            field.set(6, DynamicValue::String(type_name.to_owned()));
+        }
+        DynamicValue::Message(field)
+    }
+
+    #[test]
+    fn file_descriptor_set() {
+        let bootstrap = descriptor_pool();
+
+        let mut entry = DynamicMessage::new("google.protobuf.DescriptorProto");
+        entry.set(1, DynamicValue::String("CountsEntry".to_owned()));
+        entry.set(
+            2,
+            DynamicValue::List(vec![
+                field_desc("key", 1, 1, 9, None),
+                field_desc("value", 2, 1, 13, None),
+            ]),
+        );
+        let mut opts = DynamicMessage::new("google.protobuf.MessageOptions");
+        opts.set(7, DynamicValue::Bool(true));
+        entry.set(7, DynamicValue::Message(opts));
+
+        let mut oneof_field = field_desc("id", 4, 1, 4, None)
+            .as_message()
+            .unwrap()
+            .clone();
+        oneof_field.set(9, DynamicValue::I32(0));
+        let mut optional_field = field_desc("seq", 5, 1, 5, None)
+            .as_message()
+            .unwrap()
+            .clone();
+        optional_field.set(9, DynamicValue::I32(1));
+        optional_field.set(17, DynamicValue::Bool(true));
+
+        let mut msg = DynamicMessage::new("google.protobuf.DescriptorProto");
+        msg.set(1, DynamicValue::String("Device".to_owned()));
+        msg.set(
+            2,
+            DynamicValue::List(vec![
+                field_desc("name", 1, 1, 9, None),
+                field_desc("samples", 2, 3, 17, None),
+                field_desc("counts", 3, 3, 11, Some(".pkg.Device.CountsEntry")),
+                DynamicValue::Message(oneof_field),
+                DynamicValue::Message(optional_field),
+                field_desc("group", 6, 1, 10, None),
+            ]),
+        );
+        msg.set(3, DynamicValue::List(vec![DynamicValue::Message(entry)]));
+
+        let mut file = DynamicMessage::new("google.protobuf.FileDescriptorProto");
+        file.set(1, DynamicValue::String("device.proto".to_owned()));
+        file.set(2, DynamicValue::String("pkg".to_owned()));
+        file.set(4, DynamicValue::List(vec![DynamicValue::Message(msg)]));
+        file.set(12, DynamicValue::String("proto3".to_owned()));
+        let mut set = DynamicMessage::new("google.protobuf.FileDescriptorSet");
+        set.set(1, DynamicValue::List(vec![DynamicValue::Message(file)]));
+
+        let bytes = set.encode(&bootstrap).unwrap();
+        let pool = DescriptorPool::from_file_descriptor_set(&bytes).unwrap();
+        assert_eq!(pool.messages().count(), 2);
+        assert_eq!(
+            pool.message("pkg.Device").unwrap().fields,
+            [
+                FieldSchema::new(1, "name", FieldKind::String, FieldLabel::Single),
+                FieldSchema::new(
+                    2,
+                    "samples",
+                    FieldKind::Sint32,
+                    FieldLabel::Repeated { packed: true }
+                ),
+                FieldSchema::new(
+                    3,
+                    "counts",
+                    FieldKind::Message("pkg.Device.CountsEntry".to_owned()),
+                    FieldLabel::Map
+                ),
+                FieldSchema::oneof(4, "id", FieldKind::Uint64, 0),
+                FieldSchema::new(5, "seq", FieldKind::Int32, FieldLabel::Optional),
+            ]
+        );
+        assert_eq!(
+            pool.message("pkg.Device.CountsEntry")
+                .unwrap()
+                .field_by_name("value")
+                .unwrap()
+                .kind,
+            FieldKind::Uint32
+        );
+    }
+}
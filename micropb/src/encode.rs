@@ -1,4 +1,9 @@
-use crate::{MessageEncode, Tag, VarInt};
+use core::{
+    fmt::{self, Display},
+    mem::MaybeUninit,
+};
+
+use crate::{size::MAX_LEN, FixedSize, MessageEncode, PbVec, Tag, VarInt};
 
 /// A writer to which Protobuf data is written, similar to [`std::io::Write`].
 ///
@@ -14,18 +19,69 @@ pub trait PbWrite {
     ///
     /// This is analogous to [`std::io::Write::write_all`].
     fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Prepare for at least `additional` more bytes to be written.
+    ///
+    /// The encoder calls this before writing a message or length-delimited record whose size is
+    /// known up front. Growable writers can reserve capacity to avoid repeated reallocations,
+    /// while fixed-capacity writers can fail before any part of the record is written, rather
+    /// than leaving it half-written. Does nothing by default.
+    #[inline]
+    fn pb_reserve(&mut self, _additional: usize) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Reserve space for the length prefix of a length-delimited record, which is filled in by
+    /// [`pb_patch_len`](Self::pb_patch_len) after the contents of the record are written.
+    ///
+    /// Returns a handle to the reserved space, or `None` if the writer doesn't support filling
+    /// in length prefixes afterwards, in which case the encoder computes the length of the
+    /// record up front instead. Only writers that can modify bytes after writing them, such as
+    /// [`BackpatchWriter`], support this.
+    #[inline]
+    fn pb_reserve_len(&mut self) -> Result<Option<usize>, Self::Error> {
+        Ok(None)
+    }
+
+    /// Fill in the length prefix reserved by [`pb_reserve_len`](Self::pb_reserve_len) with the
+    /// number of bytes written since the reservation.
+    #[inline]
+    fn pb_patch_len(&mut self, _slot: usize) -> Result<(), Self::Error> {
+        Ok(())
+    }
 }
 
-impl<W: PbWrite> PbWrite for &mut W {
+impl<W: PbWrite + ?Sized> PbWrite for &mut W {
     type Error = W::Error;
 
     #[inline]
     fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
         (*self).pb_write(data)
     }
+
+    #[inline]
+    fn pb_reserve(&mut self, additional: usize) -> Result<(), Self::Error> {
+        (*self).pb_reserve(additional)
+    }
+
+    #[inline]
+    fn pb_reserve_len(&mut self) -> Result<Option<usize>, Self::Error> {
+        (*self).pb_reserve_len()
+    }
+
+    #[inline]
+    fn pb_patch_len(&mut self, slot: usize) -> Result<(), Self::Error> {
+        (*self).pb_patch_len(slot)
+    }
 }
 
 #[cfg(feature = "container-arrayvec")]
+/// Appends the written bytes to the vector.
+///
+/// Writes that don't fit into the remaining capacity fail with [`arrayvec::CapacityError`]
+/// without writing anything, leaving the bytes from previous writes in place. To avoid writing
+/// part of a message that doesn't fit, encode it with [`PbEncoder::encode_message`], which checks
+/// the size of the message against the remaining capacity first.
 impl<const N: usize> PbWrite for arrayvec::ArrayVec<u8, N> {
     type Error = arrayvec::CapacityError;
 
@@ -33,6 +89,14 @@ impl<const N: usize> PbWrite for arrayvec::ArrayVec<u8, N> {
     fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
         self.try_extend_from_slice(data)
     }
+
+    #[inline]
+    fn pb_reserve(&mut self, additional: usize) -> Result<(), Self::Error> {
+        if additional > self.remaining_capacity() {
+            return Err(arrayvec::CapacityError::new(()));
+        }
+        Ok(())
+    }
 }
 
 #[cfg(feature = "container-heapless")]
@@ -43,23 +107,45 @@ impl<const N: usize> PbWrite for heapless::Vec<u8, N> {
     fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
         self.extend_from_slice(data)
     }
+
+    #[inline]
+    fn pb_reserve(&mut self, additional: usize) -> Result<(), Self::Error> {
+        if additional > self.capacity() - self.len() {
+            return Err(());
+        }
+        Ok(())
+    }
 }
 
 #[cfg(feature = "alloc")]
 impl PbWrite for alloc::vec::Vec<u8> {
-    type Error = never::Never;
+    type Error = crate::Never;
 
     #[inline]
     fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
         self.extend_from_slice(data);
         Ok(())
     }
+
+    #[inline]
+    fn pb_reserve(&mut self, additional: usize) -> Result<(), Self::Error> {
+        self.reserve(additional);
+        Ok(())
+    }
 }
 
 #[cfg(feature = "std")]
 #[derive(Debug, Clone)]
 /// Adapter that implements [`PbWrite`] for all implementers of [`std::io::Write`], allowing the
-/// encoder to write to `std` writers.
+/// encoder to write to `std` writers such as files and sockets.
+///
+/// Each write is performed with [`write_all`](std::io::Write::write_all), so short writes are
+/// retried until all bytes are written, as are writes that fail with
+/// [`std::io::ErrorKind::Interrupted`]. All other I/O errors are returned from the encoder as-is.
+///
+/// The encoder issues many small writes, so wrap unbuffered writers in a
+/// [`BufWriter`](std::io::BufWriter). The adapter never flushes the underlying writer, so call
+/// [`flush`](std::io::Write::flush) on it after encoding if needed.
 pub struct StdWriter<W>(pub W);
 
 #[cfg(feature = "std")]
@@ -72,6 +158,545 @@ impl<W: std::io::Write> PbWrite for StdWriter<W> {
     }
 }
 
+#[cfg(feature = "embedded-io")]
+#[derive(Debug, Clone)]
+/// Adapter that implements [`PbWrite`] for all implementers of [`embedded_io::Write`], allowing
+/// the encoder to write directly to drivers such as UARTs or flash storage.
+///
+/// Each write is performed with [`write_all`](embedded_io::Write::write_all), so short writes
+/// are retried until all bytes are written. Errors from the driver are returned from the encoder
+/// as-is. Note that `write_all` panics if the driver violates the `embedded-io` contract by
+/// accepting 0 bytes from a non-empty buffer.
+///
+/// Encoding doesn't require any staging buffer, since the lengths of nested messages are computed
+/// before they're written. The adapter never flushes the underlying writer, so call
+/// [`flush`](embedded_io::Write::flush) on it after encoding if needed.
+pub struct EmbeddedIoWriter<W>(pub W);
+
+#[cfg(feature = "embedded-io")]
+impl<W: embedded_io::Write> PbWrite for EmbeddedIoWriter<W> {
+    type Error = W::Error;
+
+    #[inline]
+    fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.0.write_all(data)
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Adapter that implements [`PbWrite`] on top of a `write` callback.
+///
+/// Every chunk of bytes produced by the encoder is passed straight to the callback without any
+/// additional buffering, so chunks are often as small as a single tag or length prefix. This
+/// allows the encoder to write into transports that only hand out transmit space through a
+/// callback, rather than a persistent writer object. Errors returned by the callback stop the
+/// encoder and are returned from it as-is.
+///
+/// # Example
+///
+/// ```
+/// use micropb::{CallbackWriter, PbEncoder};
+///
+/// let mut sent = Vec::new();
+/// let mut encoder = PbEncoder::new(CallbackWriter(|chunk: &[u8]| {
+///     sent.extend_from_slice(chunk);
+///     Ok::<_, ()>(())
+/// }));
+/// encoder.encode_varint32(150)?;
+/// encoder.encode_string("ab")?;
+/// assert_eq!(sent, [0x96, 0x01, 2, b'a', b'b']);
+/// # Ok::<(), micropb::EncodeError<()>>(())
+/// ```
+pub struct CallbackWriter<F>(pub F);
+
+impl<E, F: FnMut(&[u8]) -> Result<(), E>> PbWrite for CallbackWriter<F> {
+    type Error = E;
+
+    #[inline]
+    fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        (self.0)(data)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Error returned by [`SliceWriter`], [`UninitSliceWriter`], and [`BackpatchWriter`] when the
+/// slice is too small for the encoded data.
+pub struct BufferTooSmall {
+    /// Number of bytes written into the slice before the error
+    pub written: usize,
+    /// Minimum number of additional bytes needed to complete the write that failed.
+    ///
+    /// Since encoding stops at the first failed write, the message may need more bytes than this.
+    /// The exact amount can be computed from [`compute_size`](crate::MessageEncode::compute_size).
+    ///
+    /// If a record is too large to be encoded at all, this is `usize::MAX`.
+    pub needed: usize,
+    /// Number of bytes left in the slice when the write failed
+    pub available: usize,
+}
+
+impl BufferTooSmall {
+    /// Check that a write of `len` bytes fits into the `remaining` bytes after position `written`
+    #[inline]
+    fn check(written: usize, remaining: usize, len: usize) -> Result<(), Self> {
+        if len > remaining {
+            return Err(Self {
+                written,
+                needed: len - remaining,
+                available: remaining,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Display for BufferTooSmall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "buffer too small: wrote {} bytes, needed at least {} more than the {} available",
+            self.written, self.needed, self.available
+        )
+    }
+}
+
+#[cfg(any(feature = "std", feature = "core-error"))]
+impl crate::ErrorTrait for BufferTooSmall {}
+
+impl BufferTooSmall {
+    /// Error for records that are too large to be encoded at all, which don't fit into any slice.
+    /// Since the position of the writer isn't known, `written` and `available` are 0.
+    const TOO_LARGE: Self = Self {
+        written: 0,
+        needed: usize::MAX,
+        available: 0,
+    };
+}
+
+/// Slices can't fit records that are too large to be encoded, so
+/// [`TooLarge`](EncodeError::TooLarge) is reported with [`needed`](BufferTooSmall::needed) set to
+/// `usize::MAX`.
+impl From<EncodeError<BufferTooSmall>> for BufferTooSmall {
+    fn from(err: EncodeError<BufferTooSmall>) -> Self {
+        match err {
+            EncodeError::TooLarge => Self::TOO_LARGE,
+            EncodeError::Writer(err) => err,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Protobuf [encoder](PbEncoder) error.
+///
+/// The error is parametrized by the underlying writer's error type `E`.
+pub enum EncodeError<E> {
+    /// Length-delimited record, such as a nested message or a `bytes` field, is longer than the
+    /// 2 GiB limit of Protobuf, given by [`MAX_LEN`](crate::size::MAX_LEN).
+    ///
+    /// Sizes of messages saturate rather than overflow, so messages whose size doesn't fit into
+    /// `usize` are reported the same way. Nothing is written for the record.
+    TooLarge,
+    /// Error returned from writer
+    Writer(E),
+}
+
+impl<E: Display> Display for EncodeError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodeError::TooLarge => f.write_str("message too large to encode"),
+            EncodeError::Writer(e) => write!(f, "writer error: {e}"),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", feature = "core-error"))]
+impl<E: crate::ErrorTrait + 'static> crate::ErrorTrait for EncodeError<E> {
+    fn source(&self) -> Option<&(dyn crate::ErrorTrait + 'static)> {
+        match self {
+            EncodeError::Writer(e) => Some(e),
+            EncodeError::TooLarge => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+/// Records that are too large are reported as [`InvalidInput`](std::io::ErrorKind::InvalidInput).
+impl From<EncodeError<std::io::Error>> for std::io::Error {
+    fn from(err: EncodeError<std::io::Error>) -> Self {
+        match err {
+            EncodeError::TooLarge => std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                EncodeError::<crate::Never>::TooLarge,
+            ),
+            EncodeError::Writer(err) => err,
+        }
+    }
+}
+
+#[derive(Debug)]
+/// Writer that encodes into a mutable byte slice, such as a DMA buffer, and tracks the write
+/// position.
+///
+/// Writes that don't fit into the rest of the slice fail with [`BufferTooSmall`] without
+/// writing anything, leaving the bytes from previous writes in place.
+///
+/// # Example
+/// ```
+/// use micropb::{PbEncoder, SliceWriter};
+///
+/// let mut buf = [0; 8];
+/// let mut encoder = PbEncoder::new(SliceWriter::new(&mut buf));
+/// encoder.encode_varint32(150)?;
+/// encoder.encode_string("ab")?;
+/// assert_eq!(encoder.into_writer().into_written(), &[0x96, 0x01, 2, b'a', b'b']);
+/// # Ok::<(), micropb::BufferTooSmall>(())
+/// ```
+pub struct SliceWriter<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> SliceWriter<'a> {
+    /// Construct a writer that writes from the start of `buf`.
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// Number of bytes written so far.
+    pub fn written(&self) -> usize {
+        self.pos
+    }
+
+    /// Number of bytes left in the slice.
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    /// Get the bytes written so far.
+    pub fn as_written(&self) -> &[u8] {
+        &self.buf[..self.pos]
+    }
+
+    /// Transform the writer into the bytes written so far.
+    pub fn into_written(self) -> &'a mut [u8] {
+        &mut self.buf[..self.pos]
+    }
+
+    /// Move the write position back to the start of the slice, so it can be reused for another
+    /// message.
+    pub fn reset(&mut self) {
+        self.pos = 0;
+    }
+}
+
+impl PbWrite for SliceWriter<'_> {
+    type Error = BufferTooSmall;
+
+    #[inline]
+    fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        BufferTooSmall::check(self.pos, self.remaining(), data.len())?;
+        self.buf[self.pos..self.pos + data.len()].copy_from_slice(data);
+        self.pos += data.len();
+        Ok(())
+    }
+
+    #[inline]
+    fn pb_reserve(&mut self, additional: usize) -> Result<(), Self::Error> {
+        BufferTooSmall::check(self.pos, self.remaining(), additional)
+    }
+}
+
+#[derive(Debug)]
+/// Writer that encodes into a slice of uninitialized memory, such as a buffer handed out by a
+/// flash driver, without zeroing it first.
+///
+/// Behaves like [`SliceWriter`], except that the bytes before the write position are the only
+/// ones known to be initialized, so only those are ever exposed as `&[u8]`. Each byte is written
+/// before it's read, and a write that doesn't fit into the rest of the slice fails with
+/// [`BufferTooSmall`] without writing anything, so the initialized prefix never extends past the
+/// bytes that were actually written.
+///
+/// # Example
+/// ```
+/// use core::mem::MaybeUninit;
+/// use micropb::{PbEncoder, UninitSliceWriter};
+///
+/// let mut buf = [MaybeUninit::uninit(); 8];
+/// let mut encoder = PbEncoder::new(UninitSliceWriter::new(&mut buf));
+/// encoder.encode_varint32(150)?;
+/// assert_eq!(encoder.into_writer().into_written(), &[0x96, 0x01]);
+/// # Ok::<(), micropb::BufferTooSmall>(())
+/// ```
+pub struct UninitSliceWriter<'a> {
+    /// Invariant: `buf[..pos]` is initialized
+    buf: &'a mut [MaybeUninit<u8>],
+    pos: usize,
+}
+
+impl<'a> UninitSliceWriter<'a> {
+    /// Construct a writer that writes from the start of `buf`.
+    pub fn new(buf: &'a mut [MaybeUninit<u8>]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// Number of bytes written so far.
+    pub fn written(&self) -> usize {
+        self.pos
+    }
+
+    /// Number of bytes left in the slice.
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    /// Get the bytes written so far.
+    pub fn as_written(&self) -> &[u8] {
+        // SAFETY: `buf[..pos]` is initialized, and `MaybeUninit<u8>` has the same layout as `u8`
+        unsafe { core::slice::from_raw_parts(self.buf.as_ptr().cast(), self.pos) }
+    }
+
+    /// Transform the writer into the bytes written so far.
+    pub fn into_written(self) -> &'a mut [u8] {
+        // SAFETY: `buf[..pos]` is initialized, and `MaybeUninit<u8>` has the same layout as `u8`
+        unsafe { core::slice::from_raw_parts_mut(self.buf.as_mut_ptr().cast(), self.pos) }
+    }
+
+    /// Move the write position back to the start of the slice, so it can be reused for another
+    /// message.
+    ///
+    /// The bytes written previously are no longer considered initialized.
+    pub fn reset(&mut self) {
+        self.pos = 0;
+    }
+}
+
+impl PbWrite for UninitSliceWriter<'_> {
+    type Error = BufferTooSmall;
+
+    #[inline]
+    fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        BufferTooSmall::check(self.pos, self.remaining(), data.len())?;
+        for (dst, &byte) in self.buf[self.pos..self.pos + data.len()]
+            .iter_mut()
+            .zip(data)
+        {
+            dst.write(byte);
+        }
+        self.pos += data.len();
+        Ok(())
+    }
+
+    #[inline]
+    fn pb_reserve(&mut self, additional: usize) -> Result<(), Self::Error> {
+        BufferTooSmall::check(self.pos, self.remaining(), additional)
+    }
+}
+
+#[derive(Debug)]
+/// Writer that encodes into a mutable byte slice in a single pass, by filling in the length
+/// prefixes of nested messages after the messages are written.
+///
+/// By default, the encoder computes the size of each nested message before writing it, so
+/// deeply-nested messages are traversed once per level of nesting. Instead, this writer reserves
+/// space for each length prefix, writes the nested message, then goes back and fills in the
+/// length. There are two ways of filling in the length:
+///
+/// - [`padded`](Self::padded) reserves a fixed number of bytes for each length prefix. If the
+///   length takes fewer bytes, the varint is padded with continuation bytes to fill the space,
+///   which is valid Protobuf but makes the output larger than necessary.
+/// - [`exact`](Self::exact) reserves a single byte and moves the nested message forward if its
+///   length needs more bytes, producing the same output as the regular encoder.
+///
+/// In both modes, lengths that don't fit into the reserved space cause the nested message to be
+/// moved, so any message can be encoded. Writes that don't fit into the rest of the slice fail
+/// with [`BufferTooSmall`], leaving the bytes from previous writes in place. Since reserved
+/// length prefixes may not have been filled in yet, the written bytes aren't valid Protobuf after
+/// a failure.
+///
+/// The generated code still computes the sizes of map entries and packed fields up front.
+///
+/// # Example
+/// ```
+/// use micropb::{BackpatchWriter, MessageEncode, PbEncoder, PbWrite};
+///
+/// # struct Inner;
+/// # impl MessageEncode for Inner {
+/// #   fn encode<W: PbWrite>(&self, encoder: &mut PbEncoder<W>) -> Result<(), micropb::EncodeError<W::Error>> {
+/// #       encoder.encode_varint32(0x08)?;
+/// #       encoder.encode_varint32(150)
+/// #   }
+/// #   fn compute_size(&self) -> usize { 3 }
+/// # }
+/// let mut buf = [0; 16];
+/// let mut encoder = PbEncoder::new(BackpatchWriter::padded(&mut buf, 2));
+/// // Length prefixes take 2 bytes each
+/// Inner.encode_len_delimited(&mut encoder)?;
+/// assert_eq!(encoder.into_writer().into_written(), &[0x83, 0x00, 0x08, 0x96, 0x01]);
+/// # Ok::<(), micropb::BufferTooSmall>(())
+/// ```
+pub struct BackpatchWriter<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+    /// Number of bytes reserved for each length prefix
+    width: usize,
+    padded: bool,
+}
+
+impl<'a> BackpatchWriter<'a> {
+    /// Construct a writer that reserves `width` bytes for each length prefix and pads lengths
+    /// that take fewer bytes.
+    ///
+    /// Widths of 2 and 3 bytes fit lengths of up to 16 KiB and 2 MiB respectively.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `width` isn't between 1 and 5.
+    pub fn padded(buf: &'a mut [u8], width: usize) -> Self {
+        assert!(
+            (1..=5).contains(&width),
+            "length prefix width must be between 1 and 5"
+        );
+        Self {
+            buf,
+            pos: 0,
+            width,
+            padded: true,
+        }
+    }
+
+    /// Construct a writer that encodes each length prefix in as few bytes as possible, producing
+    /// the same output as [`SliceWriter`].
+    pub fn exact(buf: &'a mut [u8]) -> Self {
+        Self {
+            buf,
+            pos: 0,
+            width: 1,
+            padded: false,
+        }
+    }
+
+    /// Number of bytes written so far.
+    pub fn written(&self) -> usize {
+        self.pos
+    }
+
+    /// Number of bytes left in the slice.
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    /// Get the bytes written so far.
+    pub fn as_written(&self) -> &[u8] {
+        &self.buf[..self.pos]
+    }
+
+    /// Transform the writer into the bytes written so far.
+    pub fn into_written(self) -> &'a mut [u8] {
+        &mut self.buf[..self.pos]
+    }
+
+    /// Move the write position back to the start of the slice, so it can be reused for another
+    /// message.
+    pub fn reset(&mut self) {
+        self.pos = 0;
+    }
+
+    fn check_remaining(&self, len: usize) -> Result<(), BufferTooSmall> {
+        BufferTooSmall::check(self.pos, self.remaining(), len)
+    }
+}
+
+impl PbWrite for BackpatchWriter<'_> {
+    type Error = BufferTooSmall;
+
+    #[inline]
+    fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.check_remaining(data.len())?;
+        self.buf[self.pos..self.pos + data.len()].copy_from_slice(data);
+        self.pos += data.len();
+        Ok(())
+    }
+
+    #[inline]
+    fn pb_reserve(&mut self, additional: usize) -> Result<(), Self::Error> {
+        self.check_remaining(additional)
+    }
+
+    fn pb_reserve_len(&mut self) -> Result<Option<usize>, Self::Error> {
+        self.check_remaining(self.width)?;
+        let slot = self.pos;
+        self.pos += self.width;
+        Ok(Some(slot))
+    }
+
+    fn pb_patch_len(&mut self, slot: usize) -> Result<(), Self::Error> {
+        let start = slot + self.width;
+        let len = self.pos - start;
+        if len > MAX_LEN {
+            return Err(BufferTooSmall::TOO_LARGE);
+        }
+        let len_size = crate::size::sizeof_varint32(len as u32);
+
+        if self.padded && len_size <= self.width {
+            // All bytes except the last have the continuation bit set
+            let mut varint = len as u32;
+            for b in &mut self.buf[slot..start - 1] {
+                *b = varint as u8 | 0x80;
+                varint >>= 7;
+            }
+            self.buf[start - 1] = varint as u8;
+            return Ok(());
+        }
+
+        // Move the contents so that the length prefix takes exactly as many bytes as it needs
+        let new_start = slot + len_size;
+        if new_start > start {
+            self.check_remaining(new_start - start)?;
+        }
+        self.buf.copy_within(start..self.pos, new_start);
+        self.pos = new_start + len;
+        PbEncoder::new(SliceWriter::new(&mut self.buf[slot..new_start]))
+            .encode_varint32(len as u32)
+            .map_err(Into::into)
+    }
+}
+
+#[derive(Debug)]
+/// Adapter that implements [`PbWrite`] for all [`PbVec<u8>`](crate::PbVec<u8>) containers by
+/// appending the written bytes to the container.
+///
+/// Writes fail if the container doesn't have enough capacity for the data.
+pub struct PbVecWriter<'a, V>(pub &'a mut V);
+
+impl<V: PbVec<u8>> PbWrite for PbVecWriter<'_, V> {
+    type Error = ();
+
+    #[inline]
+    fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        let len = self.0.len();
+        self.0.pb_reserve(data.len());
+        let spare_cap = self.0.pb_spare_cap();
+        if spare_cap.len() < data.len() {
+            return Err(());
+        }
+        for (dst, src) in spare_cap.iter_mut().zip(data) {
+            dst.write(*src);
+        }
+        // SAFETY: the bytes after the existing contents have just been initialized from `data`
+        unsafe { self.0.pb_set_len(len + data.len()) };
+        Ok(())
+    }
+
+    #[inline]
+    fn pb_reserve(&mut self, additional: usize) -> Result<(), Self::Error> {
+        self.0.pb_reserve(additional);
+        if self.0.pb_spare_cap().len() < additional {
+            return Err(());
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 /// Encoder that serializes Rust types into Protobuf messages and values.
 ///
@@ -88,7 +713,7 @@ impl<W: std::io::Write> PbWrite for StdWriter<W> {
 /// # #[derive(Default)]
 /// # struct ProtoMessage(u32);
 /// # impl micropb::MessageEncode for ProtoMessage {
-/// #   fn encode<W: PbWrite>(&self, encoder: &mut PbEncoder<W>) -> Result<(), W::Error> { todo!() }
+/// #   fn encode<W: PbWrite>(&self, encoder: &mut PbEncoder<W>) -> Result<(), micropb::EncodeError<W::Error>> { todo!() }
 /// #   fn compute_size(&self) -> usize { 0 }
 /// # }
 ///
@@ -99,14 +724,16 @@ impl<W: std::io::Write> PbWrite for StdWriter<W> {
 /// // allowing the encoder to write into it. Same applies to `container-arrayvec` and `alloc`.
 /// let mut encoder = PbEncoder::new(Vec::<u8, 10>::new());
 /// message.encode(&mut encoder)?;
-/// # Ok::<(), ()>(())
+/// # Ok::<(), micropb::EncodeError<()>>(())
 /// ```
 ///
 /// # Reducing Code Size
 ///
 /// To prevent multiple monomorphizations and increased code size, make sure you instantiate
 /// `PbEncoder` with only one writer type across the whole application. If multiple writers need to
-/// be supported, wrap them in an enum or use a trait object.
+/// be supported, wrap them in an enum or use a trait object. Alternatively, the code generator's
+/// `dyn_encode` option makes generated messages encode through [`DynPbWrite`], so only a thin
+/// wrapper is instantiated for each writer type.
 pub struct PbEncoder<W: PbWrite> {
     writer: W,
 }
@@ -131,13 +758,12 @@ impl<W: PbWrite> PbEncoder<W> {
     }
 
     #[inline]
-    fn write(&mut self, bytes: &[u8]) -> Result<(), W::Error> {
-        self.writer.pb_write(bytes)?;
-        Ok(())
+    pub(crate) fn write(&mut self, bytes: &[u8]) -> Result<(), EncodeError<W::Error>> {
+        self.writer.pb_write(bytes).map_err(EncodeError::Writer)
     }
 
     #[inline]
-    fn encode_byte(&mut self, b: u8) -> Result<(), W::Error> {
+    fn encode_byte(&mut self, b: u8) -> Result<(), EncodeError<W::Error>> {
         self.write(&[b])
     }
 
@@ -145,10 +771,16 @@ impl<W: PbWrite> PbEncoder<W> {
         &mut self,
         mut varint: U,
         negative_int32: bool,
-    ) -> Result<(), W::Error> {
+    ) -> Result<(), EncodeError<W::Error>> {
+        // Fast paths for 1-byte and 2-byte varints, which cover tags and most small values
         if varint <= From::from(0x7F) {
             return self.encode_byte(varint.as_());
         }
+        if (varint >> 14).is_zero() {
+            let lo: u8 = varint.as_();
+            self.encode_byte(lo | 0x80)?;
+            return self.encode_byte((varint >> 7).as_());
+        }
 
         while {
             let mut b = varint.as_() & 0x7F;
@@ -176,66 +808,66 @@ impl<W: PbWrite> PbEncoder<W> {
 
     #[inline]
     /// Encode an `uint32`.
-    pub fn encode_varint32(&mut self, u: u32) -> Result<(), W::Error> {
+    pub fn encode_varint32(&mut self, u: u32) -> Result<(), EncodeError<W::Error>> {
         self.encode_varint(u, false)
     }
 
     #[inline]
     #[cfg(feature = "enable-64bit")]
     /// Encode an `uint64`.
-    pub fn encode_varint64(&mut self, u: u64) -> Result<(), W::Error> {
+    pub fn encode_varint64(&mut self, u: u64) -> Result<(), EncodeError<W::Error>> {
         self.encode_varint(u, false)
     }
 
     #[inline]
     /// Encode an `int32`.
-    pub fn encode_int32(&mut self, i: i32) -> Result<(), W::Error> {
+    pub fn encode_int32(&mut self, i: i32) -> Result<(), EncodeError<W::Error>> {
         self.encode_varint(i as u32, i < 0)
     }
 
     #[inline]
     #[cfg(feature = "enable-64bit")]
     /// Encode an `int64`.
-    pub fn encode_int64(&mut self, i: i64) -> Result<(), W::Error> {
+    pub fn encode_int64(&mut self, i: i64) -> Result<(), EncodeError<W::Error>> {
         self.encode_varint64(i as u64)
     }
 
     #[inline]
     /// Encode an `sint32`.
-    pub fn encode_sint32(&mut self, i: i32) -> Result<(), W::Error> {
+    pub fn encode_sint32(&mut self, i: i32) -> Result<(), EncodeError<W::Error>> {
         self.encode_varint32(((i << 1) ^ (i >> 31)) as u32)
     }
 
     #[inline]
     #[cfg(feature = "enable-64bit")]
     /// Encode an `sint64`.
-    pub fn encode_sint64(&mut self, i: i64) -> Result<(), W::Error> {
+    pub fn encode_sint64(&mut self, i: i64) -> Result<(), EncodeError<W::Error>> {
         self.encode_varint64(((i << 1) ^ (i >> 63)) as u64)
     }
 
     #[inline]
     /// Encode a `bool`.
-    pub fn encode_bool(&mut self, b: bool) -> Result<(), W::Error> {
+    pub fn encode_bool(&mut self, b: bool) -> Result<(), EncodeError<W::Error>> {
         self.encode_byte(b as u8)
     }
 
     #[inline]
     /// Encode a `fixed32`.
-    pub fn encode_fixed32(&mut self, u: u32) -> Result<(), W::Error> {
+    pub fn encode_fixed32(&mut self, u: u32) -> Result<(), EncodeError<W::Error>> {
         self.write(&u.to_le_bytes())
     }
 
     #[inline]
     #[cfg(feature = "enable-64bit")]
     /// Encode a `fixed64`.
-    pub fn encode_fixed64(&mut self, u: u64) -> Result<(), W::Error> {
+    pub fn encode_fixed64(&mut self, u: u64) -> Result<(), EncodeError<W::Error>> {
         self.write(&u.to_le_bytes())
     }
 
     /// Encode a 32-bit number as `fixed64`.
     ///
     /// Avoids 64-bit operations, which can have benefits on 32-bit architectures.
-    pub fn encode_fixed64_as_32(&mut self, u: u32) -> Result<(), W::Error> {
+    pub fn encode_fixed64_as_32(&mut self, u: u32) -> Result<(), EncodeError<W::Error>> {
         let mut bytes = [0; 8];
         bytes[..4].copy_from_slice(&u.to_le_bytes());
         self.write(&bytes)
@@ -243,21 +875,21 @@ impl<W: PbWrite> PbEncoder<W> {
 
     #[inline]
     /// Encode a `sfixed32`.
-    pub fn encode_sfixed32(&mut self, i: i32) -> Result<(), W::Error> {
+    pub fn encode_sfixed32(&mut self, i: i32) -> Result<(), EncodeError<W::Error>> {
         self.encode_fixed32(i as u32)
     }
 
     #[inline]
     #[cfg(feature = "enable-64bit")]
     /// Encode a `sfixed64`.
-    pub fn encode_sfixed64(&mut self, i: i64) -> Result<(), W::Error> {
+    pub fn encode_sfixed64(&mut self, i: i64) -> Result<(), EncodeError<W::Error>> {
         self.encode_fixed64(i as u64)
     }
 
     /// Encode a 32-bit number as `sfixed64`.
     ///
     /// Avoids 64-bit operations, which can have benefits on 32-bit architectures.
-    pub fn encode_sfixed64_as_32(&mut self, i: i32) -> Result<(), W::Error> {
+    pub fn encode_sfixed64_as_32(&mut self, i: i32) -> Result<(), EncodeError<W::Error>> {
         // If i is -ve, then write the extra bits to 1
         let mut bytes = if i < 0 { [0xFF; 8] } else { [0; 8] };
         bytes[..4].copy_from_slice(&i.to_le_bytes());
@@ -266,52 +898,87 @@ impl<W: PbWrite> PbEncoder<W> {
 
     #[inline]
     /// Encode a `float`.
-    pub fn encode_float(&mut self, f: f32) -> Result<(), W::Error> {
+    pub fn encode_float(&mut self, f: f32) -> Result<(), EncodeError<W::Error>> {
         self.write(&f.to_le_bytes())
     }
 
     #[inline]
     /// Encode a `double`.
-    pub fn encode_double(&mut self, f: f64) -> Result<(), W::Error> {
+    pub fn encode_double(&mut self, f: f64) -> Result<(), EncodeError<W::Error>> {
         self.write(&f.to_le_bytes())
     }
 
     #[inline(always)]
     /// Encode a Protobuf tag.
-    pub fn encode_tag(&mut self, tag: Tag) -> Result<(), W::Error> {
+    pub fn encode_tag(&mut self, tag: Tag) -> Result<(), EncodeError<W::Error>> {
         self.encode_varint32(tag.varint())
     }
 
+    /// Encode the length prefix of a length-delimited record.
+    ///
+    /// Fails with [`EncodeError::TooLarge`] if `len` exceeds [`MAX_LEN`](crate::size::MAX_LEN).
+    #[inline]
+    pub(crate) fn encode_len(&mut self, len: usize) -> Result<(), EncodeError<W::Error>> {
+        if len > MAX_LEN {
+            return Err(EncodeError::TooLarge);
+        }
+        self.encode_varint32(len as u32)
+    }
+
     /// Encode a `bytes` field.
-    pub fn encode_bytes(&mut self, bytes: &[u8]) -> Result<(), W::Error> {
-        self.encode_varint32(bytes.len() as u32)?;
+    pub fn encode_bytes(&mut self, bytes: &[u8]) -> Result<(), EncodeError<W::Error>> {
+        self.encode_len(bytes.len())?;
         self.write(bytes)
     }
 
     #[inline]
     /// Encode a `string` field.
-    pub fn encode_string(&mut self, string: &str) -> Result<(), W::Error> {
+    pub fn encode_string(&mut self, string: &str) -> Result<(), EncodeError<W::Error>> {
         self.encode_bytes(string.as_bytes())
     }
 
-    //pub fn encode_packed_fixed<T: Copy>(&mut self, elems: &[T]) -> Result<(), W::Error> {
-    //// O(1) operation that gets total size of slice
-    //let len = sizeof_packed_fixed(elems);
-    //let bytes = unsafe { core::slice::from_raw_parts(elems.as_ptr() as *const u8, len) };
-    //self.encode_bytes(bytes)
-    //}
+    /// Encode a repeated packed field of fixed-size elements, including the length prefix.
+    ///
+    /// Produces the same output as [`encode_packed`](Self::encode_packed) with the matching
+    /// `encode_fixed*` method. On little-endian targets, the elements are written in one go, since
+    /// their in-memory representation matches the wire format.
+    pub fn encode_packed_fixed<T: FixedSize>(
+        &mut self,
+        elems: &[T],
+    ) -> Result<(), EncodeError<W::Error>> {
+        self.encode_len(core::mem::size_of_val(elems))?;
+        #[cfg(target_endian = "little")]
+        {
+            // SAFETY: `FixedSize` types are primitives without padding, so their memory can be
+            // viewed as bytes
+            let bytes = unsafe {
+                core::slice::from_raw_parts(
+                    elems.as_ptr() as *const u8,
+                    core::mem::size_of_val(elems),
+                )
+            };
+            self.write(bytes)
+        }
+        #[cfg(not(target_endian = "little"))]
+        {
+            for &elem in elems {
+                self.write(elem.to_le().as_ref())?;
+            }
+            Ok(())
+        }
+    }
 
     /// Encode a repeated packed field from a slice of elements.
     ///
     /// The `encoder` callback determines how each element is encoded onto the wire, and `len` is
     /// the length of the packed record on the wire.
-    pub fn encode_packed<T: Copy, F: FnMut(&mut Self, T) -> Result<(), W::Error>>(
+    pub fn encode_packed<T: Copy, F: FnMut(&mut Self, T) -> Result<(), EncodeError<W::Error>>>(
         &mut self,
         len: usize,
         elems: &[T],
         mut encoder: F,
-    ) -> Result<(), W::Error> {
-        self.encode_varint32(len as u32)?;
+    ) -> Result<(), EncodeError<W::Error>> {
+        self.encode_len(len)?;
         for &e in elems {
             encoder(self, e)?;
         }
@@ -327,8 +994,8 @@ impl<W: PbWrite> PbEncoder<W> {
     pub fn encode_map_elem<
         K: ?Sized,
         V: ?Sized,
-        EK: FnMut(&mut Self, &K) -> Result<(), W::Error>,
-        EV: FnMut(&mut Self, &V) -> Result<(), W::Error>,
+        EK: FnMut(&mut Self, &K) -> Result<(), EncodeError<W::Error>>,
+        EV: FnMut(&mut Self, &V) -> Result<(), EncodeError<W::Error>>,
     >(
         &mut self,
         len: usize,
@@ -338,23 +1005,182 @@ impl<W: PbWrite> PbEncoder<W> {
         val_wtype: u8,
         mut key_encoder: EK,
         mut val_encoder: EV,
-    ) -> Result<(), W::Error> {
-        self.encode_varint32(len as u32)?;
+    ) -> Result<(), EncodeError<W::Error>> {
         let key_tag = Tag::from_parts(1, key_wtype);
         let val_tag = Tag::from_parts(2, val_wtype);
 
-        self.encode_tag(key_tag)?;
-        key_encoder(self, key)?;
-        self.encode_tag(val_tag)?;
-        val_encoder(self, val)?;
-        Ok(())
+        // Message values may be padded by the writer, so the length of the entry is filled in
+        // afterwards if possible
+        self.encode_len_delimited(
+            || len,
+            |encoder| {
+                encoder.encode_tag(key_tag)?;
+                key_encoder(encoder, key)?;
+                encoder.encode_tag(val_tag)?;
+                val_encoder(encoder, val)
+            },
+        )
+    }
+
+    /// Encode a length-delimited record, such as a nested message, with the contents written by
+    /// `contents`.
+    ///
+    /// If the writer supports filling in length prefixes afterwards, such as
+    /// [`BackpatchWriter`], then the length prefix is filled in after the contents are written.
+    /// Otherwise, `len` is called to compute the length of the contents up front.
+    pub fn encode_len_delimited<L, F>(
+        &mut self,
+        len: L,
+        contents: F,
+    ) -> Result<(), EncodeError<W::Error>>
+    where
+        L: FnOnce() -> usize,
+        F: FnOnce(&mut Self) -> Result<(), EncodeError<W::Error>>,
+    {
+        if let Some(slot) = self.writer.pb_reserve_len().map_err(EncodeError::Writer)? {
+            contents(self)?;
+            self.writer.pb_patch_len(slot).map_err(EncodeError::Writer)
+        } else {
+            let len = len();
+            if len > MAX_LEN {
+                return Err(EncodeError::TooLarge);
+            }
+            self.reserve(crate::size::sizeof_len_record(len))?;
+            self.encode_len(len)?;
+            contents(self)
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    /// Encode a field with the tag value `tag`, followed by `val` encoded with `encode`.
+    ///
+    /// This is never inlined, so all fields encoded with the same `encode` function share a single
+    /// copy of the encoding logic. Generated code uses this when optimizing for size.
+    #[inline(never)]
+    pub fn encode_tagged<T>(
+        &mut self,
+        tag: u32,
+        val: T,
+        encode: fn(&mut Self, T) -> Result<(), EncodeError<W::Error>>,
+    ) -> Result<(), EncodeError<W::Error>> {
+        self.encode_varint32(tag)?;
+        encode(self, val)
+    }
+
+    /// Prepare the writer for at least `additional` more bytes to be written.
+    ///
+    /// Growable writers reserve capacity up front, while fixed-capacity writers fail early if
+    /// there isn't enough space. See [`PbWrite::pb_reserve`] for more info.
+    #[inline]
+    pub fn reserve(&mut self, additional: usize) -> Result<(), EncodeError<W::Error>> {
+        self.writer
+            .pb_reserve(additional)
+            .map_err(EncodeError::Writer)
     }
 
     /// Encode a message to the wire.
+    ///
+    /// Unlike [`MessageEncode::encode`], the size of the message is computed and reserved in the
+    /// writer before the message is written, so growable writers only allocate once and
+    /// fixed-capacity writers fail before anything is written. This traverses the message an
+    /// extra time, so call [`MessageEncode::encode`] directly with writers that don't need it,
+    /// such as [`BackpatchWriter`].
+    ///
+    /// Fails with [`EncodeError::TooLarge`] if the message is larger than
+    /// [`MAX_LEN`](crate::size::MAX_LEN).
     #[inline]
-    pub fn encode_message<M: MessageEncode>(&mut self, msg: &M) -> Result<(), W::Error> {
+    pub fn encode_message<M: MessageEncode>(
+        &mut self,
+        msg: &M,
+    ) -> Result<(), EncodeError<W::Error>> {
+        let size = msg.compute_size();
+        if size > MAX_LEN {
+            return Err(EncodeError::TooLarge);
+        }
+        self.reserve(size)?;
         msg.encode(self)
     }
+
+    /// Run `f` with an encoder that writes into this encoder's writer through [`DynPbWrite`].
+    ///
+    /// Since `f` only sees the type-erased writer, the code in `f` is shared between all writer
+    /// types, at the cost of a dynamic call for every write. Errors from the writer are passed
+    /// through to the caller. Generated code uses this when the `dyn_encode` option is set.
+    pub fn encode_dyn<F>(&mut self, f: F) -> Result<(), EncodeError<W::Error>>
+    where
+        F: FnOnce(&mut PbEncoder<&mut DynPbWrite<'_>>) -> Result<(), EncodeError<DynWriteError>>,
+    {
+        let mut erased = ErasedWriter {
+            writer: &mut self.writer,
+            error: None,
+        };
+        let res = f(&mut PbEncoder::new(&mut erased));
+        match (res, erased.error) {
+            (Err(EncodeError::Writer(_)), Some(err)) => Err(EncodeError::Writer(err)),
+            (Err(_), _) => Err(EncodeError::TooLarge),
+            (Ok(()), _) => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Error returned by [`DynPbWrite`] when the underlying writer fails.
+///
+/// The original error is returned by [`PbEncoder::encode_dyn`] once encoding stops.
+pub struct DynWriteError(());
+
+impl Display for DynWriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("underlying writer failed")
+    }
+}
+
+#[cfg(any(feature = "std", feature = "core-error"))]
+impl crate::ErrorTrait for DynWriteError {}
+
+/// Type-erased writer, which lets encoding logic be compiled once for all writer types.
+///
+/// See [`PbEncoder::encode_dyn`] for more info.
+pub type DynPbWrite<'a> = dyn PbWrite<Error = DynWriteError> + 'a;
+
+/// Writer behind [`DynPbWrite`] that stores the error of the underlying writer
+struct ErasedWriter<'a, W: PbWrite> {
+    writer: &'a mut W,
+    error: Option<W::Error>,
+}
+
+impl<W: PbWrite> ErasedWriter<'_, W> {
+    #[inline]
+    fn store<T>(&mut self, res: Result<T, W::Error>) -> Result<T, DynWriteError> {
+        res.map_err(|err| {
+            self.error = Some(err);
+            DynWriteError(())
+        })
+    }
+}
+
+impl<W: PbWrite> PbWrite for ErasedWriter<'_, W> {
+    type Error = DynWriteError;
+
+    fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        let res = self.writer.pb_write(data);
+        self.store(res)
+    }
+
+    fn pb_reserve(&mut self, additional: usize) -> Result<(), Self::Error> {
+        let res = self.writer.pb_reserve(additional);
+        self.store(res)
+    }
+
+    fn pb_reserve_len(&mut self) -> Result<Option<usize>, Self::Error> {
+        let res = self.writer.pb_reserve_len();
+        self.store(res)
+    }
+
+    fn pb_patch_len(&mut self, slot: usize) -> Result<(), Self::Error> {
+        let res = self.writer.pb_patch_len(slot);
+        self.store(res)
+    }
 }
 
 #[cfg(test)]
@@ -565,19 +1391,30 @@ mod tests {
         assert_encode_nosize!(&[4, 208, 151, 208, 180], encode_string("Зд"));
     }
 
-    //#[test]
-    //#[cfg(target_endian = "little")]
-    //fn packed_fixed() {
-    //assert_encode_nosize!([0], encode_packed_fixed(&[0u32; 0]));
-    //assert_encode_nosize!(
-    //[4, 0x1, 0x0, 0x0, 0x1],
-    //encode_packed_fixed(&[true, false, false, true])
-    //);
-    //assert_encode_nosize!(
-    //[8, 0x1, 0x0, 0x0, 0x0, 0x6, 0x0, 0x0, 0x0],
-    //encode_packed_fixed(&[1u32, 6u32])
-    //);
-    //}
+    #[test]
+    fn packed_fixed() {
+        assert_encode_nosize!([0], encode_packed_fixed(&[0u32; 0]));
+        assert_encode_nosize!(
+            [8, 0x1, 0x0, 0x0, 0x0, 0x6, 0x0, 0x0, 0x0],
+            encode_packed_fixed(&[1u32, 6u32])
+        );
+        assert_encode_nosize!(
+            [8, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0xC0, 0x3F],
+            encode_packed_fixed(&[-1i32, 0x3FC00000])
+        );
+        assert_encode_nosize!(
+            [8, 0, 0, 0, 0, 0, 0, 0xF8, 0x3F],
+            encode_packed_fixed(&[1.5f64])
+        );
+
+        // Output matches encoding the elements one by one
+        let elems = [1.5f32, -2.0, f32::MAX];
+        let mut encoder = PbEncoder::new(ArrayVec::<_, 20>::new());
+        encoder
+            .encode_packed(12, &elems, PbEncoder::encode_float)
+            .unwrap();
+        assert_encode_nosize!(encoder.writer.as_slice(), encode_packed_fixed(&elems));
+    }
 
     #[test]
     fn packed() {
@@ -634,4 +1471,650 @@ mod tests {
         );
         assert_encode_map_elem!([5, 0x08, 0x96, 0x01, 0x12, 0], &150, "");
     }
+
+    #[test]
+    fn sizes_saturate() {
+        assert_eq!(usize::MAX, sizeof_len_record(usize::MAX));
+        assert_eq!(usize::MAX, sizeof_len_record(usize::MAX - 3));
+        assert_eq!(usize::MAX, sizeof_map_elem(&0, &0, |_| usize::MAX, |_| 1));
+        assert_eq!(usize::MAX, sizeof_packed(&[0; 3], |_| usize::MAX / 2));
+    }
+
+    #[test]
+    fn too_large() {
+        // Nothing is reserved or written if the length is over the limit
+        let mut encoder = PbEncoder::new(Vec::new());
+        assert_eq!(
+            encoder.encode_len_delimited(|| MAX_LEN + 1, |_| unreachable!()),
+            Err(EncodeError::TooLarge)
+        );
+        assert_eq!(
+            encoder.encode_len_delimited(|| usize::MAX, |_| unreachable!()),
+            Err(EncodeError::TooLarge)
+        );
+        assert_eq!(
+            encoder.encode_packed(MAX_LEN + 1, &[0u32], PbEncoder::encode_varint32),
+            Err(EncodeError::TooLarge)
+        );
+        assert!(encoder.writer.is_empty());
+
+        // The limit itself is allowed
+        let mut encoder = PbEncoder::new(ArrayVec::<_, 5>::new());
+        encoder.encode_len(MAX_LEN).unwrap();
+        assert_eq!([0xFF, 0xFF, 0xFF, 0xFF, 0x07], encoder.writer.as_slice());
+
+        // Slice APIs report the error as a buffer that's too small
+        let mut buf = [0; 8];
+        let mut encoder = PbEncoder::new(SliceWriter::new(&mut buf));
+        assert_eq!(
+            encoder
+                .encode_len(MAX_LEN + 1)
+                .map_err(BufferTooSmall::from),
+            Err(BufferTooSmall {
+                written: 0,
+                needed: usize::MAX,
+                available: 0
+            })
+        );
+    }
+
+    #[test]
+    fn message_too_large() {
+        struct Huge;
+
+        impl MessageEncode for Huge {
+            fn encode<W: PbWrite>(
+                &self,
+                _: &mut PbEncoder<W>,
+            ) -> Result<(), EncodeError<W::Error>> {
+                unreachable!()
+            }
+
+            fn compute_size(&self) -> usize {
+                MAX_LEN + 1
+            }
+        }
+
+        let mut encoder = PbEncoder::new(Vec::new());
+        assert_eq!(encoder.encode_message(&Huge), Err(EncodeError::TooLarge));
+        assert_eq!(
+            Huge.encode_len_delimited(&mut encoder),
+            Err(EncodeError::TooLarge)
+        );
+        assert_eq!(
+            Huge.write_delimited(&mut encoder),
+            Err(EncodeError::TooLarge)
+        );
+        assert!(encoder.writer.is_empty());
+
+        // Errors that don't come from the writer are reported as too large
+        let mut buf = [0; 8];
+        let mut encoder = PbEncoder::new(SliceWriter::new(&mut buf));
+        assert_eq!(
+            encoder.encode_dyn(|encoder| Huge.encode_len_delimited(encoder)),
+            Err(EncodeError::TooLarge)
+        );
+        assert_eq!(encoder.into_writer().as_written(), &[]);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn bytes_too_large() {
+        // Zeroed allocations are cheap, since the pages are never touched
+        let bytes = vec![0u8; MAX_LEN + 1];
+        assert_eq!(MAX_LEN + 6, sizeof_len_record(bytes.len()));
+
+        let mut encoder = PbEncoder::new(Vec::new());
+        assert_eq!(encoder.encode_bytes(&bytes), Err(EncodeError::TooLarge));
+        assert_eq!(
+            encoder.encode_packed_fixed(&vec![0u32; MAX_LEN / 4 + 1]),
+            Err(EncodeError::TooLarge)
+        );
+        assert!(encoder.writer.is_empty());
+        encoder.encode_bytes(&bytes[..1]).unwrap();
+        assert_eq!([1, 0], encoder.writer.as_slice());
+
+        let mut buf = [0; 16];
+        let mut encoder = crate::ReverseEncoder::new(&mut buf);
+        assert_eq!(
+            encoder.encode_bytes(&bytes),
+            Err(BufferTooSmall {
+                written: 0,
+                needed: usize::MAX,
+                available: 16
+            })
+        );
+        assert_eq!(encoder.into_written(), &[]);
+    }
+
+    #[test]
+    fn pb_vec_writer() {
+        let mut vec = ArrayVec::<u8, 4>::new();
+        let mut encoder = PbEncoder::new(PbVecWriter(&mut vec));
+        encoder.encode_varint32(150).unwrap();
+        encoder.encode_bool(true).unwrap();
+        assert_eq!(encoder.encode_fixed32(1), Err(EncodeError::Writer(())));
+        assert_eq!(vec.as_slice(), &[0x96, 0x01, 0x01]);
+
+        let mut vec = Vec::new();
+        let mut encoder = PbEncoder::new(PbVecWriter(&mut vec));
+        encoder.encode_fixed32(1).unwrap();
+        encoder.encode_string("ab").unwrap();
+        assert_eq!(vec, &[0x01, 0x00, 0x00, 0x00, 2, b'a', b'b']);
+    }
+
+    /// Message with a varint and a nested copy of itself
+    struct Nested<'a> {
+        val: u32,
+        child: Option<&'a Nested<'a>>,
+    }
+
+    impl MessageEncode for Nested<'_> {
+        fn encode<W: PbWrite>(
+            &self,
+            encoder: &mut PbEncoder<W>,
+        ) -> Result<(), EncodeError<W::Error>> {
+            encoder.encode_tag(Tag::from_parts(1, WIRE_TYPE_VARINT))?;
+            encoder.encode_varint32(self.val)?;
+            if let Some(child) = self.child {
+                encoder.encode_tag(Tag::from_parts(2, WIRE_TYPE_LEN))?;
+                child.encode_len_delimited(encoder)?;
+            }
+            Ok(())
+        }
+
+        fn compute_size(&self) -> usize {
+            1 + sizeof_varint32(self.val)
+                + self
+                    .child
+                    .map_or(0, |c| 1 + sizeof_len_record(c.compute_size()))
+        }
+    }
+
+    const NESTED: Nested = Nested {
+        val: 150,
+        child: Some(&Nested {
+            val: 1,
+            child: Some(&Nested {
+                val: 300,
+                child: None,
+            }),
+        }),
+    };
+
+    fn encode_nested() -> Vec<u8> {
+        let mut encoder = PbEncoder::new(Vec::new());
+        NESTED.encode(&mut encoder).unwrap();
+        let data = encoder.into_writer();
+        assert_eq!(data.len(), NESTED.compute_size());
+        data
+    }
+
+    #[test]
+    fn slice_writer() {
+        let expected = encode_nested();
+
+        // Message exactly fits the slice
+        let mut buf = [0; 32];
+        let buf = &mut buf[..expected.len()];
+        assert_eq!(NESTED.encode_to_slice(buf).unwrap(), expected);
+        let mut encoder = PbEncoder::new(SliceWriter::new(buf));
+        NESTED.encode(&mut encoder).unwrap();
+        assert_eq!(encoder.as_writer().written(), expected.len());
+        assert_eq!(encoder.as_writer().remaining(), 0);
+        assert_eq!(encoder.into_writer().into_written(), expected);
+
+        // One byte short
+        let mut buf = [0; 32];
+        let buf = &mut buf[..expected.len() - 1];
+        assert_eq!(
+            NESTED.encode_to_slice(buf),
+            Err(BufferTooSmall {
+                written: 0,
+                needed: 1,
+                available: expected.len() - 1
+            })
+        );
+        // Nothing is written if the message doesn't fit
+        assert!(buf.iter().all(|&b| b == 0));
+        let mut encoder = PbEncoder::new(SliceWriter::new(buf));
+        let err = BufferTooSmall::from(NESTED.encode(&mut encoder).unwrap_err());
+        let writer = encoder.into_writer();
+        // The nested message is checked before its length prefix is written, so encoding stops
+        // right after its tag, with the exact number of missing bytes
+        assert_eq!(
+            err,
+            BufferTooSmall {
+                written: 4,
+                needed: 1,
+                available: expected.len() - 5
+            }
+        );
+        assert_eq!(err.written, writer.written());
+        // Bytes from before the failed write are left in place
+        assert_eq!(writer.as_written(), &expected[..err.written]);
+    }
+
+    #[test]
+    fn slice_writer_reset() {
+        let mut buf = [0; 8];
+        let mut writer = SliceWriter::new(&mut buf);
+        let mut encoder = PbEncoder::new(&mut writer);
+        encoder.encode_varint32(150).unwrap();
+        encoder.encode_string("abc").unwrap();
+        assert_eq!(writer.as_written(), &[0x96, 0x01, 3, b'a', b'b', b'c']);
+        assert_eq!(writer.remaining(), 2);
+
+        // Reuse the same slice for another message
+        writer.reset();
+        assert_eq!(writer.written(), 0);
+        let mut encoder = PbEncoder::new(&mut writer);
+        encoder.encode_fixed32(1).unwrap();
+        assert_eq!(
+            encoder.encode_fixed64(2),
+            Err(EncodeError::Writer(BufferTooSmall {
+                written: 4,
+                needed: 4,
+                available: 4
+            }))
+        );
+        assert_eq!(writer.into_written(), &[0x01, 0, 0, 0]);
+    }
+
+    #[test]
+    fn uninit_slice_writer() {
+        let expected = encode_nested();
+
+        let mut buf = [MaybeUninit::uninit(); 32];
+        assert_eq!(NESTED.encode_to_uninit_slice(&mut buf).unwrap(), expected);
+        let mut buf = [MaybeUninit::uninit(); 32];
+        let mut encoder = PbEncoder::new(UninitSliceWriter::new(&mut buf));
+        NESTED.encode(&mut encoder).unwrap();
+        assert_eq!(encoder.as_writer().as_written(), expected);
+        assert_eq!(encoder.as_writer().remaining(), 32 - expected.len());
+        assert_eq!(encoder.into_writer().into_written(), expected);
+
+        // One byte short, so nothing is written
+        let mut buf = [MaybeUninit::uninit(); 32];
+        let buf = &mut buf[..expected.len() - 1];
+        assert_eq!(
+            NESTED.encode_to_uninit_slice(buf),
+            Err(BufferTooSmall {
+                written: 0,
+                needed: 1,
+                available: expected.len() - 1
+            })
+        );
+        // Only the bytes before the failed write are exposed, all of which were written
+        let mut encoder = PbEncoder::new(UninitSliceWriter::new(buf));
+        let err = BufferTooSmall::from(NESTED.encode(&mut encoder).unwrap_err());
+        let written = encoder.into_writer().into_written();
+        assert_eq!(err.written, written.len());
+        assert_eq!(written, &expected[..written.len()]);
+    }
+
+    #[test]
+    fn uninit_slice_writer_partial() {
+        let mut buf = [MaybeUninit::uninit(); 6];
+        let mut writer = UninitSliceWriter::new(&mut buf);
+        let mut encoder = PbEncoder::new(&mut writer);
+        encoder.encode_fixed32(1).unwrap();
+        assert_eq!(
+            encoder.encode_fixed32(2),
+            Err(EncodeError::Writer(BufferTooSmall {
+                written: 4,
+                needed: 2,
+                available: 2
+            }))
+        );
+        assert_eq!(writer.as_written(), &[0x01, 0, 0, 0]);
+
+        writer.reset();
+        assert_eq!(writer.as_written(), &[]);
+        let mut encoder = PbEncoder::new(&mut writer);
+        encoder.encode_varint32(150).unwrap();
+        assert_eq!(writer.into_written(), &[0x96, 0x01]);
+    }
+
+    #[test]
+    fn reserve() {
+        let expected = encode_nested();
+
+        // The whole message is reserved up front
+        let mut encoder = PbEncoder::new(Vec::new());
+        encoder.encode_message(&NESTED).unwrap();
+        let data = encoder.into_writer();
+        assert_eq!(data, expected);
+        assert!(data.capacity() >= expected.len());
+
+        // Fixed-capacity writers fail before anything is written
+        let mut buf = [0; 32];
+        let mut encoder = PbEncoder::new(SliceWriter::new(&mut buf[..expected.len() - 2]));
+        assert_eq!(
+            encoder.encode_message(&NESTED),
+            Err(EncodeError::Writer(BufferTooSmall {
+                written: 0,
+                needed: 2,
+                available: expected.len() - 2
+            }))
+        );
+        assert_eq!(encoder.as_writer().written(), 0);
+
+        let mut encoder = PbEncoder::new(ArrayVec::<u8, 4>::new());
+        assert_eq!(
+            encoder.encode_message(&NESTED),
+            Err(EncodeError::Writer(arrayvec::CapacityError::new(())))
+        );
+        assert!(encoder.into_writer().is_empty());
+        let mut vec = ArrayVec::<u8, 4>::new();
+        vec.push(1);
+        assert!(vec.pb_reserve(3).is_ok());
+        assert!(vec.pb_reserve(4).is_err());
+    }
+
+    #[test]
+    fn encode_tagged() {
+        let mut encoder = PbEncoder::new(vec![]);
+        encoder
+            .encode_tagged(8, -1, PbEncoder::encode_int32)
+            .unwrap();
+        encoder
+            .encode_tagged(21, 1.5, PbEncoder::encode_float)
+            .unwrap();
+        assert_eq!(
+            encoder.into_writer(),
+            [
+                0x08, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, // field 1
+                0x15, 0x00, 0x00, 0xC0, 0x3F, // field 2
+            ]
+        );
+    }
+
+    #[test]
+    fn encode_dyn() {
+        let mut buf = [0; 6];
+        let mut encoder = PbEncoder::new(SliceWriter::new(&mut buf));
+        encoder
+            .encode_dyn(|encoder| {
+                encoder.encode_varint32(150)?;
+                encoder.encode_fixed32(1)
+            })
+            .unwrap();
+        assert_eq!(
+            encoder.into_writer().as_written(),
+            &[0x96, 0x01, 1, 0, 0, 0]
+        );
+
+        // The original error is returned
+        let mut encoder = PbEncoder::new(SliceWriter::new(&mut buf));
+        let res = encoder.encode_dyn(|encoder| {
+            encoder.encode_fixed32(1)?;
+            encoder.encode_fixed32(2)
+        });
+        assert_eq!(
+            res,
+            Err(EncodeError::Writer(BufferTooSmall {
+                written: 4,
+                needed: 2,
+                available: 2
+            }))
+        );
+    }
+
+    #[test]
+    fn callback_writer() {
+        let mut buf = [0; 32];
+        let expected = NESTED.encode_to_slice(&mut buf).unwrap();
+
+        let mut chunks = Vec::new();
+        let mut encoder = PbEncoder::new(CallbackWriter(|chunk: &[u8]| {
+            chunks.push(chunk.to_vec());
+            Ok::<_, ()>(())
+        }));
+        NESTED.encode(&mut encoder).unwrap();
+        // Chunks are passed through as the encoder produces them
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks.concat(), expected);
+
+        // Errors from the callback are returned from the encoder
+        let mut calls = 0;
+        let mut encoder = PbEncoder::new(CallbackWriter(|_: &[u8]| {
+            calls += 1;
+            if calls == 3 {
+                Err("tx buffer unavailable")
+            } else {
+                Ok(())
+            }
+        }));
+        assert_eq!(
+            NESTED.encode(&mut encoder),
+            Err(EncodeError::Writer("tx buffer unavailable"))
+        );
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn backpatch_writer() {
+        let expected = encode_nested();
+
+        let mut buf = [0; 32];
+        let mut encoder = PbEncoder::new(BackpatchWriter::exact(&mut buf));
+        NESTED.encode(&mut encoder).unwrap();
+        assert_eq!(encoder.into_writer().into_written(), expected);
+
+        // Each length prefix takes up 2 bytes, regardless of the length
+        let mut buf = [0; 32];
+        let mut encoder = PbEncoder::new(BackpatchWriter::padded(&mut buf, 2));
+        NESTED.encode(&mut encoder).unwrap();
+        let padded = encoder.into_writer().into_written();
+        assert_eq!(
+            padded,
+            &[0x08, 0x96, 0x01, 0x12, 0x88, 0x00, 0x08, 0x01, 0x12, 0x83, 0x00, 0x08, 0xAC, 0x02]
+        );
+
+        // Padded lengths are accepted by the decoder
+        let mut decoder = crate::PbDecoder::new(&padded[4..]);
+        assert_eq!(decoder.decode_len(), Ok(8));
+        assert_eq!(decoder.decode_varint32(), Ok(0x08));
+        assert_eq!(decoder.decode_varint32(), Ok(1));
+        assert_eq!(decoder.decode_varint32(), Ok(0x12));
+        assert_eq!(decoder.decode_len(), Ok(3));
+    }
+
+    #[test]
+    fn backpatch_writer_move() {
+        // Length of 201 takes 2 bytes, so the contents are moved forward
+        let mut buf = [0; 256];
+        let mut encoder = PbEncoder::new(BackpatchWriter::exact(&mut buf));
+        encoder
+            .encode_len_delimited(
+                || unreachable!(),
+                |encoder| {
+                    encoder.encode_varint32(1)?;
+                    encoder.encode_len_delimited(|| unreachable!(), |_| Ok(()))?;
+                    encoder.encode_bytes(&[0xAA; 197])
+                },
+            )
+            .unwrap();
+        let written = encoder.into_writer().into_written();
+        assert_eq!(&written[..6], &[0xC9, 0x01, 0x01, 0x00, 0xC5, 0x01]);
+        assert_eq!(written.len(), 203);
+        assert!(written[6..].iter().all(|&b| b == 0xAA));
+
+        // Lengths that don't fit into the padding are moved as well
+        let mut buf = [0; 256];
+        let mut encoder = PbEncoder::new(BackpatchWriter::padded(&mut buf, 1));
+        encoder
+            .encode_len_delimited(|| unreachable!(), |encoder| encoder.encode_bytes(&[0; 127]))
+            .unwrap();
+        assert_eq!(&encoder.as_writer().as_written()[..3], &[0x80, 0x01, 0x7F]);
+        assert_eq!(encoder.as_writer().written(), 130);
+
+        // Not enough space to move the contents
+        let mut buf = [0; 201];
+        let mut encoder = PbEncoder::new(BackpatchWriter::exact(&mut buf));
+        let err = encoder
+            .encode_len_delimited(|| unreachable!(), |encoder| encoder.encode_bytes(&[0; 198]))
+            .map_err(BufferTooSmall::from)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            BufferTooSmall {
+                written: 201,
+                needed: 1,
+                available: 0
+            }
+        );
+
+        let mut buf = [0; 2];
+        let mut encoder = PbEncoder::new(BackpatchWriter::padded(&mut buf, 3));
+        assert_eq!(
+            NESTED.encode_len_delimited(&mut encoder),
+            Err(EncodeError::Writer(BufferTooSmall {
+                written: 0,
+                needed: 1,
+                available: 2
+            }))
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[derive(Default)]
+    struct OneByteWriter {
+        data: Vec<u8>,
+        interrupt: bool,
+    }
+
+    #[cfg(feature = "std")]
+    impl std::io::Write for OneByteWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            // Interrupt every other write to exercise the retry logic
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                return Err(std::io::ErrorKind::Interrupted.into());
+            }
+            self.data.extend(buf.first());
+            Ok(buf.len().min(1))
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn std_writer() {
+        let expected = encode_nested();
+
+        // Writing through `io::Write` produces the same bytes as encoding into a `Vec` directly
+        let mut out = Vec::new();
+        NESTED.encode_to_writer(&mut out).unwrap();
+        assert_eq!(out, expected);
+
+        // Short and interrupted writes are retried
+        let mut writer = OneByteWriter::default();
+        NESTED.encode_to_writer(&mut writer).unwrap();
+        assert_eq!(writer.data, expected);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn std_writer_error() {
+        struct ErrWriter;
+        impl std::io::Write for ErrWriter {
+            fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut encoder = PbEncoder::new(StdWriter(ErrWriter));
+        let err = std::io::Error::from(encoder.encode_varint32(1).unwrap_err());
+        assert_eq!(err.kind(), std::io::ErrorKind::BrokenPipe);
+
+        // Writers that accept no bytes fail instead of looping forever
+        let mut encoder = PbEncoder::new(StdWriter(&mut [0u8; 0][..]));
+        let err = std::io::Error::from(encoder.encode_varint32(1).unwrap_err());
+        assert_eq!(err.kind(), std::io::ErrorKind::WriteZero);
+    }
+
+    #[cfg(feature = "embedded-io")]
+    #[derive(Default)]
+    struct ChunkedWriter {
+        data: Vec<u8>,
+        calls: usize,
+        fail: bool,
+    }
+
+    #[cfg(feature = "embedded-io")]
+    impl embedded_io::ErrorType for ChunkedWriter {
+        type Error = embedded_io::ErrorKind;
+    }
+
+    #[cfg(feature = "embedded-io")]
+    impl embedded_io::Write for ChunkedWriter {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            if self.fail {
+                return Err(embedded_io::ErrorKind::Other);
+            }
+            // Accept at most 7 bytes per call
+            let n = buf.len().min(7);
+            self.data.extend_from_slice(&buf[..n]);
+            self.calls += 1;
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "embedded-io")]
+    #[test]
+    fn embedded_io_writer() {
+        let expected = encode_nested();
+
+        let mut encoder = PbEncoder::new(EmbeddedIoWriter(ChunkedWriter::default()));
+        NESTED.encode(&mut encoder).unwrap();
+        // Strings longer than the chunk size are split across multiple writes
+        encoder.encode_string("0123456789abcdef").unwrap();
+        let writer = encoder.into_writer().0;
+        assert_eq!(&writer.data[..expected.len()], expected);
+        assert_eq!(&writer.data[expected.len()..], b"\x100123456789abcdef");
+        assert!(writer.calls > writer.data.len() / 7);
+
+        let mut encoder = PbEncoder::new(EmbeddedIoWriter(ChunkedWriter {
+            fail: true,
+            ..Default::default()
+        }));
+        assert_eq!(
+            NESTED.encode(&mut encoder),
+            Err(EncodeError::Writer(embedded_io::ErrorKind::Other))
+        );
+    }
+
+    #[test]
+    fn error_display() {
+        let err = BufferTooSmall {
+            written: 3,
+            needed: 2,
+            available: 1,
+        };
+        assert_eq!(
+            err.to_string(),
+            "buffer too small: wrote 3 bytes, needed at least 2 more than the 1 available"
+        );
+        assert_eq!(
+            EncodeError::<BufferTooSmall>::TooLarge.to_string(),
+            "message too large to encode"
+        );
+        assert_eq!(
+            EncodeError::Writer(DynWriteError(())).to_string(),
+            "writer error: underlying writer failed"
+        );
+    }
 }
@@ -0,0 +1,292 @@
+use core::fmt::{self, Display};
+
+use crate::{size::MAX_LEN, BufferTooSmall, EncodeError, MessageEncode, PbEncoder, SliceWriter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Error returned by [`encode_async`].
+pub enum AsyncEncodeError<E> {
+    /// Message doesn't fit into the buffer of [`encode_async`], so its `N` parameter needs to be
+    /// increased. Nothing was written.
+    BufferTooSmall(BufferTooSmall),
+    /// Message is longer than the 2 GiB limit of Protobuf, given by [`MAX_LEN`], or contains a
+    /// record that is. Nothing was written.
+    TooLarge,
+    /// Error returned from writer
+    Writer(E),
+}
+
+impl<E: Display> Display for AsyncEncodeError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsyncEncodeError::BufferTooSmall(e) => write!(f, "encode buffer too small: {e}"),
+            AsyncEncodeError::TooLarge => f.write_str("message too large to encode"),
+            AsyncEncodeError::Writer(e) => write!(f, "writer error: {e}"),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", feature = "core-error"))]
+impl<E: crate::ErrorTrait + 'static> crate::ErrorTrait for AsyncEncodeError<E> {
+    fn source(&self) -> Option<&(dyn crate::ErrorTrait + 'static)> {
+        match self {
+            AsyncEncodeError::Writer(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Encode a message into an [`embedded_io_async::Write`], such as a UART or USB transmitter.
+///
+/// This is buffered rather than streaming: since the generated encoding logic is synchronous, the
+/// whole message is first encoded into an `N`-byte buffer on the stack, which is then written to
+/// the writer. `N` needs to be at least the [size](MessageEncode::compute_size) of the message,
+/// and [`MessageEncode::MAX_SIZE`] can be used to pick it for messages with a bounded size.
+/// Messages that don't fit into the buffer fail with [`AsyncEncodeError::BufferTooSmall`], and
+/// messages over the 2 GiB limit of Protobuf fail with [`AsyncEncodeError::TooLarge`], both
+/// before anything is written.
+///
+/// Errors from the writer are returned as [`AsyncEncodeError::Writer`]. If the returned future is
+/// dropped before completion, the bytes written so far are left in the writer. Recovering the
+/// framing of the stream is up to the caller. The writer isn't flushed afterwards.
+///
+/// This requires the `embedded-io-async` feature, which needs Rust 1.75 or later.
+///
+/// # Example
+/// ```no_run
+/// # async fn send<W: embedded_io_async::Write>(
+/// #     msg: &impl micropb::MessageEncode,
+/// #     tx: &mut W,
+/// # ) -> Result<(), micropb::AsyncEncodeError<W::Error>> {
+/// // Encode messages of up to 64 bytes
+/// micropb::encode_async::<_, _, 64>(msg, tx).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn encode_async<M, W, const N: usize>(
+    msg: &M,
+    writer: &mut W,
+) -> Result<(), AsyncEncodeError<W::Error>>
+where
+    M: MessageEncode + ?Sized,
+    W: embedded_io_async::Write,
+{
+    // Sizes saturate, so messages with oversized records are also caught here
+    let size = msg.compute_size();
+    if size > MAX_LEN {
+        return Err(AsyncEncodeError::TooLarge);
+    }
+    if size > N {
+        return Err(AsyncEncodeError::BufferTooSmall(BufferTooSmall {
+            written: 0,
+            needed: size - N,
+            available: N,
+        }));
+    }
+
+    let mut buf = [0; N];
+    let mut encoder = PbEncoder::new(SliceWriter::new(&mut buf));
+    msg.encode(&mut encoder).map_err(|err| match err {
+        EncodeError::TooLarge => AsyncEncodeError::TooLarge,
+        EncodeError::Writer(err) => AsyncEncodeError::BufferTooSmall(err),
+    })?;
+    writer
+        .write_all(encoder.into_writer().into_written())
+        .await
+        .map_err(AsyncEncodeError::Writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        future::Future,
+        pin::pin,
+        task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+    };
+
+    use crate::{size::*, PbWrite, Tag, WIRE_TYPE_LEN, WIRE_TYPE_VARINT};
+
+    use super::*;
+
+    /// Poll a future to completion, counting how many times it was pending
+    fn block_on<F: Future>(fut: F) -> (F::Output, usize) {
+        fn noop_raw_waker() -> RawWaker {
+            fn clone(_: *const ()) -> RawWaker {
+                noop_raw_waker()
+            }
+            fn noop(_: *const ()) {}
+            static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+            RawWaker::new(core::ptr::null(), &VTABLE)
+        }
+
+        // SAFETY: the vtable functions don't touch the data pointer
+        let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+        let mut cx = Context::from_waker(&waker);
+        let mut fut = pin!(fut);
+        let mut pending = 0;
+        loop {
+            match fut.as_mut().poll(&mut cx) {
+                Poll::Ready(out) => return (out, pending),
+                Poll::Pending => pending += 1,
+            }
+        }
+    }
+
+    /// Future that is pending once before completing
+    struct YieldNow(bool);
+
+    impl Future for YieldNow {
+        type Output = ();
+
+        fn poll(mut self: core::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                Poll::Ready(())
+            } else {
+                self.0 = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Async writer that accepts one byte at a time, yielding before every byte
+    #[derive(Default)]
+    struct OneByteWriter {
+        data: Vec<u8>,
+        fail_at: Option<usize>,
+    }
+
+    impl embedded_io_async::ErrorType for OneByteWriter {
+        type Error = embedded_io_async::ErrorKind;
+    }
+
+    impl embedded_io_async::Write for OneByteWriter {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            YieldNow(false).await;
+            if self.fail_at == Some(self.data.len()) {
+                return Err(embedded_io_async::ErrorKind::Other);
+            }
+            self.data.extend(buf.first());
+            Ok(buf.len().min(1))
+        }
+    }
+
+    /// Message with a varint, a string, and a nested copy of itself
+    struct Nested<'a> {
+        val: u32,
+        name: &'a str,
+        child: Option<&'a Nested<'a>>,
+    }
+
+    impl MessageEncode for Nested<'_> {
+        fn encode<W: PbWrite>(
+            &self,
+            encoder: &mut PbEncoder<W>,
+        ) -> Result<(), EncodeError<W::Error>> {
+            encoder.encode_tag(Tag::from_parts(1, WIRE_TYPE_VARINT))?;
+            encoder.encode_varint32(self.val)?;
+            encoder.encode_tag(Tag::from_parts(2, WIRE_TYPE_LEN))?;
+            encoder.encode_string(self.name)?;
+            if let Some(child) = self.child {
+                encoder.encode_tag(Tag::from_parts(3, WIRE_TYPE_LEN))?;
+                child.encode_len_delimited(encoder)?;
+            }
+            Ok(())
+        }
+
+        fn compute_size(&self) -> usize {
+            1 + sizeof_varint32(self.val)
+                + 1
+                + sizeof_len_record(self.name.len())
+                + self
+                    .child
+                    .map_or(0, |c| 1 + sizeof_len_record(c.compute_size()))
+        }
+    }
+
+    const NESTED: Nested = Nested {
+        val: 150,
+        name: "outer message",
+        child: Some(&Nested {
+            val: 1,
+            name: "inner",
+            child: None,
+        }),
+    };
+
+    fn encode_async_buffered<const N: usize>(expected: &[u8]) {
+        let mut writer = OneByteWriter::default();
+        let (res, pending) = block_on(encode_async::<_, _, N>(&NESTED, &mut writer));
+        assert_eq!(res, Ok(()));
+        assert_eq!(writer.data, expected);
+        // Yielded once for every byte
+        assert_eq!(pending, expected.len());
+    }
+
+    #[test]
+    fn encode_async_matches_sync() {
+        let mut encoder = PbEncoder::new(Vec::new());
+        NESTED.encode(&mut encoder).unwrap();
+        let expected = encoder.into_writer();
+        assert_eq!(expected.len(), NESTED.compute_size());
+        assert_eq!(expected.len(), 29);
+
+        encode_async_buffered::<29>(&expected);
+        encode_async_buffered::<64>(&expected);
+
+        // Nothing is written if the message doesn't fit into the buffer
+        let mut writer = OneByteWriter::default();
+        let (res, _) = block_on(encode_async::<_, _, 28>(&NESTED, &mut writer));
+        assert_eq!(
+            res,
+            Err(AsyncEncodeError::BufferTooSmall(BufferTooSmall {
+                written: 0,
+                needed: 1,
+                available: 28
+            }))
+        );
+        assert!(writer.data.is_empty());
+    }
+
+    #[test]
+    fn encode_async_error() {
+        let mut writer = OneByteWriter {
+            fail_at: Some(5),
+            ..Default::default()
+        };
+        let (res, _) = block_on(encode_async::<_, _, 64>(&NESTED, &mut writer));
+        assert_eq!(
+            res,
+            Err(AsyncEncodeError::Writer(
+                embedded_io_async::ErrorKind::Other
+            ))
+        );
+        assert_eq!(writer.data.len(), 5);
+    }
+
+    #[test]
+    fn encode_async_too_large() {
+        /// Message with a nested record over the length limit
+        struct Huge;
+
+        impl MessageEncode for Huge {
+            fn encode<W: PbWrite>(
+                &self,
+                encoder: &mut PbEncoder<W>,
+            ) -> Result<(), EncodeError<W::Error>> {
+                encoder.encode_tag(Tag::from_parts(1, WIRE_TYPE_VARINT))?;
+                encoder.encode_varint32(150)?;
+                encoder.encode_tag(Tag::from_parts(2, WIRE_TYPE_LEN))?;
+                encoder.encode_len_delimited(|| MAX_LEN + 1, |_| unreachable!())
+            }
+
+            fn compute_size(&self) -> usize {
+                usize::MAX
+            }
+        }
+
+        let mut writer = OneByteWriter::default();
+        let (res, _) = block_on(encode_async::<_, _, 64>(&Huge, &mut writer));
+        assert_eq!(res, Err(AsyncEncodeError::TooLarge));
+        assert!(writer.data.is_empty());
+    }
+}
@@ -0,0 +1,433 @@
+use crate::{size::MAX_LEN, BufferTooSmall, EncodeError, FixedSize, PbEncoder, SliceWriter, Tag};
+
+#[derive(Debug)]
+/// Encoder that writes Protobuf values back-to-front into the end of a byte slice.
+///
+/// Since the contents of a length-delimited record are written before its length prefix, the
+/// length is always known by the time the prefix is written. This allows messages to be encoded
+/// in a single pass without computing the sizes of nested messages beforehand, at the cost of
+/// needing the whole output buffer to be available up front.
+///
+/// Each value is written in front of everything written so far, so values need to be written in
+/// the reverse of their order on the wire. For example, a field's value is written before its
+/// tag. Use [`MessageEncode::encode_to_slice_reverse`](crate::MessageEncode::encode_to_slice_reverse)
+/// to encode a whole message.
+///
+/// Writes that don't fit into the rest of the slice fail with [`BufferTooSmall`], leaving the
+/// bytes from previous writes in place. Records longer than [`MAX_LEN`] also fail with
+/// [`BufferTooSmall`], with `needed` set to `usize::MAX`.
+///
+/// # Example
+/// ```
+/// use micropb::ReverseEncoder;
+///
+/// let mut buf = [0; 8];
+/// let mut encoder = ReverseEncoder::new(&mut buf);
+/// // Values are written in reverse order
+/// encoder.encode_string("ab")?;
+/// encoder.encode_varint32(150)?;
+/// assert_eq!(encoder.into_written(), &[0x96, 0x01, 2, b'a', b'b']);
+/// # Ok::<(), micropb::BufferTooSmall>(())
+/// ```
+pub struct ReverseEncoder<'a> {
+    buf: &'a mut [u8],
+    /// Start of the written bytes, which extend to the end of the slice
+    pos: usize,
+}
+
+impl<'a> ReverseEncoder<'a> {
+    /// Construct an encoder that writes backwards from the end of `buf`.
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        let pos = buf.len();
+        Self { buf, pos }
+    }
+
+    /// Number of bytes written so far.
+    pub fn written(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    /// Number of bytes left in front of the written bytes.
+    pub fn remaining(&self) -> usize {
+        self.pos
+    }
+
+    /// Get the bytes written so far, which are at the end of the slice.
+    pub fn as_written(&self) -> &[u8] {
+        &self.buf[self.pos..]
+    }
+
+    /// Transform the encoder into the bytes written so far.
+    pub fn into_written(self) -> &'a mut [u8] {
+        &mut self.buf[self.pos..]
+    }
+
+    /// Reserve `len` bytes directly in front of the bytes written so far, returning the reserved
+    /// bytes so they can be filled in by the caller.
+    ///
+    /// This is useful for writing data that has been encoded front-to-back, such as with
+    /// [`PbEncoder`] and [`SliceWriter`].
+    pub fn reserve(&mut self, len: usize) -> Result<&mut [u8], BufferTooSmall> {
+        if len > self.pos {
+            return Err(BufferTooSmall {
+                written: self.written(),
+                needed: len - self.pos,
+                available: self.pos,
+            });
+        }
+        self.pos -= len;
+        Ok(&mut self.buf[self.pos..self.pos + len])
+    }
+
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) -> Result<(), BufferTooSmall> {
+        self.reserve(bytes.len())?.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Encode a scalar value front-to-back into a scratch buffer, then write it out
+    #[inline]
+    fn write_scalar<F>(&mut self, encode: F) -> Result<(), BufferTooSmall>
+    where
+        F: FnOnce(&mut PbEncoder<SliceWriter>) -> Result<(), EncodeError<BufferTooSmall>>,
+    {
+        // Scalar values take at most 10 bytes on the wire
+        let mut scratch = [0; 10];
+        let mut encoder = PbEncoder::new(SliceWriter::new(&mut scratch));
+        encode(&mut encoder)?;
+        self.write(encoder.into_writer().into_written())
+    }
+
+    #[inline]
+    /// Encode an `uint32`.
+    pub fn encode_varint32(&mut self, u: u32) -> Result<(), BufferTooSmall> {
+        self.write_scalar(|e| e.encode_varint32(u))
+    }
+
+    #[inline]
+    #[cfg(feature = "enable-64bit")]
+    /// Encode an `uint64`.
+    pub fn encode_varint64(&mut self, u: u64) -> Result<(), BufferTooSmall> {
+        self.write_scalar(|e| e.encode_varint64(u))
+    }
+
+    #[inline]
+    /// Encode an `int32`.
+    pub fn encode_int32(&mut self, i: i32) -> Result<(), BufferTooSmall> {
+        self.write_scalar(|e| e.encode_int32(i))
+    }
+
+    #[inline]
+    #[cfg(feature = "enable-64bit")]
+    /// Encode an `int64`.
+    pub fn encode_int64(&mut self, i: i64) -> Result<(), BufferTooSmall> {
+        self.write_scalar(|e| e.encode_int64(i))
+    }
+
+    #[inline]
+    /// Encode an `sint32`.
+    pub fn encode_sint32(&mut self, i: i32) -> Result<(), BufferTooSmall> {
+        self.write_scalar(|e| e.encode_sint32(i))
+    }
+
+    #[inline]
+    #[cfg(feature = "enable-64bit")]
+    /// Encode an `sint64`.
+    pub fn encode_sint64(&mut self, i: i64) -> Result<(), BufferTooSmall> {
+        self.write_scalar(|e| e.encode_sint64(i))
+    }
+
+    #[inline]
+    /// Encode a `bool`.
+    pub fn encode_bool(&mut self, b: bool) -> Result<(), BufferTooSmall> {
+        self.write(&[b as u8])
+    }
+
+    #[inline]
+    /// Encode a `fixed32`.
+    pub fn encode_fixed32(&mut self, u: u32) -> Result<(), BufferTooSmall> {
+        self.write(&u.to_le_bytes())
+    }
+
+    #[inline]
+    #[cfg(feature = "enable-64bit")]
+    /// Encode a `fixed64`.
+    pub fn encode_fixed64(&mut self, u: u64) -> Result<(), BufferTooSmall> {
+        self.write(&u.to_le_bytes())
+    }
+
+    #[inline]
+    /// Encode a 32-bit number as `fixed64`.
+    pub fn encode_fixed64_as_32(&mut self, u: u32) -> Result<(), BufferTooSmall> {
+        self.write_scalar(|e| e.encode_fixed64_as_32(u))
+    }
+
+    #[inline]
+    /// Encode a `sfixed32`.
+    pub fn encode_sfixed32(&mut self, i: i32) -> Result<(), BufferTooSmall> {
+        self.write(&i.to_le_bytes())
+    }
+
+    #[inline]
+    #[cfg(feature = "enable-64bit")]
+    /// Encode a `sfixed64`.
+    pub fn encode_sfixed64(&mut self, i: i64) -> Result<(), BufferTooSmall> {
+        self.write(&i.to_le_bytes())
+    }
+
+    #[inline]
+    /// Encode a 32-bit number as `sfixed64`.
+    pub fn encode_sfixed64_as_32(&mut self, i: i32) -> Result<(), BufferTooSmall> {
+        self.write_scalar(|e| e.encode_sfixed64_as_32(i))
+    }
+
+    #[inline]
+    /// Encode a `float`.
+    pub fn encode_float(&mut self, f: f32) -> Result<(), BufferTooSmall> {
+        self.write(&f.to_le_bytes())
+    }
+
+    #[inline]
+    /// Encode a `double`.
+    pub fn encode_double(&mut self, f: f64) -> Result<(), BufferTooSmall> {
+        self.write(&f.to_le_bytes())
+    }
+
+    #[inline(always)]
+    /// Encode a Protobuf tag.
+    pub fn encode_tag(&mut self, tag: Tag) -> Result<(), BufferTooSmall> {
+        self.encode_varint32(tag.varint())
+    }
+
+    /// Encode the length prefix of a record, failing if the record is too large.
+    fn encode_len(&mut self, len: usize) -> Result<(), BufferTooSmall> {
+        if len > MAX_LEN {
+            return Err(BufferTooSmall {
+                written: self.written(),
+                needed: usize::MAX,
+                available: self.pos,
+            });
+        }
+        self.encode_varint32(len as u32)
+    }
+
+    /// Encode a `bytes` field, including the length prefix.
+    pub fn encode_bytes(&mut self, bytes: &[u8]) -> Result<(), BufferTooSmall> {
+        // Check the length before writing anything
+        if bytes.len() > MAX_LEN {
+            return self.encode_len(bytes.len());
+        }
+        self.write(bytes)?;
+        self.encode_len(bytes.len())
+    }
+
+    #[inline]
+    /// Encode a `string` field, including the length prefix.
+    pub fn encode_string(&mut self, string: &str) -> Result<(), BufferTooSmall> {
+        self.encode_bytes(string.as_bytes())
+    }
+
+    /// Encode a repeated packed field of fixed-size elements, including the length prefix.
+    ///
+    /// On little-endian targets, the elements are written in one go, since their in-memory
+    /// representation matches the wire format.
+    pub fn encode_packed_fixed<T: FixedSize>(&mut self, elems: &[T]) -> Result<(), BufferTooSmall> {
+        #[cfg(target_endian = "little")]
+        {
+            // SAFETY: `FixedSize` types are primitives without padding, so their memory can be
+            // viewed as bytes
+            let bytes = unsafe {
+                core::slice::from_raw_parts(
+                    elems.as_ptr() as *const u8,
+                    core::mem::size_of_val(elems),
+                )
+            };
+            self.encode_bytes(bytes)
+        }
+        #[cfg(not(target_endian = "little"))]
+        {
+            self.encode_len_delimited(|encoder| {
+                for &elem in elems.iter().rev() {
+                    encoder.write(elem.to_le().as_ref())?;
+                }
+                Ok(())
+            })
+        }
+    }
+
+    /// Encode a length-delimited record, such as a nested message or a packed field.
+    ///
+    /// The contents of the record are written by `contents`, after which the length prefix is
+    /// written in front of them.
+    pub fn encode_len_delimited<F>(&mut self, contents: F) -> Result<(), BufferTooSmall>
+    where
+        F: FnOnce(&mut Self) -> Result<(), BufferTooSmall>,
+    {
+        let start = self.written();
+        contents(self)?;
+        self.encode_len(self.written() - start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{MessageEncode, WIRE_TYPE_LEN, WIRE_TYPE_VARINT};
+
+    use super::*;
+
+    /// Check that a value encoded in reverse matches the value encoded forwards
+    fn assert_matches_forward<F, R>(forward: F, reverse: R)
+    where
+        F: FnOnce(&mut PbEncoder<Vec<u8>>) -> Result<(), EncodeError<crate::Never>>,
+        R: FnOnce(&mut ReverseEncoder) -> Result<(), BufferTooSmall>,
+    {
+        let mut encoder = PbEncoder::new(Vec::new());
+        forward(&mut encoder).unwrap();
+        let expected = encoder.into_writer();
+
+        let mut buf = [0; 32];
+        let mut encoder = ReverseEncoder::new(&mut buf);
+        reverse(&mut encoder).unwrap();
+        assert_eq!(encoder.as_written(), expected);
+    }
+
+    macro_rules! assert_scalar {
+        ($func:ident, $($val:expr),+) => {
+            $(assert_matches_forward(|e| e.$func($val), |e| e.$func($val));)+
+        };
+    }
+
+    #[test]
+    fn scalars() {
+        assert_scalar!(encode_varint32, 0, 1, 150, u32::MAX);
+        assert_scalar!(encode_varint64, 0, 150, u64::MAX);
+        assert_scalar!(encode_int32, 0, -1, i32::MIN, i32::MAX);
+        assert_scalar!(encode_int64, 0, -1, i64::MIN, i64::MAX);
+        assert_scalar!(encode_sint32, 0, -1, i32::MIN, i32::MAX);
+        assert_scalar!(encode_sint64, 0, -1, i64::MIN, i64::MAX);
+        assert_scalar!(encode_bool, true, false);
+        assert_scalar!(encode_fixed32, 0, u32::MAX);
+        assert_scalar!(encode_fixed64, 0, u64::MAX);
+        assert_scalar!(encode_fixed64_as_32, 0, u32::MAX);
+        assert_scalar!(encode_sfixed32, -1, i32::MAX);
+        assert_scalar!(encode_sfixed64, -1, i64::MAX);
+        assert_scalar!(encode_sfixed64_as_32, -1, i32::MAX);
+        assert_scalar!(encode_float, -1.5, f32::INFINITY);
+        assert_scalar!(encode_double, -1.5, f64::NAN);
+        assert_scalar!(encode_bytes, b"", &[0xFF; 20]);
+        assert_scalar!(encode_string, "", "hello");
+        assert_scalar!(
+            encode_tag,
+            Tag::from_parts(1, WIRE_TYPE_LEN),
+            Tag::from_parts(1000, WIRE_TYPE_VARINT)
+        );
+    }
+
+    #[test]
+    fn len_delimited() {
+        let mut buf = [0; 8];
+        let mut encoder = ReverseEncoder::new(&mut buf);
+        encoder
+            .encode_len_delimited(|e| {
+                e.encode_varint32(300)?;
+                e.encode_bool(true)
+            })
+            .unwrap();
+        encoder
+            .encode_tag(Tag::from_parts(2, WIRE_TYPE_LEN))
+            .unwrap();
+        assert_eq!(encoder.as_written(), &[0x12, 3, 1, 0xAC, 0x02]);
+        assert_eq!(encoder.written(), 5);
+        assert_eq!(encoder.remaining(), 3);
+    }
+
+    #[test]
+    fn packed_fixed() {
+        let elems = [1u64, u64::MAX, 0x0102030405060708];
+        assert_matches_forward(
+            |e| e.encode_packed_fixed(&elems),
+            |e| e.encode_packed_fixed(&elems),
+        );
+        assert_matches_forward(
+            |e| e.encode_packed_fixed(&[0.5f32, -1.0]),
+            |e| e.encode_packed_fixed(&[0.5f32, -1.0]),
+        );
+        assert_matches_forward(
+            |e| e.encode_packed_fixed::<i32>(&[]),
+            |e| e.encode_packed_fixed::<i32>(&[]),
+        );
+    }
+
+    #[test]
+    fn buffer_too_small() {
+        let mut buf = [0; 4];
+        let mut encoder = ReverseEncoder::new(&mut buf);
+        encoder.encode_fixed32(5).unwrap();
+        assert_eq!(
+            encoder.encode_bool(true),
+            Err(BufferTooSmall {
+                written: 4,
+                needed: 1,
+                available: 0
+            })
+        );
+        // Failed writes leave the existing bytes in place
+        assert_eq!(encoder.into_written(), &[5, 0, 0, 0]);
+
+        let mut buf = [0; 3];
+        let mut encoder = ReverseEncoder::new(&mut buf);
+        assert_eq!(
+            encoder.encode_string("abc"),
+            Err(BufferTooSmall {
+                written: 3,
+                needed: 1,
+                available: 0
+            })
+        );
+    }
+
+    /// Message without a generated reverse encoding, which uses the default implementation
+    struct Forward(u32);
+
+    impl MessageEncode for Forward {
+        fn encode<W: crate::PbWrite>(
+            &self,
+            encoder: &mut PbEncoder<W>,
+        ) -> Result<(), EncodeError<W::Error>> {
+            encoder.encode_tag(Tag::from_parts(1, WIRE_TYPE_VARINT))?;
+            encoder.encode_varint32(self.0)
+        }
+
+        fn compute_size(&self) -> usize {
+            1 + crate::size::sizeof_varint32(self.0)
+        }
+    }
+
+    #[test]
+    fn default_encode_reverse() {
+        let mut buf = [0; 8];
+        let encoded = Forward(150).encode_to_slice_reverse(&mut buf).unwrap();
+        assert_eq!(encoded, &[0x08, 0x96, 0x01]);
+        assert_eq!(buf[..5], [0; 5]);
+
+        let mut buf = [0; 8];
+        let mut encoder = ReverseEncoder::new(&mut buf);
+        Forward(1)
+            .encode_reverse_len_delimited(&mut encoder)
+            .unwrap();
+        encoder
+            .encode_tag(Tag::from_parts(3, WIRE_TYPE_LEN))
+            .unwrap();
+        assert_eq!(encoder.as_written(), &[0x1A, 2, 0x08, 0x01]);
+
+        let mut buf = [0; 2];
+        assert_eq!(
+            Forward(150).encode_to_slice_reverse(&mut buf),
+            Err(BufferTooSmall {
+                written: 0,
+                needed: 1,
+                available: 2
+            })
+        );
+    }
+}
@@ -0,0 +1,353 @@
+use core::fmt::{self, Display};
+
+use crate::{
+    size::{sizeof_len_record, MAX_LEN},
+    EncodeError, MessageEncode, PbEncoder, PbWrite,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Error returned by [`MessageWriter::write_message`].
+pub enum MessageWriteError<E> {
+    /// Message length exceeded the limit set by [`MessageWriter::max_len`], or the 2 GiB limit of
+    /// Protobuf. Nothing was written.
+    SizeLimit,
+    /// Writing the message would exceed the budget set by [`MessageWriter::max_total`]. Nothing
+    /// was written.
+    BudgetExceeded,
+    /// Writer failed before any of the message was written, so the stream is still intact.
+    Writer(E),
+    /// Writer failed after some of the message was written, so the stream may end with a torn
+    /// record, starting at [`MessageWriter::written`]. Reading the stream back will fail at that
+    /// record.
+    TornRecord(E),
+}
+
+impl<E: Display> Display for MessageWriteError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageWriteError::SizeLimit => f.write_str("message length exceeded limit"),
+            MessageWriteError::BudgetExceeded => f.write_str("stream size exceeded budget"),
+            MessageWriteError::Writer(e) => write!(f, "writer error: {e}"),
+            MessageWriteError::TornRecord(e) => {
+                write!(f, "writer error in the middle of a record: {e}")
+            }
+        }
+    }
+}
+
+#[cfg(any(feature = "std", feature = "core-error"))]
+impl<E: crate::ErrorTrait + 'static> crate::ErrorTrait for MessageWriteError<E> {
+    fn source(&self) -> Option<&(dyn crate::ErrorTrait + 'static)> {
+        match self {
+            MessageWriteError::Writer(e) | MessageWriteError::TornRecord(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+/// Writer for a stream of consecutive length-delimited messages.
+///
+/// This is the counterpart of [`MessageStream`](crate::MessageStream). Each message is written
+/// with its length as a varint prefix, followed by the message itself. The writer keeps track of
+/// the number of bytes written, and can enforce limits on the length of each message and on the
+/// total size of the stream, such as for an append-only log in flash.
+///
+/// # Example
+///
+/// ```
+/// use micropb::{MessageWriter, MessageEncode, PbEncoder, PbWrite};
+///
+/// # struct ProtoMessage;
+/// # impl MessageEncode for ProtoMessage {
+/// #   fn encode<W: PbWrite>(&self, _: &mut PbEncoder<W>) -> Result<(), micropb::EncodeError<W::Error>> { Ok(()) }
+/// #   fn compute_size(&self) -> usize { 0 }
+/// # }
+/// let mut buf = [0; 64];
+/// let mut stream = MessageWriter::new(micropb::SliceWriter::new(&mut buf)).max_total(64);
+///
+/// stream.write_message(&ProtoMessage)?;
+/// stream.write_message(&ProtoMessage)?;
+/// assert_eq!(stream.written(), 2);
+/// # Ok::<(), micropb::MessageWriteError<micropb::BufferTooSmall>>(())
+/// ```
+pub struct MessageWriter<W: PbWrite> {
+    writer: W,
+    written: usize,
+    max_len: usize,
+    max_total: usize,
+}
+
+impl<W: PbWrite> MessageWriter<W> {
+    /// Construct a new stream from a [`PbWrite`] with no limits besides the 2 GiB limit on
+    /// message length.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            written: 0,
+            max_len: MAX_LEN,
+            max_total: usize::MAX,
+        }
+    }
+
+    /// Set the maximum allowed length of each message, not including the length prefix.
+    ///
+    /// Messages that are longer fail with [`MessageWriteError::SizeLimit`] without writing
+    /// anything.
+    pub fn max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Set the maximum number of bytes that can be written to the stream in total, including
+    /// the length prefixes.
+    ///
+    /// Messages that would exceed the budget fail with [`MessageWriteError::BudgetExceeded`]
+    /// without writing anything.
+    pub fn max_total(mut self, max_total: usize) -> Self {
+        self.max_total = max_total;
+        self
+    }
+
+    /// Total number of bytes written by all complete messages, including the length prefixes.
+    ///
+    /// Messages that fail partway through aren't counted, so this is also the offset of the torn
+    /// record after [`MessageWriteError::TornRecord`].
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    /// Get mutable reference to the underlying writer.
+    pub fn writer(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Transform the stream into the underlying writer.
+    pub fn into_writer(self) -> W {
+        self.writer
+    }
+
+    /// Write a message to the stream, prefixed by its length, and return the number of bytes
+    /// written.
+    ///
+    /// The limits are checked before anything is written. The whole record is then reserved in
+    /// the writer with [`PbWrite::pb_reserve`], so fixed-capacity writers that can't fit the
+    /// record fail with [`MessageWriteError::Writer`] and leave the stream intact. Writer errors
+    /// after that point are returned as [`MessageWriteError::TornRecord`].
+    pub fn write_message<M: MessageEncode + ?Sized>(
+        &mut self,
+        msg: &M,
+    ) -> Result<usize, MessageWriteError<W::Error>> {
+        let len = msg.compute_size();
+        if len > self.max_len || len > MAX_LEN {
+            return Err(MessageWriteError::SizeLimit);
+        }
+        let record_len = sizeof_len_record(len);
+        if record_len > self.max_total.saturating_sub(self.written) {
+            return Err(MessageWriteError::BudgetExceeded);
+        }
+
+        let mut encoder = PbEncoder::new(StartedWriter {
+            writer: &mut self.writer,
+            started: false,
+        });
+        let res = encoder
+            .reserve(record_len)
+            .and_then(|()| encoder.encode_len(len))
+            .and_then(|()| msg.encode(&mut encoder));
+        match res {
+            Ok(()) => {
+                self.written += record_len;
+                Ok(record_len)
+            }
+            // Nested records are never larger than the message, which is within the limit
+            Err(EncodeError::TooLarge) => Err(MessageWriteError::SizeLimit),
+            Err(EncodeError::Writer(e)) if encoder.into_writer().started => {
+                Err(MessageWriteError::TornRecord(e))
+            }
+            Err(EncodeError::Writer(e)) => Err(MessageWriteError::Writer(e)),
+        }
+    }
+}
+
+/// Writer that tracks whether anything has been written to the underlying writer
+struct StartedWriter<'a, W> {
+    writer: &'a mut W,
+    started: bool,
+}
+
+impl<W: PbWrite> PbWrite for StartedWriter<'_, W> {
+    type Error = W::Error;
+
+    #[inline]
+    fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        // Failed writes may still have written some of the data
+        self.started = true;
+        self.writer.pb_write(data)
+    }
+
+    #[inline]
+    fn pb_reserve(&mut self, additional: usize) -> Result<(), Self::Error> {
+        self.writer.pb_reserve(additional)
+    }
+
+    #[inline]
+    fn pb_reserve_len(&mut self) -> Result<Option<usize>, Self::Error> {
+        self.started = true;
+        self.writer.pb_reserve_len()
+    }
+
+    #[inline]
+    fn pb_patch_len(&mut self, slot: usize) -> Result<(), Self::Error> {
+        self.writer.pb_patch_len(slot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        BufferTooSmall, CallbackWriter, DecodeError, MessageDecode, MessageStream, PbDecoder,
+        PbRead, SliceWriter, Tag, WIRE_TYPE_VARINT,
+    };
+
+    use super::*;
+
+    #[derive(Debug, Default, PartialEq)]
+    struct Msg(u32);
+
+    impl MessageEncode for Msg {
+        fn encode<W: PbWrite>(
+            &self,
+            encoder: &mut PbEncoder<W>,
+        ) -> Result<(), EncodeError<W::Error>> {
+            if self.0 != 0 {
+                encoder.encode_tag(Tag::from_parts(1, WIRE_TYPE_VARINT))?;
+                encoder.encode_varint32(self.0)?;
+            }
+            Ok(())
+        }
+
+        fn compute_size(&self) -> usize {
+            if self.0 != 0 {
+                1 + crate::size::sizeof_varint32(self.0)
+            } else {
+                0
+            }
+        }
+    }
+
+    impl MessageDecode for Msg {
+        fn decode<R: PbRead>(
+            &mut self,
+            decoder: &mut PbDecoder<R>,
+            len: usize,
+        ) -> Result<(), DecodeError<R::Error>> {
+            let before = decoder.bytes_read();
+            while decoder.bytes_read() - before < len {
+                let tag = decoder.decode_tag()?;
+                match tag.field_num() {
+                    1 => self.0 = decoder.decode_varint32()?,
+                    _ => decoder.skip_wire_value(tag.wire_type())?,
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_messages() {
+        let mut stream = MessageWriter::new(Vec::new());
+        assert_eq!(stream.write_message(&Msg(1)), Ok(3));
+        assert_eq!(stream.write_message(&Msg(0)), Ok(1));
+        assert_eq!(stream.write_message(&Msg(150)), Ok(4));
+        assert_eq!(stream.written(), 8);
+        let data = stream.into_writer();
+        assert_eq!(data, [0x02, 0x08, 0x01, 0x00, 0x03, 0x08, 0x96, 0x01]);
+
+        // The messages can be read back with the stream reader
+        let mut reader = MessageStream::new(data.as_slice());
+        let msgs: Result<Vec<Msg>, _> = reader.messages().collect();
+        assert_eq!(msgs.unwrap(), [Msg(1), Msg(0), Msg(150)]);
+    }
+
+    #[test]
+    fn limits() {
+        let mut stream = MessageWriter::new(Vec::new()).max_len(2).max_total(6);
+        assert_eq!(stream.write_message(&Msg(1)), Ok(3));
+        assert_eq!(
+            stream.write_message(&Msg(150)),
+            Err(MessageWriteError::SizeLimit)
+        );
+        assert_eq!(stream.write_message(&Msg(2)), Ok(3));
+        assert_eq!(
+            stream.write_message(&Msg(0)),
+            Err(MessageWriteError::BudgetExceeded)
+        );
+        assert_eq!(stream.written(), 6);
+        assert_eq!(stream.into_writer(), [0x02, 0x08, 0x01, 0x02, 0x08, 0x02]);
+    }
+
+    #[test]
+    fn writer_full() {
+        // Writers that reserve space fail before writing anything
+        let mut buf = [0; 5];
+        let mut stream = MessageWriter::new(SliceWriter::new(&mut buf));
+        assert_eq!(stream.write_message(&Msg(1)), Ok(3));
+        assert_eq!(
+            stream.write_message(&Msg(150)),
+            Err(MessageWriteError::Writer(BufferTooSmall {
+                written: 3,
+                needed: 2,
+                available: 2
+            }))
+        );
+        assert_eq!(stream.into_writer().as_written(), &[0x02, 0x08, 0x01]);
+
+        // Other writers may fill up in the middle of a message, leaving a torn record
+        let mut log = Vec::new();
+        let mut stream = MessageWriter::new(CallbackWriter(|data: &[u8]| {
+            if log.len() + data.len() > 5 {
+                return Err(());
+            }
+            log.extend_from_slice(data);
+            Ok(())
+        }));
+        assert_eq!(stream.write_message(&Msg(1)), Ok(3));
+        assert_eq!(
+            stream.write_message(&Msg(150)),
+            Err(MessageWriteError::TornRecord(()))
+        );
+        assert_eq!(stream.written(), 3);
+        assert_eq!(log, [0x02, 0x08, 0x01, 0x03, 0x08]);
+
+        // Reading the log back fails at the torn record
+        let mut reader = MessageStream::new(log.as_slice());
+        assert_eq!(reader.next_message(), Ok(Some(Msg(1))));
+        assert_eq!(
+            reader.next_message::<Msg>(),
+            Err(DecodeError::UnexpectedEof)
+        );
+    }
+
+    #[test]
+    fn error_display() {
+        let cases: [(MessageWriteError<&str>, &str); 4] = [
+            (
+                MessageWriteError::SizeLimit,
+                "message length exceeded limit",
+            ),
+            (
+                MessageWriteError::BudgetExceeded,
+                "stream size exceeded budget",
+            ),
+            (MessageWriteError::Writer("full"), "writer error: full"),
+            (
+                MessageWriteError::TornRecord("full"),
+                "writer error in the middle of a record: full",
+            ),
+        ];
+        for (err, msg) in cases {
+            assert_eq!(err.to_string(), msg);
+        }
+    }
+}
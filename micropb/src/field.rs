@@ -7,7 +7,13 @@
 #[cfg(feature = "decode")]
 use crate::decode::{DecodeError, PbDecoder, PbRead};
 #[cfg(feature = "encode")]
-use crate::encode::{PbEncoder, PbWrite};
+use core::ops::Range;
+
+#[cfg(feature = "encode")]
+use crate::{
+    encode::{BufferTooSmall, EncodeError, PbEncoder, PbWrite, SliceWriter},
+    encode_reverse::ReverseEncoder,
+};
 
 #[cfg(feature = "decode")]
 /// One or more Protobuf fields that can be decoded from the wire.
@@ -103,7 +109,7 @@ impl<T: Default + FieldDecode> FieldDecode for Option<T> {
 /// struct Bits(u8);
 ///
 /// impl FieldEncode for Bits {
-///     fn encode_fields<W: PbWrite>(&self, encoder: &mut PbEncoder<W>) -> Result<(), W::Error> {
+///     fn encode_fields<W: PbWrite>(&self, encoder: &mut PbEncoder<W>) -> Result<(), micropb::EncodeError<W::Error>> {
 ///         // Encode each of the 8 bits using field numbers 1 to 8
 ///         for i in 0..8 {
 ///             let b = (self.0 & (1 << i)) != 0;
@@ -124,28 +130,88 @@ pub trait FieldEncode {
     /// Unlike `FieldDecode::decode_field`, this call is expected to write out complete fields,
     /// including the tags. It should also write out all fields as once, since it will only be
     /// called once. For non-packed repeated fields, each element is written out as its own field.
-    fn encode_fields<W: PbWrite>(&self, encoder: &mut PbEncoder<W>) -> Result<(), W::Error>;
+    fn encode_fields<W: PbWrite>(
+        &self,
+        encoder: &mut PbEncoder<W>,
+    ) -> Result<(), EncodeError<W::Error>>;
 
     /// Compute size of all fields, including the tags.
     fn compute_fields_size(&self) -> usize;
+
+    /// Encode all fields back-to-front into a [`ReverseEncoder`], including the tags.
+    ///
+    /// The default implementation reserves [`compute_fields_size`](Self::compute_fields_size)
+    /// bytes in the encoder and encodes the fields forwards into them with
+    /// [`encode_fields`](Self::encode_fields).
+    fn encode_fields_reverse(
+        &self,
+        encoder: &mut ReverseEncoder<'_>,
+    ) -> Result<(), BufferTooSmall> {
+        let buf = encoder.reserve(self.compute_fields_size())?;
+        self.encode_fields(&mut PbEncoder::new(SliceWriter::new(buf)))
+            .map_err(Into::into)
+    }
+
+    /// Encode the fields whose field numbers are within `nums`, in ascending order of field
+    /// number.
+    ///
+    /// Messages generated with the `canonical_order` config call this on their unknown field
+    /// handler once for every gap between the message's own field numbers, so that unknown fields
+    /// are interleaved with the other fields in order of field number. The range of the last call
+    /// ends at `u32::MAX`.
+    ///
+    /// The default implementation writes all fields with [`encode_fields`](Self::encode_fields)
+    /// during the last call, which places them after the message's own fields. Handlers that
+    /// keep track of field numbers should override this to produce fully ordered output.
+    fn encode_fields_in<W: PbWrite>(
+        &self,
+        encoder: &mut PbEncoder<W>,
+        nums: Range<u32>,
+    ) -> Result<(), EncodeError<W::Error>> {
+        if nums.end == u32::MAX {
+            self.encode_fields(encoder)?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(feature = "encode")]
 impl<T: FieldEncode> FieldEncode for &T {
-    fn encode_fields<W: PbWrite>(&self, encoder: &mut PbEncoder<W>) -> Result<(), W::Error> {
+    fn encode_fields<W: PbWrite>(
+        &self,
+        encoder: &mut PbEncoder<W>,
+    ) -> Result<(), EncodeError<W::Error>> {
         (*self).encode_fields(encoder)
     }
 
     fn compute_fields_size(&self) -> usize {
         (*self).compute_fields_size()
     }
+
+    fn encode_fields_reverse(
+        &self,
+        encoder: &mut ReverseEncoder<'_>,
+    ) -> Result<(), BufferTooSmall> {
+        (*self).encode_fields_reverse(encoder)
+    }
+
+    fn encode_fields_in<W: PbWrite>(
+        &self,
+        encoder: &mut PbEncoder<W>,
+        nums: Range<u32>,
+    ) -> Result<(), EncodeError<W::Error>> {
+        (*self).encode_fields_in(encoder, nums)
+    }
 }
 
 #[cfg(feature = "encode")]
 /// Convenience implementation for fields wrapped in `Option`. If the value is `None`, then the
 /// field isn't encoded at all.
 impl<T: FieldEncode> FieldEncode for Option<T> {
-    fn encode_fields<W: PbWrite>(&self, encoder: &mut PbEncoder<W>) -> Result<(), W::Error> {
+    fn encode_fields<W: PbWrite>(
+        &self,
+        encoder: &mut PbEncoder<W>,
+    ) -> Result<(), EncodeError<W::Error>> {
         if let Some(f) = self {
             f.encode_fields(encoder)?;
         }
@@ -153,9 +219,27 @@ impl<T: FieldEncode> FieldEncode for Option<T> {
     }
 
     fn compute_fields_size(&self) -> usize {
+        self.as_ref().map_or(0, |f| f.compute_fields_size())
+    }
+
+    fn encode_fields_reverse(
+        &self,
+        encoder: &mut ReverseEncoder<'_>,
+    ) -> Result<(), BufferTooSmall> {
         if let Some(f) = self {
-            f.compute_fields_size();
+            f.encode_fields_reverse(encoder)?;
         }
-        0
+        Ok(())
+    }
+
+    fn encode_fields_in<W: PbWrite>(
+        &self,
+        encoder: &mut PbEncoder<W>,
+        nums: Range<u32>,
+    ) -> Result<(), EncodeError<W::Error>> {
+        if let Some(f) = self {
+            f.encode_fields_in(encoder, nums)?;
+        }
+        Ok(())
     }
 }
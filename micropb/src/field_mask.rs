@@ -0,0 +1,297 @@
+//! Partial updates of messages with the paths of a `google.protobuf.FieldMask`.
+//!
+//! A field mask lists the fields of a message that an update applies to, as paths of field names
+//! in the `.proto` file, such as `name` or `config.timeout`. [`ApplyFieldMask`] copies the fields
+//! listed by a mask from one message into another, leaving the other fields alone. It's
+//! implemented on generated messages by `Config::field_mask` from `micropb-gen`.
+//!
+//! The mask is taken as the `paths` field of the generated `FieldMask` message, or as any other
+//! list of strings, so this module doesn't depend on the container types of `FieldMask`.
+//!
+//! This requires the `field-mask` feature.
+//!
+//! # Example
+//!
+//! ```
+//! use micropb::field_mask::{split_path, ApplyFieldMask};
+//!
+//! #[derive(Debug, Default, PartialEq)]
+//! struct Point {
+//!     x: i32,
+//!     y: i32,
+//! }
+//!
+//! impl ApplyFieldMask for Point {
+//!     fn check_path(path: &str) -> Result<(), &str> {
+//!         match split_path(path) {
+//!             ("x" | "y", None) => Ok(()),
+//!             ("x" | "y", Some(rest)) => Err(split_path(rest).0),
+//!             (name, _) => Err(name),
+//!         }
+//!     }
+//!
+//!     fn apply_path(&mut self, src: &Self, path: &str) {
+//!         match path {
+//!             "x" => self.x = src.x,
+//!             "y" => self.y = src.y,
+//!             _ => {}
+//!         }
+//!     }
+//! }
+//!
+//! let mut point = Point { x: 1, y: 2 };
+//! point.apply_field_mask(&Point { x: 5, y: 6 }, &["y"]).unwrap();
+//! assert_eq!(point, Point { x: 1, y: 6 });
+//!
+//! let err = point.apply_field_mask(&Point::default(), &["x", "y.z"]).unwrap_err();
+//! assert_eq!(err.segment, "z");
+//! // Nothing is copied if any of the paths is unknown
+//! assert_eq!(point, Point { x: 1, y: 6 });
+//! ```
+
+use core::{
+    fmt::{self, Display},
+    ops::Deref,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Error returned when a path of a field mask doesn't name a field of the message.
+pub struct FieldMaskError<'a> {
+    /// Full path from the field mask
+    pub path: &'a str,
+    /// First segment of the path that doesn't name a field, which is empty if the path has an
+    /// empty segment
+    pub segment: &'a str,
+}
+
+impl Display for FieldMaskError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown field \"{}\" in field mask path \"{}\"",
+            self.segment, self.path
+        )
+    }
+}
+
+#[cfg(any(feature = "std", feature = "core-error"))]
+impl crate::ErrorTrait for FieldMaskError<'_> {}
+
+/// Split the first segment off a field mask path, returning the segment and the rest of the path
+/// after the `.`, if any.
+///
+/// Used by [`ApplyFieldMask`] implementations to walk nested paths.
+///
+/// ```
+/// use micropb::field_mask::split_path;
+///
+/// assert_eq!(split_path("config.timeout.seconds"), ("config", Some("timeout.seconds")));
+/// assert_eq!(split_path("name"), ("name", None));
+/// ```
+pub fn split_path(path: &str) -> (&str, Option<&str>) {
+    match path.split_once('.') {
+        Some((name, rest)) => (name, Some(rest)),
+        None => (path, None),
+    }
+}
+
+/// Message whose fields can be copied from another instance according to a field mask.
+///
+/// Paths are made of the snake_case field names from the `.proto` file, joined by `.` to select
+/// fields of nested messages. Copying a field copies its presence along with its value, so an
+/// unset optional field in the source clears the field in the destination. Repeated and `map`
+/// fields are replaced as a whole rather than merged.
+pub trait ApplyFieldMask {
+    /// Check that `path` names a field of the message, returning the first segment of the path
+    /// that doesn't name a field otherwise.
+    ///
+    /// Paths can only continue past message fields, so a path that continues past any other field
+    /// is unknown.
+    fn check_path(path: &str) -> Result<(), &str>;
+
+    /// Copy the field named by `path` from `src` into `self`.
+    ///
+    /// Paths rejected by [`check_path`](Self::check_path) are ignored.
+    fn apply_path(&mut self, src: &Self, path: &str);
+
+    /// Copy the fields named by the paths of a field mask from `src` into `self`.
+    ///
+    /// All paths are checked before anything is copied, so on error `self` is left untouched.
+    /// The error describes the first unknown path. Use [`unknown_paths`] to list all of them.
+    fn apply_field_mask<'p, I, P>(&mut self, src: &Self, paths: I) -> Result<(), FieldMaskError<'p>>
+    where
+        I: IntoIterator<Item = &'p P> + Clone,
+        P: Deref<Target = str> + ?Sized + 'p,
+    {
+        if let Some(err) = unknown_paths::<Self, _, _>(paths.clone()).next() {
+            return Err(err);
+        }
+        for path in paths {
+            self.apply_path(src, path);
+        }
+        Ok(())
+    }
+}
+
+/// Return an error for each path of a field mask that doesn't name a field of `M`.
+///
+/// ```
+/// use micropb::field_mask::{unknown_paths, ApplyFieldMask};
+/// # struct Point;
+/// # impl ApplyFieldMask for Point {
+/// #     fn check_path(path: &str) -> Result<(), &str> {
+/// #         match path { "x" | "y" => Ok(()), _ => Err(micropb::field_mask::split_path(path).0) }
+/// #     }
+/// #     fn apply_path(&mut self, _: &Self, _: &str) {}
+/// # }
+///
+/// let paths = ["x", "z", "w.y"];
+/// let unknown: Vec<_> = unknown_paths::<Point, _, _>(&paths).map(|e| e.path).collect();
+/// assert_eq!(unknown, ["z", "w.y"]);
+/// ```
+pub fn unknown_paths<'p, M, I, P>(paths: I) -> impl Iterator<Item = FieldMaskError<'p>>
+where
+    M: ApplyFieldMask + ?Sized,
+    I: IntoIterator<Item = &'p P>,
+    P: Deref<Target = str> + ?Sized + 'p,
+{
+    paths.into_iter().filter_map(|path| {
+        let path: &'p str = path;
+        M::check_path(path)
+            .err()
+            .map(|segment| FieldMaskError { path, segment })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Default, Clone, PartialEq)]
+    struct Inner {
+        val: u32,
+        list: [u8; 2],
+    }
+
+    impl ApplyFieldMask for Inner {
+        fn check_path(path: &str) -> Result<(), &str> {
+            match split_path(path) {
+                ("val" | "list", None) => Ok(()),
+                ("val" | "list", Some(rest)) => Err(split_path(rest).0),
+                (name, _) => Err(name),
+            }
+        }
+
+        fn apply_path(&mut self, src: &Self, path: &str) {
+            match path {
+                "val" => self.val = src.val,
+                "list" => self.list = src.list,
+                _ => {}
+            }
+        }
+    }
+
+    #[derive(Debug, Default, Clone, PartialEq)]
+    struct Outer {
+        id: u32,
+        inner: Inner,
+    }
+
+    impl ApplyFieldMask for Outer {
+        fn check_path(path: &str) -> Result<(), &str> {
+            match split_path(path) {
+                ("id", None) | ("inner", None) => Ok(()),
+                ("id", Some(rest)) => Err(split_path(rest).0),
+                ("inner", Some(rest)) => Inner::check_path(rest),
+                (name, _) => Err(name),
+            }
+        }
+
+        fn apply_path(&mut self, src: &Self, path: &str) {
+            match split_path(path) {
+                ("id", None) => self.id = src.id,
+                ("inner", None) => self.inner = src.inner.clone(),
+                ("inner", Some(rest)) => self.inner.apply_path(&src.inner, rest),
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn split() {
+        assert_eq!(split_path("a.b.c"), ("a", Some("b.c")));
+        assert_eq!(split_path("a."), ("a", Some("")));
+        assert_eq!(split_path(""), ("", None));
+    }
+
+    #[test]
+    fn apply() {
+        let src = Outer {
+            id: 7,
+            inner: Inner {
+                val: 3,
+                list: [1, 2],
+            },
+        };
+
+        let mut dst = Outer::default();
+        dst.apply_field_mask(&src, &["inner.val"]).unwrap();
+        assert_eq!(dst.id, 0);
+        assert_eq!(
+            dst.inner,
+            Inner {
+                val: 3,
+                list: [0; 2]
+            }
+        );
+
+        let mut dst = Outer::default();
+        let paths = ["id".to_owned(), "inner".to_owned()];
+        dst.apply_field_mask(&src, &paths).unwrap();
+        assert_eq!(dst, src);
+
+        // Empty mask copies nothing
+        let mut dst = Outer::default();
+        dst.apply_field_mask(&src, &[] as &[&str]).unwrap();
+        assert_eq!(dst, Outer::default());
+    }
+
+    #[test]
+    fn unknown() {
+        let src = Outer {
+            id: 7,
+            ..Default::default()
+        };
+        let mut dst = Outer::default();
+        let err = dst
+            .apply_field_mask(&src, &["id", "inner.size"])
+            .unwrap_err();
+        assert_eq!(
+            err,
+            FieldMaskError {
+                path: "inner.size",
+                segment: "size"
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "unknown field \"size\" in field mask path \"inner.size\""
+        );
+        // Valid paths aren't applied if any path is unknown
+        assert_eq!(dst, Outer::default());
+
+        let paths = ["id.x", "inner", "", "inner..val", "name"];
+        let errs: Vec<_> = unknown_paths::<Outer, _, _>(&paths)
+            .map(|e| (e.path, e.segment))
+            .collect();
+        assert_eq!(
+            errs,
+            [
+                ("id.x", "x"),
+                ("", ""),
+                ("inner..val", ""),
+                ("name", "name")
+            ]
+        );
+    }
+}
@@ -0,0 +1,410 @@
+//! Framing of encoded messages with a length prefix and a checksum, for transports that need to
+//! detect corrupted data, such as UARTs or radio links.
+//!
+//! Each frame consists of the length of the encoded message as a varint, followed by the encoded
+//! message, followed by a checksum of the encoded message. The checksum is computed by a
+//! [`FrameCrc`] and written in little-endian byte order, regardless of the target's endianness.
+//! The length prefix isn't covered by the checksum, but a corrupted length causes the checksum to
+//! be read from the wrong position, which is detected as a mismatch.
+//!
+//! This requires the `framing` feature.
+//!
+//! # Example
+//!
+//! ```
+//! use micropb::framing::{decode_framed, encode_framed, Crc16Ccitt};
+//! # use micropb::{MessageEncode, MessageDecode, PbEncoder, PbWrite, PbDecoder, PbRead, DecodeError};
+//! # #[derive(Default)]
+//! # struct ProtoMessage;
+//! # impl MessageEncode for ProtoMessage {
+//! #   fn encode<W: PbWrite>(&self, _: &mut PbEncoder<W>) -> Result<(), micropb::EncodeError<W::Error>> { Ok(()) }
+//! #   fn compute_size(&self) -> usize { 0 }
+//! # }
+//! # impl MessageDecode for ProtoMessage {
+//! #   fn decode<R: PbRead>(&mut self, _: &mut PbDecoder<R>, _: usize) -> Result<(), DecodeError<R::Error>> { Ok(()) }
+//! # }
+//!
+//! let mut tx = Vec::new();
+//! encode_framed(&ProtoMessage, &mut tx, Crc16Ccitt::new())?;
+//! // Length prefix, followed by the empty message and its CRC
+//! assert_eq!(tx, [0x00, 0xFF, 0xFF]);
+//!
+//! let mut msg = ProtoMessage::default();
+//! let frame_len = decode_framed(&mut msg, &tx, Crc16Ccitt::new()).unwrap();
+//! assert_eq!(frame_len, tx.len());
+//! # Ok::<(), micropb::EncodeError<micropb::Never>>(())
+//! ```
+
+#[cfg(feature = "encode")]
+use crate::{
+    size::{sizeof_len_record, MAX_LEN},
+    EncodeError, MessageEncode, PbEncoder, PbWrite,
+};
+#[cfg(feature = "decode")]
+use crate::{DecodeError, MessageDecode, PbDecoder};
+
+/// Checksum algorithm used to protect frames.
+///
+/// Implemented by [`Crc16Ccitt`] and [`Crc32`]. Implement it for other algorithms to use them with
+/// [`encode_framed`] and [`decode_framed`].
+pub trait FrameCrc {
+    /// Number of bytes of the checksum on the wire, which must be at most 4.
+    const LEN: usize;
+
+    /// Add `data` to the checksum.
+    fn update(&mut self, data: &[u8]);
+
+    /// Return the checksum of all data added so far.
+    ///
+    /// Only the lowest [`LEN`](Self::LEN) bytes are written to the wire.
+    fn finish(&self) -> u32;
+}
+
+/// Return the checksum in its wire format
+#[cfg(any(feature = "encode", feature = "decode"))]
+fn crc_bytes<C: FrameCrc>(crc: &C) -> [u8; 4] {
+    crc.finish().to_le_bytes()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// CRC-16/CCITT-FALSE, with polynomial `0x1021`, initial value `0xFFFF`, no reflection, and no
+/// final XOR.
+///
+/// Computed bit-by-bit without lookup tables to keep code size small.
+pub struct Crc16Ccitt(u16);
+
+impl Crc16Ccitt {
+    /// Start a new checksum.
+    pub const fn new() -> Self {
+        Self(0xFFFF)
+    }
+}
+
+impl Default for Crc16Ccitt {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameCrc for Crc16Ccitt {
+    const LEN: usize = 2;
+
+    fn update(&mut self, data: &[u8]) {
+        for &b in data {
+            self.0 ^= (b as u16) << 8;
+            for _ in 0..8 {
+                self.0 = if self.0 & 0x8000 != 0 {
+                    (self.0 << 1) ^ 0x1021
+                } else {
+                    self.0 << 1
+                };
+            }
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        self.0 as u32
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// CRC-32 as used by Ethernet and zlib, with reflected polynomial `0xEDB88320`, initial value
+/// `0xFFFFFFFF`, and final XOR `0xFFFFFFFF`.
+///
+/// Computed bit-by-bit without lookup tables to keep code size small.
+pub struct Crc32(u32);
+
+impl Crc32 {
+    /// Start a new checksum.
+    pub const fn new() -> Self {
+        Self(0xFFFFFFFF)
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameCrc for Crc32 {
+    const LEN: usize = 4;
+
+    fn update(&mut self, data: &[u8]) {
+        for &b in data {
+            self.0 ^= b as u32;
+            for _ in 0..8 {
+                self.0 = if self.0 & 1 != 0 {
+                    (self.0 >> 1) ^ 0xEDB88320
+                } else {
+                    self.0 >> 1
+                };
+            }
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+/// Writer that adds everything written to a checksum
+#[cfg(feature = "encode")]
+struct CrcWriter<W, C> {
+    writer: W,
+    crc: C,
+}
+
+// Length prefixes can't be backpatched, since the checksum is computed as the bytes are written,
+// so `pb_reserve_len` isn't forwarded
+#[cfg(feature = "encode")]
+impl<W: PbWrite, C: FrameCrc> PbWrite for CrcWriter<W, C> {
+    type Error = W::Error;
+
+    #[inline]
+    fn pb_write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.writer.pb_write(data)?;
+        self.crc.update(data);
+        Ok(())
+    }
+
+    #[inline]
+    fn pb_reserve(&mut self, additional: usize) -> Result<(), Self::Error> {
+        self.writer.pb_reserve(additional)
+    }
+}
+
+/// Encode a message as a frame, consisting of its length prefix, the message, and its checksum.
+///
+/// The checksum is computed as the message is written, so the message isn't buffered. The whole
+/// frame is reserved in the writer beforehand, so fixed-capacity writers fail before anything is
+/// written if the frame doesn't fit.
+#[cfg(feature = "encode")]
+pub fn encode_framed<M: MessageEncode + ?Sized, W: PbWrite, C: FrameCrc>(
+    msg: &M,
+    writer: W,
+    crc: C,
+) -> Result<(), EncodeError<W::Error>> {
+    let len = msg.compute_size();
+    if len > MAX_LEN {
+        return Err(EncodeError::TooLarge);
+    }
+    let mut encoder = PbEncoder::new(writer);
+    encoder.reserve(sizeof_len_record(len).saturating_add(C::LEN))?;
+    encoder.encode_len(len)?;
+
+    let mut encoder = PbEncoder::new(CrcWriter {
+        writer: encoder.into_writer(),
+        crc,
+    });
+    msg.encode(&mut encoder)?;
+    let CrcWriter { writer, crc } = encoder.into_writer();
+    PbEncoder::new(writer).write(&crc_bytes(&crc)[..C::LEN])
+}
+
+/// Decode a frame written by [`encode_framed`] from the start of `data`, merging the message into
+/// `msg`, and return the length of the frame.
+///
+/// The checksum is validated before the message is decoded, so `msg` is left untouched if the
+/// frame is corrupted. Any bytes after the frame are ignored, so the returned length can be used
+/// to find the next frame in `data`.
+///
+/// # Errors
+///
+/// If the checksum doesn't match the message, return [`DecodeError::CrcMismatch`]. If `data`
+/// ends before the end of the frame, return [`DecodeError::UnexpectedEof`].
+#[cfg(feature = "decode")]
+pub fn decode_framed<M: MessageDecode + ?Sized, C: FrameCrc>(
+    msg: &mut M,
+    data: &[u8],
+    mut crc: C,
+) -> Result<usize, DecodeError<crate::Never>> {
+    let mut decoder = PbDecoder::new(data);
+    let len = decoder.decode_len()?;
+    let start = decoder.bytes_read();
+    let end = start.checked_add(len).ok_or(DecodeError::UnexpectedEof)?;
+    let frame_end = end.checked_add(C::LEN).ok_or(DecodeError::UnexpectedEof)?;
+    let payload = data.get(start..end).ok_or(DecodeError::UnexpectedEof)?;
+    let checksum = data.get(end..frame_end).ok_or(DecodeError::UnexpectedEof)?;
+
+    crc.update(payload);
+    if checksum != &crc_bytes(&crc)[..C::LEN] {
+        return Err(DecodeError::CrcMismatch);
+    }
+    msg.merge_from_bytes(payload)?;
+    Ok(frame_end)
+}
+
+#[cfg(all(test, feature = "encode", feature = "decode"))]
+mod tests {
+    use crate::{PbRead, Tag, WIRE_TYPE_LEN};
+
+    use super::*;
+
+    #[derive(Debug, Default, PartialEq)]
+    struct Msg<'a>(&'a str);
+
+    impl MessageEncode for Msg<'_> {
+        fn encode<W: PbWrite>(
+            &self,
+            encoder: &mut PbEncoder<W>,
+        ) -> Result<(), EncodeError<W::Error>> {
+            encoder.encode_tag(Tag::from_parts(1, WIRE_TYPE_LEN))?;
+            encoder.encode_string(self.0)
+        }
+
+        fn compute_size(&self) -> usize {
+            1 + sizeof_len_record(self.0.len())
+        }
+    }
+
+    /// Decoded form of `Msg`, which owns its string
+    #[derive(Debug, Default, PartialEq)]
+    struct OwnedMsg(String);
+
+    impl MessageDecode for OwnedMsg {
+        fn decode<R: PbRead>(
+            &mut self,
+            decoder: &mut PbDecoder<R>,
+            len: usize,
+        ) -> Result<(), DecodeError<R::Error>> {
+            let before = decoder.bytes_read();
+            while decoder.bytes_read() - before < len {
+                let tag = decoder.decode_tag()?;
+                match tag.field_num() {
+                    1 => decoder.decode_string(&mut self.0, crate::Presence::Implicit)?,
+                    _ => decoder.skip_wire_value(tag.wire_type())?,
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn checksum<C: FrameCrc>(mut crc: C, data: &[u8]) -> u32 {
+        crc.update(data);
+        crc.finish()
+    }
+
+    #[test]
+    fn check_values() {
+        assert_eq!(checksum(Crc16Ccitt::new(), b"123456789"), 0x29B1);
+        assert_eq!(checksum(Crc32::new(), b"123456789"), 0xCBF43926);
+        assert_eq!(checksum(Crc32::new(), b""), 0);
+
+        // Updating in pieces is the same as updating in one go
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.finish(), 0xCBF43926);
+    }
+
+    fn round_trip<C: FrameCrc + Copy>(crc: C) -> Vec<u8> {
+        let mut frames = Vec::new();
+        encode_framed(&Msg("hi"), &mut frames, crc).unwrap();
+        let first_len = frames.len();
+        assert_eq!(first_len, 1 + 4 + C::LEN);
+        encode_framed(&Msg("there"), &mut frames, crc).unwrap();
+
+        // Frames can be decoded one after another
+        let mut msg = OwnedMsg::default();
+        assert_eq!(decode_framed(&mut msg, &frames, crc), Ok(first_len));
+        assert_eq!(msg.0, "hi");
+        let mut msg = OwnedMsg::default();
+        let second = &frames[first_len..];
+        assert_eq!(decode_framed(&mut msg, second, crc), Ok(second.len()));
+        assert_eq!(msg.0, "there");
+
+        frames.truncate(first_len);
+        frames
+    }
+
+    #[test]
+    fn framed() {
+        let frame = round_trip(Crc16Ccitt::new());
+        let crc = checksum(Crc16Ccitt::new(), &frame[1..5]);
+        // Checksum is written in little-endian order
+        assert_eq!(frame, [4, 0x0A, 2, b'h', b'i', crc as u8, (crc >> 8) as u8]);
+
+        let frame = round_trip(Crc32::new());
+        assert_eq!(
+            &frame[5..],
+            checksum(Crc32::new(), &frame[1..5]).to_le_bytes()
+        );
+    }
+
+    #[test]
+    fn corrupted() {
+        let frame = round_trip(Crc32::new());
+        for i in 1..frame.len() {
+            for bit in 0..8 {
+                let mut corrupted = frame.clone();
+                corrupted[i] ^= 1 << bit;
+                // The message isn't touched if the frame is corrupted
+                let mut msg = OwnedMsg::default();
+                assert_eq!(
+                    decode_framed(&mut msg, &corrupted, Crc32::new()),
+                    Err(DecodeError::CrcMismatch),
+                    "byte {i}, bit {bit}"
+                );
+                assert_eq!(msg, OwnedMsg::default());
+            }
+        }
+
+        // Corrupted length makes the checksum be read from the wrong place
+        let mut corrupted = frame.clone();
+        corrupted[0] = 3;
+        let mut msg = OwnedMsg::default();
+        assert_eq!(
+            decode_framed(&mut msg, &corrupted, Crc32::new()),
+            Err(DecodeError::CrcMismatch)
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let frame = round_trip(Crc16Ccitt::new());
+        for len in 0..frame.len() {
+            let mut msg = OwnedMsg::default();
+            assert_eq!(
+                decode_framed(&mut msg, &frame[..len], Crc16Ccitt::new()),
+                Err(DecodeError::UnexpectedEof)
+            );
+        }
+    }
+
+    #[test]
+    fn writer_full() {
+        // The whole frame is reserved up front, so nothing is written if it doesn't fit
+        let mut buf = [0; 8];
+        let mut writer = crate::SliceWriter::new(&mut buf);
+        assert!(encode_framed(&Msg("hi"), &mut writer, Crc32::new()).is_err());
+        assert_eq!(writer.as_written(), &[]);
+    }
+
+    #[test]
+    fn custom_crc() {
+        /// Sum of all bytes
+        struct Sum(u8);
+
+        impl FrameCrc for Sum {
+            const LEN: usize = 1;
+
+            fn update(&mut self, data: &[u8]) {
+                for &b in data {
+                    self.0 = self.0.wrapping_add(b);
+                }
+            }
+
+            fn finish(&self) -> u32 {
+                self.0 as u32
+            }
+        }
+
+        let mut frame = Vec::new();
+        encode_framed(&Msg("ab"), &mut frame, Sum(0)).unwrap();
+        assert_eq!(frame, [4, 0x0A, 2, b'a', b'b', 0x0C + b'a' + b'b']);
+        let mut msg = OwnedMsg::default();
+        assert_eq!(decode_framed(&mut msg, &frame, Sum(0)), Ok(frame.len()));
+        assert_eq!(msg.0, "ab");
+    }
+}
@@ -0,0 +1,393 @@
+//! Length-prefixed message framing used by gRPC.
+//!
+//! Each frame consists of a 1-byte compressed flag, followed by the length of the encoded message
+//! as a 4-byte big-endian integer, followed by the encoded message. Only uncompressed frames are
+//! supported, so frames are always written with a flag of 0, and frames with any other flag are
+//! rejected with [`DecodeError::CompressedFrame`]. Transporting the frames, such as over HTTP/2,
+//! is left to the application.
+//!
+//! This requires the `grpc` feature.
+//!
+//! # Example
+//!
+//! ```
+//! use micropb::grpc::{decode_grpc_frame, encode_grpc_frame};
+//! # use micropb::{MessageEncode, MessageDecode, PbEncoder, PbWrite, PbDecoder, PbRead, DecodeError};
+//! # #[derive(Default)]
+//! # struct ProtoMessage;
+//! # impl MessageEncode for ProtoMessage {
+//! #   fn encode<W: PbWrite>(&self, _: &mut PbEncoder<W>) -> Result<(), micropb::EncodeError<W::Error>> { Ok(()) }
+//! #   fn compute_size(&self) -> usize { 0 }
+//! # }
+//! # impl MessageDecode for ProtoMessage {
+//! #   fn decode<R: PbRead>(&mut self, _: &mut PbDecoder<R>, _: usize) -> Result<(), DecodeError<R::Error>> { Ok(()) }
+//! # }
+//!
+//! let mut body = Vec::new();
+//! encode_grpc_frame(&ProtoMessage, &mut body)?;
+//! // Uncompressed flag, followed by the length of the empty message
+//! assert_eq!(body, [0, 0, 0, 0, 0]);
+//!
+//! let msg: ProtoMessage = decode_grpc_frame(body.as_slice(), 4096).unwrap();
+//! # Ok::<(), micropb::EncodeError<micropb::Never>>(())
+//! ```
+
+#[cfg(feature = "decode")]
+use core::marker::PhantomData;
+
+#[cfg(feature = "decode")]
+use crate::{DecodeError, MessageDecode, PbDecoder, PbRead};
+#[cfg(feature = "encode")]
+use crate::{EncodeError, MessageEncode, PbEncoder, PbWrite};
+
+/// Length of the header in front of each frame, consisting of the compressed flag and the length
+/// of the message
+pub const GRPC_HEADER_LEN: usize = 5;
+
+/// Encode a message as a gRPC frame, consisting of the header and the message.
+///
+/// The whole frame is reserved in the writer beforehand, so fixed-capacity writers fail before
+/// anything is written if the frame doesn't fit. Returns [`EncodeError::TooLarge`] if the length of the
+/// message doesn't fit in the 32-bit length of the header.
+#[cfg(feature = "encode")]
+pub fn encode_grpc_frame<M: MessageEncode + ?Sized, W: PbWrite>(
+    msg: &M,
+    writer: W,
+) -> Result<(), EncodeError<W::Error>> {
+    let len = msg.compute_size();
+    let len_bytes = u32::try_from(len)
+        .map_err(|_| EncodeError::TooLarge)?
+        .to_be_bytes();
+    let mut encoder = PbEncoder::new(writer);
+    encoder.reserve(GRPC_HEADER_LEN.saturating_add(len))?;
+    encoder.write(&[0])?;
+    encoder.write(&len_bytes)?;
+    msg.encode(&mut encoder)
+}
+
+/// Decode the header and message of the frame at the start of the decoder's input
+#[cfg(feature = "decode")]
+fn decode_frame<M: MessageDecode + Default, R: PbRead>(
+    decoder: &mut PbDecoder<R>,
+    max_len: usize,
+) -> Result<M, DecodeError<R::Error>> {
+    if decoder.get_byte()? != 0 {
+        return Err(DecodeError::CompressedFrame);
+    }
+    let mut len_bytes = [0; 4];
+    for b in &mut len_bytes {
+        *b = decoder.get_byte()?;
+    }
+    let len = u32::from_be_bytes(len_bytes) as usize;
+    if len > max_len {
+        return Err(DecodeError::SizeLimit);
+    }
+    let before = decoder.bytes_read();
+    let msg = decoder.decode_message(len)?;
+    if decoder.bytes_read() - before != len {
+        return Err(DecodeError::WrongLen);
+    }
+    Ok(msg)
+}
+
+/// Decode a gRPC frame written by [`encode_grpc_frame`] from `reader`, and return its message.
+///
+/// Any input after the frame is left unread.
+///
+/// # Errors
+///
+/// If the compressed flag is set, return [`DecodeError::CompressedFrame`]. If the length in the
+/// header exceeds `max_len`, return [`DecodeError::SizeLimit`] before any of the message is read.
+/// If the input ends before the end of the frame, return [`DecodeError::UnexpectedEof`].
+#[cfg(feature = "decode")]
+pub fn decode_grpc_frame<M: MessageDecode + Default, R: PbRead>(
+    reader: R,
+    max_len: usize,
+) -> Result<M, DecodeError<R::Error>> {
+    decode_frame(&mut PbDecoder::new(reader), max_len)
+}
+
+#[derive(Debug)]
+/// Reader for a stream of consecutive gRPC frames, such as the body of a streaming call.
+///
+/// Messages are decoded one at a time via [`next_message`](Self::next_message), or via the
+/// iterator returned by [`messages`](Self::messages).
+///
+/// # Example
+///
+/// ```
+/// use micropb::{grpc::GrpcStream, PbRead, PbDecoder, MessageDecode, DecodeError};
+///
+/// # #[derive(Default)]
+/// # struct ProtoMessage;
+/// # impl MessageDecode for ProtoMessage {
+/// #   fn decode<R: PbRead>(&mut self, decoder: &mut PbDecoder<R>, len: usize) -> Result<(), DecodeError<R::Error>> {
+/// #       decoder.skip_bytes(len)
+/// #   }
+/// # }
+/// // Two frames with empty messages
+/// let data = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+/// let mut stream = GrpcStream::new(data.as_slice()).max_len(4096);
+///
+/// while let Some(msg) = stream.next_message::<ProtoMessage>()? {
+///     // Process message
+/// }
+/// # Ok::<(), DecodeError<micropb::Never>>(())
+/// ```
+#[cfg(feature = "decode")]
+pub struct GrpcStream<R: PbRead> {
+    decoder: PbDecoder<R>,
+    max_len: usize,
+}
+
+#[cfg(feature = "decode")]
+impl<R: PbRead> GrpcStream<R> {
+    /// Construct a new stream from a [`PbRead`] with no limit on message length.
+    pub fn new(reader: R) -> Self {
+        Self {
+            decoder: PbDecoder::new(reader),
+            max_len: usize::MAX,
+        }
+    }
+
+    /// Set the maximum allowed length of each message in the stream.
+    ///
+    /// If the length in a frame's header exceeds this value, [`DecodeError::SizeLimit`] is
+    /// returned before any of the message is read.
+    pub fn max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Get mutable reference to the underlying decoder.
+    pub fn decoder(&mut self) -> &mut PbDecoder<R> {
+        &mut self.decoder
+    }
+
+    /// Transform the stream into the underlying decoder.
+    pub fn into_decoder(self) -> PbDecoder<R> {
+        self.decoder
+    }
+
+    /// Decode the message of the next frame in the stream.
+    ///
+    /// Returns `None` if the stream is at EOF before the start of the next frame. Reaching EOF in
+    /// the middle of a frame, including its header, returns [`DecodeError::UnexpectedEof`].
+    pub fn next_message<M: MessageDecode + Default>(
+        &mut self,
+    ) -> Result<Option<M>, DecodeError<R::Error>> {
+        if self.decoder.is_eof()? {
+            return Ok(None);
+        }
+        decode_frame(&mut self.decoder, self.max_len).map(Some)
+    }
+
+    /// Return an iterator over the messages in the stream.
+    ///
+    /// The iterator stops after the stream reaches EOF or after the first error.
+    pub fn messages<M: MessageDecode + Default>(&mut self) -> GrpcMessages<'_, R, M> {
+        GrpcMessages {
+            stream: self,
+            done: false,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+#[derive(Debug)]
+/// Iterator over the messages in a [`GrpcStream`].
+///
+/// Returned from [`GrpcStream::messages`].
+#[cfg(feature = "decode")]
+pub struct GrpcMessages<'a, R: PbRead, M> {
+    stream: &'a mut GrpcStream<R>,
+    done: bool,
+    _phantom: PhantomData<M>,
+}
+
+#[cfg(feature = "decode")]
+impl<'a, R: PbRead, M: MessageDecode + Default> Iterator for GrpcMessages<'a, R, M> {
+    type Item = Result<M, DecodeError<R::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let res = self.stream.next_message().transpose();
+        if !matches!(res, Some(Ok(_))) {
+            self.done = true;
+        }
+        res
+    }
+}
+
+#[cfg(all(test, feature = "encode", feature = "decode"))]
+mod tests {
+    use crate::{size::sizeof_len_record, Never, Tag, WIRE_TYPE_LEN};
+
+    use super::*;
+
+    #[derive(Debug, Default, PartialEq)]
+    struct Msg(String);
+
+    impl MessageEncode for Msg {
+        fn encode<W: PbWrite>(
+            &self,
+            encoder: &mut PbEncoder<W>,
+        ) -> Result<(), EncodeError<W::Error>> {
+            encoder.encode_tag(Tag::from_parts(1, WIRE_TYPE_LEN))?;
+            encoder.encode_string(&self.0)
+        }
+
+        fn compute_size(&self) -> usize {
+            1 + sizeof_len_record(self.0.len())
+        }
+    }
+
+    impl MessageDecode for Msg {
+        fn decode<R: PbRead>(
+            &mut self,
+            decoder: &mut PbDecoder<R>,
+            len: usize,
+        ) -> Result<(), DecodeError<R::Error>> {
+            let before = decoder.bytes_read();
+            while decoder.bytes_read() - before < len {
+                let tag = decoder.decode_tag()?;
+                match tag.field_num() {
+                    1 => decoder.decode_string(&mut self.0, crate::Presence::Implicit)?,
+                    _ => decoder.skip_wire_value(tag.wire_type())?,
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn msg(s: &str) -> Msg {
+        Msg(s.to_owned())
+    }
+
+    const HI_FRAME: [u8; 9] = [0, 0, 0, 0, 4, 0x0A, 2, b'h', b'i'];
+
+    #[test]
+    fn encode() {
+        let mut frame = Vec::new();
+        encode_grpc_frame(&msg("hi"), &mut frame).unwrap();
+        assert_eq!(frame, HI_FRAME);
+
+        let mut frame = Vec::new();
+        encode_grpc_frame(&msg(""), &mut frame).unwrap();
+        assert_eq!(frame, [0, 0, 0, 0, 2, 0x0A, 0]);
+
+        // The whole frame is reserved up front, so nothing is written if it doesn't fit
+        let mut buf = [0; 8];
+        let mut writer = crate::SliceWriter::new(&mut buf);
+        assert!(encode_grpc_frame(&msg("hi"), &mut writer).is_err());
+        assert_eq!(writer.as_written(), &[]);
+    }
+
+    #[test]
+    fn decode() {
+        // Input after the frame is ignored
+        let mut data = HI_FRAME.to_vec();
+        data.extend_from_slice(&[0xFF; 3]);
+        assert_eq!(decode_grpc_frame(data.as_slice(), 4), Ok(msg("hi")));
+
+        // Length of the message is big-endian
+        let mut data = vec![0, 0, 0, 1, 3, 0x0A, 0x80, 0x02];
+        data.extend_from_slice(&[b'x'; 256]);
+        assert_eq!(
+            decode_grpc_frame(data.as_slice(), usize::MAX),
+            Ok(msg(&"x".repeat(256)))
+        );
+    }
+
+    #[test]
+    fn compressed() {
+        let mut frame = HI_FRAME;
+        frame[0] = 1;
+        assert_eq!(
+            decode_grpc_frame::<Msg, _>(frame.as_slice(), usize::MAX),
+            Err(DecodeError::CompressedFrame)
+        );
+        // Flags other than 0 and 1 aren't valid either
+        frame[0] = 0x80;
+        assert_eq!(
+            decode_grpc_frame::<Msg, _>(frame.as_slice(), usize::MAX),
+            Err(DecodeError::CompressedFrame)
+        );
+    }
+
+    #[test]
+    fn size_limit() {
+        assert_eq!(
+            decode_grpc_frame::<Msg, _>(HI_FRAME.as_slice(), 3),
+            Err(DecodeError::SizeLimit)
+        );
+        // The limit is checked before reading the message, so a huge length isn't trusted
+        let frame = [0, 0xFF, 0xFF, 0xFF, 0xFF];
+        assert_eq!(
+            decode_grpc_frame::<Msg, _>(frame.as_slice(), 1024),
+            Err(DecodeError::SizeLimit)
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        // Truncated header, including a truncated length
+        for len in 0..GRPC_HEADER_LEN {
+            assert_eq!(
+                decode_grpc_frame::<Msg, _>(&HI_FRAME[..len], usize::MAX),
+                Err(DecodeError::UnexpectedEof),
+                "{len}"
+            );
+        }
+        // Truncated message
+        for len in GRPC_HEADER_LEN + 1..HI_FRAME.len() {
+            assert_eq!(
+                decode_grpc_frame::<Msg, _>(&HI_FRAME[..len], usize::MAX),
+                Err(DecodeError::UnexpectedEof),
+                "{len}"
+            );
+        }
+    }
+
+    #[test]
+    fn stream() {
+        let mut data = Vec::new();
+        for s in ["a", "", "bcd"] {
+            encode_grpc_frame(&msg(s), &mut data).unwrap();
+        }
+        let mut stream = GrpcStream::new(data.as_slice()).max_len(16);
+        let msgs: Result<Vec<Msg>, _> = stream.messages().collect();
+        assert_eq!(msgs.unwrap(), [msg("a"), msg(""), msg("bcd")]);
+        assert_eq!(stream.next_message::<Msg>(), Ok(None));
+
+        // Stops after the first error, such as a frame that's cut off
+        data.extend_from_slice(&HI_FRAME[..7]);
+        let mut stream = GrpcStream::new(data.as_slice());
+        let msgs: Vec<Result<Msg, DecodeError<Never>>> = stream.messages().collect();
+        assert_eq!(msgs.len(), 4);
+        assert_eq!(msgs[3], Err(DecodeError::UnexpectedEof));
+
+        // Frames with compressed flags are rejected in the middle of a stream
+        let mut data = HI_FRAME.to_vec();
+        data.extend_from_slice(&[1, 0, 0, 0, 0]);
+        let mut stream = GrpcStream::new(data.as_slice());
+        assert_eq!(stream.next_message(), Ok(Some(msg("hi"))));
+        assert_eq!(
+            stream.next_message::<Msg>(),
+            Err(DecodeError::CompressedFrame)
+        );
+    }
+
+    #[test]
+    fn std_reader() {
+        let mut data = Vec::new();
+        encode_grpc_frame(&msg("hi"), &mut data).unwrap();
+        encode_grpc_frame(&msg("there"), &mut data).unwrap();
+        let mut stream = GrpcStream::new(crate::StdReader(data.as_slice()));
+        assert_eq!(stream.next_message().unwrap(), Some(msg("hi")));
+        assert_eq!(stream.next_message().unwrap(), Some(msg("there")));
+        assert_eq!(stream.next_message::<Msg>().unwrap(), None);
+    }
+}
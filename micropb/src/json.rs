@@ -0,0 +1,1265 @@
+//! Serialization of messages into and from the canonical Protobuf JSON format.
+//!
+//! Generated messages implement [`MessageToJson`] and [`MessageFromJson`] if the `json` config is
+//! set in `micropb-gen`.
+//! The output follows the proto3 JSON mapping: fields are keyed by their JSON names, enums are
+//! written as their names, `bytes` as base64, 64-bit integers as strings, and non-finite floats
+//! as `"NaN"`, `"Infinity"`, and `"-Infinity"`. Fields without presence that hold their default
+//! values are omitted, as are empty repeated and `map` fields.
+//!
+//! The output is written into any [`fmt::Write`], so it works without allocation by writing into
+//! a fixed-capacity string. Use [`PbFmtWriter`] to write into a [`PbWrite`](crate::PbWrite)
+//! instead.
+//!
+//! Parsing accepts both the original field names and the JSON names as keys, enums as names or
+//! numbers, `bytes` in both the standard and URL-safe base64 alphabets, and integers as numbers or
+//! strings. `null` resets a field to its default value. [`JsonParser`] parses straight into the
+//! fields' containers, so fixed-capacity containers that run out of space return
+//! [`JsonErrorKind::Capacity`] along with the key of the offending entry.
+//!
+//! This requires the `json` feature.
+//!
+//! # Example
+//!
+//! ```
+//! use core::fmt::Write;
+//! use micropb::json::{JsonObject, MessageToJson};
+//!
+//! // Normally implemented by the generated code
+//! struct Sample { seq: u64 }
+//! impl MessageToJson for Sample {
+//!     fn write_json<W: Write + ?Sized>(&self, w: &mut W) -> core::fmt::Result {
+//!         let mut obj = JsonObject::begin(w)?;
+//!         micropb::json::write_num_str(obj.key("seq")?, self.seq)?;
+//!         obj.end()
+//!     }
+//! }
+//!
+//! let mut out = String::new();
+//! Sample { seq: 5 }.write_json(&mut out)?;
+//! assert_eq!(out, r#"{"seq":"5"}"#);
+//! # Ok::<(), core::fmt::Error>(())
+//! ```
+
+use core::{
+    fmt::{self, Display, Write},
+    mem::MaybeUninit,
+};
+
+use crate::container::{PbString, PbVec};
+
+/// Protobuf message that can be written as JSON.
+pub trait MessageToJson {
+    /// Write the message as a JSON object into `w`.
+    fn write_json<W: Write + ?Sized>(&self, w: &mut W) -> fmt::Result;
+}
+
+/// Protobuf enum that's written as JSON by its name.
+pub trait JsonEnum {
+    /// Name of the enum value in the `.proto` file, or `None` if the value is unknown.
+    fn json_name(&self) -> Option<&'static str>;
+
+    /// Integer value of the enum, written in place of the name for unknown values.
+    fn json_value(&self) -> i32;
+
+    /// Enum value with the given name in the `.proto` file, or `None` if there's no such value.
+    fn from_json_name(name: &str) -> Option<Self>
+    where
+        Self: Sized;
+
+    /// Enum value with the given integer value, which may be unknown.
+    fn from_json_value(val: i32) -> Self
+    where
+        Self: Sized;
+}
+
+/// Writer of a JSON object, which takes care of the separators between entries.
+pub struct JsonObject<'a, W: ?Sized> {
+    w: &'a mut W,
+    empty: bool,
+}
+
+impl<'a, W: Write + ?Sized> JsonObject<'a, W> {
+    /// Start writing an object into `w`.
+    pub fn begin(w: &'a mut W) -> Result<Self, fmt::Error> {
+        w.write_char('{')?;
+        Ok(Self { w, empty: true })
+    }
+
+    fn separator(&mut self) -> fmt::Result {
+        if !self.empty {
+            self.w.write_char(',')?;
+        }
+        self.empty = false;
+        Ok(())
+    }
+
+    /// Write the key of the next entry, returning the writer for its value.
+    pub fn key(&mut self, key: &str) -> Result<&mut W, fmt::Error> {
+        self.separator()?;
+        write_str(self.w, key)?;
+        self.w.write_char(':')?;
+        Ok(self.w)
+    }
+
+    /// Write a key that doesn't need escaping, such as an integer `map` key, returning the
+    /// writer for its value.
+    pub fn key_display<T: Display>(&mut self, key: T) -> Result<&mut W, fmt::Error> {
+        self.separator()?;
+        write!(self.w, "\"{key}\":")?;
+        Ok(self.w)
+    }
+
+    /// Finish the object.
+    pub fn end(self) -> fmt::Result {
+        self.w.write_char('}')
+    }
+}
+
+/// Writer of a JSON array, which takes care of the separators between elements.
+pub struct JsonArray<'a, W: ?Sized> {
+    w: &'a mut W,
+    empty: bool,
+}
+
+impl<'a, W: Write + ?Sized> JsonArray<'a, W> {
+    /// Start writing an array into `w`.
+    pub fn begin(w: &'a mut W) -> Result<Self, fmt::Error> {
+        w.write_char('[')?;
+        Ok(Self { w, empty: true })
+    }
+
+    /// Start the next element, returning the writer for its value.
+    pub fn elem(&mut self) -> Result<&mut W, fmt::Error> {
+        if !self.empty {
+            self.w.write_char(',')?;
+        }
+        self.empty = false;
+        Ok(self.w)
+    }
+
+    /// Finish the array.
+    pub fn end(self) -> fmt::Result {
+        self.w.write_char(']')
+    }
+}
+
+/// Write a number that's represented as a JSON number, such as a 32-bit integer.
+pub fn write_num<W: Write + ?Sized, T: Display>(w: &mut W, val: T) -> fmt::Result {
+    write!(w, "{val}")
+}
+
+/// Write a number that's represented as a JSON string, such as a 64-bit integer.
+pub fn write_num_str<W: Write + ?Sized, T: Display>(w: &mut W, val: T) -> fmt::Result {
+    write!(w, "\"{val}\"")
+}
+
+/// Write a `bool`.
+pub fn write_bool<W: Write + ?Sized>(w: &mut W, val: bool) -> fmt::Result {
+    w.write_str(if val { "true" } else { "false" })
+}
+
+/// Write a `float`, with non-finite values written as strings.
+pub fn write_f32<W: Write + ?Sized>(w: &mut W, val: f32) -> fmt::Result {
+    if val.is_finite() {
+        write!(w, "{val}")
+    } else {
+        write_f64(w, val as f64)
+    }
+}
+
+/// Write a `double`, with non-finite values written as strings.
+pub fn write_f64<W: Write + ?Sized>(w: &mut W, val: f64) -> fmt::Result {
+    if val.is_nan() {
+        w.write_str("\"NaN\"")
+    } else if val == f64::INFINITY {
+        w.write_str("\"Infinity\"")
+    } else if val == f64::NEG_INFINITY {
+        w.write_str("\"-Infinity\"")
+    } else {
+        write!(w, "{val}")
+    }
+}
+
+/// Write an escaped string.
+pub fn write_str<W: Write + ?Sized>(w: &mut W, val: &str) -> fmt::Result {
+    w.write_char('"')?;
+    write_escaped(w, val)?;
+    w.write_char('"')
+}
+
+/// Write a string stored as bytes, replacing invalid UTF-8 sequences with `U+FFFD`.
+pub fn write_str_bytes<W: Write + ?Sized>(w: &mut W, mut val: &[u8]) -> fmt::Result {
+    w.write_char('"')?;
+    loop {
+        match core::str::from_utf8(val) {
+            Ok(s) => {
+                write_escaped(w, s)?;
+                break;
+            }
+            Err(e) => {
+                let (valid, invalid) = val.split_at(e.valid_up_to());
+                write_escaped(w, core::str::from_utf8(valid).map_err(|_| fmt::Error)?)?;
+                w.write_char(char::REPLACEMENT_CHARACTER)?;
+                val = &invalid[e.error_len().unwrap_or(invalid.len())..];
+            }
+        }
+    }
+    w.write_char('"')
+}
+
+/// Write the contents of a string, without the quotes
+fn write_escaped<W: Write + ?Sized>(w: &mut W, val: &str) -> fmt::Result {
+    let mut start = 0;
+    for (i, c) in val.char_indices() {
+        let escape = match c {
+            '"' => "\\\"",
+            '\\' => "\\\\",
+            '\n' => "\\n",
+            '\r' => "\\r",
+            '\t' => "\\t",
+            c if c < ' ' => "",
+            _ => continue,
+        };
+        w.write_str(&val[start..i])?;
+        if escape.is_empty() {
+            write!(w, "\\u{:04x}", c as u32)?;
+        } else {
+            w.write_str(escape)?;
+        }
+        start = i + c.len_utf8();
+    }
+    w.write_str(&val[start..])
+}
+
+/// Write bytes as a base64 string, using the standard alphabet with padding.
+pub fn write_bytes<W: Write + ?Sized>(w: &mut W, val: &[u8]) -> fmt::Result {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    w.write_char('"')?;
+    for chunk in val.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            let c = if i <= chunk.len() {
+                ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize]
+            } else {
+                b'='
+            };
+            w.write_char(c as char)?;
+        }
+    }
+    w.write_char('"')
+}
+
+/// Write an enum value as its name, or as its integer value if it's unknown.
+pub fn write_enum<W: Write + ?Sized, E: JsonEnum>(w: &mut W, val: E) -> fmt::Result {
+    match val.json_name() {
+        Some(name) => write_str(w, name),
+        None => write_num(w, val.json_value()),
+    }
+}
+
+/// Protobuf message that can be parsed from JSON.
+pub trait MessageFromJson {
+    /// Parse the value of the object entry with `key` into the matching field of the message.
+    ///
+    /// Returns `false` without consuming the value if `key` doesn't match any field.
+    fn merge_json_field<'a>(
+        &mut self,
+        key: &str,
+        parser: &mut JsonParser<'a>,
+    ) -> Result<bool, JsonError<'a>>;
+
+    /// Parse a JSON object and merge its entries into the message.
+    fn merge_json<'a>(&mut self, parser: &mut JsonParser<'a>) -> Result<(), JsonError<'a>> {
+        parser.parse_object(|parser, key| {
+            if !self.merge_json_field(key, parser)? {
+                parser.unknown_field()?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Parse a message from a JSON document, rejecting unknown fields.
+    ///
+    /// To ignore unknown fields instead, configure a [`JsonParser`] and pass it to
+    /// [`merge_json`](Self::merge_json).
+    fn from_json(input: &str) -> Result<Self, JsonError<'_>>
+    where
+        Self: Default + Sized,
+    {
+        let mut msg = Self::default();
+        let mut parser = JsonParser::new(input);
+        msg.merge_json(&mut parser)?;
+        parser.finish()?;
+        Ok(msg)
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Kind of [`JsonError`].
+pub enum JsonErrorKind {
+    /// Input isn't valid JSON
+    Syntax,
+    /// JSON value has the wrong type for the field, such as a string for a `bool` field
+    WrongType,
+    /// Number is malformed or out of range for the field's type
+    InvalidNumber,
+    /// `bytes` value isn't valid base64
+    Base64,
+    /// Enum name isn't declared in the Protobuf enum
+    UnknownEnumValue,
+    /// Object key doesn't match any field, and the parser doesn't ignore unknown fields
+    UnknownField,
+    /// Exceeded capacity of fixed container for `string`, `bytes`, repeated, or `map` field, or
+    /// an escaped object key is longer than [`JsonParser::MAX_ESCAPED_KEY_LEN`]
+    Capacity,
+    /// Nesting depth of objects and arrays exceeded the parser's `max_depth`
+    DepthLimit,
+}
+
+impl Display for JsonErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            JsonErrorKind::Syntax => "invalid JSON",
+            JsonErrorKind::WrongType => "wrong JSON value type for field",
+            JsonErrorKind::InvalidNumber => "invalid number",
+            JsonErrorKind::Base64 => "invalid base64",
+            JsonErrorKind::UnknownEnumValue => "unknown enum value",
+            JsonErrorKind::UnknownField => "unknown field",
+            JsonErrorKind::Capacity => "field exceeded capacity of fixed container",
+            JsonErrorKind::DepthLimit => "nesting depth exceeded limit",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Error from parsing JSON, along with where it occurred.
+pub struct JsonError<'a> {
+    /// What went wrong
+    pub kind: JsonErrorKind,
+    /// Byte offset into the input where the error was detected
+    pub offset: usize,
+    /// Key of the innermost object entry containing the error, as it appears in the input. This
+    /// is the field name for most errors, or the map key for errors inside `map` entries.
+    pub key: Option<&'a str>,
+}
+
+impl Display for JsonError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.key {
+            Some(key) => write!(f, "{} at key \"{key}\", offset {}", self.kind, self.offset),
+            None => write!(f, "{} at offset {}", self.kind, self.offset),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", feature = "core-error"))]
+impl crate::ErrorTrait for JsonError<'_> {}
+
+/// Parser of JSON values, used by [`MessageFromJson`] implementations.
+///
+/// The parser works on a borrowed string and doesn't allocate. Values are parsed directly into
+/// the containers of the message fields.
+///
+/// # Example
+///
+/// ```
+/// use micropb::json::JsonParser;
+///
+/// let mut parser = JsonParser::new(r#"{"nums": ["1", 2, 3e0], "other": null}"#);
+/// let mut sum = 0;
+/// parser.parse_object(|parser, key| match key {
+///     "nums" => parser.parse_array(|parser| {
+///         sum += parser.parse_int::<u32>()?;
+///         Ok(())
+///     }),
+///     _ => parser.skip_value(),
+/// })?;
+/// parser.finish()?;
+/// assert_eq!(sum, 6);
+/// # Ok::<(), micropb::json::JsonError>(())
+/// ```
+pub struct JsonParser<'a> {
+    input: &'a str,
+    pos: usize,
+    depth: usize,
+    /// If this flag is set, object entries with keys that don't match any field are skipped.
+    /// Otherwise, they return [`JsonErrorKind::UnknownField`].
+    pub ignore_unknown_fields: bool,
+    /// Maximum nesting depth of objects and arrays. Parsing a value nested deeper than this
+    /// returns [`JsonErrorKind::DepthLimit`], which prevents malicious inputs from overflowing
+    /// the stack.
+    ///
+    /// Defaults to [`DEFAULT_MAX_DEPTH`](Self::DEFAULT_MAX_DEPTH).
+    pub max_depth: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    /// Default value of [`max_depth`](Self::max_depth).
+    pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+    /// Maximum length of object keys that contain escape sequences, which have to be unescaped
+    /// into a buffer on the stack before they're matched.
+    pub const MAX_ESCAPED_KEY_LEN: usize = 64;
+
+    /// Construct a parser over `input`.
+    pub fn new(input: &'a str) -> Self {
+        Self {
+            input,
+            pos: 0,
+            depth: 0,
+            ignore_unknown_fields: false,
+            max_depth: Self::DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    /// Byte offset of the parser within the input.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Construct an error of `kind` at the parser's current position.
+    pub fn error(&self, kind: JsonErrorKind) -> JsonError<'a> {
+        JsonError {
+            kind,
+            offset: self.pos,
+            key: None,
+        }
+    }
+
+    /// Check that only whitespace is left in the input.
+    pub fn finish(&mut self) -> Result<(), JsonError<'a>> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(self.error(JsonErrorKind::Syntax)),
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        let rest = &self.input.as_bytes()[self.pos..];
+        let ws = rest
+            .iter()
+            .take_while(|b| matches!(b, b' ' | b'\t' | b'\n' | b'\r'))
+            .count();
+        self.pos += ws;
+        rest.get(ws).copied()
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), JsonError<'a>> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(JsonErrorKind::Syntax))
+        }
+    }
+
+    fn eat_literal(&mut self, lit: &str) -> bool {
+        self.peek();
+        let found = self.input[self.pos..].starts_with(lit);
+        if found {
+            self.pos += lit.len();
+        }
+        found
+    }
+
+    /// Consume a `null` if it's the next value. Returns whether `null` was consumed.
+    pub fn parse_null(&mut self) -> Result<bool, JsonError<'a>> {
+        Ok(self.eat_literal("null"))
+    }
+
+    /// Parse a `true` or `false`.
+    pub fn parse_bool(&mut self) -> Result<bool, JsonError<'a>> {
+        if self.eat_literal("true") {
+            Ok(true)
+        } else if self.eat_literal("false") {
+            Ok(false)
+        } else {
+            Err(self.error(JsonErrorKind::WrongType))
+        }
+    }
+
+    /// Scan a string and return its contents without the quotes and with the escape sequences
+    /// still in place.
+    fn scan_string(&mut self) -> Result<&'a str, JsonError<'a>> {
+        if self.peek() != Some(b'"') {
+            return Err(self.error(JsonErrorKind::WrongType));
+        }
+        let bytes = self.input.as_bytes();
+        let start = self.pos + 1;
+        let mut i = start;
+        loop {
+            match bytes.get(i) {
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    let len = match bytes.get(i + 1) {
+                        Some(b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't') => 2,
+                        Some(b'u')
+                            if bytes
+                                .get(i + 2..i + 6)
+                                .is_some_and(|h| h.iter().all(u8::is_ascii_hexdigit)) =>
+                        {
+                            6
+                        }
+                        _ => {
+                            self.pos = i;
+                            return Err(self.error(JsonErrorKind::Syntax));
+                        }
+                    };
+                    i += len;
+                }
+                Some(0x20..) => i += 1,
+                // Control characters must be escaped
+                _ => {
+                    self.pos = i;
+                    return Err(self.error(JsonErrorKind::Syntax));
+                }
+            }
+        }
+        self.pos = i + 1;
+        Ok(&self.input[start..i])
+    }
+
+    /// Scan a number, or a string if `quoted` is allowed, and return its text.
+    fn scan_number(&mut self, quoted: bool) -> Result<&'a str, JsonError<'a>> {
+        let start = self.pos;
+        let text = match self.peek() {
+            Some(b'"') if quoted => self.scan_string()?,
+            Some(b'-' | b'0'..=b'9') => {
+                let len = self.input.as_bytes()[self.pos..]
+                    .iter()
+                    .take_while(|b| matches!(b, b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E'))
+                    .count();
+                self.pos += len;
+                &self.input[self.pos - len..self.pos]
+            }
+            _ => return Err(self.error(JsonErrorKind::WrongType)),
+        };
+        if is_json_number(text) {
+            Ok(text)
+        } else {
+            self.pos = start;
+            Err(self.error(JsonErrorKind::InvalidNumber))
+        }
+    }
+
+    /// Parse an integer from a number or a string.
+    ///
+    /// Numbers in exponent or decimal notation are accepted if they have integral values.
+    pub fn parse_int<T: TryFrom<i128>>(&mut self) -> Result<T, JsonError<'a>> {
+        let text = self.scan_number(true)?;
+        int_from_str(text).ok_or_else(|| self.error(JsonErrorKind::InvalidNumber))
+    }
+
+    /// Parse a `double` from a number or a string, including `"NaN"`, `"Infinity"`, and
+    /// `"-Infinity"`.
+    pub fn parse_f64(&mut self) -> Result<f64, JsonError<'a>> {
+        for (lit, val) in [
+            ("\"NaN\"", f64::NAN),
+            ("\"Infinity\"", f64::INFINITY),
+            ("\"-Infinity\"", f64::NEG_INFINITY),
+        ] {
+            if self.eat_literal(lit) {
+                return Ok(val);
+            }
+        }
+        let text = self.scan_number(true)?;
+        match text.parse::<f64>() {
+            Ok(val) if val.is_finite() => Ok(val),
+            _ => Err(self.error(JsonErrorKind::InvalidNumber)),
+        }
+    }
+
+    /// Parse a `float` from a number or a string, including `"NaN"`, `"Infinity"`, and
+    /// `"-Infinity"`.
+    pub fn parse_f32(&mut self) -> Result<f32, JsonError<'a>> {
+        let val = self.parse_f64()?;
+        let val32 = val as f32;
+        if val.is_finite() && !val32.is_finite() {
+            return Err(self.error(JsonErrorKind::InvalidNumber));
+        }
+        Ok(val32)
+    }
+
+    /// Parse an enum from its name or its integer value.
+    pub fn parse_enum<E: JsonEnum>(&mut self) -> Result<E, JsonError<'a>> {
+        if self.peek() == Some(b'"') {
+            // Enum names never need escaping
+            let name = self.scan_string()?;
+            E::from_json_name(name).ok_or_else(|| self.error(JsonErrorKind::UnknownEnumValue))
+        } else {
+            Ok(E::from_json_value(self.parse_int()?))
+        }
+    }
+
+    /// Parse a string into a [`PbString`], replacing its contents.
+    pub fn parse_str<S: PbString>(&mut self, string: &mut S) -> Result<(), JsonError<'a>> {
+        let raw = self.scan_string()?;
+        string.pb_clear();
+        string.pb_reserve(raw.len());
+        let len = unescape_into(raw, string.pb_spare_cap()).map_err(|kind| self.error(kind))?;
+        // SAFETY: `unescape_into` wrote `len` bytes of UTF-8 into the spare capacity
+        unsafe { string.pb_set_len(len) };
+        Ok(())
+    }
+
+    /// Parse a string into a [`PbVec<u8>`], replacing its contents. This is used for `string`
+    /// fields that are stored as bytes.
+    pub fn parse_str_bytes<V: PbVec<u8>>(&mut self, vec: &mut V) -> Result<(), JsonError<'a>> {
+        let raw = self.scan_string()?;
+        vec.pb_clear();
+        vec.pb_reserve(raw.len());
+        let len = unescape_into(raw, vec.pb_spare_cap()).map_err(|kind| self.error(kind))?;
+        // SAFETY: `unescape_into` initialized `len` bytes of the spare capacity
+        unsafe { vec.pb_set_len(len) };
+        Ok(())
+    }
+
+    /// Parse a base64 string into a [`PbVec<u8>`], replacing its contents. Both the standard and
+    /// the URL-safe alphabets are accepted, with or without padding.
+    pub fn parse_bytes<V: PbVec<u8>>(&mut self, vec: &mut V) -> Result<(), JsonError<'a>> {
+        let raw = self.scan_string()?;
+        vec.pb_clear();
+        vec.pb_reserve(raw.len() / 4 * 3 + 2);
+        let len = decode_base64_into(raw, vec.pb_spare_cap()).map_err(|kind| self.error(kind))?;
+        // SAFETY: `decode_base64_into` initialized `len` bytes of the spare capacity
+        unsafe { vec.pb_set_len(len) };
+        Ok(())
+    }
+
+    fn enter(&mut self, open: u8) -> Result<(), JsonError<'a>> {
+        if !self.eat(open) {
+            return Err(self.error(JsonErrorKind::WrongType));
+        }
+        self.depth += 1;
+        if self.depth > self.max_depth {
+            return Err(self.error(JsonErrorKind::DepthLimit));
+        }
+        Ok(())
+    }
+
+    /// Parse an array, calling `f` to parse each element.
+    pub fn parse_array(
+        &mut self,
+        mut f: impl FnMut(&mut Self) -> Result<(), JsonError<'a>>,
+    ) -> Result<(), JsonError<'a>> {
+        self.enter(b'[')?;
+        if !self.eat(b']') {
+            loop {
+                f(self)?;
+                if !self.eat(b',') {
+                    self.expect(b']')?;
+                    break;
+                }
+            }
+        }
+        self.depth -= 1;
+        Ok(())
+    }
+
+    /// Parse an object, calling `f` with the unescaped key of each entry to parse its value.
+    ///
+    /// Errors returned by `f` are tagged with the key of the entry, unless they already have a
+    /// key from a nested object.
+    pub fn parse_object(
+        &mut self,
+        mut f: impl FnMut(&mut Self, &str) -> Result<(), JsonError<'a>>,
+    ) -> Result<(), JsonError<'a>> {
+        self.enter(b'{')?;
+        if !self.eat(b'}') {
+            loop {
+                if self.peek() != Some(b'"') {
+                    return Err(self.error(JsonErrorKind::Syntax));
+                }
+                let raw_key = self.scan_string()?;
+                self.expect(b':')?;
+                let mut buf = [MaybeUninit::uninit(); Self::MAX_ESCAPED_KEY_LEN];
+                let key = if raw_key.contains('\\') {
+                    let len = unescape_into(raw_key, &mut buf).map_err(|kind| JsonError {
+                        key: Some(raw_key),
+                        ..self.error(kind)
+                    })?;
+                    // SAFETY: `unescape_into` wrote `len` bytes of UTF-8 into the buffer
+                    unsafe {
+                        core::str::from_utf8_unchecked(
+                            crate::misc::maybe_uninit_slice_assume_init_ref(&buf[..len]),
+                        )
+                    }
+                } else {
+                    raw_key
+                };
+                f(self, key).map_err(|mut e| {
+                    e.key.get_or_insert(raw_key);
+                    e
+                })?;
+                if !self.eat(b',') {
+                    self.expect(b'}')?;
+                    break;
+                }
+            }
+        }
+        self.depth -= 1;
+        Ok(())
+    }
+
+    /// Skip over the next value, including all of its nested values.
+    pub fn skip_value(&mut self) -> Result<(), JsonError<'a>> {
+        match self.peek() {
+            Some(b'{') => self.parse_object(|parser, _| parser.skip_value()),
+            Some(b'[') => self.parse_array(|parser| parser.skip_value()),
+            Some(b'"') => self.scan_string().map(|_| ()),
+            Some(b't' | b'f') => self.parse_bool().map(|_| ()),
+            Some(b'n') if self.parse_null()? => Ok(()),
+            _ => self
+                .scan_number(false)
+                .map(|_| ())
+                .map_err(|_| self.error(JsonErrorKind::Syntax)),
+        }
+    }
+
+    /// Handle the value of an object entry whose key doesn't match any field, by either skipping
+    /// the value or returning [`JsonErrorKind::UnknownField`], depending on
+    /// [`ignore_unknown_fields`](Self::ignore_unknown_fields).
+    pub fn unknown_field(&mut self) -> Result<(), JsonError<'a>> {
+        if self.ignore_unknown_fields {
+            self.skip_value()
+        } else {
+            Err(self.error(JsonErrorKind::UnknownField))
+        }
+    }
+
+    /// Parse an integer `map` key.
+    pub fn parse_key_int<T: TryFrom<i128>>(&self, key: &str) -> Result<T, JsonError<'a>> {
+        is_json_number(key)
+            .then(|| int_from_str(key))
+            .flatten()
+            .ok_or_else(|| self.error(JsonErrorKind::InvalidNumber))
+    }
+
+    /// Parse a `bool` `map` key.
+    pub fn parse_key_bool(&self, key: &str) -> Result<bool, JsonError<'a>> {
+        match key {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(self.error(JsonErrorKind::WrongType)),
+        }
+    }
+
+    /// Copy a `string` `map` key into a [`PbString`].
+    pub fn parse_key_str<S: PbString>(&self, key: &str) -> Result<S, JsonError<'a>> {
+        S::pb_from_str(key).map_err(|_| self.error(JsonErrorKind::Capacity))
+    }
+
+    /// Construct a [`JsonErrorKind::Capacity`] error, for when a repeated or `map` field is full.
+    pub fn capacity_error(&self) -> JsonError<'a> {
+        self.error(JsonErrorKind::Capacity)
+    }
+}
+
+/// Whether `s` follows the JSON grammar for numbers.
+fn is_json_number(s: &str) -> bool {
+    let b = s.as_bytes();
+    let digits = |i: &mut usize| {
+        let start = *i;
+        while b.get(*i).is_some_and(u8::is_ascii_digit) {
+            *i += 1;
+        }
+        *i > start
+    };
+    let mut i = 0;
+    if b.first() == Some(&b'-') {
+        i += 1;
+    }
+    match b.get(i) {
+        Some(b'0') => i += 1,
+        Some(b'1'..=b'9') => {
+            digits(&mut i);
+        }
+        _ => return false,
+    }
+    if b.get(i) == Some(&b'.') {
+        i += 1;
+        if !digits(&mut i) {
+            return false;
+        }
+    }
+    if matches!(b.get(i), Some(b'e' | b'E')) {
+        i += 1;
+        if matches!(b.get(i), Some(b'+' | b'-')) {
+            i += 1;
+        }
+        if !digits(&mut i) {
+            return false;
+        }
+    }
+    i == b.len()
+}
+
+/// Convert a valid JSON number into an integer, if it has an integral value that fits into `T`.
+fn int_from_str<T: TryFrom<i128>>(s: &str) -> Option<T> {
+    let n = if s.bytes().all(|b| b == b'-' || b.is_ascii_digit()) {
+        s.parse::<i128>().ok()?
+    } else {
+        let f = s.parse::<f64>().ok()?;
+        let n = f as i128;
+        // Casting saturates, so out-of-range and fractional values don't round-trip
+        if n as f64 != f {
+            return None;
+        }
+        n
+    };
+    T::try_from(n).ok()
+}
+
+fn hex4(s: &str) -> u32 {
+    // Validated by `scan_string`
+    u32::from_str_radix(s, 16).unwrap_or(0)
+}
+
+/// Unescape the contents of a scanned JSON string, passing the result to `f` in chunks.
+fn unescape(
+    raw: &str,
+    mut f: impl FnMut(&str) -> Result<(), JsonErrorKind>,
+) -> Result<(), JsonErrorKind> {
+    let mut rest = raw;
+    while let Some(i) = rest.find('\\') {
+        f(&rest[..i])?;
+        let (c, len) = match rest.as_bytes()[i + 1] {
+            b'b' => ('\u{8}', 2),
+            b'f' => ('\u{c}', 2),
+            b'n' => ('\n', 2),
+            b'r' => ('\r', 2),
+            b't' => ('\t', 2),
+            b'u' => {
+                let hi = hex4(&rest[i + 2..i + 6]);
+                if (0xD800..0xDC00).contains(&hi) && rest[i + 6..].starts_with("\\u") {
+                    let lo = hex4(&rest[i + 8..i + 12]);
+                    if !(0xDC00..0xE000).contains(&lo) {
+                        return Err(JsonErrorKind::Syntax);
+                    }
+                    let c = 0x10000 + ((hi - 0xD800) << 10) + (lo - 0xDC00);
+                    (char::from_u32(c).ok_or(JsonErrorKind::Syntax)?, 12)
+                } else {
+                    // Unpaired surrogates aren't valid chars
+                    (char::from_u32(hi).ok_or(JsonErrorKind::Syntax)?, 6)
+                }
+            }
+            c => (c as char, 2),
+        };
+        f(c.encode_utf8(&mut [0; 4]))?;
+        rest = &rest[i + len..];
+    }
+    f(rest)
+}
+
+/// Unescape a scanned JSON string into `buf`, returning the number of bytes written.
+fn unescape_into(raw: &str, buf: &mut [MaybeUninit<u8>]) -> Result<usize, JsonErrorKind> {
+    let mut len = 0;
+    unescape(raw, |chunk| {
+        let dst = buf
+            .get_mut(len..len + chunk.len())
+            .ok_or(JsonErrorKind::Capacity)?;
+        crate::misc::maybe_uninit_write_slice(dst, chunk.as_bytes());
+        len += chunk.len();
+        Ok(())
+    })?;
+    Ok(len)
+}
+
+/// Decode a scanned base64 string into `buf`, returning the number of bytes written.
+fn decode_base64_into(raw: &str, buf: &mut [MaybeUninit<u8>]) -> Result<usize, JsonErrorKind> {
+    let mut len = 0;
+    let mut acc = 0u32;
+    let mut bits = 0;
+    let mut symbols = 0;
+    let mut padding = 0;
+    unescape(raw, |chunk| {
+        for b in chunk.bytes() {
+            if b == b'=' {
+                padding += 1;
+                continue;
+            }
+            let val = match b {
+                b'A'..=b'Z' => b - b'A',
+                b'a'..=b'z' => b - b'a' + 26,
+                b'0'..=b'9' => b - b'0' + 52,
+                b'+' | b'-' => 62,
+                b'/' | b'_' => 63,
+                _ => return Err(JsonErrorKind::Base64),
+            };
+            // Padding is only allowed at the end
+            if padding > 0 {
+                return Err(JsonErrorKind::Base64);
+            }
+            symbols += 1;
+            acc = (acc << 6) | val as u32;
+            bits += 6;
+            if bits >= 8 {
+                bits -= 8;
+                buf.get_mut(len)
+                    .ok_or(JsonErrorKind::Capacity)?
+                    .write((acc >> bits) as u8);
+                acc &= (1 << bits) - 1;
+                len += 1;
+            }
+        }
+        Ok(())
+    })?;
+    if symbols % 4 == 1 || (padding > 0 && (symbols + padding) % 4 != 0) {
+        return Err(JsonErrorKind::Base64);
+    }
+    Ok(len)
+}
+
+#[cfg(feature = "encode")]
+/// Adapter that implements [`fmt::Write`] for a [`PbWrite`](crate::PbWrite), for writing JSON
+/// into the same outputs as the encoder.
+///
+/// Since [`fmt::Error`] can't carry the error of the writer, the error is stored in the adapter
+/// and can be retrieved with [`error`](Self::error).
+///
+/// # Example
+///
+/// ```
+/// use micropb::json::{write_str, PbFmtWriter};
+///
+/// let mut buf = [0; 4];
+/// let mut w = PbFmtWriter::new(micropb::SliceWriter::new(&mut buf));
+/// // The quotes make the output 6 bytes long
+/// assert!(write_str(&mut w, "long").is_err());
+/// assert_eq!(w.error().unwrap().needed, 1);
+/// ```
+pub struct PbFmtWriter<W: crate::PbWrite> {
+    writer: W,
+    error: Option<W::Error>,
+}
+
+#[cfg(feature = "encode")]
+impl<W: crate::PbWrite> PbFmtWriter<W> {
+    /// Wrap a [`PbWrite`](crate::PbWrite).
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            error: None,
+        }
+    }
+
+    /// Error returned by the last failed write, if any.
+    pub fn error(&self) -> Option<&W::Error> {
+        self.error.as_ref()
+    }
+
+    /// Transform the adapter into the underlying writer.
+    pub fn into_writer(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(feature = "encode")]
+impl<W: crate::PbWrite> Write for PbFmtWriter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.writer.pb_write(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_string(f: impl FnOnce(&mut String) -> fmt::Result) -> String {
+        let mut out = String::new();
+        f(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(to_string(|w| write_str(w, "abc")), r#""abc""#);
+        assert_eq!(
+            to_string(|w| write_str(w, "a\"b\\c\nd\u{1}é")),
+            r#""a\"b\\c\nd\u0001é""#
+        );
+        assert_eq!(to_string(|w| write_str_bytes(w, b"a\"b")), r#""a\"b""#);
+        assert_eq!(
+            to_string(|w| write_str_bytes(w, b"a\xFFb\xE2\x82")),
+            "\"a\u{FFFD}b\u{FFFD}\""
+        );
+    }
+
+    #[test]
+    fn base64() {
+        assert_eq!(to_string(|w| write_bytes(w, b"")), r#""""#);
+        assert_eq!(to_string(|w| write_bytes(w, b"f")), r#""Zg==""#);
+        assert_eq!(to_string(|w| write_bytes(w, b"fo")), r#""Zm8=""#);
+        assert_eq!(to_string(|w| write_bytes(w, b"foo")), r#""Zm9v""#);
+        assert_eq!(to_string(|w| write_bytes(w, b"foobar")), r#""Zm9vYmFy""#);
+        assert_eq!(to_string(|w| write_bytes(w, &[0xFB, 0xFF])), r#""+/8=""#);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(to_string(|w| write_num(w, -5)), "-5");
+        assert_eq!(
+            to_string(|w| write_num_str(w, u64::MAX)),
+            r#""18446744073709551615""#
+        );
+        assert_eq!(to_string(|w| write_f64(w, 1.5)), "1.5");
+        assert_eq!(to_string(|w| write_f32(w, 0.1)), "0.1");
+        assert_eq!(to_string(|w| write_f64(w, f64::NAN)), r#""NaN""#);
+        assert_eq!(to_string(|w| write_f32(w, f32::INFINITY)), r#""Infinity""#);
+        assert_eq!(
+            to_string(|w| write_f64(w, f64::NEG_INFINITY)),
+            r#""-Infinity""#
+        );
+        assert_eq!(to_string(|w| write_bool(w, false)), "false");
+    }
+
+    #[test]
+    fn containers() {
+        let out = to_string(|w| {
+            let mut obj = JsonObject::begin(w)?;
+            let mut arr = JsonArray::begin(obj.key("list")?)?;
+            write_num(arr.elem()?, 1)?;
+            write_num(arr.elem()?, 2)?;
+            arr.end()?;
+            let mut map = JsonObject::begin(obj.key("map")?)?;
+            write_bool(map.key_display(-3)?, true)?;
+            map.end()?;
+            JsonObject::begin(obj.key("empty")?)?.end()?;
+            obj.end()
+        });
+        assert_eq!(out, r#"{"list":[1,2],"map":{"-3":true},"empty":{}}"#);
+    }
+
+    #[test]
+    fn enums() {
+        struct Enum(i32);
+        impl JsonEnum for Enum {
+            fn json_name(&self) -> Option<&'static str> {
+                (self.0 == 1).then_some("ONE")
+            }
+
+            fn json_value(&self) -> i32 {
+                self.0
+            }
+
+            fn from_json_name(name: &str) -> Option<Self> {
+                (name == "ONE").then_some(Enum(1))
+            }
+
+            fn from_json_value(val: i32) -> Self {
+                Enum(val)
+            }
+        }
+
+        assert_eq!(to_string(|w| write_enum(w, Enum(1))), r#""ONE""#);
+        assert_eq!(to_string(|w| write_enum(w, Enum(7))), "7");
+
+        let mut parser = JsonParser::new(r#"["ONE", 7, "TWO"]"#);
+        let mut vals = vec![];
+        let err = parser
+            .parse_array(|parser| {
+                vals.push(parser.parse_enum::<Enum>()?.0);
+                Ok(())
+            })
+            .unwrap_err();
+        assert_eq!(vals, [1, 7]);
+        assert_eq!(err.kind, JsonErrorKind::UnknownEnumValue);
+    }
+
+    #[test]
+    fn parse_scalars() {
+        let mut parser = JsonParser::new(r#" true null -12 "34" 1.5e1 "1e2" "-0.5" "NaN" 1e400 "#);
+        assert!(parser.parse_bool().unwrap());
+        assert!(parser.parse_null().unwrap());
+        assert_eq!(parser.parse_int::<i8>().unwrap(), -12);
+        assert_eq!(parser.parse_int::<u64>().unwrap(), 34);
+        assert_eq!(parser.parse_int::<i32>().unwrap(), 15);
+        assert_eq!(parser.parse_f32().unwrap(), 100.0);
+        assert_eq!(parser.parse_f64().unwrap(), -0.5);
+        assert!(parser.parse_f64().unwrap().is_nan());
+        assert_eq!(
+            parser.parse_f64().unwrap_err().kind,
+            JsonErrorKind::InvalidNumber
+        );
+
+        for input in ["300", "1.5", "\"01\"", "-", "\" 1\""] {
+            let err = JsonParser::new(input).parse_int::<u8>().unwrap_err();
+            assert_eq!(err.kind, JsonErrorKind::InvalidNumber, "{input}");
+        }
+        assert_eq!(
+            JsonParser::new("\"1\"").parse_bool().unwrap_err().kind,
+            JsonErrorKind::WrongType
+        );
+        assert_eq!(
+            JsonParser::new("1e39").parse_f32().unwrap_err().kind,
+            JsonErrorKind::InvalidNumber
+        );
+        assert_eq!(
+            JsonParser::new("18446744073709551615")
+                .parse_int::<u64>()
+                .unwrap(),
+            u64::MAX
+        );
+    }
+
+    #[test]
+    fn parse_strings() {
+        let mut parser = JsonParser::new(r#""a\"b\\c\/\n\u00e9\ud83d\ude00" "plain" "\ud800" "a"#);
+        let mut s = String::new();
+        parser.parse_str(&mut s).unwrap();
+        assert_eq!(s, "a\"b\\c/\né😀");
+        let mut bytes = vec![];
+        parser.parse_str_bytes(&mut bytes).unwrap();
+        assert_eq!(bytes, b"plain");
+        assert_eq!(
+            parser.parse_str(&mut s).unwrap_err().kind,
+            JsonErrorKind::Syntax
+        );
+        assert_eq!(
+            parser.parse_str(&mut s).unwrap_err().kind,
+            JsonErrorKind::Syntax
+        );
+        assert_eq!(
+            JsonParser::new("\"\n\"")
+                .parse_str(&mut s)
+                .unwrap_err()
+                .kind,
+            JsonErrorKind::Syntax
+        );
+
+        let mut s = arrayvec::ArrayString::<4>::new();
+        JsonParser::new(r#""\u00e9\u00e9""#)
+            .parse_str(&mut s)
+            .unwrap();
+        assert_eq!(s.as_str(), "éé");
+        assert_eq!(
+            JsonParser::new(r#""abcde""#)
+                .parse_str(&mut s)
+                .unwrap_err()
+                .kind,
+            JsonErrorKind::Capacity
+        );
+    }
+
+    #[test]
+    fn parse_base64() {
+        let decode = |input: &str| {
+            let mut bytes = vec![];
+            JsonParser::new(input)
+                .parse_bytes(&mut bytes)
+                .map(|_| bytes)
+                .map_err(|e| e.kind)
+        };
+        assert_eq!(decode(r#""""#).unwrap(), b"");
+        assert_eq!(decode(r#""Zg==""#).unwrap(), b"f");
+        assert_eq!(decode(r#""Zg""#).unwrap(), b"f");
+        assert_eq!(decode(r#""Zm9vYmFy""#).unwrap(), b"foobar");
+        assert_eq!(decode(r#""+/8=""#).unwrap(), [0xFB, 0xFF]);
+        assert_eq!(decode(r#""-_8""#).unwrap(), [0xFB, 0xFF]);
+        assert_eq!(decode(r#""Z\u006d8=""#).unwrap(), b"fo");
+        assert_eq!(decode(r#""Zg=""#), Err(JsonErrorKind::Base64));
+        assert_eq!(decode(r#""Z""#), Err(JsonErrorKind::Base64));
+        assert_eq!(decode(r#""Zg==Zg==""#), Err(JsonErrorKind::Base64));
+        assert_eq!(decode(r#""Zm 9v""#), Err(JsonErrorKind::Base64));
+    }
+
+    #[test]
+    fn parse_containers() {
+        let input = r#"{ "a" : [1, [], {"x": null}], "b\u0031": {}, "c": "s" }"#;
+        let mut keys = vec![];
+        let mut parser = JsonParser::new(input);
+        parser
+            .parse_object(|parser, key| {
+                keys.push(key.to_owned());
+                parser.skip_value()
+            })
+            .unwrap();
+        parser.finish().unwrap();
+        assert_eq!(keys, ["a", "b1", "c"]);
+
+        for input in [r#"{"a":1,}"#, r#"[1 2]"#, r#"{"a" 1}"#, r#"{a:1}"#, "[1,"] {
+            let err = JsonParser::new(input).skip_value().unwrap_err();
+            assert_eq!(err.kind, JsonErrorKind::Syntax, "{input}");
+        }
+        let mut parser = JsonParser::new("{} {}");
+        parser.skip_value().unwrap();
+        assert_eq!(parser.finish().unwrap_err().offset, 3);
+
+        let mut parser = JsonParser::new("[[[1]]]");
+        parser.max_depth = 2;
+        let err = parser.skip_value().unwrap_err();
+        assert_eq!((err.kind, err.offset), (JsonErrorKind::DepthLimit, 3));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let mut parser = JsonParser::new(r#"{"outer": {"inner": 3}, "next": 1}"#);
+        let err = parser
+            .parse_object(|parser, _| {
+                parser.parse_object(|parser, _| {
+                    parser.parse_int::<u8>()?;
+                    Err(parser.capacity_error())
+                })
+            })
+            .unwrap_err();
+        assert_eq!(err.kind, JsonErrorKind::Capacity);
+        assert_eq!(err.key, Some("inner"));
+        assert_eq!(
+            err.to_string(),
+            r#"field exceeded capacity of fixed container at key "inner", offset 21"#
+        );
+
+        let mut parser = JsonParser::new(r#"{"known": 1, "unknown": [{}]}"#);
+        fn parse<'a>(parser: &mut JsonParser<'a>) -> Result<(), JsonError<'a>> {
+            parser.parse_object(|parser, key| match key {
+                "known" => parser.parse_int::<u8>().map(|_| ()),
+                _ => parser.unknown_field(),
+            })
+        }
+        let err = parse(&mut parser).unwrap_err();
+        assert_eq!(err.kind, JsonErrorKind::UnknownField);
+        assert_eq!(err.key, Some("unknown"));
+
+        let mut parser = JsonParser::new(r#"{"known": 1, "unknown": [{}]}"#);
+        parser.ignore_unknown_fields = true;
+        parse(&mut parser).unwrap();
+    }
+
+    #[test]
+    fn error_kind_display() {
+        let cases = [
+            (JsonErrorKind::Syntax, "invalid JSON"),
+            (JsonErrorKind::WrongType, "wrong JSON value type for field"),
+            (JsonErrorKind::InvalidNumber, "invalid number"),
+            (JsonErrorKind::Base64, "invalid base64"),
+            (JsonErrorKind::UnknownEnumValue, "unknown enum value"),
+            (JsonErrorKind::UnknownField, "unknown field"),
+            (
+                JsonErrorKind::Capacity,
+                "field exceeded capacity of fixed container",
+            ),
+            (JsonErrorKind::DepthLimit, "nesting depth exceeded limit"),
+        ];
+        for (kind, msg) in cases {
+            assert_eq!(kind.to_string(), msg);
+        }
+    }
+}
@@ -5,18 +5,59 @@
 #[cfg(feature = "alloc")]
 extern crate alloc;
 
+#[cfg(any(feature = "encode", feature = "decode"))]
 use num_traits::{AsPrimitive, PrimInt};
 
 pub mod container;
 #[cfg(feature = "decode")]
 mod decode;
+#[cfg(all(feature = "decode", feature = "embedded-io-async"))]
+mod decode_async;
+#[cfg(all(feature = "decode", feature = "iter-reader"))]
+mod decode_iter;
+#[cfg(feature = "dynamic")]
+pub mod dynamic;
 #[cfg(feature = "encode")]
 mod encode;
+#[cfg(all(feature = "encode", feature = "embedded-io-async"))]
+mod encode_async;
+#[cfg(feature = "encode")]
+mod encode_reverse;
+#[cfg(feature = "encode")]
+mod encode_stream;
 pub mod field;
+#[cfg(feature = "field-mask")]
+pub mod field_mask;
+#[cfg(feature = "framing")]
+pub mod framing;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "json")]
+pub mod json;
 mod message;
 mod misc;
+#[cfg(feature = "decode")]
+mod raw;
+pub mod service;
 #[cfg(feature = "encode")]
 pub mod size;
+#[cfg(feature = "decode")]
+mod stream;
+#[cfg(feature = "text")]
+pub mod text;
+#[cfg(feature = "well-known")]
+pub mod well_known;
+#[cfg(all(feature = "encode", feature = "decode"))]
+mod unknown;
+
+// `std::error::Error` is a re-export of `core::error::Error` since Rust 1.81, so implementing
+// either one covers both. Unused if none of the modules with error types are enabled.
+#[cfg(feature = "core-error")]
+#[allow(unused_imports)]
+use core::error::Error as ErrorTrait;
+#[cfg(all(feature = "std", not(feature = "core-error")))]
+#[allow(unused_imports)]
+use std::error::Error as ErrorTrait;
 
 #[cfg(feature = "container-arrayvec")]
 pub use ::arrayvec;
@@ -25,17 +66,72 @@ pub use ::heapless;
 
 pub use container::{PbContainer, PbMap, PbString, PbVec};
 #[cfg(feature = "decode")]
-pub use decode::{DecodeError, PbDecoder, PbRead};
+pub use decode::{
+    BufferedReader, DecodeError, DecodeLocation, FieldStats, FieldStatsReader, LocatedDecodeError,
+    OverflowPolicy, PbDecoder, PbRead, UnknownFieldHook,
+};
+#[cfg(all(feature = "decode", feature = "embedded-io"))]
+pub use decode::{EmbeddedIoBufReader, EmbeddedIoReader};
+#[cfg(all(feature = "decode", feature = "std"))]
+pub use decode::{StdBufReader, StdReader};
+#[cfg(all(feature = "decode", feature = "embedded-io-async"))]
+pub use decode_async::{decode_async, decode_len_delimited_async};
+#[cfg(all(feature = "decode", feature = "iter-reader"))]
+pub use decode_iter::{decode_from_iter, IterReader, IterReaderItem};
+#[cfg(all(feature = "encode", feature = "embedded-io"))]
+pub use encode::EmbeddedIoWriter;
+#[cfg(all(feature = "encode", feature = "std"))]
+pub use encode::StdWriter;
 #[cfg(feature = "encode")]
-pub use encode::{PbEncoder, PbWrite};
+pub use encode::{
+    BackpatchWriter, BufferTooSmall, CallbackWriter, DynPbWrite, DynWriteError, EncodeError,
+    PbEncoder, PbVecWriter, PbWrite, SliceWriter, UninitSliceWriter,
+};
+#[cfg(all(feature = "encode", feature = "embedded-io-async"))]
+pub use encode_async::{encode_async, AsyncEncodeError};
+#[cfg(feature = "encode")]
+pub use encode_reverse::ReverseEncoder;
+#[cfg(feature = "encode")]
+pub use encode_stream::{MessageWriteError, MessageWriter};
 #[cfg(feature = "decode")]
 pub use field::FieldDecode;
 #[cfg(feature = "encode")]
 pub use field::FieldEncode;
 #[cfg(feature = "decode")]
-pub use message::MessageDecode;
+pub use message::{DynMessageDecode, MessageDecode};
+pub use message::{
+    ConversionError, FieldDescriptor, FieldLabel, MessageFields, MessageName,
+    DEFAULT_TYPE_URL_PREFIX,
+};
 #[cfg(feature = "encode")]
-pub use message::MessageEncode;
+pub use message::{DynMessageEncode, MessageEncode};
+#[cfg(feature = "decode")]
+pub use raw::{find_field, RawField, RawFields};
+#[cfg(feature = "decode")]
+pub use stream::{MessageStream, Messages};
+#[cfg(all(feature = "encode", feature = "decode"))]
+pub use unknown::UnknownFields;
+
+/// Helpers used by generated code. Not part of the public API.
+#[doc(hidden)]
+pub mod __private {
+    /// Assign the value returned by `init` to `slot`.
+    ///
+    /// Generated decoders use this to initialize oneofs and optional fields. Since this function
+    /// is never inlined, the temporary value is constructed in its own stack frame rather than in
+    /// the frame of the caller's `decode`, which otherwise stays on the stack for every level of a
+    /// nested message.
+    #[inline(never)]
+    pub fn set_outlined<T>(slot: &mut T, init: impl FnOnce() -> T) {
+        *slot = init();
+    }
+}
+
+/// Error type of readers and writers that never fail, such as slices.
+///
+/// This is an alias of [`Infallible`](core::convert::Infallible), so it implements the standard
+/// error trait.
+pub type Never = core::convert::Infallible;
 
 /// Protobuf wire type for varints.
 pub const WIRE_TYPE_VARINT: u8 = 0;
@@ -43,6 +139,10 @@ pub const WIRE_TYPE_VARINT: u8 = 0;
 pub const WIRE_TYPE_I64: u8 = 1;
 /// Protobuf wire type for length-delimited records.
 pub const WIRE_TYPE_LEN: u8 = 2;
+/// Protobuf wire type for the start of a group, which is deprecated.
+pub const WIRE_TYPE_SGROUP: u8 = 3;
+/// Protobuf wire type for the end of a group, which is deprecated.
+pub const WIRE_TYPE_EGROUP: u8 = 4;
 /// Protobuf wire type for fixed 32-bit values.
 pub const WIRE_TYPE_I32: u8 = 5;
 
@@ -77,18 +177,65 @@ impl Tag {
     }
 }
 
+#[cfg(any(feature = "encode", feature = "decode"))]
 trait VarInt: PrimInt + From<u8> + AsPrimitive<u8> {
+    // Only used for decoding
+    #[cfg_attr(not(feature = "decode"), allow(dead_code))]
     const BYTES: u8;
 }
 
+#[cfg(any(feature = "encode", feature = "decode"))]
 impl VarInt for u32 {
     const BYTES: u8 = 5;
 }
 
+#[cfg(any(feature = "encode", feature = "decode"))]
 impl VarInt for u64 {
     const BYTES: u8 = 10;
 }
 
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Element type of packed `fixed32`, `sfixed32`, `fixed64`, `sfixed64`, `float`, and `double`
+/// fields, which are encoded as their little-endian bytes on the wire.
+///
+/// Used by [`PbDecoder::decode_packed_fixed`] and [`PbEncoder::encode_packed_fixed`] to copy packed
+/// fields in bulk. This trait is sealed and can't be implemented outside of this crate.
+pub trait FixedSize: Copy + sealed::Sealed {
+    #[doc(hidden)]
+    type Bytes: AsRef<[u8]> + AsMut<[u8]> + Default;
+
+    #[doc(hidden)]
+    fn from_le(bytes: Self::Bytes) -> Self;
+
+    #[doc(hidden)]
+    fn to_le(self) -> Self::Bytes;
+}
+
+macro_rules! impl_fixed_size {
+    ($($t:ty),+) => {$(
+        impl sealed::Sealed for $t {}
+
+        impl FixedSize for $t {
+            type Bytes = [u8; core::mem::size_of::<$t>()];
+
+            #[inline]
+            fn from_le(bytes: Self::Bytes) -> Self {
+                Self::from_le_bytes(bytes)
+            }
+
+            #[inline]
+            fn to_le(self) -> Self::Bytes {
+                self.to_le_bytes()
+            }
+        }
+    )+};
+}
+
+impl_fixed_size!(u32, i32, f32, u64, i64, f64);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 /// Field presence discipline
 pub enum Presence {
@@ -119,4 +266,41 @@ mod tests {
         assert_eq!(tag.field_num(), 0);
         assert_eq!(tag.wire_type(), 0);
     }
+
+    #[test]
+    #[cfg(all(
+        feature = "encode",
+        feature = "decode",
+        any(feature = "std", feature = "core-error")
+    ))]
+    fn error_traits() {
+        fn is_error<E: ErrorTrait + 'static>(_: &E) {}
+
+        is_error(&decode::DecodeError::<Never>::UnexpectedEof);
+        is_error(&decode::LocatedDecodeError {
+            error: decode::DecodeError::<Never>::Deprecation,
+            location: decode::DecodeLocation {
+                offset: 0,
+                field_num: 1,
+                depth: 0,
+            },
+        });
+        is_error(&encode::BufferTooSmall {
+            written: 0,
+            needed: 1,
+            available: 0,
+        });
+        is_error(&encode::EncodeError::<encode::DynWriteError>::TooLarge);
+        is_error(&MessageWriteError::<Never>::SizeLimit);
+    }
+
+    #[test]
+    #[cfg(all(feature = "decode", feature = "std"))]
+    fn error_source() {
+        let err = decode::DecodeError::Reader(std::io::Error::other("oops"));
+        assert_eq!(err.source().unwrap().to_string(), "oops");
+        assert!(decode::DecodeError::<std::io::Error>::ZeroField
+            .source()
+            .is_none());
+    }
 }
@@ -1,7 +1,19 @@
 #[cfg(feature = "decode")]
 use crate::decode::{DecodeError, PbDecoder, PbRead};
+#[cfg(feature = "decode")]
+use crate::Never;
+#[cfg(any(feature = "encode", feature = "decode"))]
+use crate::PbString;
+#[cfg(feature = "encode")]
+use crate::{
+    encode::{
+        BufferTooSmall, DynPbWrite, DynWriteError, EncodeError, PbEncoder, PbWrite, SliceWriter,
+        UninitSliceWriter,
+    },
+    encode_reverse::ReverseEncoder,
+};
 #[cfg(feature = "encode")]
-use crate::encode::{PbEncoder, PbWrite};
+use core::mem::MaybeUninit;
 
 #[cfg(feature = "decode")]
 /// Protobuf message that can be decoded from the wire.
@@ -11,6 +23,18 @@ pub trait MessageDecode {
     /// Decode an instance of the message from the decoder and merge it into `self`.
     ///
     /// Length of the message needs to be known beforehand.
+    ///
+    /// Decoding never clears `self` beforehand. Instead, the decoded message is merged into
+    /// `self` following Protobuf merge semantics:
+    /// - Scalar, `string`, and `bytes` fields are overwritten by values on the wire.
+    /// - Singular message fields are recursively merged.
+    /// - Repeated fields are appended to, subject to container capacity. Map entries are inserted,
+    ///   overwriting existing keys. Repeated scalar fields are accepted in both packed and
+    ///   unpacked form, regardless of how they're declared.
+    /// - Oneofs are overwritten as a whole, unless the variant on the wire is already active and
+    ///   is a message, in which case the variant is merged.
+    ///
+    /// To decode a fresh message, call this on a default instance of the message.
     fn decode<R: PbRead>(
         &mut self,
         decoder: &mut PbDecoder<R>,
@@ -19,11 +43,66 @@ pub trait MessageDecode {
 
     /// Decode an instance of the message from the decoder as a length-delimited record, starting with a length
     /// prefix.
+    ///
+    /// Each length-delimited message counts as one level of nesting towards the decoder's
+    /// [`max_depth`](PbDecoder::max_depth).
     fn decode_len_delimited<R: PbRead>(
         &mut self,
         decoder: &mut PbDecoder<R>,
     ) -> Result<(), DecodeError<R::Error>> {
-        decoder.decode_len_record(|len, _, decoder| self.decode(decoder, len))
+        decoder.decode_nested(self)
+    }
+
+    /// Decode an instance of the message from the decoder as a length-delimited record, rejecting
+    /// messages longer than `max_len` bytes.
+    ///
+    /// Returns [`DecodeError::SizeLimit`] if the length prefix exceeds `max_len`, before any of
+    /// the message is read. Otherwise this behaves like
+    /// [`decode_len_delimited`](Self::decode_len_delimited), returning
+    /// [`DecodeError::UnexpectedEof`] if the prefix or the message is cut off, and
+    /// [`DecodeError::WrongLen`] if the message doesn't end exactly at the specified length.
+    fn decode_len_delimited_max<R: PbRead>(
+        &mut self,
+        decoder: &mut PbDecoder<R>,
+        max_len: usize,
+    ) -> Result<(), DecodeError<R::Error>> {
+        decoder.decode_len_delimited_max(max_len, |decoder, len| self.decode(decoder, len))
+    }
+
+    /// Reset the message to its default state, while retaining the allocations of its containers.
+    ///
+    /// Calling this before [`decode`](Self::decode) decodes a fresh message without dropping and
+    /// reallocating `string`, `bytes`, repeated, and `map` fields. Messages generated with
+    /// `in_place_clear` enabled reset each field in place. The default implementation simply
+    /// replaces `self` with its default value, which drops the allocations.
+    fn clear(&mut self)
+    where
+        Self: Default,
+    {
+        *self = Self::default();
+    }
+
+    /// Decode a message from a byte slice and merge it into `self`.
+    ///
+    /// The entire slice is treated as the message. See [`decode`](Self::decode) for the merge
+    /// semantics.
+    fn merge_from_bytes(&mut self, bytes: &[u8]) -> Result<(), DecodeError<Never>> {
+        let mut decoder = PbDecoder::new(bytes);
+        self.decode(&mut decoder, bytes.len())
+    }
+
+    /// Decode a length-delimited message from the start of a byte slice and merge it into `self`.
+    ///
+    /// Unlike [`merge_from_bytes`](Self::merge_from_bytes), the slice may contain trailing data
+    /// after the message, such as a checksum. Returns the number of bytes consumed, which is the
+    /// length of the prefix plus the length of the message. The trailing data is left unread.
+    fn merge_len_delimited_from_bytes(
+        &mut self,
+        bytes: &[u8],
+    ) -> Result<usize, DecodeError<Never>> {
+        let mut decoder = PbDecoder::new(bytes);
+        self.decode_len_delimited(&mut decoder)?;
+        Ok(decoder.bytes_read())
     }
 }
 
@@ -50,22 +129,203 @@ impl<T: MessageDecode> MessageDecode for &mut T {
 ///
 /// Implementations are auto-generated by `micropb`.
 pub trait MessageEncode {
+    /// Maximum size of this message on the wire, without a length prefix, or `None` if the size
+    /// is unbounded.
+    ///
+    /// Messages generated with `max_size` enabled have a bounded size if all of their fields do,
+    /// while other messages keep the default of `None`. Scalar fields are bounded by the Rust
+    /// type of the field, while string, bytes, repeated, and map fields are bounded only if they
+    /// have a `max_bytes` or `max_len` setting. Message fields are bounded if the nested message
+    /// is bounded, but boxed fields, custom fields, visited fields, and unknown field handlers
+    /// are always considered unbounded.
+    ///
+    /// This can be used to size encode buffers at compile time:
+    /// ```ignore
+    /// const BUF_LEN: usize = match Msg::MAX_SIZE {
+    ///     Some(size) => size,
+    ///     None => panic!("message is unbounded"),
+    /// };
+    /// let mut buf = [0u8; BUF_LEN];
+    /// ```
+    const MAX_SIZE: Option<usize> = None;
+
     /// Encode this message using the encoder.
-    fn encode<W: PbWrite>(&self, encoder: &mut PbEncoder<W>) -> Result<(), W::Error>;
+    fn encode<W: PbWrite>(&self, encoder: &mut PbEncoder<W>) -> Result<(), EncodeError<W::Error>>;
 
     /// Encode this message as a length-delimited record, starting with a length prefix.
-    fn encode_len_delimited<W: PbWrite>(&self, encoder: &mut PbEncoder<W>) -> Result<(), W::Error> {
-        encoder.encode_varint32(self.compute_size() as u32)?;
-        self.encode(encoder)
+    ///
+    /// The length is computed with [`compute_size`](Self::compute_size), unless the writer fills
+    /// in length prefixes after the message is written, such as
+    /// [`BackpatchWriter`](crate::BackpatchWriter).
+    fn encode_len_delimited<W: PbWrite>(
+        &self,
+        encoder: &mut PbEncoder<W>,
+    ) -> Result<(), EncodeError<W::Error>> {
+        encoder.encode_len_delimited(|| self.compute_size(), |encoder| self.encode(encoder))
+    }
+
+    /// Encode this message as a length-delimited record and return the total number of bytes
+    /// written, including the varint length prefix.
+    ///
+    /// This is the counterpart of [`MessageDecode::decode_len_delimited`] and
+    /// [`MessageDecode::merge_len_delimited_from_bytes`], and is useful for writing a stream of
+    /// messages or framing a message within a larger buffer.
+    ///
+    /// [`MessageDecode::decode_len_delimited`]: crate::MessageDecode::decode_len_delimited
+    /// [`MessageDecode::merge_len_delimited_from_bytes`]: crate::MessageDecode::merge_len_delimited_from_bytes
+    fn write_delimited<W: PbWrite>(
+        &self,
+        encoder: &mut PbEncoder<W>,
+    ) -> Result<usize, EncodeError<W::Error>> {
+        let size = self.compute_size();
+        if size > crate::size::MAX_LEN {
+            return Err(EncodeError::TooLarge);
+        }
+        encoder.reserve(crate::size::sizeof_len_record(size))?;
+        encoder.encode_len(size)?;
+        self.encode(encoder)?;
+        Ok(crate::size::sizeof_len_record(size))
     }
 
-    /// Compute the size of this message on the wire.
+    /// Compute the size of this message on the wire, without encoding it.
+    ///
+    /// This is the exact number of bytes written by [`encode`](Self::encode), which is useful
+    /// for allocating a buffer of the right size before encoding. The size doesn't include a
+    /// length prefix. Use [`compute_size_len_delimited`](Self::compute_size_len_delimited) for
+    /// the size of a length-delimited record.
+    ///
+    /// Generated messages don't cache their sizes, so the size is recomputed from the message's
+    /// current contents on every call, and fields can be mutated freely between encodes. Since
+    /// the length prefix of a nested message is computed right before it's encoded, encoding a
+    /// deeply-nested message computes the sizes of inner messages multiple times. Use
+    /// [`encode_to_slice_reverse`](Self::encode_to_slice_reverse) or
+    /// [`BackpatchWriter`](crate::BackpatchWriter) to avoid this.
     fn compute_size(&self) -> usize;
+
+    /// Compute the size of this message as a length-delimited record, including the varint
+    /// length prefix.
+    ///
+    /// This is the exact number of bytes written by
+    /// [`encode_len_delimited`](Self::encode_len_delimited).
+    fn compute_size_len_delimited(&self) -> usize {
+        crate::size::sizeof_len_record(self.compute_size())
+    }
+
+    /// Encode this message into the start of a byte slice, returning the encoded bytes.
+    ///
+    /// The size of the message is checked before anything is written, so if the slice is too
+    /// small, the slice is left untouched and the returned error states exactly how many more
+    /// bytes are needed.
+    fn encode_to_slice<'a>(&self, buf: &'a mut [u8]) -> Result<&'a mut [u8], BufferTooSmall> {
+        let size = self.compute_size();
+        if size > buf.len() {
+            return Err(BufferTooSmall {
+                written: 0,
+                needed: size - buf.len(),
+                available: buf.len(),
+            });
+        }
+        let mut encoder = PbEncoder::new(SliceWriter::new(buf));
+        self.encode(&mut encoder)?;
+        Ok(encoder.into_writer().into_written())
+    }
+
+    /// Encode this message into the start of a slice of uninitialized memory, returning the
+    /// encoded bytes.
+    ///
+    /// Behaves like [`encode_to_slice`](Self::encode_to_slice), but doesn't require the slice to
+    /// be initialized beforehand. Only the returned bytes are initialized by encoding. If the
+    /// slice is too small, nothing is written.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut buf = [MaybeUninit::uninit(); 64];
+    /// let encoded: &mut [u8] = message.encode_to_uninit_slice(&mut buf)?;
+    /// ```
+    fn encode_to_uninit_slice<'a>(
+        &self,
+        buf: &'a mut [MaybeUninit<u8>],
+    ) -> Result<&'a mut [u8], BufferTooSmall> {
+        let size = self.compute_size();
+        if size > buf.len() {
+            return Err(BufferTooSmall {
+                written: 0,
+                needed: size - buf.len(),
+                available: buf.len(),
+            });
+        }
+        let mut encoder = PbEncoder::new(UninitSliceWriter::new(buf));
+        self.encode(&mut encoder)?;
+        Ok(encoder.into_writer().into_written())
+    }
+
+    /// Encode this message back-to-front into the end of a [`ReverseEncoder`].
+    ///
+    /// Messages generated with `encode_reverse` enabled write their fields in reverse, so that
+    /// nested messages are encoded in a single pass without computing their sizes beforehand.
+    /// The default implementation computes the size of the message, reserves that many bytes in
+    /// the encoder, and encodes the message forwards into them with [`encode`](Self::encode).
+    fn encode_reverse(&self, encoder: &mut ReverseEncoder<'_>) -> Result<(), BufferTooSmall> {
+        let buf = encoder.reserve(self.compute_size())?;
+        self.encode(&mut PbEncoder::new(SliceWriter::new(buf)))
+            .map_err(Into::into)
+    }
+
+    /// Encode this message back-to-front as a length-delimited record, with the length prefix
+    /// in front of the message.
+    fn encode_reverse_len_delimited(
+        &self,
+        encoder: &mut ReverseEncoder<'_>,
+    ) -> Result<(), BufferTooSmall> {
+        encoder.encode_len_delimited(|encoder| self.encode_reverse(encoder))
+    }
+
+    /// Encode this message into the end of a byte slice, returning the encoded bytes.
+    ///
+    /// Unlike [`encode_to_slice`](Self::encode_to_slice), the message is encoded back-to-front
+    /// using [`encode_reverse`](Self::encode_reverse), so the sizes of nested messages never
+    /// need to be computed separately. This makes encoding a single pass over the message, which
+    /// is faster for deeply-nested messages, as long as they're generated with `encode_reverse`
+    /// enabled. The fields of such messages are written in the reverse of their declaration
+    /// order, which is still valid Protobuf and decodes to the same message. Elements of repeated
+    /// fields keep their order.
+    ///
+    /// If the slice is too small, encoding stops at the first write that doesn't fit, leaving the
+    /// bytes written so far at the end of the slice.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut buf = [0u8; 64];
+    /// let encoded: &[u8] = message.encode_to_slice_reverse(&mut buf)?;
+    /// ```
+    fn encode_to_slice_reverse<'a>(
+        &self,
+        buf: &'a mut [u8],
+    ) -> Result<&'a mut [u8], BufferTooSmall> {
+        let mut encoder = ReverseEncoder::new(buf);
+        self.encode_reverse(&mut encoder)?;
+        Ok(encoder.into_written())
+    }
+
+    #[cfg(feature = "std")]
+    /// Encode this message into a [`std::io::Write`], such as a file or socket.
+    ///
+    /// The message is written out while it's being encoded using [`StdWriter`](crate::StdWriter),
+    /// without first being encoded into a buffer. Since length-delimited records need their
+    /// length up front, the size of each nested message is computed with
+    /// [`compute_size`](Self::compute_size) right before it's written, so nested messages are
+    /// traversed twice. The writer isn't flushed afterwards.
+    fn encode_to_writer<W: std::io::Write>(&self, writer: W) -> std::io::Result<()> {
+        self.encode(&mut PbEncoder::new(crate::encode::StdWriter(writer)))
+            .map_err(Into::into)
+    }
 }
 
 #[cfg(feature = "encode")]
 impl<T: MessageEncode> MessageEncode for &T {
-    fn encode<W: PbWrite>(&self, encoder: &mut PbEncoder<W>) -> Result<(), W::Error> {
+    const MAX_SIZE: Option<usize> = T::MAX_SIZE;
+
+    fn encode<W: PbWrite>(&self, encoder: &mut PbEncoder<W>) -> Result<(), EncodeError<W::Error>> {
         (*self).encode(encoder)
     }
 
@@ -73,7 +333,275 @@ impl<T: MessageEncode> MessageEncode for &T {
         (*self).compute_size()
     }
 
-    fn encode_len_delimited<W: PbWrite>(&self, encoder: &mut PbEncoder<W>) -> Result<(), W::Error> {
+    fn encode_len_delimited<W: PbWrite>(
+        &self,
+        encoder: &mut PbEncoder<W>,
+    ) -> Result<(), EncodeError<W::Error>> {
         (*self).encode_len_delimited(encoder)
     }
+
+    fn encode_reverse(&self, encoder: &mut ReverseEncoder<'_>) -> Result<(), BufferTooSmall> {
+        (*self).encode_reverse(encoder)
+    }
+}
+
+#[cfg(feature = "decode")]
+/// Object-safe counterpart of [`MessageDecode`], implemented for every message that implements
+/// `MessageDecode` and `Default`.
+///
+/// `MessageDecode` can't be used as a trait object, since it's generic over the reader. This
+/// trait only decodes from byte slices, so different message types can be decoded through
+/// `&mut dyn DynMessageDecode`, such as in a table of command handlers.
+pub trait DynMessageDecode {
+    /// Decode `len` bytes from the decoder and merge them into `self`. Same as
+    /// [`MessageDecode::decode`].
+    fn decode_dyn(
+        &mut self,
+        decoder: &mut PbDecoder<&[u8]>,
+        len: usize,
+    ) -> Result<(), DecodeError<Never>>;
+
+    /// Reset the message to its default state. Same as [`MessageDecode::clear`].
+    fn clear_dyn(&mut self);
+
+    /// Decode a message from a byte slice and merge it into `self`. Same as
+    /// [`MessageDecode::merge_from_bytes`].
+    fn merge_from_bytes_dyn(&mut self, bytes: &[u8]) -> Result<(), DecodeError<Never>> {
+        let mut decoder = PbDecoder::new(bytes);
+        self.decode_dyn(&mut decoder, bytes.len())
+    }
+}
+
+#[cfg(feature = "decode")]
+impl<T: MessageDecode + Default> DynMessageDecode for T {
+    fn decode_dyn(
+        &mut self,
+        decoder: &mut PbDecoder<&[u8]>,
+        len: usize,
+    ) -> Result<(), DecodeError<Never>> {
+        self.decode(decoder, len)
+    }
+
+    fn clear_dyn(&mut self) {
+        self.clear()
+    }
+}
+
+#[cfg(feature = "encode")]
+/// Object-safe counterpart of [`MessageEncode`], implemented for every message that implements
+/// `MessageEncode`.
+///
+/// `MessageEncode` can't be used as a trait object, since it's generic over the writer. This
+/// trait encodes into [`DynPbWrite`] instead, so different message types can be stored behind
+/// `&dyn DynMessageEncode`, such as in a table of replies. Use [`PbEncoder::encode_dyn`] to
+/// encode into any writer.
+///
+/// # Example
+/// ```ignore
+/// let replies: [(u32, &dyn DynMessageEncode); 2] = [(1, &ping), (2, &status)];
+/// let (_, msg) = replies[1];
+/// encoder.encode_dyn(|encoder| msg.encode_dyn(encoder))?;
+/// ```
+pub trait DynMessageEncode {
+    /// Encode this message into a type-erased writer. Same as [`MessageEncode::encode`].
+    fn encode_dyn(
+        &self,
+        encoder: &mut PbEncoder<&mut DynPbWrite<'_>>,
+    ) -> Result<(), EncodeError<DynWriteError>>;
+
+    /// Compute the size of this message on the wire. Same as [`MessageEncode::compute_size`].
+    fn compute_size_dyn(&self) -> usize;
+}
+
+#[cfg(feature = "encode")]
+impl<T: MessageEncode> DynMessageEncode for T {
+    fn encode_dyn(
+        &self,
+        encoder: &mut PbEncoder<&mut DynPbWrite<'_>>,
+    ) -> Result<(), EncodeError<DynWriteError>> {
+        self.encode(encoder)
+    }
+
+    fn compute_size_dyn(&self) -> usize {
+        self.compute_size()
+    }
+}
+
+/// Type URL prefix used by `google.protobuf.Any` messages, which is followed by the full name of
+/// the packed message type.
+pub const DEFAULT_TYPE_URL_PREFIX: &str = "type.googleapis.com/";
+
+/// Protobuf message with a fully-qualified name, which identifies the type of the message in the
+/// type URL of a `google.protobuf.Any`.
+///
+/// Implementations are auto-generated by `micropb` if `message_names` or `field_descriptors` is
+/// enabled.
+pub trait MessageName {
+    /// Fully-qualified Protobuf name of the message, without the leading dot, such as
+    /// `google.protobuf.Timestamp`.
+    const FULL_NAME: &'static str;
+
+    /// Check whether `type_url` refers to this message type.
+    ///
+    /// Only the part of the URL after the last `/` is compared against
+    /// [`FULL_NAME`](Self::FULL_NAME), so any prefix is accepted.
+    fn matches_type_url(type_url: &str) -> bool {
+        let name = type_url.rsplit_once('/').map_or(type_url, |(_, name)| name);
+        name == Self::FULL_NAME
+    }
+
+    #[cfg(any(feature = "encode", feature = "decode"))]
+    #[allow(clippy::result_unit_err)]
+    /// Construct the type URL of this message type, which is `prefix` followed by
+    /// [`FULL_NAME`](Self::FULL_NAME).
+    ///
+    /// Returns error if the URL is longer than the fixed capacity of the string type.
+    fn type_url<S: PbString>(prefix: &str) -> Result<S, ()> {
+        let mut url = S::pb_from_str(prefix)?;
+        let name = Self::FULL_NAME.as_bytes();
+        let len = url.len();
+        url.pb_reserve(name.len());
+        let spare_cap = url.pb_spare_cap();
+        if spare_cap.len() < name.len() {
+            return Err(());
+        }
+        for (dst, src) in spare_cap.iter_mut().zip(name) {
+            dst.write(*src);
+        }
+        // SAFETY: the bytes after the existing contents have just been initialized from the name,
+        // which is valid UTF-8
+        unsafe { url.pb_set_len(len + name.len()) };
+        Ok(url)
+    }
+}
+
+/// Shape of a message field, as recorded in [`FieldDescriptor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FieldLabel {
+    /// Field with implicit presence
+    Single,
+    /// Field with explicit presence, such as a `proto3` field marked `optional`
+    Optional,
+    /// Repeated field. If `packed` is true, the elements are encoded as a single
+    /// length-delimited record.
+    Repeated {
+        /// Whether the field is packed
+        packed: bool,
+    },
+    /// Map field
+    Map,
+    /// Member of a oneof
+    Oneof,
+}
+
+/// Static description of a message field, used to inspect messages at runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FieldDescriptor {
+    /// Field number
+    pub num: u32,
+    /// Protobuf name of the field, or `None` if names were left out of the generated tables to
+    /// save space
+    pub name: Option<&'static str>,
+    /// Wire type of the field's values. For repeated fields this is the wire type of a single
+    /// element, even if the field is packed.
+    pub wire_type: u8,
+    /// Shape of the field
+    pub label: FieldLabel,
+}
+
+/// Protobuf message with a static table of its fields.
+///
+/// Implementations are auto-generated by `micropb` if `field_descriptors` is enabled.
+pub trait MessageFields: MessageName {
+    /// Descriptors of the message's fields, sorted by field number
+    const FIELDS: &'static [FieldDescriptor];
+
+    /// Look up the descriptor of the field with the given number.
+    fn field_descriptor(num: u32) -> Option<&'static FieldDescriptor> {
+        Self::FIELDS.iter().find(|f| f.num == num)
+    }
+}
+
+/// Error from converting a `prost` message into its `micropb` counterpart.
+///
+/// Returned by the `TryFrom` implementations generated when `prost_path` is configured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConversionError {
+    /// String, bytes, repeated, or map field exceeds the capacity of its container
+    Capacity,
+    /// Integer field doesn't fit in the integer type of the `micropb` field
+    IntOverflow,
+}
+
+impl core::fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ConversionError::Capacity => f.write_str("field exceeds container capacity"),
+            ConversionError::IntOverflow => f.write_str("integer field out of range"),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", feature = "core-error"))]
+impl crate::ErrorTrait for ConversionError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Inner;
+
+    impl MessageName for Inner {
+        const FULL_NAME: &'static str = "pkg.Outer.Inner";
+    }
+
+    #[test]
+    fn matches_type_url() {
+        assert!(Inner::matches_type_url(
+            "type.googleapis.com/pkg.Outer.Inner"
+        ));
+        assert!(Inner::matches_type_url("example.com/types/pkg.Outer.Inner"));
+        assert!(Inner::matches_type_url("pkg.Outer.Inner"));
+        assert!(!Inner::matches_type_url("type.googleapis.com/pkg.Outer"));
+        assert!(!Inner::matches_type_url("type.googleapis.com/Inner"));
+        assert!(!Inner::matches_type_url(""));
+    }
+
+    impl MessageFields for Inner {
+        const FIELDS: &'static [FieldDescriptor] = &[
+            FieldDescriptor {
+                num: 1,
+                name: Some("id"),
+                wire_type: crate::WIRE_TYPE_VARINT,
+                label: FieldLabel::Single,
+            },
+            FieldDescriptor {
+                num: 3,
+                name: Some("tags"),
+                wire_type: crate::WIRE_TYPE_LEN,
+                label: FieldLabel::Repeated { packed: false },
+            },
+        ];
+    }
+
+    #[test]
+    fn field_descriptor() {
+        assert_eq!(Inner::field_descriptor(3).unwrap().name, Some("tags"));
+        assert_eq!(
+            Inner::field_descriptor(1).unwrap().label,
+            FieldLabel::Single
+        );
+        assert!(Inner::field_descriptor(2).is_none());
+    }
+
+    #[cfg(feature = "container-heapless")]
+    #[test]
+    fn type_url() {
+        let url: heapless::String<40> = Inner::type_url(DEFAULT_TYPE_URL_PREFIX).unwrap();
+        assert_eq!(url, "type.googleapis.com/pkg.Outer.Inner");
+        let url: heapless::String<20> = Inner::type_url("/").unwrap();
+        assert_eq!(url, "/pkg.Outer.Inner");
+        assert!(Inner::type_url::<heapless::String<20>>(DEFAULT_TYPE_URL_PREFIX).is_err());
+        assert!(Inner::type_url::<heapless::String<16>>("ab").is_err());
+    }
 }
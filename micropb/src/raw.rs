@@ -0,0 +1,205 @@
+use crate::Never;
+
+use crate::{DecodeError, PbDecoder, Tag, WIRE_TYPE_LEN};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Top-level field of an encoded message, with its value left undecoded.
+///
+/// Returned from [`RawFields`] and [`find_field`].
+pub struct RawField<'a> {
+    /// Tag of the field
+    pub tag: Tag,
+    /// Raw bytes of the field's value.
+    ///
+    /// For length-delimited fields, this is the contents of the record without the length
+    /// prefix. For groups, this is everything after the start-group tag, up to and including the
+    /// end-group tag. For all other wire types, this is the encoded value itself.
+    pub value: &'a [u8],
+}
+
+impl<'a> RawField<'a> {
+    /// Create a decoder over the raw value of the field.
+    ///
+    /// The returned decoder can be used to decode the value according to the type of the field,
+    /// such as with [`decode_varint32`](PbDecoder::decode_varint32) for `uint32` fields, or with
+    /// [`MessageDecode::decode`](crate::MessageDecode::decode) for message fields.
+    pub fn decoder(&self) -> PbDecoder<&'a [u8]> {
+        PbDecoder::new(self.value)
+    }
+}
+
+#[derive(Debug)]
+/// Iterator over the top-level fields of an encoded message, without decoding their values.
+///
+/// Each value is skipped over on the wire, so nothing is written to any container. Since the
+/// fields are produced lazily, iteration can stop as soon as the desired field is found.
+///
+/// # Example
+/// ```
+/// use micropb::{RawFields, DecodeError, Never};
+///
+/// let data = [0x08, 0x02, 0x12, 0x01, 0xFF];
+/// // Stop at the first occurrence of field 1
+/// let field = RawFields::new(&data).find(|f| f.as_ref().map_or(true, |f| f.tag.field_num() == 1));
+/// let msg_type = field.transpose()?.map(|f| f.decoder().decode_varint32()).transpose()?;
+/// assert_eq!(msg_type, Some(2));
+/// # Ok::<(), DecodeError<Never>>(())
+/// ```
+pub struct RawFields<'a> {
+    data: &'a [u8],
+    decoder: PbDecoder<&'a [u8]>,
+    done: bool,
+}
+
+impl<'a> RawFields<'a> {
+    /// Iterate over the fields of the message encoded in `data`.
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            decoder: PbDecoder::new(data),
+            done: false,
+        }
+    }
+
+    fn next_field(&mut self) -> Result<Option<RawField<'a>>, DecodeError<Never>> {
+        if self.decoder.is_eof()? {
+            return Ok(None);
+        }
+        let tag = self.decoder.decode_tag()?;
+        if tag.field_num() == 0 {
+            return Err(DecodeError::ZeroField);
+        }
+        if tag.wire_type() == WIRE_TYPE_LEN {
+            let len = self.decoder.decode_len()?;
+            let start = self.decoder.bytes_read();
+            self.decoder.skip_bytes(len)?;
+            return Ok(Some(RawField {
+                tag,
+                value: &self.data[start..start + len],
+            }));
+        }
+        let start = self.decoder.bytes_read();
+        self.decoder.skip_wire_value(tag.wire_type())?;
+        Ok(Some(RawField {
+            tag,
+            value: &self.data[start..self.decoder.bytes_read()],
+        }))
+    }
+}
+
+impl<'a> Iterator for RawFields<'a> {
+    type Item = Result<RawField<'a>, DecodeError<Never>>;
+
+    /// The iterator stops after the end of the message or after the first error.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let res = self.next_field().transpose();
+        if !matches!(res, Some(Ok(_))) {
+            self.done = true;
+        }
+        res
+    }
+}
+
+/// Find a top-level field in an encoded message without decoding the rest of the message.
+///
+/// All other fields are skipped without being decoded. If the field appears multiple times, the
+/// last occurrence is returned, following the semantics of singular fields. Returns `None` if the
+/// field doesn't appear at all. Use [`RawFields`] directly to stop at the first occurrence.
+///
+/// # Example
+/// ```
+/// use micropb::{find_field, DecodeError, Never};
+///
+/// // Field 1 is a `uint32` and field 2 is a `bytes`
+/// let data = [0x08, 0x02, 0x12, 0x03, 0xAA, 0xBB, 0xCC];
+/// let msg_type = find_field(&data, 1)?.map(|f| f.decoder().decode_varint32()).transpose()?;
+/// assert_eq!(msg_type, Some(2));
+/// assert_eq!(find_field(&data, 2)?.unwrap().value, &[0xAA, 0xBB, 0xCC]);
+/// assert!(find_field(&data, 3)?.is_none());
+/// # Ok::<(), DecodeError<Never>>(())
+/// ```
+pub fn find_field(data: &[u8], field_num: u32) -> Result<Option<RawField<'_>>, DecodeError<Never>> {
+    let mut found = None;
+    for field in RawFields::new(data) {
+        let field = field?;
+        if field.tag.field_num() == field_num {
+            found = Some(field);
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{WIRE_TYPE_I32, WIRE_TYPE_SGROUP, WIRE_TYPE_VARINT};
+
+    use super::*;
+
+    #[test]
+    fn raw_fields() {
+        let data = [
+            0x08, 0x96, 0x01, // field 1, varint
+            0x12, 0x02, 0xAA, 0xBB, // field 2, len
+            0x1D, 0x01, 0x02, 0x03, 0x04, // field 3, fixed32
+            0x23, 0x08, 0x01, 0x24, // field 4, group
+        ];
+        let fields: Vec<_> = RawFields::new(&data).collect();
+        assert_eq!(
+            fields,
+            [
+                Ok(RawField {
+                    tag: Tag::from_parts(1, WIRE_TYPE_VARINT),
+                    value: &[0x96, 0x01]
+                }),
+                Ok(RawField {
+                    tag: Tag::from_parts(2, WIRE_TYPE_LEN),
+                    value: &[0xAA, 0xBB]
+                }),
+                Ok(RawField {
+                    tag: Tag::from_parts(3, WIRE_TYPE_I32),
+                    value: &[0x01, 0x02, 0x03, 0x04]
+                }),
+                Ok(RawField {
+                    tag: Tag::from_parts(4, WIRE_TYPE_SGROUP),
+                    value: &[0x08, 0x01, 0x24]
+                }),
+            ]
+        );
+        assert_eq!(fields[0].unwrap().decoder().decode_varint32(), Ok(150));
+        assert_eq!(
+            fields[2].unwrap().decoder().decode_fixed32(),
+            Ok(0x04030201)
+        );
+
+        assert_eq!(RawFields::new(&[]).count(), 0);
+    }
+
+    #[test]
+    fn raw_fields_error() {
+        // Iteration stops after the first error
+        let data = [0x08, 0x01, 0x12, 0x05, 0xAA];
+        let fields: Vec<_> = RawFields::new(&data).collect();
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[1], Err(DecodeError::UnexpectedEof));
+
+        let fields: Vec<_> = RawFields::new(&[0x00, 0x01]).collect();
+        assert_eq!(fields, [Err(DecodeError::ZeroField)]);
+    }
+
+    #[test]
+    fn find() {
+        let data = [0x08, 0x01, 0x12, 0x01, 0xAA, 0x08, 0x02];
+        // Last occurrence wins
+        let field = find_field(&data, 1).unwrap().unwrap();
+        assert_eq!(field.value, &[0x02]);
+        assert_eq!(find_field(&data, 2).unwrap().unwrap().value, &[0xAA]);
+        assert_eq!(find_field(&data, 3), Ok(None));
+
+        // Errors anywhere in the message are reported, even after the field is found
+        let data = [0x08, 0x01, 0x12, 0x05, 0xAA];
+        assert_eq!(find_field(&data, 1), Err(DecodeError::UnexpectedEof));
+    }
+}
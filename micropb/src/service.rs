@@ -0,0 +1,108 @@
+//! Runtime support for generated Protobuf services.
+//!
+//! If service generation is enabled, `micropb-gen` emits a trait for each Protobuf service with
+//! one method per RPC, a table of [`MethodDescriptor`]s, and a `dispatch` function that decodes a
+//! request, calls the matching trait method, and encodes the response. Methods are addressed by
+//! their index in the table, so any transport that can carry an index along with the request
+//! bytes can be used to call them.
+
+#[cfg(feature = "decode")]
+use core::fmt::{self, Display};
+
+#[cfg(feature = "decode")]
+use crate::DecodeError;
+#[cfg(feature = "decode")]
+use crate::Never;
+
+/// Static description of an RPC method of a service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MethodDescriptor {
+    /// Index of the method in the service's method table, which is also its index in `dispatch`
+    pub index: usize,
+    /// Protobuf name of the method
+    pub name: &'static str,
+    /// Fully-qualified Protobuf name of the request message
+    pub request: &'static str,
+    /// Fully-qualified Protobuf name of the response message
+    pub response: &'static str,
+}
+
+/// Look up the descriptor of a method by its Protobuf name.
+pub fn find_method(
+    methods: &'static [MethodDescriptor],
+    name: &str,
+) -> Option<&'static MethodDescriptor> {
+    methods.iter().find(|m| m.name == name)
+}
+
+/// Error returned by a generated service dispatcher.
+///
+/// `E` is the error type of the service implementation and `W` is the error type of the writer
+/// that the response is encoded into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg(feature = "decode")]
+pub enum DispatchError<E, W> {
+    /// The method index isn't in the service's method table
+    UnknownMethod(usize),
+    /// The request couldn't be decoded
+    Decode(DecodeError<Never>),
+    /// The service implementation returned an error
+    Service(E),
+    /// The response couldn't be encoded
+    Encode(W),
+}
+
+#[cfg(feature = "decode")]
+impl<E: Display, W: Display> Display for DispatchError<E, W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DispatchError::UnknownMethod(index) => write!(f, "unknown method index {index}"),
+            DispatchError::Decode(e) => write!(f, "failed to decode request: {e}"),
+            DispatchError::Service(e) => write!(f, "service error: {e}"),
+            DispatchError::Encode(e) => write!(f, "failed to encode response: {e}"),
+        }
+    }
+}
+
+#[cfg(all(feature = "decode", any(feature = "std", feature = "core-error")))]
+impl<E, W> crate::ErrorTrait for DispatchError<E, W>
+where
+    E: crate::ErrorTrait + 'static,
+    W: crate::ErrorTrait + 'static,
+{
+    fn source(&self) -> Option<&(dyn crate::ErrorTrait + 'static)> {
+        match self {
+            DispatchError::UnknownMethod(_) => None,
+            DispatchError::Decode(e) => Some(e),
+            DispatchError::Service(e) => Some(e),
+            DispatchError::Encode(e) => Some(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METHODS: &[MethodDescriptor] = &[
+        MethodDescriptor {
+            index: 0,
+            name: "GetStatus",
+            request: "pkg.Empty",
+            response: "pkg.Status",
+        },
+        MethodDescriptor {
+            index: 1,
+            name: "Reset",
+            request: "pkg.Empty",
+            response: "pkg.Empty",
+        },
+    ];
+
+    #[test]
+    fn find() {
+        assert_eq!(find_method(METHODS, "Reset").unwrap().index, 1);
+        assert_eq!(find_method(METHODS, "GetStatus").unwrap().index, 0);
+        assert!(find_method(METHODS, "get_status").is_none());
+    }
+}
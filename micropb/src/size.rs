@@ -1,8 +1,18 @@
 //! Functions for calculating the size of Protobuf values on the wire, which is necessary for
 //! encoding Protobuf messages.
+//!
+//! Sizes saturate at `usize::MAX` instead of overflowing, so the size of a message that's too
+//! large to be encoded is always greater than [`MAX_LEN`].
 
 use crate::Tag;
 
+/// Maximum length of a length-delimited record on the wire, such as a nested message or a
+/// `bytes` field, which is 2 GiB minus one byte.
+///
+/// This is the limit imposed by the Protobuf spec. Encoding a longer record fails with
+/// [`EncodeError::TooLarge`](crate::EncodeError::TooLarge).
+pub const MAX_LEN: usize = i32::MAX as usize;
+
 /// Calculate size of `uint32` on the wire.
 pub const fn sizeof_varint32(v: u32) -> usize {
     match v {
@@ -78,13 +88,60 @@ pub const fn sizeof_tag(tag: Tag) -> usize {
 /// assert_eq!(size, 5);
 /// ```
 pub fn sizeof_packed<T: Copy, F: Fn(&T) -> usize>(elems: &[T], sizer: F) -> usize {
-    elems.iter().map(sizer).sum()
+    elems.iter().map(sizer).fold(0, usize::saturating_add)
 }
 
 #[inline]
 /// Calculate size of length-delimited record on the wire, including the length prefix.
 pub const fn sizeof_len_record(len: usize) -> usize {
-    len + sizeof_varint32(len as u32)
+    let prefix = if len <= u32::MAX as usize {
+        sizeof_varint32(len as u32)
+    } else {
+        5
+    };
+    len.saturating_add(prefix)
+}
+
+/// Add two maximum sizes, where `None` means the size is unbounded.
+///
+/// Used by generated code to calculate [`MessageEncode::MAX_SIZE`](crate::MessageEncode::MAX_SIZE).
+pub const fn max_size_add(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+    match (a, b) {
+        (Some(a), Some(b)) => a.checked_add(b),
+        _ => None,
+    }
+}
+
+/// Multiply a maximum size by a number of elements, where `None` means the size is unbounded.
+///
+/// Used by generated code to calculate [`MessageEncode::MAX_SIZE`](crate::MessageEncode::MAX_SIZE).
+pub const fn max_size_mul(size: Option<usize>, n: usize) -> Option<usize> {
+    match size {
+        Some(size) => size.checked_mul(n),
+        None => None,
+    }
+}
+
+/// Take the larger of two maximum sizes, where `None` means the size is unbounded.
+///
+/// Used by generated code to calculate [`MessageEncode::MAX_SIZE`](crate::MessageEncode::MAX_SIZE).
+pub const fn max_size_max(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+    match (a, b) {
+        (Some(a), Some(b)) if a > b => Some(a),
+        (Some(_), Some(b)) => Some(b),
+        _ => None,
+    }
+}
+
+/// Calculate the maximum size of a length-delimited record from the maximum length of its
+/// contents, where `None` means the size is unbounded.
+///
+/// Used by generated code to calculate [`MessageEncode::MAX_SIZE`](crate::MessageEncode::MAX_SIZE).
+pub const fn max_size_len_record(len: Option<usize>) -> Option<usize> {
+    match len {
+        Some(len) if len <= u32::MAX as usize => len.checked_add(sizeof_varint32(len as u32)),
+        _ => None,
+    }
 }
 
 #[inline]
@@ -97,5 +154,7 @@ pub fn sizeof_map_elem<K: ?Sized, V: ?Sized, FK: FnMut(&K) -> usize, FV: FnMut(&
 ) -> usize {
     // key and value field numbers are 1 and 2, so the tags will always be small numbers, so tag
     // sizes are 1 each
-    2 + key_sizer(key) + val_sizer(val)
+    key_sizer(key)
+        .saturating_add(val_sizer(val))
+        .saturating_add(2)
 }
@@ -0,0 +1,214 @@
+use core::marker::PhantomData;
+
+use crate::{DecodeError, MessageDecode, PbDecoder, PbRead};
+
+#[derive(Debug)]
+/// Reader for a stream of consecutive length-delimited messages.
+///
+/// Each message in the stream is expected to be prefixed by its length as a varint, which is the
+/// de facto framing for Protobuf streams. Messages are decoded one at a time via
+/// [`next_message`](Self::next_message), or via the iterator returned by
+/// [`messages`](Self::messages).
+///
+/// # Example
+///
+/// ```
+/// use micropb::{MessageStream, PbRead, PbDecoder, MessageDecode, DecodeError};
+///
+/// # #[derive(Default)]
+/// # struct ProtoMessage;
+/// # impl MessageDecode for ProtoMessage {
+/// #   fn decode<R: PbRead>(&mut self, decoder: &mut PbDecoder<R>, len: usize) -> Result<(), DecodeError<R::Error>> {
+/// #       decoder.skip_bytes(len)
+/// #   }
+/// # }
+/// // Two empty messages
+/// let data = [0x00, 0x00];
+/// let mut stream = MessageStream::new(data.as_slice()).max_len(256);
+///
+/// while let Some(msg) = stream.next_message::<ProtoMessage>()? {
+///     // Process message
+/// }
+/// # Ok::<(), DecodeError<micropb::Never>>(())
+/// ```
+pub struct MessageStream<R: PbRead> {
+    decoder: PbDecoder<R>,
+    max_len: usize,
+}
+
+impl<R: PbRead> MessageStream<R> {
+    /// Construct a new stream from a [`PbRead`] with no limit on message length.
+    pub fn new(reader: R) -> Self {
+        Self::from_decoder(PbDecoder::new(reader))
+    }
+
+    /// Construct a new stream from an existing decoder with no limit on message length.
+    pub fn from_decoder(decoder: PbDecoder<R>) -> Self {
+        Self {
+            decoder,
+            max_len: usize::MAX,
+        }
+    }
+
+    /// Set the maximum allowed length of each message in the stream.
+    ///
+    /// If a length prefix exceeds this value, [`DecodeError::SizeLimit`] is returned before any
+    /// of the message is read, so a corrupted prefix can't cause unbounded reads.
+    pub fn max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Get mutable reference to the underlying decoder.
+    pub fn decoder(&mut self) -> &mut PbDecoder<R> {
+        &mut self.decoder
+    }
+
+    /// Transform the stream into the underlying decoder.
+    pub fn into_decoder(self) -> PbDecoder<R> {
+        self.decoder
+    }
+
+    /// Decode the next message from the stream.
+    ///
+    /// Returns `None` if the stream is at EOF before the start of the next message. Reaching EOF
+    /// in the middle of a message returns [`DecodeError::UnexpectedEof`].
+    pub fn next_message<M: MessageDecode + Default>(
+        &mut self,
+    ) -> Result<Option<M>, DecodeError<R::Error>> {
+        if self.decoder.is_eof()? {
+            return Ok(None);
+        }
+
+        let len = self.decoder.decode_len()?;
+        if len > self.max_len {
+            return Err(DecodeError::SizeLimit);
+        }
+        let before = self.decoder.bytes_read();
+        let msg = self.decoder.decode_message(len)?;
+        if self.decoder.bytes_read() - before != len {
+            return Err(DecodeError::WrongLen);
+        }
+        Ok(Some(msg))
+    }
+
+    /// Return an iterator over the messages in the stream.
+    ///
+    /// The iterator stops after the stream reaches EOF or after the first error.
+    pub fn messages<M: MessageDecode + Default>(&mut self) -> Messages<'_, R, M> {
+        Messages {
+            stream: self,
+            done: false,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+#[derive(Debug)]
+/// Iterator over the messages in a [`MessageStream`].
+///
+/// Returned from [`MessageStream::messages`].
+pub struct Messages<'a, R: PbRead, M> {
+    stream: &'a mut MessageStream<R>,
+    done: bool,
+    _phantom: PhantomData<M>,
+}
+
+impl<'a, R: PbRead, M: MessageDecode + Default> Iterator for Messages<'a, R, M> {
+    type Item = Result<M, DecodeError<R::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let res = self.stream.next_message().transpose();
+        if !matches!(res, Some(Ok(_))) {
+            self.done = true;
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Never;
+
+    use super::*;
+
+    #[derive(Debug, Default, PartialEq)]
+    struct Msg(u32);
+
+    impl MessageDecode for Msg {
+        fn decode<R: PbRead>(
+            &mut self,
+            decoder: &mut PbDecoder<R>,
+            len: usize,
+        ) -> Result<(), DecodeError<R::Error>> {
+            let before = decoder.bytes_read();
+            while decoder.bytes_read() - before < len {
+                let tag = decoder.decode_tag()?;
+                match tag.field_num() {
+                    1 => self.0 = decoder.decode_varint32()?,
+                    _ => decoder.skip_wire_value(tag.wire_type())?,
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn next_message() {
+        let data = [0x02, 0x08, 0x01, 0x00, 0x03, 0x08, 0x96, 0x01];
+        let mut stream = MessageStream::new(data.as_slice());
+        assert_eq!(stream.next_message(), Ok(Some(Msg(1))));
+        assert_eq!(stream.next_message(), Ok(Some(Msg(0))));
+        assert_eq!(stream.next_message(), Ok(Some(Msg(150))));
+        assert_eq!(stream.next_message::<Msg>(), Ok(None));
+        assert_eq!(stream.next_message::<Msg>(), Ok(None));
+
+        // EOF in the middle of the message
+        let mut stream = MessageStream::new([0x02, 0x08, 0x01, 0x03, 0x08].as_slice());
+        assert_eq!(stream.next_message(), Ok(Some(Msg(1))));
+        assert_eq!(
+            stream.next_message::<Msg>(),
+            Err(DecodeError::UnexpectedEof)
+        );
+
+        // EOF in the middle of the length prefix
+        let mut stream = MessageStream::new([0x80].as_slice());
+        assert_eq!(
+            stream.next_message::<Msg>(),
+            Err(DecodeError::UnexpectedEof)
+        );
+
+        // Message overruns the length prefix
+        let mut stream = MessageStream::new([0x02, 0x08, 0x96, 0x01].as_slice());
+        assert_eq!(stream.next_message::<Msg>(), Err(DecodeError::WrongLen));
+    }
+
+    #[test]
+    fn max_len() {
+        let data = [0x02, 0x08, 0x01, 0x7F];
+        let mut stream = MessageStream::new(data.as_slice()).max_len(2);
+        assert_eq!(stream.next_message(), Ok(Some(Msg(1))));
+        assert_eq!(stream.next_message::<Msg>(), Err(DecodeError::SizeLimit));
+        // Nothing past the length prefix should have been read
+        assert_eq!(stream.into_decoder().bytes_read(), 4);
+    }
+
+    #[test]
+    fn iterator() {
+        let data = [0x02, 0x08, 0x01, 0x00, 0x03, 0x08];
+        let mut stream = MessageStream::new(data.as_slice());
+        let msgs: Vec<Result<Msg, DecodeError<Never>>> = stream.messages().collect();
+        assert_eq!(
+            msgs,
+            [Ok(Msg(1)), Ok(Msg(0)), Err(DecodeError::UnexpectedEof)]
+        );
+
+        let data = [0x02, 0x08, 0x01, 0x00];
+        let mut stream = MessageStream::new(data.as_slice());
+        let msgs: Vec<Result<Msg, DecodeError<Never>>> = stream.messages().collect();
+        assert_eq!(msgs, [Ok(Msg(1)), Ok(Msg(0))]);
+    }
+}
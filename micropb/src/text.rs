@@ -0,0 +1,1200 @@
+//! Output of messages in the Protobuf text format, as read by `protoc --encode` and written by
+//! `protoc --decode`.
+//!
+//! Generated messages implement [`MessageToText`] if the `text` config is set in `micropb-gen`.
+//! The whole message is written on one line, such as `id: 3 name: "a" inner { val: 1 }`. Fields
+//! are written with their names in the `.proto` file, enums as their names, and repeated fields
+//! as one entry per element. Each `map` entry is written as a block with a `key` and a `value`.
+//! Strings and bytes are quoted and escaped, and floats are written with enough digits to be read
+//! back exactly. Fields without presence that hold their default values are omitted, as are unset
+//! optional fields and unknown fields.
+//!
+//! The output is written into any [`fmt::Write`], so it works without allocation by writing into
+//! a fixed-capacity string. [`Text`] wraps a message to implement [`Display`] on it.
+//!
+//! Generated messages also implement [`MessageFromText`], which parses the text format written by
+//! `protoc --decode` or by hand. Fields can be separated by whitespace, commas, or semicolons, and
+//! `#` starts a comment that runs to the end of the line. Enums are accepted as names or numbers,
+//! strings as adjacent quoted pieces with C-style escapes, and repeated fields as either repeated
+//! entries or `[a, b]` lists. [`TextParser`] parses straight into the fields' containers, so
+//! fixed-capacity containers that run out of space return [`TextErrorKind::Capacity`]. Errors
+//! carry the line and column where they were detected.
+//!
+//! This requires the `text` feature.
+//!
+//! # Example
+//!
+//! ```
+//! use micropb::text::{MessageToText, Text, TextWriter};
+//!
+//! // Normally implemented by the generated code
+//! struct Sample { seq: u64, name: &'static str }
+//! impl MessageToText for Sample {
+//!     fn write_text_fields<W: core::fmt::Write + ?Sized>(
+//!         &self,
+//!         t: &mut TextWriter<'_, W>,
+//!     ) -> core::fmt::Result {
+//!         micropb::text::write_num(t.field("seq")?, self.seq)?;
+//!         micropb::text::write_str(t.field("name")?, self.name)
+//!     }
+//! }
+//!
+//! let msg = Sample { seq: 5, name: "a\"b" };
+//! assert_eq!(Text(&msg).to_string(), r#"seq: 5 name: "a\"b""#);
+//! ```
+
+use core::{
+    fmt::{self, Display, LowerExp, Write},
+    mem::MaybeUninit,
+};
+
+use crate::container::{PbString, PbVec};
+
+/// Protobuf message that can be written in text format.
+pub trait MessageToText {
+    /// Write the fields of the message into `t`, without surrounding braces.
+    fn write_text_fields<W: Write + ?Sized>(&self, t: &mut TextWriter<'_, W>) -> fmt::Result;
+
+    /// Write the message in text format into `w`.
+    fn write_text<W: Write + ?Sized>(&self, w: &mut W) -> fmt::Result {
+        self.write_text_fields(&mut TextWriter::new(w))
+    }
+}
+
+/// Protobuf enum that's written in text format by its name.
+pub trait TextEnum {
+    /// Name of the enum value in the `.proto` file, or `None` if the value is unknown.
+    fn text_name(&self) -> Option<&'static str>;
+
+    /// Integer value of the enum, written in place of the name for unknown values.
+    fn text_value(&self) -> i32;
+
+    /// Enum value with the given name in the `.proto` file, or `None` if there's no such value.
+    fn from_text_name(name: &str) -> Option<Self>
+    where
+        Self: Sized;
+
+    /// Enum value with the given integer value, which may be unknown.
+    fn from_text_value(val: i32) -> Self
+    where
+        Self: Sized;
+}
+
+/// Wrapper that implements [`Display`] on a message by writing it in text format.
+#[derive(Debug, Clone, Copy)]
+pub struct Text<'a, M: ?Sized>(pub &'a M);
+
+impl<M: MessageToText + ?Sized> Display for Text<'_, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.write_text(f)
+    }
+}
+
+/// Writer of the fields of a message, which takes care of the separators between fields.
+pub struct TextWriter<'a, W: ?Sized> {
+    w: &'a mut W,
+    empty: bool,
+}
+
+impl<'a, W: Write + ?Sized> TextWriter<'a, W> {
+    /// Start writing the fields of a message into `w`.
+    pub fn new(w: &'a mut W) -> Self {
+        Self { w, empty: true }
+    }
+
+    fn separator(&mut self) -> fmt::Result {
+        if !self.empty {
+            self.w.write_char(' ')?;
+        }
+        self.empty = false;
+        Ok(())
+    }
+
+    /// Write the name of the next scalar field, returning the writer for its value.
+    pub fn field(&mut self, name: &str) -> Result<&mut W, fmt::Error> {
+        self.separator()?;
+        self.w.write_str(name)?;
+        self.w.write_str(": ")?;
+        Ok(self.w)
+    }
+
+    /// Write a message field or `map` entry as a block, with its fields written by `f`.
+    pub fn message(
+        &mut self,
+        name: &str,
+        f: impl FnOnce(&mut TextWriter<'_, W>) -> fmt::Result,
+    ) -> fmt::Result {
+        self.separator()?;
+        self.w.write_str(name)?;
+        self.w.write_str(" {")?;
+        // The first field inside the block is separated from the opening brace
+        f(&mut TextWriter {
+            w: self.w,
+            empty: false,
+        })?;
+        self.w.write_str(" }")
+    }
+}
+
+/// Write an integer.
+pub fn write_num<W: Write + ?Sized, T: Display>(w: &mut W, val: T) -> fmt::Result {
+    write!(w, "{val}")
+}
+
+/// Write a `bool`.
+pub fn write_bool<W: Write + ?Sized>(w: &mut W, val: bool) -> fmt::Result {
+    w.write_str(if val { "true" } else { "false" })
+}
+
+/// Write a `float` with the fewest digits that read back as the same value.
+pub fn write_f32<W: Write + ?Sized>(w: &mut W, val: f32) -> fmt::Result {
+    let abs = val.abs();
+    write_float(
+        w,
+        val,
+        val.is_nan(),
+        abs != 0.0 && !(1e-5..1e16).contains(&abs),
+    )
+}
+
+/// Write a `double` with the fewest digits that read back as the same value.
+pub fn write_f64<W: Write + ?Sized>(w: &mut W, val: f64) -> fmt::Result {
+    let abs = val.abs();
+    write_float(
+        w,
+        val,
+        val.is_nan(),
+        abs != 0.0 && !(1e-5..1e16).contains(&abs),
+    )
+}
+
+fn write_float<W: Write + ?Sized, T: Display + LowerExp>(
+    w: &mut W,
+    val: T,
+    nan: bool,
+    scientific: bool,
+) -> fmt::Result {
+    if nan {
+        w.write_str("nan")
+    } else if scientific {
+        // Very large or small values are written in scientific notation rather than as long
+        // strings of digits, which other parsers may read as overflowing integers. Infinities
+        // fall in here too, and are written as `inf` either way.
+        write!(w, "{val:e}")
+    } else {
+        write!(w, "{val}")
+    }
+}
+
+/// Write an escaped and quoted string.
+///
+/// Non-ASCII characters are written as-is, since the output is valid UTF-8.
+pub fn write_str<W: Write + ?Sized>(w: &mut W, val: &str) -> fmt::Result {
+    w.write_char('"')?;
+    let mut start = 0;
+    for (i, c) in val.char_indices() {
+        if c.is_ascii() && escape(w, c as u8, &val[start..i])? {
+            start = i + 1;
+        }
+    }
+    w.write_str(&val[start..])?;
+    w.write_char('"')
+}
+
+/// Write escaped and quoted bytes, such as the value of a `bytes` field.
+///
+/// Bytes outside of printable ASCII are written as octal escapes.
+pub fn write_bytes<W: Write + ?Sized>(w: &mut W, val: &[u8]) -> fmt::Result {
+    w.write_char('"')?;
+    for &b in val {
+        if !escape(w, b, "")? {
+            w.write_char(b as char)?;
+        }
+    }
+    w.write_char('"')
+}
+
+/// Write `prefix` followed by the escape sequence for `b`, returning `false` without writing
+/// anything if `b` doesn't need escaping
+fn escape<W: Write + ?Sized>(w: &mut W, b: u8, prefix: &str) -> Result<bool, fmt::Error> {
+    let escape = match b {
+        b'"' => "\\\"",
+        b'\'' => "\\'",
+        b'\\' => "\\\\",
+        b'\n' => "\\n",
+        b'\r' => "\\r",
+        b'\t' => "\\t",
+        b' '..=b'~' => return Ok(false),
+        _ => "",
+    };
+    w.write_str(prefix)?;
+    if escape.is_empty() {
+        write!(w, "\\{b:03o}")?;
+    } else {
+        w.write_str(escape)?;
+    }
+    Ok(true)
+}
+
+/// Write an enum value as its name, or as its integer value if it's unknown.
+pub fn write_enum<W: Write + ?Sized, E: TextEnum>(w: &mut W, val: E) -> fmt::Result {
+    match val.text_name() {
+        Some(name) => w.write_str(name),
+        None => write_num(w, val.text_value()),
+    }
+}
+
+/// Protobuf message that can be parsed from text format.
+pub trait MessageFromText {
+    /// Parse the value of the field `name` into the matching field of the message, starting from
+    /// the `:` separator or the opening brace.
+    ///
+    /// Returns `false` without consuming anything if `name` doesn't match any field.
+    fn merge_text_field<'a>(
+        &mut self,
+        name: &str,
+        parser: &mut TextParser<'a>,
+    ) -> Result<bool, TextError<'a>>;
+
+    /// Parse fields in text format and merge them into the message, until the end of the input
+    /// or the closing brace of the enclosing message.
+    fn merge_text<'a>(&mut self, parser: &mut TextParser<'a>) -> Result<(), TextError<'a>> {
+        parser.parse_fields(|parser, name| {
+            if !self.merge_text_field(name, parser)? {
+                parser.unknown_field()?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Parse a message from a text format document, rejecting unknown fields.
+    ///
+    /// To ignore unknown fields instead, configure a [`TextParser`] and pass it to
+    /// [`merge_text`](Self::merge_text).
+    fn from_text(input: &str) -> Result<Self, TextError<'_>>
+    where
+        Self: Default + Sized,
+    {
+        let mut msg = Self::default();
+        let mut parser = TextParser::new(input);
+        msg.merge_text(&mut parser)?;
+        parser.finish()?;
+        Ok(msg)
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Kind of [`TextError`].
+pub enum TextErrorKind {
+    /// Input isn't valid text format, such as an unterminated string or a missing brace
+    Syntax,
+    /// Value has the wrong type for the field, such as a string for an integer field
+    WrongType,
+    /// Number is malformed or out of range for the field's type
+    InvalidNumber,
+    /// `string` value isn't valid UTF-8 after unescaping
+    InvalidUtf8,
+    /// Enum name isn't declared in the Protobuf enum
+    UnknownEnumValue,
+    /// Field name doesn't match any field, and the parser doesn't ignore unknown fields
+    UnknownField,
+    /// Exceeded capacity of fixed container for `string`, `bytes`, repeated, or `map` field
+    Capacity,
+    /// Nesting depth of messages exceeded the parser's `max_depth`
+    DepthLimit,
+}
+
+impl Display for TextErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TextErrorKind::Syntax => "invalid text format",
+            TextErrorKind::WrongType => "wrong value type for field",
+            TextErrorKind::InvalidNumber => "invalid number",
+            TextErrorKind::InvalidUtf8 => "invalid UTF-8 in string",
+            TextErrorKind::UnknownEnumValue => "unknown enum value",
+            TextErrorKind::UnknownField => "unknown field",
+            TextErrorKind::Capacity => "field exceeded capacity of fixed container",
+            TextErrorKind::DepthLimit => "nesting depth exceeded limit",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Error from parsing text format, along with where it occurred.
+pub struct TextError<'a> {
+    /// What went wrong
+    pub kind: TextErrorKind,
+    /// Line of the input where the error was detected, starting from 1
+    pub line: usize,
+    /// Column of the input where the error was detected in characters, starting from 1
+    pub column: usize,
+    /// Name of the innermost field containing the error, as it appears in the input. For errors
+    /// inside `map` entries, this is `key` or `value`.
+    pub field: Option<&'a str>,
+}
+
+impl Display for TextError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at line {}, column {}",
+            self.kind, self.line, self.column
+        )?;
+        if let Some(field) = self.field {
+            write!(f, " in field \"{field}\"")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(any(feature = "std", feature = "core-error"))]
+impl crate::ErrorTrait for TextError<'_> {}
+
+/// Parser of text format fields and values, used by [`MessageFromText`] implementations.
+///
+/// The parser works on a borrowed string and doesn't allocate. Values are parsed directly into
+/// the containers of the message fields.
+///
+/// # Example
+///
+/// ```
+/// use micropb::text::TextParser;
+///
+/// let mut parser = TextParser::new("nums: [1, 0x2] # comment\n nums: 3; other { x: 'a' }");
+/// let mut sum = 0;
+/// parser.parse_fields(|parser, name| match name {
+///     "nums" => parser.parse_repeated_field(|parser| {
+///         sum += parser.parse_int::<u32>()?;
+///         Ok(())
+///     }),
+///     _ => parser.skip_field(),
+/// })?;
+/// parser.finish()?;
+/// assert_eq!(sum, 6);
+/// # Ok::<(), micropb::text::TextError>(())
+/// ```
+pub struct TextParser<'a> {
+    input: &'a str,
+    pos: usize,
+    depth: usize,
+    /// If this flag is set, fields with names that don't match any field are skipped. Otherwise,
+    /// they return [`TextErrorKind::UnknownField`].
+    pub ignore_unknown_fields: bool,
+    /// Maximum nesting depth of messages. Parsing a message nested deeper than this returns
+    /// [`TextErrorKind::DepthLimit`], which prevents malicious inputs from overflowing the stack.
+    ///
+    /// Defaults to [`DEFAULT_MAX_DEPTH`](Self::DEFAULT_MAX_DEPTH).
+    pub max_depth: usize,
+}
+
+impl<'a> TextParser<'a> {
+    /// Default value of [`max_depth`](Self::max_depth).
+    pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+    /// Construct a parser over `input`.
+    pub fn new(input: &'a str) -> Self {
+        Self {
+            input,
+            pos: 0,
+            depth: 0,
+            ignore_unknown_fields: false,
+            max_depth: Self::DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    /// Byte offset of the parser within the input.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Construct an error of `kind` at the parser's current position.
+    pub fn error(&self, kind: TextErrorKind) -> TextError<'a> {
+        self.error_at(self.pos, kind)
+    }
+
+    fn error_at(&self, pos: usize, kind: TextErrorKind) -> TextError<'a> {
+        let before = &self.input[..pos];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        TextError {
+            kind,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            field: None,
+        }
+    }
+
+    /// Check that only whitespace and comments are left in the input.
+    pub fn finish(&mut self) -> Result<(), TextError<'a>> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(self.error(TextErrorKind::Syntax)),
+        }
+    }
+
+    /// Skip whitespace and comments, and return the next byte
+    fn peek(&mut self) -> Option<u8> {
+        let bytes = self.input.as_bytes();
+        loop {
+            match bytes.get(self.pos) {
+                Some(b' ' | b'\t' | b'\n' | b'\r' | 0x0B | 0x0C) => self.pos += 1,
+                Some(b'#') => {
+                    let rest = &bytes[self.pos..];
+                    self.pos += rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+                }
+                b => return b.copied(),
+            }
+        }
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), TextError<'a>> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(TextErrorKind::Syntax))
+        }
+    }
+
+    /// Scan an identifier or number, which is empty if the next token is neither
+    fn scan_token(&mut self) -> &'a str {
+        self.peek();
+        let bytes = self.input.as_bytes();
+        let start = self.pos;
+        let mut i = start;
+        if bytes.get(i) == Some(&b'-') {
+            i += 1;
+        }
+        let numeric = bytes
+            .get(i)
+            .is_some_and(|b| b.is_ascii_digit() || *b == b'.')
+            && !matches!(bytes.get(i + 1), Some(b'x' | b'X'));
+        while let Some(&b) = bytes.get(i) {
+            let exponent_sign =
+                numeric && matches!(b, b'+' | b'-') && matches!(bytes[i - 1], b'e' | b'E');
+            if b.is_ascii_alphanumeric() || b == b'_' || b == b'.' || exponent_sign {
+                i += 1;
+            } else {
+                break;
+            }
+        }
+        if i == start + 1 && bytes[start] == b'-' {
+            i = start;
+        }
+        self.pos = i;
+        &self.input[start..i]
+    }
+
+    /// Scan a field name, which is an identifier or an extension name in brackets
+    fn scan_name(&mut self) -> Result<&'a str, TextError<'a>> {
+        let start = self.pos;
+        if self.peek() == Some(b'[') {
+            let len = self.input[start..]
+                .find(']')
+                .ok_or_else(|| self.error(TextErrorKind::Syntax))?;
+            self.pos += len + 1;
+            return Ok(&self.input[start..self.pos]);
+        }
+        let name = self.scan_token();
+        if name
+            .bytes()
+            .next()
+            .is_some_and(|b| b.is_ascii_alphabetic() || b == b'_')
+            && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+        {
+            Ok(name)
+        } else {
+            Err(self.error_at(start, TextErrorKind::Syntax))
+        }
+    }
+
+    /// Scan an identifier or number for a scalar value, returning its start position
+    fn scan_scalar(&mut self) -> Result<(&'a str, usize), TextError<'a>> {
+        self.peek();
+        let start = self.pos;
+        let token = self.scan_token();
+        if token.is_empty() {
+            Err(self.error(TextErrorKind::WrongType))
+        } else {
+            Ok((token, start))
+        }
+    }
+
+    /// Parse an integer in decimal, hexadecimal, or octal notation.
+    pub fn parse_int<T: TryFrom<i128>>(&mut self) -> Result<T, TextError<'a>> {
+        let (token, start) = self.scan_scalar()?;
+        int_from_token(token)
+            .and_then(|n| T::try_from(n).ok())
+            .ok_or_else(|| self.error_at(start, TextErrorKind::InvalidNumber))
+    }
+
+    /// Parse a `double`, including `inf`, `infinity`, and `nan` in any case.
+    pub fn parse_f64(&mut self) -> Result<f64, TextError<'a>> {
+        let (token, start) = self.scan_scalar()?;
+        float_from_token(token).ok_or_else(|| self.error_at(start, TextErrorKind::InvalidNumber))
+    }
+
+    /// Parse a `float`, including `inf`, `infinity`, and `nan` in any case.
+    pub fn parse_f32(&mut self) -> Result<f32, TextError<'a>> {
+        self.parse_f64().map(|val| val as f32)
+    }
+
+    /// Parse a `bool` from `true`, `True`, `t`, `1`, or their `false` counterparts.
+    pub fn parse_bool(&mut self) -> Result<bool, TextError<'a>> {
+        let (token, start) = self.scan_scalar()?;
+        match token {
+            "true" | "True" | "t" | "1" => Ok(true),
+            "false" | "False" | "f" | "0" => Ok(false),
+            _ => Err(self.error_at(start, TextErrorKind::WrongType)),
+        }
+    }
+
+    /// Parse an enum from its name or its integer value.
+    pub fn parse_enum<E: TextEnum>(&mut self) -> Result<E, TextError<'a>> {
+        let (token, start) = self.scan_scalar()?;
+        if token.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            E::from_text_name(token)
+                .ok_or_else(|| self.error_at(start, TextErrorKind::UnknownEnumValue))
+        } else {
+            int_from_token(token)
+                .and_then(|n| i32::try_from(n).ok())
+                .map(E::from_text_value)
+                .ok_or_else(|| self.error_at(start, TextErrorKind::InvalidNumber))
+        }
+    }
+
+    /// Scan one or more adjacent quoted strings, returning the text from the first opening quote
+    /// to the last closing quote with the escape sequences still in place
+    fn scan_strings(&mut self) -> Result<&'a str, TextError<'a>> {
+        if !matches!(self.peek(), Some(b'"' | b'\'')) {
+            return Err(self.error(TextErrorKind::WrongType));
+        }
+        let bytes = self.input.as_bytes();
+        let start = self.pos;
+        let mut end = start;
+        while let Some(quote @ (b'"' | b'\'')) = self.peek() {
+            let mut i = self.pos + 1;
+            loop {
+                match bytes.get(i) {
+                    Some(&b) if b == quote => break,
+                    Some(b'\\') => match escape_len(&bytes[i + 1..]) {
+                        Some(len) => i += 1 + len,
+                        None => return Err(self.error_at(i, TextErrorKind::Syntax)),
+                    },
+                    // Strings can't span lines
+                    Some(b'\n') | None => return Err(self.error_at(i, TextErrorKind::Syntax)),
+                    Some(_) => i += 1,
+                }
+            }
+            self.pos = i + 1;
+            end = self.pos;
+        }
+        Ok(&self.input[start..end])
+    }
+
+    /// Parse a string into a [`PbString`], replacing its contents.
+    pub fn parse_str<S: PbString>(&mut self, string: &mut S) -> Result<(), TextError<'a>> {
+        self.peek();
+        let start = self.pos;
+        let raw = self.scan_strings()?;
+        string.pb_clear();
+        // Escape sequences are never shorter than the bytes they stand for
+        string.pb_reserve(raw.len());
+        let spare = string.pb_spare_cap();
+        let len = unescape_into(raw, spare).map_err(|kind| self.error_at(start, kind))?;
+        // SAFETY: `unescape_into` initialized `len` bytes of the spare capacity
+        let unescaped = unsafe { crate::misc::maybe_uninit_slice_assume_init_ref(&spare[..len]) };
+        if core::str::from_utf8(unescaped).is_err() {
+            return Err(self.error_at(start, TextErrorKind::InvalidUtf8));
+        }
+        // SAFETY: `len` bytes of valid UTF-8 were written into the spare capacity
+        unsafe { string.pb_set_len(len) };
+        Ok(())
+    }
+
+    /// Parse a string into a [`PbVec<u8>`], replacing its contents. This is used for `bytes`
+    /// fields and `string` fields that are stored as bytes.
+    pub fn parse_bytes<V: PbVec<u8>>(&mut self, vec: &mut V) -> Result<(), TextError<'a>> {
+        self.peek();
+        let start = self.pos;
+        let raw = self.scan_strings()?;
+        vec.pb_clear();
+        vec.pb_reserve(raw.len());
+        let len =
+            unescape_into(raw, vec.pb_spare_cap()).map_err(|kind| self.error_at(start, kind))?;
+        // SAFETY: `unescape_into` initialized `len` bytes of the spare capacity
+        unsafe { vec.pb_set_len(len) };
+        Ok(())
+    }
+
+    /// Parse fields until the end of the input or the closing brace of the enclosing message,
+    /// calling `f` with the name of each field to parse its value.
+    ///
+    /// Errors returned by `f` are tagged with the name of the field, unless they already have a
+    /// name from a nested message.
+    pub fn parse_fields(
+        &mut self,
+        mut f: impl FnMut(&mut Self, &'a str) -> Result<(), TextError<'a>>,
+    ) -> Result<(), TextError<'a>> {
+        while !matches!(self.peek(), None | Some(b'}' | b'>')) {
+            let name = self.scan_name()?;
+            f(self, name).map_err(|mut e| {
+                e.field.get_or_insert(name);
+                e
+            })?;
+            if !self.eat(b',') {
+                self.eat(b';');
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse a message value enclosed in braces or angle brackets, calling `f` to parse its
+    /// fields.
+    fn parse_block(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<(), TextError<'a>>,
+    ) -> Result<(), TextError<'a>> {
+        let close = match self.peek() {
+            Some(b'{') => b'}',
+            Some(b'<') => b'>',
+            _ => return Err(self.error(TextErrorKind::WrongType)),
+        };
+        self.pos += 1;
+        self.depth += 1;
+        if self.depth > self.max_depth {
+            return Err(self.error(TextErrorKind::DepthLimit));
+        }
+        f(self)?;
+        self.expect(close)?;
+        self.depth -= 1;
+        Ok(())
+    }
+
+    /// Parse a list in square brackets, calling `f` to parse each element
+    fn parse_list(
+        &mut self,
+        mut f: impl FnMut(&mut Self) -> Result<(), TextError<'a>>,
+    ) -> Result<(), TextError<'a>> {
+        if !self.eat(b']') {
+            loop {
+                f(self)?;
+                if !self.eat(b',') {
+                    self.expect(b']')?;
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse the `:` separator of a scalar field, then call `f` to parse the value.
+    pub fn parse_field(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<(), TextError<'a>>,
+    ) -> Result<(), TextError<'a>> {
+        self.expect(b':')?;
+        f(self)
+    }
+
+    /// Parse the `:` separator of a repeated scalar field, then call `f` to parse either the
+    /// value or each element of a list of values.
+    pub fn parse_repeated_field(
+        &mut self,
+        mut f: impl FnMut(&mut Self) -> Result<(), TextError<'a>>,
+    ) -> Result<(), TextError<'a>> {
+        self.expect(b':')?;
+        if self.eat(b'[') {
+            self.parse_list(f)
+        } else {
+            f(self)
+        }
+    }
+
+    /// Parse the value of a message field, calling `f` to parse the fields inside the braces.
+    pub fn parse_message_field(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<(), TextError<'a>>,
+    ) -> Result<(), TextError<'a>> {
+        // The separator is optional for message fields
+        self.eat(b':');
+        self.parse_block(f)
+    }
+
+    /// Parse the value of a repeated message field or `map` field, calling `f` to parse the
+    /// fields inside the braces of either the value or each element of a list of values.
+    pub fn parse_repeated_message_field(
+        &mut self,
+        mut f: impl FnMut(&mut Self) -> Result<(), TextError<'a>>,
+    ) -> Result<(), TextError<'a>> {
+        self.eat(b':');
+        if self.eat(b'[') {
+            self.parse_list(|parser| parser.parse_block(&mut f))
+        } else {
+            self.parse_block(f)
+        }
+    }
+
+    /// Skip over the value of a field, including all of its nested fields.
+    pub fn skip_field(&mut self) -> Result<(), TextError<'a>> {
+        if !self.eat(b':') {
+            return self.skip_block();
+        }
+        if self.eat(b'[') {
+            self.parse_list(Self::skip_value)
+        } else {
+            self.skip_value()
+        }
+    }
+
+    fn skip_block(&mut self) -> Result<(), TextError<'a>> {
+        self.parse_block(|parser| parser.parse_fields(|parser, _| parser.skip_field()))
+    }
+
+    fn skip_value(&mut self) -> Result<(), TextError<'a>> {
+        match self.peek() {
+            Some(b'{' | b'<') => self.skip_block(),
+            Some(b'"' | b'\'') => self.scan_strings().map(|_| ()),
+            _ => self
+                .scan_scalar()
+                .map(|_| ())
+                .map_err(|_| self.error(TextErrorKind::Syntax)),
+        }
+    }
+
+    /// Handle a field whose name doesn't match any field, by either skipping its value or
+    /// returning [`TextErrorKind::UnknownField`], depending on
+    /// [`ignore_unknown_fields`](Self::ignore_unknown_fields).
+    pub fn unknown_field(&mut self) -> Result<(), TextError<'a>> {
+        if self.ignore_unknown_fields {
+            self.skip_field()
+        } else {
+            Err(self.error(TextErrorKind::UnknownField))
+        }
+    }
+
+    /// Construct a [`TextErrorKind::Capacity`] error, for when a repeated or `map` field is full.
+    pub fn capacity_error(&self) -> TextError<'a> {
+        self.error(TextErrorKind::Capacity)
+    }
+}
+
+/// Convert an integer token in decimal, hexadecimal, or octal notation
+fn int_from_token(token: &str) -> Option<i128> {
+    let (neg, digits) = match token.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, token),
+    };
+    let (radix, digits) = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        (16, hex)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        (8, &digits[1..])
+    } else {
+        (10, digits)
+    };
+    // `from_str_radix` accepts a leading `+`, which isn't valid here
+    if !digits.bytes().all(|b| b.is_ascii_alphanumeric()) {
+        return None;
+    }
+    let n = i128::try_from(u128::from_str_radix(digits, radix).ok()?).ok()?;
+    Some(if neg { -n } else { n })
+}
+
+/// Convert a float token, which may have an `f` suffix
+fn float_from_token(token: &str) -> Option<f64> {
+    let (neg, body) = match token.strip_prefix('-') {
+        Some(body) => (true, body),
+        None => (false, token),
+    };
+    let val = if body.eq_ignore_ascii_case("inf") || body.eq_ignore_ascii_case("infinity") {
+        f64::INFINITY
+    } else if body.eq_ignore_ascii_case("nan") {
+        f64::NAN
+    } else {
+        let body = body.strip_suffix(['f', 'F']).unwrap_or(body);
+        if !body.starts_with(|c: char| c.is_ascii_digit() || c == '.')
+            || !body
+                .bytes()
+                .all(|b| matches!(b, b'0'..=b'9' | b'.' | b'e' | b'E' | b'+' | b'-'))
+        {
+            return None;
+        }
+        body.parse::<f64>().ok()?
+    };
+    Some(if neg { -val } else { val })
+}
+
+/// Length of the escape sequence at the start of `rest`, which follows a backslash, or `None`
+/// if it's invalid
+fn escape_len(rest: &[u8]) -> Option<usize> {
+    let hex_digits = |s: &[u8], max: usize| {
+        s.iter()
+            .take(max)
+            .take_while(|b| b.is_ascii_hexdigit())
+            .count()
+    };
+    match rest.first()? {
+        b'a' | b'b' | b'f' | b'n' | b'r' | b't' | b'v' | b'\\' | b'\'' | b'"' | b'?' => Some(1),
+        b'0'..=b'7' => {
+            let len = rest
+                .iter()
+                .take(3)
+                .take_while(|b| matches!(b, b'0'..=b'7'))
+                .count();
+            // Octal escapes stand for single bytes
+            (rest[0] <= b'3' || len < 3).then_some(len)
+        }
+        b'x' | b'X' => match hex_digits(&rest[1..], 2) {
+            0 => None,
+            n => Some(1 + n),
+        },
+        b'u' | b'U' => {
+            let len = if rest[0] == b'u' { 4 } else { 8 };
+            let digits = rest.get(1..1 + len)?;
+            let c = u32::from_str_radix(core::str::from_utf8(digits).ok()?, 16).ok()?;
+            (hex_digits(digits, len) == len && char::from_u32(c).is_some()).then_some(1 + len)
+        }
+        _ => None,
+    }
+}
+
+/// Unescape strings scanned by `scan_strings` into `buf`, returning the number of bytes written
+fn unescape_into(raw: &str, buf: &mut [MaybeUninit<u8>]) -> Result<usize, TextErrorKind> {
+    let bytes = raw.as_bytes();
+    let mut len = 0;
+    let mut write = |chunk: &[u8]| {
+        let dst = buf
+            .get_mut(len..len + chunk.len())
+            .ok_or(TextErrorKind::Capacity)?;
+        crate::misc::maybe_uninit_write_slice(dst, chunk);
+        len += chunk.len();
+        Ok(())
+    };
+    let mut i = 0;
+    while i < bytes.len() {
+        let quote = bytes[i];
+        if !matches!(quote, b'"' | b'\'') {
+            // Whitespace or comments between the pieces, which were validated by `scan_strings`
+            if quote == b'#' {
+                i += bytes[i..].iter().position(|&b| b == b'\n').unwrap_or(0);
+            }
+            i += 1;
+            continue;
+        }
+        i += 1;
+        let mut start = i;
+        while bytes[i] != quote {
+            if bytes[i] != b'\\' {
+                i += 1;
+                continue;
+            }
+            write(&bytes[start..i])?;
+            let esc = &bytes[i + 1..];
+            // Validated by `scan_strings`
+            let esc_len = escape_len(esc).unwrap_or(1);
+            let digits = core::str::from_utf8(&esc[1..esc_len]).unwrap_or("");
+            let mut utf8 = [0; 4];
+            let out: &[u8] = match esc[0] {
+                b'a' => b"\x07",
+                b'b' => b"\x08",
+                b'f' => b"\x0C",
+                b'n' => b"\n",
+                b'r' => b"\r",
+                b't' => b"\t",
+                b'v' => b"\x0B",
+                b'0'..=b'7' => {
+                    let octal = core::str::from_utf8(&esc[..esc_len]).unwrap_or("");
+                    utf8[0] = u8::from_str_radix(octal, 8).unwrap_or(0);
+                    &utf8[..1]
+                }
+                b'x' | b'X' => {
+                    utf8[0] = u8::from_str_radix(digits, 16).unwrap_or(0);
+                    &utf8[..1]
+                }
+                b'u' | b'U' => {
+                    let c = u32::from_str_radix(digits, 16)
+                        .ok()
+                        .and_then(char::from_u32);
+                    c.unwrap_or(char::REPLACEMENT_CHARACTER)
+                        .encode_utf8(&mut utf8)
+                        .as_bytes()
+                }
+                // Quotes, backslashes, and question marks stand for themselves
+                _ => &esc[..1],
+            };
+            write(out)?;
+            i += 1 + esc_len;
+            start = i;
+        }
+        write(&bytes[start..i])?;
+        i += 1;
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_string(f: impl FnOnce(&mut String) -> fmt::Result) -> String {
+        let mut out = String::new();
+        f(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(to_string(|w| write_str(w, "abc")), r#""abc""#);
+        assert_eq!(
+            to_string(|w| write_str(w, "a\"b'\\c\nd\u{1}\u{7F}é")),
+            r#""a\"b\'\\c\nd\001\177é""#
+        );
+        assert_eq!(to_string(|w| write_bytes(w, b"")), r#""""#);
+        assert_eq!(
+            to_string(|w| write_bytes(w, b"a\"\t\0\xFF\xC3\xA9")),
+            r#""a\"\t\000\377\303\251""#
+        );
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(to_string(|w| write_num(w, -3i64)), "-3");
+        assert_eq!(to_string(|w| write_bool(w, true)), "true");
+        assert_eq!(to_string(|w| write_f32(w, 1.5)), "1.5");
+        assert_eq!(to_string(|w| write_f32(w, 1.0)), "1");
+        assert_eq!(to_string(|w| write_f32(w, -0.0)), "-0");
+        assert_eq!(to_string(|w| write_f32(w, 0.1)), "0.1");
+        assert_eq!(to_string(|w| write_f32(w, 3e38)), "3e38");
+        assert_eq!(to_string(|w| write_f64(w, 1e-300)), "1e-300");
+        assert_eq!(
+            to_string(|w| write_f64(w, 0.1 + 0.2)),
+            "0.30000000000000004"
+        );
+        assert_eq!(to_string(|w| write_f32(w, f32::NAN)), "nan");
+        assert_eq!(to_string(|w| write_f64(w, f64::INFINITY)), "inf");
+        assert_eq!(to_string(|w| write_f64(w, f64::NEG_INFINITY)), "-inf");
+    }
+
+    #[test]
+    fn writer() {
+        assert_eq!(
+            to_string(|w| TextWriter::new(w).message("a", |_| Ok(()))),
+            "a { }"
+        );
+        let out = to_string(|w| {
+            let mut t = TextWriter::new(w);
+            write_num(t.field("a")?, 1)?;
+            t.message("b", |t| {
+                write_num(t.field("c")?, 2)?;
+                t.message("d", |t| write_bool(t.field("e")?, false))
+            })?;
+            write_str(t.field("f")?, "")
+        });
+        assert_eq!(out, r#"a: 1 b { c: 2 d { e: false } } f: """#);
+    }
+
+    #[test]
+    fn display() {
+        struct Msg;
+        impl MessageToText for Msg {
+            fn write_text_fields<W: Write + ?Sized>(
+                &self,
+                t: &mut TextWriter<'_, W>,
+            ) -> fmt::Result {
+                write_num(t.field("x")?, 5)
+            }
+        }
+
+        assert_eq!(Text(&Msg).to_string(), "x: 5");
+        // Writes into fixed-capacity strings fail once they run out of space
+        let mut buf = heapless::String::<3>::new();
+        assert!(Msg.write_text(&mut buf).is_err());
+        let mut buf = heapless::String::<4>::new();
+        Msg.write_text(&mut buf).unwrap();
+        assert_eq!(buf, "x: 5");
+    }
+
+    #[test]
+    fn parse_scalars() {
+        let mut parser = TextParser::new("-5 0x1F 017 0 true t 0 False 1.5f -inf NaN 1e3 .5 -0");
+        assert_eq!(parser.parse_int::<i32>(), Ok(-5));
+        assert_eq!(parser.parse_int::<u8>(), Ok(31));
+        assert_eq!(parser.parse_int::<u8>(), Ok(15));
+        assert_eq!(parser.parse_int::<u8>(), Ok(0));
+        assert_eq!(parser.parse_bool(), Ok(true));
+        assert_eq!(parser.parse_bool(), Ok(true));
+        assert_eq!(parser.parse_bool(), Ok(false));
+        assert_eq!(parser.parse_bool(), Ok(false));
+        assert_eq!(parser.parse_f32(), Ok(1.5));
+        assert_eq!(parser.parse_f64(), Ok(f64::NEG_INFINITY));
+        assert!(parser.parse_f64().unwrap().is_nan());
+        assert_eq!(parser.parse_f64(), Ok(1000.0));
+        assert_eq!(parser.parse_f64(), Ok(0.5));
+        assert_eq!(parser.parse_f32(), Ok(-0.0));
+        parser.finish().unwrap();
+
+        let mut parser = TextParser::new(" 256 -1 1.5 0x 08");
+        for _ in 0..5 {
+            let start = parser.position();
+            let err = parser.parse_int::<u8>().unwrap_err();
+            assert_eq!(err.kind, TextErrorKind::InvalidNumber);
+            assert_eq!(err.column, start + 2);
+        }
+    }
+
+    #[test]
+    fn parse_strings() {
+        fn parse(input: &str) -> Result<String, TextErrorKind> {
+            let mut out = String::new();
+            let mut parser = TextParser::new(input);
+            parser.parse_str(&mut out).map_err(|e| e.kind)?;
+            parser.finish().unwrap();
+            Ok(out)
+        }
+
+        assert_eq!(parse(r#""abc""#).unwrap(), "abc");
+        assert_eq!(parse(r#"'a"b' "c'd""#).unwrap(), "a\"bc'd");
+        assert_eq!(parse("'a' # comment\n 'b'").unwrap(), "ab");
+        assert_eq!(
+            parse(r#""\a\b\f\n\r\t\v\\\'\"\?""#).unwrap(),
+            "\x07\x08\x0C\n\r\t\x0B\\'\"?"
+        );
+        assert_eq!(parse(r#""\101\x42\x4é\U0001F600""#).unwrap(), "AB\x04é😀");
+        assert_eq!(parse(r#""\303\251""#).unwrap(), "é");
+        assert_eq!(parse(r#""\377""#), Err(TextErrorKind::InvalidUtf8));
+        assert_eq!(parse(r#""\400""#), Err(TextErrorKind::Syntax));
+        assert_eq!(parse(r#""\q""#), Err(TextErrorKind::Syntax));
+        assert_eq!(parse(r#""\ud800""#), Err(TextErrorKind::Syntax));
+        assert_eq!(parse("\"a\nb\""), Err(TextErrorKind::Syntax));
+        assert_eq!(parse(r#""abc"#), Err(TextErrorKind::Syntax));
+        assert_eq!(parse("abc"), Err(TextErrorKind::WrongType));
+
+        let mut bytes = Vec::new();
+        TextParser::new(r#""\000\377a""#)
+            .parse_bytes(&mut bytes)
+            .unwrap();
+        assert_eq!(bytes, b"\0\xFFa");
+
+        let mut bytes = heapless::Vec::<u8, 2>::new();
+        let err = TextParser::new(r#""a" "bc""#)
+            .parse_bytes(&mut bytes)
+            .unwrap_err();
+        assert_eq!(err.kind, TextErrorKind::Capacity);
+    }
+
+    #[test]
+    fn parse_structure() {
+        let input = r#"
+            # Leading comment
+            a: 1, b { c: 2; d < e: 3 > } b: {}
+            list: [4, 5] list: 6 list: []
+            msgs [{ c: 7 }, < c: 8 >] msgs { c: 9 }
+            [ext.name]: 10
+        "#;
+        let mut parser = TextParser::new(input);
+        let mut seen = Vec::new();
+        parser
+            .parse_fields(|parser, name| match name {
+                "a" => parser.parse_field(|parser| {
+                    seen.push(parser.parse_int::<u32>()?);
+                    Ok(())
+                }),
+                "b" => parser.parse_message_field(|parser| {
+                    parser.parse_fields(|parser, _| parser.skip_field())
+                }),
+                "list" => parser.parse_repeated_field(|parser| {
+                    seen.push(parser.parse_int()?);
+                    Ok(())
+                }),
+                "msgs" => parser.parse_repeated_message_field(|parser| {
+                    parser.parse_fields(|parser, _| {
+                        parser.parse_field(|parser| {
+                            seen.push(parser.parse_int()?);
+                            Ok(())
+                        })
+                    })
+                }),
+                _ => {
+                    assert_eq!(name, "[ext.name]");
+                    parser.skip_field()
+                }
+            })
+            .unwrap();
+        parser.finish().unwrap();
+        assert_eq!(seen, [1, 4, 5, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        fn parse(input: &str) -> TextError<'_> {
+            let mut parser = TextParser::new(input);
+            parser.max_depth = 2;
+            parser
+                .parse_fields(|parser, name| match name {
+                    "x" => parser.parse_field(|parser| parser.parse_int::<u8>().map(|_| ())),
+                    "unknown" => parser.unknown_field(),
+                    _ => parser.skip_field(),
+                })
+                .and_then(|_| parser.finish())
+                .unwrap_err()
+        }
+
+        let err = parse("a: 1\n  x: 300");
+        assert_eq!(
+            (err.kind, err.line, err.column),
+            (TextErrorKind::InvalidNumber, 2, 6)
+        );
+        assert_eq!(err.field, Some("x"));
+        assert_eq!(
+            err.to_string(),
+            r#"invalid number at line 2, column 6 in field "x""#
+        );
+
+        let err = parse("a { b { é: 1 unknown: 2 } }");
+        assert_eq!(
+            (err.kind, err.line, err.column),
+            (TextErrorKind::Syntax, 1, 9)
+        );
+        let err = parse("a: 1 unknown { }");
+        assert_eq!(err.kind, TextErrorKind::UnknownField);
+        assert_eq!(err.field, Some("unknown"));
+        assert_eq!(parse("a { b { c { } } }").kind, TextErrorKind::DepthLimit);
+        assert_eq!(parse("x 1").kind, TextErrorKind::Syntax);
+        assert_eq!(parse("x: 'a'").kind, TextErrorKind::WrongType);
+        assert_eq!(parse("a { b: 1 ").kind, TextErrorKind::Syntax);
+        assert_eq!(parse("a { b: 1 >").kind, TextErrorKind::Syntax);
+        assert_eq!(parse("a: 1 }").kind, TextErrorKind::Syntax);
+        assert_eq!(parse("a: [1, 2").kind, TextErrorKind::Syntax);
+        assert_eq!(parse("a:").kind, TextErrorKind::Syntax);
+    }
+
+    #[test]
+    fn error_kind_display() {
+        let cases = [
+            (TextErrorKind::Syntax, "invalid text format"),
+            (TextErrorKind::WrongType, "wrong value type for field"),
+            (TextErrorKind::InvalidNumber, "invalid number"),
+            (TextErrorKind::InvalidUtf8, "invalid UTF-8 in string"),
+            (TextErrorKind::UnknownEnumValue, "unknown enum value"),
+            (TextErrorKind::UnknownField, "unknown field"),
+            (
+                TextErrorKind::Capacity,
+                "field exceeded capacity of fixed container",
+            ),
+            (TextErrorKind::DepthLimit, "nesting depth exceeded limit"),
+        ];
+        for (kind, msg) in cases {
+            assert_eq!(kind.to_string(), msg);
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use core::ops::Range;
+
+use crate::{
+    DecodeError, EncodeError, FieldDecode, FieldEncode, PbDecoder, PbEncoder, PbRead, PbVec,
+    PbWrite, RawFields, Tag,
+};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+/// Unknown field handler that preserves unknown fields, so that they're written back out when the
+/// message is encoded again.
+///
+/// Unknown fields are stored in a [`PbVec<u8>`](crate::PbVec<u8>) container in their wire format,
+/// in the order they were decoded. Use it by setting the `unknown_handler` of a message to a type
+/// such as `micropb::UnknownFields<Vec<u8>>` or `micropb::UnknownFields<heapless::Vec<u8, 64>>`.
+///
+/// Preserved fields are encoded after all of the message's own fields, in the order they were
+/// decoded. With the `canonical_order` config, they're instead interleaved with the message's own
+/// fields in order of field number, and fields with the same number keep their relative order.
+/// Either way, decoding the encoded output and encoding it again produces the same bytes, so a
+/// message stays byte-identical when it's passed through any number of decode-encode cycles.
+///
+/// The values of preserved fields are copied as-is, including non-minimal varints, but their tags
+/// are re-encoded in minimal form. Groups aren't preserved, so they're skipped instead. If the
+/// container runs out of capacity, decoding fails with [`DecodeError::Capacity`].
+///
+/// This requires both the `encode` and `decode` features.
+pub struct UnknownFields<B> {
+    bytes: B,
+}
+
+impl<B: PbVec<u8>> UnknownFields<B> {
+    /// Encoded bytes of the preserved fields, in the order they were decoded.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Iterate over the preserved fields, in the order they were decoded.
+    pub fn iter(&self) -> RawFields<'_> {
+        RawFields::new(&self.bytes)
+    }
+
+    /// Return `true` if no fields are preserved.
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Remove all preserved fields.
+    pub fn clear(&mut self) {
+        self.bytes.pb_clear();
+    }
+
+    /// Field numbers and encoded bytes of the preserved fields, in the order they were decoded
+    fn records(&self) -> impl Iterator<Item = (u32, &[u8])> {
+        let bytes: &[u8] = &self.bytes;
+        let mut decoder = PbDecoder::new(bytes);
+        core::iter::from_fn(move || {
+            let start = decoder.bytes_read();
+            // Fields are validated before they're stored, so this only stops at the end
+            let tag = decoder.decode_tag().ok()?;
+            decoder.skip_wire_value(tag.wire_type()).ok()?;
+            Some((tag.field_num(), &bytes[start..decoder.bytes_read()]))
+        })
+    }
+}
+
+impl<B: PbVec<u8>> FieldDecode for UnknownFields<B> {
+    fn decode_field<R: PbRead>(
+        &mut self,
+        tag: Tag,
+        decoder: &mut PbDecoder<R>,
+    ) -> Result<bool, DecodeError<R::Error>> {
+        decoder.decode_raw_field_append(tag, &mut self.bytes)
+    }
+}
+
+impl<B: PbVec<u8>> FieldEncode for UnknownFields<B> {
+    fn encode_fields<W: PbWrite>(
+        &self,
+        encoder: &mut PbEncoder<W>,
+    ) -> Result<(), EncodeError<W::Error>> {
+        encoder.write(&self.bytes)
+    }
+
+    fn compute_fields_size(&self) -> usize {
+        self.bytes.len()
+    }
+
+    fn encode_fields_in<W: PbWrite>(
+        &self,
+        encoder: &mut PbEncoder<W>,
+        nums: Range<u32>,
+    ) -> Result<(), EncodeError<W::Error>> {
+        // Write out each field number in ascending order, keeping the decoded order of fields
+        // with the same number
+        let mut next = nums.start;
+        while let Some(num) = self
+            .records()
+            .map(|(num, _)| num)
+            .filter(|&num| num >= next && num < nums.end)
+            .min()
+        {
+            for (_, record) in self.records().filter(|&(n, _)| n == num) {
+                encoder.write(record)?;
+            }
+            next = num + 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrayvec::ArrayVec;
+
+    use crate::{WIRE_TYPE_LEN, WIRE_TYPE_SGROUP, WIRE_TYPE_VARINT};
+
+    use super::*;
+
+    /// Decode every field of `data` into the handler
+    fn decode_all(unknown: &mut UnknownFields<Vec<u8>>, data: &[u8]) {
+        let mut decoder = PbDecoder::new(data);
+        while !decoder.is_eof().unwrap() {
+            let tag = decoder.decode_tag().unwrap();
+            assert!(unknown.decode_field(tag, &mut decoder).unwrap());
+        }
+    }
+
+    fn encode_in(unknown: &UnknownFields<Vec<u8>>, nums: Range<u32>) -> Vec<u8> {
+        let mut encoder = PbEncoder::new(vec![]);
+        unknown.encode_fields_in(&mut encoder, nums).unwrap();
+        encoder.into_writer()
+    }
+
+    const FIELDS: &[u8] = &[
+        0x28, 0x80, 0x00, // field 5, non-minimal varint
+        0x0D, 1, 2, 3, 4, // field 1, fixed32
+        0x12, 2, b'a', b'b', // field 2, bytes
+        0x29, 1, 2, 3, 4, 5, 6, 7, 8, // field 5, fixed64
+        0x0A, 0, // field 1, empty bytes
+    ];
+
+    #[test]
+    fn preserve() {
+        let mut unknown = UnknownFields::<Vec<u8>>::default();
+        decode_all(&mut unknown, FIELDS);
+        assert_eq!(unknown.as_bytes(), FIELDS);
+        assert_eq!(unknown.compute_fields_size(), FIELDS.len());
+        assert_eq!(unknown.iter().count(), 5);
+
+        let mut encoder = PbEncoder::new(vec![]);
+        unknown.encode_fields(&mut encoder).unwrap();
+        assert_eq!(encoder.into_writer(), FIELDS);
+
+        // Non-minimal tags are re-encoded
+        let mut other = UnknownFields::<Vec<u8>>::default();
+        decode_all(&mut other, &[0x88, 0x00, 0x01]);
+        assert_eq!(other.as_bytes(), &[0x08, 0x01]);
+
+        unknown.clear();
+        assert!(unknown.is_empty());
+    }
+
+    #[test]
+    fn encode_in_order() {
+        let mut unknown = UnknownFields::<Vec<u8>>::default();
+        decode_all(&mut unknown, FIELDS);
+        assert_eq!(
+            encode_in(&unknown, 0..u32::MAX),
+            [
+                0x0D, 1, 2, 3, 4, // field 1
+                0x0A, 0, // field 1
+                0x12, 2, b'a', b'b', // field 2
+                0x28, 0x80, 0x00, // field 5
+                0x29, 1, 2, 3, 4, 5, 6, 7, 8, // field 5
+            ]
+        );
+        assert_eq!(encode_in(&unknown, 2..5), [0x12, 2, b'a', b'b']);
+        assert_eq!(encode_in(&unknown, 3..5), []);
+    }
+
+    #[test]
+    fn not_preserved() {
+        let mut unknown = UnknownFields::<Vec<u8>>::default();
+        // Groups are left for the decoder to skip
+        let data = [0x0C];
+        let mut decoder = PbDecoder::new(data.as_slice());
+        let tag = Tag::from_parts(1, WIRE_TYPE_SGROUP);
+        assert!(!unknown.decode_field(tag, &mut decoder).unwrap());
+        assert_eq!(decoder.bytes_read(), 0);
+
+        // Partially decoded fields aren't kept
+        let data = [0x80, 0x80];
+        let mut decoder = PbDecoder::new(data.as_slice());
+        let tag = Tag::from_parts(1, WIRE_TYPE_VARINT);
+        assert_eq!(
+            unknown.decode_field(tag, &mut decoder),
+            Err(DecodeError::UnexpectedEof)
+        );
+        let data = [3, b'a'];
+        let mut decoder = PbDecoder::new(data.as_slice());
+        let tag = Tag::from_parts(1, WIRE_TYPE_LEN);
+        assert_eq!(
+            unknown.decode_field(tag, &mut decoder),
+            Err(DecodeError::UnexpectedEof)
+        );
+        assert!(unknown.is_empty());
+
+        // Fields that don't fit aren't kept either
+        let mut unknown = UnknownFields::<ArrayVec<u8, 3>>::default();
+        let data = [2, b'a', b'b'];
+        let mut decoder = PbDecoder::new(data.as_slice());
+        assert_eq!(
+            unknown.decode_field(tag, &mut decoder),
+            Err(DecodeError::Capacity {
+                needed: 1,
+                capacity: 3
+            })
+        );
+        assert!(unknown.is_empty());
+    }
+}
@@ -0,0 +1,793 @@
+//! Runtime types for the `google.protobuf.Timestamp`, `google.protobuf.Duration`, and
+//! `google.protobuf.Empty` well-known types.
+//!
+//! [`Timestamp`] and [`Duration`] have the same layout as the generated messages, with a `seconds`
+//! and a `nanos` field. On top of encoding and decoding, they provide normalization, range checks,
+//! and checked arithmetic, as well as conversions to and from [`core::time::Duration`] and
+//! [`std::time::SystemTime`]. [`Empty`] is zero-sized. All three are substituted for the generated
+//! messages by `Generator::use_well_known_types` from `micropb-gen`.
+//!
+//! Values on the wire aren't validated during decoding, since the Protobuf spec leaves that to the
+//! application. Call [`Timestamp::is_valid`] or [`Duration::is_valid`] to check a decoded value,
+//! or `normalize` to bring `nanos` back into range. The derived comparisons compare `seconds`
+//! before `nanos`, which gives the correct ordering for normalized values.
+//!
+//! In JSON and text format, the types are written as regular messages rather than in the special
+//! string forms of the Protobuf JSON mapping, like the rest of `micropb`.
+//!
+//! This requires the `well-known` feature.
+//!
+//! # Example
+//!
+//! ```
+//! use micropb::well_known::{Duration, Timestamp};
+//!
+//! let start = Timestamp::new(10, 900_000_000);
+//! let end = start.checked_add(Duration::new(1, 200_000_000)).unwrap();
+//! assert_eq!(end, Timestamp::new(12, 100_000_000));
+//!
+//! let elapsed = start.checked_duration_since(end).unwrap();
+//! assert_eq!(elapsed, Duration::new(-1, -200_000_000));
+//! // Negative durations can't be converted to `core::time::Duration`
+//! assert!(core::time::Duration::try_from(elapsed).is_err());
+//! ```
+
+use core::fmt::{self, Display};
+
+#[cfg(feature = "decode")]
+use crate::{
+    decode::{DecodeError, PbDecoder, PbRead},
+    MessageDecode, WIRE_TYPE_VARINT,
+};
+#[cfg(feature = "encode")]
+use crate::{
+    encode::{EncodeError, PbEncoder, PbWrite},
+    size::{sizeof_int32, sizeof_int64},
+    MessageEncode,
+};
+
+const NANOS_PER_SEC: i128 = 1_000_000_000;
+
+fn total_nanos(seconds: i64, nanos: i32) -> i128 {
+    seconds as i128 * NANOS_PER_SEC + nanos as i128
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Error returned when converting between time types fails because the value can't be
+/// represented by the target type, such as a negative [`Duration`] being converted into a
+/// [`core::time::Duration`].
+pub struct OutOfRange;
+
+impl Display for OutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("time value out of range")
+    }
+}
+
+#[cfg(any(feature = "std", feature = "core-error"))]
+impl crate::ErrorTrait for OutOfRange {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+/// Point in time, independent of any time zone, corresponding to `google.protobuf.Timestamp`.
+///
+/// Represented as the number of seconds since the Unix epoch, plus a non-negative fraction of a
+/// second in nanoseconds. Timestamps before the epoch still count `nanos` forward in time, so
+/// half a second before the epoch is `seconds: -1, nanos: 500_000_000`.
+pub struct Timestamp {
+    /// Seconds since the Unix epoch, from [`MIN_SECONDS`](Self::MIN_SECONDS) to
+    /// [`MAX_SECONDS`](Self::MAX_SECONDS)
+    pub seconds: i64,
+    /// Fraction of a second in nanoseconds, from 0 to 999,999,999
+    pub nanos: i32,
+}
+
+impl Timestamp {
+    /// Earliest valid value of `seconds`, which is `0001-01-01T00:00:00Z`.
+    pub const MIN_SECONDS: i64 = -62_135_596_800;
+    /// Latest valid value of `seconds`, which is `9999-12-31T23:59:59Z`.
+    pub const MAX_SECONDS: i64 = 253_402_300_799;
+    /// The Unix epoch, `1970-01-01T00:00:00Z`.
+    pub const UNIX_EPOCH: Self = Self::new(0, 0);
+
+    /// Create a timestamp from its fields, without normalizing it.
+    pub const fn new(seconds: i64, nanos: i32) -> Self {
+        Self { seconds, nanos }
+    }
+
+    fn from_total_nanos(total: i128) -> Option<Self> {
+        let seconds = i64::try_from(total.div_euclid(NANOS_PER_SEC)).ok()?;
+        Some(Self::new(seconds, total.rem_euclid(NANOS_PER_SEC) as i32))
+    }
+
+    /// Returns `true` if `nanos` is in range and the timestamp is between the years 1 and 9999,
+    /// as required by the Protobuf spec.
+    pub fn is_valid(&self) -> bool {
+        (0..NANOS_PER_SEC as i32).contains(&self.nanos)
+            && (Self::MIN_SECONDS..=Self::MAX_SECONDS).contains(&self.seconds)
+    }
+
+    /// Carry whole seconds out of `nanos`, so that `nanos` is from 0 to 999,999,999 without
+    /// changing the point in time. `seconds` saturates if it overflows.
+    ///
+    /// Doesn't check whether `seconds` is in the range allowed by the spec.
+    pub fn normalize(&mut self) {
+        let total = total_nanos(self.seconds, self.nanos);
+        *self = Self::from_total_nanos(total).unwrap_or(if total < 0 {
+            Self::new(i64::MIN, 0)
+        } else {
+            Self::new(i64::MAX, NANOS_PER_SEC as i32 - 1)
+        });
+    }
+
+    /// Add a duration to the timestamp, returning a normalized timestamp, or `None` if the result
+    /// isn't valid.
+    pub fn checked_add(self, duration: Duration) -> Option<Self> {
+        Self::from_total_nanos(
+            total_nanos(self.seconds, self.nanos) + total_nanos(duration.seconds, duration.nanos),
+        )
+        .filter(Self::is_valid)
+    }
+
+    /// Subtract a duration from the timestamp, returning a normalized timestamp, or `None` if the
+    /// result isn't valid.
+    pub fn checked_sub(self, duration: Duration) -> Option<Self> {
+        Self::from_total_nanos(
+            total_nanos(self.seconds, self.nanos) - total_nanos(duration.seconds, duration.nanos),
+        )
+        .filter(Self::is_valid)
+    }
+
+    /// Duration from `earlier` to `self`, which is negative if `earlier` is after `self`. Returns
+    /// `None` if the result isn't a valid [`Duration`].
+    pub fn checked_duration_since(self, earlier: Self) -> Option<Duration> {
+        Duration::from_total_nanos(
+            total_nanos(self.seconds, self.nanos) - total_nanos(earlier.seconds, earlier.nanos),
+        )
+        .filter(Duration::is_valid)
+    }
+}
+
+#[cfg(feature = "std")]
+impl TryFrom<std::time::SystemTime> for Timestamp {
+    type Error = OutOfRange;
+
+    fn try_from(time: std::time::SystemTime) -> Result<Self, Self::Error> {
+        let total = match time.duration_since(std::time::UNIX_EPOCH) {
+            Ok(after) => after.as_nanos() as i128,
+            Err(before) => -(before.duration().as_nanos() as i128),
+        };
+        Self::from_total_nanos(total).ok_or(OutOfRange)
+    }
+}
+
+#[cfg(feature = "std")]
+impl TryFrom<Timestamp> for std::time::SystemTime {
+    type Error = OutOfRange;
+
+    fn try_from(ts: Timestamp) -> Result<Self, Self::Error> {
+        let total = total_nanos(ts.seconds, ts.nanos);
+        let offset = core::time::Duration::new(
+            (total.unsigned_abs() / NANOS_PER_SEC as u128) as u64,
+            (total.unsigned_abs() % NANOS_PER_SEC as u128) as u32,
+        );
+        if total < 0 {
+            std::time::UNIX_EPOCH.checked_sub(offset)
+        } else {
+            std::time::UNIX_EPOCH.checked_add(offset)
+        }
+        .ok_or(OutOfRange)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+/// Signed span of time, corresponding to `google.protobuf.Duration`.
+///
+/// Unlike [`core::time::Duration`], durations can be negative. For negative durations, both
+/// `seconds` and `nanos` are negative or zero, so minus half a second is
+/// `seconds: 0, nanos: -500_000_000`.
+pub struct Duration {
+    /// Whole seconds of the duration, from `-MAX_SECONDS` to [`MAX_SECONDS`](Self::MAX_SECONDS)
+    pub seconds: i64,
+    /// Fraction of a second in nanoseconds, from -999,999,999 to 999,999,999, with the same sign
+    /// as `seconds`
+    pub nanos: i32,
+}
+
+impl Duration {
+    /// Largest valid magnitude of `seconds`, which is roughly 10,000 years.
+    pub const MAX_SECONDS: i64 = 315_576_000_000;
+    /// Zero-length duration.
+    pub const ZERO: Self = Self::new(0, 0);
+
+    /// Create a duration from its fields, without normalizing it.
+    pub const fn new(seconds: i64, nanos: i32) -> Self {
+        Self { seconds, nanos }
+    }
+
+    fn from_total_nanos(total: i128) -> Option<Self> {
+        // Truncating division keeps the signs of both fields in agreement
+        let seconds = i64::try_from(total / NANOS_PER_SEC).ok()?;
+        Some(Self::new(seconds, (total % NANOS_PER_SEC) as i32))
+    }
+
+    /// Returns `true` if the fields are in range and have the same sign, as required by the
+    /// Protobuf spec.
+    pub fn is_valid(&self) -> bool {
+        self.seconds.unsigned_abs() <= Self::MAX_SECONDS as u64
+            && self.nanos.unsigned_abs() < NANOS_PER_SEC as u32
+            && (self.seconds == 0 || self.nanos == 0 || (self.seconds < 0) == (self.nanos < 0))
+    }
+
+    /// Returns `true` if the duration is less than zero.
+    pub fn is_negative(&self) -> bool {
+        total_nanos(self.seconds, self.nanos) < 0
+    }
+
+    /// Carry whole seconds out of `nanos` and fix up the signs of the fields, without changing the
+    /// length of the duration. `seconds` saturates if it overflows.
+    ///
+    /// Doesn't check whether `seconds` is in the range allowed by the spec.
+    pub fn normalize(&mut self) {
+        let total = total_nanos(self.seconds, self.nanos);
+        *self = Self::from_total_nanos(total).unwrap_or(if total < 0 {
+            Self::new(i64::MIN, 1 - NANOS_PER_SEC as i32)
+        } else {
+            Self::new(i64::MAX, NANOS_PER_SEC as i32 - 1)
+        });
+    }
+
+    /// Add two durations, returning a normalized duration, or `None` if the result isn't valid.
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        Self::from_total_nanos(
+            total_nanos(self.seconds, self.nanos) + total_nanos(other.seconds, other.nanos),
+        )
+        .filter(Self::is_valid)
+    }
+
+    /// Subtract a duration from another, returning a normalized duration, or `None` if the result
+    /// isn't valid.
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        Self::from_total_nanos(
+            total_nanos(self.seconds, self.nanos) - total_nanos(other.seconds, other.nanos),
+        )
+        .filter(Self::is_valid)
+    }
+}
+
+/// Fails with [`OutOfRange`] if the duration is negative. Durations with out-of-range `nanos` are
+/// normalized.
+impl TryFrom<Duration> for core::time::Duration {
+    type Error = OutOfRange;
+
+    fn try_from(duration: Duration) -> Result<Self, Self::Error> {
+        let total = total_nanos(duration.seconds, duration.nanos);
+        if total < 0 {
+            return Err(OutOfRange);
+        }
+        Ok(core::time::Duration::new(
+            (total / NANOS_PER_SEC) as u64,
+            (total % NANOS_PER_SEC) as u32,
+        ))
+    }
+}
+
+/// Fails with [`OutOfRange`] if the number of seconds doesn't fit into `i64`.
+impl TryFrom<core::time::Duration> for Duration {
+    type Error = OutOfRange;
+
+    fn try_from(duration: core::time::Duration) -> Result<Self, Self::Error> {
+        let seconds = i64::try_from(duration.as_secs()).map_err(|_| OutOfRange)?;
+        Ok(Self::new(seconds, duration.subsec_nanos() as i32))
+    }
+}
+
+#[cfg(feature = "decode")]
+fn decode_fields<R: PbRead>(
+    decoder: &mut PbDecoder<R>,
+    len: usize,
+    seconds: &mut i64,
+    nanos: &mut i32,
+) -> Result<(), DecodeError<R::Error>> {
+    decoder.check_size_limit(len)?;
+    let before = decoder.bytes_read();
+    while decoder.bytes_read() - before < len {
+        let tag = decoder.decode_tag()?;
+        match (tag.field_num(), tag.wire_type()) {
+            (0, _) => return Err(DecodeError::ZeroField),
+            (1, WIRE_TYPE_VARINT) => *seconds = decoder.decode_int64()?,
+            (2, WIRE_TYPE_VARINT) => *nanos = decoder.decode_int32()?,
+            (1 | 2, _) => return Err(DecodeError::WrongWireType),
+            _ => decoder.skip_unknown_field(tag)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "encode")]
+fn encode_fields<W: PbWrite>(
+    encoder: &mut PbEncoder<W>,
+    seconds: i64,
+    nanos: i32,
+) -> Result<(), EncodeError<W::Error>> {
+    if seconds != 0 {
+        encoder.encode_varint32(8)?;
+        encoder.encode_int64(seconds)?;
+    }
+    if nanos != 0 {
+        encoder.encode_varint32(16)?;
+        encoder.encode_int32(nanos)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "encode")]
+fn sizeof_fields(seconds: i64, nanos: i32) -> usize {
+    let mut size = 0;
+    if seconds != 0 {
+        size += 1 + sizeof_int64(seconds);
+    }
+    if nanos != 0 {
+        size += 1 + sizeof_int32(nanos);
+    }
+    size
+}
+
+macro_rules! impl_message {
+    ($ty:ident) => {
+        impl crate::MessageName for $ty {
+            const FULL_NAME: &'static str = concat!("google.protobuf.", stringify!($ty));
+        }
+
+        #[cfg(feature = "decode")]
+        impl MessageDecode for $ty {
+            fn decode<R: PbRead>(
+                &mut self,
+                decoder: &mut PbDecoder<R>,
+                len: usize,
+            ) -> Result<(), DecodeError<R::Error>> {
+                decode_fields(decoder, len, &mut self.seconds, &mut self.nanos)
+            }
+        }
+
+        #[cfg(feature = "encode")]
+        impl MessageEncode for $ty {
+            // Both fields are 10 bytes long if negative, plus a 1-byte tag
+            const MAX_SIZE: Option<usize> = Some(22);
+
+            fn encode<W: PbWrite>(
+                &self,
+                encoder: &mut PbEncoder<W>,
+            ) -> Result<(), EncodeError<W::Error>> {
+                encode_fields(encoder, self.seconds, self.nanos)
+            }
+
+            fn compute_size(&self) -> usize {
+                sizeof_fields(self.seconds, self.nanos)
+            }
+        }
+
+        #[cfg(feature = "json")]
+        impl crate::json::MessageToJson for $ty {
+            fn write_json<W: fmt::Write + ?Sized>(&self, w: &mut W) -> fmt::Result {
+                let mut obj = crate::json::JsonObject::begin(w)?;
+                if self.seconds != 0 {
+                    crate::json::write_num_str(obj.key("seconds")?, self.seconds)?;
+                }
+                if self.nanos != 0 {
+                    crate::json::write_num(obj.key("nanos")?, self.nanos)?;
+                }
+                obj.end()
+            }
+        }
+
+        #[cfg(feature = "json")]
+        impl crate::json::MessageFromJson for $ty {
+            fn merge_json_field<'a>(
+                &mut self,
+                key: &str,
+                parser: &mut crate::json::JsonParser<'a>,
+            ) -> Result<bool, crate::json::JsonError<'a>> {
+                let is_null = match key {
+                    "seconds" | "nanos" => parser.parse_null()?,
+                    _ => return Ok(false),
+                };
+                match key {
+                    "seconds" if is_null => self.seconds = 0,
+                    "seconds" => self.seconds = parser.parse_int()?,
+                    _ if is_null => self.nanos = 0,
+                    _ => self.nanos = parser.parse_int()?,
+                }
+                Ok(true)
+            }
+        }
+
+        #[cfg(feature = "text")]
+        impl crate::text::MessageToText for $ty {
+            fn write_text_fields<W: fmt::Write + ?Sized>(
+                &self,
+                t: &mut crate::text::TextWriter<'_, W>,
+            ) -> fmt::Result {
+                if self.seconds != 0 {
+                    crate::text::write_num(t.field("seconds")?, self.seconds)?;
+                }
+                if self.nanos != 0 {
+                    crate::text::write_num(t.field("nanos")?, self.nanos)?;
+                }
+                Ok(())
+            }
+        }
+
+        #[cfg(feature = "text")]
+        impl crate::text::MessageFromText for $ty {
+            fn merge_text_field<'a>(
+                &mut self,
+                name: &str,
+                parser: &mut crate::text::TextParser<'a>,
+            ) -> Result<bool, crate::text::TextError<'a>> {
+                match name {
+                    "seconds" => parser.parse_field(|parser| {
+                        self.seconds = parser.parse_int()?;
+                        Ok(())
+                    })?,
+                    "nanos" => parser.parse_field(|parser| {
+                        self.nanos = parser.parse_int()?;
+                        Ok(())
+                    })?,
+                    _ => return Ok(false),
+                }
+                Ok(true)
+            }
+        }
+
+        #[cfg(feature = "field-mask")]
+        impl crate::field_mask::ApplyFieldMask for $ty {
+            fn check_path(path: &str) -> Result<(), &str> {
+                use crate::field_mask::split_path;
+                match split_path(path) {
+                    ("seconds" | "nanos", None) => Ok(()),
+                    ("seconds" | "nanos", Some(rest)) => Err(split_path(rest).0),
+                    (name, _) => Err(name),
+                }
+            }
+
+            fn apply_path(&mut self, src: &Self, path: &str) {
+                match path {
+                    "seconds" => self.seconds = src.seconds,
+                    "nanos" => self.nanos = src.nanos,
+                    _ => {}
+                }
+            }
+        }
+    };
+}
+
+impl_message!(Timestamp);
+impl_message!(Duration);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+/// Message without fields, corresponding to `google.protobuf.Empty`.
+///
+/// Zero-sized, so `Empty` fields and collections of them take no space beyond their presence.
+/// Decoding skips any fields sent by peers with a newer definition, and encoding writes nothing,
+/// so a set `Empty` field is encoded as a tag and a zero length.
+pub struct Empty;
+
+impl crate::MessageName for Empty {
+    const FULL_NAME: &'static str = "google.protobuf.Empty";
+}
+
+#[cfg(feature = "decode")]
+impl MessageDecode for Empty {
+    fn decode<R: PbRead>(
+        &mut self,
+        decoder: &mut PbDecoder<R>,
+        len: usize,
+    ) -> Result<(), DecodeError<R::Error>> {
+        decoder.check_size_limit(len)?;
+        let before = decoder.bytes_read();
+        while decoder.bytes_read() - before < len {
+            let tag = decoder.decode_tag()?;
+            if tag.field_num() == 0 {
+                return Err(DecodeError::ZeroField);
+            }
+            decoder.skip_unknown_field(tag)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "encode")]
+impl MessageEncode for Empty {
+    const MAX_SIZE: Option<usize> = Some(0);
+
+    fn encode<W: PbWrite>(&self, _encoder: &mut PbEncoder<W>) -> Result<(), EncodeError<W::Error>> {
+        Ok(())
+    }
+
+    fn compute_size(&self) -> usize {
+        0
+    }
+}
+
+#[cfg(feature = "json")]
+impl crate::json::MessageToJson for Empty {
+    fn write_json<W: fmt::Write + ?Sized>(&self, w: &mut W) -> fmt::Result {
+        crate::json::JsonObject::begin(w)?.end()
+    }
+}
+
+#[cfg(feature = "json")]
+impl crate::json::MessageFromJson for Empty {
+    fn merge_json_field<'a>(
+        &mut self,
+        _key: &str,
+        _parser: &mut crate::json::JsonParser<'a>,
+    ) -> Result<bool, crate::json::JsonError<'a>> {
+        Ok(false)
+    }
+}
+
+#[cfg(feature = "text")]
+impl crate::text::MessageToText for Empty {
+    fn write_text_fields<W: fmt::Write + ?Sized>(
+        &self,
+        _t: &mut crate::text::TextWriter<'_, W>,
+    ) -> fmt::Result {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "text")]
+impl crate::text::MessageFromText for Empty {
+    fn merge_text_field<'a>(
+        &mut self,
+        _name: &str,
+        _parser: &mut crate::text::TextParser<'a>,
+    ) -> Result<bool, crate::text::TextError<'a>> {
+        Ok(false)
+    }
+}
+
+#[cfg(feature = "field-mask")]
+impl crate::field_mask::ApplyFieldMask for Empty {
+    fn check_path(path: &str) -> Result<(), &str> {
+        Err(crate::field_mask::split_path(path).0)
+    }
+
+    fn apply_path(&mut self, _src: &Self, _path: &str) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamp_normalize() {
+        let mut ts = Timestamp::new(1, -1);
+        ts.normalize();
+        assert_eq!(ts, Timestamp::new(0, 999_999_999));
+        assert!(ts.is_valid());
+
+        let mut ts = Timestamp::new(-1, 2_000_000_500);
+        ts.normalize();
+        assert_eq!(ts, Timestamp::new(1, 500));
+
+        // Seconds saturate instead of overflowing
+        let mut ts = Timestamp::new(i64::MAX, 1_000_000_000);
+        ts.normalize();
+        assert_eq!(ts, Timestamp::new(i64::MAX, 999_999_999));
+        assert!(!ts.is_valid());
+        let mut ts = Timestamp::new(i64::MIN, -1);
+        ts.normalize();
+        assert_eq!(ts, Timestamp::new(i64::MIN, 0));
+
+        assert!(Timestamp::new(Timestamp::MIN_SECONDS, 0).is_valid());
+        assert!(!Timestamp::new(Timestamp::MIN_SECONDS - 1, 999_999_999).is_valid());
+        assert!(Timestamp::new(Timestamp::MAX_SECONDS, 999_999_999).is_valid());
+        assert!(!Timestamp::new(Timestamp::MAX_SECONDS + 1, 0).is_valid());
+        assert!(!Timestamp::new(0, -1).is_valid());
+        assert!(!Timestamp::new(0, 1_000_000_000).is_valid());
+    }
+
+    #[test]
+    fn timestamp_arithmetic() {
+        let ts = Timestamp::new(-1, 500_000_000);
+        assert_eq!(
+            ts.checked_add(Duration::new(0, 600_000_000)),
+            Some(Timestamp::new(0, 100_000_000))
+        );
+        assert_eq!(
+            ts.checked_sub(Duration::new(1, 600_000_000)),
+            Some(Timestamp::new(-3, 900_000_000))
+        );
+        assert_eq!(
+            ts.checked_duration_since(Timestamp::new(1, 0)),
+            Some(Duration::new(-1, -500_000_000))
+        );
+        assert_eq!(
+            Timestamp::new(1, 0).checked_duration_since(ts),
+            Some(Duration::new(1, 500_000_000))
+        );
+
+        let max = Timestamp::new(Timestamp::MAX_SECONDS, 999_999_999);
+        assert_eq!(max.checked_add(Duration::new(0, 1)), None);
+        let min = Timestamp::new(Timestamp::MIN_SECONDS, 0);
+        assert_eq!(min.checked_sub(Duration::new(0, 1)), None);
+        assert_eq!(
+            max.checked_duration_since(min),
+            Some(Duration::new(315_537_897_599, 999_999_999))
+        );
+
+        assert!(Timestamp::new(-1, 999_999_999) < Timestamp::UNIX_EPOCH);
+        assert!(Timestamp::new(0, 1) > Timestamp::UNIX_EPOCH);
+    }
+
+    #[test]
+    fn duration_normalize() {
+        let mut d = Duration::new(1, -1);
+        d.normalize();
+        assert_eq!(d, Duration::new(0, 999_999_999));
+        let mut d = Duration::new(-1, 1);
+        d.normalize();
+        assert_eq!(d, Duration::new(0, -999_999_999));
+        let mut d = Duration::new(0, -2_000_000_500);
+        d.normalize();
+        assert_eq!(d, Duration::new(-2, -500));
+        let mut d = Duration::new(i64::MIN, -1_000_000_000);
+        d.normalize();
+        assert_eq!(d, Duration::new(i64::MIN, -999_999_999));
+
+        assert!(Duration::new(-1, -1).is_valid());
+        assert!(!Duration::new(-1, 1).is_valid());
+        assert!(!Duration::new(1, -1).is_valid());
+        assert!(Duration::new(0, -999_999_999).is_valid());
+        assert!(!Duration::new(0, 1_000_000_000).is_valid());
+        assert!(Duration::new(-Duration::MAX_SECONDS, 0).is_valid());
+        assert!(!Duration::new(Duration::MAX_SECONDS + 1, 0).is_valid());
+
+        assert!(Duration::new(1, -1_500_000_000).is_negative());
+        assert!(!Duration::ZERO.is_negative());
+    }
+
+    #[test]
+    fn duration_arithmetic() {
+        assert_eq!(
+            Duration::new(1, 0).checked_sub(Duration::new(1, 500_000_000)),
+            Some(Duration::new(0, -500_000_000))
+        );
+        assert_eq!(
+            Duration::new(-1, -600_000_000).checked_add(Duration::new(0, -600_000_000)),
+            Some(Duration::new(-2, -200_000_000))
+        );
+        let max = Duration::new(Duration::MAX_SECONDS, 999_999_999);
+        assert_eq!(max.checked_add(Duration::new(0, 1)), None);
+        assert_eq!(max.checked_sub(max), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn duration_conversions() {
+        assert_eq!(
+            core::time::Duration::try_from(Duration::new(1, 500)),
+            Ok(core::time::Duration::new(1, 500))
+        );
+        // Denormalized values are normalized during conversion
+        assert_eq!(
+            core::time::Duration::try_from(Duration::new(2, -500_000_000)),
+            Ok(core::time::Duration::from_millis(1500))
+        );
+        assert_eq!(
+            core::time::Duration::try_from(Duration::new(0, -1)),
+            Err(OutOfRange)
+        );
+        assert_eq!(
+            core::time::Duration::try_from(Duration::new(1, -1_000_000_001)),
+            Err(OutOfRange)
+        );
+        assert_eq!(
+            core::time::Duration::try_from(Duration::new(i64::MAX, 999_999_999)),
+            Ok(core::time::Duration::new(i64::MAX as u64, 999_999_999))
+        );
+
+        assert_eq!(
+            Duration::try_from(core::time::Duration::from_millis(2500)),
+            Ok(Duration::new(2, 500_000_000))
+        );
+        assert_eq!(
+            Duration::try_from(core::time::Duration::MAX),
+            Err(OutOfRange)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn system_time_conversions() {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let time = UNIX_EPOCH - core::time::Duration::from_millis(1500);
+        let ts = Timestamp::try_from(time).unwrap();
+        assert_eq!(ts, Timestamp::new(-2, 500_000_000));
+        assert_eq!(SystemTime::try_from(ts), Ok(time));
+
+        let ts = Timestamp::new(1_700_000_000, 1);
+        let time = SystemTime::try_from(ts).unwrap();
+        assert_eq!(
+            time.duration_since(UNIX_EPOCH).unwrap(),
+            core::time::Duration::new(1_700_000_000, 1)
+        );
+        assert_eq!(Timestamp::try_from(time), Ok(ts));
+    }
+
+    #[test]
+    #[cfg(all(feature = "encode", feature = "decode"))]
+    fn encode_decode() {
+        let ts = Timestamp::new(-1, 500_000_000);
+        let mut encoder = PbEncoder::new(arrayvec::ArrayVec::<u8, 32>::new());
+        ts.encode(&mut encoder).unwrap();
+        let data = encoder.into_writer();
+        assert_eq!(data.len(), ts.compute_size());
+        assert_eq!(
+            data.as_slice(),
+            [
+                0x08, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, 0x10, 0x80, 0xCA,
+                0xB5, 0xEE, 0x01
+            ]
+        );
+        let mut decoded = Timestamp::default();
+        decoded.merge_from_bytes(&data).unwrap();
+        assert_eq!(decoded, ts);
+
+        // Worst case for the size bound
+        let d = Duration::new(-1, -1);
+        assert_eq!(Some(d.compute_size()), Duration::MAX_SIZE);
+        assert_eq!(Duration::ZERO.compute_size(), 0);
+
+        // Unknown fields are skipped, and out-of-range values are decoded as-is
+        let mut decoded = Duration::default();
+        decoded
+            .merge_from_bytes(&[0x18, 0x01, 0x10, 0x80, 0x94, 0xEB, 0xDC, 0x03])
+            .unwrap();
+        assert_eq!(decoded, Duration::new(0, 1_000_000_000));
+        assert_eq!(
+            Duration::default().merge_from_bytes(&[0x0A, 0x00]),
+            Err(DecodeError::WrongWireType)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "field-mask")]
+    fn field_mask() {
+        use crate::field_mask::ApplyFieldMask;
+
+        let mut ts = Timestamp::new(1, 2);
+        ts.apply_field_mask(&Timestamp::new(5, 6), &["nanos"])
+            .unwrap();
+        assert_eq!(ts, Timestamp::new(1, 6));
+        assert_eq!(Duration::check_path("seconds.x"), Err("x"));
+        assert_eq!(Duration::check_path("millis"), Err("millis"));
+    }
+
+    #[test]
+    #[cfg(all(feature = "encode", feature = "decode"))]
+    fn empty() {
+        assert_eq!(core::mem::size_of::<Empty>(), 0);
+        assert_eq!(Empty.compute_size(), 0);
+        assert_eq!(Empty::MAX_SIZE, Some(0));
+        let mut encoder = PbEncoder::new(arrayvec::ArrayVec::<u8, 4>::new());
+        Empty.encode(&mut encoder).unwrap();
+        assert!(encoder.into_writer().is_empty());
+
+        // Fields from newer definitions are skipped
+        let mut empty = Empty;
+        empty
+            .merge_from_bytes(&[0x08, 0x96, 0x01, 0x12, 0x01, 0xFF, 0x1D, 0, 0, 0, 0])
+            .unwrap();
+        assert_eq!(
+            empty.merge_from_bytes(&[0x00, 0x00]),
+            Err(DecodeError::ZeroField)
+        );
+        assert_eq!(
+            empty.merge_from_bytes(&[0x12, 0x02, 0x00]),
+            Err(DecodeError::UnexpectedEof)
+        );
+    }
+}
@@ -0,0 +1,14 @@
+use micropb_gen::{Config, Generator, Target};
+
+fn main() {
+    let mut gen = Generator::new();
+    // Containers and boxes come from `alloc` without configuring them
+    gen.target(Target::Alloc)
+        .configure(".Node.child", Config::new().boxed(true))
+        .add_protoc_arg("-I..")
+        .compile_protos(
+            &["heap.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/heap.rs",
+        )
+        .unwrap();
+}
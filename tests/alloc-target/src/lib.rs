@@ -0,0 +1,36 @@
+#![no_std]
+
+extern crate alloc;
+
+mod heap {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/heap.rs"));
+}
+
+#[cfg(test)]
+#[test]
+fn roundtrip() {
+    use alloc::{boxed::Box, collections::BTreeMap, string::String, vec::Vec};
+    use micropb::{MessageDecode, MessageEncode, PbDecoder, PbEncoder};
+
+    let mut node = heap::Node {
+        name: String::from("root"),
+        data: Vec::from([1, 2]),
+        values: Vec::from([3, 4]),
+        attrs: BTreeMap::from([(String::from("a"), -1)]),
+        child: None,
+    };
+    node.set_child(heap::Node::default());
+    let _: &Option<Box<heap::Node>> = &node.child;
+
+    let mut encoder = PbEncoder::new(Vec::new());
+    node.encode(&mut encoder).unwrap();
+    let bytes = encoder.into_writer();
+
+    let mut decoded = heap::Node::default();
+    decoded
+        .decode(&mut PbDecoder::new(bytes.as_slice()), bytes.len())
+        .unwrap();
+    assert_eq!(decoded, node);
+}
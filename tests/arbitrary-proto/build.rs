@@ -0,0 +1,34 @@
+use micropb_gen::{Config, Generator};
+
+fn main() {
+    // Compile the generated `Arbitrary` impls on stable, the same way `cargo fuzz` does
+    println!("cargo:rustc-cfg=fuzzing");
+
+    let mut generator = Generator::new();
+    generator.use_container_heapless();
+    generator.configure(".", Config::new().max_len(4).max_bytes(8).arbitrary(true));
+    // heapless maps only implement `PartialEq` for `Eq` values
+    generator.configure(".Arbitrary", Config::new().no_partial_eq_impl(true));
+    generator.configure(".Arbitrary.child", Config::new().boxed(true));
+    generator.configure(".Arbitrary.oneof_child", Config::new().boxed(true));
+    generator
+        .add_protoc_arg("-I../basic-proto/proto")
+        .compile_protos(
+            &["../basic-proto/proto/arbitrary.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/arbitrary_heapless.rs",
+        )
+        .unwrap();
+
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator.configure(".", Config::new().arbitrary(true));
+    generator.configure(".Arbitrary.child", Config::new().boxed(true));
+    generator.configure(".Arbitrary.oneof_child", Config::new().boxed(true));
+    generator
+        .add_protoc_arg("-I../basic-proto/proto")
+        .compile_protos(
+            &["../basic-proto/proto/arbitrary.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/arbitrary_alloc.rs",
+        )
+        .unwrap();
+}
@@ -0,0 +1,21 @@
+//! Timing helper shared by the benchmarks.
+
+use std::time::{Duration, Instant};
+
+/// Run `f` repeatedly for a second after warming up, then print the average time per iteration.
+pub fn bench(name: &str, mut f: impl FnMut()) {
+    // Warm up, then run for a fixed amount of time
+    for _ in 0..100 {
+        f();
+    }
+    let mut iters = 0u32;
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(1) {
+        f();
+        iters += 1;
+    }
+    println!(
+        "{name:<32} {:>10.0} ns/iter",
+        start.elapsed().as_nanos() as f64 / iters as f64
+    );
+}
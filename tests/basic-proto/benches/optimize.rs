@@ -0,0 +1,99 @@
+//! Comparison of the `OptimizeFor` modes of the code generator on the `basic` and `nested`
+//! schemas, run with `cargo bench -p basic-proto --bench optimize`.
+//!
+//! The messages are generated once for each mode. Besides the timings printed by this benchmark,
+//! the code size of each mode can be compared by summing the sizes of the code symbols of each
+//! module in the benchmark binary:
+//!
+//! ```sh
+//! nm -S -C target/release/deps/optimize-* | grep -E ' [tT] .*size_proto'
+//! ```
+//!
+//! On x86-64 with Rust 1.95, the results are:
+//!
+//! | Mode     | Code size                             | Decode     | Encode     |
+//! |----------|---------------------------------------|------------|------------|
+//! | Balanced | 7877 bytes                            | 144 ns     | 83 ns      |
+//! | Speed    | inlined into the benchmark            | 137 ns     | 81 ns      |
+//! | Size     | 3931 bytes, plus 422 bytes of helpers | 175 ns     | 139 ns     |
+//!
+//! The helpers used by `OptimizeFor::Size` are shared with all other messages, so the savings
+//! grow with the number of scalar fields in the schema.
+
+extern crate alloc;
+
+mod common;
+
+use std::hint::black_box;
+
+use common::bench;
+use micropb::{MessageDecode, MessageEncode, PbDecoder, PbEncoder};
+
+mod size_proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/optimize_size.rs"));
+}
+
+mod speed_proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/optimize_speed.rs"));
+}
+
+mod balanced_proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/optimize_balanced.rs"));
+}
+
+/// Encoded `Nested` message with every field of `BasicTypes` set
+fn nested_data() -> Vec<u8> {
+    let mut basic = balanced_proto::basic_::BasicTypes::default();
+    basic.set_int32_num(-5);
+    basic.set_int64_num(-300);
+    basic.set_uint32_num(150);
+    basic.set_uint64_num(70000);
+    basic.set_sint32_num(-2);
+    basic.set_sint64_num(1 << 40);
+    basic.set_fixed32_num(7);
+    basic.set_fixed64_num(8);
+    basic.set_sfixed32_num(-9);
+    basic.set_sfixed64_num(-10);
+    basic.set_boolean(true);
+    basic.set_flt(1.5);
+    basic.set_dbl(-2.5);
+    let mut msg = balanced_proto::nested_::Nested::default();
+    msg.set_basic(basic);
+    msg.inner = Some(balanced_proto::nested_::Nested_::Inner::Scalar(true));
+
+    let mut encoder = PbEncoder::new(Vec::new());
+    msg.encode(&mut encoder).unwrap();
+    encoder.into_writer()
+}
+
+fn run<M: MessageDecode + MessageEncode + Default>(mode: &str, data: &[u8]) {
+    bench(&format!("decode ({mode})"), || {
+        let mut decoded = M::default();
+        let mut decoder = PbDecoder::new(black_box(data));
+        decoded.decode(&mut decoder, data.len()).unwrap();
+        black_box(decoded);
+    });
+
+    let mut msg = M::default();
+    msg.merge_from_bytes(data).unwrap();
+    let mut buf = Vec::with_capacity(data.len());
+    bench(&format!("encode ({mode})"), || {
+        buf.clear();
+        let mut encoder = PbEncoder::new(&mut buf);
+        black_box(&msg).encode(&mut encoder).unwrap();
+    });
+    assert_eq!(buf, data);
+}
+
+fn main() {
+    let data = nested_data();
+    run::<balanced_proto::nested_::Nested>("balanced", &data);
+    run::<speed_proto::nested_::Nested>("speed", &data);
+    run::<size_proto::nested_::Nested>("size", &data);
+}
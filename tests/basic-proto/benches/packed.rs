@@ -0,0 +1,130 @@
+//! Benchmarks of packed field encoding and decoding, run with `cargo bench -p basic-proto`.
+//!
+//! For varints, decoding from a slice uses the fast path that reads varints straight from the
+//! buffer, while decoding from a reader that hands out one byte at a time always takes the
+//! byte-by-byte path, which serves as the baseline. For fixed-size elements, the bulk copy used by
+//! generated code is compared against decoding and encoding the elements one by one.
+
+extern crate alloc;
+
+mod common;
+
+use std::hint::black_box;
+
+use common::bench;
+use micropb::{MessageDecode, MessageEncode, OverflowPolicy, PbDecoder, PbEncoder, PbRead};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/packed.rs"));
+}
+
+use proto::packed_::{Fixed, Packing};
+
+/// Reader that returns one byte per chunk, so varints can never be decoded in one go
+struct ByteByByte<'a>(&'a [u8]);
+
+impl PbRead for ByteByByte<'_> {
+    type Error = micropb::Never;
+
+    fn pb_read_chunk(&mut self) -> Result<&[u8], Self::Error> {
+        Ok(&self.0[..self.0.len().min(1)])
+    }
+
+    fn pb_advance(&mut self, bytes: usize) {
+        self.0 = &self.0[bytes..];
+    }
+}
+
+fn varints() {
+    // Mostly 1-byte and 2-byte values, with some larger and negative ones mixed in
+    let values: Vec<i32> = (0..1000)
+        .map(|i| match i % 10 {
+            0 => i * 100_000,
+            1 => -i,
+            n => n * i % 300,
+        })
+        .collect();
+    let msg = Packing {
+        implicit: values.clone(),
+        unpacked: values.clone(),
+        packed: values,
+    };
+    let mut encoder = PbEncoder::new(Vec::new());
+    msg.encode(&mut encoder).unwrap();
+    let data = encoder.into_writer();
+
+    bench("decode (fast path)", || {
+        let mut decoded = Packing::default();
+        let mut decoder = PbDecoder::new(black_box(data.as_slice()));
+        decoded.decode(&mut decoder, data.len()).unwrap();
+        black_box(decoded);
+    });
+    bench("decode (byte-by-byte)", || {
+        let mut decoded = Packing::default();
+        let mut decoder = PbDecoder::new(ByteByByte(black_box(data.as_slice())));
+        decoded.decode(&mut decoder, data.len()).unwrap();
+        black_box(decoded);
+    });
+
+    let mut buf = Vec::with_capacity(data.len());
+    bench("encode", || {
+        buf.clear();
+        let mut encoder = PbEncoder::new(&mut buf);
+        black_box(&msg).encode(&mut encoder).unwrap();
+    });
+    assert_eq!(buf, data);
+}
+
+fn fixed() {
+    let msg = Fixed {
+        fixed32: (0..1024).map(|i| i * 0x01010101).collect(),
+        ..Default::default()
+    };
+    let mut encoder = PbEncoder::new(Vec::new());
+    msg.encode(&mut encoder).unwrap();
+    let data = encoder.into_writer();
+    // Skip the tag
+    let record = &data[1..];
+
+    bench("decode fixed32 (bulk)", || {
+        let mut decoded = Vec::<u32>::new();
+        let mut decoder = PbDecoder::new(black_box(record));
+        decoder
+            .decode_packed_fixed(&mut decoded, OverflowPolicy::Error)
+            .unwrap();
+        black_box(decoded);
+    });
+    bench("decode fixed32 (per element)", || {
+        let mut decoded = Vec::<u32>::new();
+        let mut decoder = PbDecoder::new(black_box(record));
+        decoder
+            .decode_packed(&mut decoded, |rd| rd.decode_fixed32())
+            .unwrap();
+        black_box(decoded);
+    });
+
+    let mut buf = Vec::with_capacity(record.len());
+    bench("encode fixed32 (bulk)", || {
+        buf.clear();
+        let mut encoder = PbEncoder::new(&mut buf);
+        encoder
+            .encode_packed_fixed(black_box(&msg.fixed32))
+            .unwrap();
+    });
+    assert_eq!(buf, record);
+    bench("encode fixed32 (per element)", || {
+        buf.clear();
+        let mut encoder = PbEncoder::new(&mut buf);
+        encoder
+            .encode_packed(4096, black_box(&msg.fixed32), PbEncoder::encode_fixed32)
+            .unwrap();
+    });
+    assert_eq!(buf, record);
+}
+
+fn main() {
+    varints();
+    fixed();
+}
@@ -1,11 +1,12 @@
 use micropb_gen::{
-    config::{CustomField, IntSize, OptionalRepr},
-    Config, EncodeDecode, Generator,
+    config::{CustomField, IntSize, OnOverflow, OnUnknownEnum, OptionalRepr, OverflowPolicy},
+    Config, EncodeDecode, Generator, OptimizeFor,
 };
 
 fn no_config() {
     let mut generator = Generator::new();
     generator
+        .missing_fields(true)
         .compile_protos(
             &[
                 "proto/basic.proto",
@@ -19,7 +20,7 @@ fn no_config() {
 
 fn boxed_and_option() {
     let mut generator = Generator::new();
-    generator.use_container_alloc();
+    generator.use_container_alloc().missing_fields(true);
 
     generator.configure(".basic.BasicTypes.boolean", Config::new().boxed(true));
     generator.configure(
@@ -140,7 +141,10 @@ fn keyword_fields() {
 
 fn container_heapless() {
     let mut generator = Generator::new();
-    generator.use_container_heapless();
+    generator
+        .use_container_heapless()
+        .capacity_asserts(true)
+        .max_size(true);
     generator.configure(".Data.s", Config::new().max_bytes(3));
     generator.configure(".Data.b", Config::new().max_bytes(5));
     generator.configure(".List.list", Config::new().max_len(2));
@@ -165,7 +169,7 @@ fn container_heapless() {
 
 fn container_arrayvec() {
     let mut generator = Generator::new();
-    generator.use_container_arrayvec();
+    generator.use_container_arrayvec().capacity_asserts(true);
     generator.configure(".Data.s", Config::new().max_bytes(3));
     generator.configure(".Data.b", Config::new().max_bytes(5));
     generator.configure(".List.list", Config::new().max_len(2));
@@ -186,7 +190,7 @@ fn container_arrayvec() {
 
 fn container_alloc() {
     let mut generator = Generator::new();
-    generator.use_container_alloc();
+    generator.use_container_alloc().encode_reverse(true);
     generator.configure(".NumList.list.elem", Config::new().int_size(IntSize::S8));
 
     generator
@@ -197,6 +201,20 @@ fn container_alloc() {
         .unwrap();
 }
 
+fn string_as_bytes() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator.configure(".Data.s", Config::new().string_as_bytes(true));
+    generator.configure(".StrList.list", Config::new().string_as_bytes(true));
+
+    generator
+        .compile_protos(
+            &["proto/collections.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/string_as_bytes.rs",
+        )
+        .unwrap();
+}
+
 fn custom_field() {
     let mut generator = Generator::new();
     generator.configure(
@@ -249,7 +267,20 @@ fn custom_field() {
 
 fn implicit_presence() {
     let mut generator = Generator::new();
-    generator.use_container_alloc();
+    generator
+        .use_container_alloc()
+        .encode_reverse(true)
+        .in_place_clear(true);
+    // Custom int types must be skipped when zero, same as the default ones
+    generator.configure(".Enum", Config::new().enum_int_size(IntSize::S8));
+    generator.configure(
+        ".NonOptional.int64_num",
+        Config::new().int_size(IntSize::S8),
+    );
+    generator.configure(
+        ".NonOptional.fixed64_num",
+        Config::new().int_size(IntSize::S16),
+    );
     generator
         .compile_protos(
             &["proto/implicit_presence.proto"],
@@ -258,6 +289,18 @@ fn implicit_presence() {
         .unwrap();
 }
 
+fn emit_defaults() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc().encode_reverse(true);
+    generator.configure(".", Config::new().emit_defaults(true));
+    generator
+        .compile_protos(
+            &["proto/implicit_presence.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/emit_defaults.rs",
+        )
+        .unwrap();
+}
+
 fn extern_import() {
     let mut gen1 = Generator::new();
     gen1.compile_protos(
@@ -315,6 +358,10 @@ fn recursive() {
     generator.configure(".Recursive.recursive", Config::new().boxed(true));
     generator.configure(".Recursive.of", Config::new().boxed(true));
     generator.configure(".Recursive.rec", Config::new().boxed(true));
+    generator.configure(
+        ".Recursive",
+        Config::new().unknown_handler("crate::recursive::StackProbe"),
+    );
     generator
         .compile_protos(
             &["proto/recursive.proto"],
@@ -354,6 +401,325 @@ fn extension() {
         .unwrap();
 }
 
+fn merge() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc().in_place_clear(true);
+    generator
+        .compile_protos(
+            &[
+                "proto/basic.proto",
+                "proto/nested.proto",
+                "proto/collections.proto",
+                "proto/map.proto",
+            ],
+            std::env::var("OUT_DIR").unwrap() + "/merge.rs",
+        )
+        .unwrap();
+}
+
+fn overflow_policy() {
+    let mut generator = Generator::new();
+    generator.use_container_heapless();
+    generator.configure(
+        ".Overflow",
+        Config::new()
+            .max_len(2)
+            .max_bytes(4)
+            .overflow_policy(OverflowPolicy::DropExtra),
+    );
+    generator.configure(
+        ".Overflow.fixed",
+        Config::new().overflow_policy(OverflowPolicy::DropExtraWithFlag),
+    );
+    generator.configure(
+        ".Overflow.mapping",
+        Config::new().overflow_policy(OverflowPolicy::DropExtraWithFlag),
+    );
+    generator.configure(
+        ".Overflow.log",
+        Config::new().on_overflow(OnOverflow::Truncate),
+    );
+    generator.configure(
+        ".Overflow.blob",
+        Config::new().on_overflow(OnOverflow::TruncateWithFlag),
+    );
+    generator
+        .compile_protos(
+            &["proto/overflow.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/overflow_policy.rs",
+        )
+        .unwrap();
+}
+
+fn visitor() {
+    let mut generator = Generator::new();
+    generator.use_container_heapless().encode_reverse(true);
+    generator.configure(".", Config::new().max_len(4).max_bytes(8));
+    generator.configure(".Telemetry", Config::new().visitor("crate::visitor::Stats"));
+    generator.configure(".Telemetry.batches", Config::new().visit(true));
+    generator.configure(".Telemetry.readings", Config::new().visit(true));
+    generator.configure(".Telemetry.tags", Config::new().visit(true));
+    generator.configure(
+        ".Telemetry.outgoing",
+        Config::new().visit(true).visit_decode(false),
+    );
+    generator
+        .compile_protos(
+            &["proto/visitor.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/visitor.rs",
+        )
+        .unwrap();
+}
+
+fn lazy() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator.configure(".Envelope.payload", Config::new().lazy(true));
+    generator.configure(".Envelope.boxed", Config::new().lazy(true).boxed(true));
+    generator.configure(".Envelope.history", Config::new().lazy(true));
+    generator
+        .compile_protos(
+            &["proto/lazy.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/lazy.rs",
+        )
+        .unwrap();
+}
+
+fn bytes_range() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator.encode_decode(EncodeDecode::DecodeOnly);
+    generator.configure(".Frame.payload", Config::new().bytes_range(true));
+    generator.configure(".Frame.trailer", Config::new().bytes_range(true));
+    generator
+        .compile_protos(
+            &["proto/bytes_range.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/bytes_range.rs",
+        )
+        .unwrap();
+}
+
+fn unknown_enum() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator.configure(".Paint", Config::new().on_unknown_enum(OnUnknownEnum::Drop));
+    generator.configure(
+        ".Paint.preserve",
+        Config::new().on_unknown_enum(OnUnknownEnum::Preserve),
+    );
+    generator.configure(
+        ".Paint.error",
+        Config::new().on_unknown_enum(OnUnknownEnum::Error),
+    );
+    generator.configure(
+        ".Paint.fallback",
+        Config::new().on_unknown_enum(OnUnknownEnum::Default),
+    );
+    generator.configure(
+        ".Paint.drop_option",
+        Config::new().optional_repr(OptionalRepr::Option),
+    );
+    generator
+        .compile_protos(
+            &["proto/unknown_enum.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/unknown_enum.rs",
+        )
+        .unwrap();
+}
+
+fn field_stats() {
+    let mut generator = Generator::new();
+    generator.field_stats(true);
+    generator
+        .compile_protos(
+            &["proto/basic.proto", "proto/nested.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/field_stats.rs",
+        )
+        .unwrap();
+}
+
+fn arbitrary() {
+    let mut generator = Generator::new();
+    generator.use_container_heapless().max_size(true);
+    // The `Arbitrary` impls are only compiled with `--cfg fuzzing`, as in `fuzz/`
+    generator.configure(".", Config::new().max_len(4).max_bytes(8).arbitrary(true));
+    // heapless maps only implement `PartialEq` for `Eq` values
+    generator.configure(".Arbitrary", Config::new().no_partial_eq_impl(true));
+    generator.configure(".Arbitrary.child", Config::new().boxed(true));
+    generator.configure(".Arbitrary.oneof_child", Config::new().boxed(true));
+    generator.configure(
+        ".Arbitrary.strs",
+        Config::new().overflow_policy(OverflowPolicy::DropExtra),
+    );
+    generator.configure(
+        ".Arbitrary.text",
+        Config::new().on_overflow(OnOverflow::Truncate),
+    );
+    generator
+        .compile_protos(
+            &["proto/arbitrary.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/arbitrary_heapless.rs",
+        )
+        .unwrap();
+
+    let mut generator = Generator::new();
+    generator
+        .use_container_alloc()
+        .max_size(true)
+        .encode_reverse(true)
+        .in_place_clear(true);
+    generator.configure(".Arbitrary.child", Config::new().boxed(true));
+    generator.configure(".Arbitrary.oneof_child", Config::new().boxed(true));
+    generator
+        .compile_protos(
+            &["proto/arbitrary.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/arbitrary_alloc.rs",
+        )
+        .unwrap();
+}
+
+fn sorted_map() {
+    let mut generator = Generator::new();
+    generator.use_container_heapless().encode_reverse(true);
+    generator.configure(
+        ".Map.mapping",
+        Config::new().max_len(8).sort_map_entries(true),
+    );
+    generator.configure(".Map.mapping.key", Config::new().max_bytes(4));
+    generator.configure(".Map.mapping.value", Config::new().max_bytes(3));
+    generator
+        .compile_protos(
+            &["proto/map.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/sorted_map_heapless.rs",
+        )
+        .unwrap();
+
+    let mut generator = Generator::new();
+    generator.use_container_std().encode_reverse(true);
+    generator.configure(".Map.mapping", Config::new().sort_map_entries(true));
+    generator
+        .compile_protos(
+            &["proto/map.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/sorted_map_std.rs",
+        )
+        .unwrap();
+}
+
+fn field_order() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc().encode_reverse(true);
+    generator.configure(".", Config::new().canonical_order(true));
+    generator.configure(
+        ".Ordered",
+        Config::new().unknown_handler("crate::field_order::UnknownVarints"),
+    );
+    generator
+        .compile_protos(
+            &["proto/field_order.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/field_order.rs",
+        )
+        .unwrap();
+
+    // Same messages without canonical order, for comparison
+    let mut generator = Generator::new();
+    generator.use_container_alloc().encode_reverse(true);
+    generator
+        .compile_protos(
+            &["proto/field_order.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/field_order_default.rs",
+        )
+        .unwrap();
+}
+
+fn packed() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc().encode_reverse(true);
+    // Not stored as the native type, so it can't be copied in bulk
+    generator.configure(
+        ".packed.Fixed.narrow.elem",
+        Config::new().int_size(IntSize::S16),
+    );
+    generator
+        .compile_protos(
+            &["proto/packed.proto", "proto/packed2.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/packed.rs",
+        )
+        .unwrap();
+}
+
+fn dyn_encode() {
+    let protos = [
+        "proto/basic.proto",
+        "proto/nested.proto",
+        "proto/collections.proto",
+        "proto/map.proto",
+    ];
+    let mut generator = Generator::new();
+    generator.use_container_alloc().encode_reverse(true);
+    generator.dyn_encode(true);
+    generator
+        .compile_protos(
+            &protos,
+            std::env::var("OUT_DIR").unwrap() + "/dyn_encode.rs",
+        )
+        .unwrap();
+
+    // Same messages without dyn encoding, for comparison
+    let mut generator = Generator::new();
+    generator.use_container_alloc().encode_reverse(true);
+    generator
+        .compile_protos(
+            &protos,
+            std::env::var("OUT_DIR").unwrap() + "/dyn_encode_default.rs",
+        )
+        .unwrap();
+}
+
+fn optimize() {
+    let protos = [
+        "proto/basic.proto",
+        "proto/basic3.proto",
+        "proto/nested.proto",
+        "proto/collections.proto",
+    ];
+    for (optimize, file) in [
+        (OptimizeFor::Size, "/optimize_size.rs"),
+        (OptimizeFor::Speed, "/optimize_speed.rs"),
+        (OptimizeFor::Balanced, "/optimize_balanced.rs"),
+    ] {
+        let mut generator = Generator::new();
+        generator.use_container_alloc().encode_reverse(true);
+        generator.optimize(optimize);
+        generator
+            .compile_protos(&protos, std::env::var("OUT_DIR").unwrap() + file)
+            .unwrap();
+    }
+}
+
+fn unknown_fields() {
+    for (canonical, file) in [
+        (false, "/unknown_fields.rs"),
+        (true, "/unknown_fields_canonical.rs"),
+    ] {
+        let mut generator = Generator::new();
+        generator
+            .use_container_alloc()
+            .encode_reverse(true)
+            .in_place_clear(true);
+        generator.configure(".", Config::new().canonical_order(canonical));
+        generator.configure(
+            ".Partial",
+            Config::new().unknown_handler("micropb::UnknownFields<Vec<u8>>"),
+        );
+        generator
+            .compile_protos(
+                &["proto/unknown_fields.proto"],
+                std::env::var("OUT_DIR").unwrap() + file,
+            )
+            .unwrap();
+    }
+}
+
 fn main() {
     no_config();
     boxed_and_option();
@@ -363,12 +729,473 @@ fn main() {
     container_heapless();
     container_arrayvec();
     container_alloc();
+    string_as_bytes();
     custom_field();
     implicit_presence();
+    emit_defaults();
     extern_import();
     lifetime_fields();
     recursive();
     conflicting_names();
     default_str_escape();
     extension();
+    merge();
+    overflow_policy();
+    visitor();
+    lazy();
+    bytes_range();
+    unknown_enum();
+    field_stats();
+    arbitrary();
+    sorted_map();
+    field_order();
+    packed();
+    dyn_encode();
+    optimize();
+    unknown_fields();
+    defmt();
+    json();
+    text();
+    well_known();
+    wrappers();
+    any();
+    field_mask();
+    empty();
+    structs();
+    roundtrip();
+    domain();
+    migration();
+    reflection();
+    service();
+    size_int();
+    container_template();
+}
+
+fn defmt() {
+    // The `defmt` derives are gated behind a feature that this crate never enables, so declare it
+    // to keep the cfg checks happy
+    println!("cargo:rustc-check-cfg=cfg(feature, values(\"defmt\"))");
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator.configure(".", Config::new().defmt(true));
+    generator.configure(
+        ".nested.Nested",
+        Config::new().unknown_handler("micropb::UnknownFields<Vec<u8>>"),
+    );
+    generator
+        .compile_protos(
+            &["proto/basic.proto", "proto/nested.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/defmt.rs",
+        )
+        .unwrap();
+}
+
+fn json() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator.configure(".", Config::new().json(true));
+    generator.configure(".json.Json", Config::new().field_name_consts(true));
+    generator
+        .compile_protos(
+            &["proto/json.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/json.rs",
+        )
+        .unwrap();
+
+    // Fixed containers for checking capacity errors
+    let mut generator = Generator::new();
+    generator.use_container_heapless();
+    generator.configure(".", Config::new().json(true).max_len(2).max_bytes(4));
+    // `IndexMap` only implements `PartialEq` for values that implement `Eq`
+    generator.configure(".json.Json", Config::new().no_partial_eq_impl(true));
+    generator
+        .compile_protos(
+            &["proto/json.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/json_fixed.rs",
+        )
+        .unwrap();
+}
+
+fn text() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator.configure(".", Config::new().text(true));
+    generator
+        .compile_protos(
+            &["proto/text.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/text.rs",
+        )
+        .unwrap();
+
+    // Fixed containers for checking capacity errors
+    let mut generator = Generator::new();
+    generator.use_container_heapless();
+    generator.configure(".", Config::new().text(true).max_len(2).max_bytes(4));
+    // `IndexMap` only implements `PartialEq` for values that implement `Eq`
+    generator.configure(".text.Text", Config::new().no_partial_eq_impl(true));
+    generator
+        .compile_protos(
+            &["proto/text.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/text_fixed.rs",
+        )
+        .unwrap();
+}
+
+fn well_known() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator.use_well_known_types();
+    generator.configure(".", Config::new().json(true).text(true));
+    generator
+        // Imports of the well-known types are resolved from the `proto` directory
+        .add_protoc_arg("-Iproto")
+        .compile_protos(
+            &["proto/well_known.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/well_known.rs",
+        )
+        .unwrap();
+}
+
+fn wrappers() {
+    let mut generator = Generator::new();
+    generator
+        .use_container_alloc()
+        .max_size(true)
+        .encode_reverse(true);
+    generator.configure(
+        ".",
+        Config::new().unwrap_wrappers(true).json(true).text(true),
+    );
+    generator.configure(
+        ".wrappers.Settings.ratio",
+        Config::new().optional_repr(OptionalRepr::Option),
+    );
+    generator
+        .add_protoc_arg("-Iproto")
+        .compile_protos(
+            &["proto/wrappers.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/wrappers.rs",
+        )
+        .unwrap();
+
+    // Fixed containers for checking that `max_bytes` applies to the wrapped values
+    let mut generator = Generator::new();
+    generator
+        .use_container_heapless()
+        .max_size(true)
+        .encode_reverse(true);
+    generator.configure(
+        ".",
+        Config::new().unwrap_wrappers(true).max_len(2).max_bytes(4),
+    );
+    generator
+        .add_protoc_arg("-Iproto")
+        .compile_protos(
+            &["proto/wrappers.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/wrappers_fixed.rs",
+        )
+        .unwrap();
+}
+
+fn any() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc().message_names(true);
+    generator
+        .add_protoc_arg("-Iproto")
+        .compile_protos(
+            &["proto/any.proto", "proto/google/protobuf/any.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/any.rs",
+        )
+        .unwrap();
+
+    // Fixed containers for checking that packing respects `max_bytes`
+    let mut generator = Generator::new();
+    generator.use_container_heapless().message_names(true);
+    generator.configure(".", Config::new().max_bytes(40));
+    generator.configure(".google.protobuf.Any.value", Config::new().max_bytes(2));
+    generator
+        .add_protoc_arg("-Iproto")
+        .compile_protos(
+            &["proto/any.proto", "proto/google/protobuf/any.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/any_fixed.rs",
+        )
+        .unwrap();
+}
+
+fn field_mask() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator.configure(".", Config::new().field_mask(true));
+    generator.configure(
+        ".field_mask.Device.limits",
+        Config::new().optional_repr(OptionalRepr::Option),
+    );
+    generator.configure(".field_mask.Device.network", Config::new().boxed(true));
+    generator
+        .add_protoc_arg("-Iproto")
+        .compile_protos(
+            &[
+                "proto/field_mask.proto",
+                "proto/google/protobuf/field_mask.proto",
+            ],
+            std::env::var("OUT_DIR").unwrap() + "/field_mask.rs",
+        )
+        .unwrap();
+}
+
+fn empty() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator.use_well_known_types();
+    generator.configure(".", Config::new().json(true).text(true));
+    generator
+        .add_protoc_arg("-Iproto")
+        .compile_protos(
+            &["proto/empty.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/empty.rs",
+        )
+        .unwrap();
+
+    // Without the runtime type, `Empty` is generated from `empty.proto` like any other message
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator
+        .add_protoc_arg("-Iproto")
+        .compile_protos(
+            &["proto/empty.proto", "proto/google/protobuf/empty.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/empty_generated.rs",
+        )
+        .unwrap();
+}
+
+fn structs() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator
+        .add_protoc_arg("-Iproto")
+        .compile_protos(
+            &["proto/struct.proto", "proto/google/protobuf/struct.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/struct.rs",
+        )
+        .unwrap();
+
+    let mut generator = Generator::new();
+    generator.use_container_heapless();
+    // `heapless` maps only implement `PartialEq` if their values implement `Eq`, which `Value`
+    // can't because of its `double` variant
+    generator.configure(
+        ".",
+        Config::new()
+            .max_len(4)
+            .max_bytes(16)
+            .no_partial_eq_impl(true),
+    );
+    // Without `alloc`, the recursion is broken by storing nested values as their encoded bytes
+    // instead of boxing them
+    for variant in ["struct_value", "list_value"] {
+        generator.configure(
+            &format!(".google.protobuf.Value.{variant}"),
+            Config::new().lazy(true).max_bytes(64),
+        );
+    }
+    generator
+        .add_protoc_arg("-Iproto")
+        .compile_protos(
+            &["proto/struct.proto", "proto/google/protobuf/struct.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/struct_fixed.rs",
+        )
+        .unwrap();
+}
+
+fn roundtrip() {
+    let protos = [
+        "proto/basic.proto",
+        "proto/nested.proto",
+        "proto/collections.proto",
+        "proto/map.proto",
+        "proto/recursive.proto",
+    ];
+    let mut generator = Generator::new();
+    generator.use_container_heapless().roundtrip_tests(true);
+    generator.configure(".", Config::new().max_len(4).max_bytes(6));
+    generator.configure(
+        ".basic.BasicTypes.int32_num",
+        Config::new().int_size(IntSize::S8),
+    );
+    generator.configure(
+        ".basic.BasicTypes.uint64_num",
+        Config::new().int_size(IntSize::S16),
+    );
+    generator.configure(".Recursive.recursive", Config::new().boxed(true));
+    generator.configure(".Recursive.of", Config::new().boxed(true));
+    generator
+        .compile_protos(
+            &protos,
+            std::env::var("OUT_DIR").unwrap() + "/roundtrip_heapless.rs",
+        )
+        .unwrap();
+
+    let mut generator = Generator::new();
+    generator
+        .use_container_alloc()
+        .roundtrip_tests(true)
+        .message_names(true)
+        // Kept for the dynamic message tests
+        .file_descriptor_set_path(std::env::var("OUT_DIR").unwrap() + "/roundtrip.fdset");
+    generator.configure(".Recursive.recursive", Config::new().boxed(true));
+    generator.configure(".Recursive.of", Config::new().boxed(true));
+    generator
+        .compile_protos(
+            &protos,
+            std::env::var("OUT_DIR").unwrap() + "/roundtrip_alloc.rs",
+        )
+        .unwrap();
+}
+
+fn domain() {
+    let mut generator = Generator::new();
+    generator.use_container_heapless();
+    generator.configure(".", Config::new().max_len(2).max_bytes(8));
+    generator.configure(
+        ".domain.Location",
+        Config::new().domain_type("crate::domain::Location"),
+    );
+    generator.configure(
+        ".domain.Sensor",
+        Config::new()
+            .domain_type("crate::domain::Sensor")
+            .domain_extra_fields("calibrated"),
+    );
+    generator.configure(
+        ".domain.Sensor.debug_counter",
+        Config::new().domain_skip(true),
+    );
+    generator
+        .compile_protos(
+            &["proto/domain.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/domain.rs",
+        )
+        .unwrap();
+}
+
+fn migration() {
+    let mut generator = Generator::new();
+    generator.use_container_heapless();
+    generator.configure(".", Config::new().max_len(4).max_bytes(16));
+    generator.configure(
+        ".migration",
+        Config::new().prost_path("crate::migration::pb"),
+    );
+    generator.configure(
+        ".migration.Reading.value",
+        Config::new().int_size(IntSize::S16),
+    );
+    generator
+        .compile_protos(
+            &["proto/migration.proto", "proto/migration2.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/migration.rs",
+        )
+        .unwrap();
+}
+
+fn reflection() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator.configure(".", Config::new().field_descriptors(true));
+    generator.configure(
+        ".reflection.Sample.type",
+        Config::new().rename_field("kind"),
+    );
+    generator.configure(".reflection.Sample.secret", Config::new().skip(true));
+    generator.configure(
+        ".reflection.Sample.Inner",
+        Config::new().field_descriptor_names(false),
+    );
+    generator
+        .compile_protos(
+            &["proto/reflection.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/reflection.rs",
+        )
+        .unwrap();
+}
+
+fn service() {
+    let mut generator = Generator::new();
+    generator.use_container_alloc();
+    generator.services(true);
+    generator
+        .compile_protos(
+            &["proto/service.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/service.rs",
+        )
+        .unwrap();
+}
+
+fn size_int() {
+    let mut generator = Generator::new();
+    generator.configure(
+        ".basic.BasicTypes.uint32_num",
+        Config::new().int_size(IntSize::Size),
+    );
+    generator.configure(
+        ".basic.BasicTypes.sint32_num",
+        Config::new().int_size(IntSize::Size),
+    );
+    generator.configure(
+        ".basic.BasicTypes.fixed32_num",
+        Config::new().int_size(IntSize::Size),
+    );
+    generator
+        .compile_protos(
+            &["proto/basic.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/size_int.rs",
+        )
+        .unwrap();
+}
+
+fn container_template() {
+    let mut generator = Generator::new();
+    generator.use_container_heapless().capacity_asserts(true);
+    generator.configure(".Data.s", Config::new().max_bytes(3));
+    generator.configure(
+        ".Data.b",
+        Config::new()
+            .vec_type("crate::container_template::ReorderedVec<$N, $T>")
+            .max_bytes(5),
+    );
+    generator.configure(
+        ".List.list",
+        Config::new()
+            .vec_type("crate::container_template::ReorderedVec<$N, $T>")
+            .max_len(2),
+    );
+    // Template without a capacity, so no max_len is needed
+    generator.configure(
+        ".NumList.list",
+        Config::new().vec_type("::std::vec::Vec<$T>"),
+    );
+    generator.configure(".StrList.list", Config::new().max_len(3));
+    generator.configure(
+        ".StrList.list.elem",
+        Config::new()
+            .string_type("::micropb::heapless::String<$N>")
+            .max_bytes(2),
+    );
+    generator.configure(".FixedList.list", Config::new().max_len(2));
+    generator.configure(".EnumList.list", Config::new().max_len(2));
+
+    generator.configure(
+        ".Map.mapping",
+        Config::new()
+            .map_type("crate::container_template::ReorderedMap<$N, $K, $V>")
+            .max_len(4),
+    );
+    generator.configure(".Map.mapping.key", Config::new().max_bytes(4));
+    generator.configure(".Map.mapping.value", Config::new().max_bytes(3));
+    generator
+        .compile_protos(
+            &["proto/collections.proto", "proto/map.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/container_template.rs",
+        )
+        .unwrap();
 }
@@ -0,0 +1,18 @@
+use micropb_gen::{Config, Generator};
+
+fn main() {
+    let mut generator = Generator::new();
+    generator.use_container_heapless();
+    generator.configure(".", Config::new().max_len(4).max_bytes(8).arbitrary(true));
+    // heapless maps only implement `PartialEq` for `Eq` values
+    generator.configure(".Arbitrary", Config::new().no_partial_eq_impl(true));
+    generator.configure(".Arbitrary.child", Config::new().boxed(true));
+    generator.configure(".Arbitrary.oneof_child", Config::new().boxed(true));
+    generator
+        .add_protoc_arg("-I../proto")
+        .compile_protos(
+            &["../proto/arbitrary.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/arbitrary.rs",
+        )
+        .unwrap();
+}
@@ -0,0 +1,27 @@
+#![no_main]
+
+extern crate alloc;
+
+use libfuzzer_sys::fuzz_target;
+use micropb::{MessageDecode, MessageEncode, PbEncoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/arbitrary.rs"));
+}
+
+// Messages built by `Arbitrary` always fit their containers, so they must survive an encode and
+// decode. The encodings are compared rather than the messages, since NaN isn't equal to itself.
+fuzz_target!(|msg: proto::Arbitrary| {
+    let mut encoder = PbEncoder::new(Vec::new());
+    msg.encode(&mut encoder).unwrap();
+    let encoded = encoder.into_writer();
+    assert_eq!(encoded.len(), msg.compute_size());
+
+    let mut decoded = proto::Arbitrary::default();
+    decoded.merge_from_bytes(&encoded).unwrap();
+    let mut encoder = PbEncoder::new(Vec::new());
+    decoded.encode(&mut encoder).unwrap();
+    assert_eq!(encoder.into_writer(), encoded);
+});
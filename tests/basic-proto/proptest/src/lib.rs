@@ -0,0 +1,50 @@
+//! Property tests driven by the generated `proptest` strategies
+
+extern crate alloc;
+
+mod proto_heapless {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/arbitrary_heapless.rs"));
+}
+
+mod proto_alloc {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/arbitrary_alloc.rs"));
+}
+
+#[cfg(all(test, feature = "proptest"))]
+mod tests {
+    use micropb::{MessageDecode, MessageEncode, PbEncoder};
+    use proptest::prelude::*;
+
+    use super::{proto_alloc, proto_heapless};
+
+    fn encode<M: MessageEncode>(msg: &M) -> Vec<u8> {
+        let mut encoder = PbEncoder::new(Vec::new());
+        msg.encode(&mut encoder).unwrap();
+        let encoded = encoder.into_writer();
+        assert_eq!(encoded.len(), msg.compute_size());
+        encoded
+    }
+
+    // The encodings are compared rather than the messages, since NaN isn't equal to itself
+    proptest! {
+        #[test]
+        fn round_trip_heapless(msg in proto_heapless::Arbitrary::arbitrary_strategy()) {
+            let encoded = encode(&msg);
+            let mut decoded = proto_heapless::Arbitrary::default();
+            decoded.merge_from_bytes(&encoded).unwrap();
+            prop_assert_eq!(encode(&decoded), encoded);
+        }
+
+        #[test]
+        fn round_trip_alloc(msg in proto_alloc::Arbitrary::arbitrary_strategy()) {
+            let encoded = encode(&msg);
+            let mut decoded = proto_alloc::Arbitrary::default();
+            decoded.merge_from_bytes(&encoded).unwrap();
+            prop_assert_eq!(encode(&decoded), encoded);
+        }
+    }
+}
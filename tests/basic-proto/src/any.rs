@@ -0,0 +1,106 @@
+use micropb::{
+    well_known::Timestamp, DecodeError, MessageDecode, MessageEncode, MessageName, PbDecoder,
+    PbEncoder,
+};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/any.rs"));
+}
+
+mod proto_fixed {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/any_fixed.rs"));
+}
+
+use proto::{
+    any_::{Command, Command_::Reboot, Ping},
+    google_::protobuf_::Any,
+};
+
+#[test]
+fn full_names() {
+    assert_eq!(Ping::FULL_NAME, "any.Ping");
+    assert_eq!(Command::FULL_NAME, "any.Command");
+    assert_eq!(Reboot::FULL_NAME, "any.Command.Reboot");
+    assert_eq!(Any::FULL_NAME, "google.protobuf.Any");
+    assert_eq!(Timestamp::FULL_NAME, "google.protobuf.Timestamp");
+}
+
+#[test]
+fn pack_unpack() {
+    let reboot = Reboot { delay_ms: 300 };
+    let any = Any::pack(&reboot).unwrap();
+    assert_eq!(any.type_url, "type.googleapis.com/any.Command.Reboot");
+    assert_eq!(any.value, [0x08, 0xAC, 0x02]);
+    assert!(any.is::<Reboot>());
+    assert!(!any.is::<Ping>());
+    assert_eq!(any.unpack::<Reboot>().unwrap(), Some(reboot.clone()));
+    assert_eq!(any.unpack::<Ping>().unwrap(), None);
+
+    // The envelope round-trips with the packed message intact
+    let mut cmd = Command {
+        id: 7,
+        ..Default::default()
+    };
+    cmd.set_payload(any);
+    let mut encoder = PbEncoder::new(vec![]);
+    cmd.encode(&mut encoder).unwrap();
+    let bytes = encoder.into_writer();
+    let mut decoded = Command::default();
+    decoded
+        .decode(&mut PbDecoder::new(bytes.as_slice()), bytes.len())
+        .unwrap();
+    assert_eq!(
+        decoded.payload().unwrap().unpack::<Reboot>().unwrap(),
+        Some(reboot)
+    );
+}
+
+#[test]
+fn type_url_prefix() {
+    let any = Any::pack_with_prefix(&Ping { seq: 1 }, "example.com/types/").unwrap();
+    assert_eq!(any.type_url, "example.com/types/any.Ping");
+    assert_eq!(any.unpack::<Ping>().unwrap(), Some(Ping { seq: 1 }));
+
+    // Only the name after the last slash is checked
+    let any = Any {
+        type_url: "any.Ping".to_owned(),
+        value: vec![0x08, 0x02],
+    };
+    assert_eq!(any.unpack::<Ping>().unwrap(), Some(Ping { seq: 2 }));
+    let any = Any {
+        type_url: "type.googleapis.com/other.Ping".to_owned(),
+        value: vec![],
+    };
+    assert_eq!(any.unpack::<Ping>().unwrap(), None);
+}
+
+#[test]
+fn unpack_error() {
+    let any = Any {
+        type_url: "type.googleapis.com/any.Ping".to_owned(),
+        value: vec![0x08],
+    };
+    assert_eq!(any.unpack::<Ping>(), Err(DecodeError::UnexpectedEof));
+}
+
+#[test]
+fn max_bytes() {
+    use proto_fixed::{
+        any_::{Command_::Reboot, Ping},
+        google_::protobuf_::Any,
+    };
+
+    // `value` holds at most 2 bytes
+    let any = Any::pack(&Ping { seq: 1 }).unwrap();
+    assert_eq!(any.value, [0x08, 0x01]);
+    assert_eq!(any.unpack::<Ping>().unwrap(), Some(Ping { seq: 1 }));
+    assert!(Any::pack(&Reboot { delay_ms: 300 }).is_err());
+    // `type_url` holds at most 40 bytes
+    assert!(
+        Any::pack_with_prefix(&Ping { seq: 1 }, "https://example.com/a/longer/prefix/").is_err()
+    );
+}
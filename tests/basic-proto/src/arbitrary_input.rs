@@ -0,0 +1,173 @@
+use micropb::{MessageDecode, MessageEncode, PbDecoder, PbEncoder};
+
+mod heapless_proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/arbitrary_heapless.rs"));
+}
+
+mod alloc_proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/arbitrary_alloc.rs"));
+}
+
+/// Small deterministic PRNG, so failures are reproducible
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// Decode the input into every generated message, from both contiguous and split readers. The
+/// results don't matter, as long as nothing panics.
+fn decode_all(data: &[u8]) {
+    let _ = heapless_proto::Arbitrary::default().merge_from_bytes(data);
+    let _ = alloc_proto::Arbitrary::default().merge_from_bytes(data);
+    let _ = alloc_proto::Leaf::default().merge_from_bytes(data);
+
+    let (first, second) = data.split_at(data.len() / 2);
+    let mut decoder = PbDecoder::new((first, second));
+    let _ = heapless_proto::Arbitrary::default().decode(&mut decoder, data.len());
+    let mut decoder = PbDecoder::new((first, second));
+    let _ = alloc_proto::Arbitrary::default().decode(&mut decoder, data.len());
+}
+
+fn seed() -> Vec<u8> {
+    use alloc_proto::{Arbitrary, Arbitrary_, Kind, Leaf};
+
+    let mut leaf = Leaf {
+        num: -5,
+        big: i64::MIN,
+        fixed: 7,
+        real: 1.5,
+        packed: vec![1, 300, 70000],
+        unpacked: vec![-1, 2],
+        ..Default::default()
+    };
+    leaf.set_name("leaf".to_owned());
+
+    let mut msg = Arbitrary {
+        text: "hello world".to_owned(),
+        data: vec![0xAA; 12],
+        strs: vec!["a".to_owned(), "bc".to_owned(), "".to_owned()],
+        blobs: vec![vec![1, 2, 3]],
+        fixeds: vec![u64::MAX, 0],
+        leaf_list: vec![leaf.clone(), Leaf::default()],
+        kind: Kind::B,
+        kind_list: vec![Kind::A, Kind(99)],
+        choice: Some(Arbitrary_::Choice::OneofChild(Box::default())),
+        ..Default::default()
+    };
+    msg.leaves.insert("key".to_owned(), leaf.clone());
+    msg.kinds.insert(-3, Kind::A);
+    msg.set_leaf(leaf.clone());
+    msg.set_flag(true);
+    msg.child = Some(Box::new(Arbitrary {
+        choice: Some(Arbitrary_::Choice::OneofLeaf(leaf)),
+        ..Default::default()
+    }));
+
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    let data = encoder.into_writer();
+
+    let mut decoded = Arbitrary::default();
+    decoded.merge_from_bytes(&data).unwrap();
+    assert_eq!(decoded, msg);
+    data
+}
+
+#[test]
+fn random_bytes() {
+    let mut rng = XorShift(0x2545F4914F6CDD1D);
+    for _ in 0..5000 {
+        let data: Vec<u8> = (0..rng.below(64)).map(|_| rng.next() as u8).collect();
+        decode_all(&data);
+    }
+}
+
+#[test]
+fn truncated_input() {
+    let seed = seed();
+    for len in 0..seed.len() {
+        decode_all(&seed[..len]);
+    }
+}
+
+#[test]
+fn mutated_input() {
+    let seed = seed();
+    let mut rng = XorShift(0x9E3779B97F4A7C15);
+    for _ in 0..5000 {
+        let mut data = seed.clone();
+        for _ in 0..=rng.below(4) {
+            let idx = rng.below(data.len());
+            data[idx] = match rng.below(3) {
+                0 => rng.next() as u8,
+                1 => data[idx] ^ (1 << rng.below(8)),
+                _ => 0xFF,
+            };
+        }
+        decode_all(&data);
+    }
+}
+
+/// Fill a bounded message with random values, picking extreme values and full containers often,
+/// and check that its size never exceeds `MAX_SIZE`
+#[test]
+fn max_size() {
+    use heapless_proto::{Arbitrary, Leaf};
+
+    const MAX_SIZE: usize = match Leaf::MAX_SIZE {
+        Some(size) => size,
+        None => panic!("Leaf should be bounded"),
+    };
+    // Messages with boxed or unbounded fields have no maximum size
+    assert_eq!(Arbitrary::MAX_SIZE, None);
+    assert_eq!(alloc_proto::Leaf::MAX_SIZE, None);
+
+    let mut rng = XorShift(0xD1B54A32D192ED03);
+    let mut largest = 0;
+    for _ in 0..2000 {
+        // Every other value is the extreme value of its type
+        let extreme = rng.below(2) == 0;
+        let mut leaf = Leaf {
+            num: if extreme { i32::MIN } else { rng.next() as i32 },
+            big: if extreme { i64::MIN } else { rng.next() as i64 },
+            fixed: rng.next() as u32,
+            real: rng.next() as f64,
+            ..Default::default()
+        };
+        if rng.below(2) == 0 {
+            let len = if extreme { 8 } else { rng.below(9) };
+            leaf.set_name("xxxxxxxx"[..len].try_into().unwrap());
+        }
+        for _ in 0..if extreme { 4 } else { rng.below(5) } {
+            let val = if extreme { u32::MAX } else { rng.next() as u32 };
+            leaf.packed.push(val).unwrap();
+        }
+        for _ in 0..if extreme { 4 } else { rng.below(5) } {
+            let val = if extreme { i32::MIN } else { rng.next() as i32 };
+            leaf.unpacked.push(val).unwrap();
+        }
+
+        let size = leaf.compute_size();
+        assert!(size <= MAX_SIZE, "{size} > {MAX_SIZE}: {leaf:?}");
+        largest = largest.max(size);
+
+        let mut buf = [0; MAX_SIZE];
+        assert_eq!(leaf.encode_to_slice(&mut buf).unwrap().len(), size);
+    }
+    // The bound is tight for messages with every field at its largest
+    assert_eq!(largest, MAX_SIZE);
+}
@@ -0,0 +1,122 @@
+use micropb::{
+    size::sizeof_varint32, BackpatchWriter, BufferTooSmall, EncodeError, MessageDecode,
+    MessageEncode, PbEncoder,
+};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/arbitrary_alloc.rs"));
+}
+
+use proto::{Arbitrary, Leaf};
+
+use crate::reference;
+
+/// Message with a nested child of about `data_len` bytes
+fn message(data_len: usize) -> Arbitrary {
+    let mut leaf = Leaf {
+        num: 3,
+        packed: vec![1, 300],
+        ..Default::default()
+    };
+    leaf.set_name("leaf".to_owned());
+
+    let mut child = Arbitrary {
+        data: vec![0xAA; data_len],
+        ..Default::default()
+    };
+    child.set_leaf(leaf.clone());
+
+    let mut msg = Arbitrary {
+        text: "outer".to_owned(),
+        child: Some(Box::new(child)),
+        ..Default::default()
+    };
+    msg.leaves.insert("one".to_owned(), leaf);
+    msg
+}
+
+fn encode_forward(msg: &Arbitrary) -> Vec<u8> {
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    encoder.into_writer()
+}
+
+fn encode_backpatch(msg: &Arbitrary, writer: BackpatchWriter) -> Vec<u8> {
+    let mut encoder = PbEncoder::new(writer);
+    msg.encode(&mut encoder).unwrap();
+    encoder.into_writer().as_written().to_vec()
+}
+
+#[test]
+fn exact() {
+    let mut buf = vec![0; 32 * 1024];
+    // Lengths of the child take 1, 2, and 3 bytes
+    for (data_len, len_size) in [(10, 1), (200, 2), (20000, 3)] {
+        let msg = message(data_len);
+        let child_len = msg.child.as_ref().unwrap().compute_size();
+        assert_eq!(sizeof_varint32(child_len as u32), len_size);
+
+        let encoded = encode_backpatch(&msg, BackpatchWriter::exact(&mut buf));
+        assert_eq!(encoded, encode_forward(&msg));
+    }
+}
+
+#[test]
+fn padded() {
+    use prost::Message;
+
+    let mut buf = vec![0; 32 * 1024];
+    for data_len in [10, 200, 20000] {
+        let msg = message(data_len);
+        let forward = encode_forward(&msg);
+        let child_len = msg.child.as_ref().unwrap().compute_size();
+
+        for width in 1..=5 {
+            let encoded = encode_backpatch(&msg, BackpatchWriter::padded(&mut buf, width));
+            // The child is padded unless its length needs more bytes. The leaf inside the child,
+            // the map entry, and the leaf inside the map entry are all shorter than 128 bytes.
+            let padding = width.saturating_sub(sizeof_varint32(child_len as u32)) + 3 * (width - 1);
+            assert_eq!(encoded.len(), forward.len() + padding);
+
+            let mut decoded = Arbitrary::default();
+            decoded.merge_from_bytes(&encoded).unwrap();
+            assert_eq!(decoded, msg);
+
+            // Padded lengths are accepted by other decoders as well
+            let reference = reference::Arbitrary::decode(encoded.as_slice()).unwrap();
+            assert_eq!(
+                reference,
+                reference::Arbitrary::decode(forward.as_slice()).unwrap()
+            );
+            assert_eq!(reference.child.unwrap().data.len(), data_len);
+        }
+    }
+}
+
+#[test]
+fn buffer_too_small() {
+    let msg = message(200);
+    let size = msg.compute_size();
+
+    let mut buf = vec![0; size];
+    assert_eq!(
+        encode_backpatch(&msg, BackpatchWriter::exact(&mut buf)),
+        encode_forward(&msg)
+    );
+
+    // Moving the child forward needs an extra byte at the end of the slice
+    let mut buf = vec![0; size - 1];
+    let mut encoder = PbEncoder::new(BackpatchWriter::exact(&mut buf));
+    let err = msg.encode(&mut encoder).unwrap_err();
+    assert!(matches!(
+        err,
+        EncodeError::Writer(BufferTooSmall { needed: 1, .. })
+    ));
+
+    // Padding makes the message bigger than the computed size
+    let mut buf = vec![0; size];
+    let mut encoder = PbEncoder::new(BackpatchWriter::padded(&mut buf, 3));
+    assert!(msg.encode(&mut encoder).is_err());
+}
@@ -33,6 +33,13 @@ fn boxed_and_option() {
     assert_eq!(basic.uint32_num, Box::new(3));
     assert_eq!(basic.uint32_num(), Some(&3));
     assert!(basic._has.uint32_num());
+
+    // Fields set above, including the ones represented by Option, aren't missing
+    let missing: Vec<_> = basic.missing_fields().collect();
+    assert!(!missing.contains(&1));
+    assert!(!missing.contains(&3));
+    assert!(!missing.contains(&11));
+    assert!(missing.contains(&2));
 }
 
 #[test]
@@ -0,0 +1,75 @@
+use micropb::{DecodeError, MessageDecode, PbDecoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/bytes_range.rs"));
+}
+
+use proto::{Frame, Wrapper};
+
+#[test]
+fn range_types() {
+    fn assert_static<T: 'static>(_: &T) {}
+
+    let frame = Frame::default();
+    let _: &core::ops::Range<usize> = &frame.payload;
+    let _: Option<&core::ops::Range<usize>> = frame.trailer();
+    // Messages with range fields don't borrow the input
+    assert_static(&frame);
+}
+
+#[test]
+fn decode_ranges() {
+    let data = [
+        0x08, 0x01, // seq
+        0x12, 3, 0x10, 0x20, 0x30, // payload
+        0x1A, 1, 0x40, // trailer
+        0x22, 2, b'o', b'k', // note
+    ];
+    let mut frame = Frame::default();
+    frame.merge_from_bytes(&data).unwrap();
+    assert_eq!(frame.seq, 1);
+    assert_eq!(frame.payload, 4..7);
+    assert_eq!(frame.payload_slice(&data), &[0x10, 0x20, 0x30]);
+    assert_eq!(frame.trailer_slice(&data), Some([0x40].as_slice()));
+    assert_eq!(frame.note, "ok");
+
+    // Empty and missing values
+    let data = [0x12, 0];
+    let mut frame = Frame::default();
+    frame.merge_from_bytes(&data).unwrap();
+    assert_eq!(frame.payload_slice(&data), &[]);
+    assert_eq!(frame.trailer_slice(&data), None);
+}
+
+#[test]
+fn nested_ranges() {
+    // Ranges are relative to the start of the whole input, not the nested message
+    let data = [
+        0x08, 0x07, // id
+        0x12, 4, 0x12, 2, 0xAA, 0xBB, // frame
+    ];
+    let mut wrapper = Wrapper::default();
+    wrapper.merge_from_bytes(&data).unwrap();
+    let frame = wrapper.frame().unwrap();
+    assert_eq!(frame.payload, 6..8);
+    assert_eq!(frame.payload_slice(&data), &[0xAA, 0xBB]);
+}
+
+#[test]
+fn non_contiguous_reader() {
+    let data = [0x08, 0x01, 0x12, 1, 0x10];
+    let (first, second) = data.split_at(2);
+    let mut decoder = PbDecoder::new((first, second));
+    let mut frame = Frame::default();
+    assert_eq!(
+        frame.decode(&mut decoder, data.len()),
+        Err(DecodeError::NonContiguous)
+    );
+
+    // Messages without range fields can be decoded as usual
+    let mut decoder = PbDecoder::new((first, second));
+    decoder.decode_tag().unwrap();
+    assert_eq!(decoder.decode_varint32(), Ok(1));
+}
@@ -204,7 +204,7 @@ fn decode_packed() {
 fn decode_packed_fixed() {
     let mut list = proto::FixedList::default();
     // non-packed decoding
-    let mut decoder = PbDecoder::new([0x08, 0x12, 0x11, 0x00, 0x00].as_slice());
+    let mut decoder = PbDecoder::new([0x0D, 0x12, 0x11, 0x00, 0x00].as_slice());
     let len = decoder.as_reader().len();
     list.decode(&mut decoder, len).unwrap();
     assert_eq!(list.list.len(), 1);
@@ -271,6 +271,44 @@ fn decode_packed_enums() {
     );
 }
 
+fn decode_bytes<M: MessageDecode + Default>(bytes: &[u8]) -> M {
+    let mut msg = M::default();
+    msg.decode(&mut PbDecoder::new(bytes), bytes.len()).unwrap();
+    msg
+}
+
+#[test]
+fn decode_packed_unpacked_mixed() {
+    // Repeated scalars are decoded the same way regardless of whether they're packed on the wire,
+    // and regardless of how they're declared in the schema
+    let unpacked = [0x08, 0x01, 0x08, 0x96, 0x01, 0x08, 0x03];
+    let packed = [0x0A, 4, 0x01, 0x96, 0x01, 0x03];
+    let mixed = [0x08, 0x01, 0x0A, 2, 0x96, 0x01, 0x08, 0x03];
+    let expected = [1, 150, 3];
+    for bytes in [unpacked.as_slice(), &packed, &mixed] {
+        assert_eq!(decode_bytes::<proto::NumList>(bytes).list, expected);
+    }
+
+    let unpacked = [0x0D, 0x01, 0x00, 0x00, 0x00, 0x0D, 0x02, 0x00, 0x00, 0x00];
+    let packed = [0x0A, 8, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00];
+    let mixed = [
+        0x0A, 4, 0x01, 0x00, 0x00, 0x00, 0x0D, 0x02, 0x00, 0x00, 0x00,
+    ];
+    for bytes in [unpacked.as_slice(), &packed, &mixed] {
+        assert_eq!(decode_bytes::<proto::FixedList>(bytes).list, [1, 2]);
+    }
+
+    let unpacked = [0x08, 0x01, 0x08, 0x02];
+    let packed = [0x0A, 2, 0x01, 0x02];
+    let mixed = [0x0A, 1, 0x01, 0x08, 0x02];
+    for bytes in [unpacked.as_slice(), &packed, &mixed] {
+        assert_eq!(
+            decode_bytes::<proto::EnumList>(bytes).list,
+            [proto::Enum(1), proto::Enum(2)]
+        );
+    }
+}
+
 #[test]
 fn encode_packed_enums() {
     let enumlist = proto::EnumList {
@@ -1,5 +1,10 @@
 use std::mem::{size_of, size_of_val};
 
+use micropb::{
+    arrayvec::{ArrayString, ArrayVec, CapacityError},
+    EncodeError, MessageDecode, MessageEncode, PbEncoder,
+};
+
 mod proto {
     #![allow(clippy::all)]
     #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
@@ -15,27 +20,74 @@ fn string_bytes() {
     assert_eq!(data.b.as_slice(), &[0x0, 0xFF]);
     assert_eq!(data.s.capacity(), 3);
     assert_eq!(data.b.capacity(), 5);
-    let _: micropb::arrayvec::ArrayString<3> = data.s;
-    let _: micropb::arrayvec::ArrayVec<u8, 5> = data.b;
+    let _: ArrayString<3> = data.s;
+    let _: ArrayVec<u8, 5> = data.b;
 }
 
 #[test]
 fn repeated() {
     let list = proto::List::default();
     assert!(list.list.is_empty());
-    assert_eq!(
-        size_of_val(&list),
-        size_of::<micropb::arrayvec::ArrayVec<proto::Data, 2>>()
-    );
+    assert_eq!(size_of_val(&list), size_of::<ArrayVec<proto::Data, 2>>());
     assert_eq!(list.list.capacity(), 2);
-    let _: micropb::arrayvec::ArrayVec<proto::Data, 2> = list.list;
+    let _: ArrayVec<proto::Data, 2> = list.list;
 
     let numlist = proto::NumList::default();
     assert!(numlist.list.is_empty());
     assert_eq!(numlist.list.capacity(), 2);
+    assert_eq!(size_of_val(&numlist), size_of::<ArrayVec<u8, 2>>());
+    let _: ArrayVec<u8, 2> = numlist.list;
+}
+
+fn data(s: &str, b: &[u8]) -> proto::Data {
+    let mut data = proto::Data::default();
+    data.set_s(ArrayString::from(s).unwrap());
+    data.set_b(ArrayVec::try_from(b).unwrap());
+    data
+}
+
+fn list() -> proto::List {
+    let mut list = proto::List::default();
+    list.list.push(data("ab", &[1, 2, 3]));
+    list.list.push(data("", &[]));
+    list
+}
+
+const LIST_BYTES: [u8; 17] = [
+    0x0A, 9, 0x0A, 2, b'a', b'b', 0x12, 3, 1, 2, 3, // first element
+    0x0A, 4, 0x0A, 0, 0x12, 0, // second element
+];
+
+#[test]
+fn encode_roundtrip() {
+    let list = list();
+    let mut encoder = PbEncoder::new(ArrayVec::<u8, 32>::new());
+    list.encode(&mut encoder).unwrap();
+    let bytes = encoder.into_writer();
+    assert_eq!(bytes.as_slice(), LIST_BYTES);
+    assert_eq!(bytes.len(), list.compute_size());
+
+    let mut decoded = proto::List::default();
+    decoded.merge_from_bytes(&bytes).unwrap();
+    assert_eq!(decoded, list);
+}
+
+#[test]
+fn encode_capacity() {
+    let list = list();
+    // Message exactly fills the buffer
+    let mut encoder = PbEncoder::new(ArrayVec::<u8, 17>::new());
+    list.encode(&mut encoder).unwrap();
+    let bytes = encoder.into_writer();
+    assert!(bytes.is_full());
+    assert_eq!(bytes.as_slice(), LIST_BYTES);
+
+    // One byte short, so the last element fails before its length prefix is written, and the
+    // bytes written before it are left in place
+    let mut encoder = PbEncoder::new(ArrayVec::<u8, 16>::new());
     assert_eq!(
-        size_of_val(&numlist),
-        size_of::<micropb::arrayvec::ArrayVec<u8, 2>>()
+        list.encode(&mut encoder),
+        Err(EncodeError::Writer(CapacityError::new(())))
     );
-    let _: micropb::arrayvec::ArrayVec<u8, 2> = numlist.list;
+    assert_eq!(encoder.into_writer().as_slice(), &LIST_BYTES[..12]);
 }
@@ -1,6 +1,6 @@
 use std::mem::{size_of, size_of_val};
 
-use micropb::{DecodeError, MessageDecode, PbDecoder};
+use micropb::{DecodeError, MessageDecode, MessageEncode, PbDecoder};
 
 mod proto {
     #![allow(clippy::all)]
@@ -64,6 +64,30 @@ fn map() {
     > = map.mapping;
 }
 
+#[test]
+fn max_size() {
+    // 1-byte tags, length prefixes, and contents of 3 and 5 bytes
+    assert_eq!(proto::Data::MAX_SIZE, Some(12));
+    // 2 records containing a Data each
+    assert_eq!(proto::List::MAX_SIZE, Some(2 * (1 + 1 + 12)));
+    // 2 u8 elements, which take 2 bytes each as varints
+    assert_eq!(proto::NumList::MAX_SIZE, Some(2 * (1 + 2)));
+    assert_eq!(proto::StrList::MAX_SIZE, Some(3 * (1 + 1 + 2)));
+    // Packed fields have one tag and length prefix
+    assert_eq!(proto::FixedList::MAX_SIZE, Some(1 + 1 + 2 * 4));
+    assert_eq!(proto::EnumList::MAX_SIZE, Some(1 + 1 + 2 * 10));
+    // Each entry contains a 1-byte key tag, a 5-byte key, a 1-byte value tag, and a 4-byte value
+    assert_eq!(proto::Map::MAX_SIZE, Some(8 * (1 + 1 + 11)));
+
+    let mut list = proto::List::default();
+    let mut data = proto::Data::default();
+    data.set_s("abc".try_into().unwrap());
+    data.set_b(micropb::heapless::Vec::from_slice(&[0xFF; 5]).unwrap());
+    list.list.push(data.clone()).unwrap();
+    list.list.push(data).unwrap();
+    assert_eq!(Some(list.compute_size()), proto::List::MAX_SIZE);
+}
+
 #[test]
 fn decode_string_bytes_cap() {
     let mut data = proto::Data::default();
@@ -81,11 +105,23 @@ fn decode_string_bytes_cap() {
 
     let mut decoder = PbDecoder::new([0x0A, 4, b'a', b'b', b'c', b'd'].as_slice()); // field 1
     let len = decoder.as_reader().len();
-    assert_eq!(data.decode(&mut decoder, len), Err(DecodeError::Capacity));
+    assert_eq!(
+        data.decode(&mut decoder, len),
+        Err(DecodeError::Capacity {
+            needed: 1,
+            capacity: 3
+        })
+    );
 
     let mut decoder = PbDecoder::new([0x12, 6, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06].as_slice()); // field 2
     let len = decoder.as_reader().len();
-    assert_eq!(data.decode(&mut decoder, len), Err(DecodeError::Capacity));
+    assert_eq!(
+        data.decode(&mut decoder, len),
+        Err(DecodeError::Capacity {
+            needed: 1,
+            capacity: 5
+        })
+    );
 }
 
 #[test]
@@ -106,7 +142,13 @@ fn decode_repeated_cap() {
 
     let mut decoder = PbDecoder::new([0x0A, 3, 0x0A, 1, b'x'].as_slice()); // field 1
     let len = decoder.as_reader().len();
-    assert_eq!(list.decode(&mut decoder, len), Err(DecodeError::Capacity));
+    assert_eq!(
+        list.decode(&mut decoder, len),
+        Err(DecodeError::Capacity {
+            needed: 1,
+            capacity: 2
+        })
+    );
 
     let mut decoder = PbDecoder::new([0x0A, 3, 0x0A, 1, b'x'].as_slice()); // field 1
     decoder.ignore_repeated_cap_err = true;
@@ -122,7 +164,13 @@ fn decode_repeated_cap_inner() {
     let mut list = proto::StrList::default();
     let mut decoder = PbDecoder::new([0x0A, 3, b'a', b'b', b'c'].as_slice());
     let len = decoder.as_reader().len();
-    assert_eq!(list.decode(&mut decoder, len), Err(DecodeError::Capacity));
+    assert_eq!(
+        list.decode(&mut decoder, len),
+        Err(DecodeError::Capacity {
+            needed: 1,
+            capacity: 2
+        })
+    );
 }
 
 #[test]
@@ -138,7 +186,10 @@ fn decode_packed_cap() {
     let len = decoder.as_reader().len();
     assert_eq!(
         numlist.decode(&mut decoder, len),
-        Err(DecodeError::Capacity)
+        Err(DecodeError::Capacity {
+            needed: 1,
+            capacity: 2
+        })
     );
 
     let mut decoder = PbDecoder::new([0x0A, 1, 0x01].as_slice());
@@ -155,7 +206,10 @@ fn decode_packed_cap_oneshot() {
     let len = decoder.as_reader().len();
     assert_eq!(
         numlist.decode(&mut decoder, len),
-        Err(DecodeError::Capacity)
+        Err(DecodeError::Capacity {
+            needed: 1,
+            capacity: 2
+        })
     );
 
     numlist.list.clear();
@@ -177,7 +231,13 @@ fn decode_packed_fixed_cap() {
         .as_slice(),
     );
     let len = decoder.as_reader().len();
-    assert_eq!(list.decode(&mut decoder, len), Err(DecodeError::Capacity));
+    assert_eq!(
+        list.decode(&mut decoder, len),
+        Err(DecodeError::Capacity {
+            needed: 1,
+            capacity: 2
+        })
+    );
 }
 
 #[test]
@@ -198,8 +258,17 @@ fn decode_map_cap() {
         .as_slice(),
     );
     let len = decoder.as_reader().len();
-    assert_eq!(map.decode(&mut decoder, len), Err(DecodeError::Capacity));
+    assert_eq!(
+        map.decode(&mut decoder, len),
+        Err(DecodeError::Capacity {
+            needed: 1,
+            capacity: 8
+        })
+    );
     assert_eq!(map.mapping.len(), map.mapping.capacity());
+    // Error is attributed to the map field rather than the field inside the entry
+    let location = decoder.location();
+    assert_eq!((location.field_num, location.depth), (1, 0));
 
     let mut decoder = PbDecoder::new(
         [
@@ -224,7 +293,13 @@ fn decode_map_cap_inner() {
         .as_slice(),
     );
     let len = decoder.as_reader().len();
-    assert_eq!(map.decode(&mut decoder, len), Err(DecodeError::Capacity));
+    assert_eq!(
+        map.decode(&mut decoder, len),
+        Err(DecodeError::Capacity {
+            needed: 1,
+            capacity: 4
+        })
+    );
 
     let mut decoder = PbDecoder::new(
         [
@@ -233,5 +308,49 @@ fn decode_map_cap_inner() {
         .as_slice(),
     );
     let len = decoder.as_reader().len();
-    assert_eq!(map.decode(&mut decoder, len), Err(DecodeError::Capacity));
+    assert_eq!(
+        map.decode(&mut decoder, len),
+        Err(DecodeError::Capacity {
+            needed: 1,
+            capacity: 3
+        })
+    );
+}
+
+#[test]
+fn decode_map_entry_order() {
+    let mut map = proto::Map::default();
+    map.merge_from_bytes(&[
+        0x0A, 6, 0x12, 1, 0x01, 0x0A, 1, b'a', // value before key
+        0x0A, 3, 0x0A, 1, b'b', // missing value
+        0x0A, 3, 0x12, 1, 0x02, // missing key
+        0x0A, 12, 0x0A, 1, b'x', 0x12, 1, 0x03, 0x0A, 1, b'c', 0x12, 1, 0x04, // duplicates
+    ])
+    .unwrap();
+    assert_eq!(map.mapping.len(), 4);
+    assert_eq!(map.mapping[&"a".try_into().unwrap()], &[0x01]);
+    assert_eq!(map.mapping[&"b".try_into().unwrap()], &[]);
+    assert_eq!(map.mapping[&"".try_into().unwrap()], &[0x02]);
+    assert_eq!(map.mapping[&"c".try_into().unwrap()], &[0x04]);
+
+    // Later entries replace the whole value
+    map.merge_from_bytes(&[0x0A, 3, 0x0A, 1, b'a']).unwrap();
+    assert_eq!(map.mapping[&"a".try_into().unwrap()], &[]);
+}
+
+#[test]
+fn decode_map_duplicate_at_cap() {
+    let mut map = proto::Map::default();
+    for key in b'a'..b'a' + 8 {
+        map.merge_from_bytes(&[0x0A, 6, 0x0A, 1, key, 0x12, 1, 0x01])
+            .unwrap();
+    }
+    assert_eq!(map.mapping.len(), map.mapping.capacity());
+
+    // Duplicate key overwrites the existing entry without needing a new slot
+    map.merge_from_bytes(&[0x0A, 6, 0x0A, 1, b'c', 0x12, 1, 0x02])
+        .unwrap();
+    assert_eq!(map.mapping.len(), map.mapping.capacity());
+    assert_eq!(map.mapping[&"c".try_into().unwrap()], &[0x02]);
+    assert_eq!(map.mapping[&"a".try_into().unwrap()], &[0x01]);
 }
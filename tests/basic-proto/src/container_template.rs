@@ -0,0 +1,81 @@
+use micropb::{MessageDecode, MessageEncode, PbDecoder, PbEncoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/container_template.rs"));
+}
+
+/// Vec whose capacity parameter comes before the element type
+pub type ReorderedVec<const N: usize, T> = micropb::heapless::Vec<T, N>;
+
+/// Map whose capacity parameter comes before the key and value types
+pub type ReorderedMap<const N: usize, K, V> = micropb::heapless::FnvIndexMap<K, V, N>;
+
+#[test]
+fn vec_type() {
+    let data = proto::Data::default();
+    assert_eq!(data.b.capacity(), 5);
+    let _: micropb::heapless::Vec<u8, 5> = data.b;
+
+    let list = proto::List::default();
+    assert_eq!(list.list.capacity(), 2);
+    let _: micropb::heapless::Vec<proto::Data, 2> = list.list;
+
+    // Template without `$N` is dynamic-capacity
+    let numlist = proto::NumList::default();
+    let _: Vec<u32> = numlist.list;
+}
+
+#[test]
+fn string_type() {
+    let mut strlist = proto::StrList::default();
+    strlist.list.push("ab".try_into().unwrap()).unwrap();
+    assert_eq!(strlist.list[0].capacity(), 2);
+    let _: micropb::heapless::Vec<micropb::heapless::String<2>, 3> = strlist.list;
+}
+
+#[test]
+fn map_type() {
+    let map = proto::Map::default();
+    assert_eq!(map.mapping.capacity(), 4);
+    let _: micropb::heapless::FnvIndexMap<
+        micropb::heapless::String<4>,
+        micropb::heapless::Vec<u8, 3>,
+        4,
+    > = map.mapping;
+}
+
+#[test]
+fn round_trip() {
+    let mut map = proto::Map::default();
+    map.mapping
+        .insert(
+            "a".try_into().unwrap(),
+            [1, 2].as_slice().try_into().unwrap(),
+        )
+        .unwrap();
+
+    let mut encoder = PbEncoder::new(vec![]);
+    map.encode(&mut encoder).unwrap();
+    let bytes = encoder.into_writer();
+
+    let mut decoded = proto::Map::default();
+    decoded
+        .decode(&mut PbDecoder::new(bytes.as_slice()), bytes.len())
+        .unwrap();
+    assert_eq!(decoded.mapping, map.mapping);
+
+    let mut numlist = proto::NumList::default();
+    numlist.list.extend([1, 300, 70000]);
+
+    let mut encoder = PbEncoder::new(vec![]);
+    numlist.encode(&mut encoder).unwrap();
+    let bytes = encoder.into_writer();
+
+    let mut decoded = proto::NumList::default();
+    decoded
+        .decode(&mut PbDecoder::new(bytes.as_slice()), bytes.len())
+        .unwrap();
+    assert_eq!(decoded.list, [1, 300, 70000]);
+}
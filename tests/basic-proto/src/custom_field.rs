@@ -37,7 +37,7 @@ impl FieldEncode for MockField {
     fn encode_fields<W: micropb::PbWrite>(
         &self,
         encoder: &mut micropb::PbEncoder<W>,
-    ) -> Result<(), W::Error> {
+    ) -> Result<(), micropb::EncodeError<W::Error>> {
         for tag in &self.tags {
             encoder.encode_tag(*tag)?;
         }
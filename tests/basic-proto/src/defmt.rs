@@ -0,0 +1,25 @@
+use micropb::{MessageDecode, MessageEncode, PbEncoder};
+
+// The `defmt` feature is never enabled, so the `defmt::Format` derives must compile away without
+// the `defmt` crate
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/defmt.rs"));
+}
+
+use proto::nested_::{Nested, Nested_};
+
+#[test]
+fn feature_off() {
+    let mut msg = Nested::default();
+    msg.basic.set_boolean(true);
+    msg._has.set_basic();
+    msg.inner = Some(Nested_::Inner::InnerEnum(Nested_::InnerEnum::Val));
+
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    let mut decoded = Nested::default();
+    decoded.merge_from_bytes(&encoder.into_writer()).unwrap();
+    assert_eq!(decoded, msg);
+}
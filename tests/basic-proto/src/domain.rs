@@ -0,0 +1,185 @@
+use std::collections::BTreeMap;
+
+use micropb::heapless::String;
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/domain.rs"));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Celsius,
+    Kelvin,
+}
+
+impl TryFrom<proto::domain_::Unit> for Unit {
+    type Error = ();
+
+    fn try_from(unit: proto::domain_::Unit) -> Result<Self, ()> {
+        match unit {
+            proto::domain_::Unit::Celsius => Ok(Unit::Celsius),
+            proto::domain_::Unit::Kelvin => Ok(Unit::Kelvin),
+            _ => Err(()),
+        }
+    }
+}
+
+impl From<Unit> for proto::domain_::Unit {
+    fn from(unit: Unit) -> Self {
+        match unit {
+            Unit::Celsius => proto::domain_::Unit::Celsius,
+            Unit::Kelvin => proto::domain_::Unit::Kelvin,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Pin(u32),
+    Address(String<8>),
+}
+
+impl From<proto::domain_::Sensor_::Source> for Source {
+    fn from(source: proto::domain_::Sensor_::Source) -> Self {
+        match source {
+            proto::domain_::Sensor_::Source::Pin(pin) => Source::Pin(pin),
+            proto::domain_::Sensor_::Source::Address(addr) => Source::Address(addr),
+        }
+    }
+}
+
+impl From<Source> for proto::domain_::Sensor_::Source {
+    fn from(source: Source) -> Self {
+        match source {
+            Source::Pin(pin) => Self::Pin(pin),
+            Source::Address(addr) => Self::Address(addr),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub lat: f32,
+    pub lon: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sensor {
+    pub id: u32,
+    pub name: String<8>,
+    pub unit: Unit,
+    pub offset: Option<i32>,
+    pub location: Option<Location>,
+    pub readings: Vec<f32>,
+    pub labels: BTreeMap<String<8>, u32>,
+    pub source: Option<Source>,
+    // Not in the message
+    pub calibrated: bool,
+}
+
+fn sensor() -> Sensor {
+    Sensor {
+        id: 3,
+        name: String::try_from("probe").unwrap(),
+        unit: Unit::Kelvin,
+        offset: Some(-2),
+        location: Some(Location {
+            lat: 1.5,
+            lon: -0.5,
+        }),
+        readings: vec![273.0, 274.5],
+        labels: [(String::try_from("room").unwrap(), 12)].into(),
+        source: Some(Source::Address(String::try_from("0x1f").unwrap())),
+        calibrated: false,
+    }
+}
+
+#[test]
+fn to_proto() {
+    let msg = proto::domain_::Sensor::try_from(sensor()).unwrap();
+    assert_eq!(msg.id, 3);
+    assert_eq!(msg.name, "probe");
+    assert_eq!(msg.unit, proto::domain_::Unit::Kelvin);
+    assert_eq!(msg.offset(), Some(&-2));
+    let location = msg.location().unwrap();
+    assert_eq!(location.lat, 1.5);
+    assert_eq!(location.lon, -0.5);
+    assert_eq!(msg.readings, [273.0, 274.5]);
+    assert_eq!(
+        msg.labels.get(&String::try_from("room").unwrap()),
+        Some(&12)
+    );
+    assert_eq!(
+        msg.source,
+        Some(proto::domain_::Sensor_::Source::Address(
+            String::try_from("0x1f").unwrap()
+        ))
+    );
+    // Skipped fields keep their defaults
+    assert_eq!(msg.debug_counter, 0);
+}
+
+#[test]
+fn from_proto() {
+    let mut msg = proto::domain_::Sensor::try_from(sensor()).unwrap();
+    msg.debug_counter = 5;
+    let sensor = Sensor::try_from(msg).unwrap();
+    // Extra fields are set to their defaults
+    assert_eq!(sensor, self::sensor());
+
+    let sensor = Sensor::try_from(proto::domain_::Sensor::default()).unwrap_err();
+    // Unknown enum values fail to convert
+    assert_eq!(
+        sensor,
+        proto::DomainError {
+            message: "domain.Sensor",
+            field: "unit"
+        }
+    );
+}
+
+#[test]
+fn presence() {
+    let msg = proto::domain_::Sensor {
+        unit: proto::domain_::Unit::Celsius,
+        ..Default::default()
+    };
+    let sensor = Sensor::try_from(msg).unwrap();
+    assert_eq!(sensor.offset, None);
+    assert_eq!(sensor.location, None);
+    assert_eq!(sensor.source, None);
+
+    let msg = proto::domain_::Sensor::try_from(sensor).unwrap();
+    assert_eq!(msg.offset(), None);
+    assert_eq!(msg.location(), None);
+    assert_eq!(msg.source, None);
+}
+
+#[test]
+fn capacity_overflow() {
+    let mut sensor = sensor();
+    sensor.readings.push(275.0);
+    let err = proto::domain_::Sensor::try_from(sensor).unwrap_err();
+    assert_eq!(
+        err,
+        proto::DomainError {
+            message: "domain.Sensor",
+            field: "readings"
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "failed to convert field `readings` of `domain.Sensor`"
+    );
+
+    let mut sensor = self::sensor();
+    for (i, key) in ["a", "b"].into_iter().enumerate() {
+        sensor
+            .labels
+            .insert(String::try_from(key).unwrap(), i as u32);
+    }
+    let err = proto::domain_::Sensor::try_from(sensor).unwrap_err();
+    assert_eq!(err.field, "labels");
+}
@@ -0,0 +1,101 @@
+use micropb::{
+    BackpatchWriter, BufferTooSmall, EncodeError, MessageDecode, MessageEncode, PbEncoder,
+    SliceWriter,
+};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/dyn_encode.rs"));
+}
+
+mod default_proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/dyn_encode_default.rs"));
+}
+
+fn encode<M: MessageEncode>(msg: &M) -> Vec<u8> {
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    encoder.into_writer()
+}
+
+/// Encode a message generated without `dyn_encode`, then decode it into its `dyn_encode` twin
+fn twin<M: MessageEncode, D: MessageDecode + Default>(msg: &M) -> (Vec<u8>, D) {
+    let data = encode(msg);
+    let mut decoded = D::default();
+    decoded.merge_from_bytes(&data).unwrap();
+    (data, decoded)
+}
+
+fn nested() -> default_proto::nested_::Nested {
+    let mut basic = default_proto::basic_::BasicTypes::default();
+    basic.set_int64_num(-300);
+    basic.set_fixed32_num(7);
+    basic.set_flt(2.5);
+    let mut msg = default_proto::nested_::Nested {
+        inner: Some(default_proto::nested_::Nested_::Inner::InnerMsg(
+            Default::default(),
+        )),
+        ..Default::default()
+    };
+    msg.set_basic(basic);
+    msg
+}
+
+fn map() -> default_proto::Map {
+    let mut msg = default_proto::Map::default();
+    msg.mapping.insert("a".to_owned(), vec![1, 2]);
+    msg.mapping.insert("bc".to_owned(), vec![]);
+    msg
+}
+
+#[test]
+fn same_output() {
+    let (data, msg) = twin::<_, proto::nested_::Nested>(&nested());
+    assert_eq!(encode(&msg), data);
+    let (data, msg) = twin::<_, proto::Map>(&map());
+    assert_eq!(encode(&msg), data);
+    let mut list = default_proto::List::default();
+    list.list.push(Default::default());
+    list.list[0].set_s("hello".to_owned());
+    let (data, msg) = twin::<_, proto::List>(&list);
+    assert_eq!(encode(&msg), data);
+    let fixed = default_proto::FixedList {
+        list: vec![1, 2, u32::MAX],
+    };
+    let (data, msg) = twin::<_, proto::FixedList>(&fixed);
+    assert_eq!(encode(&msg), data);
+    assert_eq!(msg.compute_size(), data.len());
+}
+
+#[test]
+fn writers() {
+    let (data, msg) = twin::<_, proto::nested_::Nested>(&nested());
+    let mut buf = [0; 64];
+
+    assert_eq!(msg.encode_to_slice(&mut buf).unwrap(), data);
+    let mut encoder = PbEncoder::new(BackpatchWriter::exact(&mut buf));
+    msg.encode(&mut encoder).unwrap();
+    assert_eq!(encoder.into_writer().as_written(), data);
+    assert_eq!(msg.encode_to_slice_reverse(&mut buf).unwrap(), data);
+}
+
+#[test]
+fn writer_error() {
+    let original = nested();
+    let (data, msg) = twin::<_, proto::nested_::Nested>(&original);
+
+    // The original error of the writer is returned, same as without `dyn_encode`
+    for len in 0..data.len() {
+        let mut buf = vec![0; len];
+        let mut encoder = PbEncoder::new(SliceWriter::new(&mut buf));
+        let err: EncodeError<BufferTooSmall> = msg.encode(&mut encoder).unwrap_err();
+
+        let mut expected_buf = vec![0; len];
+        let mut expected = PbEncoder::new(SliceWriter::new(&mut expected_buf));
+        assert_eq!(err, original.encode(&mut expected).unwrap_err());
+        assert_eq!(buf, expected_buf);
+    }
+}
@@ -0,0 +1,96 @@
+use micropb::{
+    DecodeError, DynMessageDecode, DynMessageEncode, MessageEncode, PbDecoder, PbEncoder,
+};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/implicit_presence.rs"));
+}
+
+fn non_opt() -> proto::NonOptional {
+    proto::NonOptional {
+        int32_num: -5,
+        st: String::from("status"),
+        ..Default::default()
+    }
+}
+
+fn coll() -> proto::Collections {
+    let mut coll = proto::Collections {
+        packed: vec![1, 150],
+        ..Default::default()
+    };
+    coll.mapping.insert(String::from("a"), 3);
+    coll
+}
+
+/// Encode each message of the registry as an ID followed by a length-delimited record
+fn encode_registry(registry: &[(u32, &dyn DynMessageEncode)]) -> Vec<u8> {
+    let mut encoder = PbEncoder::new(vec![]);
+    for (id, msg) in registry {
+        encoder.encode_varint32(*id).unwrap();
+        encoder
+            .encode_varint32(msg.compute_size_dyn() as u32)
+            .unwrap();
+        encoder
+            .encode_dyn(|encoder| msg.encode_dyn(encoder))
+            .unwrap();
+    }
+    encoder.into_writer()
+}
+
+#[test]
+fn encode() {
+    let (non_opt, coll) = (non_opt(), coll());
+    let registry: [(u32, &dyn DynMessageEncode); 2] = [(1, &non_opt), (2, &coll)];
+    let data = encode_registry(&registry);
+
+    let mut encoder = PbEncoder::new(vec![]);
+    encoder.encode_varint32(1).unwrap();
+    non_opt.encode_len_delimited(&mut encoder).unwrap();
+    encoder.encode_varint32(2).unwrap();
+    coll.encode_len_delimited(&mut encoder).unwrap();
+    assert_eq!(data, encoder.into_writer());
+
+    // Size is the same as the generic version
+    assert_eq!(registry[0].1.compute_size_dyn(), non_opt.compute_size());
+    assert_eq!(registry[1].1.compute_size_dyn(), coll.compute_size());
+}
+
+#[test]
+fn decode() {
+    let (non_opt, coll) = (non_opt(), coll());
+    let data = encode_registry(&[(2, &coll), (1, &non_opt), (2, &coll)]);
+
+    let mut decoded_non_opt = proto::NonOptional::default();
+    let mut decoded_coll = proto::Collections::default();
+    {
+        let mut registry: [(u32, &mut dyn DynMessageDecode); 2] =
+            [(1, &mut decoded_non_opt), (2, &mut decoded_coll)];
+        let mut decoder = PbDecoder::new(data.as_slice());
+        while decoder.bytes_read() < data.len() {
+            let id = decoder.decode_varint32().unwrap();
+            let len = decoder.decode_varint32().unwrap() as usize;
+            let (_, msg) = registry.iter_mut().find(|(i, _)| *i == id).unwrap();
+            msg.decode_dyn(&mut decoder, len).unwrap();
+        }
+    }
+    assert_eq!(decoded_non_opt, non_opt);
+    // Decoding the same message twice merges it
+    assert_eq!(decoded_coll.packed, [1, 150, 1, 150]);
+    assert_eq!(decoded_coll.mapping, coll.mapping);
+
+    let msg: &mut dyn DynMessageDecode = &mut decoded_coll;
+    msg.clear_dyn();
+    let mut encoder = PbEncoder::new(vec![]);
+    coll.encode(&mut encoder).unwrap();
+    msg.merge_from_bytes_dyn(&encoder.into_writer()).unwrap();
+    assert_eq!(decoded_coll, coll);
+
+    let msg: &mut dyn DynMessageDecode = &mut decoded_non_opt;
+    assert_eq!(
+        msg.merge_from_bytes_dyn(&[0x08]),
+        Err(DecodeError::UnexpectedEof)
+    );
+}
@@ -0,0 +1,88 @@
+use micropb::dynamic::{DescriptorPool, DynamicMessage, DynamicValue, FieldKind};
+use micropb::{FieldLabel, MessageDecode, MessageEncode, MessageName, PbDecoder, PbEncoder};
+
+use crate::roundtrip::proto_alloc;
+
+fn pool() -> DescriptorPool {
+    let fdset = std::fs::read(concat!(env!("OUT_DIR"), "/roundtrip.fdset")).unwrap();
+    DescriptorPool::from_file_descriptor_set(&fdset).unwrap()
+}
+
+fn encode<M: MessageEncode>(msg: &M) -> Vec<u8> {
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    encoder.into_writer()
+}
+
+fn decode<M: MessageDecode + Default>(bytes: &[u8]) -> M {
+    let mut msg = M::default();
+    msg.decode(&mut PbDecoder::new(bytes), bytes.len()).unwrap();
+    msg
+}
+
+/// Decode the output of the generated code dynamically, then check that encoding it again
+/// produces a message that the generated code decodes to the original
+fn check_roundtrip<M>(pool: &DescriptorPool, msg: &M)
+where
+    M: MessageEncode + MessageDecode + MessageName + Default + PartialEq + std::fmt::Debug,
+{
+    let bytes = encode(msg);
+    let dynamic = DynamicMessage::decode(pool, M::FULL_NAME, &bytes).unwrap();
+    assert!(dynamic.unknown_fields().is_empty());
+    let encoded = dynamic.encode(pool).unwrap();
+    assert_eq!(&decode::<M>(&encoded), msg);
+    assert_eq!(
+        DynamicMessage::decode(pool, M::FULL_NAME, &encoded).unwrap(),
+        dynamic
+    );
+}
+
+#[test]
+fn roundtrip_samples() {
+    let pool = pool();
+    for filled in [false, true] {
+        check_roundtrip(
+            &pool,
+            &proto_alloc::basic_::BasicTypes::_roundtrip_sample(filled, 1),
+        );
+        check_roundtrip(
+            &pool,
+            &proto_alloc::nested_::Nested::_roundtrip_sample(filled, 1),
+        );
+        check_roundtrip(&pool, &proto_alloc::List::_roundtrip_sample(filled, 1));
+        check_roundtrip(&pool, &proto_alloc::NumList::_roundtrip_sample(filled, 1));
+        check_roundtrip(&pool, &proto_alloc::StrList::_roundtrip_sample(filled, 1));
+        check_roundtrip(&pool, &proto_alloc::FixedList::_roundtrip_sample(filled, 1));
+        check_roundtrip(&pool, &proto_alloc::EnumList::_roundtrip_sample(filled, 1));
+        check_roundtrip(&pool, &proto_alloc::Map::_roundtrip_sample(filled, 1));
+        check_roundtrip(&pool, &proto_alloc::Recursive::_roundtrip_sample(filled, 3));
+    }
+}
+
+#[test]
+fn schema() {
+    let pool = pool();
+    let map = pool.message("Map").unwrap().field(1).unwrap();
+    assert_eq!(map.label, FieldLabel::Map);
+    assert_eq!(map.kind, FieldKind::Message("Map.MappingEntry".to_owned()));
+
+    let recursive = pool.message("Recursive").unwrap();
+    assert_eq!(recursive.field(1).unwrap().label, FieldLabel::Optional);
+    assert_eq!(recursive.field(3).unwrap().label, FieldLabel::Oneof);
+    assert_eq!(recursive.field(3).unwrap().oneof_index, Some(0));
+}
+
+#[test]
+fn decode_fields() {
+    let pool = pool();
+    let mut msg = proto_alloc::Map::default();
+    msg.mapping.insert("a".to_owned(), vec![1, 2]);
+    let dynamic = DynamicMessage::decode(&pool, "Map", &encode(&msg)).unwrap();
+    let entry = dynamic.get_list(1)[0].as_message().unwrap();
+    assert_eq!(entry.full_name(), "Map.MappingEntry");
+    assert_eq!(entry.get(1).and_then(DynamicValue::as_str), Some("a"));
+    assert_eq!(
+        entry.get(2).and_then(DynamicValue::as_bytes),
+        Some(&[1, 2][..])
+    );
+}
@@ -0,0 +1,98 @@
+use micropb::{MessageDecode, MessageEncode, PbEncoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/emit_defaults.rs"));
+}
+
+mod default_proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/implicit_presence.rs"));
+}
+
+fn encode<M: MessageEncode>(msg: &M) -> Vec<u8> {
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    let data = encoder.into_writer();
+    assert_eq!(msg.compute_size(), data.len());
+    let mut buf = [0; 128];
+    assert_eq!(msg.encode_to_slice_reverse(&mut buf).unwrap(), data);
+    data
+}
+
+static ZEROED_REPR: &[u8] = &[
+    0x08, 0x00, // field 1
+    0x10, 0x00, // field 2
+    0x18, 0x00, // field 3
+    0x20, 0x00, // field 4
+    0x28, 0x00, // field 5
+    0x30, 0x00, // field 6
+    0x3D, 0x00, 0x00, 0x00, 0x00, // field 7
+    0x41, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // field 8
+    0x4D, 0x00, 0x00, 0x00, 0x00, // field 9
+    0x51, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // field 10
+    0x58, 0x00, // field 11
+    0x65, 0x00, 0x00, 0x00, 0x00, // field 12
+    0x69, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // field 13
+    0x70, 0x00, // field 14
+    0x7A, 0x00, // field 15
+    0x82, 0x01, 0x00, // field 16
+];
+
+#[test]
+fn emit_defaults() {
+    assert_eq!(encode(&default_proto::NonOptional::default()), &[]);
+    assert_eq!(encode(&proto::NonOptional::default()), ZEROED_REPR);
+
+    // Non-default values are encoded the same way with or without the option
+    let msg = proto::NonOptional {
+        int32_num: -1,
+        flt: 1.0,
+        st: String::from("axe"),
+        ..Default::default()
+    };
+    let default_msg = default_proto::NonOptional {
+        int32_num: -1,
+        flt: 1.0,
+        st: String::from("axe"),
+        ..Default::default()
+    };
+    let data = encode(&msg);
+    assert_eq!(data.len(), ZEROED_REPR.len() + 9 + 3);
+    let mut decoded = default_proto::NonOptional::default();
+    decoded.merge_from_bytes(&data).unwrap();
+    assert_eq!(decoded, default_msg);
+    assert_eq!(
+        encode(&default_msg),
+        &[
+            0x08, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, // field 1
+            0x65, 0x00, 0x00, 0x80, 0x3F, // field 12
+            0x7A, 3, b'a', b'x', b'e', // field 15
+        ]
+    );
+}
+
+#[test]
+fn explicit_presence_unaffected() {
+    let mut msg = proto::Optional::default();
+    assert_eq!(encode(&msg), &[]);
+    msg.set_int32_num(0);
+    msg.set_st(String::new());
+    assert_eq!(
+        encode(&msg),
+        &[
+            0x08, 0x00, // field 1
+            0x7A, 0x00, // field 15
+        ]
+    );
+}
+
+#[test]
+fn empty_collections_skipped() {
+    let mut msg = proto::Collections::default();
+    assert_eq!(encode(&msg), &[]);
+    msg.packed.push(0);
+    assert_eq!(encode(&msg), &[0x0A, 0x01, 0x00]);
+}
@@ -0,0 +1,101 @@
+use std::mem::size_of;
+
+use micropb::{
+    json::{MessageFromJson, MessageToJson},
+    text::{MessageFromText, Text},
+    well_known::Empty,
+    MessageDecode, MessageEncode, PbEncoder,
+};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/empty.rs"));
+}
+
+mod proto_generated {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/empty_generated.rs"));
+}
+
+use proto::empty_::{Heartbeat, Heartbeat_::Reply};
+
+fn sample() -> Heartbeat {
+    let mut heartbeat = Heartbeat {
+        seq: 1,
+        beats: vec![Empty, Empty],
+        reply: Some(Reply::Ok(Empty)),
+        ..Default::default()
+    };
+    heartbeat.set_ack(Empty);
+    heartbeat
+}
+
+// Every `Empty` is encoded as a tag and a zero length
+const ENCODED: [u8; 10] = [0x08, 0x01, 0x12, 0x00, 0x1A, 0x00, 0x1A, 0x00, 0x22, 0x00];
+
+#[test]
+fn zero_sized() {
+    assert_eq!(size_of::<Empty>(), 0);
+    assert_eq!(size_of::<proto_generated::google_::protobuf_::Empty>(), 0);
+}
+
+#[test]
+fn encode_decode() {
+    let heartbeat = sample();
+    let mut encoder = PbEncoder::new(vec![]);
+    heartbeat.encode(&mut encoder).unwrap();
+    assert_eq!(encoder.into_writer(), ENCODED);
+    assert_eq!(heartbeat.compute_size(), ENCODED.len());
+
+    let mut decoded = Heartbeat::default();
+    decoded.merge_from_bytes(&ENCODED).unwrap();
+    assert_eq!(decoded, heartbeat);
+
+    // The generated `Empty` has the same wire format
+    let mut generated = proto_generated::empty_::Heartbeat::default();
+    generated.merge_from_bytes(&ENCODED).unwrap();
+    assert!(generated.ack().is_some());
+    assert_eq!(generated.beats.len(), 2);
+    let mut encoder = PbEncoder::new(vec![]);
+    generated.encode(&mut encoder).unwrap();
+    assert_eq!(encoder.into_writer(), ENCODED);
+}
+
+#[test]
+fn unknown_fields() {
+    // A peer with a newer definition of `Empty` sends fields inside it, which are skipped
+    let data = [
+        0x12, 0x05, 0x08, 0x96, 0x01, 0x10, 0x01, // ack
+        0x22, 0x03, 0x0A, 0x01, b'x', // reply.ok
+        0x08, 0x07, // seq
+    ];
+    let mut decoded = Heartbeat::default();
+    decoded.merge_from_bytes(&data).unwrap();
+    assert_eq!(decoded.ack(), Some(&Empty));
+    assert_eq!(decoded.reply, Some(Reply::Ok(Empty)));
+    assert_eq!(decoded.seq, 7);
+
+    let mut generated = proto_generated::empty_::Heartbeat::default();
+    generated.merge_from_bytes(&data).unwrap();
+    assert!(generated.ack().is_some());
+    assert_eq!(generated.seq, 7);
+
+    // Truncated content is still an error
+    let mut decoded = Heartbeat::default();
+    assert!(decoded.merge_from_bytes(&[0x12, 0x02, 0x08]).is_err());
+}
+
+#[test]
+fn json_text() {
+    let heartbeat = sample();
+    let mut json = String::new();
+    heartbeat.write_json(&mut json).unwrap();
+    assert_eq!(json, r#"{"seq":1,"ack":{},"beats":[{},{}],"ok":{}}"#);
+    assert_eq!(Heartbeat::from_json(&json).unwrap(), heartbeat);
+
+    let text = Text(&heartbeat).to_string();
+    assert_eq!(text, "seq: 1 ack { } beats { } beats { } ok { }");
+    assert_eq!(Heartbeat::from_text(&text).unwrap(), heartbeat);
+}
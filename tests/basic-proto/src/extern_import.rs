@@ -11,7 +11,10 @@ mod proto {
 struct Empty;
 
 impl MessageEncode for Empty {
-    fn encode<W: micropb::PbWrite>(&self, _encoder: &mut PbEncoder<W>) -> Result<(), W::Error> {
+    fn encode<W: micropb::PbWrite>(
+        &self,
+        _encoder: &mut PbEncoder<W>,
+    ) -> Result<(), micropb::EncodeError<W::Error>> {
         Ok(())
     }
 
@@ -31,6 +34,7 @@ impl MessageDecode for Empty {
 }
 
 #[test]
+#[allow(unused_assignments)]
 fn imported_types() {
     let mut nested = proto::nested_::Nested::default();
     let _basic: Empty = nested.basic;
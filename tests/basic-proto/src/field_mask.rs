@@ -0,0 +1,195 @@
+use micropb::field_mask::{unknown_paths, ApplyFieldMask, FieldMaskError};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/field_mask.rs"));
+}
+
+use proto::{
+    field_mask_::{
+        Device,
+        Device_::{Mode, Network},
+        Limits, UpdateDevice,
+    },
+    google_::protobuf_::FieldMask,
+};
+
+fn source() -> Device {
+    let mut power = Limits {
+        max: 20,
+        ..Default::default()
+    };
+    power.set_min(5);
+    let mut network = Network {
+        ssid: "home".to_owned(),
+        channel: 6,
+        ..Default::default()
+    };
+    network.set_power(power);
+
+    let mut device = Device {
+        id: 9,
+        network: Some(Box::new(network)),
+        limits: Some(Limits {
+            max: 100,
+            ..Default::default()
+        }),
+        ports: vec![80, 443],
+        mode: Some(Mode::SleepMs(500)),
+        ..Default::default()
+    };
+    device.set_name("sensor".to_owned());
+    device.counters.insert("boots".to_owned(), 3);
+    device
+}
+
+#[test]
+fn top_level() {
+    let src = source();
+    let mut dst = Device {
+        id: 1,
+        ports: vec![1, 2, 3],
+        ..Default::default()
+    };
+    dst.counters.insert("resets".to_owned(), 1);
+
+    dst.apply_field_mask(&src, &["name", "ports", "counters", "limits"])
+        .unwrap();
+    assert_eq!(dst.id, 1);
+    assert_eq!(dst.name(), Some(&"sensor".to_owned()));
+    // Repeated and map fields are replaced rather than merged
+    assert_eq!(dst.ports, [80, 443]);
+    assert_eq!(dst.counters, src.counters);
+    assert_eq!(dst.limits, src.limits);
+    assert_eq!(dst.network, None);
+
+    // Unset fields in the source clear the destination
+    dst.apply_field_mask(&Device::default(), &["name", "limits", "ports"])
+        .unwrap();
+    assert_eq!(dst.name(), None);
+    assert_eq!(dst.limits, None);
+    assert!(dst.ports.is_empty());
+    assert_eq!(dst.counters, src.counters);
+}
+
+#[test]
+fn nested() {
+    let src = source();
+    let mut dst = Device::default();
+    dst.apply_field_mask(
+        &src,
+        &["network.channel", "network.power.min", "limits.max"],
+    )
+    .unwrap();
+
+    // Nested messages are created as needed, and only the named fields are copied
+    let network = dst.network.as_deref().unwrap();
+    assert_eq!(network.channel, 6);
+    assert_eq!(network.ssid, "");
+    let power = network.power().unwrap();
+    assert_eq!(power.min(), Some(&5));
+    assert_eq!(power.max, 0);
+    assert_eq!(dst.limits.as_ref().unwrap().max, 100);
+
+    // Copying from an unset nested message resets the named fields
+    let mut dst = source();
+    dst.apply_field_mask(&Device::default(), &["network.power.max", "limits.min"])
+        .unwrap();
+    let network = dst.network.as_deref().unwrap();
+    assert_eq!(network.power().unwrap().max, 0);
+    assert_eq!(network.power().unwrap().min(), Some(&5));
+    assert_eq!(network.ssid, "home");
+    assert_eq!(dst.limits.as_ref().unwrap().max, 100);
+
+    // Copying a whole nested message
+    let mut dst = Device::default();
+    dst.apply_field_mask(&src, &["network"]).unwrap();
+    assert_eq!(dst.network, src.network);
+}
+
+#[test]
+fn oneof_members() {
+    let src = source();
+    let mut dst = Device::default();
+    dst.apply_field_mask(&src, &["sleep_ms"]).unwrap();
+    assert_eq!(dst.mode, Some(Mode::SleepMs(500)));
+
+    // Naming a member that isn't set in the source clears it in the destination
+    dst.apply_field_mask(&src, &["script"]).unwrap();
+    assert_eq!(dst.mode, Some(Mode::SleepMs(500)));
+    dst.mode = Some(Mode::Script("run".to_owned()));
+    dst.apply_field_mask(&src, &["script"]).unwrap();
+    assert_eq!(dst.mode, None);
+
+    // Naming the member set in the source replaces any other member
+    dst.mode = Some(Mode::Throttle(Limits::default()));
+    dst.apply_field_mask(&src, &["sleep_ms"]).unwrap();
+    assert_eq!(dst.mode, Some(Mode::SleepMs(500)));
+}
+
+#[test]
+fn unknown() {
+    let src = source();
+    let mut dst = Device::default();
+    assert_eq!(
+        dst.apply_field_mask(&src, &["id", "network.bssid"]),
+        Err(FieldMaskError {
+            path: "network.bssid",
+            segment: "bssid"
+        })
+    );
+    // Nothing is copied if any path is unknown
+    assert_eq!(dst, Device::default());
+
+    let paths = [
+        "mode",
+        "id.value",
+        "network.power.max.x",
+        "throttle.max",
+        "network..ssid",
+        "network.ssid",
+    ];
+    let errs: Vec<_> = unknown_paths::<Device, _, _>(&paths)
+        .map(|e| (e.path, e.segment))
+        .collect();
+    assert_eq!(
+        errs,
+        [
+            ("mode", "mode"),
+            ("id.value", "value"),
+            ("network.power.max.x", "x"),
+            // Fields of messages inside oneofs can't be named
+            ("throttle.max", "max"),
+            ("network..ssid", ""),
+        ]
+    );
+}
+
+#[test]
+fn update_request() {
+    let mut update = UpdateDevice::default();
+    update.set_device(source());
+    update.set_update_mask(FieldMask {
+        paths: vec!["id".to_owned(), "network.ssid".to_owned()],
+    });
+
+    let mut dst = Device::default();
+    dst.set_name("kept".to_owned());
+    dst.apply_field_mask(
+        update.device().unwrap(),
+        &update.update_mask().unwrap().paths,
+    )
+    .unwrap();
+    assert_eq!(dst.id, 9);
+    assert_eq!(dst.name(), Some(&"kept".to_owned()));
+    assert_eq!(dst.network.as_deref().unwrap().ssid, "home");
+    assert_eq!(dst.network.as_deref().unwrap().channel, 0);
+
+    // The request messages implement it as well
+    let mut dst = UpdateDevice::default();
+    dst.apply_field_mask(&update, &["update_mask.paths"])
+        .unwrap();
+    assert_eq!(dst.device(), None);
+    assert_eq!(dst.update_mask(), update.update_mask());
+}
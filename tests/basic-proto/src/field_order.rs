@@ -0,0 +1,150 @@
+use std::ops::Range;
+
+use micropb::{
+    size::{sizeof_tag, sizeof_varint64},
+    DecodeError, FieldDecode, FieldEncode, MessageDecode, MessageEncode, PbDecoder, PbEncoder,
+    PbRead, PbWrite, Tag, WIRE_TYPE_VARINT,
+};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/field_order.rs"));
+}
+
+mod default_proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/field_order_default.rs"));
+}
+
+use proto::{Inner, Ordered, Ordered_};
+
+/// Unknown field handler that keeps varint fields, sorted by field number
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct UnknownVarints(Vec<(u32, u64)>);
+
+impl FieldDecode for UnknownVarints {
+    fn decode_field<R: PbRead>(
+        &mut self,
+        tag: Tag,
+        decoder: &mut PbDecoder<R>,
+    ) -> Result<bool, DecodeError<R::Error>> {
+        if tag.wire_type() != WIRE_TYPE_VARINT {
+            return Ok(false);
+        }
+        let val = decoder.decode_varint64()?;
+        self.0.push((tag.field_num(), val));
+        self.0.sort_by_key(|(num, _)| *num);
+        Ok(true)
+    }
+}
+
+impl FieldEncode for UnknownVarints {
+    fn encode_fields<W: PbWrite>(
+        &self,
+        encoder: &mut PbEncoder<W>,
+    ) -> Result<(), micropb::EncodeError<W::Error>> {
+        self.encode_fields_in(encoder, 0..u32::MAX)
+    }
+
+    fn compute_fields_size(&self) -> usize {
+        self.0
+            .iter()
+            .map(|&(num, val)| {
+                sizeof_tag(Tag::from_parts(num, WIRE_TYPE_VARINT)) + sizeof_varint64(val)
+            })
+            .sum()
+    }
+
+    fn encode_fields_in<W: PbWrite>(
+        &self,
+        encoder: &mut PbEncoder<W>,
+        nums: Range<u32>,
+    ) -> Result<(), micropb::EncodeError<W::Error>> {
+        for &(num, val) in self.0.iter().filter(|(num, _)| nums.contains(num)) {
+            encoder.encode_tag(Tag::from_parts(num, WIRE_TYPE_VARINT))?;
+            encoder.encode_varint64(val)?;
+        }
+        Ok(())
+    }
+}
+
+fn message(choice: Ordered_::Choice) -> Ordered {
+    let mut msg = Ordered {
+        c: 5,
+        a: "hi".to_owned(),
+        choice: Some(choice),
+        list: vec![1, 2],
+        z: 10,
+        _unknown: UnknownVarints(vec![(6, 1), (9, 3), (12, 4)]),
+        ..Default::default()
+    };
+    msg.set_inner(Inner { a: 1, b: 2 });
+    msg
+}
+
+fn encode<M: MessageEncode>(msg: &M) -> Vec<u8> {
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    encoder.into_writer()
+}
+
+#[test]
+fn canonical_order() {
+    let msg = message(Ordered_::Choice::X(7));
+    let expected = [
+        0x0A, 0x02, b'h', b'i', // 1: a
+        0x12, 0x04, 0x08, 0x01, 0x10, 0x02, // 2: inner, with its fields in order
+        0x18, 0x07, // 3: x
+        0x22, 0x02, 0x01, 0x02, // 4: list
+        0x28, 0x05, // 5: c
+        0x30, 0x01, // 6: unknown
+        0x48, 0x03, // 9: unknown
+        0x50, 0x0A, // 10: z
+        0x60, 0x04, // 12: unknown
+    ];
+    assert_eq!(encode(&msg), expected);
+    assert_eq!(msg.compute_size(), expected.len());
+    let mut buf = [0; 64];
+    assert_eq!(msg.encode_to_slice_reverse(&mut buf).unwrap(), expected);
+
+    let mut decoded = Ordered::default();
+    decoded.merge_from_bytes(&expected).unwrap();
+    assert_eq!(decoded, msg);
+
+    // Oneof variants are encoded at their own field number
+    let msg = message(Ordered_::Choice::Y("y".to_owned()));
+    let expected = [
+        0x0A, 0x02, b'h', b'i', // 1: a
+        0x12, 0x04, 0x08, 0x01, 0x10, 0x02, // 2: inner
+        0x22, 0x02, 0x01, 0x02, // 4: list
+        0x28, 0x05, // 5: c
+        0x30, 0x01, // 6: unknown
+        0x42, 0x01, b'y', // 8: y
+        0x48, 0x03, // 9: unknown
+        0x50, 0x0A, // 10: z
+        0x60, 0x04, // 12: unknown
+    ];
+    assert_eq!(encode(&msg), expected);
+    assert_eq!(msg.encode_to_slice_reverse(&mut buf).unwrap(), expected);
+}
+
+#[test]
+fn canonical_order_reverse() {
+    // Messages without unknown handlers are encoded back-to-front in order as well
+    let inner = Inner { a: 1, b: 2 };
+    let mut buf = [0; 8];
+    assert_eq!(
+        inner.encode_to_slice_reverse(&mut buf).unwrap(),
+        &[0x08, 0x01, 0x10, 0x02]
+    );
+    assert_eq!(encode(&inner), &[0x08, 0x01, 0x10, 0x02]);
+}
+
+#[test]
+fn declaration_order() {
+    // Without canonical order, fields are encoded in declaration order
+    let inner = default_proto::Inner { a: 1, b: 2 };
+    assert_eq!(encode(&inner), &[0x10, 0x02, 0x08, 0x01]);
+}
@@ -0,0 +1,58 @@
+use micropb::{FieldStats, FieldStatsReader, MessageDecode, PbDecoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/field_stats.rs"));
+}
+
+use proto::nested_::Nested;
+
+#[derive(Default)]
+struct Records(Vec<(u8, u32, usize)>);
+
+impl FieldStats for Records {
+    fn record(&mut self, depth: u8, field_num: u32, bytes: usize) {
+        self.0.push((depth, field_num, bytes));
+    }
+}
+
+#[test]
+fn nested_stats() {
+    let data = [
+        0x0A, 4, // basic
+        0x08, 0x05, // basic.int32_num
+        0x58, 0x01, // basic.boolean
+        0x1A, 2, // inner_msg
+        0x08, 0x02, // inner_msg.val
+        0x30, 0x01, // field 6, unknown
+    ];
+    let mut records = Records::default();
+    let mut decoder = PbDecoder::new(FieldStatsReader::new(data.as_slice(), &mut records));
+    let mut nested = Nested::default();
+    nested.decode(&mut decoder, data.len()).unwrap();
+
+    assert_eq!(
+        records.0,
+        [
+            (1, 1, 2),
+            (1, 11, 2),
+            // Total size of the nested message, including tag and length
+            (0, 1, 6),
+            (1, 1, 2),
+            (0, 3, 4),
+            (0, 6, 2),
+        ]
+    );
+    // Top-level fields add up to the whole message
+    let total: usize = records.0.iter().filter(|r| r.0 == 0).map(|r| r.2).sum();
+    assert_eq!(total, data.len());
+}
+
+#[test]
+fn no_stats_sink() {
+    // Decoding without a stats sink works as usual
+    let mut nested = Nested::default();
+    nested.merge_from_bytes(&[0x0A, 2, 0x08, 0x05]).unwrap();
+    assert_eq!(nested.basic().unwrap().int32_num(), Some(&5));
+}
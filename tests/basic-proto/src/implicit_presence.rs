@@ -45,13 +45,12 @@ fn implicit_presence() {
         st: String::from("stuff"),
         bt: vec![0x01, 0x02],
     };
-    let orig = non_opt.clone();
 
-    // Decoding 0s shouldn't overwrite any field
+    // Zeroes on the wire are the last value of each field, so they overwrite the existing values
     let mut decoder = PbDecoder::new(ZEROED_REPR);
     let len = decoder.as_reader().len();
     non_opt.decode(&mut decoder, len).unwrap();
-    assert_eq!(non_opt, orig);
+    assert_eq!(non_opt, proto::NonOptional::default());
 }
 
 #[test]
@@ -86,6 +85,89 @@ fn encode_implicit_presence() {
     assert_eq!(encoder.into_writer(), &[]);
 }
 
+/// Encode `msg` forwards and in reverse, checking that both match `expected`
+fn assert_encoded<M: MessageEncode>(msg: &M, expected: &[u8]) {
+    assert_eq!(msg.compute_size(), expected.len());
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    assert_eq!(encoder.into_writer(), expected);
+    let mut buf = [0; 64];
+    assert_eq!(msg.encode_to_slice_reverse(&mut buf).unwrap(), expected);
+}
+
+#[test]
+fn encode_all_defaults() {
+    assert_encoded(&proto::NonOptional::default(), &[]);
+    assert_encoded(&proto::Collections::default(), &[]);
+
+    // Fields with custom int sizes are skipped the same way
+    let mut non_opt = proto::NonOptional {
+        int64_num: 1,
+        fixed64_num: 1,
+        enumeration: proto::Enum::One,
+        ..Default::default()
+    };
+    assert_encoded(
+        &non_opt,
+        &[
+            0x10, 0x01, // field 2
+            0x41, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // field 8
+            0x70, 0x01, // field 14
+        ],
+    );
+    non_opt.int64_num = 0;
+    non_opt.fixed64_num = 0;
+    non_opt.enumeration = proto::Enum::Zero;
+    assert_encoded(&non_opt, &[]);
+}
+
+#[test]
+fn encode_negative_zero() {
+    // Negative zero isn't the default value, so it's encoded
+    let non_opt = proto::NonOptional {
+        flt: -0.0,
+        dbl: -0.0,
+        ..Default::default()
+    };
+    assert_encoded(
+        &non_opt,
+        &[
+            0x65, 0x00, 0x00, 0x00, 0x80, // field 12
+            0x69, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, // field 13
+        ],
+    );
+
+    let mut decoded = proto::NonOptional::default();
+    decoded
+        .merge_from_bytes(&[0x65, 0x00, 0x00, 0x00, 0x80])
+        .unwrap();
+    assert!(decoded.flt.is_sign_negative());
+}
+
+#[test]
+fn encode_default_elements() {
+    // Elements of repeated and map fields are always encoded, even if they're default values
+    let mut coll = proto::Collections {
+        packed: vec![0],
+        unpacked: vec![String::new()],
+        ..Default::default()
+    };
+    coll.mapping.insert(String::new(), 0);
+    assert_encoded(
+        &coll,
+        &[
+            0x0A, 0x01, 0x00, // field 1
+            0x12, 0x00, // field 2
+            0x1A, 0x04, 0x0A, 0x00, 0x10, 0x00, // field 3
+        ],
+    );
+
+    coll.packed.clear();
+    coll.unpacked.clear();
+    coll.mapping.clear();
+    assert_encoded(&coll, &[]);
+}
+
 #[test]
 fn decode_explicit_presence() {
     let mut opt = proto::Optional {
@@ -117,13 +199,13 @@ fn decode_explicit_presence() {
             0x20, 0x00, // field 4
             0x28, 0x00, // field 5
             0x30, 0x00, // field 6
-            0x38, 0x00, 0x00, 0x00, 0x00, // field 7
-            0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // field 8
-            0x48, 0x00, 0x00, 0x00, 0x00, // field 9
-            0x50, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // field 10
+            0x3D, 0x00, 0x00, 0x00, 0x00, // field 7
+            0x41, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // field 8
+            0x4D, 0x00, 0x00, 0x00, 0x00, // field 9
+            0x51, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // field 10
             0x58, 0x00, // field 11
-            0x60, 0x00, 0x00, 0x00, 0x00, // field 12
-            0x68, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // field 13
+            0x65, 0x00, 0x00, 0x00, 0x00, // field 12
+            0x69, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // field 13
             0x70, 0x00, // field 14
             0x7A, 0x00, // field 15
             0x82, 0x01, 0x00, // field 16
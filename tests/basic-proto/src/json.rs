@@ -0,0 +1,272 @@
+use micropb::json::{JsonErrorKind, JsonParser, MessageFromJson, MessageToJson};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/json.rs"));
+}
+
+mod proto_fixed {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/json_fixed.rs"));
+}
+
+use proto::json_::{Inner, Json, Json_, Status};
+
+fn to_json(msg: &impl MessageToJson) -> String {
+    let mut out = String::new();
+    msg.write_json(&mut out).unwrap();
+    out
+}
+
+#[test]
+fn default_omitted() {
+    assert_eq!(to_json(&Json::default()), "{}");
+    // Explicit presence fields are written even if they hold the default value
+    let mut msg = Json::default();
+    msg.set_opt_num(0);
+    msg.set_inner(Inner::default());
+    assert_eq!(to_json(&msg), r#"{"optNum":0,"inner":{}}"#);
+}
+
+#[test]
+fn scalars() {
+    let msg = Json {
+        small_num: -3,
+        big_num: -1 << 40,
+        unsigned_big: u64::MAX,
+        flt: 1.5,
+        dbl: f64::NEG_INFINITY,
+        flag: true,
+        text: "a\"b\n".to_owned(),
+        data: b"hi!?".to_vec(),
+        custom: 7,
+        ..Default::default()
+    };
+    assert_eq!(
+        to_json(&msg),
+        r#"{"smallNum":-3,"bigNum":"-1099511627776","unsignedBig":"18446744073709551615","flt":1.5,"dbl":"-Infinity","flag":true,"text":"a\"b\n","data":"aGkhPw==","renamedField":7}"#
+    );
+
+    // Negative zero isn't the default value
+    let msg = Json {
+        flt: f32::NAN,
+        dbl: -0.0,
+        ..Default::default()
+    };
+    assert_eq!(to_json(&msg), r#"{"flt":"NaN","dbl":-0}"#);
+}
+
+#[test]
+fn enums() {
+    let msg = Json {
+        status: Status::AliasOk,
+        ..Default::default()
+    };
+    // Aliases are written with the first name of the value
+    assert_eq!(to_json(&msg), r#"{"status":"STATUS_OK"}"#);
+    let msg = Json {
+        status: Status(9),
+        ..Default::default()
+    };
+    assert_eq!(to_json(&msg), r#"{"status":9}"#);
+}
+
+#[test]
+fn collections() {
+    let mut msg = Json {
+        list: vec![1, -2],
+        inners: vec![Inner { val: 1 }, Inner::default()],
+        ..Default::default()
+    };
+    msg.by_id.insert(-4, "four".to_owned());
+    msg.by_name.insert("x".to_owned(), Inner { val: 2 });
+    assert_eq!(
+        to_json(&msg),
+        r#"{"list":["1","-2"],"inners":[{"val":1},{}],"byId":{"-4":"four"},"byName":{"x":{"val":2}}}"#
+    );
+}
+
+#[test]
+fn oneof() {
+    let msg = Json {
+        choice: Some(Json_::Choice::ChoiceText(String::new())),
+        ..Default::default()
+    };
+    // Set oneof variants are always written
+    assert_eq!(to_json(&msg), r#"{"choiceText":""}"#);
+    let msg = Json {
+        choice: Some(Json_::Choice::ChoiceInner(Inner { val: 3 })),
+        ..Default::default()
+    };
+    assert_eq!(to_json(&msg), r#"{"choiceInner":{"val":3}}"#);
+}
+
+fn round_trip(msg: &Json) {
+    let json = to_json(msg);
+    assert_eq!(&Json::from_json(&json).unwrap(), msg, "{json}");
+}
+
+#[test]
+fn parse_round_trip() {
+    round_trip(&Json::default());
+    let mut msg = Json {
+        small_num: -3,
+        big_num: i64::MIN,
+        unsigned_big: u64::MAX,
+        flt: f32::INFINITY,
+        dbl: -0.25,
+        flag: true,
+        text: "a\"b\n\u{1}é".to_owned(),
+        data: vec![0, 0xFB, 0xFF, 7],
+        status: Status::Ok,
+        list: vec![1, -2],
+        inners: vec![Inner { val: 1 }, Inner::default()],
+        custom: 7,
+        choice: Some(Json_::Choice::ChoiceInner(Inner { val: 3 })),
+        ..Default::default()
+    };
+    msg.set_opt_num(0);
+    msg.set_inner(Inner { val: -1 });
+    msg.by_id.insert(-4, "four".to_owned());
+    msg.by_name.insert("x".to_owned(), Inner { val: 2 });
+    round_trip(&msg);
+
+    msg.status = Status(9);
+    msg.choice = Some(Json_::Choice::ChoiceText("c".to_owned()));
+    round_trip(&msg);
+}
+
+#[test]
+fn parse_mapping() {
+    // Both original and JSON names are accepted as keys
+    let msg = Json::from_json(
+        r#"{
+            "small_num": "5", "bigNum": 12, "unsigned_big": "1e3", "flt": "NaN", "dbl": "2.5",
+            "status": 1, "data": "-_8", "opt_num": null, "custom": 1, "by_id": {"7": "seven"},
+            "choice_text": "t", "inners": [{"val": 1}, {}]
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(msg.small_num, 5);
+    assert_eq!(msg.big_num, 12);
+    assert_eq!(msg.unsigned_big, 1000);
+    assert!(msg.flt.is_nan());
+    assert_eq!(msg.dbl, 2.5);
+    assert_eq!(msg.status, Status::Ok);
+    assert_eq!(msg.data, [0xFB, 0xFF]);
+    assert_eq!(msg.opt_num(), None);
+    assert_eq!(msg.custom, 1);
+    assert_eq!(msg.by_id[&7], "seven");
+    assert_eq!(msg.choice, Some(Json_::Choice::ChoiceText("t".to_owned())));
+    assert_eq!(msg.inners, [Inner { val: 1 }, Inner::default()]);
+
+    // Aliases are accepted, and null resets fields to their default values
+    let mut msg = Json::default();
+    msg.set_opt_num(3);
+    msg.flag = true;
+    msg.list = vec![1];
+    let mut parser = JsonParser::new(
+        r#"{"status": "STATUS_ALIAS_OK", "optNum": null, "flag": null, "list": null}"#,
+    );
+    msg.merge_json(&mut parser).unwrap();
+    parser.finish().unwrap();
+    assert_eq!(msg.status, Status::Ok);
+    assert_eq!(msg.opt_num(), None);
+    assert!(!msg.flag);
+    assert!(msg.list.is_empty());
+}
+
+#[test]
+fn parse_unknown_fields() {
+    let input = r#"{"smallNum": 1, "extra": {"nested": [1, "x"]}, "flag": true}"#;
+    let err = Json::from_json(input).unwrap_err();
+    assert_eq!(err.kind, JsonErrorKind::UnknownField);
+    assert_eq!(err.key, Some("extra"));
+
+    let mut msg = Json::default();
+    let mut parser = JsonParser::new(input);
+    parser.ignore_unknown_fields = true;
+    msg.merge_json(&mut parser).unwrap();
+    assert_eq!(msg.small_num, 1);
+    assert!(msg.flag);
+}
+
+#[test]
+fn parse_malformed() {
+    for (input, kind, key) in [
+        (r#"{"smallNum": 1"#, JsonErrorKind::Syntax, None),
+        (r#"{"smallNum": 1} x"#, JsonErrorKind::Syntax, None),
+        (r#"[]"#, JsonErrorKind::WrongType, None),
+        (
+            r#"{"smallNum": "x"}"#,
+            JsonErrorKind::InvalidNumber,
+            Some("smallNum"),
+        ),
+        (
+            r#"{"smallNum": 3000000000}"#,
+            JsonErrorKind::InvalidNumber,
+            Some("smallNum"),
+        ),
+        (r#"{"flag": 1}"#, JsonErrorKind::WrongType, Some("flag")),
+        (r#"{"data": "a"}"#, JsonErrorKind::Base64, Some("data")),
+        (
+            r#"{"status": "NOPE"}"#,
+            JsonErrorKind::UnknownEnumValue,
+            Some("status"),
+        ),
+        (
+            r#"{"inner": {"val": true}}"#,
+            JsonErrorKind::WrongType,
+            Some("val"),
+        ),
+        (
+            r#"{"byId": {"x": "y"}}"#,
+            JsonErrorKind::InvalidNumber,
+            Some("x"),
+        ),
+    ] {
+        let err = Json::from_json(input).unwrap_err();
+        assert_eq!((err.kind, err.key), (kind, key), "{input}");
+    }
+}
+
+#[test]
+fn parse_capacity() {
+    use proto_fixed::json_::Json;
+
+    let msg = Json::from_json(r#"{"text": "abcd", "list": [1, 2], "byName": {"k": {}}}"#).unwrap();
+    assert_eq!(msg.text, "abcd");
+    assert_eq!(msg.list, [1, 2]);
+
+    for (input, key) in [
+        (r#"{"text": "abcde"}"#, "text"),
+        (r#"{"data": "AAAAAAA="}"#, "data"),
+        (r#"{"list": [1, 2, 3]}"#, "list"),
+        (r#"{"byName": {"a": {}, "b": {}, "c": {}}}"#, "c"),
+        (r#"{"byName": {"long key": {}}}"#, "long key"),
+    ] {
+        let err = Json::from_json(input).unwrap_err();
+        assert_eq!(err.kind, JsonErrorKind::Capacity, "{input}");
+        assert_eq!(err.key, Some(key), "{input}");
+    }
+}
+
+#[test]
+fn name_consts() {
+    use Json_::{_field_names, _json_names};
+
+    assert_eq!(_field_names::SMALL_NUM, "small_num");
+    assert_eq!(_json_names::SMALL_NUM, "smallNum");
+    assert_eq!(_field_names::CUSTOM, "custom");
+    assert_eq!(_json_names::CUSTOM, "renamedField");
+    // Oneof fields are included as well
+    assert_eq!(_json_names::CHOICE_INNER, "choiceInner");
+
+    let msg = Json {
+        custom: 2,
+        ..Default::default()
+    };
+    assert_eq!(to_json(&msg), format!(r#"{{"{}":2}}"#, _json_names::CUSTOM));
+}
@@ -0,0 +1,115 @@
+use micropb::{MessageDecode, MessageEncode, PbEncoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/lazy.rs"));
+}
+
+use proto::{Envelope, Payload};
+
+fn payload(id: u32, values: &[u32], name: &str) -> Payload {
+    Payload {
+        id,
+        values: values.to_vec(),
+        name: name.to_owned(),
+    }
+}
+
+#[test]
+fn lazy_types() {
+    let envelope = Envelope::default();
+    let _: &Vec<u8> = &envelope.payload;
+    let _: &Option<Box<Vec<u8>>> = &envelope.boxed;
+    let _: &Vec<Vec<u8>> = &envelope.history;
+}
+
+#[test]
+fn decode_on_demand() {
+    let payload_bytes = [0x08, 0x05, 0x12, 2, 0x01, 0x02, 0x1A, 2, b'a', b'b'];
+    let mut data = vec![0x08, 0x01, 0x12, payload_bytes.len() as u8];
+    data.extend_from_slice(&payload_bytes);
+
+    let mut envelope = Envelope::default();
+    envelope.merge_from_bytes(&data).unwrap();
+    assert_eq!(envelope.kind, 1);
+    // Only the raw bytes are stored
+    assert_eq!(envelope.payload().unwrap(), &payload_bytes);
+    assert_eq!(envelope.decode_payload(), Ok(payload(5, &[1, 2], "ab")));
+
+    // Absent lazy fields decode to the default message
+    assert!(envelope.boxed().is_none());
+    assert_eq!(envelope.decode_boxed(), Ok(Payload::default()));
+
+    // Invalid bytes only fail when decoded
+    envelope.set_payload(vec![0x08]);
+    assert_eq!(
+        envelope.decode_payload(),
+        Err(micropb::DecodeError::UnexpectedEof)
+    );
+}
+
+#[test]
+fn merge_concatenates() {
+    let data = [
+        0x12, 4, 0x08, 0x05, 0x10, 0x01, // first occurrence
+        0x12, 4, 0x08, 0x06, 0x10, 0x02, // second occurrence
+        0x1A, 2, 0x08, 0x07, // boxed
+        0x1A, 2, 0x10, 0x03, // boxed again
+    ];
+    let mut envelope = Envelope::default();
+    envelope.merge_from_bytes(&data).unwrap();
+    assert_eq!(
+        envelope.payload().unwrap(),
+        &[0x08, 0x05, 0x10, 0x01, 0x08, 0x06, 0x10, 0x02]
+    );
+    // Concatenated bytes decode to the merged message
+    assert_eq!(envelope.decode_payload(), Ok(payload(6, &[1, 2], "")));
+    assert_eq!(envelope.decode_boxed(), Ok(payload(7, &[3], "")));
+
+    // Merging into a cleared field discards the stale bytes
+    envelope.clear_payload();
+    envelope.merge_from_bytes(&[0x12, 2, 0x08, 0x09]).unwrap();
+    assert_eq!(envelope.payload().unwrap(), &[0x08, 0x09]);
+
+    // Repeated lazy messages are stored separately
+    let mut envelope = Envelope::default();
+    envelope
+        .merge_from_bytes(&[0x22, 2, 0x08, 0x01, 0x22, 0, 0x22, 2, 0x08, 0x02])
+        .unwrap();
+    assert_eq!(
+        envelope.history,
+        [vec![0x08, 0x01], vec![], vec![0x08, 0x02]]
+    );
+}
+
+#[test]
+fn set_and_encode() {
+    let mut envelope = Envelope::default();
+    envelope
+        .set_payload_msg(&payload(5, &[1, 2], "ab"))
+        .unwrap();
+    envelope.set_boxed_msg(&Payload::default()).unwrap();
+    assert_eq!(
+        envelope.payload().unwrap(),
+        &[0x08, 0x05, 0x12, 2, 0x01, 0x02, 0x1A, 2, b'a', b'b']
+    );
+    assert_eq!(envelope.boxed().unwrap(), &[]);
+
+    let mut encoder = PbEncoder::new(vec![]);
+    envelope.encode(&mut encoder).unwrap();
+    let data = encoder.into_writer();
+    assert_eq!(
+        data,
+        [0x12, 10, 0x08, 0x05, 0x12, 2, 0x01, 0x02, 0x1A, 2, b'a', b'b', 0x1A, 0]
+    );
+    assert_eq!(envelope.compute_size(), data.len());
+
+    // Stored bytes are written verbatim, even if they aren't a valid encoding
+    let mut envelope = Envelope::default();
+    envelope.set_payload(vec![0xFF]);
+    envelope.history.push(vec![0x08, 0x01]);
+    let mut encoder = PbEncoder::new(vec![]);
+    envelope.encode(&mut encoder).unwrap();
+    assert_eq!(encoder.into_writer(), [0x12, 1, 0xFF, 0x22, 2, 0x08, 0x01]);
+}
@@ -1,8 +1,16 @@
 extern crate alloc;
 
+#[cfg(test)]
+mod any;
+#[cfg(test)]
+mod arbitrary_input;
+#[cfg(test)]
+mod backpatch;
 #[cfg(test)]
 mod boxed_and_option;
 #[cfg(test)]
+mod bytes_range;
+#[cfg(test)]
 mod conflicting_names;
 #[cfg(test)]
 mod container_alloc;
@@ -11,24 +19,94 @@ mod container_arrayvec;
 #[cfg(test)]
 mod container_heapless;
 #[cfg(test)]
+mod container_template;
+#[cfg(test)]
 mod custom_field;
 #[cfg(test)]
 mod default_str_escape;
 #[cfg(test)]
+mod defmt;
+#[cfg(test)]
+mod domain;
+#[cfg(test)]
+mod dyn_encode;
+#[cfg(test)]
+mod dyn_message;
+#[cfg(test)]
+mod dynamic;
+#[cfg(test)]
+mod emit_defaults;
+#[cfg(test)]
+mod empty;
+#[cfg(test)]
 mod extension;
 #[cfg(test)]
 mod extern_import;
 #[cfg(test)]
+mod field_mask;
+#[cfg(test)]
+mod field_order;
+#[cfg(test)]
+mod field_stats;
+#[cfg(test)]
 mod implicit_presence;
 #[cfg(test)]
 mod int_type;
 #[cfg(test)]
+mod json;
+#[cfg(test)]
 mod keyword_fields;
 #[cfg(test)]
+mod lazy;
+#[cfg(test)]
 mod lifetime_fields;
 #[cfg(test)]
+mod merge;
+#[cfg(test)]
+mod migration;
+#[cfg(test)]
 mod no_config;
 #[cfg(test)]
+mod optimize;
+#[cfg(test)]
+mod overflow_policy;
+#[cfg(test)]
+mod packed;
+#[cfg(test)]
 mod recursive;
 #[cfg(test)]
+mod reference;
+#[cfg(test)]
+mod reflection;
+#[cfg(test)]
+mod reverse_encode;
+#[cfg(test)]
+mod roundtrip;
+#[cfg(test)]
+mod service;
+#[cfg(test)]
+mod size_int;
+#[cfg(test)]
 mod skip;
+#[cfg(test)]
+mod sorted_map;
+#[cfg(test)]
+mod string_as_bytes;
+#[cfg(test)]
+mod structs;
+#[cfg(test)]
+mod text;
+#[cfg(test)]
+mod too_large;
+#[cfg(test)]
+mod unknown_enum;
+#[cfg(test)]
+mod unknown_fields;
+#[cfg(test)]
+mod unknown_hook;
+#[cfg(test)]
+mod visitor;
+#[cfg(test)]
+mod well_known;
+#[cfg(test)]
+mod wrappers;
@@ -13,7 +13,7 @@ impl<'a> FieldEncode for RefField<'a> {
     fn encode_fields<W: micropb::PbWrite>(
         &self,
         _encoder: &mut micropb::PbEncoder<W>,
-    ) -> Result<(), W::Error> {
+    ) -> Result<(), micropb::EncodeError<W::Error>> {
         unimplemented!()
     }
 
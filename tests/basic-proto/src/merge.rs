@@ -0,0 +1,251 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+use micropb::MessageDecode;
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/merge.rs"));
+}
+
+mod proto3 {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/implicit_presence.rs"));
+}
+
+/// Allocator that counts the allocations made by the current thread, so that tests running in
+/// parallel don't interfere with each other
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCS.with(|a| a.set(a.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCS.with(|a| a.set(a.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn count_allocs(f: impl FnOnce()) -> usize {
+    let before = ALLOCS.with(|a| a.get());
+    f();
+    ALLOCS.with(|a| a.get()) - before
+}
+
+use proto::nested_::Nested_::{Inner, InnerMsg};
+
+#[test]
+fn merge_scalars() {
+    let mut basic = proto::basic_::BasicTypes::default();
+    basic.merge_from_bytes(&[0x08, 0x01]).unwrap();
+    basic.merge_from_bytes(&[0x08, 0x02, 0x18, 0x03]).unwrap();
+    // Later values overwrite earlier ones, and untouched fields stay set
+    assert_eq!(basic.int32_num(), Some(&2));
+    assert_eq!(basic.uint32_num(), Some(&3));
+
+    basic.merge_from_bytes(&[]).unwrap();
+    assert_eq!(basic.int32_num(), Some(&2));
+    assert_eq!(basic.uint32_num(), Some(&3));
+}
+
+#[test]
+fn merge_nested_message() {
+    let mut nested = proto::nested_::Nested::default();
+    nested.merge_from_bytes(&[0x0A, 2, 0x08, 0x01]).unwrap();
+    nested.merge_from_bytes(&[0x0A, 2, 0x18, 0x03]).unwrap();
+    // Singular message fields are merged recursively
+    let basic = nested.basic().unwrap();
+    assert_eq!(basic.int32_num(), Some(&1));
+    assert_eq!(basic.uint32_num(), Some(&3));
+}
+
+#[test]
+fn merge_oneof() {
+    let mut nested = proto::nested_::Nested::default();
+    nested.merge_from_bytes(&[0x1A, 2, 0x08, 0x02]).unwrap();
+    nested.merge_from_bytes(&[0x1A, 2, 0x10, 0x04]).unwrap();
+    // Same message variant is merged
+    let mut expected = InnerMsg::default();
+    expected.set_val(1);
+    expected.set_val2(2);
+    assert_eq!(nested.inner, Some(Inner::InnerMsg(expected)));
+
+    // Different variant overwrites the oneof
+    nested.merge_from_bytes(&[0x28, 0x01]).unwrap();
+    assert_eq!(nested.inner, Some(Inner::Scalar(true)));
+
+    // Message variant replacing another variant starts from default
+    nested.merge_from_bytes(&[0x1A, 2, 0x10, 0x04]).unwrap();
+    let mut expected = InnerMsg::default();
+    expected.set_val2(2);
+    assert_eq!(nested.inner, Some(Inner::InnerMsg(expected)));
+}
+
+#[test]
+fn merge_repeated() {
+    let mut list = proto::NumList::default();
+    list.merge_from_bytes(&[0x08, 0x01, 0x08, 0x02]).unwrap();
+    list.merge_from_bytes(&[0x0A, 2, 0x03, 0x04]).unwrap();
+    // Repeated fields are appended to
+    assert_eq!(list.list, &[1, 2, 3, 4]);
+}
+
+#[test]
+fn merge_map() {
+    let mut map = proto::Map::default();
+    map.merge_from_bytes(&[0x0A, 6, 0x0A, 1, b'a', 0x12, 1, 0x01])
+        .unwrap();
+    map.merge_from_bytes(&[
+        0x0A, 6, 0x0A, 1, b'b', 0x12, 1, 0x02, // new key
+        0x0A, 6, 0x0A, 1, b'a', 0x12, 1, 0x03, // existing key
+    ])
+    .unwrap();
+    assert_eq!(map.mapping.len(), 2);
+    assert_eq!(map.mapping["a"], &[0x03]);
+    assert_eq!(map.mapping["b"], &[0x02]);
+}
+
+#[test]
+fn clear_reuses_allocations() {
+    let mut list = proto::NumList::default();
+    list.merge_from_bytes(&[0x0A, 4, 0x01, 0x02, 0x03, 0x04])
+        .unwrap();
+    let cap = list.list.capacity();
+    list.clear();
+    assert!(list.list.is_empty());
+    assert_eq!(list.list.capacity(), cap);
+    list.merge_from_bytes(&[0x0A, 2, 0x05, 0x06]).unwrap();
+    assert_eq!(list.list, &[5, 6]);
+    assert_eq!(list.list.capacity(), cap);
+
+    let mut data = proto::Data::default();
+    data.merge_from_bytes(&[0x0A, 3, b'a', b'b', b'c']).unwrap();
+    assert_eq!(data.s(), Some(&"abc".to_owned()));
+    data.clear();
+    // Strings with default values are reset to the default
+    assert_eq!(data, proto::Data::default());
+
+    let mut map = proto::Map::default();
+    map.merge_from_bytes(&[0x0A, 6, 0x0A, 1, b'a', 0x12, 1, 0x01])
+        .unwrap();
+    map.clear();
+    assert!(map.mapping.is_empty());
+}
+
+#[test]
+fn clear_nested() {
+    let mut nested = proto::nested_::Nested::default();
+    nested
+        .merge_from_bytes(&[0x0A, 2, 0x08, 0x01, 0x1A, 2, 0x08, 0x02])
+        .unwrap();
+    nested.clear();
+    assert_eq!(nested, proto::nested_::Nested::default());
+
+    // Oneofs are reset, even if they hold a variant with a default value
+    nested.inner = Some(proto::nested_::Nested_::Inner::Enumeration(
+        proto::basic_::Enum(0),
+    ));
+    nested.clear();
+    assert_eq!(nested.inner, None);
+}
+
+#[test]
+fn clear_and_decode_without_alloc() {
+    let list_data = [0x0A, 4, 0x01, 0x02, 0x03, 0x04];
+    let mut list = proto::NumList::default();
+    list.merge_from_bytes(&list_data).unwrap();
+    let allocs = count_allocs(|| {
+        list.clear();
+        list.merge_from_bytes(&list_data).unwrap();
+    });
+    assert_eq!(allocs, 0);
+    assert_eq!(list.list, &[1, 2, 3, 4]);
+
+    let msg_data = [
+        0x08, 0x01, // field 1
+        0x7A, 3, b'a', b'b', b'c', // field 15
+        0x82, 0x01, 2, 0x01, 0x02, // field 16
+    ];
+    let mut msg = proto3::NonOptional::default();
+    msg.merge_from_bytes(&msg_data).unwrap();
+    let allocs = count_allocs(|| {
+        msg.clear();
+        msg.merge_from_bytes(&msg_data).unwrap();
+    });
+    assert_eq!(allocs, 0);
+    assert_eq!(msg.int32_num, 1);
+    assert_eq!(msg.st, "abc");
+    assert_eq!(msg.bt, &[0x01, 0x02]);
+}
+
+#[test]
+fn duplicate_scalar_last_wins() {
+    let mut basic = proto::basic_::BasicTypes::default();
+    basic
+        .merge_from_bytes(&[0x08, 0x01, 0x08, 0x02, 0x08, 0x03])
+        .unwrap();
+    assert_eq!(basic.int32_num(), Some(&3));
+
+    // Zero on the wire still overwrites an implicit-presence field
+    let mut msg = proto3::NonOptional::default();
+    msg.merge_from_bytes(&[0x08, 0x05, 0x08, 0x00]).unwrap();
+    assert_eq!(msg.int32_num, 0);
+}
+
+#[test]
+fn duplicate_string_last_wins() {
+    let mut data = proto::Data::default();
+    data.merge_from_bytes(&[0x0A, 3, b'a', b'b', b'c', 0x0A, 1, b'x'])
+        .unwrap();
+    assert_eq!(data.s(), Some(&"x".to_owned()));
+
+    // Empty string on the wire overwrites an implicit-presence field
+    let mut msg = proto3::NonOptional::default();
+    msg.merge_from_bytes(&[0x7A, 3, b'a', b'b', b'c', 0x7A, 0])
+        .unwrap();
+    assert_eq!(msg.st, "");
+}
+
+#[test]
+fn duplicate_message_merges() {
+    let mut nested = proto::nested_::Nested::default();
+    nested
+        .merge_from_bytes(&[
+            0x0A, 4, 0x08, 0x01, 0x18, 0x05, // first occurrence
+            0x0A, 2, 0x08, 0x02, // second occurrence
+        ])
+        .unwrap();
+    let basic = nested.basic().unwrap();
+    assert_eq!(basic.int32_num(), Some(&2));
+    assert_eq!(basic.uint32_num(), Some(&5));
+}
+
+#[test]
+fn duplicate_map_key_last_wins() {
+    let mut map = proto::Map::default();
+    map.merge_from_bytes(&[
+        0x0A, 6, 0x0A, 1, b'a', 0x12, 1, 0x01, // first occurrence
+        0x0A, 6, 0x0A, 1, b'a', 0x12, 1, 0x02, // second occurrence
+    ])
+    .unwrap();
+    assert_eq!(map.mapping.len(), 1);
+    assert_eq!(map.mapping["a"], &[0x02]);
+}
@@ -0,0 +1,222 @@
+use micropb::{ConversionError, MessageDecode, MessageEncode};
+use prost::Message;
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/migration.rs"));
+}
+
+/// Definitions of `migration.proto` and `migration2.proto` in the layout of `prost-build`'s output
+pub mod pb {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Reading {
+        #[prost(sint32, tag = "1")]
+        pub value: i32,
+        #[prost(string, optional, tag = "2")]
+        pub note: Option<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Device {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(enumeration = "Status", tag = "2")]
+        pub status: i32,
+        #[prost(uint64, optional, tag = "3")]
+        pub uptime: Option<u64>,
+        #[prost(message, optional, tag = "4")]
+        pub last: Option<Reading>,
+        #[prost(message, repeated, tag = "5")]
+        pub history: Vec<Reading>,
+        #[prost(map = "string, int32", tag = "6")]
+        pub counters: std::collections::HashMap<String, i32>,
+        #[prost(message, optional, tag = "7")]
+        pub settings: Option<device::Settings>,
+        #[prost(oneof = "device::Link", tags = "8, 9, 10")]
+        pub link: Option<device::Link>,
+        #[prost(enumeration = "Status", repeated, tag = "11")]
+        pub statuses: Vec<i32>,
+    }
+
+    pub mod device {
+        #[derive(Clone, PartialEq, prost::Message)]
+        pub struct Settings {
+            #[prost(uint32, tag = "1")]
+            pub interval: u32,
+            #[prost(bytes = "vec", tag = "2")]
+            pub key: Vec<u8>,
+        }
+
+        #[derive(Clone, PartialEq, prost::Oneof)]
+        pub enum Link {
+            #[prost(uint32, tag = "8")]
+            Port(u32),
+            #[prost(string, tag = "9")]
+            Host(String),
+            #[prost(message, tag = "10")]
+            Relay(super::Reading),
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Status {
+        Unknown = 0,
+        Ok = 1,
+        Failed = 2,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Legacy {
+        #[prost(fixed32, required, tag = "1")]
+        pub id: u32,
+        #[prost(message, required, tag = "2")]
+        pub reading: Reading,
+        #[prost(string, optional, tag = "3")]
+        pub label: Option<String>,
+    }
+}
+
+use proto::migration_::{Device, Device_, Legacy, Reading, Status};
+
+fn reading(value: i16, note: Option<&str>) -> Reading {
+    let mut reading = Reading {
+        value,
+        ..Default::default()
+    };
+    if let Some(note) = note {
+        reading.set_note(note.try_into().unwrap());
+    }
+    reading
+}
+
+fn device() -> Device {
+    let mut device = Device {
+        name: "sensor".try_into().unwrap(),
+        status: Status::Failed,
+        link: Some(Device_::Link::Relay(reading(-4, Some("relay")))),
+        ..Default::default()
+    };
+    device.set_uptime(1 << 40);
+    device.set_last(reading(7, None));
+    device.history.push(reading(1, Some("a"))).unwrap();
+    device.history.push(reading(-300, None)).unwrap();
+    device.counters.insert("x".try_into().unwrap(), -1).unwrap();
+    device.set_settings(Device_::Settings {
+        interval: 60,
+        key: [0xAB, 0xCD].as_slice().try_into().unwrap(),
+    });
+    device.statuses.push(Status::Ok).unwrap();
+    device.statuses.push(Status(9)).unwrap();
+    device
+}
+
+/// Converting to the `prost` type gives the same result as encoding and decoding with `prost`
+fn check_parity<M: MessageEncode, P: Message + Default + PartialEq + std::fmt::Debug>(
+    msg: &M,
+    converted: &P,
+) {
+    let mut encoded = Vec::new();
+    msg.encode(&mut micropb::PbEncoder::new(&mut encoded))
+        .unwrap();
+    assert_eq!(&P::decode(encoded.as_slice()).unwrap(), converted);
+}
+
+#[test]
+fn to_prost() {
+    let device = device();
+    let converted = pb::Device::from(device.clone());
+    assert_eq!(converted.name, "sensor");
+    assert_eq!(converted.status, pb::Status::Failed as i32);
+    assert_eq!(converted.uptime, Some(1 << 40));
+    assert_eq!(converted.last.as_ref().unwrap().value, 7);
+    assert_eq!(converted.last.as_ref().unwrap().note, None);
+    assert_eq!(converted.history[1].value, -300);
+    assert_eq!(converted.counters["x"], -1);
+    assert_eq!(converted.settings.as_ref().unwrap().key, [0xAB, 0xCD]);
+    assert_eq!(
+        converted.link,
+        Some(pb::device::Link::Relay(pb::Reading {
+            value: -4,
+            note: Some("relay".to_owned())
+        }))
+    );
+    // Unknown enum values are kept
+    assert_eq!(converted.statuses, [1, 9]);
+    check_parity(&device, &converted);
+
+    // Unset fields are left unset
+    let converted = pb::Device::from(Device::default());
+    assert_eq!(converted, pb::Device::default());
+    check_parity(&Device::default(), &converted);
+}
+
+#[test]
+fn from_prost() {
+    let device = device();
+    let converted = Device::try_from(pb::Device::from(device.clone())).unwrap();
+    assert_eq!(converted, device);
+
+    let mut encoded = Vec::new();
+    let prost = pb::Device {
+        link: Some(pb::device::Link::Host("host".to_owned())),
+        ..Default::default()
+    };
+    prost.encode(&mut encoded).unwrap();
+    let mut decoded = Device::default();
+    decoded.merge_from_bytes(&encoded).unwrap();
+    assert_eq!(Device::try_from(prost).unwrap(), decoded);
+}
+
+#[test]
+fn required() {
+    let mut legacy = Legacy::default();
+    legacy.set_id(5);
+    legacy.set_reading(reading(2, None));
+    let converted = pb::Legacy::from(legacy.clone());
+    assert_eq!(converted.id, 5);
+    assert_eq!(converted.reading.value, 2);
+    assert_eq!(converted.label, None);
+    check_parity(&legacy, &converted);
+
+    // Required fields are always set when converting from prost
+    let converted = Legacy::try_from(pb::Legacy::default()).unwrap();
+    assert_eq!(converted.id(), Some(&0));
+    assert_eq!(converted.reading(), Some(&Reading::default()));
+    assert_eq!(converted.label(), None);
+}
+
+#[test]
+fn capacity_overflow() {
+    let prost = pb::Device {
+        statuses: vec![0; 5],
+        ..Default::default()
+    };
+    assert_eq!(Device::try_from(prost), Err(ConversionError::Capacity));
+
+    let prost = pb::Device {
+        name: "x".repeat(100),
+        ..Default::default()
+    };
+    assert_eq!(Device::try_from(prost), Err(ConversionError::Capacity));
+}
+
+#[test]
+fn int_overflow() {
+    // `value` is narrowed to 16 bits by `int_size`
+    let prost = pb::Reading {
+        value: 70000,
+        note: None,
+    };
+    assert_eq!(Reading::try_from(prost), Err(ConversionError::IntOverflow));
+
+    let prost = pb::Device {
+        history: vec![pb::Reading {
+            value: i32::MIN,
+            note: None,
+        }],
+        ..Default::default()
+    };
+    assert_eq!(Device::try_from(prost), Err(ConversionError::IntOverflow));
+}
@@ -115,6 +115,28 @@ fn nested_msg() {
     }
 }
 
+#[test]
+fn missing_fields() {
+    let mut nested = proto::nested_::Nested::default();
+    // The oneof is reported using the lowest field number in the oneof
+    assert_eq!(nested.missing_fields().collect::<Vec<_>>(), [1, 2]);
+    nested.inner = Some(proto::nested_::Nested_::Inner::Scalar(true));
+    assert_eq!(nested.missing_fields().collect::<Vec<_>>(), [1]);
+    nested.set_basic(proto::basic_::BasicTypes::default());
+    assert_eq!(nested.missing_fields().count(), 0);
+
+    let mut basic = proto::basic_::BasicTypes::default();
+    assert_eq!(
+        basic.missing_fields().collect::<Vec<_>>(),
+        (1..=14).collect::<Vec<_>>()
+    );
+    basic.merge_from_bytes(&[0x08, 0x01, 0x58, 0x01]).unwrap();
+    assert_eq!(
+        basic.missing_fields().collect::<Vec<_>>(),
+        [2, 3, 4, 5, 6, 7, 8, 9, 10, 12, 13, 14]
+    );
+}
+
 #[test]
 fn proto3() {
     let non_opt = proto::basic3_::NonOptional::default();
@@ -216,7 +238,7 @@ fn decode_fixed() {
     let mut basic = proto::basic_::BasicTypes::default();
     let mut decoder = PbDecoder::new(
         [
-            0x39, 0x11, 0x00, 0x00, 0x12, // field 7
+            0x3D, 0x11, 0x00, 0x00, 0x12, // field 7
             0x41, 0x30, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // field 8
         ]
         .as_slice(),
@@ -228,7 +250,7 @@ fn decode_fixed() {
 
     let mut decoder = PbDecoder::new(
         [
-            0x49, 0x12, 0x32, 0x98, 0xF4, // field 9
+            0x4D, 0x12, 0x32, 0x98, 0xF4, // field 9
             0x51, 0x30, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // field 10
         ]
         .as_slice(),
@@ -241,7 +263,7 @@ fn decode_fixed() {
     let mut decoder = PbDecoder::new(
         [
             0x58, 0x01, // field 11
-            0x61, 0xC7, 0x46, 0xE8, 0xC1, // field 12
+            0x65, 0xC7, 0x46, 0xE8, 0xC1, // field 12
             0x69, 0x5E, 0x09, 0x52, 0x2B, 0x83, 0x07, 0x3A, 0x40, // field 13
         ]
         .as_slice(),
@@ -374,6 +396,35 @@ fn decode_nested() {
     );
 }
 
+#[test]
+fn decode_max_size() {
+    let data = [0x0A, 0x02, 0x08, 0x01, 0x28, 0x01];
+
+    let mut nested = proto::nested_::Nested::default();
+    let mut decoder = PbDecoder::new(data.as_slice());
+    decoder.max_size = data.len();
+    nested.decode(&mut decoder, data.len()).unwrap();
+    assert_eq!(nested.basic().unwrap().int32_num(), Some(&1));
+
+    // Top-level message is rejected before anything is decoded
+    let mut nested = proto::nested_::Nested::default();
+    let mut decoder = PbDecoder::new(data.as_slice());
+    decoder.max_size = data.len() - 1;
+    assert_eq!(
+        nested.decode(&mut decoder, data.len()),
+        Err(DecodeError::SizeLimit)
+    );
+    assert_eq!(decoder.bytes_read(), 0);
+
+    // Nested message that exceeds the remaining budget is rejected as soon as its length prefix
+    // is read, even though the top-level length is within the limit
+    let mut decoder = PbDecoder::new(data.as_slice());
+    decoder.max_size = 3;
+    assert_eq!(nested.decode(&mut decoder, 3), Err(DecodeError::SizeLimit));
+    assert_eq!(decoder.bytes_read(), 2);
+    assert_eq!(nested, Default::default());
+}
+
 #[test]
 fn encode_nested() {
     let mut nested = proto::nested_::Nested::default();
@@ -412,6 +463,142 @@ fn encode_nested() {
     assert_eq!(encoder.into_writer(), &[0x28, 0x00]);
 }
 
+#[test]
+fn decode_trailing_data() {
+    // Length-delimited message followed by a CRC
+    let data = [3, 0x08, 0x96, 0x01, 0xDE, 0xAD, 0xBE, 0xEF];
+    let mut basic = proto::basic_::BasicTypes::default();
+    assert_eq!(basic.merge_len_delimited_from_bytes(&data), Ok(4));
+    assert_eq!(basic.int32_num(), Some(&150));
+    assert_eq!(&data[4..], &[0xDE, 0xAD, 0xBE, 0xEF]);
+
+    // Trailing garbage that isn't valid Protobuf is left unread
+    let mut decoder = PbDecoder::new(data.as_slice());
+    let mut basic = proto::basic_::BasicTypes::default();
+    basic.decode_len_delimited(&mut decoder).unwrap();
+    assert_eq!(decoder.bytes_read(), 4);
+    assert_eq!(decoder.remaining(), &[0xDE, 0xAD, 0xBE, 0xEF]);
+
+    // Empty message
+    let mut basic = proto::basic_::BasicTypes::default();
+    assert_eq!(basic.merge_len_delimited_from_bytes(&[0, 0xFF]), Ok(1));
+    assert_eq!(basic, proto::basic_::BasicTypes::default());
+
+    // The prefix can't point past the end of the slice
+    let mut basic = proto::basic_::BasicTypes::default();
+    assert_eq!(
+        basic.merge_len_delimited_from_bytes(&[5, 0x08, 0x01]),
+        Err(DecodeError::UnexpectedEof)
+    );
+}
+
+#[test]
+fn write_delimited() {
+    let mut basic = proto::basic_::BasicTypes::default();
+    basic.set_int32_num(150);
+    basic.set_boolean(true);
+    let zst = proto::basic3_::ZST::default();
+    assert_eq!(zst.compute_size(), 0);
+
+    let mut encoder = PbEncoder::new(vec![]);
+    let first = basic.write_delimited(&mut encoder).unwrap();
+    assert_eq!(first, basic.compute_size() + 1);
+    // Zero-length message is just a 0 prefix
+    assert_eq!(zst.write_delimited(&mut encoder), Ok(1));
+    assert_eq!(basic.write_delimited(&mut encoder), Ok(first));
+    let data = encoder.into_writer();
+    assert_eq!(data.len(), first * 2 + 1);
+    assert_eq!(data[first], 0);
+
+    // Decoding returns the same lengths
+    let mut decoded = proto::basic_::BasicTypes::default();
+    assert_eq!(decoded.merge_len_delimited_from_bytes(&data), Ok(first));
+    assert_eq!(decoded, basic);
+    let mut decoded_zst = proto::basic3_::ZST::default();
+    assert_eq!(
+        decoded_zst.merge_len_delimited_from_bytes(&data[first..]),
+        Ok(1)
+    );
+    let mut decoded = proto::basic_::BasicTypes::default();
+    assert_eq!(
+        decoded.merge_len_delimited_from_bytes(&data[first + 1..]),
+        Ok(first)
+    );
+    assert_eq!(decoded, basic);
+
+    // Message doesn't fit into the writer
+    let mut encoder = PbEncoder::new(micropb::heapless::Vec::<u8, 4>::new());
+    assert_eq!(
+        basic.write_delimited(&mut encoder),
+        Err(micropb::EncodeError::Writer(()))
+    );
+}
+
+fn assert_size<M: MessageEncode>(msg: &M) {
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    assert_eq!(msg.compute_size(), encoder.into_writer().len());
+
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode_len_delimited(&mut encoder).unwrap();
+    assert_eq!(
+        msg.compute_size_len_delimited(),
+        encoder.into_writer().len()
+    );
+}
+
+#[test]
+fn compute_size() {
+    let mut basic = proto::basic_::BasicTypes::default();
+    assert_size(&basic);
+    basic.set_int32_num(-1);
+    basic.set_uint64_num(u64::MAX);
+    basic.set_sint32_num(-150);
+    basic.set_fixed64_num(7);
+    basic.set_dbl(1.5);
+    assert_size(&basic);
+
+    assert_size(&proto::basic3_::ZST::default());
+    assert_size(&proto::basic3_::NonOptional { non_opt: 300 });
+
+    let mut nested = proto::nested_::Nested::default();
+    assert_size(&nested);
+    nested.set_basic(basic);
+    assert_size(&nested);
+    nested.inner = Some(proto::nested_::Nested_::Inner::Scalar(true));
+    assert_size(&nested);
+}
+
+#[test]
+fn decode_len_delimited_max() {
+    let data = [3, 0x08, 0x96, 0x01, 0xDE, 0xAD];
+    let mut basic = proto::basic_::BasicTypes::default();
+    let mut decoder = PbDecoder::new(data.as_slice());
+    basic.decode_len_delimited_max(&mut decoder, 3).unwrap();
+    assert_eq!(basic.int32_num(), Some(&150));
+    assert_eq!(decoder.remaining(), &[0xDE, 0xAD]);
+
+    let mut decoder = PbDecoder::new(data.as_slice());
+    assert_eq!(
+        basic.decode_len_delimited_max(&mut decoder, 2),
+        Err(DecodeError::SizeLimit)
+    );
+
+    // Last field of the message runs past the length prefix
+    let mut decoder = PbDecoder::new([2, 0x08, 0x96, 0x01].as_slice());
+    assert_eq!(
+        basic.decode_len_delimited_max(&mut decoder, 16),
+        Err(DecodeError::WrongLen)
+    );
+
+    // Length prefix runs past the end of the input
+    let mut decoder = PbDecoder::new([4, 0x08, 0x96, 0x01].as_slice());
+    assert_eq!(
+        basic.decode_len_delimited_max(&mut decoder, 16),
+        Err(DecodeError::UnexpectedEof)
+    );
+}
+
 #[test]
 fn decode_non_optional() {
     let mut non_opt = proto::basic3_::NonOptional::default();
@@ -421,6 +608,32 @@ fn decode_non_optional() {
     assert_eq!(non_opt.non_opt, 150);
 }
 
+#[test]
+fn decode_unknown_group() {
+    let mut non_opt = proto::basic3_::NonOptional::default();
+    let mut decoder = PbDecoder::new(
+        [
+            0xA3, 0x01, // start group 20
+            0x08, 0x01, // field 1 inside the group, which shouldn't be decoded
+            0xAB, 0x01, 0xAC, 0x01, // nested group 21
+            0xA4, 0x01, // end group 20
+            0x08, 0x96, 0x01, // field 1
+        ]
+        .as_slice(),
+    );
+    let len = decoder.as_reader().len();
+    non_opt.decode(&mut decoder, len).unwrap();
+    assert_eq!(non_opt.non_opt, 150);
+
+    // Group is never closed
+    let mut decoder = PbDecoder::new([0xA3, 0x01, 0x08, 0x01].as_slice());
+    let len = decoder.as_reader().len();
+    assert_eq!(
+        non_opt.decode(&mut decoder, len),
+        Err(DecodeError::UnexpectedEof)
+    );
+}
+
 #[test]
 fn encode_non_optional() {
     let mut non_opt = proto::basic3_::NonOptional::default();
@@ -468,4 +681,19 @@ fn decode_errors() {
         Err(DecodeError::WrongLen)
     );
     assert_eq!(decoder.bytes_read(), 4);
+
+    let mut decoder = PbDecoder::new([0x1A, 0x01, 0x01].as_slice()); // field 3 as LEN
+    let len = decoder.as_reader().len();
+    assert_eq!(
+        basic.decode(&mut decoder, len),
+        Err(DecodeError::WrongWireType)
+    );
+
+    let mut nested = proto::nested_::Nested::default();
+    let mut decoder = PbDecoder::new([0x2A, 0x00].as_slice()); // oneof field 5 as LEN
+    let len = decoder.as_reader().len();
+    assert_eq!(
+        nested.decode(&mut decoder, len),
+        Err(DecodeError::WrongWireType)
+    );
 }
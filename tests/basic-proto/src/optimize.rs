@@ -0,0 +1,94 @@
+use micropb::{DecodeError, MessageDecode, MessageEncode, PbEncoder};
+
+mod size_proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/optimize_size.rs"));
+}
+
+mod speed_proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/optimize_speed.rs"));
+}
+
+mod balanced_proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/optimize_balanced.rs"));
+}
+
+fn encode<M: MessageEncode>(msg: &M) -> Vec<u8> {
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    encoder.into_writer()
+}
+
+/// Decode `data` into a message, then check that it's encoded back into the same bytes
+fn round_trip<M: MessageEncode + MessageDecode + Default>(data: &[u8]) -> M {
+    let mut msg = M::default();
+    msg.merge_from_bytes(data).unwrap();
+    assert_eq!(encode(&msg), data);
+    assert_eq!(msg.compute_size(), data.len());
+    let mut buf = [0; 128];
+    assert_eq!(msg.encode_to_slice_reverse(&mut buf).unwrap(), data);
+    msg
+}
+
+fn basic() -> balanced_proto::basic_::BasicTypes {
+    let mut msg = balanced_proto::basic_::BasicTypes::default();
+    msg.set_int32_num(-5);
+    msg.set_int64_num(-300);
+    msg.set_uint32_num(150);
+    msg.set_uint64_num(u64::MAX);
+    msg.set_sint32_num(-2);
+    msg.set_sint64_num(i64::MIN);
+    msg.set_fixed32_num(7);
+    msg.set_fixed64_num(8);
+    msg.set_sfixed32_num(-9);
+    msg.set_sfixed64_num(-10);
+    msg.set_boolean(true);
+    msg.set_flt(1.5);
+    msg.set_dbl(-2.5);
+    msg.set_enumeration(balanced_proto::basic_::Enum::Two);
+    msg
+}
+
+#[test]
+fn same_output() {
+    let msg = basic();
+    let data = encode(&msg);
+    let size_msg: size_proto::basic_::BasicTypes = round_trip(&data);
+    assert_eq!(size_msg.int64_num(), Some(&-300));
+    assert_eq!(size_msg.dbl(), Some(&-2.5));
+    let _: speed_proto::basic_::BasicTypes = round_trip(&data);
+
+    let mut nested = balanced_proto::nested_::Nested::default();
+    nested.set_basic(msg);
+    nested.inner = Some(balanced_proto::nested_::Nested_::Inner::Scalar(true));
+    let data = encode(&nested);
+    let _: size_proto::nested_::Nested = round_trip(&data);
+    let _: speed_proto::nested_::Nested = round_trip(&data);
+
+    let list = balanced_proto::NumList {
+        list: vec![1, 150, u32::MAX],
+    };
+    let data = encode(&list);
+    let _: size_proto::NumList = round_trip(&data);
+    let _: speed_proto::NumList = round_trip(&data);
+
+    let non_opt = balanced_proto::basic3_::NonOptional { non_opt: -1 };
+    let data = encode(&non_opt);
+    let _: size_proto::basic3_::NonOptional = round_trip(&data);
+    let _: speed_proto::basic3_::NonOptional = round_trip(&data);
+}
+
+#[test]
+fn wrong_wire_type() {
+    // Field 1 of `BasicTypes` is a varint, not a fixed32
+    let data = [0x0D, 0x01, 0x00, 0x00, 0x00];
+    let mut msg = size_proto::basic_::BasicTypes::default();
+    assert_eq!(msg.merge_from_bytes(&data), Err(DecodeError::WrongWireType));
+    let mut msg = balanced_proto::basic_::BasicTypes::default();
+    assert_eq!(msg.merge_from_bytes(&data), Err(DecodeError::WrongWireType));
+}
@@ -0,0 +1,177 @@
+use micropb::{DecodeError, MessageDecode, PbDecoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/overflow_policy.rs"));
+}
+
+/// Decode the message and return it along with the decoder's `truncated` flag
+fn decode(data: &[u8]) -> (proto::Overflow, bool) {
+    let mut msg = proto::Overflow::default();
+    let mut decoder = PbDecoder::new(data);
+    msg.decode(&mut decoder, data.len()).unwrap();
+    (msg, decoder.truncated)
+}
+
+#[test]
+fn drop_extra_unpacked() {
+    let (msg, truncated) = decode(&[
+        0x08, 0x01, 0x08, 0x02, 0x08, 0x03, // field 1 unpacked
+        0x30, 0x07, // field 6
+    ]);
+    assert_eq!(msg.nums, &[1, 2]);
+    assert_eq!(msg.after(), Some(&7));
+    assert!(!truncated);
+}
+
+#[test]
+fn drop_extra_packed() {
+    let (msg, truncated) = decode(&[
+        0x08, 0x01, // field 1 unpacked
+        0x0A, 3, 0x02, 0x96, 0x01, // field 1 packed
+        0x30, 0x07, // field 6
+    ]);
+    assert_eq!(msg.nums, &[1, 2]);
+    // Rest of the packed record is skipped, so the next field is parsed correctly
+    assert_eq!(msg.after(), Some(&7));
+    assert!(!truncated);
+}
+
+#[test]
+fn drop_extra_messages_and_strings() {
+    let (msg, truncated) = decode(&[
+        0x1A, 2, 0x08, 0x01, // field 3
+        0x1A, 2, 0x08, 0x02, // field 3
+        0x1A, 2, 0x08, 0x03, // field 3
+        0x22, 1, b'a', // field 4
+        0x22, 1, b'b', // field 4
+        0x22, 3, b'c', b'd', b'e', // field 4
+        0x30, 0x07, // field 6
+    ]);
+    assert_eq!(msg.msgs.len(), 2);
+    assert_eq!(msg.msgs[1].val(), Some(&2));
+    assert_eq!(msg.strs, &["a", "b"]);
+    assert_eq!(msg.after(), Some(&7));
+    assert!(!truncated);
+}
+
+#[test]
+fn drop_extra_with_flag() {
+    let (msg, truncated) = decode(&[
+        0x12, 12, 0x01, 0, 0, 0, 0x02, 0, 0, 0, 0x03, 0, 0, 0, // field 2
+        0x30, 0x07, // field 6
+    ]);
+    assert_eq!(msg.fixed, &[1, 2]);
+    assert_eq!(msg.after(), Some(&7));
+    assert!(truncated);
+
+    // Flag isn't set if everything fits
+    let (msg, truncated) = decode(&[0x12, 4, 0x01, 0, 0, 0]);
+    assert_eq!(msg.fixed, &[1]);
+    assert!(!truncated);
+}
+
+#[test]
+fn map_drop_extra_with_flag() {
+    let (msg, truncated) = decode(&[
+        0x2A, 4, 0x08, 0x01, 0x10, 0x01, // field 5
+        0x2A, 4, 0x08, 0x02, 0x10, 0x02, // field 5
+        0x2A, 4, 0x08, 0x03, 0x10, 0x03, // field 5
+        0x30, 0x07, // field 6
+    ]);
+    assert_eq!(msg.mapping.len(), 2);
+    assert_eq!(msg.mapping[&1], 1);
+    assert_eq!(msg.mapping[&2], 2);
+    assert_eq!(msg.after(), Some(&7));
+    assert!(truncated);
+
+    // Overwriting an existing key doesn't count as overflow
+    let (msg, truncated) = decode(&[
+        0x2A, 4, 0x08, 0x01, 0x10, 0x01, // field 5
+        0x2A, 4, 0x08, 0x02, 0x10, 0x02, // field 5
+        0x2A, 4, 0x08, 0x01, 0x10, 0x03, // field 5
+    ]);
+    assert_eq!(msg.mapping[&1], 3);
+    assert!(!truncated);
+}
+
+#[test]
+fn truncated_is_sticky() {
+    let mut msg = proto::Overflow::default();
+    let data = [0x12, 12, 0x01, 0, 0, 0, 0x02, 0, 0, 0, 0x03, 0, 0, 0];
+    let mut decoder = PbDecoder::new(data.as_slice());
+    msg.decode(&mut decoder, data.len()).unwrap();
+    assert!(decoder.truncated);
+
+    // Decoding more fields that fit doesn't reset the flag
+    msg.clear();
+    let mut decoder = PbDecoder::new([0x30, 0x07].as_slice());
+    decoder.truncated = true;
+    msg.decode(&mut decoder, 2).unwrap();
+    assert!(decoder.truncated);
+}
+
+#[test]
+fn truncate_string() {
+    let (msg, truncated) = decode(&[
+        0x3A, 5, b'a', b'b', b'c', b'd', b'e', // field 7
+        0x30, 0x07, // field 6
+    ]);
+    assert_eq!(msg.log(), Some(&"abcd".try_into().unwrap()));
+    assert_eq!(msg.after(), Some(&7));
+    assert!(!truncated);
+
+    // Multi-byte character straddling the capacity limit is dropped entirely
+    let (msg, _) = decode(&[
+        0x3A, 6, b'a', b'b', b'c', 0xE2, 0x82, 0xAC, // field 7
+        0x30, 0x07, // field 6
+    ]);
+    assert_eq!(msg.log(), Some(&"abc".try_into().unwrap()));
+    assert_eq!(msg.after(), Some(&7));
+
+    let (msg, _) = decode(&[0x3A, 6, 208, 151, 208, 180, 208, 181]);
+    assert_eq!(msg.log(), Some(&"Зд".try_into().unwrap()));
+
+    // Later occurrence replaces the earlier value before being truncated
+    let (msg, _) = decode(&[
+        0x3A, 1, b'x', // field 7
+        0x3A, 6, 0xE2, 0x82, 0xAC, 0xE2, 0x82, 0xAC, // field 7
+    ]);
+    assert_eq!(msg.log(), Some(&"€".try_into().unwrap()));
+}
+
+#[test]
+fn truncate_bytes_with_flag() {
+    let (msg, truncated) = decode(&[
+        0x4A, 6, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, // field 9
+        0x30, 0x07, // field 6
+    ]);
+    assert_eq!(msg.blob().unwrap(), &[0x01, 0x02, 0x03, 0x04]);
+    assert_eq!(msg.after(), Some(&7));
+    assert!(truncated);
+}
+
+#[test]
+fn string_overflow_error() {
+    let mut msg = proto::Overflow::default();
+    let data = [0x42, 5, b'a', b'b', b'c', b'd', b'e'];
+    let mut decoder = PbDecoder::new(data.as_slice());
+    assert_eq!(
+        msg.decode(&mut decoder, data.len()),
+        Err(DecodeError::Capacity {
+            needed: 1,
+            capacity: 4
+        })
+    );
+    // Fields without a truncation policy aren't affected by ignore_repeated_cap_err
+    let mut decoder = PbDecoder::new(data.as_slice());
+    decoder.ignore_repeated_cap_err = true;
+    assert_eq!(
+        msg.decode(&mut decoder, data.len()),
+        Err(DecodeError::Capacity {
+            needed: 1,
+            capacity: 4
+        })
+    );
+}
@@ -0,0 +1,114 @@
+use micropb::{MessageDecode, MessageEncode, PbEncoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/packed.rs"));
+}
+
+fn encode<M: MessageEncode>(msg: &M) -> Vec<u8> {
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    encoder.into_writer()
+}
+
+#[test]
+fn proto3() {
+    let msg = proto::packed_::Packing {
+        implicit: vec![1, 150],
+        unpacked: vec![1, 150],
+        packed: vec![1, 150],
+    };
+    let data = encode(&msg);
+    assert_eq!(
+        data,
+        [
+            0x0A, 0x03, 0x01, 0x96, 0x01, // implicit, packed by default
+            0x10, 0x01, 0x10, 0x96, 0x01, // unpacked
+            0x1A, 0x03, 0x01, 0x96, 0x01, // packed
+        ]
+    );
+    assert_eq!(msg.compute_size(), data.len());
+    let mut buf = [0; 16];
+    assert_eq!(msg.encode_to_slice_reverse(&mut buf).unwrap(), data);
+}
+
+#[test]
+fn proto2() {
+    let msg = proto::packed2_::Packing {
+        implicit: vec![1, 150],
+        unpacked: vec![1, 150],
+        packed: vec![1, 150],
+    };
+    let data = encode(&msg);
+    assert_eq!(
+        data,
+        [
+            0x08, 0x01, 0x08, 0x96, 0x01, // implicit, unpacked by default
+            0x10, 0x01, 0x10, 0x96, 0x01, // unpacked
+            0x1A, 0x03, 0x01, 0x96, 0x01, // packed
+        ]
+    );
+    assert_eq!(msg.compute_size(), data.len());
+    let mut buf = [0; 16];
+    assert_eq!(msg.encode_to_slice_reverse(&mut buf).unwrap(), data);
+}
+
+#[test]
+fn decode_either() {
+    // Both forms are accepted when decoding, regardless of the declared option
+    let packed = [0x0A, 0x02, 0x01, 0x02, 0x12, 0x02, 0x01, 0x02];
+    let unpacked = [0x08, 0x01, 0x08, 0x02, 0x10, 0x01, 0x10, 0x02];
+    for data in [&packed[..], &unpacked[..]] {
+        let mut msg = proto::packed_::Packing::default();
+        msg.merge_from_bytes(data).unwrap();
+        assert_eq!(msg.implicit, [1, 2]);
+        assert_eq!(msg.unpacked, [1, 2]);
+
+        let mut msg = proto::packed2_::Packing::default();
+        msg.merge_from_bytes(data).unwrap();
+        assert_eq!(msg.implicit, [1, 2]);
+        assert_eq!(msg.unpacked, [1, 2]);
+    }
+}
+
+/// Encoding of `Fixed`, with every element encoded one by one
+const FIXED: &[u8] = &[
+    0x0A, 8, 0x01, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, // fixed32: [1, u32::MAX]
+    0x12, 8, 0xFE, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, // sfixed64: [-2]
+    0x1A, 8, 0x00, 0x00, 0xC0, 0x3F, 0x00, 0x00, 0x80, 0xBF, // float: [1.5, -1.0]
+    0x22, 8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xF8, 0x3F, // double: [1.5]
+    0x2A, 4, 0x03, 0x01, 0x00, 0x00, // narrow: [259]
+];
+
+#[test]
+fn fixed() {
+    let msg = proto::packed_::Fixed {
+        fixed32: vec![1, u32::MAX],
+        sfixed64: vec![-2],
+        float: vec![1.5, -1.0],
+        double: vec![1.5],
+        narrow: vec![259],
+    };
+    let data = encode(&msg);
+    assert_eq!(data, FIXED);
+    assert_eq!(msg.compute_size(), data.len());
+    let mut buf = [0; 64];
+    assert_eq!(msg.encode_to_slice_reverse(&mut buf).unwrap(), data);
+
+    let mut decoded = proto::packed_::Fixed::default();
+    decoded.merge_from_bytes(FIXED).unwrap();
+    assert_eq!(decoded, msg);
+    // Packed elements are appended to the existing ones
+    decoded.merge_from_bytes(FIXED).unwrap();
+    assert_eq!(decoded.fixed32, [1, u32::MAX, 1, u32::MAX]);
+    assert_eq!(decoded.narrow, [259, 259]);
+
+    // Unpacked elements are accepted as well
+    let mut decoded = proto::packed_::Fixed::default();
+    decoded
+        .merge_from_bytes(&[0x0D, 0x01, 0x00, 0x00, 0x00, 0x1D, 0x00, 0x00, 0xC0, 0x3F])
+        .unwrap();
+    assert_eq!(decoded.fixed32, [1]);
+    assert_eq!(decoded.float, [1.5]);
+}
@@ -1,3 +1,4 @@
+use micropb::MessageDecode;
 use proto::Recursive;
 
 mod proto {
@@ -19,5 +20,82 @@ fn recursive_types() {
         proto::Recursive_::Of::Num(i) => {
             let _: i32 = i;
         }
+        proto::Recursive_::Of::Large(_) => {}
+    }
+}
+
+/// Unknown field handler that records the address of the stack when it encounters field 15
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct StackProbe;
+
+std::thread_local! {
+    static STACK_ADDR: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+impl micropb::FieldDecode for StackProbe {
+    fn decode_field<R: micropb::PbRead>(
+        &mut self,
+        tag: micropb::Tag,
+        decoder: &mut micropb::PbDecoder<R>,
+    ) -> Result<bool, micropb::DecodeError<R::Error>> {
+        if tag.field_num() == 15 {
+            let local = 0u8;
+            STACK_ADDR.with(|a| a.set(std::hint::black_box(&local) as *const u8 as usize));
+        }
+        decoder.skip_wire_value(tag.wire_type())?;
+        Ok(true)
+    }
+}
+
+impl micropb::FieldEncode for StackProbe {
+    fn encode_fields<W: micropb::PbWrite>(
+        &self,
+        _encoder: &mut micropb::PbEncoder<W>,
+    ) -> Result<(), micropb::EncodeError<W::Error>> {
+        Ok(())
+    }
+
+    fn compute_fields_size(&self) -> usize {
+        0
+    }
+}
+
+/// Decode `levels` of nested messages with the probe field at the innermost level, and return the
+/// stack address recorded by the probe
+fn probe_stack(levels: usize, field: u8) -> usize {
+    let mut data = vec![0x78, 0x00];
+    for _ in 0..levels {
+        let mut outer = vec![field, data.len() as u8];
+        outer.extend_from_slice(&data);
+        data = outer;
+    }
+    let mut msg = Recursive::default();
+    msg.decode(&mut micropb::PbDecoder::new(data.as_slice()), data.len())
+        .unwrap();
+    STACK_ADDR.with(|a| a.get())
+}
+
+/// Stack used per nesting level by an optimized x86-64 build when the generated decode
+/// constructed oneof variants and optional fields in its own frame, measured before they were
+/// outlined
+const BASELINE_PER_LEVEL: usize = 2240;
+
+#[test]
+#[cfg_attr(
+    debug_assertions,
+    ignore = "stack frames of unoptimized builds don't reflect the generated code"
+)]
+fn nested_decode_stack_usage() {
+    // Nest through both the optional field and the oneof
+    for field in [0x0A, 0x12] {
+        let top = probe_stack(1, field);
+        let bottom = probe_stack(9, field);
+        let per_level = (top - bottom) / 8;
+        // The large oneof variant should never be constructed on the stack frame of the decode,
+        // since that frame stays on the stack for every level of nesting
+        assert!(
+            per_level < BASELINE_PER_LEVEL / 4,
+            "field {field:#x} uses {per_level} bytes of stack per nesting level"
+        );
     }
 }
@@ -0,0 +1,67 @@
+//! Definitions of `arbitrary.proto` for `prost`, which is used as a reference implementation
+
+use std::collections::HashMap;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Leaf {
+    #[prost(int32, tag = "1")]
+    pub num: i32,
+    #[prost(sint64, tag = "2")]
+    pub big: i64,
+    #[prost(fixed32, tag = "3")]
+    pub fixed: u32,
+    #[prost(double, tag = "4")]
+    pub real: f64,
+    #[prost(string, optional, tag = "5")]
+    pub name: Option<String>,
+    #[prost(uint32, repeated, tag = "6")]
+    pub packed: Vec<u32>,
+    #[prost(sint32, repeated, packed = "false", tag = "7")]
+    pub unpacked: Vec<i32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Arbitrary {
+    #[prost(string, tag = "1")]
+    pub text: String,
+    #[prost(bytes = "vec", tag = "2")]
+    pub data: Vec<u8>,
+    #[prost(string, repeated, tag = "3")]
+    pub strs: Vec<String>,
+    #[prost(bytes = "vec", repeated, tag = "4")]
+    pub blobs: Vec<Vec<u8>>,
+    #[prost(fixed64, repeated, tag = "5")]
+    pub fixeds: Vec<u64>,
+    #[prost(map = "string, message", tag = "6")]
+    pub leaves: HashMap<String, Leaf>,
+    #[prost(map = "int32, int32", tag = "7")]
+    pub kinds: HashMap<i32, i32>,
+    #[prost(message, optional, tag = "8")]
+    pub leaf: Option<Leaf>,
+    #[prost(message, repeated, tag = "9")]
+    pub leaf_list: Vec<Leaf>,
+    #[prost(int32, tag = "10")]
+    pub kind: i32,
+    #[prost(int32, repeated, tag = "11")]
+    pub kind_list: Vec<i32>,
+    #[prost(bool, optional, tag = "12")]
+    pub flag: Option<bool>,
+    #[prost(message, optional, boxed, tag = "13")]
+    pub child: Option<Box<Arbitrary>>,
+    #[prost(oneof = "Choice", tags = "14, 15, 16, 17, 18")]
+    pub choice: Option<Choice>,
+}
+
+#[derive(Clone, PartialEq, prost::Oneof)]
+pub enum Choice {
+    #[prost(message, tag = "14")]
+    Leaf(Leaf),
+    #[prost(string, tag = "15")]
+    Str(String),
+    #[prost(bytes = "vec", tag = "16")]
+    Bytes(Vec<u8>),
+    #[prost(float, tag = "17")]
+    Float(f32),
+    #[prost(message, boxed, tag = "18")]
+    Child(Box<Arbitrary>),
+}
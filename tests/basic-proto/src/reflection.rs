@@ -0,0 +1,70 @@
+use micropb::{
+    FieldDescriptor, FieldLabel, MessageFields, MessageName, WIRE_TYPE_I32, WIRE_TYPE_I64,
+    WIRE_TYPE_LEN, WIRE_TYPE_VARINT,
+};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/reflection.rs"));
+}
+
+use proto::reflection_::*;
+
+fn desc(num: u32, name: &'static str, wire_type: u8, label: FieldLabel) -> FieldDescriptor {
+    FieldDescriptor {
+        num,
+        name: Some(name),
+        wire_type,
+        label,
+    }
+}
+
+#[test]
+fn field_table() {
+    assert_eq!(Sample::FULL_NAME, "reflection.Sample");
+    assert_eq!(
+        Sample::FIELDS,
+        &[
+            desc(1, "label", WIRE_TYPE_LEN, FieldLabel::Optional),
+            // Renaming doesn't change the recorded name
+            desc(2, "type", WIRE_TYPE_VARINT, FieldLabel::Single),
+            desc(3, "delta", WIRE_TYPE_VARINT, FieldLabel::Single),
+            desc(
+                4,
+                "samples",
+                WIRE_TYPE_I32,
+                FieldLabel::Repeated { packed: true }
+            ),
+            desc(
+                5,
+                "inners",
+                WIRE_TYPE_LEN,
+                FieldLabel::Repeated { packed: false }
+            ),
+            desc(6, "counters", WIRE_TYPE_LEN, FieldLabel::Map),
+            desc(7, "value", WIRE_TYPE_I64, FieldLabel::Oneof),
+            desc(8, "raw", WIRE_TYPE_LEN, FieldLabel::Oneof),
+            // Skipped field 9 is left out
+        ]
+    );
+}
+
+#[test]
+fn field_table_without_names() {
+    assert_eq!(Sample_::Inner::FULL_NAME, "reflection.Sample.Inner");
+    assert_eq!(Sample_::Inner::FIELDS.len(), 2);
+    assert!(Sample_::Inner::FIELDS.iter().all(|f| f.name.is_none()));
+    assert_eq!(
+        Sample_::Inner::field_descriptor(2).unwrap().wire_type,
+        WIRE_TYPE_LEN
+    );
+}
+
+#[test]
+fn field_lookup() {
+    let raw = Sample::field_descriptor(8).unwrap();
+    assert_eq!(raw.name, Some("raw"));
+    assert!(Sample::field_descriptor(9).is_none());
+    assert!(Sample::field_descriptor(0).is_none());
+}
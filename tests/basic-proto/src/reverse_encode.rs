@@ -0,0 +1,157 @@
+use micropb::{BufferTooSmall, MessageDecode, MessageEncode, PbEncoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/arbitrary_alloc.rs"));
+}
+
+use proto::{Arbitrary, Arbitrary_, Kind, Leaf};
+
+use crate::reference;
+
+fn leaf(num: i32) -> Leaf {
+    let mut leaf = Leaf {
+        num,
+        big: i64::MIN,
+        fixed: 7,
+        real: 1.5,
+        packed: vec![1, 300, 70000],
+        unpacked: vec![-1, 2, i32::MIN],
+        ..Default::default()
+    };
+    leaf.set_name(format!("leaf{num}"));
+    leaf
+}
+
+fn message() -> Arbitrary {
+    let mut msg = Arbitrary {
+        text: "hello world".to_owned(),
+        data: vec![0xAA; 12],
+        strs: vec!["a".to_owned(), "bc".to_owned(), "".to_owned()],
+        blobs: vec![vec![1, 2, 3], vec![]],
+        fixeds: vec![u64::MAX, 0, 5],
+        leaf_list: vec![leaf(1), Leaf::default(), leaf(-2)],
+        kind: Kind::B,
+        kind_list: vec![Kind::A, Kind(99), Kind::B],
+        choice: Some(Arbitrary_::Choice::OneofStr("choice".to_owned())),
+        ..Default::default()
+    };
+    msg.leaves.insert("one".to_owned(), leaf(3));
+    msg.leaves.insert("two".to_owned(), Leaf::default());
+    msg.kinds.insert(-3, Kind::A);
+    msg.kinds.insert(4, Kind::B);
+    msg.set_leaf(leaf(4));
+    msg.set_flag(false);
+    msg.child = Some(Box::new(Arbitrary {
+        choice: Some(Arbitrary_::Choice::OneofChild(Box::new(Arbitrary {
+            choice: Some(Arbitrary_::Choice::OneofLeaf(leaf(5))),
+            ..Default::default()
+        }))),
+        ..Default::default()
+    }));
+    msg
+}
+
+fn encode_forward(msg: &Arbitrary) -> Vec<u8> {
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    encoder.into_writer()
+}
+
+#[test]
+fn roundtrip() {
+    let msg = message();
+    let mut buf = [0; 1024];
+    let encoded = msg.encode_to_slice_reverse(&mut buf).unwrap();
+    assert_eq!(encoded.len(), msg.compute_size());
+    // Fields are written in reverse order, so the output differs from the forward encoding
+    assert_ne!(encoded, encode_forward(&msg));
+
+    let mut decoded = Arbitrary::default();
+    decoded.merge_from_bytes(encoded).unwrap();
+    assert_eq!(decoded, msg);
+
+    let mut buf = [0; 64];
+    let empty = Arbitrary::default();
+    assert_eq!(empty.encode_to_slice_reverse(&mut buf).unwrap(), &[]);
+}
+
+#[test]
+fn reference_decoder() {
+    use prost::Message;
+
+    let msg = message();
+    let mut buf = [0; 1024];
+    let encoded = msg.encode_to_slice_reverse(&mut buf).unwrap();
+    let reference = reference::Arbitrary::decode(&encoded[..]).unwrap();
+    assert_eq!(
+        reference,
+        reference::Arbitrary::decode(encode_forward(&msg).as_slice()).unwrap()
+    );
+    assert_eq!(reference.strs, ["a", "bc", ""]);
+    assert_eq!(reference.leaf_list[2].unpacked, [-1, 2, i32::MIN]);
+
+    // Encoding the decoded message with the reference encoder yields the original message
+    let mut decoded = Arbitrary::default();
+    decoded
+        .merge_from_bytes(&reference.encode_to_vec())
+        .unwrap();
+    assert_eq!(decoded, msg);
+}
+
+#[test]
+fn buffer_too_small() {
+    let msg = message();
+    let size = msg.compute_size();
+    let mut buf = vec![0; size];
+    assert!(msg.encode_to_slice_reverse(&mut buf).is_ok());
+
+    let mut buf = vec![0; size - 1];
+    let err: BufferTooSmall = msg.encode_to_slice_reverse(&mut buf).unwrap_err();
+    assert!(err.written < size);
+    assert!(err.needed >= 1);
+}
+
+#[test]
+fn mutate_after_decode() {
+    use prost::Message;
+
+    let mut msg = Arbitrary::default();
+    msg.merge_from_bytes(&encode_forward(&message())).unwrap();
+    // Grow nested strings past 127 bytes, so their length prefixes and those of every enclosing
+    // message get longer
+    msg.leaf_list[2].set_name("x".repeat(200));
+    if let Some(Arbitrary_::Choice::OneofChild(child)) = &mut msg.child.as_mut().unwrap().choice {
+        child.text = "y".repeat(300);
+    }
+    msg.leaves
+        .get_mut("one")
+        .unwrap()
+        .mut_name()
+        .unwrap()
+        .clear();
+
+    // Sizes are never cached, so every encoding reflects the mutations
+    let forward = encode_forward(&msg);
+    assert_eq!(forward.len(), msg.compute_size());
+    let mut buf = [0; 2048];
+    let reverse = msg.encode_to_slice_reverse(&mut buf).unwrap();
+
+    for encoded in [&forward[..], reverse] {
+        let reference = reference::Arbitrary::decode(encoded).unwrap();
+        assert_eq!(
+            reference.leaf_list[2].name.as_deref(),
+            Some(&*"x".repeat(200))
+        );
+        assert_eq!(reference.leaves["one"].name.as_deref(), Some(""));
+        let Some(reference::Choice::Child(child)) = &reference.child.unwrap().choice else {
+            panic!("missing child");
+        };
+        assert_eq!(child.text, "y".repeat(300));
+
+        let mut decoded = Arbitrary::default();
+        decoded.merge_from_bytes(encoded).unwrap();
+        assert_eq!(decoded, msg);
+    }
+}
@@ -0,0 +1,51 @@
+// The generated `roundtrip_tests` modules run as part of this crate's tests
+mod proto_heapless {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/roundtrip_heapless.rs"));
+}
+
+pub(crate) mod proto_alloc {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/roundtrip_alloc.rs"));
+}
+
+#[test]
+fn boundary_sample() {
+    let basic = proto_heapless::basic_::BasicTypes::_roundtrip_sample(false, 1);
+    // Every optional field is set, with the maximum of its configured type
+    assert_eq!(basic.int32_num(), Some(&i8::MAX));
+    assert_eq!(basic.uint64_num(), Some(&u16::MAX));
+    assert_eq!(basic.sfixed64_num(), Some(&i64::MAX));
+    assert_eq!(basic.boolean(), Some(&true));
+
+    let list = proto_heapless::StrList::_roundtrip_sample(false, 1);
+    assert_eq!(list.list.len(), 1);
+    // The 4-byte character fits within `max_bytes`
+    assert_eq!(list.list[0], "\u{10FFFF}");
+
+    // Nested messages are left empty at the depth limit
+    let rec = proto_alloc::Recursive::_roundtrip_sample(false, 2);
+    let inner = rec.recursive.as_deref().unwrap();
+    assert_eq!(
+        inner.recursive.as_deref(),
+        Some(&proto_alloc::Recursive::default())
+    );
+}
+
+#[test]
+fn filled_sample() {
+    // Fixed containers are filled to capacity
+    let list = proto_heapless::StrList::_roundtrip_sample(true, 1);
+    assert_eq!(list.list.len(), 4);
+    assert_eq!(list.list[0], "xxxxxx");
+    let map = proto_heapless::Map::_roundtrip_sample(true, 1);
+    assert_eq!(map.mapping.len(), 4);
+
+    // Unbounded containers hold a few elements
+    let map = proto_alloc::Map::_roundtrip_sample(true, 1);
+    let keys: Vec<_> = map.mapping.keys().map(String::as_str).collect();
+    assert_eq!(keys, ["a", "b", "c"]);
+    assert_eq!(map.mapping["a"], [0xFF; 3]);
+}
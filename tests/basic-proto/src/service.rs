@@ -0,0 +1,136 @@
+use micropb::{
+    service::{find_method, DispatchError, MethodDescriptor},
+    DecodeError, EncodeError, MessageDecode, MessageEncode, Never, PbEncoder,
+};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/service.rs"));
+}
+
+use proto::service_::*;
+
+#[derive(Default)]
+struct Device {
+    level: u32,
+}
+
+#[derive(Debug, PartialEq)]
+struct Rejected;
+
+impl DeviceControl for Device {
+    type Error = Rejected;
+
+    fn get_status(&mut self, _req: Empty) -> Result<Status, Rejected> {
+        Ok(Status {
+            uptime: 100 + self.level,
+            ok: true,
+        })
+    }
+
+    fn set_level(&mut self, req: SetLevelRequest) -> Result<Empty, Rejected> {
+        if req.level > 10 {
+            return Err(Rejected);
+        }
+        self.level = req.level;
+        Ok(Empty {})
+    }
+
+    fn echo(&mut self, req: Status_::Detail) -> Result<Status_::Detail, Rejected> {
+        Ok(req)
+    }
+}
+
+fn call<M: MessageEncode>(
+    device: &mut Device,
+    method: &str,
+    req: &M,
+) -> Result<Vec<u8>, DispatchError<Rejected, EncodeError<Never>>> {
+    let index = find_method(DeviceControl_::METHODS, method).unwrap().index;
+    let mut request = vec![];
+    req.encode(&mut PbEncoder::new(&mut request)).unwrap();
+
+    let mut response = vec![];
+    DeviceControl_::dispatch(device, index, &request, &mut PbEncoder::new(&mut response))?;
+    Ok(response)
+}
+
+#[test]
+fn method_table() {
+    assert_eq!(
+        DeviceControl_::METHODS,
+        &[
+            MethodDescriptor {
+                index: 0,
+                name: "GetStatus",
+                request: "service.Empty",
+                response: "service.Status",
+            },
+            MethodDescriptor {
+                index: 1,
+                name: "SetLevel",
+                request: "service.SetLevelRequest",
+                response: "service.Empty",
+            },
+            MethodDescriptor {
+                index: 2,
+                name: "Echo",
+                request: "service.Status.Detail",
+                response: "service.Status.Detail",
+            },
+        ]
+    );
+}
+
+#[test]
+fn dispatch() {
+    let mut device = Device::default();
+
+    let resp = call(&mut device, "SetLevel", &SetLevelRequest { level: 5 }).unwrap();
+    assert!(resp.is_empty());
+    assert_eq!(device.level, 5);
+
+    let resp = call(&mut device, "GetStatus", &Empty {}).unwrap();
+    let mut status = Status::default();
+    status.merge_from_bytes(&resp).unwrap();
+    assert_eq!(
+        status,
+        Status {
+            uptime: 105,
+            ok: true
+        }
+    );
+
+    let detail = Status_::Detail {
+        text: "hello".to_owned(),
+    };
+    let resp = call(&mut device, "Echo", &detail).unwrap();
+    let mut echoed = Status_::Detail::default();
+    echoed.merge_from_bytes(&resp).unwrap();
+    assert_eq!(echoed, detail);
+}
+
+#[test]
+fn dispatch_errors() {
+    let mut device = Device::default();
+
+    assert_eq!(
+        call(&mut device, "SetLevel", &SetLevelRequest { level: 11 }),
+        Err(DispatchError::Service(Rejected))
+    );
+    assert_eq!(device.level, 0);
+
+    let mut response = vec![];
+    let mut encoder = PbEncoder::new(&mut response);
+    assert_eq!(
+        DeviceControl_::dispatch(&mut device, 3, &[], &mut encoder),
+        Err(DispatchError::UnknownMethod(3))
+    );
+    // Truncated varint in the request of `SetLevel`
+    assert_eq!(
+        DeviceControl_::dispatch(&mut device, 1, &[0x08, 0x80], &mut encoder),
+        Err(DispatchError::Decode(DecodeError::UnexpectedEof))
+    );
+    assert!(response.is_empty());
+}
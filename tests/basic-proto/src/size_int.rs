@@ -0,0 +1,53 @@
+use micropb::{MessageDecode, MessageEncode, PbDecoder, PbEncoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/size_int.rs"));
+}
+
+#[test]
+fn field_type() {
+    let basic = proto::basic_::BasicTypes::default();
+    let _: usize = basic.uint32_num;
+    let _: Option<&usize> = basic.uint32_num();
+    let _: isize = basic.sint32_num;
+    let _: Option<&isize> = basic.sint32_num();
+    let _: usize = basic.fixed32_num;
+    let _: Option<&usize> = basic.fixed32_num();
+}
+
+#[test]
+fn decode() {
+    let mut basic = proto::basic_::BasicTypes::default();
+    let mut decoder = PbDecoder::new(
+        [
+            0x18, 0xFF, 0xFF, 0xFF, 0xFF, 0x0F, // field 3
+            0x28, 0xFF, 0xFF, 0xFF, 0xFF, 0x0F, // field 5
+            0x3D, 0x01, 0x02, 0x03, 0x04, // field 7
+        ]
+        .as_slice(),
+    );
+    basic.decode(&mut decoder, 17).unwrap();
+    assert_eq!(basic.uint32_num(), Some(&(u32::MAX as usize)));
+    assert_eq!(basic.sint32_num(), Some(&(i32::MIN as isize)));
+    assert_eq!(basic.fixed32_num(), Some(&0x04030201));
+}
+
+#[test]
+fn encode() {
+    let mut basic = proto::basic_::BasicTypes::default();
+    basic.set_uint32_num(300);
+    basic.set_sint32_num(-2);
+    assert_eq!(basic.compute_size(), 5);
+
+    let mut encoder = PbEncoder::new(vec![]);
+    basic.encode(&mut encoder).unwrap();
+    assert_eq!(
+        encoder.into_writer(),
+        &[
+            0x18, 0xAC, 0x02, // field 3
+            0x28, 0x03, // field 5
+        ]
+    );
+}
@@ -0,0 +1,87 @@
+use micropb::{MessageDecode, MessageEncode, PbEncoder, PbMap};
+
+mod heapless_proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/sorted_map_heapless.rs"));
+}
+
+mod std_proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/sorted_map_std.rs"));
+}
+
+const ENTRIES: [(&str, &[u8]); 4] = [
+    ("b", b"\x02"),
+    ("", b""),
+    ("ab", b"\x01\x02"),
+    ("a", b"\x01"),
+];
+
+/// Encoded entries of `ENTRIES` in ascending key order
+const SORTED: &[u8] = &[
+    0x0A, 0x04, 0x0A, 0x00, 0x12, 0x00, // "" => ""
+    0x0A, 0x06, 0x0A, 0x01, b'a', 0x12, 0x01, 0x01, // "a" => [1]
+    0x0A, 0x08, 0x0A, 0x02, b'a', b'b', 0x12, 0x02, 0x01, 0x02, // "ab" => [1, 2]
+    0x0A, 0x06, 0x0A, 0x01, b'b', 0x12, 0x01, 0x02, // "b" => [2]
+];
+
+fn encode<M: MessageEncode>(msg: &M) -> Vec<u8> {
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    encoder.into_writer()
+}
+
+#[test]
+fn heapless() {
+    let mut forward = heapless_proto::Map::default();
+    for (k, v) in ENTRIES {
+        forward
+            .mapping
+            .pb_insert(k.try_into().unwrap(), v.try_into().unwrap())
+            .unwrap();
+    }
+    let mut backward = heapless_proto::Map::default();
+    for (k, v) in ENTRIES.into_iter().rev() {
+        backward
+            .mapping
+            .pb_insert(k.try_into().unwrap(), v.try_into().unwrap())
+            .unwrap();
+    }
+    // `FnvIndexMap` iterates in insertion order
+    assert!(forward.mapping.keys().ne(backward.mapping.keys()));
+
+    assert_eq!(encode(&forward), SORTED);
+    assert_eq!(encode(&backward), SORTED);
+
+    // Reverse encoding produces the same order
+    let mut buf = [0; 64];
+    assert_eq!(backward.encode_to_slice_reverse(&mut buf).unwrap(), SORTED);
+
+    let mut decoded = heapless_proto::Map::default();
+    decoded.merge_from_bytes(SORTED).unwrap();
+    assert_eq!(decoded.mapping.len(), 4);
+}
+
+#[test]
+fn hash_map() {
+    let mut msg = std_proto::Map::default();
+    for (k, v) in ENTRIES {
+        msg.mapping.insert(k.to_owned(), v.to_vec());
+    }
+    assert_eq!(encode(&msg), SORTED);
+
+    // Each `HashMap` has its own random order, so try it a few times
+    for _ in 0..8 {
+        let mut other = std_proto::Map::default();
+        for (k, v) in ENTRIES.into_iter().rev() {
+            other.mapping.insert(k.to_owned(), v.to_vec());
+        }
+        assert_eq!(encode(&other), SORTED);
+        let mut buf = [0; 64];
+        assert_eq!(other.encode_to_slice_reverse(&mut buf).unwrap(), SORTED);
+    }
+
+    assert_eq!(encode(&std_proto::Map::default()), &[]);
+}
@@ -0,0 +1,40 @@
+use micropb::{MessageDecode, MessageEncode, PbEncoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/string_as_bytes.rs"));
+}
+
+#[test]
+fn string_type() {
+    let data = proto::Data::default();
+    // String defaults aren't escaped like bytes defaults
+    assert_eq!(data.s, b"a\n\0");
+    let _: Vec<u8> = data.s;
+
+    let list = proto::StrList::default();
+    let _: Vec<Vec<u8>> = list.list;
+}
+
+#[test]
+fn decode_without_validation() {
+    let mut data = proto::Data::default();
+    data.merge_from_bytes(&[0x0A, 3, b'a', 0xFF, b'c']).unwrap();
+    assert_eq!(data.s().unwrap(), &[b'a', 0xFF, b'c']);
+
+    let mut list = proto::StrList::default();
+    list.merge_from_bytes(&[0x0A, 2, 0x80, 0x80, 0x0A, 1, b'x'])
+        .unwrap();
+    assert_eq!(list.list, &[vec![0x80, 0x80], vec![b'x']]);
+}
+
+#[test]
+fn encode_same_as_string() {
+    let mut data = proto::Data::default();
+    data.set_s(b"abc".to_vec());
+    let mut encoder = PbEncoder::new(vec![]);
+    data.encode(&mut encoder).unwrap();
+    assert_eq!(encoder.into_writer(), &[0x0A, 3, b'a', b'b', b'c']);
+    assert_eq!(data.compute_size(), 5);
+}
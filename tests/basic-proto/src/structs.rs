@@ -0,0 +1,177 @@
+use micropb::{DecodeError, MessageDecode, MessageEncode, PbDecoder, PbEncoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/struct.rs"));
+}
+
+mod proto_fixed {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/struct_fixed.rs"));
+}
+
+use proto::{
+    google_::protobuf_::{ListValue, NullValue, Struct, Value, Value_::Kind},
+    structs_::DeviceConfig,
+};
+
+// Encoding of the following `DeviceConfig` in text format, laid out like the output of
+// `protoc --encode`, with fields in number order and map entries in the order listed:
+//
+// name: "dev"
+// settings {
+//   fields { key: "enabled" value { bool_value: true } }
+//   fields { key: "gains" value { list_value { values { number_value: 1.5 } values { null_value: NULL_VALUE } } } }
+//   fields { key: "net" value { struct_value { fields { key: "ssid" value { string_value: "home" } } } } }
+// }
+const FIXTURE: &[u8] = &[
+    0x0A, 0x03, b'd', b'e', b'v', // name
+    0x12, 0x46, // settings
+    0x0A, 0x0D, 0x0A, 0x07, b'e', b'n', b'a', b'b', b'l', b'e', b'd', // "enabled"
+    0x12, 0x02, 0x20, 0x01, // bool_value
+    0x0A, 0x1A, 0x0A, 0x05, b'g', b'a', b'i', b'n', b's', // "gains"
+    0x12, 0x11, 0x32, 0x0F, // list_value
+    0x0A, 0x09, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xF8, 0x3F, // number_value
+    0x0A, 0x02, 0x08, 0x00, // null_value
+    0x0A, 0x19, 0x0A, 0x03, b'n', b'e', b't', // "net"
+    0x12, 0x12, 0x2A, 0x10, // struct_value
+    0x0A, 0x0E, 0x0A, 0x04, b's', b's', b'i', b'd', // "ssid"
+    0x12, 0x06, 0x1A, 0x04, b'h', b'o', b'm', b'e', // string_value
+];
+
+#[test]
+fn decode_fixture() {
+    let mut config = DeviceConfig::default();
+    config.merge_from_bytes(FIXTURE).unwrap();
+    assert_eq!(config.name, "dev");
+
+    let fields = &config.settings.fields;
+    assert_eq!(fields.len(), 3);
+    assert_eq!(fields["enabled"].as_bool(), Some(true));
+    assert_eq!(fields["enabled"].as_f64(), None);
+
+    let gains = fields["gains"].as_list().unwrap();
+    assert_eq!(gains.values.len(), 2);
+    assert_eq!(gains.values[0].as_f64(), Some(1.5));
+    assert!(gains.values[1].is_null());
+    assert!(!gains.values[0].is_null());
+
+    let net = fields["net"].as_struct().unwrap();
+    assert_eq!(net.fields["ssid"].as_str(), Some("home"));
+    assert_eq!(net.fields["ssid"].as_struct(), None);
+
+    // Map entries are encoded in key order, which matches the fixture
+    let mut encoder = PbEncoder::new(vec![]);
+    config.encode(&mut encoder).unwrap();
+    assert_eq!(encoder.into_writer(), FIXTURE);
+    assert_eq!(config.compute_size(), FIXTURE.len());
+}
+
+#[test]
+fn build_value() {
+    let mut inner = Struct::default();
+    inner.fields.insert(
+        "ssid".to_owned(),
+        Value {
+            kind: Some(Kind::StringValue("home".to_owned())),
+        },
+    );
+    let list = ListValue {
+        values: vec![
+            Value {
+                kind: Some(Kind::NumberValue(1.5)),
+            },
+            Value {
+                kind: Some(Kind::NullValue(NullValue::NullValue)),
+            },
+        ],
+    };
+
+    let mut settings = Struct::default();
+    settings.fields.insert(
+        "enabled".to_owned(),
+        Value {
+            kind: Some(Kind::BoolValue(true)),
+        },
+    );
+    // Variants holding `Struct` and `ListValue` are boxed to break the recursion
+    settings.fields.insert(
+        "gains".to_owned(),
+        Value {
+            kind: Some(Kind::ListValue(Box::new(list))),
+        },
+    );
+    settings.fields.insert(
+        "net".to_owned(),
+        Value {
+            kind: Some(Kind::StructValue(Box::new(inner))),
+        },
+    );
+
+    let mut expected = DeviceConfig::default();
+    expected.merge_from_bytes(FIXTURE).unwrap();
+    assert_eq!(settings, expected.settings);
+    // A value without a kind isn't anything
+    assert!(!Value::default().is_null());
+    assert_eq!(Value::default().as_str(), None);
+}
+
+fn field<'a>(
+    s: &'a proto_fixed::google_::protobuf_::Struct,
+    key: &str,
+) -> &'a proto_fixed::google_::protobuf_::Value {
+    s.fields.iter().find(|(k, _)| k.as_str() == key).unwrap().1
+}
+
+#[test]
+fn fixed_containers() {
+    let mut config = proto_fixed::structs_::DeviceConfig::default();
+    config.merge_from_bytes(FIXTURE).unwrap();
+    assert_eq!(config.name, "dev");
+    let settings = &config.settings;
+    assert_eq!(field(settings, "enabled").as_bool(), Some(true));
+    // Nested values are stored as their encoded bytes and decoded on access
+    let gains = field(settings, "gains").as_list().unwrap().unwrap();
+    assert_eq!(gains.values[0].as_f64(), Some(1.5));
+    assert!(gains.values[1].is_null());
+    let net = field(settings, "net").as_struct().unwrap().unwrap();
+    assert_eq!(field(&net, "ssid").as_str(), Some("home"));
+    assert!(field(settings, "enabled").as_struct().is_none());
+
+    let mut encoder = PbEncoder::new(vec![]);
+    config.encode(&mut encoder).unwrap();
+    assert_eq!(encoder.into_writer(), FIXTURE);
+
+    // Strings longer than `max_bytes` don't fit
+    let mut value = proto_fixed::google_::protobuf_::Value::default();
+    let long = [
+        0x1A, 0x11, b'0', b'1', b'2', b'3', b'4', b'5', b'6', b'7', b'8', b'9', b'a', b'b', b'c',
+        b'd', b'e', b'f', b'g',
+    ];
+    assert_eq!(
+        value.merge_from_bytes(&long),
+        Err(DecodeError::Capacity {
+            needed: 1,
+            capacity: 16
+        })
+    );
+}
+
+#[test]
+fn depth_limit() {
+    // Each level of `Value` is nested inside a `Struct` and a map entry
+    let mut config = DeviceConfig::default();
+    let mut decoder = PbDecoder::new(FIXTURE);
+    decoder.max_depth = 3;
+    assert_eq!(
+        config.decode(&mut decoder, FIXTURE.len()),
+        Err(DecodeError::DepthLimit)
+    );
+
+    let mut config = DeviceConfig::default();
+    let mut decoder = PbDecoder::new(FIXTURE);
+    decoder.max_depth = 8;
+    config.decode(&mut decoder, FIXTURE.len()).unwrap();
+}
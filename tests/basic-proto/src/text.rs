@@ -0,0 +1,287 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use micropb::{
+    text::{MessageFromText, MessageToText, Text, TextErrorKind, TextParser},
+    MessageEncode, PbEncoder,
+};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/text.rs"));
+}
+
+mod proto_fixed {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/text_fixed.rs"));
+}
+
+use proto::text_::{Inner, Mode, Text as TextMsg, Text_};
+
+fn to_text(msg: &impl MessageToText) -> String {
+    Text(msg).to_string()
+}
+
+fn sample() -> TextMsg {
+    let mut msg = TextMsg {
+        num: -3,
+        big: i64::MIN,
+        unsigned_big: u64::MAX,
+        flt: 0.1,
+        dbl: 1e300,
+        flag: true,
+        name: "a\"b\n\u{1}é".to_owned(),
+        data: b"\0\xFFz'".to_vec(),
+        mode: Mode::On,
+        nums: vec![1, -1],
+        inners: vec![
+            Inner::default(),
+            Inner {
+                val: 2,
+                ..Default::default()
+            },
+        ],
+        by_id: [(7, "seven".to_owned())].into_iter().collect(),
+        by_name: [(
+            "x".to_owned(),
+            Inner {
+                val: 1,
+                ..Default::default()
+            },
+        )]
+        .into_iter()
+        .collect(),
+        choice: Some(Text_::Choice::ChoiceText("c".to_owned())),
+        ..Default::default()
+    };
+    msg.set_opt_num(0);
+    msg.set_inner(Inner {
+        val: 5,
+        label: String::new(),
+    });
+    msg
+}
+
+#[test]
+fn default_omitted() {
+    assert_eq!(to_text(&TextMsg::default()), "");
+    // Explicit presence fields are written even if they hold the default value
+    let mut msg = TextMsg::default();
+    msg.set_opt_num(0);
+    msg.set_inner(Inner::default());
+    assert_eq!(to_text(&msg), "opt_num: 0 inner { }");
+}
+
+#[test]
+fn all_fields() {
+    assert_eq!(
+        to_text(&sample()),
+        concat!(
+            r#"num: -3 big: -9223372036854775808 unsigned_big: 18446744073709551615 "#,
+            r#"flt: 0.1 dbl: 1e300 flag: true name: "a\"b\n\001é" data: "\000\377z\'" "#,
+            r#"mode: MODE_ON opt_num: 0 inner { val: 5 } nums: 1 nums: -1 inners { } "#,
+            r#"inners { val: 2 } by_id { key: 7 value: "seven" } "#,
+            r#"by_name { key: "x" value { val: 1 } } choice_text: "c""#
+        )
+    );
+
+    let msg = TextMsg {
+        flt: f32::NEG_INFINITY,
+        dbl: f64::NAN,
+        mode: Mode(9),
+        choice: Some(Text_::Choice::ChoiceInner(Inner::default())),
+        ..Default::default()
+    };
+    assert_eq!(to_text(&msg), "flt: -inf dbl: nan mode: 9 choice_inner { }");
+}
+
+#[test]
+fn fixed_buffer() {
+    let msg = TextMsg {
+        num: 1,
+        name: "ab".to_owned(),
+        ..Default::default()
+    };
+    let mut buf = micropb::heapless::String::<32>::new();
+    msg.write_text(&mut buf).unwrap();
+    assert_eq!(buf, r#"num: 1 name: "ab""#);
+
+    let mut buf = micropb::heapless::String::<8>::new();
+    assert!(msg.write_text(&mut buf).is_err());
+}
+
+/// Encode the text format output with `protoc --encode` and check that it matches the binary
+/// encoding of the message. Skipped if `protoc` can't be run.
+#[test]
+fn protoc_encode() {
+    let protoc = std::env::var("PROTOC").unwrap_or_else(|_| "protoc".to_owned());
+    let is_protoc = Command::new(&protoc)
+        .arg("--version")
+        .output()
+        .is_ok_and(|out| out.status.success() && out.stdout.starts_with(b"libprotoc"));
+    if !is_protoc {
+        eprintln!("skipping, {protoc} can't be run");
+        return;
+    }
+
+    let msg = sample();
+    let mut child = Command::new(&protoc)
+        .arg(concat!(
+            "--proto_path=",
+            env!("CARGO_MANIFEST_DIR"),
+            "/proto"
+        ))
+        .arg("--encode=text.Text")
+        .arg("text.proto")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(to_text(&msg).as_bytes())
+        .unwrap();
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success());
+
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    assert_eq!(out.stdout, encoder.into_writer());
+}
+
+#[test]
+fn parse_round_trip() {
+    let msg = sample();
+    assert_eq!(TextMsg::from_text(&to_text(&msg)).unwrap(), msg);
+
+    let msg = TextMsg {
+        flt: f32::INFINITY,
+        dbl: -0.0,
+        mode: Mode(9),
+        choice: Some(Text_::Choice::ChoiceInner(Inner {
+            val: 1,
+            label: "\u{0}'".to_owned(),
+        })),
+        ..Default::default()
+    };
+    let parsed = TextMsg::from_text(&to_text(&msg)).unwrap();
+    assert_eq!(parsed, msg);
+    assert!(parsed.dbl.is_sign_negative());
+    assert_eq!(TextMsg::from_text("").unwrap(), TextMsg::default());
+}
+
+#[test]
+fn parse_protoc_fixture() {
+    let fixture = include_str!("../proto/text.txtpb");
+    assert_eq!(TextMsg::from_text(fixture).unwrap(), sample());
+}
+
+#[test]
+fn parse_syntax_variants() {
+    let msg = TextMsg::from_text(
+        r#"
+        # Separators, list syntax, and angle brackets are all accepted
+        num: 0x10, mode: 1; flag: t
+        nums: [1, 2] nums: 3
+        inners: [{val: 1}, <val: 2>]
+        inner < label: 'a' "b" >
+        by_id [{ value: "x" key: 1 }, { key: 2 }]
+        choice_inner { val: 1 } choice_text: "y"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(msg.num, 16);
+    assert_eq!(msg.mode, Mode::On);
+    assert!(msg.flag);
+    assert_eq!(msg.nums, [1, 2, 3]);
+    assert_eq!(msg.inners.len(), 2);
+    assert_eq!(msg.inners[1].val, 2);
+    assert_eq!(msg.inner().unwrap().label, "ab");
+    assert_eq!(msg.by_id[&1], "x");
+    assert_eq!(msg.by_id[&2], "");
+    // The last oneof variant wins
+    assert_eq!(msg.choice, Some(Text_::Choice::ChoiceText("y".to_owned())));
+
+    // Message fields are merged
+    let msg = TextMsg::from_text("inner { val: 1 } inner { label: 'a' }").unwrap();
+    assert_eq!(msg.inner().unwrap().val, 1);
+    assert_eq!(msg.inner().unwrap().label, "a");
+}
+
+#[test]
+fn parse_unknown_fields() {
+    let input = "num: 1\nextra { a: [1, 'x'] b: <> }\n[ext.field]: 2 name: 'n'";
+    let err = TextMsg::from_text(input).unwrap_err();
+    assert_eq!(err.kind, TextErrorKind::UnknownField);
+    assert_eq!((err.line, err.column), (2, 6));
+    assert_eq!(err.field, Some("extra"));
+
+    let mut msg = TextMsg::default();
+    let mut parser = TextParser::new(input);
+    parser.ignore_unknown_fields = true;
+    msg.merge_text(&mut parser).unwrap();
+    parser.finish().unwrap();
+    assert_eq!(msg.num, 1);
+    assert_eq!(msg.name, "n");
+}
+
+#[test]
+fn parse_errors() {
+    let err = TextMsg::from_text("num: 1\ninner {\n  val: x\n}").unwrap_err();
+    assert_eq!(err.kind, TextErrorKind::InvalidNumber);
+    assert_eq!((err.line, err.column), (3, 8));
+    assert_eq!(err.field, Some("val"));
+    assert_eq!(
+        err.to_string(),
+        r#"invalid number at line 3, column 8 in field "val""#
+    );
+
+    let err = TextMsg::from_text("mode: MODE_BAD").unwrap_err();
+    assert_eq!(err.kind, TextErrorKind::UnknownEnumValue);
+    let err = TextMsg::from_text(r#"name: "\377""#).unwrap_err();
+    assert_eq!(err.kind, TextErrorKind::InvalidUtf8);
+    let err = TextMsg::from_text("by_id { key: 'a' }").unwrap_err();
+    assert_eq!(
+        (err.kind, err.field),
+        (TextErrorKind::WrongType, Some("key"))
+    );
+    let err = TextMsg::from_text("inner { val: 1 ").unwrap_err();
+    assert_eq!(err.kind, TextErrorKind::Syntax);
+    let err = TextMsg::from_text("num 1").unwrap_err();
+    assert_eq!((err.kind, err.column), (TextErrorKind::Syntax, 5));
+}
+
+#[test]
+fn parse_capacity() {
+    use proto_fixed::text_::Text as FixedMsg;
+
+    let msg = FixedMsg::from_text("nums: [1, 2] name: 'abcd' data: '\\001\\002'").unwrap();
+    assert_eq!(msg.nums, [1, 2]);
+    assert_eq!(msg.name, "abcd");
+
+    let err = FixedMsg::from_text("nums: 1\nnums: [2, 3]").unwrap_err();
+    assert_eq!(err.kind, TextErrorKind::Capacity);
+    assert_eq!((err.line, err.field), (2, Some("nums")));
+    let err = FixedMsg::from_text("name: 'ab' 'cde'").unwrap_err();
+    assert_eq!(
+        (err.kind, err.field),
+        (TextErrorKind::Capacity, Some("name"))
+    );
+    let err = FixedMsg::from_text("inner { label: 'abcde' }").unwrap_err();
+    assert_eq!(
+        (err.kind, err.field),
+        (TextErrorKind::Capacity, Some("label"))
+    );
+    let err =
+        FixedMsg::from_text("by_id { key: 1 } by_id { key: 2 } by_id { key: 3 }").unwrap_err();
+    assert_eq!(
+        (err.kind, err.field),
+        (TextErrorKind::Capacity, Some("by_id"))
+    );
+}
@@ -0,0 +1,72 @@
+// Oversized messages are only cheap to construct on 64-bit hosts
+#![cfg(target_pointer_width = "64")]
+
+use micropb::{size::MAX_LEN, BufferTooSmall, EncodeError, MessageEncode, PbEncoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/container_alloc.rs"));
+}
+
+/// Message with a `bytes` field that's too long to be encoded. Zeroed allocations are cheap,
+/// since the pages are never touched.
+fn huge() -> proto::Data {
+    let mut data = proto::Data::default();
+    data.set_b(vec![0; MAX_LEN + 1]);
+    data
+}
+
+#[test]
+fn bytes_too_large() {
+    let data = huge();
+    assert_eq!(data.compute_size(), 1 + 5 + MAX_LEN + 1);
+
+    // Only the tag is written before the length is checked
+    let mut encoder = PbEncoder::new(vec![]);
+    assert_eq!(data.encode(&mut encoder), Err(EncodeError::TooLarge));
+    assert_eq!(encoder.into_writer(), [0x12]);
+
+    let mut encoder = PbEncoder::new(vec![]);
+    assert_eq!(encoder.encode_message(&data), Err(EncodeError::TooLarge));
+    assert!(encoder.into_writer().is_empty());
+
+    let mut buf = [0; 16];
+    assert_eq!(
+        data.encode_to_slice_reverse(&mut buf),
+        Err(BufferTooSmall {
+            written: 0,
+            needed: usize::MAX,
+            available: 16
+        })
+    );
+}
+
+#[test]
+fn nested_too_large() {
+    let mut list = proto::List::default();
+    list.list.push(Default::default());
+    list.list.push(huge());
+    assert_eq!(list.compute_size(), 2 + 1 + 5 + (1 + 5 + MAX_LEN + 1));
+
+    // Encoding stops at the nested message that's too large
+    let mut encoder = PbEncoder::new(vec![]);
+    assert_eq!(list.encode(&mut encoder), Err(EncodeError::TooLarge));
+    assert_eq!(encoder.into_writer(), [0x0A, 0, 0x0A]);
+
+    // The combined size of records that are each under the limit is also checked
+    let mut list = proto::List::default();
+    for _ in 0..2 {
+        let mut data = proto::Data::default();
+        data.set_b(vec![0; MAX_LEN / 2]);
+        list.list.push(data);
+    }
+    assert!(list.compute_size() > MAX_LEN);
+    let mut encoder = PbEncoder::new(vec![]);
+    assert_eq!(encoder.encode_message(&list), Err(EncodeError::TooLarge));
+    assert_eq!(
+        list.write_delimited(&mut encoder),
+        Err(EncodeError::TooLarge)
+    );
+    assert!(encoder.into_writer().is_empty());
+}
@@ -0,0 +1,93 @@
+use micropb::{DecodeError, MessageDecode};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/unknown_enum.rs"));
+}
+
+use proto::{Color, Paint, Paint_};
+
+#[test]
+fn is_known() {
+    assert!(Color::Red.is_known());
+    assert!(Color::Blue.is_known());
+    assert!(!Color(3).is_known());
+    assert!(!Color(-1).is_known());
+}
+
+#[test]
+fn known_values() {
+    let data = [
+        0x08, 0x01, // preserve
+        0x10, 0x02, // error
+        0x18, 0x01, // drop
+        0x20, 0x00, // fallback
+        0x28, 0x02, // drop_list
+        0x32, 4, 0x08, 0x01, 0x10, 0x02, // drop_map
+        0x38, 0x01, // drop_option
+        0x40, 0x02, // drop_choice
+    ];
+    let mut paint = Paint::default();
+    paint.merge_from_bytes(&data).unwrap();
+    assert_eq!(paint.preserve(), Some(&Color::Green));
+    assert_eq!(paint.error(), Some(&Color::Blue));
+    assert_eq!(paint.drop(), Some(&Color::Green));
+    assert_eq!(paint.fallback(), Some(&Color::Red));
+    assert_eq!(paint.drop_list, [Color::Blue]);
+    assert_eq!(paint.drop_map.get(&1), Some(&Color::Blue));
+    assert_eq!(paint.drop_option, Some(Color::Green));
+    assert_eq!(paint.choice, Some(Paint_::Choice::DropChoice(Color::Blue)));
+}
+
+#[test]
+fn preserve_and_default() {
+    let data = [0x08, 0x07, 0x20, 0x09];
+    let mut paint = Paint::default();
+    paint.merge_from_bytes(&data).unwrap();
+    // Unknown value is kept as-is
+    assert_eq!(paint.preserve(), Some(&Color(7)));
+    // Unknown value is replaced by the first variant of the enum, not the field default
+    assert_eq!(paint.fallback(), Some(&Color::Red));
+}
+
+#[test]
+fn error() {
+    let mut paint = Paint::default();
+    assert_eq!(
+        paint.merge_from_bytes(&[0x10, 0x07]),
+        Err(DecodeError::UnknownEnumValue)
+    );
+}
+
+#[test]
+fn drop() {
+    let data = [
+        0x18, 0x07, // drop, unknown
+        0x38, 0xAC, 0x02, // drop_option, unknown multi-byte varint
+        0x28, 0x01, 0x28, 0x09, // drop_list, unpacked
+        0x2A, 3, 0x02, 0x05, 0x00, // drop_list, packed
+        0x32, 4, 0x08, 0x01, 0x10, 0x09, // drop_map, unknown value
+        0x32, 4, 0x08, 0x02, 0x10, 0x01, // drop_map, known value
+        0x48, 0x05, // other
+        0x40, 0x03, // drop_choice, unknown
+        0x08, 0x02, // preserve
+    ];
+    let mut paint = Paint::default();
+    paint.merge_from_bytes(&data).unwrap();
+    // Dropped optional fields aren't marked as present
+    assert_eq!(paint.drop(), None);
+    assert_eq!(paint.drop_option, None);
+    assert_eq!(paint.drop_list, [Color::Green, Color::Blue, Color::Red]);
+    assert_eq!(paint.drop_map.len(), 1);
+    assert_eq!(paint.drop_map.get(&2), Some(&Color::Green));
+    // Oneof keeps its previous variant
+    assert_eq!(paint.choice, Some(Paint_::Choice::Other(5)));
+    // Fields after the dropped values are still decoded
+    assert_eq!(paint.preserve(), Some(&Color::Blue));
+
+    // Dropped values don't overwrite previous values
+    let mut paint = Paint::default();
+    paint.merge_from_bytes(&[0x18, 0x01, 0x18, 0x07]).unwrap();
+    assert_eq!(paint.drop(), Some(&Color::Green));
+}
@@ -0,0 +1,106 @@
+use micropb::{MessageDecode, MessageEncode, PbEncoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/unknown_fields.rs"));
+}
+
+mod canonical_proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/unknown_fields_canonical.rs"));
+}
+
+/// Encoding of `Full`, with unknown fields of `Partial` before, between, and after its own fields
+const FULL: &[u8] = &[
+    0x08, 0x01, // 1: a, unknown
+    0x12, 0x02, b'h', b'i', // 2: b
+    0x1D, 0x01, 0x02, 0x03, 0x04, // 3: c, unknown
+    0x22, 0x02, 0x05, 0x06, // 4: d
+    0x29, 1, 2, 3, 4, 5, 6, 7, 8, // 5: e, unknown
+    0x08, 0x80, 0x00, // 1: a again, unknown and out of order
+];
+
+fn encode<M: MessageEncode>(msg: &M) -> Vec<u8> {
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    let data = encoder.into_writer();
+    assert_eq!(msg.compute_size(), data.len());
+    data
+}
+
+/// Pass `data` through a decode-encode cycle
+fn pass<M: MessageEncode + MessageDecode + Default>(data: &[u8]) -> Vec<u8> {
+    let mut msg = M::default();
+    msg.merge_from_bytes(data).unwrap();
+    encode(&msg)
+}
+
+#[test]
+fn appended() {
+    let mut msg = proto::Partial::default();
+    msg.merge_from_bytes(FULL).unwrap();
+    assert_eq!(msg.b, "hi");
+    assert_eq!(msg.d, [5, 6]);
+    assert_eq!(msg._unknown.iter().count(), 4);
+
+    // Unknown fields are appended after the known fields, in their original order
+    let expected = [
+        0x22, 0x02, 0x05, 0x06, // 4: d
+        0x12, 0x02, b'h', b'i', // 2: b
+        0x08, 0x01, // 1: a
+        0x1D, 0x01, 0x02, 0x03, 0x04, // 3: c
+        0x29, 1, 2, 3, 4, 5, 6, 7, 8, // 5: e
+        0x08, 0x80, 0x00, // 1: a
+    ];
+    let once = encode(&msg);
+    assert_eq!(once, expected);
+    let mut buf = [0; 64];
+    assert_eq!(msg.encode_to_slice_reverse(&mut buf).unwrap(), expected);
+
+    // Further passes leave the bytes unchanged
+    let twice = pass::<proto::Partial>(&once);
+    assert_eq!(twice, once);
+    assert_eq!(pass::<proto::Partial>(&twice), once);
+
+    // The full message can still be decoded from the output
+    let mut full = proto::Full::default();
+    full.merge_from_bytes(&once).unwrap();
+    assert_eq!(full.a, 0);
+    assert_eq!(full.c, 0x04030201);
+    assert_eq!(full.d, [5, 6]);
+}
+
+#[test]
+fn interleaved() {
+    // With canonical order, unknown fields are interleaved by field number, and fields with the
+    // same number keep their original order
+    let expected = [
+        0x08, 0x01, // 1: a
+        0x08, 0x80, 0x00, // 1: a
+        0x12, 0x02, b'h', b'i', // 2: b
+        0x1D, 0x01, 0x02, 0x03, 0x04, // 3: c
+        0x22, 0x02, 0x05, 0x06, // 4: d
+        0x29, 1, 2, 3, 4, 5, 6, 7, 8, // 5: e
+    ];
+    let once = pass::<canonical_proto::Partial>(FULL);
+    assert_eq!(once, expected);
+    let twice = pass::<canonical_proto::Partial>(&once);
+    assert_eq!(twice, once);
+    assert_eq!(pass::<canonical_proto::Partial>(&twice), once);
+
+    let mut msg = canonical_proto::Partial::default();
+    msg.merge_from_bytes(FULL).unwrap();
+    let mut buf = [0; 64];
+    assert_eq!(msg.encode_to_slice_reverse(&mut buf).unwrap(), expected);
+}
+
+#[test]
+fn cleared() {
+    let mut msg = proto::Partial::default();
+    msg.merge_from_bytes(FULL).unwrap();
+    msg.clear();
+    assert!(msg._unknown.is_empty());
+    assert_eq!(encode(&msg), []);
+}
@@ -0,0 +1,56 @@
+use micropb::{MessageDecode, PbDecoder, UnknownFieldHook, WIRE_TYPE_I32, WIRE_TYPE_VARINT};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/no_config.rs"));
+}
+
+use proto::nested_::{Nested, Nested_};
+
+#[test]
+fn nested_unknown_fields() {
+    let data = [
+        0x0A, 5, // basic
+        0x08, 0x05, // basic.int32_num
+        0xA0, 0x06, 0x01, // basic.field 100, unknown
+        0x1A, 7, // inner_msg
+        0x08, 0x02, // inner_msg.val
+        0x1D, 0x01, 0x02, 0x03, 0x04, // inner_msg.field 3, unknown
+        0x30, 0x07, // field 6, unknown
+    ];
+    let mut unknown = vec![];
+    let reader = UnknownFieldHook::new(data.as_slice(), |num, wire_type, len| {
+        unknown.push((num, wire_type, len))
+    });
+    let mut decoder = PbDecoder::new(reader);
+    let mut nested = Nested::default();
+    nested.decode(&mut decoder, data.len()).unwrap();
+
+    assert_eq!(
+        unknown,
+        [
+            (100, WIRE_TYPE_VARINT, 1),
+            (3, WIRE_TYPE_I32, 4),
+            (6, WIRE_TYPE_VARINT, 1)
+        ]
+    );
+    // The known fields are still decoded correctly
+    assert_eq!(nested.basic().unwrap().int32_num, 5);
+    assert!(matches!(
+        nested.inner,
+        Some(Nested_::Inner::InnerMsg(msg)) if msg.val() == Some(&1)
+    ));
+}
+
+#[test]
+fn no_unknown_fields() {
+    let data = [0x0A, 2, 0x08, 0x05, 0x28, 0x01];
+    let mut count = 0;
+    let reader = UnknownFieldHook::new(data.as_slice(), |_, _, _| count += 1);
+    let mut nested = Nested::default();
+    nested
+        .decode(&mut PbDecoder::new(reader), data.len())
+        .unwrap();
+    assert_eq!(count, 0);
+}
@@ -0,0 +1,223 @@
+use std::{cell::Cell, mem::size_of};
+
+use micropb::{BackpatchWriter, MessageDecode, MessageEncode, PbDecoder, PbEncoder};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/visitor.rs"));
+}
+
+use proto::{SampleBatch, Telemetry, Telemetry_::_Visitor, Upload};
+
+/// Visitor that aggregates the visited fields without storing them
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Stats {
+    batches: usize,
+    sample_sum: i32,
+    last_id: u32,
+    readings_sum: i32,
+    tag_bytes: usize,
+    /// Number of batches to produce when encoding
+    send: u32,
+    /// Number of outgoing batches to produce when encoding
+    outgoing: u32,
+    /// Number of times the outgoing batches were traversed
+    traversals: Cell<usize>,
+}
+
+impl _Visitor for Stats {
+    fn on_batches(&mut self, elem: &SampleBatch) {
+        self.batches += 1;
+        self.sample_sum += elem.samples.iter().sum::<i32>();
+        self.last_id = elem.id;
+    }
+
+    fn for_each_batches<E>(
+        &self,
+        mut f: impl FnMut(&SampleBatch) -> Result<(), E>,
+    ) -> Result<(), E> {
+        for id in 0..self.send {
+            let mut batch = SampleBatch {
+                id,
+                ..Default::default()
+            };
+            batch.samples.push(id as i32).unwrap();
+            f(&batch)?;
+        }
+        Ok(())
+    }
+
+    fn for_each_outgoing<E>(
+        &self,
+        mut f: impl FnMut(&SampleBatch) -> Result<(), E>,
+    ) -> Result<(), E> {
+        self.traversals.set(self.traversals.get() + 1);
+        for id in 0..self.outgoing {
+            f(&SampleBatch {
+                id,
+                ..Default::default()
+            })?;
+        }
+        Ok(())
+    }
+
+    fn on_readings(&mut self, elem: &i32) {
+        self.readings_sum += elem;
+    }
+
+    fn on_tags(&mut self, elem: &micropb::heapless::String<8>) {
+        self.tag_bytes += elem.len();
+    }
+}
+
+#[test]
+fn fields_not_stored() {
+    // Only the non-visited field and the visitor are in the struct
+    assert_eq!(size_of::<Telemetry>(), size_of::<(u32, Stats)>());
+    let _: u32 = Telemetry::default().device;
+}
+
+#[test]
+fn visit_many_elements() {
+    let mut data = vec![];
+    for id in 0..1000u32 {
+        // Each batch contains a packed sample list of [1, 2] and an ID
+        data.extend([
+            0x12,
+            7,
+            0x0A,
+            2,
+            0x01,
+            0x02,
+            0x10,
+            0x80 | id as u8,
+            (id >> 7) as u8,
+        ]);
+    }
+    data.extend([0x08, 0x05]);
+
+    let mut msg = Telemetry::default();
+    msg.decode(&mut PbDecoder::new(data.as_slice()), data.len())
+        .unwrap();
+    assert_eq!(msg._visitor.batches, 1000);
+    assert_eq!(msg._visitor.sample_sum, 3000);
+    assert_eq!(msg._visitor.last_id, 999);
+    assert_eq!(msg.device, 5);
+}
+
+#[test]
+fn visit_scalars_and_strings() {
+    let data = [
+        0x18, 0x03, // field 3 unpacked, value -2
+        0x1A, 3, 0x02, 0x04, 0x06, // field 3 packed, values 1, 2, 3
+        0x22, 2, b'a', b'b', // field 4
+        0x22, 3, b'c', b'd', b'e', // field 4
+    ];
+    let mut msg = Telemetry::default();
+    msg.decode(&mut PbDecoder::new(data.as_slice()), data.len())
+        .unwrap();
+    assert_eq!(msg._visitor.readings_sum, 4);
+    assert_eq!(msg._visitor.tag_bytes, 5);
+
+    // Visited fields still check the wire type
+    let data = [0x21, 0, 0, 0, 0, 0, 0, 0, 0];
+    assert!(msg
+        .decode(&mut PbDecoder::new(data.as_slice()), data.len())
+        .is_err());
+}
+
+#[test]
+fn encode_from_visitor() {
+    let mut msg = Telemetry {
+        device: 1,
+        ..Default::default()
+    };
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    // No elements are produced for the other visited fields
+    assert_eq!(encoder.into_writer(), &[0x08, 0x01]);
+
+    msg._visitor.send = 3;
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    let data = encoder.into_writer();
+    assert_eq!(msg.compute_size(), data.len());
+
+    let mut decoded = Telemetry::default();
+    decoded
+        .decode(&mut PbDecoder::new(data.as_slice()), data.len())
+        .unwrap();
+    assert_eq!(decoded.device, 1);
+    assert_eq!(decoded._visitor.batches, 3);
+    assert_eq!(decoded._visitor.sample_sum, 3);
+    assert_eq!(decoded._visitor.last_id, 2);
+}
+
+fn outgoing(count: u32) -> Telemetry {
+    let mut msg = Telemetry::default();
+    msg._visitor.outgoing = count;
+    msg
+}
+
+fn nested(count: u32) -> Upload {
+    let mut msg = Upload::default();
+    msg.set_telemetry(outgoing(count));
+    msg
+}
+
+#[test]
+fn skip_decode() {
+    let msg = outgoing(3);
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    let data = encoder.into_writer();
+    assert_eq!(
+        data,
+        &[0x2A, 0x00, 0x2A, 0x02, 0x10, 0x01, 0x2A, 0x02, 0x10, 0x02]
+    );
+
+    // Outgoing batches are skipped when decoding, so they don't reach the visitor
+    let mut decoded = Telemetry::default();
+    decoded
+        .decode(&mut PbDecoder::new(data.as_slice()), data.len())
+        .unwrap();
+    assert_eq!(decoded._visitor, Stats::default());
+}
+
+#[test]
+fn traversal_count() {
+    // Encoding the top-level message traverses the field once
+    let msg = outgoing(3);
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    assert_eq!(msg._visitor.traversals.get(), 1);
+
+    // Computing the size up front takes another traversal
+    let msg = outgoing(3);
+    let len = msg.compute_size();
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    assert_eq!(encoder.into_writer().len(), len);
+    assert_eq!(msg._visitor.traversals.get(), 2);
+
+    // Length prefixes of nested messages are computed before encoding them
+    let upload = nested(3);
+    let mut encoder = PbEncoder::new(vec![]);
+    upload.encode(&mut encoder).unwrap();
+    let expected = encoder.into_writer();
+    assert_eq!(upload.telemetry._visitor.traversals.get(), 2);
+
+    // Reverse encoding still needs the size of the visited message
+    let upload = nested(3);
+    let mut buf = [0; 32];
+    assert_eq!(upload.encode_to_slice_reverse(&mut buf).unwrap(), expected);
+    assert_eq!(upload.telemetry._visitor.traversals.get(), 2);
+
+    // Backpatching fills in length prefixes afterwards, so one traversal is enough
+    let upload = nested(3);
+    let mut encoder = PbEncoder::new(BackpatchWriter::exact(&mut buf));
+    upload.encode(&mut encoder).unwrap();
+    assert_eq!(encoder.into_writer().into_written(), expected);
+    assert_eq!(upload.telemetry._visitor.traversals.get(), 1);
+}
@@ -0,0 +1,96 @@
+use micropb::{
+    json::{MessageFromJson, MessageToJson},
+    text::{MessageFromText, Text},
+    well_known::{Duration, Timestamp},
+    MessageDecode, MessageEncode, PbDecoder, PbEncoder,
+};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/well_known.rs"));
+}
+
+use proto::well_known_::Event;
+
+fn sample() -> Event {
+    let mut event = Event {
+        history: vec![Timestamp::new(-1, 500_000_000), Timestamp::UNIX_EPOCH],
+        ..Default::default()
+    };
+    event.set_time(Timestamp::new(1_700_000_000, 1));
+    // The generated field has the runtime type, so its helpers are available directly
+    let elapsed = event
+        .time()
+        .unwrap()
+        .checked_duration_since(event.history[0]);
+    event.set_elapsed(elapsed.unwrap());
+    event
+}
+
+#[test]
+fn runtime_types() {
+    let event = sample();
+    assert_eq!(
+        *event.elapsed().unwrap(),
+        Duration::new(1_700_000_000, 500_000_001)
+    );
+    assert_eq!(
+        core::time::Duration::try_from(*event.elapsed().unwrap()),
+        Ok(core::time::Duration::new(1_700_000_000, 500_000_001))
+    );
+}
+
+#[test]
+fn encode_decode() {
+    let event = sample();
+    let mut encoder = PbEncoder::new(vec![]);
+    event.encode(&mut encoder).unwrap();
+    let data = encoder.into_writer();
+    assert_eq!(data.len(), event.compute_size());
+
+    let mut decoded = Event::default();
+    decoded
+        .decode(&mut PbDecoder::new(data.as_slice()), data.len())
+        .unwrap();
+    assert_eq!(decoded, event);
+
+    // Out-of-range values are decoded as-is, and can be normalized afterwards
+    let data = [
+        0x12, 0x0D, 0x08, 0x01, 0x10, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01,
+    ];
+    let mut decoded = Event::default();
+    decoded.merge_from_bytes(&data).unwrap();
+    let mut elapsed = *decoded.elapsed().unwrap();
+    assert_eq!(elapsed, Duration::new(1, -1));
+    assert!(!elapsed.is_valid());
+    elapsed.normalize();
+    assert_eq!(elapsed, Duration::new(0, 999_999_999));
+}
+
+#[test]
+fn json_text() {
+    let event = sample();
+    let mut json = String::new();
+    event.write_json(&mut json).unwrap();
+    assert_eq!(
+        json,
+        concat!(
+            r#"{"time":{"seconds":"1700000000","nanos":1},"#,
+            r#""elapsed":{"seconds":"1700000000","nanos":500000001},"#,
+            r#""history":[{"seconds":"-1","nanos":500000000},{}]}"#
+        )
+    );
+    assert_eq!(Event::from_json(&json).unwrap(), event);
+
+    let text = Text(&event).to_string();
+    assert_eq!(
+        text,
+        concat!(
+            "time { seconds: 1700000000 nanos: 1 } ",
+            "elapsed { seconds: 1700000000 nanos: 500000001 } ",
+            "history { seconds: -1 nanos: 500000000 } history { }"
+        )
+    );
+    assert_eq!(Event::from_text(&text).unwrap(), event);
+}
@@ -0,0 +1,190 @@
+use micropb::{
+    json::{MessageFromJson, MessageToJson},
+    text::{MessageFromText, Text},
+    DecodeError, MessageDecode, MessageEncode, PbDecoder, PbEncoder,
+};
+
+mod proto {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/wrappers.rs"));
+}
+
+mod proto_fixed {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/wrappers_fixed.rs"));
+}
+
+use proto::wrappers_::{Settings, Settings_};
+
+fn encode(msg: &Settings) -> Vec<u8> {
+    let mut encoder = PbEncoder::new(vec![]);
+    msg.encode(&mut encoder).unwrap();
+    let out = encoder.into_writer();
+    assert_eq!(msg.compute_size(), out.len());
+
+    let mut buf = [0; 64];
+    assert_eq!(msg.encode_to_slice_reverse(&mut buf).unwrap(), out);
+    out
+}
+
+fn decode(bytes: &[u8]) -> Settings {
+    let mut msg = Settings::default();
+    msg.decode(&mut PbDecoder::new(bytes), bytes.len()).unwrap();
+    msg
+}
+
+fn assert_wire(msg: &Settings, bytes: &[u8]) {
+    assert_eq!(encode(msg), bytes);
+    assert_eq!(&decode(bytes), msg);
+}
+
+#[test]
+fn field_types() {
+    let msg = Settings::default();
+    let _: Option<&i32> = msg.limit();
+    let _: Option<&u64> = msg.id();
+    let _: Option<f64> = msg.ratio;
+    let _: Option<&bool> = msg.enabled();
+    let _: Option<&String> = msg.label();
+    let _: Option<&Vec<u8>> = msg.data();
+    let _: Option<&f32> = msg.scale();
+    let _: &Vec<u32> = &msg.counts;
+}
+
+#[test]
+fn wire_compat() {
+    // Reference bytes are from protoc with the actual wrapper messages
+    assert_wire(&Settings::default(), &[]);
+
+    let mut msg = Settings::default();
+    // Zero values are written as empty wrappers
+    msg.set_limit(0);
+    assert_wire(&msg, &[0x0A, 0x00]);
+    msg.set_limit(5);
+    assert_wire(&msg, &[0x0A, 0x02, 0x08, 0x05]);
+    msg.set_limit(-1);
+    assert_wire(
+        &msg,
+        &[
+            0x0A, 0x0B, 0x08, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01,
+        ],
+    );
+
+    let mut msg = Settings {
+        ratio: Some(1.5),
+        ..Default::default()
+    };
+    assert_wire(&msg, &[0x1A, 0x09, 0x09, 0, 0, 0, 0, 0, 0, 0xF8, 0x3F]);
+    msg.ratio = Some(0.0);
+    assert_wire(&msg, &[0x1A, 0x00]);
+
+    let mut msg = Settings::default();
+    msg.set_enabled(false);
+    msg.set_label(String::new());
+    assert_wire(&msg, &[0x22, 0x00, 0x2A, 0x00]);
+    msg.set_enabled(true);
+    msg.set_label("ab".to_owned());
+    msg.set_data(vec![7]);
+    assert_wire(
+        &msg,
+        &[
+            0x22, 0x02, 0x08, 0x01, 0x2A, 0x04, 0x0A, 0x02, b'a', b'b', 0x32, 0x03, 0x0A, 0x01, 7,
+        ],
+    );
+
+    let msg = Settings {
+        counts: vec![0, 3],
+        choice: Some(Settings_::Choice::ChoiceLabel("x".to_owned())),
+        ..Default::default()
+    };
+    assert_wire(
+        &msg,
+        &[
+            0x42, 0x00, 0x42, 0x02, 0x08, 0x03, 0x4A, 0x03, 0x0A, 0x01, b'x',
+        ],
+    );
+}
+
+#[test]
+fn decode_wrapper() {
+    // Empty wrappers reset values left over from cleared fields
+    let mut msg = Settings::default();
+    msg.set_limit(5);
+    msg.clear_limit();
+    msg.decode(&mut PbDecoder::new([0x0A, 0x00].as_slice()), 2)
+        .unwrap();
+    assert_eq!(msg.limit(), Some(&0));
+
+    // Wrappers are merged, with unknown fields skipped
+    let msg = decode(&[0x0A, 0x02, 0x08, 0x05, 0x0A, 0x02, 0x10, 0x01]);
+    assert_eq!(msg.limit(), Some(&5));
+    let msg = decode(&[0x0A, 0x04, 0x08, 0x05, 0x08, 0x06]);
+    assert_eq!(msg.limit(), Some(&6));
+
+    let bytes = [0x0A, 0x02, 0x0D, 0x05];
+    let mut msg = Settings::default();
+    assert_eq!(
+        msg.decode(&mut PbDecoder::new(bytes.as_slice()), bytes.len()),
+        Err(DecodeError::WrongWireType)
+    );
+}
+
+#[test]
+fn max_bytes() {
+    use proto_fixed::wrappers_::Settings as FixedSettings;
+
+    let mut msg = FixedSettings::default();
+    msg.set_label(micropb::heapless::String::try_from("abcd").unwrap());
+    let mut encoder = PbEncoder::new(micropb::heapless::Vec::<u8, 64>::new());
+    msg.encode(&mut encoder).unwrap();
+    let bytes = encoder.into_writer();
+    assert_eq!(bytes, [0x2A, 0x06, 0x0A, 0x04, b'a', b'b', b'c', b'd']);
+    assert!(FixedSettings::MAX_SIZE.is_some());
+
+    let mut decoded = FixedSettings::default();
+    decoded
+        .decode(&mut PbDecoder::new(bytes.as_slice()), bytes.len())
+        .unwrap();
+    assert_eq!(decoded, msg);
+
+    let bytes = [0x2A, 0x07, 0x0A, 0x05, b'a', b'b', b'c', b'd', b'e'];
+    let mut decoded = FixedSettings::default();
+    assert_eq!(
+        decoded.decode(&mut PbDecoder::new(bytes.as_slice()), bytes.len()),
+        Err(DecodeError::Capacity {
+            needed: 1,
+            capacity: 4
+        })
+    );
+}
+
+#[test]
+fn json_text() {
+    let mut msg = Settings {
+        ratio: Some(0.5),
+        ..Default::default()
+    };
+    msg.set_limit(0);
+    msg.set_id(u64::MAX);
+    msg.set_label("a".to_owned());
+    let mut json = String::new();
+    msg.write_json(&mut json).unwrap();
+    assert_eq!(
+        json,
+        r#"{"limit":0,"id":"18446744073709551615","ratio":0.5,"label":"a"}"#
+    );
+    assert_eq!(Settings::from_json(&json).unwrap(), msg);
+    assert_eq!(
+        Settings::from_json(r#"{"limit":null,"ratio":null}"#).unwrap(),
+        Settings::default()
+    );
+
+    let text = Text(&msg).to_string();
+    assert_eq!(
+        text,
+        r#"limit { } id { value: 18446744073709551615 } ratio { value: 0.5 } label { value: "a" }"#
+    );
+    assert_eq!(Settings::from_text(&text).unwrap(), msg);
+}
@@ -1,4 +1,4 @@
-use micropb_gen::{config::IntSize, Config, EncodeDecode, Generator};
+use micropb_gen::{config::IntSize, Config, EncodeDecode, Generator, Target};
 
 fn main() {
     let mut gen = Generator::new();
@@ -6,6 +6,8 @@ fn main() {
     gen.encode_decode(EncodeDecode::DecodeOnly)
         // Set all int sizes to 32 bits, since 64-bit support isn't enabled on micropb
         .configure(".", Config::new().int_size(IntSize::S32))
+        // Crate doesn't have an allocator
+        .target(Target::NoAlloc)
         .add_protoc_arg("-I..")
         .compile_protos(
             &["example.proto"],
@@ -0,0 +1,20 @@
+use micropb_gen::Generator;
+
+fn main() {
+    let mut gen = Generator::new();
+    // Gate the encode and decode logic behind this crate's features
+    gen.feature_gates("msg-encode", "msg-decode")
+        // Gate the inherent encode method as well
+        .dyn_encode(true)
+        .add_protoc_arg("-I..")
+        .compile_protos(
+            &["example.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/feature_gates.rs",
+        )
+        .unwrap();
+    std::fs::write(
+        std::env::var("OUT_DIR").unwrap() + "/features.toml",
+        gen.cargo_features().unwrap(),
+    )
+    .unwrap();
+}
@@ -0,0 +1,55 @@
+#![no_std]
+
+mod example {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/feature_gates.rs"));
+}
+
+#[cfg(all(test, any(feature = "msg-encode", feature = "msg-decode")))]
+static DATA: &[u8] = &[
+    0x08, 0x0C, // field 1
+    0x10, 0xA7, 0xCB, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, // field 2
+    0x38, 0x01, // field 7
+];
+
+#[cfg(all(test, any(feature = "msg-encode", feature = "msg-decode")))]
+fn msg() -> example::Msg {
+    // The message struct is always available
+    example::Msg {
+        f_int32: 12,
+        f_int64: -6745,
+        f_bool: true,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn cargo_features() {
+    // The features declared in the manifest match the ones reported by the generator
+    let features = include_str!(concat!(env!("OUT_DIR"), "/features.toml"));
+    let manifest = include_str!("../Cargo.toml");
+    for line in features.lines() {
+        assert!(manifest.contains(line), "{line} not declared in Cargo.toml");
+    }
+}
+
+#[cfg(all(test, feature = "msg-encode"))]
+#[test]
+fn encode() {
+    use micropb::MessageEncode;
+
+    let mut buf = [0; 32];
+    assert_eq!(msg().encode_to_slice(&mut buf).unwrap(), DATA);
+}
+
+#[cfg(all(test, feature = "msg-decode"))]
+#[test]
+fn decode() {
+    use micropb::MessageDecode;
+
+    let mut decoded = example::Msg::default();
+    decoded.merge_from_bytes(DATA).unwrap();
+    assert_eq!(decoded, msg());
+}
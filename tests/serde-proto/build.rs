@@ -1,4 +1,4 @@
-use micropb_gen::{Config, Generator};
+use micropb_gen::{config::OptionalRepr, Config, Generator};
 
 fn main() {
     // Enable serde and Eq derives on the proto type
@@ -40,4 +40,21 @@ fn main() {
             std::env::var("OUT_DIR").unwrap() + "/serde_proto.rs",
         )
         .unwrap();
+
+    // Implement serde on all types via the generator config instead of custom attributes
+    let mut generator = Generator::new();
+    generator
+        .use_container_heapless()
+        .configure(".", Config::new().serde(true).max_bytes(8).max_len(4))
+        .configure(
+            ".Telemetry.last",
+            Config::new().optional_repr(OptionalRepr::Option),
+        );
+
+    generator
+        .compile_protos(
+            &["proto/telemetry.proto"],
+            std::env::var("OUT_DIR").unwrap() + "/telemetry.rs",
+        )
+        .unwrap();
 }
@@ -28,3 +28,57 @@ fn impl_eq() {
     fn is_eq<T: Eq>() {}
     is_eq::<proto::Data>();
 }
+
+mod telemetry {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/telemetry.rs"));
+}
+
+#[cfg(test)]
+#[test]
+fn serde_config() {
+    use micropb::{heapless, MessageDecode, MessageEncode};
+    use telemetry::{Telemetry, Telemetry_};
+
+    let mut reading = Telemetry_::Reading::default();
+    reading.set_value(-3);
+    let mut msg = Telemetry::default();
+    msg.set_label(heapless::String::try_from("temp").unwrap());
+    msg.readings.push(reading).unwrap();
+    msg.set_mode(Telemetry_::Mode::Active);
+    msg.set_ok(true);
+    msg.source = Some(Telemetry_::Source::Raw(
+        heapless::Vec::from_slice(&[1, 2]).unwrap(),
+    ));
+
+    let mut buf = [0; 64];
+    let mut decoded = Telemetry::default();
+    decoded
+        .merge_from_bytes(msg.encode_to_slice(&mut buf).unwrap())
+        .unwrap();
+    assert_eq!(decoded, msg);
+
+    // Absent optional fields are serialized as null
+    let json = serde_json_core::ser::to_vec::<_, 200>(&decoded).unwrap();
+    assert_eq!(
+        core::str::from_utf8(&json).unwrap(),
+        r#"{"seq":null,"label":"temp","readings":[{"value":-3}],"mode":1,"last":null,"ok":true,"source":{"Raw":[1,2]}}"#
+    );
+    let (round_trip, len): (Telemetry, _) = serde_json_core::de::from_slice(&json).unwrap();
+    assert_eq!(len, json.len());
+    assert_eq!(round_trip, msg);
+    // Absent fields keep their default values
+    assert_eq!(round_trip.seq(), None);
+    assert_eq!(round_trip.seq, 7);
+
+    // Missing fields are left at their defaults
+    let (partial, _): (Telemetry, _) =
+        serde_json_core::de::from_slice(br#"{"seq":3,"mode":5}"#).unwrap();
+    assert_eq!(partial.seq(), Some(&3));
+    assert_eq!(partial.label(), None);
+    assert_eq!(partial.ok(), None);
+    assert!(partial.readings.is_empty());
+    // Unknown enum values survive the round trip
+    assert_eq!(partial.mode(), Some(&Telemetry_::Mode(5)));
+}
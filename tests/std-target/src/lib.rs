@@ -0,0 +1,33 @@
+// No `extern crate alloc`, since the generated code only refers to `std`
+mod heap {
+    #![allow(clippy::all)]
+    #![allow(nonstandard_style, unused, irrefutable_let_patterns)]
+    include!(concat!(env!("OUT_DIR"), "/heap.rs"));
+}
+
+#[cfg(test)]
+#[test]
+fn roundtrip() {
+    use micropb::{MessageDecode, MessageEncode, PbDecoder, PbEncoder};
+    use std::collections::HashMap;
+
+    let mut node = heap::Node {
+        name: String::from("root"),
+        data: vec![1, 2],
+        values: vec![3, 4],
+        attrs: HashMap::from([(String::from("a"), -1)]),
+        child: None,
+    };
+    node.set_child(heap::Node::default());
+    let _: &Option<Box<heap::Node>> = &node.child;
+
+    let mut encoder = PbEncoder::new(Vec::new());
+    node.encode(&mut encoder).unwrap();
+    let bytes = encoder.into_writer();
+
+    let mut decoded = heap::Node::default();
+    decoded
+        .decode(&mut PbDecoder::new(bytes.as_slice()), bytes.len())
+        .unwrap();
+    assert_eq!(decoded, node);
+}